@@ -316,6 +316,22 @@ pub trait SerCollectionHandle: Send + Sync {
     fn fork(&self) -> Box<dyn SerCollectionHandle>;
 }
 
+/// A handle that serves point lookups on an output collection.
+///
+/// Lookups are served from the integral of the collection indexed by key,
+/// which the circuit updates at each step, without stepping the circuit.
+pub trait SerLookupHandle: Send + Sync {
+    /// Look up a batch of keys.
+    ///
+    /// `keys` contains a JSON array of keys encoded using `json_flavor`.
+    /// Returns a JSON array with one element per key: the record associated
+    /// with the key or `null` if the collection does not contain the key.
+    fn lookup(&self, keys: &[u8], json_flavor: JsonFlavor) -> AnyResult<Vec<u8>>;
+
+    /// Returns an alias to `self`.
+    fn fork(&self) -> Box<dyn SerLookupHandle>;
+}
+
 /// Cursor that iterates over deletions before insertions.
 ///
 /// Most consumers don't understand Z-sets and expect a stream of upserts
@@ -428,7 +444,7 @@ pub trait CircuitCatalog: Send {
             OutputQuery::Table => OutputQueryHandles {
                 schema: handles.schema.clone(),
                 delta: Some(handles.delta_handle.fork()),
-                snapshot: None,
+                snapshot: handles.snapshot_handle.as_ref().map(|handle| handle.fork()),
             },
             OutputQuery::Neighborhood => OutputQueryHandles {
                 schema: handles.schema.clone(),
@@ -597,6 +613,32 @@ pub struct OutputCollectionHandles {
     /// outputs up to `N` quantiles of the input collection, computed using
     /// the [`dbsp::Stream::stream_key_quantiles`] operator.
     pub quantiles_handle: Option<Box<dyn SerCollectionHandle>>,
+
+    /// Input stream used to submit table snapshot queries.
+    ///
+    /// The stream carries values of type `(bool, Option<TableSnapshotQuery<V>>)`
+    /// (see [`TableSnapshotQuery`](`crate::static_compile::catalog::TableSnapshotQuery`)),
+    /// where `V` is the value type of the collection.  When the first
+    /// component of the tuple is `true`, the circuit outputs records of the
+    /// collection that match the query to the
+    /// [`snapshot_handle`](`Self::snapshot_handle`) stream at the end of the
+    /// current clock cycle.  `None` selects the entire collection.
+    pub snapshot_descr_handle: Option<Box<dyn DeScalarHandle>>,
+
+    /// Snapshot stream.
+    ///
+    /// Contains records selected by the last query submitted via
+    /// `snapshot_descr_handle`.  The snapshot is computed from the integral
+    /// of the collection maintained using
+    /// [`dbsp::Stream::integrate_trace`].
+    pub snapshot_handle: Option<Box<dyn SerCollectionHandle>>,
+
+    /// Point lookups by key.
+    ///
+    /// Only available for collections registered with
+    /// [`Catalog::register_output_zset_with_lookup`], i.e., views declared
+    /// with a primary key.
+    pub lookup_handle: Option<Box<dyn SerLookupHandle>>,
 }
 
 /// Query result streams.
@@ -604,9 +646,8 @@ pub struct OutputCollectionHandles {
 /// Stores the result of a a [query](`OutputQuery`) as a pair of streams:
 /// a stream of changes and a snapshot, i.e., the integral, of all previous
 /// changes.  Not all queries return both streams, e.g., the
-/// [quantiles](`OutputQuery::Quantiles`) query only returns a snapshot,
-/// while the [table](`OutputQuery::Table`) query currently only returns the
-/// delta stream; therefore the stream handles are wrapped in `Option`s.
+/// [quantiles](`OutputQuery::Quantiles`) query only returns a snapshot;
+/// therefore the stream handles are wrapped in `Option`s.
 ///
 /// Whenever both streams are present, the client may consume the result in
 /// a hybrid mode: read the initial snapshot containing a full answer to the
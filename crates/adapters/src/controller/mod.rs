@@ -40,11 +40,11 @@ use crate::transport::{
 };
 use crate::DbspCircuitHandle;
 use crate::{
-    catalog::SerBatch, Catalog, CircuitCatalog, Encoder, InputConsumer, InputEndpoint, InputFormat,
-    OutputConsumer, OutputEndpoint, OutputFormat, OutputQueryHandles, ParseError, Parser,
-    PipelineState,
+    catalog::SerBatch, AsyncErrorCallback, Catalog, CircuitCatalog, Encoder, InputConsumer,
+    InputEndpoint, InputFormat, OutputConsumer, OutputEndpoint, OutputFormat, OutputQueryHandles,
+    ParseError, Parser, PipelineState,
 };
-use anyhow::Error as AnyError;
+use anyhow::{anyhow, Error as AnyError};
 use crossbeam::channel::{self, Sender};
 use crossbeam::{
     queue::SegQueue,
@@ -56,7 +56,7 @@ use log::{debug, error, info};
 use pipeline_types::query::OutputQuery;
 use std::collections::HashMap;
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, VecDeque},
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Condvar, Mutex,
@@ -64,6 +64,7 @@ use std::{
     thread::{spawn, JoinHandle},
     time::{Duration, Instant},
 };
+use tokio::sync::watch;
 
 mod error;
 mod stats;
@@ -85,6 +86,22 @@ pub(crate) const MAX_API_CONNECTIONS: u64 = 100;
 
 pub(crate) type EndpointId = u64;
 
+/// Notification sent by the circuit thread after completing a step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepCompletion {
+    /// Step number.
+    pub step: Step,
+
+    /// Total number of input records fully processed by the circuit at the
+    /// end of this step.
+    ///
+    /// All input records counted in
+    /// [`ControllerStatus::num_total_input_records`] before this step started
+    /// have been processed, and the outputs they produced have been pushed to
+    /// the transports of all output endpoints.
+    pub processed_records: u64,
+}
+
 /// Controller that coordinates the creation, reconfiguration, teardown of
 /// input/output adapters, and implements runtime flow control.
 ///
@@ -136,6 +153,47 @@ impl Controller {
         config: &PipelineConfig,
         error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
     ) -> Result<Self, ControllerError>
+    where
+        F: FnOnce(
+                CircuitConfig,
+            )
+                -> Result<(Box<dyn DbspCircuitHandle>, Box<dyn CircuitCatalog>), ControllerError>
+            + Send
+            + 'static,
+    {
+        Self::new(circuit_factory, config, false, error_cb)
+    }
+
+    /// Create a new I/O controller for a [standby](crate#standby-pipelines)
+    /// instance of a pipeline.
+    ///
+    /// This is like [`Self::with_config`], except that the standby follows the
+    /// input steps recorded by the running instance of the pipeline that reads
+    /// from the same sources and holds back its output until
+    /// [`Self::activate`] is called.  Every input and output endpoint must be
+    /// fault tolerant, and output endpoints must not buffer output.
+    pub fn standby_with_config<F>(
+        circuit_factory: F,
+        config: &PipelineConfig,
+        error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
+    ) -> Result<Self, ControllerError>
+    where
+        F: FnOnce(
+                CircuitConfig,
+            )
+                -> Result<(Box<dyn DbspCircuitHandle>, Box<dyn CircuitCatalog>), ControllerError>
+            + Send
+            + 'static,
+    {
+        Self::new(circuit_factory, config, true, error_cb)
+    }
+
+    fn new<F>(
+        circuit_factory: F,
+        config: &PipelineConfig,
+        standby: bool,
+        error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
+    ) -> Result<Self, ControllerError>
     where
         F: FnOnce(
                 CircuitConfig,
@@ -156,6 +214,7 @@ impl Controller {
             backpressure_thread_unparker,
             error_cb,
         ));
+        inner.status.set_standby(standby);
 
         let backpressure_thread_handle = {
             let inner = inner.clone();
@@ -373,6 +432,18 @@ impl Controller {
         self.inner.pause();
     }
 
+    /// Makes a standby controller, created with [`Self::standby_with_config`],
+    /// take over from the instance of the pipeline that it was standing by
+    /// for, which must have stopped.
+    ///
+    /// The input endpoints start recording steps after the last step that the
+    /// other instance recorded, and the output endpoints output the steps that
+    /// the other instance didn't.
+    pub fn activate(&self) -> Result<(), ControllerError> {
+        debug!("Activating the pipeline");
+        self.inner.activate()
+    }
+
     /// Returns controller status.
     pub fn status(&self) -> &ControllerStatus {
         // Update pipeline metrics computed on-demand.
@@ -384,6 +455,16 @@ impl Controller {
         &self.inner.catalog
     }
 
+    /// Subscribe to step completion notifications.
+    ///
+    /// The receiver observes the most recently completed step, or `None`
+    /// if the circuit hasn't completed any steps yet.  Together with
+    /// [`ControllerStatus::num_total_input_records`], this allows clients
+    /// to wait until their input has been processed by the circuit.
+    pub fn step_completions(&self) -> watch::Receiver<Option<StepCompletion>> {
+        self.inner.step_completions.subscribe()
+    }
+
     pub fn dump_profile(&self) {
         debug!("Generating DBSP profile dump");
         self.inner.dump_profile();
@@ -514,6 +595,7 @@ impl Controller {
                     // consume buffered data.
                     // Use strict inequality in case `min_batch_size_records` is 0.
                     if controller.status.step_requested()
+                        || controller.status.is_step_replayed(step)
                         || buffered_records > min_batch_size_records
                         || start
                             .map(|start| start.elapsed() >= max_buffering_delay)
@@ -602,6 +684,7 @@ impl Controller {
                                         // labeled with this
                                         // frontier.
                                         endpoint.queue.push((step, batch, processed_records));
+                                        endpoint.queued_steps.store(step + 1, Ordering::Release);
                                         endpoint.snapshot_sent.store(true, Ordering::Release);
                                     }
                                 } else if delta_batch.is_some() {
@@ -616,6 +699,7 @@ impl Controller {
                                     };
 
                                     endpoint.queue.push((step, batch, processed_records));
+                                    endpoint.queued_steps.store(step + 1, Ordering::Release);
                                 }
 
                                 // Wake up the output thread.  We're not trying to be smart here and
@@ -624,9 +708,28 @@ impl Controller {
                                 endpoint.unparker.unpark();
                             }
                         }
+                        // The step completes once every output endpoint has
+                        // pushed the outputs queued to it so far.
+                        let queued_steps = outputs
+                            .iter_by_id()
+                            .map(|(endpoint_id, endpoint)| {
+                                (*endpoint_id, endpoint.queued_steps.load(Ordering::Acquire))
+                            })
+                            .collect();
+                        drop(outputs);
+
+                        controller.unpushed_steps.lock().unwrap().push_back((
+                            StepCompletion {
+                                step,
+                                processed_records,
+                            },
+                            queued_steps,
+                        ));
+                        controller.complete_pushed_steps();
 
                         step += 1;
                         controller.step.store(step, Ordering::Release);
+                        controller.status.set_step(step);
                         controller.unpark_backpressure();
                     } else if buffered_records > 0 {
                         // We have some buffered data, but less than `min_batch_size_records` --
@@ -730,7 +833,7 @@ struct OutputEndpointDescr {
     queue: Arc<BatchQueue>,
 
     /// True if the endpoint has already received a complete snapshot
-    /// of the query result.
+    /// of the query result or did not request one.
     snapshot_sent: AtomicBool,
 
     /// Used to notify the endpoint thread that the endpoint is being
@@ -742,6 +845,14 @@ struct OutputEndpointDescr {
 
     /// Whether the output endpoint can discard duplicate output.
     is_fault_tolerant: bool,
+
+    /// Number of steps whose outputs have been queued to the endpoint, i.e.,
+    /// the last step whose output was queued plus one.
+    queued_steps: AtomicU64,
+
+    /// Number of steps whose outputs have been pushed to the transport of the
+    /// endpoint, i.e., the last step whose output was pushed plus one.
+    pushed_steps: Arc<AtomicU64>,
 }
 
 impl OutputEndpointDescr {
@@ -749,6 +860,7 @@ impl OutputEndpointDescr {
         endpoint_name: &str,
         stream_name: &str,
         query: OutputQuery,
+        snapshot: bool,
         unparker: Unparker,
         is_fault_tolerant: bool,
     ) -> Self {
@@ -757,10 +869,13 @@ impl OutputEndpointDescr {
             stream_name: canonical_identifier(stream_name),
             query,
             queue: Arc::new(SegQueue::new()),
-            snapshot_sent: AtomicBool::new(false),
+            // Endpoints that don't expect a snapshot start receiving deltas right away.
+            snapshot_sent: AtomicBool::new(!snapshot),
             disconnect_flag: Arc::new(AtomicBool::new(false)),
             unparker,
             is_fault_tolerant,
+            queued_steps: AtomicU64::new(0),
+            pushed_steps: Arc::new(AtomicU64::new(0)),
         }
     }
 }
@@ -781,6 +896,10 @@ impl OutputEndpoints {
         }
     }
 
+    fn iter_by_id(&self) -> impl Iterator<Item = (&'_ EndpointId, &'_ OutputEndpointDescr)> {
+        self.by_id.iter()
+    }
+
     fn iter_by_stream(
         &self,
     ) -> impl Iterator<
@@ -925,6 +1044,15 @@ struct ControllerInner {
     error_cb: Box<dyn Fn(ControllerError) + Send + Sync>,
     step: AtomicStep,
 
+    /// Notifies subscribers about completed steps.
+    step_completions: watch::Sender<Option<StepCompletion>>,
+
+    /// Steps completed by the circuit whose outputs may not have been pushed
+    /// to all output endpoints yet, oldest first, along with the number of
+    /// steps each endpoint must push for the step to complete.
+    #[allow(clippy::type_complexity)]
+    unpushed_steps: Mutex<VecDeque<(StepCompletion, Vec<(EndpointId, u64)>)>>,
+
     /// The lowest-numbered input step not known to have committed yet.
     ///
     /// This is updated lazily, only when we need to wait for a step to commit
@@ -956,6 +1084,8 @@ impl ControllerInner {
             backpressure_thread_unparker,
             error_cb,
             step: AtomicStep::new(0),
+            step_completions: watch::channel(None).0,
+            unpushed_steps: Mutex::new(VecDeque::new()),
             uncommitted_step: Mutex::new(0),
             step_committed: Condvar::new(),
         }
@@ -1051,9 +1181,12 @@ impl ControllerInner {
             endpoint.is_fault_tolerant(),
         );
 
-        let reader = endpoint
-            .open(probe, 0)
-            .map_err(|e| ControllerError::input_transport_error(endpoint_name, true, e))?;
+        let reader = if self.status.is_standby() {
+            endpoint.open_standby(probe, 0)
+        } else {
+            endpoint.open(probe, 0)
+        }
+        .map_err(|e| ControllerError::input_transport_error(endpoint_name, true, e))?;
         if self.state() == PipelineState::Running {
             reader
                 .start(0)
@@ -1128,6 +1261,35 @@ impl ControllerInner {
             self.status.remove_output(endpoint_id);
             // The circuit thread may be waiting for output buffer space.
             self.unpark_circuit();
+            drop(outputs);
+            // Steps no longer wait for the endpoint.
+            self.complete_pushed_steps();
+        }
+    }
+
+    /// Notifies [`StepCompletion`] subscribers of the latest step whose
+    /// outputs have been pushed to all output endpoints that were connected
+    /// when the step completed.
+    fn complete_pushed_steps(&self) {
+        let outputs = self.outputs.read().unwrap();
+        let mut unpushed_steps = self.unpushed_steps.lock().unwrap();
+        let mut completion = None;
+        while let Some((step_completion, queued_steps)) = unpushed_steps.front() {
+            let pushed = queued_steps.iter().all(|(endpoint_id, queued_steps)| {
+                outputs.lookup_by_id(endpoint_id).map_or(true, |endpoint| {
+                    endpoint.pushed_steps.load(Ordering::Acquire) >= *queued_steps
+                })
+            });
+            if !pushed {
+                break;
+            }
+            completion = Some(*step_completion);
+            unpushed_steps.pop_front();
+        }
+        drop(unpushed_steps);
+        drop(outputs);
+        if let Some(completion) = completion {
+            self.step_completions.send_replace(Some(completion));
         }
     }
 
@@ -1164,13 +1326,27 @@ impl ControllerInner {
         let endpoint_name_str = endpoint_name.to_string();
 
         let self_weak = Arc::downgrade(self);
-        endpoint
-            .connect(Box::new(move |fatal: bool, e: AnyError| {
-                if let Some(controller) = self_weak.upgrade() {
-                    controller.output_transport_error(endpoint_id, &endpoint_name_str, fatal, e)
-                }
-            }))
-            .map_err(|e| ControllerError::output_transport_error(endpoint_name, true, e))?;
+        let async_error_callback: AsyncErrorCallback = Box::new(move |fatal: bool, e: AnyError| {
+            if let Some(controller) = self_weak.upgrade() {
+                controller.output_transport_error(endpoint_id, &endpoint_name_str, fatal, e)
+            }
+        });
+        if self.status.is_standby() {
+            // Buffering would make the division of output into steps depend
+            // on timing, so the standby's output couldn't be matched up with
+            // the output of the running instance.
+            if endpoint_config.output_buffer_config.enable_buffer {
+                return Err(ControllerError::output_transport_error(
+                    endpoint_name,
+                    true,
+                    anyhow!("output buffering must be disabled in a standby pipeline"),
+                ));
+            }
+            endpoint.connect_standby(async_error_callback)
+        } else {
+            endpoint.connect(async_error_callback)
+        }
+        .map_err(|e| ControllerError::output_transport_error(endpoint_name, true, e))?;
         let is_fault_tolerant = endpoint.is_fault_tolerant();
 
         // Create probe.
@@ -1201,11 +1377,18 @@ impl ControllerInner {
             endpoint_name,
             &endpoint_config.stream,
             endpoint_config.query,
+            endpoint_config.snapshot,
             parker.unparker().clone(),
             is_fault_tolerant,
         );
+        // The endpoint does not receive the outputs of the steps completed
+        // before it was connected.
+        let step = self.step.load(Ordering::Acquire);
+        endpoint_descr.queued_steps.store(step, Ordering::Release);
+        endpoint_descr.pushed_steps.store(step, Ordering::Release);
         let queue = endpoint_descr.queue.clone();
         let disconnect_flag = endpoint_descr.disconnect_flag.clone();
+        let pushed_steps = endpoint_descr.pushed_steps.clone();
         let controller = self.clone();
 
         outputs.insert(endpoint_id, handles, endpoint_descr);
@@ -1223,6 +1406,7 @@ impl ControllerInner {
                 parker,
                 queue,
                 disconnect_flag,
+                pushed_steps,
                 controller,
             )
         });
@@ -1267,9 +1451,11 @@ impl ControllerInner {
         parker: Parker,
         queue: Arc<BatchQueue>,
         disconnect_flag: Arc<AtomicBool>,
+        pushed_steps: Arc<AtomicU64>,
         controller: Arc<ControllerInner>,
     ) {
         let mut output_buffer = OutputBuffer::new(&endpoint_name);
+        let mut standby = controller.status.is_standby();
 
         loop {
             if controller.state() == PipelineState::Terminated {
@@ -1280,6 +1466,11 @@ impl ControllerInner {
                 return;
             }
 
+            if standby && !controller.status.is_standby() {
+                encoder.consumer().activate();
+                standby = false;
+            }
+
             if output_buffer.flush_needed(&output_buffer_config) {
                 // One of the triggering conditions for flushing the output buffer is satisfied:
                 // go ahead and flush the buffer; we will check for more messages at the next iteration
@@ -1296,6 +1487,8 @@ impl ControllerInner {
                 controller
                     .status
                     .output_buffered_batches(endpoint_id, output_buffer.buffered_processed_records);
+                pushed_steps.store(output_buffer.buffered_step + 1, Ordering::Release);
+                controller.complete_pushed_steps();
             } else if let Some((step, data, processed_records)) = queue.pop() {
                 // Dequeue the next output batch. If output buffering is enabled, push it to the
                 // buffer; we will check if the buffer needs to be flushed at the next iteration of
@@ -1331,6 +1524,8 @@ impl ControllerInner {
                         num_records,
                         &controller.circuit_thread_unparker,
                     );
+                    pushed_steps.store(step + 1, Ordering::Release);
+                    controller.complete_pushed_steps();
                 }
             } else {
                 trace!("Queue is empty -- wait for the circuit thread to wake us up when more data is available");
@@ -1374,6 +1569,27 @@ impl ControllerInner {
         self.status.state()
     }
 
+    fn activate(self: &Arc<Self>) -> Result<(), ControllerError> {
+        if !self.status.is_standby() {
+            return Err(ControllerError::not_supported(
+                "only a standby pipeline can be activated",
+            ));
+        }
+
+        for ep in self.inputs.lock().unwrap().values() {
+            ep.reader
+                .activate()
+                .map_err(|e| ControllerError::input_transport_error(&ep.endpoint_name, true, e))?;
+        }
+
+        // The output threads activate their endpoints when they notice.
+        self.status.set_standby(false);
+        for (_endpoint_id, ep) in self.outputs.read().unwrap().iter_by_id() {
+            ep.unparker.unpark();
+        }
+        Ok(())
+    }
+
     fn start(self: &Arc<Self>) {
         self.status.set_state(PipelineState::Running);
         self.unpark_backpressure();
@@ -1652,6 +1868,13 @@ impl OutputConsumer for OutputProbe {
                 .output_transport_error(self.endpoint_id, &self.endpoint_name, false, e);
         })
     }
+
+    fn activate(&mut self) {
+        self.endpoint.activate().unwrap_or_else(|e| {
+            self.controller
+                .output_transport_error(self.endpoint_id, &self.endpoint_name, true, e);
+        })
+    }
 }
 
 #[cfg(test)]
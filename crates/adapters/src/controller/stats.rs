@@ -58,6 +58,12 @@ pub struct GlobalControllerMetrics {
     #[serde(serialize_with = "serialize_pipeline_state")]
     state: AtomicU32,
 
+    /// True if the pipeline is a standby that hasn't been activated yet.
+    pub standby: AtomicBool,
+
+    /// Number of steps the circuit has performed.
+    pub step: AtomicU64,
+
     /// Resident state size of the pipeline process.
     // This field is computed on-demand by calling `ControllerStatus::update`.
     #[cfg(any(target_os = "macos", target_os = "linux"))]
@@ -106,6 +112,8 @@ impl GlobalControllerMetrics {
     fn new() -> Self {
         Self {
             state: AtomicU32::from(PipelineState::Paused as u32),
+            standby: AtomicBool::new(false),
+            step: AtomicU64::new(0),
             #[cfg(any(target_os = "macos", target_os = "linux"))]
             rss_bytes: Some(AtomicU64::new(0)),
             buffered_input_records: AtomicU64::new(0),
@@ -221,6 +229,20 @@ impl ControllerStatus {
             .store(state as u32, Ordering::Release);
     }
 
+    pub fn is_standby(&self) -> bool {
+        self.global_metrics.standby.load(Ordering::Acquire)
+    }
+
+    pub fn set_standby(&self, standby: bool) {
+        self.global_metrics
+            .standby
+            .store(standby, Ordering::Release);
+    }
+
+    pub fn set_step(&self, step: Step) {
+        self.global_metrics.step.store(step, Ordering::Release);
+    }
+
     /// Initialize stats for a new input endpoint.
     pub fn add_input(
         &self,
@@ -451,6 +473,19 @@ impl ControllerStatus {
         })
     }
 
+    /// True if there is at least one input endpoint and every input endpoint
+    /// is fault tolerant and has already read all of its input for `step`.
+    /// This happens when the endpoints replay a step that was recorded
+    /// earlier, so the circuit can perform the step without waiting for
+    /// more input.
+    pub fn is_step_replayed(&self, step: Step) -> bool {
+        let inputs = self.inputs.read().unwrap();
+        !inputs.is_empty()
+            && inputs.values().all(|status| {
+                status.is_fault_tolerant && status.metrics.step.load(Ordering::Acquire) > step
+            })
+    }
+
     pub fn is_step_committed(&self, step: Step) -> bool {
         self.uncommitted_step()
             .map_or(true, |uncommitted_step| step < uncommitted_step)
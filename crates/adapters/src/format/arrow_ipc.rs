@@ -0,0 +1,454 @@
+//! Arrow IPC input and output formats.
+//!
+//! The parser accepts Arrow IPC streams with one column per column of the
+//! table.  All records are insertions.  Columns are matched to table columns by
+//! name.
+//!
+//! Each buffer produced by the encoder is a self-contained Arrow IPC stream
+//! with a single record batch.  The batch contains a column for each column
+//! of the relation, followed by the [`WEIGHT_COLUMN`] column, which stores
+//! the weight of each record.  Positive weights are insertions, negative
+//! weights are deletions.  Unlike formats without explicit weights, a record
+//! with weight `w` is output once rather than `|w|` times.
+
+use std::{borrow::Cow, io::Cursor, mem::take, sync::Arc};
+
+use actix_web::HttpRequest;
+use anyhow::Result as AnyResult;
+use arrow::array::{ArrayRef, Int64Array};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::json::{writer::LineDelimited, WriterBuilder};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::FormatOptions;
+use erased_serde::Serialize as ErasedSerialize;
+use serde::Deserialize;
+use serde_arrow::schema::SerdeArrowSchema;
+use serde_arrow::ArrowBuilder;
+use serde_urlencoded::Deserializer as UrlDeserializer;
+use serde_yaml::Value as YamlValue;
+
+use crate::catalog::{
+    CursorWithPolarity, DeCollectionStream, InputCollectionHandle, RecordFormat, SerBatchReader,
+};
+use crate::format::parquet::relation_to_parquet_schema;
+use crate::{
+    format::{Encoder, InputFormat, OutputFormat, ParseError, Parser},
+    ControllerError, OutputConsumer,
+};
+use pipeline_types::format::arrow_ipc::{ArrowIpcEncoderConfig, ArrowIpcParserConfig};
+use pipeline_types::format::json::JsonFlavor;
+use pipeline_types::program_schema::Relation;
+
+/// Name of the column that stores record weights.
+pub(crate) const WEIGHT_COLUMN: &str = "__weight";
+
+/// Arrow IPC format parser.
+pub struct ArrowIpcInputFormat;
+
+impl InputFormat for ArrowIpcInputFormat {
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed("arrow_ipc")
+    }
+
+    fn config_from_http_request(
+        &self,
+        _endpoint_name: &str,
+        _request: &HttpRequest,
+    ) -> Result<Box<dyn ErasedSerialize>, ControllerError> {
+        Ok(Box::new(ArrowIpcParserConfig {}))
+    }
+
+    fn new_parser(
+        &self,
+        _endpoint_name: &str,
+        input_stream: &InputCollectionHandle,
+        _config: &YamlValue,
+    ) -> Result<Box<dyn Parser>, ControllerError> {
+        let input_stream = input_stream
+            .handle
+            .configure_deserializer(RecordFormat::Json(JsonFlavor::Default))?;
+        Ok(Box::new(ArrowIpcParser::new(input_stream)) as Box<dyn Parser>)
+    }
+}
+
+/// Convert temporal columns of `batch` to strings in the format expected by
+/// the default JSON flavor.
+fn temporal_columns_to_strings(batch: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let options = CastOptions {
+        safe: false,
+        format_options: FormatOptions::new()
+            .with_date_format(Some("%Y-%m-%d"))
+            .with_time_format(Some("%H:%M:%S%.f"))
+            .with_timestamp_format(Some("%F %T%.f")),
+    };
+
+    let schema = batch.schema();
+    let mut fields = Vec::with_capacity(schema.fields().len());
+    let mut columns = Vec::with_capacity(schema.fields().len());
+    for (field, column) in schema.fields().iter().zip(batch.columns()) {
+        if matches!(
+            field.data_type(),
+            DataType::Date32
+                | DataType::Date64
+                | DataType::Time32(_)
+                | DataType::Time64(_)
+                | DataType::Timestamp(_, None)
+        ) {
+            columns.push(cast_with_options(column, &DataType::Utf8, &options)?);
+            fields.push(Field::new(
+                field.name(),
+                DataType::Utf8,
+                field.is_nullable(),
+            ));
+        } else {
+            columns.push(column.clone());
+            fields.push(field.as_ref().clone());
+        }
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
+
+/// Encode the rows of `batch` as newline-delimited JSON objects.
+fn batch_to_json_rows(batch: &RecordBatch) -> Result<Vec<u8>, ArrowError> {
+    let batch = temporal_columns_to_strings(batch)?;
+    let mut writer = WriterBuilder::new()
+        .with_explicit_nulls(true)
+        .build::<_, LineDelimited>(Vec::new());
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(writer.into_inner())
+}
+
+struct ArrowIpcParser {
+    /// Input handle to push parsed data to.
+    input_stream: Box<dyn DeCollectionStream>,
+    buf: Vec<u8>,
+    last_event_number: u64,
+}
+
+impl ArrowIpcParser {
+    fn new(input_stream: Box<dyn DeCollectionStream>) -> Self {
+        Self {
+            input_stream,
+            buf: Vec::new(),
+            last_event_number: 0,
+        }
+    }
+
+    fn parse(&mut self) -> (usize, Vec<ParseError>) {
+        if self.buf.is_empty() {
+            return (0, vec![]);
+        }
+
+        let buf = take(&mut self.buf);
+        let reader = match StreamReader::try_new(Cursor::new(buf), None) {
+            Ok(reader) => reader,
+            Err(e) => {
+                return (
+                    0,
+                    vec![ParseError::bin_envelope_error(
+                        format!("Unable to read Arrow IPC stream: {e}."),
+                        &[],
+                        Some(Cow::from(
+                            "Make sure the provided data is a valid Arrow IPC stream.",
+                        )),
+                    )],
+                )
+            }
+        };
+
+        let (mut cnt, mut errors) = (0, vec![]);
+        for batch in reader {
+            let rows = match batch.and_then(|batch| batch_to_json_rows(&batch)) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    errors.push(ParseError::bin_envelope_error(
+                        format!("Unable to read a record batch from the Arrow IPC stream: {e}."),
+                        &[],
+                        None,
+                    ));
+                    break;
+                }
+            };
+            for record in rows.split(|b| *b == b'\n').filter(|r| !r.is_empty()) {
+                self.last_event_number += 1;
+                match self.input_stream.insert(record) {
+                    Ok(()) => cnt += 1,
+                    Err(e) => errors.push(ParseError::bin_event_error(
+                        format!("Error parsing record from Arrow IPC stream: {e}"),
+                        self.last_event_number,
+                        record,
+                        None,
+                    )),
+                }
+            }
+        }
+        self.input_stream.flush();
+        (cnt, errors)
+    }
+}
+
+impl Parser for ArrowIpcParser {
+    /// Buffers data until `eoi` is called.
+    fn input_fragment(&mut self, data: &[u8]) -> (usize, Vec<ParseError>) {
+        self.buf.extend_from_slice(data);
+        (0, vec![])
+    }
+
+    /// Parses `data`, which must be a complete Arrow IPC stream.
+    fn input_chunk(&mut self, data: &[u8]) -> (usize, Vec<ParseError>) {
+        self.buf.extend_from_slice(data);
+        self.parse()
+    }
+
+    fn eoi(&mut self) -> (usize, Vec<ParseError>) {
+        self.parse()
+    }
+
+    fn fork(&self) -> Box<dyn Parser> {
+        Box::new(Self::new(self.input_stream.fork()))
+    }
+}
+
+/// Arrow IPC format encoder.
+pub struct ArrowIpcOutputFormat;
+
+impl OutputFormat for ArrowIpcOutputFormat {
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed("arrow_ipc")
+    }
+
+    fn config_from_http_request(
+        &self,
+        endpoint_name: &str,
+        request: &HttpRequest,
+    ) -> Result<Box<dyn ErasedSerialize>, ControllerError> {
+        Ok(Box::new(
+            ArrowIpcEncoderConfig::deserialize(UrlDeserializer::new(form_urlencoded::parse(
+                request.query_string().as_bytes(),
+            )))
+            .map_err(|e| {
+                ControllerError::encoder_config_parse_error(
+                    endpoint_name,
+                    &e,
+                    request.query_string(),
+                )
+            })?,
+        ))
+    }
+
+    fn new_encoder(
+        &self,
+        endpoint_name: &str,
+        config: &YamlValue,
+        schema: &Relation,
+        consumer: Box<dyn OutputConsumer>,
+    ) -> Result<Box<dyn Encoder>, ControllerError> {
+        let config = ArrowIpcEncoderConfig::deserialize(config).map_err(|e| {
+            ControllerError::encoder_config_parse_error(
+                endpoint_name,
+                &e,
+                &serde_yaml::to_string(&config).unwrap_or_default(),
+            )
+        })?;
+        Ok(Box::new(ArrowIpcEncoder::new(consumer, config, schema)?))
+    }
+}
+
+struct ArrowIpcEncoder {
+    /// Consumer to push serialized data to.
+    output_consumer: Box<dyn OutputConsumer>,
+    config: ArrowIpcEncoderConfig,
+    /// Schema of the relation, used to serialize records.
+    arrow_schema: SerdeArrowSchema,
+    /// Schema of the record batches, including the weight column.
+    schema: SchemaRef,
+    buffer: Vec<u8>,
+}
+
+impl ArrowIpcEncoder {
+    fn new(
+        output_consumer: Box<dyn OutputConsumer>,
+        config: ArrowIpcEncoderConfig,
+        relation: &Relation,
+    ) -> Result<Self, ControllerError> {
+        let arrow_schema = relation_to_parquet_schema(relation)?;
+        let mut fields =
+            arrow_schema
+                .to_arrow_fields()
+                .map_err(|e| ControllerError::SchemaParseError {
+                    error: format!("Unable to convert schema to arrow: {e}"),
+                })?;
+        fields.push(Field::new(WEIGHT_COLUMN, DataType::Int64, false));
+
+        Ok(Self {
+            output_consumer,
+            config,
+            arrow_schema,
+            schema: Arc::new(Schema::new(fields)),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Write records accumulated in `builder` and `weights` as an IPC
+    /// stream and push it to the consumer.
+    fn flush(&mut self, builder: &mut ArrowBuilder, weights: &mut Vec<i64>) -> AnyResult<()> {
+        let num_records = weights.len();
+        let mut arrays = builder.build_arrays()?;
+        arrays.push(Arc::new(Int64Array::from(take(weights))) as ArrayRef);
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+
+        self.buffer.clear();
+        let mut writer = StreamWriter::try_new(&mut self.buffer, &self.schema)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        drop(writer);
+
+        self.output_consumer.push_buffer(&self.buffer, num_records);
+        Ok(())
+    }
+}
+
+impl Encoder for ArrowIpcEncoder {
+    fn consumer(&mut self) -> &mut dyn OutputConsumer {
+        self.output_consumer.as_mut()
+    }
+
+    fn encode(&mut self, batch: &dyn SerBatchReader) -> AnyResult<()> {
+        let fields = self.arrow_schema.to_arrow_fields()?;
+        let mut builder = ArrowBuilder::new(&fields)?;
+        let mut weights = Vec::new();
+
+        let mut cursor = CursorWithPolarity::new(
+            batch.cursor(RecordFormat::Parquet(self.arrow_schema.clone()))?,
+        );
+        while cursor.key_valid() {
+            if cursor.val_valid() {
+                let w = cursor.weight();
+                if w != 0 {
+                    cursor.serialize_key_to_arrow(&mut builder)?;
+                    weights.push(w);
+                }
+            }
+
+            if weights.len() >= self.config.buffer_size_records {
+                self.flush(&mut builder, &mut weights)?;
+            }
+            cursor.step_key();
+        }
+
+        if !weights.is_empty() {
+            self.flush(&mut builder, &mut weights)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    use arrow::array::{ArrayRef, Int64Array, RecordBatch};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::ipc::reader::StreamReader;
+    use arrow::ipc::writer::StreamWriter;
+    use dbsp::utils::Tup2;
+    use dbsp::OrdZSet;
+    use pipeline_types::format::arrow_ipc::ArrowIpcEncoderConfig;
+    use pretty_assertions::assert_eq;
+    use tempfile::NamedTempFile;
+
+    use super::{ArrowIpcEncoder, WEIGHT_COLUMN};
+    use crate::catalog::SerBatchReader;
+    use crate::format::parquet::test::TestStruct;
+    use crate::format::Encoder;
+    use crate::static_compile::seroutput::SerBatchImpl;
+    use crate::test::{mock_input_pipeline, wait, MockOutputConsumer, DEFAULT_TIMEOUT_MS};
+
+    #[test]
+    fn arrow_ipc_input() {
+        let test_data = TestStruct::data();
+        let temp_file = NamedTempFile::new().unwrap();
+        let config_str = format!(
+            r#"
+stream: test_input
+transport:
+    name: file_input
+    config:
+        path: {:?}
+        buffer_size_bytes: 5
+format:
+    name: arrow_ipc
+"#,
+            temp_file.path().to_str().unwrap()
+        );
+
+        let batch = RecordBatch::try_new(
+            TestStruct::schema(),
+            TestStruct::make_arrow_array(&test_data),
+        )
+        .unwrap();
+        let mut writer = StreamWriter::try_new(&temp_file, &TestStruct::schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+
+        let (endpoint, _consumer, zset) = mock_input_pipeline::<TestStruct, TestStruct>(
+            serde_yaml::from_str(&config_str).unwrap(),
+        )
+        .unwrap();
+        endpoint.start(0).unwrap();
+        wait(
+            || zset.state().flushed.len() == test_data.len(),
+            DEFAULT_TIMEOUT_MS,
+        );
+
+        for (i, upd) in zset.state().flushed.iter().enumerate() {
+            assert_eq!(upd.unwrap_insert(), &test_data[i]);
+        }
+    }
+
+    #[test]
+    fn arrow_ipc_output() {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let consumer = MockOutputConsumer::with_buffer(buffer.clone());
+
+        let test_data = TestStruct::data();
+        let mut encoder = ArrowIpcEncoder::new(
+            Box::new(consumer),
+            ArrowIpcEncoderConfig::default(),
+            &TestStruct::relation(),
+        )
+        .unwrap();
+        let zset = OrdZSet::from_keys(
+            (),
+            vec![
+                Tup2(test_data[0].clone(), 2),
+                Tup2(test_data[1].clone(), -1),
+            ],
+        );
+
+        let zset = &SerBatchImpl::<_, TestStruct, ()>::new(zset) as &dyn SerBatchReader;
+        encoder.encode(zset).unwrap();
+
+        let batches = StreamReader::try_new(Cursor::new(buffer.lock().unwrap().clone()), None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // Deletions are output before insertions.
+        let mut fields = TestStruct::schema().fields().to_vec();
+        fields.push(Arc::new(Field::new(WEIGHT_COLUMN, DataType::Int64, false)));
+        let mut columns =
+            TestStruct::make_arrow_array(&[test_data[1].clone(), test_data[0].clone()]);
+        columns.push(Arc::new(Int64Array::from(vec![-1, 2])) as ArrayRef);
+        let expected = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap();
+
+        assert_eq!(batches, vec![expected]);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::catalog::{InputCollectionHandle, SerBatchReader};
+use crate::format::arrow_ipc::{ArrowIpcInputFormat, ArrowIpcOutputFormat};
 use crate::format::parquet::{ParquetInputFormat, ParquetOutputFormat};
 use crate::{transport::Step, ControllerError};
 use actix_web::HttpRequest;
@@ -16,9 +17,10 @@ use std::{
     fmt::{Display, Error as FmtError, Formatter},
 };
 
+mod arrow_ipc;
 pub(crate) mod csv;
 mod json;
-mod parquet;
+pub(crate) mod parquet;
 
 pub use self::csv::{byte_record_deserializer, string_record_deserializer};
 use self::{
@@ -317,6 +319,10 @@ impl ParseErrorInner {
 // external crates to implement new formats.
 static INPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn InputFormat>>> = Lazy::new(|| {
     BTreeMap::from([
+        (
+            "arrow_ipc",
+            Box::new(ArrowIpcInputFormat) as Box<dyn InputFormat>,
+        ),
         ("csv", Box::new(CsvInputFormat) as Box<dyn InputFormat>),
         ("json", Box::new(JsonInputFormat) as Box<dyn InputFormat>),
         (
@@ -329,6 +335,10 @@ static INPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn InputFormat>>> = Lazy:
 /// Static map of supported output formats.
 static OUTPUT_FORMATS: Lazy<BTreeMap<&'static str, Box<dyn OutputFormat>>> = Lazy::new(|| {
     BTreeMap::from([
+        (
+            "arrow_ipc",
+            Box::new(ArrowIpcOutputFormat) as Box<dyn OutputFormat>,
+        ),
         ("csv", Box::new(CsvOutputFormat) as Box<dyn OutputFormat>),
         ("json", Box::new(JsonOutputFormat) as Box<dyn OutputFormat>),
         (
@@ -499,4 +509,8 @@ pub trait OutputConsumer: Send {
     fn push_buffer(&mut self, buffer: &[u8], num_records: usize);
     fn push_key(&mut self, key: &[u8], val: &[u8], num_records: usize);
     fn batch_end(&mut self);
+
+    /// Makes the consumer of a [standby](crate#standby-pipelines) pipeline
+    /// take over from the other instance of the pipeline.
+    fn activate(&mut self) {}
 }
@@ -29,7 +29,7 @@ use pipeline_types::format::parquet::{ParquetEncoderConfig, ParquetParserConfig}
 use pipeline_types::program_schema::{ColumnType, Relation, SqlType};
 
 #[cfg(test)]
-mod test;
+pub(crate) mod test;
 
 /// CSV format parser.
 pub struct ParquetInputFormat;
@@ -222,7 +222,9 @@ impl OutputFormat for ParquetOutputFormat {
     }
 }
 
-fn relation_to_parquet_schema(relation: &Relation) -> Result<SerdeArrowSchema, ControllerError> {
+pub(crate) fn relation_to_parquet_schema(
+    relation: &Relation,
+) -> Result<SerdeArrowSchema, ControllerError> {
     // The type conversion is chosen in accordance with our internal
     // data types (see sqllib). This may need to be adjusted in the future
     // or made configurable.
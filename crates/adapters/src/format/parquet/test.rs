@@ -45,7 +45,7 @@ use pipeline_types::{deserialize_table_record, serialize_table_record};
 )]
 #[archive_attr(derive(Clone, Ord, Eq, PartialEq, PartialOrd))]
 #[archive(compare(PartialEq, PartialOrd))]
-struct TestStruct {
+pub(crate) struct TestStruct {
     #[serde(rename = "id")]
     field: i64,
     #[serde(rename = "name")]
@@ -61,7 +61,7 @@ struct TestStruct {
 }
 
 impl TestStruct {
-    fn data() -> Vec<TestStruct> {
+    pub(crate) fn data() -> Vec<TestStruct> {
         vec![
             TestStruct {
                 field: 1,
@@ -82,7 +82,7 @@ impl TestStruct {
         ]
     }
 
-    fn schema() -> Arc<Schema> {
+    pub(crate) fn schema() -> Arc<Schema> {
         Arc::new(Schema::new(vec![
             arrow::datatypes::Field::new("id", DataType::Int64, false),
             arrow::datatypes::Field::new("name", DataType::LargeUtf8, true),
@@ -97,7 +97,7 @@ impl TestStruct {
         ]))
     }
 
-    fn relation() -> Relation {
+    pub(crate) fn relation() -> Relation {
         Relation::new(
             "TestStruct",
             false,
@@ -172,7 +172,7 @@ impl TestStruct {
         )
     }
 
-    fn make_arrow_array(data: &[TestStruct]) -> Vec<ArrayRef> {
+    pub(crate) fn make_arrow_array(data: &[TestStruct]) -> Vec<ArrayRef> {
         let row0: Vec<i64> = data.iter().map(|r| r.field).collect();
         let row1: Vec<Option<String>> = data.iter().map(|r| r.field_0.clone()).collect();
         let row2: Vec<bool> = data.iter().map(|r| r.field_1).collect();
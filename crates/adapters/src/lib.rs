@@ -143,6 +143,19 @@
 //! given a sequence of inputs, will always produce the same sequence of
 //! outputs.  Most circuits used to analyze data are deterministic.
 //!
+//! ## Standby pipelines
+//!
+//! Fault tolerance also allows a new version of a circuit to take over from a
+//! running instance of a pipeline without losing or duplicating any of its
+//! input or output.  The new instance is created as a standby (see
+//! [`Controller::standby_with_config`]).  Its fault-tolerant input endpoints
+//! replay the steps recorded by the running instance, following it as it
+//! records more, and its fault-tolerant output endpoints hold back the output
+//! of the steps that the running instance hasn't output yet.  Once the
+//! running instance has stopped, [`Controller::activate`] makes the standby
+//! record steps of its own and output the steps that the running instance
+//! didn't.
+//!
 //! [`Step`]: crate::transport::Step
 
 use num_derive::FromPrimitive;
@@ -180,14 +193,15 @@ pub use circuit_handle::DbspCircuitHandle;
 pub use server::{ErrorResponse, PipelineError};
 
 pub use catalog::{
-    Catalog, CircuitCatalog, DeCollectionHandle, DeCollectionStream, OutputQueryHandles,
-    RecordFormat, SerBatch, SerCollectionHandle, SerCursor,
+    Catalog, CircuitCatalog, DeCollectionHandle, DeCollectionStream, InputCollectionHandle,
+    OutputQueryHandles, RecordFormat, SerBatch, SerCollectionHandle, SerCursor, SerLookupHandle,
 };
 pub use format::{Encoder, InputFormat, OutputConsumer, OutputFormat, ParseError, Parser};
 
 pub use controller::{
     ConfigError, ConnectorConfig, Controller, ControllerError, ControllerStatus, FormatConfig,
-    InputEndpointConfig, OutputEndpointConfig, PipelineConfig, RuntimeConfig, TransportConfig,
+    InputEndpointConfig, OutputEndpointConfig, PipelineConfig, RuntimeConfig, StepCompletion,
+    TransportConfig,
 };
 pub use transport::{
     AsyncErrorCallback, InputConsumer, InputEndpoint, InputReader, OutputEndpoint,
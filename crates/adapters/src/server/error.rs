@@ -154,7 +154,11 @@ pub enum PipelineError {
         param: &'static str,
     },
     ApiConnectionLimit,
-    TableSnapshotNotImplemented,
+    TableSnapshotNotSupported,
+    InvalidTableSnapshotSpec {
+        spec: JsonValue,
+        parse_error: String,
+    },
     QuantileStreamingNotSupported,
     NumQuantilesOutOfRange {
         quantiles: u32,
@@ -166,6 +170,10 @@ pub enum PipelineError {
         parse_error: String,
     },
     NeighborhoodNotSupported,
+    LookupNotSupported,
+    InvalidLookupKeys {
+        parse_error: String,
+    },
     ControllerError {
         // Fold `ControllerError` directly into `PipelineError` to simplify
         // the error hierarchy from the user's pespective.
@@ -215,8 +223,11 @@ impl Display for PipelineError {
             Self::QuantilesNotSupported => {
                 f.write_str("Quantiles queries are not supported for this table.")
             }
-            Self::TableSnapshotNotImplemented => {
-                f.write_str("Taking a snapshot of a table or view is not yet supported.")
+            Self::TableSnapshotNotSupported => {
+                f.write_str("Snapshot queries are not supported for this table. Enable `materialized` in the configuration of the program to maintain the contents of its tables and views.")
+            }
+            Self::InvalidTableSnapshotSpec{spec, parse_error} => {
+                write!(f, "Unable to parse snapshot query '{spec}'. Error returned by the parser: '{parse_error}'.")
             }
             Self::MissingNeighborhoodSpec => {
                 f.write_str(r#"Neighborhood request must specify neighborhood in the body of the request: '{"anchor": ..., "before": 100, "after": 100}'."#)
@@ -230,6 +241,12 @@ impl Display for PipelineError {
             Self::NeighborhoodNotSupported => {
                 f.write_str("Neighborhood queries are not supported for this table.")
             }
+            Self::LookupNotSupported => {
+                f.write_str("Point lookups are only supported for views declared with a primary key.")
+            }
+            Self::InvalidLookupKeys{parse_error} => {
+                write!(f, "Unable to parse lookup keys. The body of the request must contain a JSON array of keys. Error returned by the parser: '{parse_error}'.")
+            }
             Self::ControllerError{ error } => {
                 error.fmt(f)
             }
@@ -263,9 +280,12 @@ impl DetailedError for PipelineError {
             Self::ApiConnectionLimit => Cow::from("ApiConnectionLimit"),
             Self::QuantileStreamingNotSupported => Cow::from("QuantileStreamingNotSupported"),
             Self::QuantilesNotSupported => Cow::from("QuantilesNotSupported"),
-            Self::TableSnapshotNotImplemented => Cow::from("TableSnapshotNotImplemented"),
+            Self::TableSnapshotNotSupported => Cow::from("TableSnapshotNotSupported"),
+            Self::InvalidTableSnapshotSpec { .. } => Cow::from("InvalidTableSnapshotSpec"),
             Self::MissingNeighborhoodSpec => Cow::from("MissingNeighborhoodSpec"),
             Self::NeighborhoodNotSupported => Cow::from("NeighborhoodNotSupported"),
+            Self::LookupNotSupported => Cow::from("LookupNotSupported"),
+            Self::InvalidLookupKeys { .. } => Cow::from("InvalidLookupKeys"),
             Self::NumQuantilesOutOfRange { .. } => Cow::from("NumQuantilesOutOfRange"),
             Self::InvalidNeighborhoodSpec { .. } => Cow::from("InvalidNeighborhoodSpec"),
             Self::ParseErrors { .. } => Cow::from("ParseErrors"),
@@ -316,9 +336,12 @@ impl ResponseError for PipelineError {
             Self::ApiConnectionLimit => StatusCode::TOO_MANY_REQUESTS,
             Self::QuantileStreamingNotSupported => StatusCode::METHOD_NOT_ALLOWED,
             Self::QuantilesNotSupported => StatusCode::METHOD_NOT_ALLOWED,
-            Self::TableSnapshotNotImplemented => StatusCode::NOT_IMPLEMENTED,
+            Self::TableSnapshotNotSupported => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InvalidTableSnapshotSpec { .. } => StatusCode::BAD_REQUEST,
             Self::MissingNeighborhoodSpec => StatusCode::BAD_REQUEST,
             Self::NeighborhoodNotSupported => StatusCode::METHOD_NOT_ALLOWED,
+            Self::LookupNotSupported => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InvalidLookupKeys { .. } => StatusCode::BAD_REQUEST,
             Self::NumQuantilesOutOfRange { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
             Self::InvalidNeighborhoodSpec { .. } => StatusCode::BAD_REQUEST,
             Self::ParseErrors { .. } => StatusCode::BAD_REQUEST,
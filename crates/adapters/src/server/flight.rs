@@ -0,0 +1,806 @@
+//! Arrow Flight service for pipeline data access.
+//!
+//! The service runs next to the HTTP server when the pipeline is started with
+//! `--flight-port`.  It supports two Flight methods:
+//!
+//! * `DoPut` pushes record batches to an input table.  The table name is the
+//!   only element of the path of the Flight descriptor attached to the first
+//!   message of the stream.  The server acknowledges each record batch with a
+//!   `PutResult` message.  When a batch contains records that could not be
+//!   ingested, `app_metadata` of the corresponding message contains a JSON
+//!   [`ErrorResponse`] describing the parse errors.  Valid records in the
+//!   batch are ingested regardless.
+//!
+//!   Record batches are forwarded to the `arrow_ipc` parser as Arrow IPC
+//!   streams without being decoded by the Flight service.
+//!
+//! * `DoGet` streams the contents of a table or view.  The ticket is a JSON
+//!   object of the form
+//!   `{"table": "<name>", "mode": "watch" | "snapshot" | "snapshot_and_watch"}`.
+//!   In the `watch` mode (default), the server streams deltas produced by the
+//!   pipeline until the client disconnects.  In the `snapshot` mode, it sends
+//!   the current contents of the table or view and closes the stream.  In the
+//!   `snapshot_and_watch` mode, it sends the current contents followed by
+//!   deltas.  Snapshots require the program to be compiled with
+//!   `materialized` enabled.  Record
+//!   batches are produced by the `arrow_ipc` encoder: they have one column per
+//!   column of the relation plus an `Int64` `__weight` column, where positive
+//!   weights are insertions and negative weights are deletions.
+//!
+//! All other methods return `UNIMPLEMENTED`.
+
+use super::{missing_controller_error, ErrorResponse, PipelineError, ServerState};
+use crate::{
+    catalog::RecordFormat,
+    controller::{ConnectorConfig, EndpointId},
+    transport::{
+        http::{HttpInputEndpoint, HttpInputTransport, HttpOutputTransport},
+        AsyncErrorCallback,
+    },
+    ControllerError, FormatConfig, InputEndpoint, InputEndpointConfig, OutputEndpoint,
+    OutputEndpointConfig,
+};
+use actix_web::{http::StatusCode, ResponseError};
+use anyhow::{bail, Result as AnyResult};
+use arrow::{
+    ipc::{
+        reader::StreamReader,
+        root_as_message,
+        writer::{write_message, EncodedData, IpcWriteOptions},
+        MessageHeader,
+    },
+    record_batch::RecordBatch,
+};
+use arrow_flight::{
+    encode::FlightDataEncoderBuilder,
+    error::FlightError,
+    flight_service_server::{FlightService, FlightServiceServer},
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaResult, Ticket,
+};
+use async_stream::stream;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use log::{debug, error, info};
+use pipeline_types::{
+    config::OutputBufferConfig,
+    format::{
+        arrow_ipc::{ArrowIpcEncoderConfig, ArrowIpcParserConfig},
+        json::JsonFlavor,
+    },
+    query::OutputQuery,
+    transport::http::EgressMode,
+};
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    io::{Cursor, Error as IoError, ErrorKind as IoErrorKind},
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::mpsc;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+/// Maximal number of encoded buffers queued for a `DoGet` client before
+/// the pipeline blocks waiting for the client to catch up.
+const MAX_BUFFERS: usize = 16;
+
+/// Contents of a `DoGet` ticket.
+#[derive(Debug, Deserialize)]
+struct FlightTicket {
+    /// Table or view to read.
+    table: String,
+
+    /// Stream deltas, read a single snapshot, or both.
+    #[serde(default)]
+    mode: EgressMode,
+}
+
+/// Resolve the address to run the Flight server on.
+pub(super) fn resolve_address(
+    bind_address: &str,
+    port: u16,
+) -> Result<SocketAddr, ControllerError> {
+    (bind_address, port)
+        .to_socket_addrs()
+        .and_then(|mut addresses| {
+            addresses
+                .next()
+                .ok_or_else(|| IoError::from(IoErrorKind::AddrNotAvailable))
+        })
+        .map_err(|e| {
+            ControllerError::io_error(
+                format!("resolving Arrow Flight server address '{bind_address}:{port}'"),
+                e,
+            )
+        })
+}
+
+/// Run the Flight server on `address` until the process terminates.
+pub(super) async fn serve(address: SocketAddr, state: Arc<ServerState>) {
+    info!("Started Arrow Flight server on {address}");
+
+    let service = FlightServiceServer::new(PipelineFlightService { state });
+    if let Err(e) = Server::builder().add_service(service).serve(address).await {
+        error!("Arrow Flight server terminated with an error: {e}");
+    }
+}
+
+/// Convert a pipeline error to the gRPC status closest to the HTTP status
+/// code returned by the REST API for the same error.
+fn status_from_error(error: PipelineError) -> Status {
+    let message = ErrorResponse::from_error(&error).message;
+    match error.status_code() {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::GONE => Status::unavailable(message),
+        StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// Unregisters an API endpoint from the controller when dropped.
+struct EndpointGuard {
+    state: Weak<ServerState>,
+    endpoint_id: EndpointId,
+    is_input: bool,
+}
+
+impl Drop for EndpointGuard {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.upgrade() {
+            // See comment in the finalizer in `output_endpoint`.
+            if let Ok(guard) = state.controller.lock() {
+                if let Some(controller) = guard.as_ref() {
+                    if self.is_input {
+                        controller.disconnect_input(&self.endpoint_id);
+                    } else {
+                        controller.disconnect_output(&self.endpoint_id);
+                    }
+                    controller.unregister_api_connection();
+                }
+            }
+        }
+    }
+}
+
+/// Output endpoint that forwards Arrow IPC buffers to a `DoGet` stream.
+#[derive(Clone)]
+struct FlightOutputEndpoint {
+    sender: Arc<Mutex<Option<mpsc::Sender<Bytes>>>>,
+    stream: bool,
+}
+
+impl FlightOutputEndpoint {
+    fn new(sender: mpsc::Sender<Bytes>, stream: bool) -> Self {
+        Self {
+            sender: Arc::new(Mutex::new(Some(sender))),
+            stream,
+        }
+    }
+}
+
+impl OutputEndpoint for FlightOutputEndpoint {
+    fn connect(&mut self, _async_error_callback: AsyncErrorCallback) -> AnyResult<()> {
+        Ok(())
+    }
+
+    fn max_buffer_size_bytes(&self) -> usize {
+        usize::MAX
+    }
+
+    fn push_buffer(&mut self, buffer: &[u8]) -> AnyResult<()> {
+        let sender = self.sender.lock().unwrap().clone();
+        if let Some(sender) = sender {
+            // A failure means that the client has disconnected.  The endpoint
+            // will be removed once the stream is dropped.
+            let _ = sender.blocking_send(Bytes::copy_from_slice(buffer));
+        }
+        Ok(())
+    }
+
+    fn push_key(&mut self, _key: &[u8], _val: &[u8]) -> AnyResult<()> {
+        bail!("Arrow Flight transport does not support key-value pairs.");
+    }
+
+    fn batch_end(&mut self) -> AnyResult<()> {
+        // In the snapshot mode, close the stream after the first batch.
+        if !self.stream {
+            *self.sender.lock().unwrap() = None;
+        }
+        Ok(())
+    }
+
+    fn is_fault_tolerant(&self) -> bool {
+        false
+    }
+}
+
+/// End-of-stream marker of the Arrow IPC streaming format.
+const IPC_EOS: [u8; 8] = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+
+/// Frame the IPC message carried by `data` as it would appear in an Arrow IPC
+/// stream, without decoding it.
+fn ipc_frame(data: &FlightData) -> Result<Vec<u8>, Status> {
+    let mut frame = Vec::new();
+    write_message(
+        &mut frame,
+        EncodedData {
+            ipc_message: data.data_header.to_vec(),
+            arrow_data: data.data_body.to_vec(),
+        },
+        &IpcWriteOptions::default(),
+    )
+    .map_err(|e| Status::internal(format!("error framing IPC message: {e}")))?;
+    Ok(frame)
+}
+
+/// Assembles the messages of a `DoPut` stream into self-contained Arrow IPC
+/// streams, one per record batch, that can be fed to the `arrow_ipc` parser.
+#[derive(Default)]
+struct IpcStreamAssembler {
+    /// Framed schema message.
+    schema: Option<Vec<u8>>,
+
+    /// Framed dictionary batches received since the schema.
+    dictionaries: Vec<u8>,
+}
+
+impl IpcStreamAssembler {
+    /// Process the next message of the stream.  Returns an IPC stream once a
+    /// record batch is received.
+    fn push(&mut self, data: &FlightData) -> Result<Option<Vec<u8>>, Status> {
+        // Clients may send the descriptor in a message without a payload.
+        if data.data_header.is_empty() {
+            return Ok(None);
+        }
+
+        let header_type = root_as_message(&data.data_header)
+            .map_err(|e| Status::invalid_argument(format!("invalid IPC message: {e}")))?
+            .header_type();
+        match header_type {
+            MessageHeader::Schema => {
+                self.schema = Some(ipc_frame(data)?);
+                self.dictionaries.clear();
+                Ok(None)
+            }
+            MessageHeader::DictionaryBatch => {
+                self.dictionaries.extend(ipc_frame(data)?);
+                Ok(None)
+            }
+            MessageHeader::RecordBatch => {
+                let Some(schema) = &self.schema else {
+                    return Err(Status::invalid_argument(
+                        "record batch received before the schema",
+                    ));
+                };
+                let mut stream = schema.clone();
+                stream.extend_from_slice(&self.dictionaries);
+                stream.extend(ipc_frame(data)?);
+                stream.extend_from_slice(&IPC_EOS);
+                Ok(Some(stream))
+            }
+            other => Err(Status::invalid_argument(format!(
+                "unexpected IPC message type {other:?}"
+            ))),
+        }
+    }
+}
+
+/// Decode a buffer produced by the `arrow_ipc` encoder.
+fn decode_ipc(buffer: Bytes) -> Result<Vec<RecordBatch>, FlightError> {
+    StreamReader::try_new(Cursor::new(buffer), None)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(FlightError::from)
+}
+
+struct PipelineFlightService {
+    state: Arc<ServerState>,
+}
+
+impl PipelineFlightService {
+    /// Connect an input endpoint for `table_name` that expects Arrow IPC
+    /// streams.
+    fn connect_input(
+        &self,
+        table_name: &str,
+    ) -> Result<(HttpInputEndpoint, EndpointGuard), PipelineError> {
+        let endpoint_name = format!("api-flight-ingress-{table_name}-{}", Uuid::new_v4());
+        let endpoint = HttpInputEndpoint::new(&endpoint_name, false);
+        let config = InputEndpointConfig {
+            stream: Cow::from(table_name.to_string()),
+            connector_config: ConnectorConfig {
+                transport: HttpInputTransport::config(),
+                format: FormatConfig {
+                    name: Cow::from("arrow_ipc"),
+                    // Serializing this type cannot fail.
+                    config: serde_yaml::to_value(ArrowIpcParserConfig {}).unwrap(),
+                },
+                max_buffered_records: HttpInputTransport::default_max_buffered_records(),
+            },
+        };
+
+        match &*self.state.controller.lock().unwrap() {
+            Some(controller) => {
+                if controller.register_api_connection().is_err() {
+                    return Err(PipelineError::ApiConnectionLimit);
+                }
+
+                match controller.add_input_endpoint(
+                    &endpoint_name,
+                    config,
+                    Box::new(endpoint.clone()) as Box<dyn InputEndpoint>,
+                ) {
+                    Ok(endpoint_id) => Ok((
+                        endpoint,
+                        EndpointGuard {
+                            state: Arc::downgrade(&self.state),
+                            endpoint_id,
+                            is_input: true,
+                        },
+                    )),
+                    Err(e) => {
+                        controller.unregister_api_connection();
+                        Err(e.into())
+                    }
+                }
+            }
+            None => Err(missing_controller_error(&self.state)),
+        }
+    }
+
+    /// Connect an output endpoint that sends the contents of `ticket.table`
+    /// encoded as Arrow IPC buffers to the returned channel.
+    fn connect_output(
+        &self,
+        ticket: FlightTicket,
+    ) -> Result<(mpsc::Receiver<Bytes>, EndpointGuard), PipelineError> {
+        let snapshot = ticket.mode != EgressMode::Watch;
+        let stream = ticket.mode != EgressMode::Snapshot;
+        let table = ticket.table.clone();
+        let endpoint_name = format!(
+            "api-flight-{}-{}-{}",
+            match ticket.mode {
+                EgressMode::Watch => "watch",
+                EgressMode::Snapshot => "snapshot",
+                EgressMode::SnapshotAndWatch => "snapshot-and-watch",
+            },
+            ticket.table,
+            Uuid::new_v4()
+        );
+        let (sender, receiver) = mpsc::channel(MAX_BUFFERS);
+        let endpoint = FlightOutputEndpoint::new(sender, stream);
+        let config = OutputEndpointConfig {
+            stream: Cow::from(ticket.table),
+            query: OutputQuery::Table,
+            snapshot,
+            output_buffer_config: OutputBufferConfig::default(),
+            connector_config: ConnectorConfig {
+                transport: HttpOutputTransport::config(),
+                format: FormatConfig {
+                    name: Cow::from("arrow_ipc"),
+                    // Serializing this type cannot fail.
+                    config: serde_yaml::to_value(ArrowIpcEncoderConfig::default()).unwrap(),
+                },
+                max_buffered_records: HttpOutputTransport::default_max_buffered_records(),
+            },
+        };
+
+        let guard = match &*self.state.controller.lock().unwrap() {
+            Some(controller) => {
+                if controller.register_api_connection().is_err() {
+                    return Err(PipelineError::ApiConnectionLimit);
+                }
+
+                match controller.add_output_endpoint(
+                    &endpoint_name,
+                    &config,
+                    Box::new(endpoint) as Box<dyn OutputEndpoint>,
+                ) {
+                    Ok(endpoint_id) => EndpointGuard {
+                        state: Arc::downgrade(&self.state),
+                        endpoint_id,
+                        is_input: false,
+                    },
+                    Err(e) => {
+                        controller.unregister_api_connection();
+                        return Err(e.into());
+                    }
+                }
+            }
+            None => return Err(missing_controller_error(&self.state)),
+        };
+
+        // The guard locks the controller when dropped, so it must not go out
+        // of scope while the lock above is held.
+        if snapshot {
+            self.request_snapshot(&table)?;
+        }
+
+        Ok((receiver, guard))
+    }
+
+    /// Ask the circuit to produce a snapshot of `table` during the next step,
+    /// same as the REST API does for `query=table&mode=snapshot`.
+    fn request_snapshot(&self, table: &str) -> Result<(), PipelineError> {
+        match &*self.state.controller.lock().unwrap() {
+            Some(controller) => {
+                if let Err(e) = controller
+                    .catalog()
+                    .lock()
+                    .unwrap()
+                    .output_handles(table)
+                    .unwrap()
+                    .snapshot_descr_handle
+                    .as_ref()
+                    .ok_or(PipelineError::TableSnapshotNotSupported)?
+                    .configure_deserializer(RecordFormat::Json(JsonFlavor::Default))?
+                    .set_for_all(b"[true, null]")
+                {
+                    return Err(PipelineError::InvalidTableSnapshotSpec {
+                        spec: serde_json::Value::Null,
+                        parse_error: e.to_string(),
+                    });
+                }
+                controller.request_step();
+                Ok(())
+            }
+            None => Err(missing_controller_error(&self.state)),
+        }
+    }
+}
+
+/// Request a step so that small inputs don't linger in the input buffer
+/// waiting for `min_batch_size_records` to be reached.
+fn request_step(state: &ServerState) {
+    if let Some(controller) = state.controller.lock().unwrap().as_ref() {
+        controller.request_step();
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for PipelineFlightService {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("handshake is not supported"))
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("ListFlights is not supported"))
+    }
+
+    async fn get_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        Err(Status::unimplemented("GetFlightInfo is not supported"))
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented("PollFlightInfo is not supported"))
+    }
+
+    async fn get_schema(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("GetSchema is not supported"))
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let ticket: FlightTicket = serde_json::from_slice(&request.into_inner().ticket)
+            .map_err(|e| Status::invalid_argument(format!("invalid ticket: {e}")))?;
+        debug!("Flight DoGet: {ticket:?}");
+
+        let (mut receiver, guard) = self.connect_output(ticket).map_err(status_from_error)?;
+
+        let batches = stream! {
+            // Disconnect the endpoint when the client drops the stream.
+            let _guard = guard;
+            while let Some(buffer) = receiver.recv().await {
+                match decode_ipc(buffer) {
+                    Ok(batches) => {
+                        for batch in batches {
+                            yield Ok(batch);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                }
+            }
+        };
+
+        let flight_data = FlightDataEncoderBuilder::new()
+            .build(batches)
+            .map_err(Status::from);
+        Ok(Response::new(flight_data.boxed()))
+    }
+
+    async fn do_put(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        let mut input = request.into_inner();
+
+        // The first message carries the descriptor of the target table.
+        let first = input
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("empty DoPut stream"))?;
+        let table_name = match &first.flight_descriptor {
+            Some(descriptor) if descriptor.path.len() == 1 => descriptor.path[0].clone(),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "DoPut requires a descriptor with a path consisting of the table name",
+                ))
+            }
+        };
+        debug!("Flight DoPut: table '{table_name}'");
+
+        let (endpoint, guard) = self.connect_input(&table_name).map_err(status_from_error)?;
+        let state = self.state.clone();
+
+        // Acknowledge each record batch as soon as it has been ingested.
+        let results = stream! {
+            // Disconnect the endpoint when the client drops the stream.
+            let _guard = guard;
+            let mut assembler = IpcStreamAssembler::default();
+            let mut input = futures::stream::once(async { Ok::<_, Status>(first) }).chain(input);
+            while let Some(data) = input.next().await {
+                let chunk = match data.and_then(|data| assembler.push(&data)) {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                };
+                let errors = match endpoint.complete_chunk(&chunk).await {
+                    Ok(errors) => errors,
+                    Err(e) => {
+                        yield Err(status_from_error(e));
+                        break;
+                    }
+                };
+                request_step(&state);
+
+                let app_metadata = if errors.is_empty() {
+                    Bytes::new()
+                } else {
+                    let error = PipelineError::parse_errors(errors.len(), errors.iter());
+                    // Serializing this type cannot fail.
+                    Bytes::from(serde_json::to_vec(&ErrorResponse::from_error(&error)).unwrap())
+                };
+                yield Ok(PutResult { app_metadata });
+            }
+        };
+
+        Ok(Response::new(results.boxed()))
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("DoAction is not supported"))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Err(Status::unimplemented("ListActions is not supported"))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("DoExchange is not supported"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::serve;
+    use crate::{
+        format::parquet::test::TestStruct,
+        server::{bootstrap, ServerArgs, ServerState},
+        Catalog, CircuitCatalog, DbspCircuitHandle,
+    };
+    use actix_web::web::Data as WebData;
+    use arrow::{array::Int64Array, record_batch::RecordBatch};
+    use arrow_flight::{
+        encode::FlightDataEncoderBuilder, error::FlightError, FlightClient, FlightDescriptor,
+        Ticket,
+    };
+    use dbsp::{circuit::CircuitConfig, Runtime};
+    use futures::{channel::mpsc, StreamExt, TryStreamExt};
+    use pipeline_types::program_schema::Relation;
+    use std::{
+        io::Write,
+        net::TcpListener,
+        thread,
+        time::{Duration, Instant},
+    };
+    use tempfile::NamedTempFile;
+    use tonic::{transport::Channel, Code};
+
+    /// Circuit with a single materialized table with a real schema, which
+    /// the Arrow IPC parser and encoder require.
+    fn flight_test_circuit(
+        config: CircuitConfig,
+    ) -> (Box<dyn DbspCircuitHandle>, Box<dyn CircuitCatalog>) {
+        let (circuit, catalog) = Runtime::init_circuit(config, |circuit| {
+            let mut catalog = Catalog::new();
+            let (input, hinput) = circuit.add_input_zset::<TestStruct>();
+
+            let mut schema = Relation::new("test_table", false, TestStruct::relation().fields);
+            schema.materialized = true;
+            let schema = serde_json::to_string(&schema).unwrap();
+
+            catalog.register_input_zset::<_, TestStruct>(input.clone(), hinput, &schema);
+            catalog.register_output_zset(input, &schema);
+
+            Ok(catalog)
+        })
+        .unwrap();
+        (Box::new(circuit), Box::new(catalog))
+    }
+
+    /// Read a snapshot of `test_table` and return the values of its `id`
+    /// column.
+    async fn snapshot_ids(client: &mut FlightClient) -> Vec<i64> {
+        let batches: Vec<RecordBatch> = client
+            .do_get(Ticket::new(
+                r#"{"table": "test_table", "mode": "snapshot"}"#,
+            ))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for batch in batches {
+            let column = batch.column_by_name("id").unwrap();
+            let column = column.as_any().downcast_ref::<Int64Array>().unwrap();
+            ids.extend(column.values().iter().copied());
+        }
+        ids.sort();
+        ids
+    }
+
+    #[actix_web::test]
+    async fn test_flight() {
+        let mut config_file = NamedTempFile::new().unwrap();
+        config_file.write_all(b"name: test\ninputs: {}\n").unwrap();
+
+        let state = WebData::new(ServerState::new(None));
+        let state_clone = state.clone();
+
+        let args = ServerArgs {
+            config_file: config_file.path().display().to_string(),
+            metadata_file: None,
+            bind_address: "127.0.0.1".to_string(),
+            default_port: None,
+            flight_port: None,
+            storage_location: None,
+            standby: false,
+        };
+        thread::spawn(move || {
+            bootstrap(
+                args,
+                |workers| Ok(flight_test_circuit(workers)),
+                state_clone,
+                std::sync::mpsc::channel().0,
+            )
+        });
+
+        let start = Instant::now();
+        while state.controller.lock().unwrap().is_none() {
+            assert!(start.elapsed() < Duration::from_millis(20_000));
+            actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        }
+        state.controller.lock().unwrap().as_ref().unwrap().start();
+
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        actix_web::rt::spawn(serve(address, state.clone().into_inner()));
+
+        let channel = loop {
+            match Channel::from_shared(format!("http://{address}"))
+                .unwrap()
+                .connect()
+                .await
+            {
+                Ok(channel) => break channel,
+                Err(_) => {
+                    assert!(start.elapsed() < Duration::from_millis(20_000));
+                    actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        };
+        let mut client = FlightClient::new(channel);
+
+        // Invalid tickets are rejected.
+        match client.do_get(Ticket::new("not json")).await {
+            Err(FlightError::Tonic(status)) => assert_eq!(status.code(), Code::InvalidArgument),
+            _ => panic!("expected an invalid argument error"),
+        }
+
+        // Each record batch is acknowledged before the client sends the next
+        // one.
+        let data = TestStruct::data();
+        let batch = |i: usize| {
+            RecordBatch::try_new(
+                TestStruct::schema(),
+                TestStruct::make_arrow_array(&data[i..i + 1]),
+            )
+            .unwrap()
+        };
+        let (sender, receiver) = mpsc::unbounded::<Result<RecordBatch, FlightError>>();
+        sender.unbounded_send(Ok(batch(0))).unwrap();
+        let flight_data = FlightDataEncoderBuilder::new()
+            .with_flight_descriptor(Some(FlightDescriptor::new_path(vec![
+                "test_table".to_string()
+            ])))
+            .build(receiver);
+        let mut results = client.do_put(flight_data).await.unwrap();
+        assert!(results
+            .next()
+            .await
+            .unwrap()
+            .unwrap()
+            .app_metadata
+            .is_empty());
+        sender.unbounded_send(Ok(batch(1))).unwrap();
+        assert!(results
+            .next()
+            .await
+            .unwrap()
+            .unwrap()
+            .app_metadata
+            .is_empty());
+        drop(sender);
+        assert!(results.next().await.is_none());
+
+        // The snapshot eventually contains both records.
+        while snapshot_ids(&mut client).await != vec![1, 2] {
+            assert!(start.elapsed() < Duration::from_millis(20_000));
+            actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        state
+            .controller
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap()
+            .stop()
+            .unwrap();
+    }
+}
@@ -4,9 +4,10 @@ use crate::{
     transport::http::{
         HttpInputEndpoint, HttpInputTransport, HttpOutputEndpoint, HttpOutputTransport,
     },
+    transport::Step,
     CircuitCatalog, Controller, ControllerError, DbspCircuitHandle, FormatConfig, InputEndpoint,
     InputEndpointConfig, InputFormat, OutputEndpoint, OutputEndpointConfig, OutputFormat,
-    PipelineConfig,
+    PipelineConfig, StepCompletion,
 };
 use actix_web::{
     dev::{ServiceFactory, ServiceRequest},
@@ -23,7 +24,10 @@ use dbsp::operator::sample::MAX_QUANTILES;
 use env_logger::Env;
 use log::{debug, error, info, warn};
 use pipeline_types::config::OutputBufferConfig;
-use pipeline_types::{format::json::JsonFlavor, transport::http::EgressMode};
+use pipeline_types::{
+    format::json::JsonFlavor,
+    transport::http::{EgressMode, IngressWait},
+};
 use pipeline_types::{query::OutputQuery, transport::http::SERVER_PORT_FILE};
 use serde::Deserialize;
 use serde_json::{json, Value as JsonValue};
@@ -40,12 +44,18 @@ use std::{
 };
 use tokio::{
     spawn,
-    sync::mpsc::{channel, Sender},
+    sync::{
+        mpsc::{channel, Sender},
+        watch,
+    },
 };
 use uuid::Uuid;
 
 pub mod error;
+#[cfg(feature = "with-flight")]
+mod flight;
 mod prometheus;
+mod websocket;
 
 pub use self::error::{ErrorResponse, PipelineError, MAX_REPORTED_PARSE_ERRORS};
 use self::prometheus::PrometheusMetrics;
@@ -139,6 +149,17 @@ pub struct ServerArgs {
     /// automatically
     #[arg(short = 'p', long)]
     default_port: Option<u16>,
+
+    /// Serve the Arrow Flight API on this port.  The Flight server is
+    /// disabled when no port is specified.
+    #[arg(long)]
+    flight_port: Option<u16>,
+
+    /// Start as a standby for a running instance of the same pipeline, to take
+    /// over from it when `/activate` is invoked.  All of the pipeline's input
+    /// and output endpoints must be fault tolerant.
+    #[arg(long)]
+    standby: bool,
 }
 
 /// Server main function.
@@ -197,10 +218,20 @@ where
         })?
         .port();
 
+    #[cfg(feature = "with-flight")]
+    let flight_address = args
+        .flight_port
+        .map(|flight_port| flight::resolve_address(&args.bind_address, flight_port))
+        .transpose()?;
+    #[cfg(not(feature = "with-flight"))]
+    let ignore_flight_port = args.flight_port.is_some();
+
     let (terminate_sender, mut terminate_receiver) = channel(1);
 
     let state = WebData::new(ServerState::new(Some(terminate_sender)));
     let state_clone = state.clone();
+    #[cfg(feature = "with-flight")]
+    let flight_state = state.clone().into_inner();
 
     // The bootstrap thread will read the config, including pipeline name,
     // and initalize the logger.  Use this channel to wait for the log to
@@ -213,6 +244,11 @@ where
     thread::spawn(move || bootstrap(args, circuit_factory, state_clone, loginit_sender));
     let _ = loginit_receiver.recv();
 
+    #[cfg(not(feature = "with-flight"))]
+    if ignore_flight_port {
+        warn!("Ignoring '--flight-port': the pipeline was built without Arrow Flight support");
+    }
+
     let server = HttpServer::new(move || {
         let state = state.clone();
         build_app(App::new().wrap(Logger::default()), state)
@@ -235,6 +271,11 @@ where
 
         info!("Started HTTP server on port {port}");
 
+        #[cfg(feature = "with-flight")]
+        if let Some(flight_address) = flight_address {
+            spawn(flight::serve(flight_address, flight_state));
+        }
+
         // We don't want outside observers (e.g., the local runner) to observe a partially
         // written port file, so we write it to a temporary file first, and then rename the
         // temporary.
@@ -396,12 +437,14 @@ where
 
     let weak_state_ref = Arc::downgrade(state);
 
-    let controller = Controller::with_config(
-        circuit_factory,
-        &config,
-        Box::new(move |e| error_handler(&weak_state_ref, e))
-            as Box<dyn Fn(ControllerError) + Send + Sync>,
-    )?;
+    let error_cb = Box::new(move |e| error_handler(&weak_state_ref, e))
+        as Box<dyn Fn(ControllerError) + Send + Sync>;
+    let controller = if args.standby {
+        info!("Starting as a standby");
+        Controller::standby_with_config(circuit_factory, &config, error_cb)?
+    } else {
+        Controller::with_config(circuit_factory, &config, error_cb)?
+    };
 
     *state.prometheus.write().unwrap() = Some(
         PrometheusMetrics::new(&controller).map_err(|e| ControllerError::prometheus_error(&e))?,
@@ -427,6 +470,7 @@ where
         )
         .service(start)
         .service(pause)
+        .service(activate)
         .service(shutdown)
         .service(stats)
         .service(metrics)
@@ -434,6 +478,9 @@ where
         .service(dump_profile)
         .service(input_endpoint)
         .service(output_endpoint)
+        .service(lookup)
+        .service(websocket::input_websocket)
+        .service(websocket::output_websocket)
 }
 
 #[get("/start")]
@@ -458,6 +505,17 @@ async fn pause(state: WebData<ServerState>) -> impl Responder {
     }
 }
 
+#[get("/activate")]
+async fn activate(state: WebData<ServerState>) -> impl Responder {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => match controller.activate() {
+            Ok(()) => Ok(HttpResponse::Ok().json("Pipeline activated")),
+            Err(e) => Err(PipelineError::from(e)),
+        },
+        None => Err(missing_controller_error(&state)),
+    }
+}
+
 #[get("/stats")]
 async fn stats(state: WebData<ServerState>) -> impl Responder {
     match &*state.controller.lock().unwrap() {
@@ -543,6 +601,11 @@ struct IngressArgs {
     /// Push data to the pipeline even if the pipeline is in a paused state.
     #[serde(default)]
     force: bool,
+    /// When to respond to the request: as soon as the data has been parsed
+    /// (default) or once the circuit has processed it and pushed its outputs
+    /// to all output transports.
+    #[serde(default)]
+    wait: IngressWait,
 }
 
 #[post("/ingress/{table_name}")]
@@ -605,9 +668,19 @@ async fn input_endpoint(
     };
 
     // Call endpoint to complete request.
-    let response = endpoint.complete_request(payload).await;
+    let mut response = endpoint.complete_request(payload).await;
     drop(endpoint);
 
+    // Wait for the circuit to process all records pushed by this request.
+    if response.is_ok() && args.wait == IngressWait::Processed {
+        response = match processed_records_target(&state) {
+            Ok((completions, num_records)) => wait_for_processed_records(completions, num_records)
+                .await
+                .map(|step| HttpResponse::Ok().json(json!({ "step": step }))),
+            Err(e) => Err(e),
+        };
+    }
+
     // Delete endpoint on completion/error.
     if let Some(controller) = state.controller.lock().unwrap().as_ref() {
         controller.disconnect_input(&endpoint_id);
@@ -617,6 +690,45 @@ async fn input_endpoint(
     response
 }
 
+/// Returns the number of input records the circuit must process for all data
+/// received so far to be processed, along with a subscription to step
+/// completion notifications, and requests a step to process the data.
+fn processed_records_target(
+    state: &ServerState,
+) -> Result<(watch::Receiver<Option<StepCompletion>>, u64), PipelineError> {
+    match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            // Subscribe before reading the record count, so that we don't
+            // miss a step completing in between.
+            let completions = controller.step_completions();
+            let num_records = controller.status().num_total_input_records();
+            controller.request_step();
+            Ok((completions, num_records))
+        }
+        None => Err(missing_controller_error(state)),
+    }
+}
+
+/// Wait until the circuit has processed `num_records` input records and
+/// pushed the resulting outputs to all output transports.
+///
+/// Returns the step at which this happened.
+async fn wait_for_processed_records(
+    mut completions: watch::Receiver<Option<StepCompletion>>,
+    num_records: u64,
+) -> Result<Step, PipelineError> {
+    loop {
+        if let Some(completion) = *completions.borrow_and_update() {
+            if completion.processed_records >= num_records {
+                return Ok(completion.step);
+            }
+        }
+        if completions.changed().await.is_err() {
+            return Err(PipelineError::Terminating);
+        }
+    }
+}
+
 /// Create an instance of `FormatConfig` from format name and
 /// HTTP request using the `InputFormat::config_from_http_request` method.
 pub fn parser_config_from_http_request(
@@ -659,6 +771,32 @@ pub fn encoder_config_from_http_request(
     })
 }
 
+/// Create config for an output endpoint that sends the output of `query`
+/// over `table_name` to an HTTP client.
+///
+/// When `snapshot` is `true`, the endpoint starts with a snapshot of the
+/// query result.
+fn http_output_endpoint_config(
+    endpoint_name: &str,
+    table_name: String,
+    query: OutputQuery,
+    snapshot: bool,
+    format_name: &str,
+    request: &HttpRequest,
+) -> Result<OutputEndpointConfig, ControllerError> {
+    Ok(OutputEndpointConfig {
+        stream: Cow::from(table_name),
+        query,
+        snapshot,
+        output_buffer_config: OutputBufferConfig::default(),
+        connector_config: ConnectorConfig {
+            transport: HttpOutputTransport::config(),
+            format: encoder_config_from_http_request(endpoint_name, format_name, request)?,
+            max_buffered_records: HttpOutputTransport::default_max_buffered_records(),
+        },
+    })
+}
+
 /// URL-encoded arguments to the `/egress` endpoint.
 #[derive(Debug, Deserialize)]
 struct EgressArgs {
@@ -679,6 +817,11 @@ struct EgressArgs {
     /// the number of quantiles to output.
     #[serde(default = "dbsp::operator::sample::default_quantiles")]
     quantiles: u32,
+
+    /// Slow down the pipeline when the client falls behind, instead of
+    /// dropping output the client hasn't received yet.
+    #[serde(default)]
+    backpressure: bool,
 }
 
 #[post("/egress/{table_name}")]
@@ -703,12 +846,9 @@ async fn output_endpoint(
 
     // Check for unsupported combinations.
     match (args.mode, args.query) {
-        (EgressMode::Watch, OutputQuery::Quantiles) => {
+        (EgressMode::Watch | EgressMode::SnapshotAndWatch, OutputQuery::Quantiles) => {
             return Err(PipelineError::QuantileStreamingNotSupported);
         }
-        (EgressMode::Snapshot, OutputQuery::Table) => {
-            return Err(PipelineError::TableSnapshotNotImplemented);
-        }
         _ => {}
     };
 
@@ -730,6 +870,7 @@ async fn output_endpoint(
         match args.mode {
             EgressMode::Watch => "watch",
             EgressMode::Snapshot => "snapshot",
+            EgressMode::SnapshotAndWatch => "snapshot-and-watch",
         },
         match args.query {
             OutputQuery::Table => "",
@@ -741,28 +882,31 @@ async fn output_endpoint(
 
     // debug!("Endpoint name: '{endpoint_name}'");
 
+    // Neighborhood and quantiles queries always start with a snapshot.  Table
+    // queries only output a snapshot in the snapshot modes.
+    let snapshot = match args.query {
+        OutputQuery::Table => args.mode != EgressMode::Watch,
+        OutputQuery::Neighborhood | OutputQuery::Quantiles => true,
+    };
+
     // Create HTTP endpoint.
     let endpoint = HttpOutputEndpoint::new(
         &endpoint_name,
         &args.format,
-        matches!(
-            args.query,
-            OutputQuery::Neighborhood | OutputQuery::Quantiles
-        ),
-        args.mode == EgressMode::Watch,
+        snapshot,
+        args.mode != EgressMode::Snapshot,
+        args.backpressure,
     );
 
     // Create endpoint config.
-    let config = OutputEndpointConfig {
-        stream: Cow::from(table_name),
-        query: args.query,
-        output_buffer_config: OutputBufferConfig::default(),
-        connector_config: ConnectorConfig {
-            transport: HttpOutputTransport::config(),
-            format: encoder_config_from_http_request(&endpoint_name, &args.format, &req)?,
-            max_buffered_records: HttpOutputTransport::default_max_buffered_records(),
-        },
-    };
+    let config = http_output_endpoint_config(
+        &endpoint_name,
+        table_name,
+        args.query,
+        snapshot,
+        &args.format,
+        &req,
+    )?;
 
     // Declare `response` in this scope, before we lock `state.controller`.  This
     // makes sure that on error the finalizer for `response` also runs in this
@@ -865,6 +1009,38 @@ async fn output_endpoint(
                         .set_for_all(args.quantiles as usize);
                     controller.request_step();
                 }
+                // Send the snapshot query, if any, to produce a table snapshot.
+                OutputQuery::Table if snapshot => {
+                    let body = body
+                        .map(|body| body.into_inner())
+                        .unwrap_or(JsonValue::Null);
+
+                    let json = serde_json::to_string(&json!([json!(true), body])).map_err(|e| {
+                        PipelineError::InvalidTableSnapshotSpec {
+                            spec: body.clone(),
+                            parse_error: e.to_string(),
+                        }
+                    })?;
+
+                    if let Err(e) = controller
+                        .catalog()
+                        .lock()
+                        .unwrap()
+                        .output_handles(&config.stream)
+                        .unwrap()
+                        .snapshot_descr_handle
+                        .as_ref()
+                        .ok_or(PipelineError::TableSnapshotNotSupported)?
+                        .configure_deserializer(RecordFormat::Json(JsonFlavor::Default))?
+                        .set_for_all(json.as_bytes())
+                    {
+                        return Err(PipelineError::InvalidTableSnapshotSpec {
+                            spec: body,
+                            parse_error: e.to_string(),
+                        });
+                    }
+                    controller.request_step();
+                }
                 OutputQuery::Table => {}
             }
         }
@@ -874,6 +1050,48 @@ async fn output_endpoint(
     Ok(response)
 }
 
+/// Look up records in a view declared with a primary key.
+///
+/// The body of the request contains a JSON array of keys.  The response
+/// contains a JSON array with one element per key: the matching record or
+/// `null`.  Lookups are served from an index maintained by the circuit and
+/// don't wait for the next step.
+#[post("/lookup/{view_name}")]
+async fn lookup(state: WebData<ServerState>, req: HttpRequest, body: web::Bytes) -> impl Responder {
+    debug!("/lookup request:{req:?}");
+
+    let view_name = match req.match_info().get("view_name") {
+        None => {
+            return Err(PipelineError::MissingUrlEncodedParam { param: "view_name" });
+        }
+        Some(view_name) => view_name.to_string(),
+    };
+
+    let lookup_handle = match &*state.controller.lock().unwrap() {
+        Some(controller) => controller
+            .catalog()
+            .lock()
+            .unwrap()
+            .output_handles(&view_name)
+            .ok_or_else(|| ControllerError::unknown_output_stream("lookup", &view_name))?
+            .lookup_handle
+            .as_ref()
+            .ok_or(PipelineError::LookupNotSupported)?
+            .fork(),
+        None => return Err(missing_controller_error(&state)),
+    };
+
+    let result = lookup_handle
+        .lookup(&body, JsonFlavor::Default)
+        .map_err(|e| PipelineError::InvalidLookupKeys {
+            parse_error: e.to_string(),
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime::APPLICATION_JSON)
+        .body(result))
+}
+
 #[cfg(test)]
 #[cfg(feature = "with-kafka")]
 mod test_with_kafka {
@@ -884,7 +1102,7 @@ mod test_with_kafka {
             generate_test_batches,
             http::{TestHttpReceiver, TestHttpSender},
             kafka::{BufferConsumer, KafkaResources, TestProducer},
-            test_circuit,
+            test_circuit, TestStruct,
         },
     };
     use actix_web::{
@@ -968,7 +1186,9 @@ outputs:
             metadata_file: None,
             bind_address: "127.0.0.1".to_string(),
             default_port: None,
+            flight_port: None,
             storage_location: None,
+            standby: false,
         };
         thread::spawn(move || {
             bootstrap(
@@ -1128,6 +1348,45 @@ outputs:
 
         sleep(Duration::from_millis(5000));
 
+        // Push data and wait for the circuit to process it.
+        println!("Push data via HTTP with wait=processed");
+        let mut resp = server
+            .post("/ingress/test_input1?wait=processed")
+            .send_body("1000,true,,foo\n")
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+        let body = resp.json::<JsonValue>().await.unwrap();
+        assert!(body["step"].is_u64());
+
+        // By the time the request completes, the output of the record has been
+        // pushed to every output transport.
+        let stats = server
+            .get("/stats")
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonValue>()
+            .await
+            .unwrap();
+        let total_input_records = stats["global_metrics"]["total_input_records"]
+            .as_u64()
+            .unwrap();
+        for output in stats["outputs"].as_array().unwrap() {
+            assert_eq!(
+                output["metrics"]["total_processed_input_records"].as_u64(),
+                Some(total_input_records),
+                "{output}"
+            );
+        }
+        buffer_consumer.wait_for_output_unordered(&[vec![TestStruct {
+            id: 1000,
+            b: true,
+            i: None,
+            s: "foo".to_string(),
+        }]]);
+        buffer_consumer.clear();
+
         // Request quantiles.
         let mut quantiles_resp1 = server
             .post("/egress/test_output1?mode=snapshot&query=quantiles")
@@ -1178,6 +1437,35 @@ outputs:
         let body = serde_json::from_slice::<JsonValue>(&body.unwrap()).unwrap();
         println!("Default neighborhood: {body}");
 
+        // Request the first page of a table snapshot.
+        let mut table_resp = server
+            .post("/egress/test_output1?mode=snapshot&query=table&format=json")
+            .send_json(&json!({"limit": 10}))
+            .await
+            .unwrap();
+        assert!(table_resp.status().is_success());
+        let body = table_resp.body().await;
+        println!("Table snapshot: {body:?}");
+
+        // Request table snapshot: invalid request.
+        let table_inv_resp = server
+            .post("/egress/test_output1?mode=snapshot&query=table")
+            .send_json(&json!({"limit": "ten"}))
+            .await
+            .unwrap();
+        assert_eq!(table_inv_resp.status(), StatusCode::BAD_REQUEST);
+
+        // Request table snapshot of a relation that is not materialized.
+        let table_unsupported_resp = server
+            .post("/egress/test_input1?mode=snapshot&query=table")
+            .send_json(&json!({"limit": 10}))
+            .await
+            .unwrap();
+        assert_eq!(
+            table_unsupported_resp.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
         // Request neighborhood snapshot: invalid request.
         let mut hood_inv_resp = server
             .post("/egress/test_output1?mode=snapshot&query=neighborhood")
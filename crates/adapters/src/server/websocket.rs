@@ -0,0 +1,599 @@
+//! WebSocket counterparts of the `/ingress` and `/egress` endpoints.
+//!
+//! * `GET /ingress/{table_name}/ws` opens a bidirectional connection to an
+//!   input table.  Each text or binary message sent by the client is a chunk of
+//!   complete records in the format selected by the `format` argument.  Chunks
+//!   are numbered sequentially starting from 0.  Once the circuit has processed
+//!   a chunk and pushed the resulting outputs to all output endpoints, the
+//!   server acknowledges it with
+//!   an [`ack`](ServerMessage::Ack) message carrying the chunk's sequence
+//!   number and the step that processed it.  The optional `egress` argument
+//!   takes a comma-separated list of tables and views whose output deltas are
+//!   sent to the client over the same connection as
+//!   [`output`](ServerMessage::Output) messages.
+//!
+//! * `GET /egress/{table_name}/ws` is an output-only connection that sends
+//!   [`output`](ServerMessage::Output) messages with deltas of a single table
+//!   or view.
+//!
+//! All messages sent by the server are JSON-encoded text messages.
+
+use super::{
+    http_output_endpoint_config, missing_controller_error, parser_config_from_http_request,
+    ErrorResponse, PipelineError, ServerState,
+};
+use crate::{
+    controller::{ConnectorConfig, EndpointId},
+    transport::{
+        http::{HttpInputEndpoint, HttpInputTransport, HttpOutputEndpoint, HttpOutputTransport},
+        Step,
+    },
+    InputEndpoint, InputEndpointConfig, OutputEndpoint, ParseError, StepCompletion,
+};
+use actix_web::{
+    get, rt,
+    web::{Bytes, Data as WebData, Payload, Query},
+    Error as ActixError, HttpRequest, HttpResponse,
+};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, Session};
+use futures::{
+    stream::{select_all, SelectAll},
+    Stream, StreamExt,
+};
+use log::debug;
+use pipeline_types::query::OutputQuery;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Weak},
+};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Message sent by the server to a WebSocket client.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    /// Input chunk `sequence_number` has been processed by the circuit.
+    ///
+    /// The circuit processed the chunk at or before step `step`.  Outputs
+    /// produced by the chunk have been pushed to all output endpoints.
+    Ack { sequence_number: u64, step: Step },
+
+    /// Input chunk `sequence_number` contained records that could not be
+    /// parsed.  Valid records in the chunk are still ingested and
+    /// acknowledged.
+    ParseErrors {
+        sequence_number: u64,
+        error: ErrorResponse,
+    },
+
+    /// A chunk of output updates to table or view `stream`, in the same
+    /// format as the chunks produced by the `/egress` endpoint.
+    Output {
+        stream: &'a str,
+        chunk: &'a RawValue,
+    },
+}
+
+impl ServerMessage<'_> {
+    /// Send message to the client.
+    ///
+    /// Fails if the connection has been closed.
+    async fn send(&self, session: &mut Session) -> Result<(), actix_ws::Closed> {
+        // Serializing these types cannot fail.
+        session.text(serde_json::to_string(self).unwrap()).await
+    }
+}
+
+/// URL-encoded arguments to the `/ingress/{table_name}/ws` endpoint.
+#[derive(Debug, Deserialize)]
+struct IngressWebSocketArgs {
+    /// Data format of input chunks, also used to encode outputs
+    /// requested via `egress`.
+    #[serde(default = "HttpInputTransport::default_format")]
+    format: String,
+
+    /// Push data to the pipeline even if the pipeline is in a paused state.
+    #[serde(default)]
+    force: bool,
+
+    /// Comma-separated list of tables and views whose output deltas should
+    /// be sent to the client over the same connection.
+    #[serde(default)]
+    egress: String,
+}
+
+/// URL-encoded arguments to the `/egress/{table_name}/ws` endpoint.
+#[derive(Debug, Deserialize)]
+struct EgressWebSocketArgs {
+    /// Data format used to encode output deltas.
+    #[serde(default = "HttpOutputTransport::default_format")]
+    format: String,
+}
+
+/// Input endpoint attached to a WebSocket connection.
+///
+/// Disconnects the endpoint from the controller when dropped.
+struct WebSocketInput {
+    state: Weak<ServerState>,
+    endpoint: HttpInputEndpoint,
+    endpoint_id: EndpointId,
+    step_completions: watch::Receiver<Option<StepCompletion>>,
+}
+
+impl WebSocketInput {
+    /// Push input chunk to the pipeline.
+    ///
+    /// Returns parse errors along with the total number of input records
+    /// received by the pipeline after pushing the chunk.  The chunk has been
+    /// processed once the circuit has processed this many records.
+    async fn push(&self, data: &[u8]) -> Result<(Vec<ParseError>, u64), PipelineError> {
+        let errors = self.endpoint.complete_chunk(data).await?;
+
+        let state = self.state.upgrade().ok_or(PipelineError::Terminating)?;
+        let guard = state.controller.lock().unwrap();
+        let controller = guard.as_ref().ok_or(PipelineError::Terminating)?;
+
+        let num_records = controller.status().num_total_input_records();
+
+        // Don't let a small chunk linger in the input buffer waiting for
+        // `min_batch_size_records` to be reached.
+        controller.request_step();
+
+        Ok((errors, num_records))
+    }
+}
+
+impl Drop for WebSocketInput {
+    fn drop(&mut self) {
+        let _ = self.endpoint.eoi();
+
+        if let Some(state) = self.state.upgrade() {
+            // See comment in the finalizer in `output_endpoint`.
+            if let Ok(guard) = state.controller.lock() {
+                if let Some(controller) = guard.as_ref() {
+                    controller.disconnect_input(&self.endpoint_id);
+                    controller.unregister_api_connection();
+                }
+            }
+        }
+    }
+}
+
+/// Stream of output chunks tagged with the name of the table or view.
+type WebSocketOutput = Pin<Box<dyn Stream<Item = (Arc<str>, Bytes)>>>;
+
+fn table_name(req: &HttpRequest) -> Result<String, PipelineError> {
+    req.match_info()
+        .get("table_name")
+        .map(ToString::to_string)
+        .ok_or(PipelineError::MissingUrlEncodedParam {
+            param: "table_name",
+        })
+}
+
+/// Connect an output endpoint that streams deltas of `stream_name` in
+/// `format` to a WebSocket client.
+///
+/// The endpoint is disconnected when the returned stream is dropped.
+fn connect_output(
+    state: &Arc<ServerState>,
+    req: &HttpRequest,
+    stream_name: &str,
+    format: &str,
+) -> Result<WebSocketOutput, PipelineError> {
+    let endpoint_name = format!("api-ws-watch-{stream_name}-{}", Uuid::new_v4());
+    let endpoint = HttpOutputEndpoint::new(&endpoint_name, format, false, true, false);
+    let config = http_output_endpoint_config(
+        &endpoint_name,
+        stream_name.to_string(),
+        OutputQuery::Table,
+        false,
+        format,
+        req,
+    )?;
+
+    let endpoint_id = match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            if controller.register_api_connection().is_err() {
+                return Err(PipelineError::ApiConnectionLimit);
+            }
+
+            match controller.add_output_endpoint(
+                &endpoint_name,
+                &config,
+                Box::new(endpoint.clone()) as Box<dyn OutputEndpoint>,
+            ) {
+                Ok(endpoint_id) => endpoint_id,
+                Err(e) => {
+                    controller.unregister_api_connection();
+                    Err(e)?
+                }
+            }
+        }
+        None => return Err(missing_controller_error(state)),
+    };
+
+    let weak_state = Arc::downgrade(state);
+    let chunks = endpoint.stream(Box::new(move || {
+        if let Some(state) = weak_state.upgrade() {
+            // See comment in the finalizer in `output_endpoint`.
+            if let Ok(guard) = state.controller.lock() {
+                if let Some(controller) = guard.as_ref() {
+                    controller.disconnect_output(&endpoint_id);
+                    controller.unregister_api_connection();
+                }
+            }
+        }
+    }));
+
+    let stream_name: Arc<str> = Arc::from(config.stream.as_ref());
+    Ok(Box::pin(
+        chunks.map(move |chunk| (stream_name.clone(), chunk)),
+    ))
+}
+
+/// Forward an output chunk to the client.
+async fn send_output(
+    session: &mut Session,
+    stream: &str,
+    chunk: &[u8],
+) -> Result<(), actix_ws::Closed> {
+    // Chunks produced by `HttpOutputEndpoint` are valid JSON objects
+    // terminated with "\r\n".
+    let chunk = std::str::from_utf8(chunk).unwrap_or_default().trim_end();
+    match serde_json::from_str::<&RawValue>(chunk) {
+        Ok(chunk) => ServerMessage::Output { stream, chunk }.send(session).await,
+        Err(e) => {
+            debug!("WebSocket: dropping invalid output chunk for '{stream}': {e}");
+            Ok(())
+        }
+    }
+}
+
+#[get("/ingress/{table_name}/ws")]
+async fn input_websocket(
+    state: WebData<ServerState>,
+    req: HttpRequest,
+    args: Query<IngressWebSocketArgs>,
+    body: Payload,
+) -> Result<HttpResponse, ActixError> {
+    debug!("{req:?}");
+    let state = state.into_inner();
+    let table_name = table_name(&req)?;
+
+    // Generate endpoint name.
+    let endpoint_name = format!("api-ingress-ws-{table_name}-{}", Uuid::new_v4());
+
+    // Create HTTP endpoint.
+    let endpoint = HttpInputEndpoint::new(&endpoint_name, args.force);
+
+    // Create endpoint config.
+    let config = InputEndpointConfig {
+        stream: Cow::from(table_name),
+        connector_config: ConnectorConfig {
+            transport: HttpInputTransport::config(),
+            format: parser_config_from_http_request(&endpoint_name, &args.format, &req)?,
+            max_buffered_records: HttpInputTransport::default_max_buffered_records(),
+        },
+    };
+
+    // Connect endpoint.
+    let input = match &*state.controller.lock().unwrap() {
+        Some(controller) => {
+            if controller.register_api_connection().is_err() {
+                return Err(PipelineError::ApiConnectionLimit.into());
+            }
+
+            match controller.add_input_endpoint(
+                &endpoint_name,
+                config,
+                Box::new(endpoint.clone()) as Box<dyn InputEndpoint>,
+            ) {
+                Ok(endpoint_id) => WebSocketInput {
+                    state: Arc::downgrade(&state),
+                    endpoint,
+                    endpoint_id,
+                    step_completions: controller.step_completions(),
+                },
+                Err(e) => {
+                    controller.unregister_api_connection();
+                    debug!("Failed to create API endpoint: '{e}'");
+                    return Err(PipelineError::from(e).into());
+                }
+            }
+        }
+        None => {
+            return Err(missing_controller_error(&state).into());
+        }
+    };
+
+    // On error, dropping `input` and `outputs` disconnects all endpoints
+    // created so far.
+    let mut outputs = Vec::new();
+    for stream_name in args
+        .egress
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        outputs.push(connect_output(&state, &req, stream_name, &args.format)?);
+    }
+
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    rt::spawn(run_input_websocket(
+        input,
+        select_all(outputs),
+        session,
+        messages.aggregate_continuations(),
+    ));
+
+    Ok(response)
+}
+
+#[get("/egress/{table_name}/ws")]
+async fn output_websocket(
+    state: WebData<ServerState>,
+    req: HttpRequest,
+    args: Query<EgressWebSocketArgs>,
+    body: Payload,
+) -> Result<HttpResponse, ActixError> {
+    debug!("{req:?}");
+    let state = state.into_inner();
+    let table_name = table_name(&req)?;
+
+    let output = connect_output(&state, &req, &table_name, &args.format)?;
+
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    rt::spawn(run_output_websocket(
+        output,
+        session,
+        messages.aggregate_continuations(),
+    ));
+
+    Ok(response)
+}
+
+/// Serve an `/ingress/{table_name}/ws` connection until either the client
+/// or the pipeline closes it.
+async fn run_input_websocket(
+    mut input: WebSocketInput,
+    mut outputs: SelectAll<WebSocketOutput>,
+    mut session: Session,
+    mut messages: AggregatedMessageStream,
+) {
+    let mut sequence_number = 0;
+
+    // Chunks waiting to be acknowledged, along with the number of
+    // input records the circuit must process for the chunk to be processed.
+    let mut pending_acks: VecDeque<(u64, u64)> = VecDeque::new();
+
+    'session: loop {
+        tokio::select! {
+            message = messages.recv() => {
+                let data = match message {
+                    Some(Ok(AggregatedMessage::Binary(data))) => data,
+                    Some(Ok(AggregatedMessage::Text(text))) => text.into_bytes(),
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Some(Ok(AggregatedMessage::Pong(_))) => continue,
+                    Some(Ok(AggregatedMessage::Close(_))) | Some(Err(_)) | None => break,
+                };
+
+                match input.push(&data).await {
+                    Ok((errors, num_records)) => {
+                        if !errors.is_empty() {
+                            let error = PipelineError::parse_errors(errors.len(), errors.iter());
+                            let message = ServerMessage::ParseErrors {
+                                sequence_number,
+                                error: ErrorResponse::from_error(&error),
+                            };
+                            if message.send(&mut session).await.is_err() {
+                                break;
+                            }
+                        }
+                        pending_acks.push_back((sequence_number, num_records));
+                    }
+                    Err(_) => break,
+                }
+                sequence_number += 1;
+            }
+            Some((stream, chunk)) = outputs.next() => {
+                if send_output(&mut session, &stream, &chunk).await.is_err() {
+                    break;
+                }
+            }
+            changed = input.step_completions.changed(), if !pending_acks.is_empty() => {
+                if changed.is_err() {
+                    break;
+                }
+                let completion = *input.step_completions.borrow_and_update();
+                if let Some(completion) = completion {
+                    while let Some((sequence_number, num_records)) = pending_acks.front().cloned() {
+                        if num_records > completion.processed_records {
+                            break;
+                        }
+                        pending_acks.pop_front();
+                        let message = ServerMessage::Ack {
+                            sequence_number,
+                            step: completion.step,
+                        };
+                        if message.send(&mut session).await.is_err() {
+                            break 'session;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    debug!("WebSocket connection closed");
+    let _ = session.close(None).await;
+}
+
+/// Serve an `/egress/{table_name}/ws` connection until either the client
+/// or the pipeline closes it.
+async fn run_output_websocket(
+    mut output: WebSocketOutput,
+    mut session: Session,
+    mut messages: AggregatedMessageStream,
+) {
+    loop {
+        tokio::select! {
+            message = messages.recv() => {
+                match message {
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    // The client isn't expected to send any data on this
+                    // connection.
+                    Some(Ok(AggregatedMessage::Text(_)))
+                    | Some(Ok(AggregatedMessage::Binary(_)))
+                    | Some(Ok(AggregatedMessage::Pong(_))) => {}
+                    Some(Ok(AggregatedMessage::Close(_))) | Some(Err(_)) | None => break,
+                }
+            }
+            chunk = output.next() => {
+                match chunk {
+                    Some((stream, chunk)) => {
+                        if send_output(&mut session, &stream, &chunk).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    debug!("WebSocket connection closed");
+    let _ = session.close(None).await;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        server::{bootstrap, build_app, ServerArgs, ServerState},
+        test::test_circuit,
+    };
+    use actix_web::{http::StatusCode, middleware::Logger, web::Data as WebData, App};
+    use awc::ws::{Frame, Message};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::Value as JsonValue;
+    use std::{
+        io::Write,
+        thread,
+        time::{Duration, Instant},
+    };
+    use tempfile::NamedTempFile;
+
+    #[actix_web::test]
+    async fn test_websocket() {
+        let mut config_file = NamedTempFile::new().unwrap();
+        config_file.write_all(b"name: test\ninputs: {}\n").unwrap();
+
+        let state = WebData::new(ServerState::new(None));
+        let state_clone = state.clone();
+
+        let args = ServerArgs {
+            config_file: config_file.path().display().to_string(),
+            metadata_file: None,
+            bind_address: "127.0.0.1".to_string(),
+            default_port: None,
+            flight_port: None,
+            storage_location: None,
+            standby: false,
+        };
+        thread::spawn(move || {
+            bootstrap(
+                args,
+                |workers| Ok(test_circuit(workers)),
+                state_clone,
+                std::sync::mpsc::channel().0,
+            )
+        });
+
+        let mut server =
+            actix_test::start(move || build_app(App::new().wrap(Logger::default()), state.clone()));
+
+        let start = Instant::now();
+        while server.get("/stats").send().await.unwrap().status() == StatusCode::SERVICE_UNAVAILABLE
+        {
+            assert!(start.elapsed() < Duration::from_millis(20_000));
+            actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        }
+        assert!(server
+            .get("/start")
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .is_success());
+
+        let mut ws = server
+            .ws_at("/ingress/test_input1/ws?egress=test_output1")
+            .await
+            .unwrap();
+        ws.send(Message::Text("1,true,,foo\n2,false,5,bar\n".into()))
+            .await
+            .unwrap();
+
+        // Wait for the acknowledgement and the output delta; they can arrive
+        // in any order.
+        let mut ack = None;
+        let mut output = None;
+        while ack.is_none() || output.is_none() {
+            let frame = ws.next().await.unwrap().unwrap();
+            let Frame::Text(bytes) = frame else {
+                panic!("unexpected frame {frame:?}");
+            };
+            let message: JsonValue = serde_json::from_slice(&bytes).unwrap();
+            match message["type"].as_str().unwrap() {
+                "ack" => ack = Some(message),
+                // Skip empty keep-alive chunks.
+                "output" if message["chunk"].get("text_data").is_some() => output = Some(message),
+                "output" => {}
+                other => panic!("unexpected message type '{other}'"),
+            }
+        }
+
+        assert_eq!(ack.unwrap()["sequence_number"], 0);
+        let output = output.unwrap();
+        assert_eq!(output["stream"], "test_output1");
+        let text_data = output["chunk"]["text_data"].as_str().unwrap();
+        assert!(text_data.contains("foo"));
+        assert!(text_data.contains("bar"));
+
+        // Parse errors are reported without closing the connection.
+        ws.send(Message::Text("invalid\n".into())).await.unwrap();
+        loop {
+            let Frame::Text(bytes) = ws.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            let message: JsonValue = serde_json::from_slice(&bytes).unwrap();
+            if message["type"] == "parse_errors" {
+                assert_eq!(message["sequence_number"], 1);
+                break;
+            }
+        }
+
+        ws.close().await.unwrap();
+        assert!(server
+            .get("/shutdown")
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .is_success());
+    }
+}
@@ -1,17 +1,19 @@
 use crate::catalog::InputCollectionHandle;
 use crate::{
-    catalog::{NeighborhoodEntry, OutputCollectionHandles, SerCollectionHandle},
-    static_compile::{DeScalarHandle, DeScalarHandleImpl},
+    catalog::{NeighborhoodEntry, OutputCollectionHandles, SerCollectionHandle, SerLookupHandle},
+    static_compile::{DeScalarHandle, DeScalarHandleImpl, LookupTrace, SerLookupHandleImpl},
     Catalog, ControllerError,
 };
 use dbsp::{
+    dynamic::{DowncastTrait, Erase},
     operator::{
         DelayedFeedback, MapHandle, NeighborhoodDescr, NeighborhoodDescrBox,
         NeighborhoodDescrStream, SetHandle, ZSetHandle,
     },
-    typed_batch::TypedBox,
+    trace::Cursor,
+    typed_batch::{DynBatchReader, TypedBox},
     utils::Tup2,
-    DBData, OrdIndexedZSet, RootCircuit, Stream, ZSet,
+    DBData, OrdIndexedZSet, OrdZSet, RootCircuit, Stream, Trace, ZSet, ZWeight,
 };
 use pipeline_types::deserialize_struct;
 use pipeline_types::program_schema::Relation;
@@ -19,7 +21,10 @@ use pipeline_types::serde_with_context::{
     DeserializeWithContext, SerializeWithContext, SqlSerdeConfig,
 };
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
 
 use super::{DeMapHandle, DeSetHandle, DeZSetHandle, SerCollectionHandleImpl};
 
@@ -36,6 +41,106 @@ deserialize_struct!(NeighborhoodQuery(K)[3]{
     after: u64 = None
 });
 
+/// A request to output a snapshot of a table or view.
+///
+/// Selects records with keys in the `[start, end)` range, strictly greater
+/// than `after`, and returns up to `limit` of them.  All fields are optional;
+/// an empty query selects the entire contents of the collection.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct TableSnapshotQuery<K> {
+    pub start: Option<K>,
+    pub end: Option<K>,
+    pub after: Option<K>,
+    pub limit: Option<u64>,
+}
+
+// Not derived to avoid requiring `K: Default`.
+impl<K> Default for TableSnapshotQuery<K> {
+    fn default() -> Self {
+        Self {
+            start: None,
+            end: None,
+            after: None,
+            limit: None,
+        }
+    }
+}
+
+deserialize_struct!(TableSnapshotQuery(K)[4]{
+    start: Option<K> = Some(None),
+    end: Option<K> = Some(None),
+    after: Option<K> = Some(None),
+    limit: Option<u64> = Some(None)
+});
+
+/// [`TableSnapshotQuery`] with bounds converted to the key type of the
+/// collection.
+#[derive(Clone, Debug)]
+struct KeyRange<K> {
+    start: Option<K>,
+    end: Option<K>,
+    after: Option<K>,
+    limit: usize,
+}
+
+impl<K> KeyRange<K>
+where
+    K: DBData,
+{
+    fn new<D, F>(query: &TableSnapshotQuery<D>, key_func: F) -> Self
+    where
+        D: Clone,
+        F: Fn(D) -> K,
+    {
+        Self {
+            start: query.start.clone().map(&key_func),
+            end: query.end.clone().map(&key_func),
+            after: query.after.clone().map(&key_func),
+            limit: query.limit.map_or(usize::MAX, |limit| limit as usize),
+        }
+    }
+
+    /// Read up to `limit` records with keys in the range from `trace`, in
+    /// key order.
+    fn read<T>(&self, trace: &T) -> Vec<Tup2<Tup2<K, T::Val>, ZWeight>>
+    where
+        T: Trace<Key = K, R = ZWeight, Time = ()>,
+    {
+        let mut cursor = trace.inner().cursor();
+        if let Some(start) = &self.start {
+            cursor.seek_key(start.erase());
+        }
+        // Has no effect if `after` is below `start`.
+        if let Some(after) = &self.after {
+            cursor.seek_key(after.erase());
+        }
+
+        let mut result = Vec::new();
+        while cursor.key_valid() && result.len() < self.limit {
+            // Safety: `trait BatchReader` guarantees that the inner key type is `T::Key`.
+            let key = unsafe { cursor.key().downcast::<K>() }.clone();
+            if self.end.as_ref().is_some_and(|end| &key >= end) {
+                break;
+            }
+            if self.after.as_ref() != Some(&key) {
+                while cursor.val_valid() && result.len() < self.limit {
+                    // Safety: `trait BatchReader` guarantees that the inner weight
+                    // and value types are `T::R` and `T::Val`.
+                    let weight = unsafe { *cursor.weight().downcast::<ZWeight>() };
+                    if weight != 0 {
+                        let val = unsafe { cursor.val().downcast::<T::Val>() }.clone();
+                        result.push(Tup2(Tup2(key.clone(), val), weight));
+                    }
+                    cursor.step_val();
+                }
+            }
+            cursor.step_key();
+        }
+
+        result
+    }
+}
+
 impl Catalog {
     fn parse_relation_schema(schema: &str) -> Result<Relation, ControllerError> {
         serde_json::from_str(schema).map_err(|e| {
@@ -175,6 +280,86 @@ impl Catalog {
 
     /// Add an output stream of Z-sets to the catalog.
     pub fn register_output_zset<Z, D>(&mut self, stream: Stream<RootCircuit, Z>, schema: &str)
+    where
+        D: for<'de> DeserializeWithContext<'de, SqlSerdeConfig>
+            + SerializeWithContext<SqlSerdeConfig>
+            + From<Z::Key>
+            + Clone
+            + Debug
+            + Send
+            + 'static,
+        Z: ZSet + Debug + Send + Sync,
+        Z::InnerBatch: Send,
+        Z::Key: Sync + From<D>,
+    {
+        let handles = Self::output_zset_handles::<Z, D>(stream, schema);
+        self.register_output_batch_handles(handles).unwrap();
+    }
+
+    /// Like [`Self::register_output_zset`], but additionally maintains the
+    /// integral of the collection indexed by `key_func`, which serves point
+    /// lookups by key without stepping the circuit (see
+    /// [`OutputCollectionHandles::lookup_handle`]).
+    ///
+    /// The SQL compiler registers views declared with a primary key using
+    /// this method.
+    ///
+    /// # Generics
+    ///
+    /// * `V` - Record type of the collection.
+    /// * `D` - Record type in the output byte stream.
+    /// * `K` - Key type of the index.
+    /// * `KD` - Key type in the lookup requests.  Keys will get deserialized
+    ///   into instances of `KD` and then converted to `K`.
+    pub fn register_output_zset_with_lookup<V, D, K, KD, F>(
+        &mut self,
+        stream: Stream<RootCircuit, OrdZSet<V>>,
+        key_func: F,
+        schema: &str,
+    ) where
+        F: Fn(&V) -> K + 'static,
+        D: for<'de> DeserializeWithContext<'de, SqlSerdeConfig>
+            + SerializeWithContext<SqlSerdeConfig>
+            + From<V>
+            + Clone
+            + Debug
+            + Send
+            + 'static,
+        KD: for<'de> DeserializeWithContext<'de, SqlSerdeConfig> + 'static,
+        V: DBData + Send + Sync + From<D>,
+        K: DBData + Send + Sync + From<KD>,
+    {
+        let lookup_trace = Arc::new(Mutex::new(LookupTrace::new()));
+        let circuit_lookup_trace = lookup_trace.clone();
+
+        // The trace is read between steps, outside of the circuit, so it
+        // cannot be the trace that `integrate_trace` keeps in the circuit.
+        // Instead, worker 0, whose catalog is returned to the client, collects
+        // the changes of all workers and integrates them into a trace shared
+        // with the lookup handle, with a single insert per step.
+        stream
+            .map_index(move |v| (key_func(v), v.clone()))
+            .gather(0)
+            .inspect(move |batch| {
+                if !batch.is_empty() {
+                    circuit_lookup_trace.lock().unwrap().insert(batch.clone());
+                }
+            });
+
+        let mut handles = Self::output_zset_handles::<OrdZSet<V>, D>(stream, schema);
+        handles.lookup_handle = Some(
+            Box::new(<SerLookupHandleImpl<K, KD, V, D>>::new(lookup_trace))
+                as Box<dyn SerLookupHandle>,
+        );
+
+        self.register_output_batch_handles(handles).unwrap();
+    }
+
+    /// Create output handles for [`Self::register_output_zset`].
+    fn output_zset_handles<Z, D>(
+        stream: Stream<RootCircuit, Z>,
+        schema: &str,
+    ) -> OutputCollectionHandles
     where
         D: for<'de> DeserializeWithContext<'de, SqlSerdeConfig>
             + SerializeWithContext<SqlSerdeConfig>
@@ -249,7 +434,67 @@ impl Catalog {
         let quantiles_handle = quantiles_stream
             .output_guarded(&num_quantiles_stream.apply(|num_quantiles| *num_quantiles > 0));
 
-        let handles = OutputCollectionHandles {
+        // Snapshot queries require maintaining the contents of the collection,
+        // so they are only supported for collections that opt into it.
+        let (snapshot_descr_handle, snapshot_handle) = if schema.materialized {
+            // Handles for the table snapshot query.
+            let (snapshot_descr_stream, snapshot_descr_handle) =
+                circuit.add_input_stream::<(bool, Option<TableSnapshotQuery<D>>)>();
+            let snapshot_range_stream = snapshot_descr_stream.apply(|(reset, query)| {
+                reset.then(|| {
+                    KeyRange::new(
+                        &query.clone().unwrap_or_default(),
+                        <Z::Key as From<D>>::from,
+                    )
+                })
+            });
+
+            // Each worker reads up to `limit` records from its shard of the
+            // integral.  Since shards are disjoint, the first `limit` records of
+            // the collection are among the records collected by worker 0, which
+            // keeps the first `limit` of them.
+            let snapshot_stream = stream
+                .shard()
+                .integrate_trace()
+                .apply2(&snapshot_range_stream, |trace, range| {
+                    OrdZSet::from_tuples(
+                        (),
+                        range
+                            .as_ref()
+                            .map(|range| range.read(trace))
+                            .unwrap_or_default(),
+                    )
+                })
+                .gather(0)
+                .apply2(&snapshot_range_stream, |batch, range| match range {
+                    Some(range) if batch.len() > range.limit => OrdZSet::from_keys(
+                        (),
+                        batch
+                            .iter()
+                            .take(range.limit)
+                            .map(|(k, (), w)| Tup2(k, w))
+                            .collect(),
+                    ),
+                    _ => batch.clone(),
+                });
+            let snapshot_handle =
+                snapshot_stream.output_guarded(&snapshot_descr_stream.apply(|(reset, _)| *reset));
+
+            (
+                Some(
+                    Box::new(DeScalarHandleImpl::new(snapshot_descr_handle, |x| x))
+                        as Box<dyn DeScalarHandle>,
+                ),
+                Some(
+                    Box::new(<SerCollectionHandleImpl<_, D, ()>>::new(snapshot_handle))
+                        as Box<dyn SerCollectionHandle>,
+                ),
+            )
+        } else {
+            (None, None)
+        };
+
+        OutputCollectionHandles {
             schema,
             delta_handle: Box::new(<SerCollectionHandleImpl<_, D, ()>>::new(delta_handle))
                 as Box<dyn SerCollectionHandle>,
@@ -273,9 +518,12 @@ impl Catalog {
             quantiles_handle: Some(Box::new(<SerCollectionHandleImpl<_, D, ()>>::new(
                 quantiles_handle,
             )) as Box<dyn SerCollectionHandle>),
-        };
 
-        self.register_output_batch_handles(handles).unwrap();
+            snapshot_descr_handle,
+            snapshot_handle,
+
+            lookup_handle: None,
+        }
     }
 
     /// Add an output stream that carries updates to an indexed Z-set that
@@ -317,6 +565,9 @@ impl Catalog {
         // if one exists.
         let stream = stream.try_sharded_version();
 
+        // `key_func` is moved into the neighborhood circuit below.
+        let snapshot_key_func = key_func.clone();
+
         // Create handles for the neighborhood query.
         let (neighborhood_descr_stream, neighborhood_descr_handle) =
             circuit.add_input_stream::<(bool, Option<NeighborhoodQuery<VD>>)>();
@@ -375,6 +626,62 @@ impl Catalog {
             .map(|Tup2(_k, v)| v.clone())
             .output_guarded(&num_quantiles_stream.apply(|num_quantiles| *num_quantiles > 0));
 
+        // See `output_zset_handles`.
+        let (snapshot_descr_handle, snapshot_handle) = if schema.materialized {
+            // Handles for the table snapshot query.  Range bounds are specified
+            // as records, which are converted to keys using `key_func`.
+            let (snapshot_descr_stream, snapshot_descr_handle) =
+                circuit.add_input_stream::<(bool, Option<TableSnapshotQuery<VD>>)>();
+            let snapshot_range_stream = snapshot_descr_stream.apply(move |(reset, query)| {
+                reset.then(|| {
+                    KeyRange::new(&query.clone().unwrap_or_default(), |val: VD| {
+                        snapshot_key_func(&V::from(val))
+                    })
+                })
+            });
+
+            let snapshot_stream = stream
+                .shard()
+                .integrate_trace()
+                .apply2(&snapshot_range_stream, |trace, range| {
+                    OrdIndexedZSet::from_tuples(
+                        (),
+                        range
+                            .as_ref()
+                            .map(|range| range.read(trace))
+                            .unwrap_or_default(),
+                    )
+                })
+                .gather(0)
+                .apply2(&snapshot_range_stream, |batch, range| match range {
+                    Some(range) if batch.len() > range.limit => OrdIndexedZSet::from_tuples(
+                        (),
+                        batch
+                            .iter()
+                            .take(range.limit)
+                            .map(|(k, v, w)| Tup2(Tup2(k, v), w))
+                            .collect(),
+                    ),
+                    _ => batch.clone(),
+                });
+            let snapshot_handle = snapshot_stream
+                .map(|(_k, v)| v.clone())
+                .output_guarded(&snapshot_descr_stream.apply(|(reset, _)| *reset));
+
+            (
+                Some(
+                    Box::new(DeScalarHandleImpl::new(snapshot_descr_handle, |x| x))
+                        as Box<dyn DeScalarHandle>,
+                ),
+                Some(
+                    Box::new(<SerCollectionHandleImpl<_, VD, ()>>::new(snapshot_handle))
+                        as Box<dyn SerCollectionHandle>,
+                ),
+            )
+        } else {
+            (None, None)
+        };
+
         let handles = OutputCollectionHandles {
             schema,
             delta_handle: Box::new(<SerCollectionHandleImpl<_, VD, ()>>::new(delta_handle))
@@ -402,6 +709,11 @@ impl Catalog {
             quantiles_handle: Some(Box::new(<SerCollectionHandleImpl<_, VD, ()>>::new(
                 quantiles_handle,
             )) as Box<dyn SerCollectionHandle>),
+
+            snapshot_descr_handle,
+            snapshot_handle,
+
+            lookup_handle: None,
         };
 
         self.register_output_batch_handles(handles).unwrap();
@@ -486,6 +798,28 @@ mod test {
             .unwrap();
     }
 
+    fn set_snapshot_descr(output_handles: &OutputCollectionHandles, query: &str) {
+        output_handles
+            .snapshot_descr_handle
+            .as_ref()
+            .unwrap()
+            .configure_deserializer(RECORD_FORMAT)
+            .unwrap()
+            .set_for_all(format!("[true, {query}]").as_bytes())
+            .unwrap();
+    }
+
+    fn get_snapshot(output_handles: &OutputCollectionHandles) -> String {
+        batch_to_json(
+            output_handles
+                .snapshot_handle
+                .as_ref()
+                .unwrap()
+                .consolidate()
+                .deref(),
+        )
+    }
+
     #[test]
     fn catalog_map_handle_test() {
         let (mut circuit, catalog) = Runtime::init_circuit(4, |circuit| {
@@ -498,7 +832,7 @@ mod test {
                 hinput,
                 |test_struct| test_struct.id,
                 |test_struct| test_struct.id,
-                r#"{"name": "input_MAP", "case_sensitive": false, "fields":[]}"#
+                r#"{"name": "input_MAP", "case_sensitive": false, "fields":[], "materialized": true}"#
             );
 
             Ok(catalog)
@@ -525,6 +859,7 @@ mod test {
 
         set_num_quantiles(output_stream_handles, 5);
         set_neighborhood_descr(output_stream_handles, &TestStruct::default(), 5, 5);
+        set_snapshot_descr(output_stream_handles, r#"{"limit": 1}"#);
 
         circuit.step().unwrap();
 
@@ -552,6 +887,13 @@ mod test {
 "#
         );
 
+        let snapshot = get_snapshot(output_stream_handles);
+        assert_eq!(
+            snapshot,
+            r#"1: {"id":1,"b":true,"i":null,"s":"1"}
+"#
+        );
+
         // Step 2: replace an entry.
 
         input_stream_handle
@@ -560,6 +902,7 @@ mod test {
         input_stream_handle.flush();
 
         set_num_quantiles(output_stream_handles, 5);
+        set_snapshot_descr(output_stream_handles, "null");
 
         circuit.step().unwrap();
 
@@ -579,11 +922,23 @@ mod test {
 "#
         );
 
+        let snapshot = get_snapshot(output_stream_handles);
+        assert_eq!(
+            snapshot,
+            r#"1: {"id":1,"b":true,"i":null,"s":"1-modified"}
+1: {"id":2,"b":true,"i":null,"s":"2"}
+"#
+        );
+
         // Step 3: delete an entry.
 
         input_stream_handle.delete(br#"2"#).unwrap();
         input_stream_handle.flush();
         set_num_quantiles(output_stream_handles, 5);
+        set_snapshot_descr(
+            output_stream_handles,
+            r#"{"after": {"id": 1, "b": true, "s": "1-modified"}}"#,
+        );
 
         circuit.step().unwrap();
 
@@ -600,5 +955,74 @@ mod test {
             r#"1: {"id":1,"b":true,"i":null,"s":"1-modified"}
 "#
         );
+
+        // Id 2 has been deleted; nothing follows id 1.
+        let snapshot = get_snapshot(output_stream_handles);
+        assert_eq!(snapshot, "");
+    }
+
+    #[test]
+    fn catalog_lookup_test() {
+        let (mut circuit, (catalog, hinput)) = Runtime::init_circuit(4, |circuit| {
+            let mut catalog = Catalog::new();
+
+            let (input, hinput) = circuit.add_input_zset::<TestStruct>();
+
+            catalog.register_output_zset_with_lookup::<TestStruct, TestStruct, u32, u32, _>(
+                input,
+                |test_struct| test_struct.id,
+                r#"{"name": "output_view", "case_sensitive": false, "fields":[], "primary_key":["id"]}"#,
+            );
+
+            Ok((catalog, hinput))
+        })
+        .unwrap();
+
+        let output_stream_handles = catalog.output_handles("output_view").unwrap();
+        let lookup_handle = output_stream_handles.lookup_handle.as_ref().unwrap();
+        let lookup = |keys: &str| {
+            String::from_utf8(
+                lookup_handle
+                    .lookup(keys.as_bytes(), JsonFlavor::Default)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let test_struct = |id: u32, s: &str| TestStruct {
+            id,
+            b: true,
+            i: None,
+            s: s.to_string(),
+        };
+
+        assert_eq!(lookup("[1, 2]"), "[null,null]");
+
+        hinput.push(test_struct(1, "1"), 1);
+        hinput.push(test_struct(2, "2"), 1);
+        circuit.step().unwrap();
+
+        assert_eq!(
+            lookup("[2, 3, 1]"),
+            r#"[{"id":2,"b":true,"i":null,"s":"2"},null,{"id":1,"b":true,"i":null,"s":"1"}]"#
+        );
+
+        // Updates replace the record with the same key.
+        hinput.push(test_struct(1, "1"), -1);
+        hinput.push(test_struct(2, "2"), -1);
+        hinput.push(test_struct(2, "2-modified"), 1);
+        circuit.step().unwrap();
+
+        assert_eq!(
+            lookup("[1, 2]"),
+            r#"[null,{"id":2,"b":true,"i":null,"s":"2-modified"}]"#
+        );
+
+        // Lookups don't require stepping the circuit.
+        hinput.push(test_struct(3, "3"), 1);
+        assert_eq!(lookup("[3]"), "[null]");
+
+        assert!(lookup_handle
+            .lookup(br#"{"id": 1}"#, JsonFlavor::Default)
+            .is_err());
     }
 }
@@ -0,0 +1,158 @@
+//! Point lookups on indexed collections.
+
+use crate::catalog::SerLookupHandle;
+use anyhow::Result as AnyResult;
+use dbsp::{
+    dynamic::{DowncastTrait, Erase},
+    trace::Cursor,
+    typed_batch::{DynBatchReader, DynSpine, DynTrace, Spine, TypedBatch},
+    BatchReader, DBData, OrdIndexedZSet, ZWeight,
+};
+use pipeline_types::{
+    format::json::JsonFlavor,
+    serde_with_context::{DeserializeWithContext, SerializeWithContext, SqlSerdeConfig},
+};
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+/// Integral of an indexed Z-set maintained outside of the circuit.
+///
+/// The circuit inserts the changes produced by each step into the trace;
+/// lookup handles read the trace between steps.
+pub struct LookupTrace<K, V>
+where
+    K: DBData,
+    V: DBData,
+{
+    trace: Spine<OrdIndexedZSet<K, V>>,
+}
+
+// Safety: key and value filters are the only components of `Spine` that are
+// not `Send`.  `LookupTrace` never installs filters.
+unsafe impl<K, V> Send for LookupTrace<K, V>
+where
+    K: DBData,
+    V: DBData,
+{
+}
+
+impl<K, V> Default for LookupTrace<K, V>
+where
+    K: DBData,
+    V: DBData,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> LookupTrace<K, V>
+where
+    K: DBData,
+    V: DBData,
+{
+    pub fn new() -> Self {
+        Self {
+            trace: TypedBatch::new(DynSpine::new(
+                &<OrdIndexedZSet<K, V>>::factories(),
+                "lookup",
+            )),
+        }
+    }
+
+    /// Add changes computed by the circuit to the trace.
+    pub fn insert(&mut self, batch: OrdIndexedZSet<K, V>) {
+        self.trace.inner_mut().insert(batch.into_inner());
+    }
+
+    /// Returns the value associated with `key`, if any.
+    fn get(&self, key: &K) -> Option<V> {
+        let mut cursor = self.trace.inner().cursor();
+        cursor.seek_key(key.erase());
+
+        // Safety: `trait BatchReader` guarantees that the inner key, value,
+        // and weight types are `K`, `V`, and `ZWeight`.
+        if !cursor.key_valid() || unsafe { cursor.key().downcast::<K>() } != key {
+            return None;
+        }
+        while cursor.val_valid() {
+            if unsafe { *cursor.weight().downcast::<ZWeight>() } != 0 {
+                return Some(unsafe { cursor.val().downcast::<V>() }.clone());
+            }
+            cursor.step_val();
+        }
+        None
+    }
+}
+
+/// [`SerLookupHandle`] implementation that reads from a [`LookupTrace`].
+///
+/// Keys are deserialized into type `KD` before converting them to `K`;
+/// values are converted to `VD` before serializing them.
+pub struct SerLookupHandleImpl<K, KD, V, VD>
+where
+    K: DBData,
+    V: DBData,
+{
+    trace: Arc<Mutex<LookupTrace<K, V>>>,
+    phantom: PhantomData<fn() -> (KD, VD)>,
+}
+
+impl<K, KD, V, VD> Clone for SerLookupHandleImpl<K, KD, V, VD>
+where
+    K: DBData,
+    V: DBData,
+{
+    fn clone(&self) -> Self {
+        Self {
+            trace: self.trace.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, KD, V, VD> SerLookupHandleImpl<K, KD, V, VD>
+where
+    K: DBData,
+    V: DBData,
+{
+    pub fn new(trace: Arc<Mutex<LookupTrace<K, V>>>) -> Self {
+        Self {
+            trace,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, KD, V, VD> SerLookupHandle for SerLookupHandleImpl<K, KD, V, VD>
+where
+    K: DBData + From<KD>,
+    V: DBData,
+    KD: for<'de> DeserializeWithContext<'de, SqlSerdeConfig> + 'static,
+    VD: From<V> + SerializeWithContext<SqlSerdeConfig> + 'static,
+{
+    fn lookup(&self, keys: &[u8], json_flavor: JsonFlavor) -> AnyResult<Vec<u8>> {
+        let config = SqlSerdeConfig::from(json_flavor);
+        let keys = Vec::<KD>::deserialize_with_context(
+            &mut serde_json::Deserializer::from_slice(keys),
+            &config,
+        )?;
+
+        let trace = self.trace.lock().unwrap();
+        let vals = keys
+            .into_iter()
+            .map(|key| trace.get(&K::from(key)).map(VD::from))
+            .collect::<Vec<_>>();
+        drop(trace);
+
+        let mut buf = Vec::new();
+        vals.serialize_with_context(&mut serde_json::Serializer::new(&mut buf), &config)?;
+        Ok(buf)
+    }
+
+    fn fork(&self) -> Box<dyn SerLookupHandle> {
+        Box::new(self.clone())
+    }
+}
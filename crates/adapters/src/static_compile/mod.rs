@@ -2,7 +2,9 @@
 
 pub mod catalog;
 pub mod deinput;
+pub mod lookup;
 pub mod seroutput;
 
 pub use deinput::{DeMapHandle, DeScalarHandle, DeScalarHandleImpl, DeSetHandle, DeZSetHandle};
+pub use lookup::{LookupTrace, SerLookupHandleImpl};
 pub use seroutput::SerCollectionHandleImpl;
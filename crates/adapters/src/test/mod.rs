@@ -142,8 +142,9 @@ pub fn test_circuit(
         let input_schema =
             serde_json::to_string(&Relation::new("test_input1", false, vec![])).unwrap();
 
-        let output_schema =
-            serde_json::to_string(&Relation::new("test_output1", false, vec![])).unwrap();
+        let mut output_schema = Relation::new("test_output1", false, vec![]);
+        output_schema.materialized = true;
+        let output_schema = serde_json::to_string(&output_schema).unwrap();
 
         catalog.register_input_zset(input.clone(), hinput, &input_schema);
         catalog.register_output_zset(input, &output_schema);
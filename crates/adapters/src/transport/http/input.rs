@@ -119,7 +119,17 @@ impl HttpInputEndpoint {
             .input_fragment(bytes)
     }
 
-    fn eoi(&self) -> Vec<ParseError> {
+    fn push_chunk(&self, bytes: &[u8]) -> Vec<ParseError> {
+        self.inner
+            .consumer
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .input_chunk(bytes)
+    }
+
+    pub(crate) fn eoi(&self) -> Vec<ParseError> {
         self.inner.consumer.lock().unwrap().as_mut().unwrap().eoi()
     }
 
@@ -199,6 +209,32 @@ impl HttpInputEndpoint {
             Err(PipelineError::parse_errors(num_errors, errors.asc_iter()))
         }
     }
+
+    /// Push a chunk of complete records received as a WebSocket message to
+    /// the pipeline.
+    ///
+    /// Waits for the endpoint to be unpaused before pushing the data.
+    /// Returns parse errors reported by the parser.
+    pub(crate) async fn complete_chunk(
+        &self,
+        bytes: &[u8],
+    ) -> Result<Vec<ParseError>, PipelineError> {
+        let mut status_watch = self.inner.status_notifier.subscribe();
+
+        loop {
+            match self.state() {
+                PipelineState::Paused => {
+                    let _ = status_watch.changed().await;
+                }
+                PipelineState::Terminated => {
+                    return Err(PipelineError::Terminating);
+                }
+                PipelineState::Running => {
+                    return Ok(self.push_chunk(bytes));
+                }
+            }
+        }
+    }
 }
 
 impl InputEndpoint for HttpInputEndpoint {
@@ -3,19 +3,23 @@ use actix_web::{http::header::ContentType, web::Bytes, HttpResponse};
 use anyhow::{anyhow, bail, Result as AnyResult};
 use async_stream::stream;
 use crossbeam::sync::ShardedLock;
+use futures::{Stream, StreamExt};
 use log::debug;
 use log::error;
 use serde::{ser::SerializeStruct, Serializer};
 use serde_json::value::RawValue;
 use std::{
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     time::Duration,
 };
 use tokio::{
-    sync::broadcast::{self, error::RecvError},
+    sync::{
+        broadcast::{self, error::RecvError},
+        Notify,
+    },
     time::timeout,
 };
 
@@ -70,27 +74,67 @@ struct HttpOutputEndpointInner {
     format: Format,
 
     total_buffers: AtomicU64,
+    // No buffer with data was pushed yet.
+    empty: AtomicBool,
     sender: ShardedLock<Option<broadcast::Sender<Buffer>>>,
+    // Notified when the client receives a buffer or disconnects.
+    client_progress: Notify,
     // This endpoint starts with sending a snapshot of a relation.
     snapshot: bool,
     stream: bool,
+    // Wait for the client to receive buffers instead of dropping them when
+    // the client falls behind.
+    backpressure: bool,
     // async_error_callback: RwLock<Option<AsyncErrorCallback>>,
 }
 
 impl HttpOutputEndpointInner {
-    pub(crate) fn new(name: &str, format: Format, snapshot: bool, stream: bool) -> Self {
+    pub(crate) fn new(
+        name: &str,
+        format: Format,
+        snapshot: bool,
+        stream: bool,
+        backpressure: bool,
+    ) -> Self {
         Self {
             name: name.to_string(),
             format,
             total_buffers: AtomicU64::new(0),
+            empty: AtomicBool::new(true),
             sender: ShardedLock::new(Some(broadcast::channel(MAX_BUFFERS).0)),
+            client_progress: Notify::new(),
             snapshot,
             stream,
+            backpressure,
             // async_error_callback: RwLock::new(None),
         }
     }
 
+    /// Blocks while the client has `MAX_BUFFERS` buffers left to receive, so
+    /// that the broadcast channel never overwrites buffers the client hasn't
+    /// received yet.  Returns immediately once the client disconnects.
+    fn wait_for_client(&self) {
+        loop {
+            match self.sender.read().unwrap().as_ref() {
+                Some(sender) if sender.receiver_count() > 0 && sender.len() >= MAX_BUFFERS => (),
+                _ => return,
+            }
+            // The client stores a permit if it makes progress before we
+            // wait, so the wakeup can't be lost.
+            futures::executor::block_on(self.client_progress.notified());
+        }
+    }
+
     fn push_buffer(&self, buffer: Option<&[u8]>) -> AnyResult<()> {
+        // Empty chunks are only sent to keep the connection alive while the
+        // channel is empty, and must not block the stream that sends them.
+        if self.backpressure && buffer.is_some() {
+            self.wait_for_client();
+        }
+        if buffer.is_some() {
+            self.empty.store(false, Ordering::Release);
+        }
+
         let seq_number = self.total_buffers.fetch_add(1, Ordering::AcqRel);
 
         let json_buf = Vec::with_capacity(buffer.map(|b| b.len()).unwrap_or(0) + 1024);
@@ -147,6 +191,30 @@ impl HttpOutputEndpointInner {
     }
 }
 
+/// Receiver of the buffers sent to a client.
+///
+/// Notifies an endpoint waiting for the client to catch up when dropped, after
+/// unsubscribing from the channel.
+struct ClientReceiver {
+    receiver: Option<broadcast::Receiver<Buffer>>,
+    inner: Arc<HttpOutputEndpointInner>,
+}
+
+impl ClientReceiver {
+    async fn recv(&mut self) -> Result<Buffer, RecvError> {
+        let result = self.receiver.as_mut().unwrap().recv().await;
+        self.inner.client_progress.notify_one();
+        result
+    }
+}
+
+impl Drop for ClientReceiver {
+    fn drop(&mut self) {
+        self.receiver = None;
+        self.inner.client_progress.notify_one();
+    }
+}
+
 struct RequestGuard {
     finalizer: Box<dyn FnMut()>,
 }
@@ -167,21 +235,36 @@ impl Drop for RequestGuard {
 /// HTTP request.
 ///
 /// This implementation provides no support for reliable delivery
-/// and is mostly intended for browser-based testing.
+/// and is mostly intended for browser-based testing.  By default, buffers
+/// are dropped when the client falls behind.  With `backpressure`, the
+/// endpoint instead blocks the pipeline until the client catches up, which
+/// guarantees that a connected client receives all buffers.
 #[derive(Clone)]
 pub(crate) struct HttpOutputEndpoint {
     inner: Arc<HttpOutputEndpointInner>,
 }
 
 impl HttpOutputEndpoint {
-    pub(crate) fn new(name: &str, format: &str, snapshot: bool, stream: bool) -> Self {
+    pub(crate) fn new(
+        name: &str,
+        format: &str,
+        snapshot: bool,
+        stream: bool,
+        backpressure: bool,
+    ) -> Self {
         let format = match format {
             "csv" => Format::Text,
             "json" => Format::Json,
             _ => Format::Binary,
         };
         Self {
-            inner: Arc::new(HttpOutputEndpointInner::new(name, format, snapshot, stream)),
+            inner: Arc::new(HttpOutputEndpointInner::new(
+                name,
+                format,
+                snapshot,
+                stream,
+                backpressure,
+            )),
         }
     }
 
@@ -199,6 +282,51 @@ impl HttpOutputEndpoint {
             .subscribe()
     }
 
+    /// Create a stream of output chunks that will continue producing
+    /// output updates until the circuit terminates or the stream is dropped.
+    ///
+    /// `finalizer` is invoked when the stream is dropped.
+    pub(crate) fn stream(&self, finalizer: Box<dyn FnMut()>) -> impl Stream<Item = Bytes> {
+        let mut receiver = ClientReceiver {
+            receiver: Some(self.connect()),
+            inner: self.inner.clone(),
+        };
+        let name = self.name().to_string();
+        let guard = RequestGuard::new(finalizer);
+
+        let inner = self.inner.clone();
+
+        stream! {
+            let _guard = guard;
+            loop {
+                // There is a bug in actix (https://github.com/actix/actix-web/issues/1313)
+                // that prevents it from dropping HTTP connections on client disconnect
+                // unless the endpoint periodically sends some data.  As a workaround,
+                // if there is not real payload to send for more than 3 seconds, we will
+                // generate an empty chunk.  Note that it takes 6s, i.e., 2x the timeout
+                // period for actix to actually drop the connection.
+                match timeout(Duration::from_millis(3_000), receiver.recv()).await {
+                    Err(_) => {
+                        // Send the empty chunk via the `push_buffer` method to
+                        // make sure it gets assigned correct sequence number.
+                        let _ = inner.push_buffer(None);
+                    }
+                    Ok(Err(RecvError::Closed)) => break,
+                    Ok(Err(RecvError::Lagged(_))) => (),
+                    Ok(Ok(buffer)) => {
+                        debug!(
+                            "HTTP output endpoint '{}': sending chunk #{} ({} bytes)",
+                            name,
+                            buffer.sequence_number,
+                            buffer.data.len(),
+                        );
+                        yield buffer.data;
+                    },
+                }
+            }
+        }
+    }
+
     /// Create an HTTP response object with a streaming body that
     /// will continue sending output updates until the circuit
     /// terminates or the client disconnects.
@@ -207,43 +335,9 @@ impl HttpOutputEndpoint {
     /// object can be returned to the actix framework, which will
     /// run its streaming body and invoke `finalizer` upon completion.
     pub(crate) fn request(&self, finalizer: Box<dyn FnMut()>) -> HttpResponse {
-        let mut receiver = self.connect();
-        let name = self.name().to_string();
-        let guard = RequestGuard::new(finalizer);
-
-        let inner = self.inner.clone();
-
         HttpResponse::Ok()
             .insert_header(ContentType::json())
-            .streaming(stream! {
-                let _guard = guard;
-                loop {
-                    // There is a bug in actix (https://github.com/actix/actix-web/issues/1313)
-                    // that prevents it from dropping HTTP connections on client disconnect
-                    // unless the endpoint periodically sends some data.  As a workaround,
-                    // if there is not real payload to send for more than 3 seconds, we will
-                    // generate an empty chunk.  Note that it takes 6s, i.e., 2x the timeout
-                    // period for actix to actually drop the connection.
-                    match timeout(Duration::from_millis(3_000), receiver.recv()).await {
-                        Err(_) => {
-                            // Send the empty chunk via the `push_buffer` method to
-                            // make sure it gets assigned correct sequence number.
-                            let _ = inner.push_buffer(None);
-                        }
-                        Ok(Err(RecvError::Closed)) => break,
-                        Ok(Err(RecvError::Lagged(_))) => (),
-                        Ok(Ok(buffer)) => {
-                            debug!(
-                                "HTTP output endpoint '{}': sending chunk #{} ({} bytes)",
-                                name,
-                                buffer.sequence_number,
-                                buffer.data.len(),
-                            );
-                            yield <AnyResult<_>>::Ok(buffer.data);
-                        },
-                    }
-                }
-            })
+            .streaming(self.stream(finalizer).map(<AnyResult<_>>::Ok))
     }
 }
 
@@ -278,8 +372,12 @@ however the HTTP transport does not support this representation."
         // Drop the sender after receiving the first batch of updates in
         // the snapshot mode.  The receiver will receive all buffered
         // messages followed by a `RecvError::Closed` notification.
-        if self.inner.snapshot && self.inner.total_buffers.load(Ordering::Acquire) == 0 {
-            let _ = self.inner.push_buffer(Some(&[]));
+        if self.inner.snapshot && self.inner.empty.load(Ordering::Acquire) {
+            let empty: &[u8] = match self.inner.format {
+                Format::Json => b"[]",
+                _ => &[],
+            };
+            let _ = self.inner.push_buffer(Some(empty));
         }
 
         if !self.inner.stream {
@@ -132,6 +132,7 @@ impl Error for ExitRequest {}
 struct Reader {
     action: Arc<Mutex<Action>>,
     complete_step: Arc<Mutex<Option<Step>>>,
+    standby: Arc<AtomicBool>,
     unparker: Unparker,
     join_handle: Option<JoinHandle<()>>,
 }
@@ -168,6 +169,12 @@ impl InputReader for Reader {
         }
     }
 
+    fn activate(&self) -> AnyResult<()> {
+        self.standby.store(false, AtomicOrdering::Release);
+        self.unparker.unpark();
+        Ok(())
+    }
+
     fn disconnect(&self) {
         self.set_action(Err(ExitRequest));
     }
@@ -188,14 +195,21 @@ impl Drop for Reader {
 }
 
 impl Reader {
-    fn new(config: &Arc<Config>, start_step: Step, consumer: Box<dyn InputConsumer>) -> Self {
+    fn new(
+        config: &Arc<Config>,
+        start_step: Step,
+        standby: bool,
+        consumer: Box<dyn InputConsumer>,
+    ) -> Self {
         let parker = Parker::new();
         let unparker = parker.unparker().clone();
         let action = Arc::new(Mutex::new(Ok(OkAction::Pause)));
         let complete_step = Arc::new(Mutex::new(None));
+        let standby = Arc::new(AtomicBool::new(standby));
         let join_handle = Some(WorkerThread::spawn(
             &action,
             &complete_step,
+            &standby,
             start_step,
             parker,
             config,
@@ -204,6 +218,7 @@ impl Reader {
         Reader {
             action,
             complete_step,
+            standby,
             unparker,
             join_handle,
         }
@@ -276,6 +291,10 @@ impl Drop for PollerThread {
 struct WorkerThread {
     action: Arc<Mutex<Action>>,
     complete_step: Arc<Mutex<Option<Step>>>,
+
+    /// Set while the reader follows the steps recorded by another instance of
+    /// the pipeline, instead of recording its own.
+    standby: Arc<AtomicBool>,
     start_step: Step,
     parker: Parker,
     config: Arc<Config>,
@@ -286,6 +305,7 @@ impl WorkerThread {
     fn spawn(
         action: &Arc<Mutex<Action>>,
         complete_step: &Arc<Mutex<Option<Step>>>,
+        standby: &Arc<AtomicBool>,
         start_step: Step,
         parker: Parker,
         config: &Arc<Config>,
@@ -295,6 +315,7 @@ impl WorkerThread {
         let worker_thread = Self {
             action: action.clone(),
             complete_step: complete_step.clone(),
+            standby: standby.clone(),
             start_step,
             parker,
             config: config.clone(),
@@ -344,6 +365,69 @@ impl WorkerThread {
         }
     }
 
+    fn is_standby(&self) -> bool {
+        self.standby.load(AtomicOrdering::Acquire)
+    }
+
+    /// Waits until every partition in `partitions` has an index entry for
+    /// `step`, as written by the instance of the pipeline that we're standing
+    /// by for.  Returns early if the reader is activated, in which case the
+    /// caller must bring the partitions up to date with
+    /// [`Self::catch_up_index`].
+    fn follow_index<F>(&self, step: Step, partitions: &mut [Partition<F>]) -> AnyResult<()>
+    where
+        F: Fn(AnyError) + Send + Sync,
+    {
+        while self.is_standby() {
+            let mut n_missing = 0;
+            for p in partitions.iter_mut() {
+                if step >= p.steps.end && !p.poll_index_entry(step)? {
+                    n_missing += 1;
+                }
+            }
+            if n_missing == 0 {
+                break;
+            }
+
+            // Wait for an index entry to arrive, for activation, or for a
+            // request to exit.
+            self.action()?;
+            self.parker.park();
+        }
+        Ok(())
+    }
+
+    /// Extends the range of steps in each of `partitions` to include all of
+    /// the steps written to the index topics so far.  Called after activation,
+    /// because the instance of the pipeline that we were standing by for might
+    /// have written index entries that we haven't received yet.
+    fn catch_up_index<F>(&self, partitions: &mut [Partition<F>]) -> AnyResult<()>
+    where
+        F: Fn(AnyError) + Send + Sync,
+    {
+        let mut index_partitions = Vec::with_capacity(partitions.len());
+        for data_topic in &self.config.data_topics {
+            let index = IndexReader::new(data_topic, &self.config, |error| {
+                self.receiver.lock().unwrap().error(false, error)
+            })
+            .with_context(|| format!("Failed to read index for {data_topic}"))?;
+            index_partitions.extend(index.into_partitions());
+        }
+        if index_partitions.len() != partitions.len() {
+            bail!(
+                "index topics now have {} partitions in total but they had {}",
+                index_partitions.len(),
+                partitions.len()
+            );
+        }
+        for (p, index_partition) in partitions.iter_mut().zip(index_partitions) {
+            if index_partition.steps.end > p.steps.end {
+                p.steps.end = index_partition.steps.end;
+            }
+        }
+        Ok(())
+    }
+
     fn run(self) -> AnyResult<()> {
         self.wait_for_pipeline_start(self.start_step)?;
 
@@ -403,7 +487,9 @@ impl WorkerThread {
             for (index, p) in index_partitions.iter().enumerate() {
                 let index = index as i32;
                 let index_ctp = Ctp::new(&consumer, index_topic, index);
-                let index_queue = index_ctp.split_partition_queue()?;
+                let mut index_queue = index_ctp.split_partition_queue()?;
+                let unparker = self.parker.unparker().clone();
+                index_queue.set_nonempty_callback(move || unparker.unpark());
                 let data_ctp = Ctp::new(&consumer, data_topic, index);
                 let mut data_queue = data_ctp.split_partition_queue()?;
                 let unparker = self.parker.unparker().clone();
@@ -417,6 +503,7 @@ impl WorkerThread {
                     next_offset: positions[index as usize].data_offset,
                     start_offset: None,
                     steps: p.steps.clone(),
+                    pending_entry: None,
                 });
             }
         }
@@ -452,12 +539,25 @@ impl WorkerThread {
         // Each loop iteration produces one step.
         let mut next_partition = 0;
         let mut saved_message = None;
+        let mut following = self.is_standby();
         for step in self.start_step.. {
             self.receiver.lock().unwrap().start_step(step);
             self.wait_for_pipeline_start(step)?;
             check_fatal_errors(consumer.client()).context("Consumer reported fatal error")?;
             check_fatal_errors(producer.client()).context("Producer reported fatal error")?;
 
+            // As a standby, we only read steps that the other instance of the
+            // pipeline has recorded.  Once we're activated, we take over
+            // recording steps, starting after the last step that it recorded.
+            if following {
+                self.follow_index(step, &mut partitions)?;
+                if !self.is_standby() {
+                    info!("activated, taking over recording steps from step {step}");
+                    self.catch_up_index(&mut partitions)?;
+                    following = false;
+                }
+            }
+
             // Get all of the messages already written to the step from any
             // partitions that already have them.
             let mut n_prewritten = 0;
@@ -467,19 +567,19 @@ impl WorkerThread {
                 }
                 n_prewritten += 1;
 
-                // Read the `IndexEntry` that tells us where to get the step's data.
-                let index_message = consumer_eh
-                    .read_partition_queue(&consumer, &p.index_queue, || self.action())
-                    .with_context(|| format!("Failed to read {} partition queue", p.index_ctp))?;
-                let payload = index_message.payload().unwrap_or(&[]);
-                let index_entry: IndexEntry =
-                    serde_json::from_slice(payload).with_context(|| {
-                        format!(
-                            "Failed to parse index entry in message at offset {} in {}",
-                            index_message.offset(),
-                            p.index_ctp
-                        )
-                    })?;
+                // Read the `IndexEntry` that tells us where to get the step's
+                // data, unless we already did while following the index.
+                let (index_entry, index_offset) = match p.pending_entry.take() {
+                    Some(pending_entry) => pending_entry,
+                    None => {
+                        let index_message = consumer_eh
+                            .read_partition_queue(&consumer, &p.index_queue, || self.action())
+                            .with_context(|| {
+                                format!("Failed to read {} partition queue", p.index_ctp)
+                            })?;
+                        p.parse_index_entry(&index_message)?
+                    }
+                };
                 if let Some(error) = {
                     if index_entry.step != step {
                         Some(format!("IndexEntry should be for step {step}"))
@@ -490,9 +590,8 @@ impl WorkerThread {
                     }
                 } {
                     bail!(
-                        "bad IndexEntry at {} offset {} ({error}): {index_entry:?}",
+                        "bad IndexEntry at {} offset {index_offset} ({error}): {index_entry:?}",
                         p.index_ctp,
-                        index_message.offset()
                     );
                 }
 
@@ -650,6 +749,55 @@ where
     next_offset: i64,
     start_offset: Option<i64>,
     steps: Range<Step>,
+
+    /// An index entry, and its offset, read from `index_queue` while following
+    /// the index as a standby but not yet used.
+    pending_entry: Option<(IndexEntry, i64)>,
+}
+
+impl<'a, F> Partition<'a, F>
+where
+    F: Fn(AnyError) + Send + Sync,
+{
+    /// Parses `message`, read from `index_queue`, as an [`IndexEntry`], and
+    /// returns it along with its offset.
+    fn parse_index_entry<M>(&self, message: &M) -> AnyResult<(IndexEntry, i64)>
+    where
+        M: Message,
+    {
+        let index_entry = IndexEntry::from_message(message).with_context(|| {
+            format!(
+                "Failed to parse index entry in message at offset {} in {}",
+                message.offset(),
+                self.index_ctp
+            )
+        })?;
+        Ok((index_entry, message.offset()))
+    }
+
+    /// Tries to read the index entry for `step`, which must be `steps.end`,
+    /// without blocking.  Returns true and extends `steps` if successful, false
+    /// if no entry is available yet.
+    fn poll_index_entry(&mut self, step: Step) -> AnyResult<bool> {
+        let Some(index_message) = self
+            .index_queue
+            .poll(Duration::ZERO)
+            .transpose()
+            .with_context(|| format!("Failed to read {} partition queue", self.index_ctp))?
+        else {
+            return Ok(false);
+        };
+        let (index_entry, index_offset) = self.parse_index_entry(&index_message)?;
+        if index_entry.step != step {
+            bail!(
+                "bad IndexEntry at {} offset {index_offset} (IndexEntry should be for step {step}): {index_entry:?}",
+                self.index_ctp
+            );
+        }
+        self.steps.end = step + 1;
+        self.pending_entry = Some((index_entry, index_offset));
+        Ok(true)
+    }
 }
 
 impl InputEndpoint for Endpoint {
@@ -678,7 +826,15 @@ impl InputEndpoint for Endpoint {
         consumer: Box<dyn InputConsumer>,
         start_step: Step,
     ) -> AnyResult<Box<dyn InputReader>> {
-        Ok(Box::new(Reader::new(&self.0, start_step, consumer)))
+        Ok(Box::new(Reader::new(&self.0, start_step, false, consumer)))
+    }
+
+    fn open_standby(
+        &self,
+        consumer: Box<dyn InputConsumer>,
+        start_step: Step,
+    ) -> AnyResult<Box<dyn InputReader>> {
+        Ok(Box::new(Reader::new(&self.0, start_step, true, consumer)))
     }
 
     fn is_fault_tolerant(&self) -> bool {
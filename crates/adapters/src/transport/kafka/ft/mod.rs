@@ -11,6 +11,10 @@
 //! initialization, we read the final step number and discard any output for
 //! duplicate steps.  We use Kafka transactions to avoid writing partial output
 //! for a step.
+//!
+//! A standby pipeline follows the index topics written by the running instance
+//! of the pipeline, instead of writing its own, and holds back its output until
+//! it is activated.
 mod input;
 mod output;
 
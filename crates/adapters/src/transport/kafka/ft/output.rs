@@ -15,7 +15,7 @@ use rdkafka::{
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    cmp::max,
+    cmp::{max, Ordering},
     sync::{Condvar, Mutex, RwLock},
     time::Duration,
 };
@@ -77,11 +77,48 @@ impl OutputPosition {
 pub struct KafkaOutputEndpoint {
     kafka_producer: ThreadedProducer<DataProducerContext>,
     topic: String,
+    seekable_consumer_config: ClientConfig,
+    initialization_timeout: Duration,
     next_partition: usize,
     n_partitions: usize,
     max_message_size: usize,
     next_step: Step,
     state: State,
+
+    /// Output held back while connected as a standby, or `None` if not a
+    /// standby.
+    standby: Option<Standby>,
+}
+
+/// State of a [`KafkaOutputEndpoint`] in a standby pipeline.
+///
+/// Another instance of the pipeline writes to the same topic, so the standby
+/// must not write to it until it is activated.  Meanwhile, the standby:
+///
+/// - Compares its output for steps that the other instance has already
+///   written against what the other instance wrote, and then discards it.  If
+///   the output differs, then the definition of the view changed, and taking
+///   over the topic would leave its readers with a mix of the old and new
+///   view's output.
+///
+/// - Holds its output for steps that the other instance hasn't written yet, to
+///   write it when it's activated.
+struct Standby {
+    /// Reads the output written by the other instance.
+    other_output: StepReader,
+
+    /// The step whose output is being received.
+    current: Option<StepOutput>,
+
+    /// The output of steps at or after `next_step`, in increasing order of
+    /// step.
+    held: Vec<StepOutput>,
+}
+
+/// The output of a single step.
+struct StepOutput {
+    step: Step,
+    buffers: Vec<Vec<u8>>,
 }
 
 impl KafkaOutputEndpoint {
@@ -109,42 +146,102 @@ impl KafkaOutputEndpoint {
         let max_message_size = message_max_bytes - MAX_MESSAGE_OVERHEAD;
         debug!("Configured max message size: {max_message_size} ('message.max.bytes={message_max_bytes}')");
 
-        // Initialize our producer.
-        //
-        // This makes first contact with the broker and gives up after a
-        // timeout.  After this, Kafka will retry indefinitely, but limiting the
-        // time for initialization is useful to make sure that the configuration
-        // is correct.
-        //
-        // Since we initialize transactions, this has the effect of achieving
-        // mutual exclusion with other instances of ourselves and any other
-        // producers cooperating with us by using the same `transactional.id`.
+        // Create our producer.  We don't initialize transactions until we
+        // connect, because a standby must not interfere with the instance of
+        // the pipeline that it's standing by for.
         let context = DataProducerContext::new(config.max_inflight_messages);
         let kafka_producer =
             ThreadedProducer::from_config_and_context(&common.producer_config, context)?;
+
+        Ok(Self {
+            kafka_producer,
+            topic: config.topic.clone(),
+            seekable_consumer_config: common.seekable_consumer_config,
+            initialization_timeout: Duration::from_secs(config.initialization_timeout_secs.into()),
+            n_partitions: 0,
+            next_partition: 0,
+            max_message_size,
+            next_step: 0,
+            state: State::New,
+            standby: None,
+        })
+    }
+
+    /// Initializes transactions for our producer, and then reads the number
+    /// of partitions and the next step number.
+    ///
+    /// Initialization makes first contact with the broker and gives up after a
+    /// timeout.  After this, Kafka will retry indefinitely, but limiting the
+    /// time for initialization is useful to make sure that the configuration
+    /// is correct.
+    ///
+    /// Since we initialize transactions, this has the effect of achieving
+    /// mutual exclusion with other instances of ourselves and any other
+    /// producers cooperating with us by using the same `transactional.id`.
+    fn init_transactions(&mut self) -> AnyResult<()> {
+        let kafka_producer = &self.kafka_producer;
         kafka_producer
             .context()
             .deferred_logging
             .with_deferred_logging(|| {
-                kafka_producer.init_transactions(Duration::from_secs(
-                    config.initialization_timeout_secs.into(),
-                ))
+                kafka_producer.init_transactions(self.initialization_timeout)
             })?;
 
         // Read the number of partitions and the next step number.  We do this
         // after initializing transactions to avoid a race.
-        let (n_partitions, next_step) =
-            Self::read_next_step(&config.topic, &common.seekable_consumer_config)?;
+        (self.n_partitions, self.next_step) =
+            Self::read_next_step(&self.topic, &self.seekable_consumer_config)?;
+        Ok(())
+    }
 
-        Ok(Self {
-            kafka_producer,
-            topic: config.topic.clone(),
-            n_partitions,
-            next_partition: 0,
-            max_message_size,
-            next_step,
-            state: State::New,
-        })
+    /// Sends `buffer` as the message at `position`.
+    fn send(&mut self, position: OutputPosition, buffer: &[u8]) -> AnyResult<()> {
+        let key = serde_json::to_string(&position).unwrap();
+        let record = BaseRecord::to(&self.topic)
+            .key(&key)
+            .partition(self.next_partition as i32)
+            .payload(buffer);
+        self.kafka_producer
+            .send(record)
+            .map_err(|(err, _record)| err)?;
+        self.kafka_producer.context().take_delivery_slot();
+
+        self.next_partition += 1;
+        if self.next_partition >= self.n_partitions {
+            self.next_partition = 0;
+        }
+        Ok(())
+    }
+
+    /// Reports `error` as fatal to the controller.
+    fn fatal_error(&self, error: AnyError) {
+        match self
+            .kafka_producer
+            .context()
+            .async_error_callback
+            .read()
+            .unwrap()
+            .as_ref()
+        {
+            Some(cb) => cb(true, error),
+            None => warn!("{error:#}"),
+        }
+    }
+
+    /// As a standby, compares the output held for steps that the other
+    /// instance of the pipeline has since written against what it wrote, and
+    /// then discards it.
+    fn discard_written_steps(&mut self) -> AnyResult<()> {
+        let standby = self.standby.as_mut().unwrap();
+        let n_written = standby
+            .held
+            .iter()
+            .take_while(|output| output.step < self.next_step)
+            .count();
+        for output in standby.held.drain(..n_written) {
+            standby.other_output.compare_step(&output)?;
+        }
+        Ok(())
     }
 
     /// Reads the tail of `topic` using `seekable_consumer_config`. Returns the
@@ -154,7 +251,7 @@ impl KafkaOutputEndpoint {
         topic: &str,
         seekable_consumer_config: &ClientConfig,
     ) -> AnyResult<(usize, Step)> {
-        let context = DataConsumerContext::new(|error| warn!("{error}"));
+        let context = DataConsumerContext::new(warn_error);
         let consumer = BaseConsumer::from_config_and_context(seekable_consumer_config, context)?;
         let n_partitions = count_partitions_in_topic(&consumer, topic)?;
         let mut next_step = 0;
@@ -193,6 +290,138 @@ impl KafkaOutputEndpoint {
     }
 }
 
+fn warn_error(error: AnyError) {
+    warn!("{error}");
+}
+
+/// Reads the output written to a topic by another instance of the pipeline,
+/// one step at a time.
+struct StepReader {
+    consumer: BaseConsumer<DataConsumerContext<fn(AnyError)>>,
+    topic: String,
+    partitions: Vec<StepReaderPartition>,
+
+    /// Messages were deleted from the beginning of the topic, so the steps
+    /// before this one might be incomplete.
+    first_complete_step: Step,
+}
+
+struct StepReaderPartition {
+    /// Offset of the next message to read.
+    next_offset: i64,
+
+    /// A message that was read but belongs to a later step, as its step and
+    /// payload.
+    lookahead: Option<(Step, Vec<u8>)>,
+}
+
+impl StepReader {
+    fn new(
+        topic: &str,
+        n_partitions: usize,
+        seekable_consumer_config: &ClientConfig,
+    ) -> AnyResult<Self> {
+        let context = DataConsumerContext::new(warn_error as fn(AnyError));
+        let consumer = BaseConsumer::from_config_and_context(seekable_consumer_config, context)?;
+        let mut partitions = Vec::with_capacity(n_partitions);
+        let mut first_complete_step = 0;
+        for partition in 0..n_partitions {
+            let ctp = Ctp::new(&consumer, topic, partition as i32);
+            let watermarks = ctp.fetch_watermarks(None)?;
+            if watermarks.start > 0 && !watermarks.is_empty() {
+                let msg = ctp.read_at_offset(watermarks.start)?;
+                let key = OutputPosition::from_message(&msg).with_context(|| {
+                    format!(
+                        "message at offset {} in {ctp} should have step and substep as key",
+                        msg.offset()
+                    )
+                })?;
+                first_complete_step = max(first_complete_step, key.step + 1);
+            }
+            partitions.push(StepReaderPartition {
+                next_offset: watermarks.start,
+                lookahead: None,
+            });
+        }
+        Ok(Self {
+            consumer,
+            topic: topic.into(),
+            partitions,
+            first_complete_step,
+        })
+    }
+
+    /// Reads and returns the payloads of the messages in `step`, which must
+    /// be greater than any step previously read and must already be committed
+    /// to the topic.
+    fn read_step(&mut self, step: Step) -> AnyResult<Vec<Vec<u8>>> {
+        let mut payloads = Vec::new();
+        for (partition, p) in self.partitions.iter_mut().enumerate() {
+            let ctp = Ctp::new(&self.consumer, &self.topic, partition as i32);
+            let mut assigned = false;
+            loop {
+                if let Some((message_step, payload)) = p.lookahead.take() {
+                    match message_step.cmp(&step) {
+                        Ordering::Less => continue,
+                        Ordering::Equal => payloads.push(payload),
+                        Ordering::Greater => {
+                            p.lookahead = Some((message_step, payload));
+                            break;
+                        }
+                    }
+                }
+
+                if !assigned {
+                    ctp.assign(p.next_offset)?;
+                    assigned = true;
+                }
+                match ctp.read_toward_end(p.next_offset) {
+                    Ok(msg) => {
+                        let key = OutputPosition::from_message(&msg).with_context(|| {
+                            format!(
+                                "message at offset {} in {ctp} should have step and substep as key",
+                                msg.offset()
+                            )
+                        })?;
+                        p.next_offset = msg.offset() + 1;
+                        p.lookahead = Some((key.step, msg.payload().unwrap_or(&[]).to_vec()));
+                    }
+                    Err(KafkaError::PartitionEOF(eof)) if eof == partition as i32 => break,
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        }
+        Ok(payloads)
+    }
+
+    /// Compares `output` against the output that the other instance wrote for
+    /// the same step, and returns an error if they differ.
+    fn compare_step(&mut self, output: &StepOutput) -> AnyResult<()> {
+        let written = self.read_step(output.step)?;
+        if output.step < self.first_complete_step {
+            debug!(
+                "not comparing output for step {} against {} because the step was partially deleted",
+                output.step, self.topic
+            );
+        } else if sorted_lines(&written) != sorted_lines(&output.buffers) {
+            bail!("output for step {} differs from the output that the running pipeline wrote to {} for the same step, probably because the definition of the view changed; connect this output to a new topic to upgrade the pipeline", output.step, self.topic);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the nonempty lines in `buffers`, sorted, for comparing output
+/// regardless of the order in which records were output.
+fn sorted_lines(buffers: &[Vec<u8>]) -> Vec<&[u8]> {
+    let mut lines = buffers
+        .iter()
+        .flat_map(|buffer| buffer.split(|&c| c == b'\n'))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    lines.sort_unstable();
+    lines
+}
+
 impl OutputEndpoint for KafkaOutputEndpoint {
     fn connect(&mut self, async_error_callback: AsyncErrorCallback) -> AnyResult<()> {
         debug_assert_eq!(self.state, State::New);
@@ -204,6 +433,65 @@ impl OutputEndpoint for KafkaOutputEndpoint {
             .async_error_callback
             .write()
             .unwrap() = Some(async_error_callback);
+        self.init_transactions()
+    }
+
+    fn connect_standby(&mut self, async_error_callback: AsyncErrorCallback) -> AnyResult<()> {
+        debug_assert_eq!(self.state, State::New);
+        self.state = State::Connected;
+
+        *self
+            .kafka_producer
+            .context()
+            .async_error_callback
+            .write()
+            .unwrap() = Some(async_error_callback);
+
+        // Read where the other instance is, without initializing transactions
+        // because that would fence it out.
+        (self.n_partitions, self.next_step) =
+            Self::read_next_step(&self.topic, &self.seekable_consumer_config)?;
+        self.standby = Some(Standby {
+            other_output: StepReader::new(
+                &self.topic,
+                self.n_partitions,
+                &self.seekable_consumer_config,
+            )?,
+            current: None,
+            held: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn activate(&mut self) -> AnyResult<()> {
+        if self.standby.is_none() {
+            return Ok(());
+        }
+        debug_assert!(!matches!(self.state, State::BatchOpen(_)));
+
+        // Fence out the other instance and find out what it wrote before it
+        // stopped.
+        self.init_transactions()?;
+        self.discard_written_steps()?;
+
+        // Write the output that the other instance didn't.
+        let standby = self.standby.take().unwrap();
+        for output in standby.held {
+            self.kafka_producer.begin_transaction()?;
+            for (substep, buffer) in output.buffers.iter().enumerate() {
+                let position = OutputPosition {
+                    step: output.step,
+                    substep: substep as u64,
+                };
+                self.send(position, buffer)?;
+            }
+            self.kafka_producer.commit_transaction(None)?;
+            self.next_step = output.step + 1;
+        }
+        info!(
+            "took over {} from the previous instance of the pipeline at step {}",
+            self.topic, self.next_step
+        );
         Ok(())
     }
 
@@ -223,22 +511,15 @@ impl OutputEndpoint for KafkaOutputEndpoint {
             substep: substep + 1,
         });
 
-        if step >= self.next_step {
-            let key = OutputPosition { step, substep };
-            let key = serde_json::to_string(&key).unwrap();
-            let record = BaseRecord::to(&self.topic)
-                .key(&key)
-                .partition(self.next_partition as i32)
-                .payload(buffer);
-            self.kafka_producer
-                .send(record)
-                .map_err(|(err, _record)| err)?;
-            self.kafka_producer.context().take_delivery_slot();
-
-            self.next_partition += 1;
-            if self.next_partition >= self.n_partitions {
-                self.next_partition = 0;
-            }
+        if let Some(standby) = self.standby.as_mut() {
+            standby
+                .current
+                .as_mut()
+                .unwrap()
+                .buffers
+                .push(buffer.to_vec());
+        } else if step >= self.next_step {
+            self.send(OutputPosition { step, substep }, buffer)?;
         }
         Ok(())
     }
@@ -256,7 +537,15 @@ impl OutputEndpoint for KafkaOutputEndpoint {
         };
         self.state = State::BatchClosed(position.step);
 
-        if position.step >= self.next_step {
+        if let Some(standby) = self.standby.as_mut() {
+            let output = standby.current.take().unwrap();
+            standby.held.push(output);
+            if let Err(error) = self.discard_written_steps() {
+                // The standby can't take over, so report the problem to the
+                // controller as fatal.
+                self.fatal_error(error);
+            }
+        } else if position.step >= self.next_step {
             self.kafka_producer.commit_transaction(None)?;
             self.next_step = position.step + 1;
         }
@@ -280,7 +569,17 @@ impl OutputEndpoint for KafkaOutputEndpoint {
             }
         };
 
-        if step >= self.next_step {
+        if let Some(standby) = self.standby.as_mut() {
+            standby.current = Some(StepOutput {
+                step,
+                buffers: Vec::new(),
+            });
+            if step >= self.next_step {
+                // Find out whether the other instance has written more.
+                (_, self.next_step) =
+                    Self::read_next_step(&self.topic, &self.seekable_consumer_config)?;
+            }
+        } else if step >= self.next_step {
             if step > self.next_step {
                 warn!("skipping from step {} to {step}", self.next_step);
             }
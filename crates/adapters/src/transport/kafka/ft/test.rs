@@ -298,6 +298,79 @@ config:
     receiver2.expect_eof();
 }
 
+/// Test a standby that follows the steps recorded by another reader and then
+/// takes over recording steps.
+#[test]
+fn test_standby_input() {
+    init_test_logger();
+
+    let mock_cluster = MockCluster::new(1).unwrap();
+    let bootstrap_servers = mock_cluster.bootstrap_servers();
+    mock_cluster.create_topic("standby", 1, 1).unwrap();
+    mock_cluster
+        .create_topic("standby_input-index", 1, 1)
+        .unwrap();
+
+    let config_str = format!(
+        r#"
+name: kafka_input
+config:
+    bootstrap.servers: "{bootstrap_servers}"
+    topics: [standby]
+    log_level: debug
+    fault_tolerance: {{}}
+"#
+    );
+
+    let endpoint = input_transport_config_to_endpoint(serde_yaml::from_str(&config_str).unwrap())
+        .unwrap()
+        .unwrap();
+
+    info!("recording steps 0 and 1");
+    let receiver = DummyInputReceiver::new();
+    let reader = endpoint.open(receiver.consumer(), 0).unwrap();
+    reader.start(Step::MAX).unwrap();
+    receiver.expect(vec![ConsumerCall::StartStep(0)]);
+    for step in 0..=1 {
+        reader.complete(step);
+        receiver.expect(vec![ConsumerCall::StartStep(step + 1)]);
+        receiver.wait_to_complete(step);
+    }
+
+    // The standby replays the steps recorded so far, but it must not record
+    // step 2 itself even though it's asked to complete it.
+    info!("opening a standby");
+    let standby_receiver = DummyInputReceiver::new();
+    let standby = endpoint
+        .open_standby(standby_receiver.consumer(), 0)
+        .unwrap();
+    standby.start(Step::MAX).unwrap();
+    for step in 0..=2 {
+        standby_receiver.expect(vec![ConsumerCall::StartStep(step)]);
+    }
+    standby_receiver.wait_to_complete(1);
+    standby.complete(2);
+    standby_receiver.expect_eof();
+    assert_eq!(endpoint.steps().unwrap(), 0..2);
+
+    info!("the standby should follow step 2 once the reader records it");
+    reader.complete(2);
+    receiver.expect(vec![ConsumerCall::StartStep(3)]);
+    receiver.wait_to_complete(2);
+    standby_receiver.expect(vec![ConsumerCall::StartStep(3)]);
+    standby_receiver.wait_to_complete(2);
+    standby_receiver.expect_eof();
+
+    info!("after activation, the standby should record step 3 itself");
+    drop(reader);
+    standby.activate().unwrap();
+    standby.complete(3);
+    standby_receiver.expect(vec![ConsumerCall::StartStep(4)]);
+    standby_receiver.wait_to_complete(3);
+    assert_eq!(endpoint.steps().unwrap(), 0..4);
+    standby_receiver.expect_eof();
+}
+
 #[test]
 fn test_input() {
     init_test_logger();
@@ -1,7 +1,6 @@
 use crate::{
     transport::{
         kafka::{rdkafka_loglevel_from, refine_kafka_error, DeferredLogging},
-        secret_resolver::MaybeSecret,
         InputReader, Step,
     },
     InputConsumer, InputEndpoint, PipelineState,
@@ -10,7 +9,7 @@ use anyhow::{anyhow, bail, Error as AnyError, Result as AnyResult};
 use crossbeam::queue::ArrayQueue;
 use log::debug;
 use num_traits::FromPrimitive;
-use pipeline_types::{secret_ref::MaybeSecretRef, transport::kafka::KafkaInputConfig};
+use pipeline_types::transport::kafka::KafkaInputConfig;
 use rdkafka::config::RDKafkaLogLevel;
 use rdkafka::{
     config::FromClientConfigAndContext,
@@ -176,17 +175,7 @@ impl KafkaInputReader {
         let mut client_config = ClientConfig::new();
 
         for (key, value) in config.kafka_options.iter() {
-            // If it is a secret reference, resolve it to the actual secret string
-            match MaybeSecret::new_using_default_directory(
-                MaybeSecretRef::new_using_pattern_match(value.clone()),
-            )? {
-                MaybeSecret::String(simple_string) => {
-                    client_config.set(key, simple_string);
-                }
-                MaybeSecret::Secret(secret_string) => {
-                    client_config.set(key, secret_string);
-                }
-            }
+            client_config.set(key, value);
         }
 
         if let Some(log_level) = config.log_level {
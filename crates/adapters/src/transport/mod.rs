@@ -21,12 +21,13 @@
 //! let reader = endpoint.open(consumer, 0);
 //! ```
 use crate::format::ParseError;
-use anyhow::{Error as AnyError, Result as AnyResult};
+use anyhow::{anyhow, Error as AnyError, Result as AnyResult};
 use std::ops::Range;
 use std::sync::atomic::AtomicU64;
 
 mod file;
 pub mod http;
+mod pipeline;
 
 pub mod url;
 
@@ -43,7 +44,9 @@ use crate::transport::file::{FileInputEndpoint, FileOutputEndpoint};
 use crate::transport::kafka::{
     KafkaFtInputEndpoint, KafkaFtOutputEndpoint, KafkaInputEndpoint, KafkaOutputEndpoint,
 };
+use crate::transport::pipeline::PipelineInputEndpoint;
 use crate::transport::s3::S3InputEndpoint;
+use crate::transport::secret_resolver::SecretResolver;
 use crate::transport::url::UrlInputEndpoint;
 
 /// Step number for fault-tolerant input and output.
@@ -62,12 +65,14 @@ pub type AtomicStep = AtomicU64;
 
 /// Creates an input transport endpoint instance using an input transport configuration.
 ///
+/// Secret references in the configuration are resolved first.
+///
 /// Returns an error if there is a invalid configuration for the endpoint.
 /// Returns `None` if the transport configuration variant is incompatible with an input endpoint.
 pub fn input_transport_config_to_endpoint(
     config: TransportConfig,
 ) -> AnyResult<Option<Box<dyn InputEndpoint>>> {
-    match config {
+    match SecretResolver::from_env().resolve_transport_config(config)? {
         TransportConfig::FileInput(config) => Ok(Some(Box::new(FileInputEndpoint::new(config)))),
         #[cfg(feature = "with-kafka")]
         TransportConfig::KafkaInput(config) => match config.fault_tolerance {
@@ -76,18 +81,23 @@ pub fn input_transport_config_to_endpoint(
         },
         TransportConfig::UrlInput(config) => Ok(Some(Box::new(UrlInputEndpoint::new(config)))),
         TransportConfig::S3Input(config) => Ok(Some(Box::new(S3InputEndpoint::new(config)))),
+        TransportConfig::PipelineInput(config) => {
+            Ok(Some(Box::new(PipelineInputEndpoint::new(config)?)))
+        }
         _ => Ok(None),
     }
 }
 
 /// Creates an output transport endpoint instance using an output transport configuration.
 ///
+/// Secret references in the configuration are resolved first.
+///
 /// Returns an error if there is a invalid configuration for the endpoint.
 /// Returns `None` if the transport configuration variant is incompatible with an output endpoint.
 pub fn output_transport_config_to_endpoint(
     config: TransportConfig,
 ) -> AnyResult<Option<Box<dyn OutputEndpoint>>> {
-    match config {
+    match SecretResolver::from_env().resolve_transport_config(config)? {
         TransportConfig::FileOutput(config) => Ok(Some(Box::new(FileOutputEndpoint::new(config)?))),
         #[cfg(feature = "with-kafka")]
         TransportConfig::KafkaOutput(config) => match config.fault_tolerance {
@@ -126,6 +136,25 @@ pub trait InputEndpoint: Send {
         start_step: Step,
     ) -> AnyResult<Box<dyn InputReader>>;
 
+    /// Returns an [`InputReader`] for a [standby](crate#standby-pipelines)
+    /// pipeline, starting at `start_step`.
+    ///
+    /// The reader reads the steps recorded by another instance of the pipeline
+    /// that reads from the same source, including the steps that the other
+    /// instance records after this call, but it doesn't record any steps of
+    /// its own until [`InputReader::activate`] is called.
+    ///
+    /// Only fault-tolerant endpoints support standby pipelines.
+    fn open_standby(
+        &self,
+        _consumer: Box<dyn InputConsumer>,
+        _start_step: Step,
+    ) -> AnyResult<Box<dyn InputReader>> {
+        Err(anyhow!(
+            "this endpoint is not fault tolerant, so it cannot be used in a standby pipeline"
+        ))
+    }
+
     /// For a fault-tolerant endpoint, notifies the endpoint that steps less
     /// than `step` aren't needed anymore.  It may optionally discard them.
     ///
@@ -178,6 +207,16 @@ pub trait InputReader: Send {
     /// complete once a step fills up to the maximum size.
     fn complete(&self, _step: Step) {}
 
+    /// Makes a reader returned by [`InputEndpoint::open_standby`] take over
+    /// from the other instance of the pipeline, which must have stopped.
+    ///
+    /// The reader first reads the steps that the other instance recorded
+    /// before it stopped, and then records steps of its own.  This is a no-op
+    /// for other readers.
+    fn activate(&self) -> AnyResult<()> {
+        Ok(())
+    }
+
     /// Disconnect the endpoint.
     ///
     /// Disconnect the endpoint and stop receiving data.  This is the last
@@ -290,6 +329,32 @@ pub trait OutputEndpoint: Send {
     /// that indicates a fatal error that the endpoint cannot recover from.
     fn connect(&mut self, async_error_callback: AsyncErrorCallback) -> AnyResult<()>;
 
+    /// Connects the endpoint for a [standby](crate#standby-pipelines)
+    /// pipeline, like [`OutputEndpoint::connect`].
+    ///
+    /// Another instance of the pipeline writes to the same destination.  The
+    /// endpoint discards the output of the steps that the other instance has
+    /// output and holds back the output of the other steps until
+    /// [`OutputEndpoint::activate`] is called.
+    ///
+    /// Only fault-tolerant endpoints support standby pipelines.
+    fn connect_standby(&mut self, _async_error_callback: AsyncErrorCallback) -> AnyResult<()> {
+        Err(anyhow!(
+            "this endpoint is not fault tolerant, so it cannot be used in a standby pipeline"
+        ))
+    }
+
+    /// Makes an endpoint connected with [`OutputEndpoint::connect_standby`]
+    /// take over from the other instance of the pipeline, which must have
+    /// stopped.
+    ///
+    /// The endpoint outputs the steps that it has held back and that the other
+    /// instance didn't output before it stopped.  This is a no-op for other
+    /// endpoints.
+    fn activate(&mut self) -> AnyResult<()> {
+        Ok(())
+    }
+
     /// Maximum buffer size that this transport can transmit.
     /// The encoder should not generate buffers exceeding this size.
     fn max_buffer_size_bytes(&self) -> usize;
@@ -0,0 +1,454 @@
+use super::url::rustls_config;
+use super::{InputConsumer, InputEndpoint, InputReader, Step};
+use crate::PipelineState;
+use actix::{clock::sleep, System};
+use actix_web::http::{header::AUTHORIZATION, StatusCode};
+use anyhow::{anyhow, bail, Result as AnyResult};
+use awc::{Client, Connector};
+use futures::StreamExt;
+use pipeline_types::transport::pipeline::PipelineInputConfig;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::{collections::HashMap, sync::Arc, thread::spawn, time::Duration};
+use tokio::{
+    select,
+    sync::watch::{channel, Receiver, Sender},
+};
+
+/// Input endpoint that reads the changes to a view of another pipeline.
+///
+/// The endpoint connects to the `/egress` endpoint of the other pipeline
+/// through the pipeline manager API, with backpressure, and reconnects when
+/// the connection is lost, e.g., because the other pipeline was restarted.
+/// Changes that the other pipeline outputs while the endpoint is
+/// disconnected are not received.  Instead, the endpoint reads a snapshot of
+/// the view whenever it connects, and retracts the records it received over
+/// the previous connection along with the snapshot.  To do so, it keeps the
+/// current contents of the view in memory.
+pub(crate) struct PipelineInputEndpoint {
+    config: Arc<PipelineInputConfig>,
+}
+
+impl PipelineInputEndpoint {
+    pub(crate) fn new(config: PipelineInputConfig) -> AnyResult<Self> {
+        if config.api_url.is_none() {
+            bail!(
+                "the URL of the pipeline manager API is not set; connectors that read from pipeline '{}' can only be used by pipelines started by the pipeline manager",
+                config.pipeline
+            );
+        }
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+}
+
+impl InputEndpoint for PipelineInputEndpoint {
+    fn open(
+        &self,
+        consumer: Box<dyn InputConsumer>,
+        _start_step: Step,
+    ) -> AnyResult<Box<dyn InputReader>> {
+        Ok(Box::new(PipelineInputReader::new(&self.config, consumer)))
+    }
+
+    fn is_fault_tolerant(&self) -> bool {
+        false
+    }
+}
+
+/// A chunk of output streamed by the `/egress` endpoint.
+#[derive(Deserialize)]
+struct Chunk<'a> {
+    /// Changes encoded as a JSON array.  Absent in the empty chunks that
+    /// keep the connection alive.
+    #[serde(borrow, default)]
+    json_data: Option<&'a RawValue>,
+}
+
+/// A change in a chunk, in the `insert_delete` format.
+#[derive(Deserialize)]
+struct Update<'a> {
+    #[serde(borrow, default)]
+    insert: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    delete: Option<&'a RawValue>,
+}
+
+struct PipelineInputReader {
+    sender: Sender<PipelineState>,
+}
+
+impl PipelineInputReader {
+    fn new(config: &Arc<PipelineInputConfig>, mut consumer: Box<dyn InputConsumer>) -> Self {
+        let (sender, receiver) = channel(PipelineState::Paused);
+        let config = config.clone();
+        let _worker = spawn(move || {
+            System::new().block_on(async move {
+                if let Err(error) = Self::worker_thread(config, &mut consumer, receiver).await {
+                    consumer.error(true, error);
+                } else {
+                    let _ = consumer.eoi();
+                };
+            });
+        });
+
+        Self { sender }
+    }
+
+    async fn worker_thread(
+        config: Arc<PipelineInputConfig>,
+        consumer: &mut Box<dyn InputConsumer>,
+        mut receiver: Receiver<PipelineState>,
+    ) -> AnyResult<()> {
+        let client = Client::builder()
+            .connector(Connector::new().rustls(rustls_config()))
+            .finish();
+        let url = format!(
+            "{}{}",
+            config
+                .api_url
+                .as_deref()
+                .unwrap_or_default()
+                .trim_end_matches('/'),
+            config.egress_path()
+        );
+        let reconnect_delay = Duration::from_millis(config.reconnect_delay_ms);
+
+        // The `ClientResponse`, if we are connected.
+        let mut response = None;
+
+        // Output received from the pipeline that doesn't end in a complete
+        // chunk yet.
+        let mut buffer = Vec::new();
+
+        // Records received from the pipeline, with their weights, keyed by
+        // their JSON encoding.
+        let mut state = HashMap::new();
+
+        // Whether the next changes we receive are the snapshot of the view
+        // that starts every connection.
+        let mut snapshot = false;
+
+        // Whether we reported that the connection is lost since we were last
+        // connected, to avoid reporting the same outage on every attempt to
+        // reconnect.
+        let mut reported = false;
+
+        loop {
+            let state = *receiver.borrow();
+            match state {
+                PipelineState::Terminated => return Ok(()),
+                PipelineState::Paused => {
+                    // Unlike the URL transport, stay connected but stop
+                    // reading.  The other pipeline then waits for us to
+                    // resume instead of dropping its output.
+                    receiver.changed().await?;
+                }
+                PipelineState::Running => {
+                    if response.is_none() {
+                        let mut request = client.post(&url);
+                        if let Some(api_key) = &config.api_key {
+                            request =
+                                request.insert_header((AUTHORIZATION, format!("Bearer {api_key}")));
+                        }
+                        let error = match request.send().await {
+                            Ok(r) if r.status().is_success() => {
+                                buffer.clear();
+                                snapshot = true;
+                                reported = false;
+                                response = Some(r);
+                                continue;
+                            }
+                            Ok(mut r) => {
+                                let status = r.status();
+                                let body = r
+                                    .body()
+                                    .await
+                                    .map(|body| String::from_utf8_lossy(&body).into_owned())
+                                    .unwrap_or_default();
+                                let error = anyhow!(
+                                    "pipeline manager responded with HTTP status code ({status}) to the request to read view '{}' of pipeline '{}': {body}",
+                                    config.view,
+                                    config.pipeline
+                                );
+                                // The pipeline isn't running or the pipeline
+                                // manager can't reach it; any other error
+                                // won't go away by retrying.
+                                if status != StatusCode::GONE && !status.is_server_error() {
+                                    return Err(error);
+                                }
+                                error
+                            }
+                            // `awc` intentionally uses errors that aren't
+                            // `Sync`, but `anyhow::Error` requires `Sync`.
+                            Err(error) => anyhow!(
+                                "unable to connect to the pipeline manager at '{url}': {error}"
+                            ),
+                        };
+                        if !reported {
+                            consumer.error(false, anyhow!("{error}; reconnecting"));
+                            reported = true;
+                        }
+                        select! {
+                            _ = receiver.changed() => (),
+                            _ = sleep(reconnect_delay) => (),
+                        }
+                        continue;
+                    }
+                    let r = response.as_mut().unwrap();
+
+                    let result = select! {
+                        _ = receiver.changed() => continue,
+                        result = r.next() => result,
+                    };
+                    match result {
+                        Some(Ok(data)) => {
+                            buffer.extend_from_slice(&data);
+                            Self::consume_chunks(&mut buffer, &mut state, &mut snapshot, consumer)?;
+                        }
+                        end => {
+                            // The other pipeline was shut down or restarted,
+                            // or the connection failed.
+                            response = None;
+                            let reason = match end {
+                                Some(Err(error)) => error.to_string(),
+                                _ => "connection closed".to_string(),
+                            };
+                            consumer.error(
+                                false,
+                                anyhow!(
+                                    "lost connection to view '{}' of pipeline '{}' ({reason}); reconnecting",
+                                    config.view,
+                                    config.pipeline
+                                ),
+                            );
+                            reported = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Passes the changes in the complete chunks at the beginning of `buffer`
+    /// to `consumer` and removes the chunks from `buffer`.
+    ///
+    /// Chunks are separated by newlines.  `state` tracks the records received
+    /// so far.  If `snapshot` is set, the first changes are the snapshot of
+    /// the view: they replace `state`, and `snapshot` is cleared.
+    fn consume_chunks(
+        buffer: &mut Vec<u8>,
+        state: &mut HashMap<String, i64>,
+        snapshot: &mut bool,
+        consumer: &mut Box<dyn InputConsumer>,
+    ) -> AnyResult<()> {
+        let mut start = 0;
+        while let Some(len) = buffer[start..].iter().position(|b| *b == b'\n') {
+            let line = &buffer[start..start + len];
+            start += len + 1;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let chunk = serde_json::from_slice::<Chunk>(line)
+                .map_err(|e| anyhow!("invalid chunk received from the pipeline: {e}"))?;
+            let Some(data) = chunk.json_data else {
+                continue;
+            };
+            let updates = serde_json::from_str::<Vec<&RawValue>>(data.get())
+                .map_err(|e| anyhow!("invalid changes received from the pipeline: {e}"))?;
+            // Retract the previous state in the same chunk as the snapshot,
+            // so that the table never appears empty in between.
+            let mut retractions = Vec::new();
+            if std::mem::take(snapshot) {
+                for (record, weight) in state.drain() {
+                    for _ in 0..weight {
+                        retractions.push(format!(r#"{{"delete":{record}}}"#));
+                    }
+                }
+            }
+            for update in &updates {
+                let update = serde_json::from_str::<Update>(update.get())
+                    .map_err(|e| anyhow!("invalid change received from the pipeline: {e}"))?;
+                if let Some(record) = update.delete {
+                    Self::update_state(state, record, -1);
+                }
+                if let Some(record) = update.insert {
+                    Self::update_state(state, record, 1);
+                }
+            }
+            if retractions.is_empty() {
+                let _ = consumer.input_chunk(data.get().as_bytes());
+            } else {
+                retractions.extend(updates.iter().map(|update| update.get().to_string()));
+                let _ = consumer.input_chunk(format!("[{}]", retractions.join(",")).as_bytes());
+            }
+        }
+        buffer.drain(..start);
+        Ok(())
+    }
+
+    /// Adds `weight` to the weight of `record` in `state`.
+    fn update_state(state: &mut HashMap<String, i64>, record: &RawValue, weight: i64) {
+        let count = state.entry(record.get().to_string()).or_default();
+        *count += weight;
+        if *count == 0 {
+            state.remove(record.get());
+        }
+    }
+}
+
+impl InputReader for PipelineInputReader {
+    fn pause(&self) -> AnyResult<()> {
+        self.sender.send_replace(PipelineState::Paused);
+        Ok(())
+    }
+
+    fn start(&self, _step: Step) -> AnyResult<()> {
+        self.sender.send_replace(PipelineState::Running);
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        self.sender.send_replace(PipelineState::Terminated);
+    }
+}
+
+impl Drop for PipelineInputReader {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        test::{mock_input_pipeline, wait, MockDeZSet, MockInputConsumer, DEFAULT_TIMEOUT_MS},
+        transport::InputReader,
+    };
+    use actix::System;
+    use actix_web::{middleware, web, App, HttpResponse, HttpServer};
+    use pipeline_types::deserialize_without_context;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::channel,
+        },
+        thread::spawn,
+    };
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+    struct TestStruct {
+        s: String,
+        b: bool,
+        i: i64,
+    }
+
+    deserialize_without_context!(TestStruct);
+
+    fn n_recs(zset: &MockDeZSet<TestStruct, TestStruct>) -> usize {
+        zset.state().flushed.len()
+    }
+
+    /// Output of the `/egress` endpoint, including an empty keep-alive chunk.
+    const EGRESS_OUTPUT: &str = concat!(
+        r#"{"sequence_number":0,"json_data":[{"insert":{"s":"foo","b":true,"i":10}}]}"#,
+        "\r\n",
+        r#"{"sequence_number":1}"#,
+        "\r\n",
+        r#"{"sequence_number":2,"json_data":[{"insert":{"s":"bar","b":false,"i":-10}}]}"#,
+        "\r\n",
+    );
+
+    /// Number of requests received by the test server.
+    static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Responds to the first request as if the pipeline wasn't running yet.
+    async fn egress() -> HttpResponse {
+        if REQUESTS.fetch_add(1, Ordering::AcqRel) == 0 {
+            HttpResponse::Gone().body("pipeline is not running")
+        } else {
+            HttpResponse::Ok().body(EGRESS_OUTPUT)
+        }
+    }
+
+    fn setup_test() -> (
+        Box<dyn InputReader>,
+        MockInputConsumer,
+        MockDeZSet<TestStruct, TestStruct>,
+    ) {
+        let (sender, receiver) = channel();
+        spawn(move || {
+            System::new().block_on(async {
+                let server = HttpServer::new(move || {
+                    App::new()
+                        .wrap(middleware::Logger::default())
+                        .service(web::resource("/v0/pipelines/upstream/egress/v").to(egress))
+                })
+                .workers(1)
+                .bind(("127.0.0.1", 0))
+                .unwrap();
+                sender.send(server.addrs()[0]).unwrap();
+                server.run().await.unwrap();
+            });
+        });
+        let addr = receiver.recv().unwrap();
+        let config_str = format!(
+            r#"
+stream: test_input
+transport:
+    name: pipeline_input
+    config:
+        pipeline: upstream
+        view: v
+        reconnect_delay_ms: 10
+        api_url: http://{addr}
+format:
+    name: json
+    config:
+        update_format: insert_delete
+        array: true
+"#
+        );
+
+        mock_input_pipeline::<TestStruct, TestStruct>(serde_yaml::from_str(&config_str).unwrap())
+            .unwrap()
+    }
+
+    /// The endpoint retries until the pipeline runs, and reconnects after the
+    /// pipeline closes the connection, retracting the records it received
+    /// before the snapshot it reads on reconnect.
+    #[test]
+    fn test_reconnect() {
+        let (endpoint, consumer, zset) = setup_test();
+        consumer.on_error(Some(Box::new(|fatal, _| assert!(!fatal))));
+
+        endpoint.start(0).unwrap();
+        wait(|| n_recs(&zset) >= 6, DEFAULT_TIMEOUT_MS).unwrap();
+        assert!(consumer.state().endpoint_error.is_some());
+        assert!(REQUESTS.load(Ordering::Acquire) >= 3);
+
+        let expected = [
+            TestStruct {
+                s: "foo".to_string(),
+                b: true,
+                i: 10,
+            },
+            TestStruct {
+                s: "bar".to_string(),
+                b: false,
+                i: -10,
+            },
+        ];
+        let state = zset.state();
+        let flushed = &state.flushed;
+        assert_eq!(flushed[0].unwrap_insert(), &expected[0]);
+        assert_eq!(flushed[1].unwrap_insert(), &expected[1]);
+        let mut retracted = vec![flushed[2].unwrap_delete(), flushed[3].unwrap_delete()];
+        retracted.sort_by_key(|record| record.i);
+        assert_eq!(retracted, [&expected[1], &expected[0]]);
+        assert_eq!(flushed[4].unwrap_insert(), &expected[0]);
+        assert_eq!(flushed[5].unwrap_insert(), &expected[1]);
+    }
+}
@@ -263,13 +263,20 @@ impl S3InputReader {
 }
 
 fn to_s3_config(config: &Arc<S3InputConfig>) -> aws_sdk_s3::Config {
-    let config_builder =
+    let mut config_builder =
         aws_sdk_s3::Config::builder().region(aws_types::region::Region::new(config.region.clone()));
+    if let Some(endpoint_url) = &config.endpoint_url {
+        // S3-compatible object stores typically do not support
+        // virtual-hosted-style bucket addressing.
+        config_builder = config_builder
+            .endpoint_url(endpoint_url)
+            .force_path_style(true);
+    }
     match &config.credentials {
-        AwsCredentials::AccessKey {
+        Some(AwsCredentials::AccessKey {
             aws_access_key_id,
             aws_secret_access_key,
-        } => {
+        }) => {
             let credentials = aws_sdk_s3::config::Credentials::new(
                 aws_access_key_id.clone(),
                 aws_secret_access_key.clone(),
@@ -279,7 +286,7 @@ fn to_s3_config(config: &Arc<S3InputConfig>) -> aws_sdk_s3::Config {
             );
             config_builder.credentials_provider(credentials).build()
         }
-        AwsCredentials::NoSignRequest => config_builder.build(),
+        Some(AwsCredentials::NoSignRequest) | None => config_builder.build(),
     }
 }
 
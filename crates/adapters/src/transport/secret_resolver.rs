@@ -1,11 +1,17 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result as AnyResult};
 use log::debug;
-use pipeline_types::secret_ref::MaybeSecretRef;
+use pipeline_types::config::TransportConfig;
+use pipeline_types::secret_ref::{
+    replace_secret_refs_in_json, secret_refs_in_json, MaybeSecretRef, SecretProviderType,
+    VaultSecretRef, ENV_SECRET_PREFIX, VAULT_PATH_PREFIX_ENV,
+};
 use regex::Regex;
 
 /// Enumeration which holds a simple string or a resolved secret's string.
@@ -67,14 +73,6 @@ impl MaybeSecret {
         re.is_match(secret_ref)
     }
 
-    /// Resolves a potential secret reference to a secret or simply return the
-    /// string if it is a string. The default secrets directory is used.
-    /// More information regarding resolution is detailed in
-    /// [MaybeSecret::new].
-    pub fn new_using_default_directory(value: MaybeSecretRef) -> AnyResult<Self> {
-        Self::new(Path::new(Self::DEFAULT_SECRETS_DIRECTORY_PATH), value)
-    }
-
     /// Resolves a potential secret reference to a secret or simply return the
     /// string if it is a string.
     ///
@@ -128,20 +126,273 @@ impl MaybeSecret {
     }
 }
 
+/// Source of secret strings, which resolves the identifiers of the secret
+/// references of a single [SecretProviderType].
+pub trait SecretProvider: Send + Sync {
+    /// Returns the secret string identified by `key`, the part of the secret
+    /// reference that follows the provider prefix.
+    fn resolve(&self, key: &str) -> AnyResult<String>;
+}
+
+/// Secrets stored as files in a directory, see [MaybeSecret::new].
+pub struct DirectorySecretProvider {
+    path: PathBuf,
+}
+
+impl DirectorySecretProvider {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl SecretProvider for DirectorySecretProvider {
+    fn resolve(&self, key: &str) -> AnyResult<String> {
+        match MaybeSecret::new(&self.path, MaybeSecretRef::SecretRef(key.to_string()))? {
+            MaybeSecret::String(s) | MaybeSecret::Secret(s) => Ok(s),
+        }
+    }
+}
+
+/// Secrets stored in environment variables of the pipeline process.
+///
+/// Only variables whose name starts with [`ENV_SECRET_PREFIX`] can be
+/// referenced.
+pub struct EnvSecretProvider {
+    vars: BTreeMap<String, String>,
+}
+
+impl EnvSecretProvider {
+    /// Creates a provider of the secrets in `vars`, which maps variable names
+    /// to values.
+    pub fn new(vars: BTreeMap<String, String>) -> Self {
+        Self {
+            vars: vars
+                .into_iter()
+                .filter(|(name, _)| name.starts_with(ENV_SECRET_PREFIX))
+                .collect(),
+        }
+    }
+
+    /// Creates a provider of the secrets in the environment of the process.
+    pub fn from_env() -> Self {
+        Self::new(std::env::vars().collect())
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn resolve(&self, key: &str) -> AnyResult<String> {
+        if !key.starts_with(ENV_SECRET_PREFIX) {
+            return Err(anyhow!(
+                "Could not resolve secret reference env:{key}: only environment variables starting with {ENV_SECRET_PREFIX} can be referenced"
+            ));
+        }
+        self.vars.get(key).cloned().ok_or_else(|| {
+            anyhow!("Could not resolve secret reference env:{key}: environment variable not found")
+        })
+    }
+}
+
+/// Secrets stored in a KV (version 2) secrets engine of HashiCorp Vault,
+/// read through its HTTP API.
+pub struct VaultSecretProvider {
+    /// Address of the Vault server, e.g., `https://vault.example.com:8200`.
+    address: String,
+    token: String,
+    namespace: Option<String>,
+    /// Path, within each secrets engine, under which secrets can be read.
+    path_prefix: Option<String>,
+}
+
+impl VaultSecretProvider {
+    /// Maximum time to wait for a response from the Vault server.
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(address: &str, token: &str, namespace: Option<&str>) -> Self {
+        Self {
+            address: address.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            namespace: namespace.map(str::to_string),
+            path_prefix: None,
+        }
+    }
+
+    /// Restricts the provider to the secrets under `path_prefix` of each
+    /// secrets engine.
+    pub fn with_path_prefix(mut self, path_prefix: &str) -> Self {
+        self.path_prefix = Some(path_prefix.to_string());
+        self
+    }
+
+    /// Configures the provider from the environment variables used by the
+    /// Vault CLI: `VAULT_ADDR`, `VAULT_TOKEN` and, optionally,
+    /// `VAULT_NAMESPACE`, restricted to the path in
+    /// [`VAULT_PATH_PREFIX_ENV`] if set.
+    ///
+    /// Returns `None` if the address or the token is not set.
+    pub fn from_env() -> Option<Self> {
+        let address = std::env::var("VAULT_ADDR").ok()?;
+        let token = std::env::var("VAULT_TOKEN").ok()?;
+        let namespace = std::env::var("VAULT_NAMESPACE").ok();
+        let vault = Self::new(&address, &token, namespace.as_deref());
+        match std::env::var(VAULT_PATH_PREFIX_ENV) {
+            Ok(path_prefix) => Some(vault.with_path_prefix(&path_prefix)),
+            Err(_) => Some(vault),
+        }
+    }
+
+    fn fetch(&self, vault_ref: &VaultSecretRef) -> AnyResult<serde_json::Value> {
+        let url = format!("{}/{}", self.address, vault_ref.api_path());
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()?;
+        let mut request = client.get(&url).header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request.send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Vault responded with {status} to GET {url}"));
+        }
+        Ok(response.json()?)
+    }
+}
+
+impl SecretProvider for VaultSecretProvider {
+    fn resolve(&self, key: &str) -> AnyResult<String> {
+        let vault_ref = VaultSecretRef::parse(key).map_err(|e| anyhow!(e))?;
+        if let Some(path_prefix) = &self.path_prefix {
+            if !vault_ref.is_within(path_prefix) {
+                return Err(anyhow!(
+                    "Could not resolve secret reference vault:{key}: the pipeline can only read secrets under path '{path_prefix}'"
+                ));
+            }
+        }
+        // The blocking HTTP client cannot run on an async runtime thread,
+        // which connectors may be created from.
+        let response = std::thread::scope(|scope| {
+            scope
+                .spawn(|| self.fetch(&vault_ref))
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("Vault request panicked")))
+        })
+        .map_err(|e| anyhow!("Could not resolve secret reference vault:{key}: {e}"))?;
+        vault_ref
+            .value_from_response(&response)
+            .map_err(|e| anyhow!(e))
+    }
+}
+
+/// Resolves secret references, dispatching each reference to the provider
+/// of its type.
+pub struct SecretResolver {
+    providers: BTreeMap<SecretProviderType, Box<dyn SecretProvider>>,
+}
+
+impl SecretResolver {
+    /// Creates a resolver without providers.
+    pub fn new() -> Self {
+        Self {
+            providers: BTreeMap::new(),
+        }
+    }
+
+    /// Adds or replaces the provider of secret references of type
+    /// `provider_type`.
+    pub fn with_provider(
+        mut self,
+        provider_type: SecretProviderType,
+        provider: Box<dyn SecretProvider>,
+    ) -> Self {
+        self.providers.insert(provider_type, provider);
+        self
+    }
+
+    /// Resolver used by pipelines: secrets from the default secrets
+    /// directory, environment variables and, if configured through the
+    /// environment, Vault.
+    ///
+    /// Secrets stored in the database of the pipeline manager are resolved
+    /// by the manager before it starts the pipeline.
+    pub fn from_env() -> Self {
+        let resolver = Self::new()
+            .with_provider(
+                SecretProviderType::Directory,
+                Box::new(DirectorySecretProvider::new(Path::new(
+                    MaybeSecret::DEFAULT_SECRETS_DIRECTORY_PATH,
+                ))),
+            )
+            .with_provider(
+                SecretProviderType::Env,
+                Box::new(EnvSecretProvider::from_env()),
+            );
+        match VaultSecretProvider::from_env() {
+            Some(vault) => resolver.with_provider(SecretProviderType::Vault, Box::new(vault)),
+            None => resolver,
+        }
+    }
+
+    /// Resolves a secret identifier, i.e., the part of a secret reference
+    /// between `${secret:` and `}`.
+    pub fn resolve(&self, secret_ref: &str) -> AnyResult<String> {
+        let (provider_type, key) = SecretProviderType::parse(secret_ref).map_err(|e| anyhow!(e))?;
+        let provider = self.providers.get(&provider_type).ok_or_else(|| {
+            anyhow!("Could not resolve secret reference {secret_ref}: the {provider_type} secret provider is not configured")
+        })?;
+        let secret = provider.resolve(key)?;
+        debug!("Secret reference resolution successful: {secret_ref}");
+        Ok(secret)
+    }
+
+    /// Returns a copy of `config` in which every string field that is a
+    /// secret reference is replaced by the secret string.
+    pub fn resolve_transport_config(&self, config: TransportConfig) -> AnyResult<TransportConfig> {
+        let mut json = serde_json::to_value(&config)?;
+        let refs = secret_refs_in_json(&json);
+        if refs.is_empty() {
+            return Ok(config);
+        }
+        let mut secrets = BTreeMap::new();
+        for (path, secret_ref) in refs {
+            let secret = self
+                .resolve(&secret_ref)
+                .map_err(|e| anyhow!("{}: {e}", path))?;
+            secrets.insert(secret_ref, secret);
+        }
+        replace_secret_refs_in_json(&mut json, &secrets);
+        Ok(serde_json::from_value(json)?)
+    }
+}
+
+impl Default for SecretResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use std::fs;
     use std::fs::File;
     use std::io::Write;
+    use std::path::Path;
 
-    use super::MaybeSecret;
-    use pipeline_types::secret_ref::MaybeSecretRef;
+    use super::{
+        DirectorySecretProvider, EnvSecretProvider, MaybeSecret, SecretResolver,
+        VaultSecretProvider,
+    };
+    use pipeline_types::config::TransportConfig;
+    use pipeline_types::secret_ref::{MaybeSecretRef, SecretProviderType};
 
     #[test]
     fn test_new_string() {
-        match MaybeSecret::new_using_default_directory(MaybeSecretRef::String(
-            "example".to_string(),
-        )) {
+        match MaybeSecret::new(
+            Path::new(MaybeSecret::DEFAULT_SECRETS_DIRECTORY_PATH),
+            MaybeSecretRef::String("example".to_string()),
+        ) {
             Ok(MaybeSecret::String(simple_string)) => {
                 assert_eq!("example", simple_string);
             }
@@ -216,9 +467,10 @@ mod tests {
 
     #[test]
     fn test_new_secret_failure_invalid_format() {
-        match MaybeSecret::new_using_default_directory(MaybeSecretRef::SecretRef(
-            "-example".to_string(),
-        )) {
+        match MaybeSecret::new(
+            Path::new(MaybeSecret::DEFAULT_SECRETS_DIRECTORY_PATH),
+            MaybeSecretRef::SecretRef("-example".to_string()),
+        ) {
             Ok(_) => {
                 assert!(false);
             }
@@ -329,4 +581,119 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_resolve_transport_config() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".api-token"), "Bearer 123").unwrap();
+        let vars = BTreeMap::from([
+            (
+                "FELDERA_SECRET_PATH".to_string(),
+                "https://example.com/data.csv".to_string(),
+            ),
+            ("HOME".to_string(), "/home/feldera".to_string()),
+        ]);
+        let resolver = SecretResolver::new()
+            .with_provider(
+                SecretProviderType::Directory,
+                Box::new(DirectorySecretProvider::new(dir.path())),
+            )
+            .with_provider(
+                SecretProviderType::Env,
+                Box::new(EnvSecretProvider::new(vars)),
+            );
+
+        let config: TransportConfig = serde_yaml::from_str(
+            r#"
+name: url_input
+config:
+    path: ${secret:env:FELDERA_SECRET_PATH}
+    headers:
+        Authorization: ${secret:api-token}
+        Accept: text/csv
+"#,
+        )
+        .unwrap();
+        let TransportConfig::UrlInput(resolved) =
+            resolver.resolve_transport_config(config).unwrap()
+        else {
+            panic!("unexpected transport")
+        };
+        assert_eq!(resolved.path, "https://example.com/data.csv");
+        assert_eq!(resolved.headers["Authorization"], "Bearer 123");
+        assert_eq!(resolved.headers["Accept"], "text/csv");
+
+        // Secrets stored by the pipeline manager are not resolved by pipelines.
+        let config: TransportConfig = serde_yaml::from_str(
+            r#"
+name: url_input
+config:
+    path: ${secret:db:url}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            resolver
+                .resolve_transport_config(config)
+                .unwrap_err()
+                .to_string(),
+            "path: Could not resolve secret reference db:url: the db secret provider is not configured"
+        );
+        assert!(resolver.resolve("env:FELDERA_SECRET_MISSING").is_err());
+        // Only variables with the secret prefix can be referenced.
+        assert!(resolver.resolve("env:HOME").is_err());
+    }
+
+    #[test]
+    fn test_vault() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        // Stand-in for the Vault server, which answers a single request.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            for line in BufReader::new(&mut stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                request.push(line.to_lowercase());
+            }
+            let body = r#"{"data": {"data": {"password": "hunter2"}, "metadata": {"version": 1}}}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            request
+        });
+
+        let vault = VaultSecretProvider::new(&address, "root-token", Some("team"))
+            .with_path_prefix("kafka");
+        let resolver =
+            SecretResolver::new().with_provider(SecretProviderType::Vault, Box::new(vault));
+        assert_eq!(
+            resolver
+                .resolve("vault:secret/kafka/prod#password")
+                .unwrap(),
+            "hunter2"
+        );
+        let request = server.join().unwrap();
+        assert!(request[0].starts_with("get /v1/secret/data/kafka/prod "));
+        assert!(request.contains(&"x-vault-token: root-token".to_string()));
+        assert!(request.contains(&"x-vault-namespace: team".to_string()));
+
+        assert!(resolver.resolve("vault:secret#password").is_err());
+        // Secrets outside the path prefix are refused without a request.
+        assert!(resolver
+            .resolve("vault:secret/billing/prod#password")
+            .is_err());
+        assert!(resolver
+            .resolve("vault:secret/kafka/../billing#password")
+            .is_err());
+    }
 }
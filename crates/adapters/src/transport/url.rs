@@ -105,6 +105,9 @@ impl UrlInputReader {
                     // following pause, connect to the server.
                     if response.is_none() {
                         let mut request = client.get(&config.path);
+                        for (name, value) in &config.headers {
+                            request = request.insert_header((name.as_str(), value.as_str()));
+                        }
                         if consumed_bytes > 0 {
                             // Try to resume at the point where we left off.
                             request =
@@ -221,7 +224,7 @@ impl Drop for UrlInputReader {
     }
 }
 
-fn rustls_config() -> Arc<ClientConfig> {
+pub(super) fn rustls_config() -> Arc<ClientConfig> {
     lazy_static! {
         static ref ROOT_STORE: Arc<ClientConfig> = {
             let mut root_store = RootCertStore::empty();
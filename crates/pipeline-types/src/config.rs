@@ -7,12 +7,14 @@
 
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value as YamlValue;
-use std::{borrow::Cow, collections::BTreeMap};
+use std::{borrow::Cow, collections::BTreeMap, time::Duration};
 use utoipa::ToSchema;
 
 use crate::query::OutputQuery;
+use crate::service::ServiceConfig;
 use crate::transport::file::{FileInputConfig, FileOutputConfig};
 use crate::transport::kafka::{KafkaInputConfig, KafkaOutputConfig};
+use crate::transport::pipeline::PipelineInputConfig;
 use crate::transport::s3::S3InputConfig;
 use crate::transport::url::UrlInputConfig;
 
@@ -85,10 +87,21 @@ pub struct RuntimeConfig {
     #[serde(default)]
     pub max_buffering_delay_usecs: u64,
 
-    /// Resource reservations and limits. This is enforced
-    /// only in Feldera Cloud.
+    /// Resource reservations and limits. These are enforced in
+    /// Feldera Cloud and, on Linux, by the local pipeline runner, which
+    /// does not enforce `storage_mb_max`.
     #[serde(default)]
     pub resources: ResourceConfig,
+
+    /// Policy for restarting the pipeline when it fails.  Defaults to
+    /// never restarting it.
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+
+    /// Port to serve the Arrow Flight API on.  The Flight server is
+    /// disabled when unset.
+    #[serde(default)]
+    pub flight_port: Option<u16>,
 }
 
 impl RuntimeConfig {
@@ -219,6 +232,11 @@ pub struct OutputEndpointConfig {
     #[serde(skip)]
     pub query: OutputQuery,
 
+    /// Send a snapshot of the query result before sending changes to it.
+    /// Only used for HTTP API endpoints.
+    #[serde(skip)]
+    pub snapshot: bool,
+
     /// Connector configuration.
     #[serde(flatten)]
     pub connector_config: ConnectorConfig,
@@ -240,6 +258,7 @@ pub enum TransportConfig {
     KafkaOutput(KafkaOutputConfig),
     UrlInput(UrlInputConfig),
     S3Input(S3InputConfig),
+    PipelineInput(PipelineInputConfig),
     /// Direct HTTP input: cannot be instantiated through API
     HttpInput,
     /// Direct HTTP output: cannot be instantiated through API
@@ -255,10 +274,75 @@ impl TransportConfig {
             TransportConfig::KafkaOutput(_) => "kafka_output".to_string(),
             TransportConfig::UrlInput(_) => "url_input".to_string(),
             TransportConfig::S3Input(_) => "s3_input".to_string(),
+            TransportConfig::PipelineInput(_) => "pipeline_input".to_string(),
             TransportConfig::HttpInput => "http_input".to_string(),
             TransportConfig::HttpOutput => "http_output".to_string(),
         }
     }
+
+    /// Name of the service referenced by the transport configuration, if any.
+    pub fn service_name(&self) -> Option<&str> {
+        match self {
+            TransportConfig::KafkaInput(config) => config.kafka_service.as_deref(),
+            TransportConfig::KafkaOutput(config) => config.kafka_service.as_deref(),
+            TransportConfig::UrlInput(config) => config.http_service.as_deref(),
+            TransportConfig::S3Input(config) => config.s3_service.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Completes the transport configuration with the settings of the
+    /// service it references, and removes the reference.
+    ///
+    /// Settings specified by the transport configuration take precedence over
+    /// those of the service.  Fails if the service is not of the type the
+    /// transport expects.
+    pub fn resolve_service(&mut self, service: &ServiceConfig) -> Result<(), String> {
+        let transport_name = self.name();
+        let mismatch = || {
+            format!(
+                "transport '{transport_name}' cannot use a service of type '{}'",
+                service.config_type()
+            )
+        };
+        match (self, service) {
+            (TransportConfig::KafkaInput(config), ServiceConfig::Kafka(kafka)) => {
+                let options = kafka.generate_final_options().map_err(|e| e.to_string())?;
+                for (k, v) in options {
+                    config.kafka_options.entry(k).or_insert(v);
+                }
+                config.kafka_service = None;
+            }
+            (TransportConfig::KafkaOutput(config), ServiceConfig::Kafka(kafka)) => {
+                let options = kafka.generate_final_options().map_err(|e| e.to_string())?;
+                for (k, v) in options {
+                    config.kafka_options.entry(k).or_insert(v);
+                }
+                config.kafka_service = None;
+            }
+            (TransportConfig::UrlInput(config), ServiceConfig::Http(http)) => {
+                config.path = http.resolve_path(&config.path);
+                for (k, v) in &http.headers {
+                    config.headers.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+                config.http_service = None;
+            }
+            (TransportConfig::S3Input(config), ServiceConfig::S3(s3)) => {
+                if config.region.is_empty() {
+                    config.region = s3.region.clone();
+                }
+                if config.endpoint_url.is_none() {
+                    config.endpoint_url = s3.endpoint_url.clone();
+                }
+                if config.credentials.is_none() {
+                    config.credentials = s3.credentials.clone();
+                }
+                config.s3_service = None;
+            }
+            _ => return Err(mismatch()),
+        }
+        Ok(())
+    }
 }
 
 /// Data format specification used to parse raw data received from the
@@ -301,3 +385,222 @@ pub struct ResourceConfig {
     #[serde(default)]
     pub storage_mb_max: Option<u64>,
 }
+
+/// Default delay before restarting a failed pipeline.
+const fn default_restart_delay_secs() -> u64 {
+    5
+}
+
+/// Default upper bound of the delay before restarting a failed pipeline
+/// with exponential backoff.
+const fn default_max_restart_delay_secs() -> u64 {
+    300
+}
+
+/// Policy for restarting a pipeline that failed.
+///
+/// The pipeline manager restarts a failed pipeline with the configuration it
+/// was deployed with, keeping the storage directory of the failed run.  The
+/// storage is deleted when the pipeline is shut down.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// Leave a failed pipeline in the `Failed` state until the user shuts it
+    /// down.
+    #[default]
+    Never,
+    /// Restart a failed pipeline after a fixed delay, up to `max_retries`
+    /// consecutive times.
+    OnFailure {
+        /// Maximum number of consecutive restarts.
+        max_retries: u32,
+        /// Delay in seconds before restarting the pipeline.
+        #[serde(default = "default_restart_delay_secs")]
+        delay_secs: u64,
+    },
+    /// Restart a failed pipeline after a delay that starts at
+    /// `initial_delay_secs` and doubles with every consecutive restart, up to
+    /// `max_delay_secs`.
+    ExponentialBackoff {
+        /// Maximum number of consecutive restarts.  Unlimited if not set.
+        #[serde(default)]
+        max_retries: Option<u32>,
+        /// Delay in seconds before the first restart.
+        #[serde(default = "default_restart_delay_secs")]
+        initial_delay_secs: u64,
+        /// Maximum delay in seconds between restarts.
+        #[serde(default = "default_max_restart_delay_secs")]
+        max_delay_secs: u64,
+    },
+}
+
+impl RestartPolicy {
+    /// Returns how long to wait before restarting a pipeline that failed
+    /// after `restarts` consecutive restarts, or `None` if the pipeline must
+    /// not be restarted.
+    pub fn restart_delay(&self, restarts: u32) -> Option<Duration> {
+        match self {
+            RestartPolicy::Never => None,
+            RestartPolicy::OnFailure {
+                max_retries,
+                delay_secs,
+            } => (restarts < *max_retries).then(|| Duration::from_secs(*delay_secs)),
+            RestartPolicy::ExponentialBackoff {
+                max_retries,
+                initial_delay_secs,
+                max_delay_secs,
+            } => {
+                if max_retries.is_some_and(|max_retries| restarts >= max_retries) {
+                    return None;
+                }
+                let delay = initial_delay_secs
+                    .saturating_mul(2u64.saturating_pow(restarts))
+                    .min(*max_delay_secs);
+                Some(Duration::from_secs(delay))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RestartPolicy, RuntimeConfig, TransportConfig};
+    use crate::service::{HttpService, KafkaService, S3Service, ServiceConfig};
+    use crate::transport::s3::AwsCredentials;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn transport(yaml: &str) -> TransportConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn resolve_kafka_service() {
+        let mut config = transport(
+            r#"
+name: kafka_input
+config:
+    topics: [t]
+    security.protocol: SSL
+    kafka_service: my-kafka"#,
+        );
+        assert_eq!(config.service_name(), Some("my-kafka"));
+        let service = ServiceConfig::Kafka(KafkaService {
+            bootstrap_servers: vec!["a:9092".to_string(), "b:9092".to_string()],
+            options: BTreeMap::from([
+                ("security.protocol".to_string(), "PLAINTEXT".to_string()),
+                ("sasl.username".to_string(), "user".to_string()),
+            ]),
+        });
+        config.resolve_service(&service).unwrap();
+        assert_eq!(config.service_name(), None);
+        let TransportConfig::KafkaInput(config) = config else {
+            unreachable!()
+        };
+        assert_eq!(
+            config.kafka_options,
+            BTreeMap::from([
+                ("bootstrap.servers".to_string(), "a:9092,b:9092".to_string()),
+                ("sasl.username".to_string(), "user".to_string()),
+                ("security.protocol".to_string(), "SSL".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn resolve_http_and_s3_services() {
+        let mut config = transport(
+            r#"
+name: url_input
+config:
+    path: /data.csv
+    http_service: my-http"#,
+        );
+        config
+            .resolve_service(&ServiceConfig::Http(HttpService {
+                url: "https://example.com/files/".to_string(),
+                headers: BTreeMap::from([("Authorization".to_string(), "token".to_string())]),
+            }))
+            .unwrap();
+        let TransportConfig::UrlInput(url) = &config else {
+            unreachable!()
+        };
+        assert_eq!(url.path, "https://example.com/files/data.csv");
+        assert_eq!(url.headers["Authorization"], "token");
+        assert_eq!(url.http_service, None);
+
+        let mut config = transport(
+            r#"
+name: s3_input
+config:
+    bucket_name: b
+    read_strategy:
+        type: Prefix
+        prefix: ""
+    s3_service: my-s3"#,
+        );
+        let s3 = S3Service {
+            region: "us-west-1".to_string(),
+            endpoint_url: Some("http://localhost:9000".to_string()),
+            credentials: Some(AwsCredentials::AccessKey {
+                aws_access_key_id: "id".to_string(),
+                aws_secret_access_key: "secret".to_string(),
+            }),
+        };
+        assert_eq!(
+            config
+                .clone()
+                .resolve_service(&ServiceConfig::Http(HttpService {
+                    url: "https://example.com".to_string(),
+                    headers: BTreeMap::new(),
+                }))
+                .unwrap_err(),
+            "transport 's3_input' cannot use a service of type 'http'"
+        );
+        config
+            .resolve_service(&ServiceConfig::S3(s3.clone()))
+            .unwrap();
+        let TransportConfig::S3Input(config) = config else {
+            unreachable!()
+        };
+        assert_eq!(config.region, s3.region);
+        assert_eq!(config.endpoint_url, s3.endpoint_url);
+        assert_eq!(config.credentials, s3.credentials);
+        assert_eq!(config.s3_service, None);
+    }
+
+    #[test]
+    fn restart_policy() {
+        let config = RuntimeConfig::from_yaml("workers: 4");
+        assert_eq!(config.restart_policy, RestartPolicy::Never);
+        assert_eq!(config.restart_policy.restart_delay(0), None);
+
+        let config = RuntimeConfig::from_yaml(
+            r#"
+restart_policy:
+  type: on_failure
+  max_retries: 2"#,
+        );
+        let policy = config.restart_policy;
+        assert_eq!(policy.restart_delay(0), Some(Duration::from_secs(5)));
+        assert_eq!(policy.restart_delay(1), Some(Duration::from_secs(5)));
+        assert_eq!(policy.restart_delay(2), None);
+
+        let config = RuntimeConfig::from_yaml(
+            r#"
+restart_policy:
+  type: exponential_backoff
+  initial_delay_secs: 1
+  max_delay_secs: 10"#,
+        );
+        let policy = config.restart_policy;
+        let delays: Vec<_> = (0..6)
+            .map(|restarts| policy.restart_delay(restarts).unwrap().as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(
+            policy.restart_delay(u32::MAX),
+            Some(Duration::from_secs(10))
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+const fn default_buffer_size_records() -> usize {
+    10_000
+}
+
+/// Configuration for the Arrow IPC parser.
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ArrowIpcParserConfig {}
+
+/// Configuration for the Arrow IPC encoder.
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct ArrowIpcEncoderConfig {
+    /// Maximal number of records in a single record batch.
+    ///
+    /// The default is 10_000.
+    #[serde(default = "default_buffer_size_records")]
+    pub buffer_size_records: usize,
+}
+
+impl Default for ArrowIpcEncoderConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size_records: default_buffer_size_records(),
+        }
+    }
+}
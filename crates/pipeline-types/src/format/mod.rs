@@ -1,3 +1,4 @@
+pub mod arrow_ipc;
 pub mod csv;
 pub mod json;
 pub mod parquet;
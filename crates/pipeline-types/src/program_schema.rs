@@ -49,6 +49,10 @@ pub struct Relation {
     pub case_sensitive: bool,
     #[cfg_attr(feature = "testing", proptest(value = "Vec::new()"))]
     pub fields: Vec<Field>,
+    /// True if the pipeline maintains the contents of the relation, so that
+    /// clients can query snapshots of it.
+    #[serde(default)]
+    pub materialized: bool,
 }
 
 impl Relation {
@@ -57,6 +61,7 @@ impl Relation {
             name: name.to_string(),
             case_sensitive,
             fields,
+            materialized: false,
         }
     }
 
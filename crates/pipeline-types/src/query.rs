@@ -36,3 +36,22 @@ pub struct NeighborhoodQuery {
     pub before: u32,
     pub after: u32,
 }
+
+/// A request to output a snapshot of a table or view.
+///
+/// Selects records with keys in the `[start, end)` range that follow the
+/// `after` row, and returns up to `limit` of them.  Range bounds are
+/// specified as rows of the table or view; for tables with a primary key,
+/// only the primary key columns of the row are compared.
+/// All fields are optional.  An empty request outputs the entire contents
+/// of the table or view.
+///
+/// To read a large table in pages, set `limit` to the page size and `after`
+/// to the row with the largest key from the previous page.
+#[derive(Deserialize, ToSchema)]
+pub struct TableSnapshotQuery {
+    pub start: Option<utoipa::openapi::Object>,
+    pub end: Option<utoipa::openapi::Object>,
+    pub after: Option<utoipa::openapi::Object>,
+    pub limit: Option<u64>,
+}
@@ -2,6 +2,8 @@ use std::fmt;
 use std::fmt::Formatter;
 
 use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
 use utoipa::ToSchema;
 
 /// Enumeration which holds a string or a secret reference which is
@@ -60,9 +62,220 @@ impl MaybeSecretRef {
     }
 }
 
+/// Prefix of the names of the environment variables that
+/// `${secret:env:<variable>}` references can read.
+///
+/// Other variables of the pipeline process, which may configure the process
+/// itself, cannot be referenced.
+pub const ENV_SECRET_PREFIX: &str = "FELDERA_SECRET_";
+
+/// Prefix of the environment variables through which the pipeline manager
+/// passes the database secrets referenced by a pipeline to the pipeline.
+///
+/// Users cannot reference these variables directly.
+pub const DATABASE_SECRET_ENV_PREFIX: &str = "FELDERA_SECRET_DB_";
+
+/// Environment variable holding the path, within each Vault secrets engine,
+/// under which the pipeline may read secrets.
+pub const VAULT_PATH_PREFIX_ENV: &str = "FELDERA_VAULT_PATH_PREFIX";
+
+/// Returns the environment variable through which the pipeline manager
+/// passes database secret `name` to a pipeline.
+pub fn database_secret_env_var(name: &str) -> String {
+    format!(
+        "{DATABASE_SECRET_ENV_PREFIX}{}",
+        name.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Provider that resolves a secret reference to the secret string.
+///
+/// The provider is selected by a prefix of the secret identifier:
+///
+/// * `${secret:<name>}`: file `.<name>` in the secrets directory mounted into
+///   the pipeline.
+/// * `${secret:env:<variable>}`: environment variable of the pipeline, whose
+///   name must start with [`ENV_SECRET_PREFIX`].
+/// * `${secret:db:<name>}`: secret stored, encrypted, in the database of the
+///   pipeline manager.
+/// * `${secret:vault:<mount>/<path>#<key>}`: key of a secret stored in a
+///   HashiCorp Vault KV (version 2) secrets engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SecretProviderType {
+    Directory,
+    Env,
+    Database,
+    Vault,
+}
+
+impl SecretProviderType {
+    /// Splits a secret identifier (the part of the reference between
+    /// `${secret:` and `}`) into its provider and the identifier of the secret
+    /// within that provider.
+    ///
+    /// Identifiers without a provider prefix refer to the secrets directory,
+    /// whose secret names cannot contain a colon.
+    pub fn parse(secret_ref: &str) -> Result<(SecretProviderType, &str), String> {
+        match secret_ref.split_once(':') {
+            None => Ok((SecretProviderType::Directory, secret_ref)),
+            Some(("env", key)) => Ok((SecretProviderType::Env, key)),
+            Some(("db", key)) => Ok((SecretProviderType::Database, key)),
+            Some(("vault", key)) => Ok((SecretProviderType::Vault, key)),
+            Some((provider, _)) => Err(format!(
+                "unknown secret provider '{provider}' in secret reference '{secret_ref}'"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SecretProviderType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretProviderType::Directory => write!(f, "directory"),
+            SecretProviderType::Env => write!(f, "env"),
+            SecretProviderType::Database => write!(f, "db"),
+            SecretProviderType::Vault => write!(f, "vault"),
+        }
+    }
+}
+
+/// Location of a secret in HashiCorp Vault, parsed from a
+/// `<mount>/<path>#<key>` identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultSecretRef<'a> {
+    /// Mount path of the KV secrets engine (e.g., `secret`).
+    pub mount: &'a str,
+    /// Path of the secret within the engine (e.g., `kafka/prod`).
+    pub path: &'a str,
+    /// Key of the value within the secret (e.g., `password`).
+    pub key: &'a str,
+}
+
+impl<'a> VaultSecretRef<'a> {
+    pub fn parse(identifier: &'a str) -> Result<Self, String> {
+        let invalid = || {
+            format!("Vault secret reference '{identifier}' does not follow the format '<mount>/<path>#<key>'")
+        };
+        let (location, key) = identifier.split_once('#').ok_or_else(invalid)?;
+        let (mount, path) = location.split_once('/').ok_or_else(invalid)?;
+        if mount.is_empty() || path.is_empty() || key.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { mount, path, key })
+    }
+
+    /// Returns true if the secret is located under path `prefix` of its
+    /// secrets engine.
+    pub fn is_within(&self, prefix: &str) -> bool {
+        let prefix = prefix.trim_matches('/');
+        !self
+            .path
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == "..")
+            && self
+                .path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// Path of the Vault HTTP API that reads the secret, relative to the
+    /// address of the server.
+    pub fn api_path(&self) -> String {
+        format!("v1/{}/data/{}", self.mount, self.path)
+    }
+
+    /// Extracts the value of the key from the response of the Vault HTTP API.
+    pub fn value_from_response(&self, response: &JsonValue) -> Result<String, String> {
+        match response
+            .pointer("/data/data")
+            .and_then(|data| data.get(self.key))
+        {
+            Some(JsonValue::String(value)) => Ok(value.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(format!(
+                "Vault secret '{}/{}' has no key '{}'",
+                self.mount, self.path, self.key
+            )),
+        }
+    }
+}
+
+/// Returns the secret identifiers referenced by the string values in `value`,
+/// each paired with the path of the value (e.g., `kafka_options.sasl.password`
+/// or `topics[0]`).
+///
+/// Working on the JSON representation of a configuration lets secret
+/// references be used in any of its string fields.
+pub fn secret_refs_in_json(value: &JsonValue) -> Vec<(String, String)> {
+    fn collect(value: &JsonValue, path: String, refs: &mut Vec<(String, String)>) {
+        match value {
+            JsonValue::String(s) => {
+                if let MaybeSecretRef::SecretRef(secret_ref) =
+                    MaybeSecretRef::new_using_pattern_match(s.clone())
+                {
+                    refs.push((path, secret_ref));
+                }
+            }
+            JsonValue::Array(values) => {
+                for (i, v) in values.iter().enumerate() {
+                    collect(v, format!("{path}[{i}]"), refs);
+                }
+            }
+            JsonValue::Object(fields) => {
+                for (k, v) in fields.iter() {
+                    let path = if path.is_empty() {
+                        k.clone()
+                    } else {
+                        format!("{path}.{k}")
+                    };
+                    collect(v, path, refs);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut refs = Vec::new();
+    collect(value, String::new(), &mut refs);
+    refs
+}
+
+/// Replaces every string value in `value` that references a secret in
+/// `secrets`, which maps secret identifiers to secret strings.
+///
+/// References to other secrets are left unchanged.
+pub fn replace_secret_refs_in_json(value: &mut JsonValue, secrets: &BTreeMap<String, String>) {
+    match value {
+        JsonValue::String(s) => {
+            if let MaybeSecretRef::SecretRef(secret_ref) =
+                MaybeSecretRef::new_using_pattern_match(s.clone())
+            {
+                if let Some(secret) = secrets.get(&secret_ref) {
+                    *s = secret.clone();
+                }
+            }
+        }
+        JsonValue::Array(values) => {
+            for v in values.iter_mut() {
+                replace_secret_refs_in_json(v, secrets);
+            }
+        }
+        JsonValue::Object(fields) => {
+            for v in fields.values_mut() {
+                replace_secret_refs_in_json(v, secrets);
+            }
+        }
+        _ => {}
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::MaybeSecretRef;
+    use super::{
+        replace_secret_refs_in_json, secret_refs_in_json, MaybeSecretRef, SecretProviderType,
+        VaultSecretRef,
+    };
+    use serde_json::json;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_format() {
@@ -175,4 +388,99 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_provider() {
+        assert_eq!(
+            SecretProviderType::parse("db-password"),
+            Ok((SecretProviderType::Directory, "db-password"))
+        );
+        assert_eq!(
+            SecretProviderType::parse("env:KAFKA_PASSWORD"),
+            Ok((SecretProviderType::Env, "KAFKA_PASSWORD"))
+        );
+        assert_eq!(
+            SecretProviderType::parse("db:kafka-password"),
+            Ok((SecretProviderType::Database, "kafka-password"))
+        );
+        assert_eq!(
+            SecretProviderType::parse("vault:secret/kafka#password"),
+            Ok((SecretProviderType::Vault, "secret/kafka#password"))
+        );
+        assert_eq!(
+            SecretProviderType::parse("aws:x"),
+            Err("unknown secret provider 'aws' in secret reference 'aws:x'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_vault() {
+        let vault_ref = VaultSecretRef::parse("secret/kafka/prod#password").unwrap();
+        assert_eq!(
+            vault_ref,
+            VaultSecretRef {
+                mount: "secret",
+                path: "kafka/prod",
+                key: "password"
+            }
+        );
+        assert_eq!(vault_ref.api_path(), "v1/secret/data/kafka/prod");
+        assert!(vault_ref.is_within("kafka"));
+        assert!(vault_ref.is_within("/kafka/"));
+        assert!(!vault_ref.is_within("kafka/prod"));
+        assert!(!vault_ref.is_within("kaf"));
+        assert!(!VaultSecretRef::parse("secret/kafka/../other/x#password")
+            .unwrap()
+            .is_within("kafka"));
+        let response = json!({"data": {"data": {"password": "hunter2", "port": 9092}}});
+        assert_eq!(vault_ref.value_from_response(&response).unwrap(), "hunter2");
+        let port_ref = VaultSecretRef::parse("secret/kafka/prod#port").unwrap();
+        assert_eq!(port_ref.value_from_response(&response).unwrap(), "9092");
+        assert!(VaultSecretRef::parse("secret/kafka/prod#user")
+            .unwrap()
+            .value_from_response(&response)
+            .is_err());
+        for invalid in [
+            "secret#password",
+            "secret/kafka",
+            "/kafka#password",
+            "secret/kafka#",
+        ] {
+            assert!(VaultSecretRef::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let mut value = json!({
+            "topics": ["${secret:env:TOPIC}", "plain"],
+            "kafka_options": {"sasl.password": "${secret:db:password}"},
+            "count": 3,
+        });
+        // Sorted, as the order of object fields is not guaranteed.
+        let mut refs = secret_refs_in_json(&value);
+        refs.sort();
+        assert_eq!(
+            refs,
+            vec![
+                (
+                    "kafka_options.sasl.password".to_string(),
+                    "db:password".to_string()
+                ),
+                ("topics[0]".to_string(), "env:TOPIC".to_string()),
+            ]
+        );
+        replace_secret_refs_in_json(
+            &mut value,
+            &BTreeMap::from([("db:password".to_string(), "hunter2".to_string())]),
+        );
+        assert_eq!(
+            value,
+            json!({
+                "topics": ["${secret:env:TOPIC}", "plain"],
+                "kafka_options": {"sasl.password": "hunter2"},
+                "count": 3,
+            })
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::service::KafkaService;
+use crate::service::{HttpService, KafkaService, PostgresService, S3Service};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -12,6 +12,9 @@ use utoipa::ToSchema;
 #[serde(rename_all = "snake_case")]
 pub enum ServiceConfig {
     Kafka(KafkaService),
+    Postgres(PostgresService),
+    S3(S3Service),
+    Http(HttpService),
 }
 
 impl ServiceConfig {
@@ -19,6 +22,9 @@ impl ServiceConfig {
     pub fn config_type(&self) -> String {
         match self {
             ServiceConfig::Kafka(_) => "kafka".to_string(),
+            ServiceConfig::Postgres(_) => "postgres".to_string(),
+            ServiceConfig::S3(_) => "s3".to_string(),
+            ServiceConfig::Http(_) => "http".to_string(),
         }
     }
 
@@ -36,7 +42,8 @@ impl ServiceConfig {
 #[cfg(test)]
 mod tests {
     use super::ServiceConfig;
-    use crate::service::KafkaService;
+    use crate::service::{HttpService, KafkaService, PostgresService, S3Service};
+    use crate::transport::s3::AwsCredentials;
     use std::collections::BTreeMap;
 
     #[test]
@@ -51,4 +58,61 @@ mod tests {
             ServiceConfig::from_yaml_str(&service_config.to_yaml())
         );
     }
+
+    #[test]
+    fn test_other_services_de_serialization() {
+        for (service_config, config_type) in [
+            (
+                ServiceConfig::Postgres(PostgresService {
+                    host: "localhost".to_string(),
+                    port: 5432,
+                    user: "postgres".to_string(),
+                    password: Some("${secret:pg-password}".to_string()),
+                    dbname: "postgres".to_string(),
+                }),
+                "postgres",
+            ),
+            (
+                ServiceConfig::S3(S3Service {
+                    region: "us-east-1".to_string(),
+                    endpoint_url: Some("http://localhost:9000".to_string()),
+                    credentials: Some(AwsCredentials::AccessKey {
+                        aws_access_key_id: "key".to_string(),
+                        aws_secret_access_key: "secret".to_string(),
+                    }),
+                }),
+                "s3",
+            ),
+            (
+                ServiceConfig::Http(HttpService {
+                    url: "https://example.com".to_string(),
+                    headers: BTreeMap::from([(
+                        "Authorization".to_string(),
+                        "Bearer token".to_string(),
+                    )]),
+                }),
+                "http",
+            ),
+        ] {
+            assert_eq!(service_config.config_type(), config_type);
+            assert_eq!(
+                service_config,
+                ServiceConfig::from_yaml_str(&service_config.to_yaml())
+            );
+        }
+
+        // Optional fields take their default values.
+        assert_eq!(
+            ServiceConfig::from_yaml_str(
+                "!postgres\nhost: localhost\nuser: postgres\ndbname: db\n"
+            ),
+            ServiceConfig::Postgres(PostgresService {
+                host: "localhost".to_string(),
+                port: 5432,
+                user: "postgres".to_string(),
+                password: None,
+                dbname: "db".to_string(),
+            })
+        );
+    }
 }
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Configuration for accessing an HTTP or HTTPS endpoint.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct HttpService {
+    /// Base URL of the endpoint (e.g., "https://example.com/data").
+    pub url: String,
+
+    /// Headers sent with every request, typically used for authentication
+    /// (e.g., an "Authorization" header).
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl HttpService {
+    /// Resolves `path` relative to the base URL of the service.
+    ///
+    /// An empty path refers to the base URL itself; otherwise the path is
+    /// appended to the base URL, separated by a single slash.
+    pub fn resolve_path(&self, path: &str) -> String {
+        if path.is_empty() {
+            self.url.clone()
+        } else {
+            format!(
+                "{}/{}",
+                self.url.trim_end_matches('/'),
+                path.trim_start_matches('/')
+            )
+        }
+    }
+}
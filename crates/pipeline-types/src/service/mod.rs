@@ -1,6 +1,12 @@
 mod config;
+mod http;
 mod kafka;
+mod postgres;
+mod s3;
 
 // Exported
 pub use config::ServiceConfig;
+pub use http::HttpService;
 pub use kafka::{KafkaService, KafkaServiceError};
+pub use postgres::PostgresService;
+pub use s3::S3Service;
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Configuration for accessing a PostgreSQL database.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PostgresService {
+    /// Hostname of the database server.
+    pub host: String,
+
+    /// Port of the database server, defaults to 5432.
+    #[serde(default = "default_postgres_port")]
+    pub port: u16,
+
+    /// Name of the user to authenticate as.
+    pub user: String,
+
+    /// Password of the user, if the server requires password authentication.
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Name of the database to connect to.
+    pub dbname: String,
+}
+
+const fn default_postgres_port() -> u16 {
+    5432
+}
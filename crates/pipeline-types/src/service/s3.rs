@@ -0,0 +1,21 @@
+use crate::transport::s3::AwsCredentials;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Configuration for accessing AWS S3 or an S3-compatible object store.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct S3Service {
+    /// AWS region.
+    pub region: String,
+
+    /// Endpoint of an S3-compatible object store (e.g.,
+    /// "http://localhost:9000"). Requests are sent to the regional AWS
+    /// endpoint if not specified.
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+
+    /// Credentials to authenticate against the object store. Requests are
+    /// not signed if not specified.
+    #[serde(default)]
+    pub credentials: Option<AwsCredentials>,
+}
@@ -44,6 +44,14 @@ pub enum EgressMode {
     /// and `OutputQuery::Neighborhood` queries.
     #[serde(rename = "snapshot")]
     Snapshot,
+    /// Output a snapshot of query results followed by a stream of deltas
+    /// relative to the snapshot.
+    ///
+    /// For `OutputQuery::Table` queries, this requires the program to be
+    /// compiled with `materialized` enabled.  Not supported for
+    /// `OutputQuery::Quantiles` queries.
+    #[serde(rename = "snapshot_and_watch")]
+    SnapshotAndWatch,
 }
 
 impl Default for EgressMode {
@@ -53,5 +61,29 @@ impl Default for EgressMode {
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum IngressWait {
+    /// Respond as soon as the data has been received and parsed.
+    ///
+    /// Records may still be buffered by the pipeline at this point.
+    #[serde(rename = "parsed")]
+    Parsed,
+    /// Respond once the circuit has finished processing all records in the
+    /// request.
+    ///
+    /// The response body contains the number of the step at which the data
+    /// was processed: `{"step": <step>}`.  Outputs produced from the data
+    /// are enqueued to output endpoints by the time the response is sent.
+    #[serde(rename = "processed")]
+    Processed,
+}
+
+impl Default for IngressWait {
+    /// If `wait` is not specified, default to `Parsed`.
+    fn default() -> Self {
+        Self::Parsed
+    }
+}
+
 // This file indicates the port used by the server
 pub const SERVER_PORT_FILE: &str = "port";
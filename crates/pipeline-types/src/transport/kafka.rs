@@ -39,6 +39,12 @@ pub struct KafkaInputConfig {
 
     /// If specified, this enables fault tolerance in the Kafka input connector.
     pub fault_tolerance: Option<KafkaInputFtConfig>,
+
+    /// Name of a Kafka service whose bootstrap servers and options are used
+    /// by this connector.  Options in `kafka_options` take precedence over
+    /// the options of the service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kafka_service: Option<String>,
 }
 
 /// Fault tolerance configuration for Kafka input connector.
@@ -229,6 +235,12 @@ pub struct KafkaOutputConfig {
     /// If specified, this enables fault tolerance in the Kafka output
     /// connector.
     pub fault_tolerance: Option<KafkaOutputFtConfig>,
+
+    /// Name of a Kafka service whose bootstrap servers and options are used
+    /// by this connector.  Options in `kafka_options` take precedence over
+    /// the options of the service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kafka_service: Option<String>,
 }
 
 /// Fault tolerance configuration for Kafka output connector.
@@ -1,5 +1,6 @@
 pub mod file;
 pub mod http;
 pub mod kafka;
+pub mod pipeline;
 pub mod s3;
 pub mod url;
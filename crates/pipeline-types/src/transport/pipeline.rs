@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Default value of `PipelineInputConfig::reconnect_delay_ms`.
+const fn default_reconnect_delay_ms() -> u64 {
+    1_000
+}
+
+/// Configuration for reading the changes to a view of another pipeline with
+/// `PipelineInputTransport`.
+///
+/// The connector subscribes to the `/egress/{view}` endpoint of the other
+/// pipeline through the API of the pipeline manager that manages both
+/// pipelines, so it follows the other pipeline when it is restarted.  The
+/// format of the connector must be `json` with `update_format:
+/// insert_delete` and `array: true`, which is the format of the changes
+/// produced by the endpoint.
+///
+/// The program of the other pipeline must be materialized: the connector
+/// reads a snapshot of the view whenever it connects, and retracts the
+/// records it received over the previous connection.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct PipelineInputConfig {
+    /// Name of the pipeline to read from.  The pipeline must belong to the
+    /// same tenant as the pipeline the connector is attached to.
+    pub pipeline: String,
+
+    /// Name of the view (or table) of that pipeline to read.
+    pub view: String,
+
+    /// Delay in milliseconds before reconnecting to the pipeline after the
+    /// connection is lost, e.g., because the pipeline is restarted.  Defaults
+    /// to 1000 ms.
+    #[serde(default = "default_reconnect_delay_ms")]
+    pub reconnect_delay_ms: u64,
+
+    /// Base URL of the pipeline manager API.  Filled in by the pipeline
+    /// manager when it starts the pipeline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+
+    /// API key used to read from the pipeline.  Issued by the pipeline
+    /// manager when it starts the pipeline and revoked when the pipeline
+    /// shuts down.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+impl PipelineInputConfig {
+    /// Path of the pipeline manager API endpoint that streams the changes to
+    /// the view.
+    ///
+    /// The endpoint sends a snapshot of the view followed by the changes to
+    /// the view, and applies backpressure to the other pipeline instead of
+    /// dropping changes when the connector falls behind.
+    pub fn egress_path(&self) -> String {
+        format!(
+            "/v0/pipelines/{}/egress/{}?format=json&mode=snapshot_and_watch&backpressure=true",
+            self.pipeline, self.view
+        )
+    }
+}
@@ -4,10 +4,23 @@ use utoipa::ToSchema;
 /// Configuration for reading data from AWS S3.
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct S3InputConfig {
-    /// Credentials to authenticate against AWS
-    pub credentials: AwsCredentials,
-    /// AWS region
+    /// Credentials to authenticate against AWS.  Requests are not signed if
+    /// not specified, unless the credentials come from `s3_service`.
+    #[serde(default)]
+    pub credentials: Option<AwsCredentials>,
+    /// AWS region.  Must be specified unless it comes from `s3_service`.
+    #[serde(default)]
     pub region: String,
+    /// Endpoint of an S3-compatible object store (e.g.,
+    /// "http://localhost:9000").  Requests are sent to the regional AWS
+    /// endpoint if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint_url: Option<String>,
+    /// Name of an S3 service providing the region, endpoint and credentials
+    /// of this connector.  Values specified in the connector take precedence
+    /// over those of the service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3_service: Option<String>,
     /// S3 bucket name to access
     pub bucket_name: String,
     /// Strategy that determines which objects to
@@ -1,10 +1,22 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use utoipa::ToSchema;
 
 /// Configuration for reading data from an HTTP or HTTPS URL with
 /// `UrlInputTransport`.
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct UrlInputConfig {
-    /// URL.
+    /// URL.  If `http_service` is specified, the path is relative to the
+    /// URL of the service.
     pub path: String,
+
+    /// Headers sent with the request, e.g., for authentication.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// Name of an HTTP service providing the base URL and headers of this
+    /// connector.  Headers specified in the connector take precedence over
+    /// those of the service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_service: Option<String>,
 }
@@ -0,0 +1,4 @@
+-- Fine-grained API key scopes and expiration.
+ALTER TABLE api_key
+ADD COLUMN resource_scopes varchar NOT NULL DEFAULT '[]', -- Vec<ApiKeyScope> (JSON serialized, resources identified by id)
+ADD COLUMN expires_at bigint;                             -- Timestamp after which the key is rejected (NULL: never)
@@ -0,0 +1,23 @@
+-- Roles of users within a tenant.
+CREATE TABLE IF NOT EXISTS user_role (
+    tenant_id uuid NOT NULL,        -- Tenant the user belongs to
+    user_name varchar NOT NULL,     -- Subject of the user's token
+    role varchar NOT NULL,          -- Role: viewer, operator, admin
+    PRIMARY KEY (tenant_id, user_name),
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE
+);
+
+-- Record of mutating API requests.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id uuid PRIMARY KEY,            -- Unique identifier
+    tenant_id uuid NOT NULL,        -- Tenant the request was issued for
+    timestamp bigint NOT NULL,      -- Timestamp when the request completed
+    actor varchar NOT NULL,         -- User or API key that issued the request
+    action varchar NOT NULL,        -- Action performed, e.g., pipeline.start
+    target varchar,                 -- Name of the targeted resource (NULL if none)
+    outcome varchar NOT NULL,       -- AuditOutcome: success, failure, denied
+    status_code integer NOT NULL,   -- HTTP status code of the response
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_tenant_id_timestamp ON audit_log (tenant_id, timestamp);
@@ -0,0 +1,17 @@
+-- History of the code and configuration of every program version.
+CREATE TABLE IF NOT EXISTS program_version (
+    program_id uuid NOT NULL,           -- Program the version belongs to
+    tenant_id uuid NOT NULL,            -- Tenant the program belongs to
+    version bigint NOT NULL,            -- Program version
+    code varchar NOT NULL,              -- SQL code of the version
+    compilation_profile varchar NOT NULL, -- Compilation profile of the version
+    schema varchar,                     -- ProgramSchema (JSON serialized), NULL until the SQL compiled
+    created_at bigint NOT NULL,         -- Timestamp when the version was created
+    PRIMARY KEY (program_id, version),
+    FOREIGN KEY (program_id, tenant_id) REFERENCES program(id, tenant_id) ON DELETE CASCADE
+);
+
+-- Only the latest version of pre-existing programs is known.
+INSERT INTO program_version (program_id, tenant_id, version, code, compilation_profile, schema, created_at)
+SELECT id, tenant_id, version, code, compilation_profile, schema, extract(epoch from status_since)::bigint
+FROM program;
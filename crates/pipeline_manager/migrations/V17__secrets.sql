@@ -0,0 +1,11 @@
+-- Secrets stored by tenants, encrypted with the master key of the pipeline manager.
+CREATE TABLE IF NOT EXISTS secret (
+    tenant_id uuid NOT NULL,        -- Tenant the secret belongs to
+    name varchar NOT NULL,          -- Name used to refer to the secret (${secret:db:<name>})
+    nonce bytea NOT NULL,           -- Nonce used to encrypt the value
+    ciphertext bytea NOT NULL,      -- Value encrypted with AES-256-GCM, followed by the authentication tag
+    created_at bigint NOT NULL,     -- Timestamp when the secret was created
+    updated_at bigint NOT NULL,     -- Timestamp when the value was last replaced
+    PRIMARY KEY (tenant_id, name),
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Automatic restarts of failed pipelines.
+ALTER TABLE pipeline_runtime_state
+ADD COLUMN restart_count bigint NOT NULL DEFAULT 0, -- Consecutive automatic restarts
+ADD COLUMN last_failure varchar;                    -- Error that caused the last restart (JSON serialized)
@@ -0,0 +1,24 @@
+-- User or API key that last changed the desired status of the pipeline.
+ALTER TABLE pipeline_runtime_state
+ADD COLUMN desired_status_actor varchar;
+
+-- History of the status transitions of pipelines.
+CREATE TABLE IF NOT EXISTS pipeline_event (
+    id uuid PRIMARY KEY,            -- Unique identifier
+    tenant_id uuid NOT NULL,        -- Tenant the pipeline belongs to
+    pipeline_id uuid NOT NULL,      -- Pipeline that changed status
+    timestamp bigint NOT NULL,      -- Timestamp of the transition
+    from_status varchar NOT NULL,   -- Status before the transition
+    to_status varchar NOT NULL,     -- Status after the transition
+    error varchar,                  -- Error that caused the transition (JSON serialized)
+    actor varchar NOT NULL,         -- User, API key or `system` that caused the transition
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE,
+    FOREIGN KEY (pipeline_id) REFERENCES pipeline(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS pipeline_event_pipeline_id_timestamp ON pipeline_event (pipeline_id, timestamp);
+
+-- Notify listeners of new events, e.g., to stream them to clients.
+CREATE TRIGGER pipeline_event_notify
+AFTER INSERT ON pipeline_event
+FOR EACH ROW EXECUTE PROCEDURE notification();
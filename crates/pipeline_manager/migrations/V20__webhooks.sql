@@ -0,0 +1,34 @@
+-- Endpoints notified of program and pipeline events.
+CREATE TABLE IF NOT EXISTS webhook (
+    id uuid PRIMARY KEY,            -- Unique identifier
+    tenant_id uuid NOT NULL,        -- Tenant the webhook belongs to
+    name varchar NOT NULL,          -- Name of the webhook, unique per tenant
+    url varchar NOT NULL,           -- Endpoint the events are posted to
+    secret_nonce bytea NOT NULL,    -- Nonce of the encrypted signing secret
+    secret_ciphertext bytea NOT NULL, -- Key of the HMAC-SHA256 signature of the deliveries, encrypted with the secrets master key
+    event_types varchar NOT NULL,   -- Event types the webhook subscribes to (JSON array)
+    created_at bigint NOT NULL,     -- Timestamp when the webhook was created
+    UNIQUE (tenant_id, name),
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE
+);
+
+-- Deliveries of events to webhooks and their outcome.
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+    id uuid PRIMARY KEY,            -- Unique identifier, sent with the delivery
+    tenant_id uuid NOT NULL,        -- Tenant the webhook belongs to
+    webhook_id uuid NOT NULL,       -- Webhook the event is delivered to
+    event_type varchar NOT NULL,    -- Type of the event
+    payload varchar NOT NULL,       -- Body of the request (JSON)
+    status varchar NOT NULL,        -- pending, delivered or failed
+    attempts bigint NOT NULL,       -- Number of delivery attempts so far
+    created_at bigint NOT NULL,     -- Timestamp when the event occurred
+    last_attempt_at bigint,         -- Timestamp of the last delivery attempt
+    next_attempt_at bigint,         -- Timestamp of the next attempt of pending deliveries
+    response_status integer,        -- HTTP status code returned by the last attempt
+    last_error varchar,             -- Error of the last failed attempt
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE,
+    FOREIGN KEY (webhook_id) REFERENCES webhook(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS webhook_delivery_webhook_id_created_at ON webhook_delivery (webhook_id, created_at);
+CREATE INDEX IF NOT EXISTS webhook_delivery_status_next_attempt_at ON webhook_delivery (status, next_attempt_at);
@@ -0,0 +1,3 @@
+-- In-place upgrades of running pipelines.
+ALTER TABLE pipeline_runtime_state
+ADD COLUMN upgrade_revision varchar; -- Revision (JSON serialized) that must replace the running instance, if any
@@ -0,0 +1,3 @@
+-- Warnings reported by the SQL compiler for the current version of a program.
+ALTER TABLE program
+ADD COLUMN warnings VARCHAR; -- JSON array of compiler diagnostics, NULL if there are none
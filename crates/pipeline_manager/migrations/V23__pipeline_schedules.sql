@@ -0,0 +1,20 @@
+-- Actions applied to pipelines at the times of a cron-style schedule.
+CREATE TABLE IF NOT EXISTS pipeline_schedule (
+    id uuid PRIMARY KEY,            -- Unique identifier
+    tenant_id uuid NOT NULL,        -- Tenant the pipeline belongs to
+    pipeline_id uuid NOT NULL,      -- Pipeline the action is applied to
+    name varchar NOT NULL,          -- Name of the schedule, unique per pipeline
+    action varchar NOT NULL,        -- start, pause or shutdown
+    cron varchar NOT NULL,          -- Cron expression of the times of the schedule
+    time_zone varchar NOT NULL,     -- IANA time zone the cron expression is evaluated in
+    enabled boolean NOT NULL,       -- Disabled schedules are not applied
+    created_at bigint NOT NULL,     -- Timestamp when the schedule was created
+    next_run_at bigint,             -- Timestamp when the action is applied next, if enabled
+    last_run_at bigint,             -- Timestamp when the action was last applied
+    last_error varchar,             -- Error of the last run, if it failed
+    UNIQUE (pipeline_id, name),
+    FOREIGN KEY (tenant_id) REFERENCES tenant(id) ON DELETE CASCADE,
+    FOREIGN KEY (pipeline_id) REFERENCES pipeline(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS pipeline_schedule_enabled_next_run_at ON pipeline_schedule (enabled, next_run_at);
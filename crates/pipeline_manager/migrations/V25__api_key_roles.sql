@@ -0,0 +1,3 @@
+-- Roles of API keys.  Existing keys keep the access they were issued with.
+ALTER TABLE api_key
+ADD COLUMN role varchar NOT NULL DEFAULT 'admin';
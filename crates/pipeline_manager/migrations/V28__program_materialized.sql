@@ -0,0 +1,6 @@
+-- Whether the tables and views of a program are materialized is part of its
+-- configuration, like the compilation profile
+ALTER TABLE program
+ADD COLUMN materialized boolean NOT NULL DEFAULT false;
+ALTER TABLE program_version
+ADD COLUMN materialized boolean NOT NULL DEFAULT false;
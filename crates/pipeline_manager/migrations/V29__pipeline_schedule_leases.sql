@@ -0,0 +1,4 @@
+-- Schedules that are due are leased by the scheduler that applies them, so
+-- that concurrent schedulers do not apply them as well
+ALTER TABLE pipeline_schedule
+ADD COLUMN leased_until bigint;
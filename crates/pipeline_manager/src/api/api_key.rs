@@ -3,7 +3,8 @@ use super::{ManagerError, ServerState};
 use crate::{
     api::{examples, parse_string_param},
     auth::TenantId,
-    db::{storage::Storage, ApiKeyId},
+    db::{audit::Role, storage::Storage, ApiKeyId, ApiKeyScope, ApiPermission, ApiResource},
+    pipeline_graph::PIPELINE_INPUT_KEY_PREFIX,
 };
 use actix_web::{
     delete, get,
@@ -12,6 +13,7 @@ use actix_web::{
     web::{self, Data as WebData, ReqData},
     HttpRequest, HttpResponse,
 };
+use chrono::{DateTime, Utc};
 use log::info;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
@@ -23,6 +25,28 @@ pub(crate) struct NewApiKeyRequest {
     /// Key name.
     #[schema(example = "my-api-key")]
     name: String,
+
+    /// Tenant-wide permissions of the key.  Defaults to both `Read` and
+    /// `Write`.
+    #[serde(default)]
+    scopes: Option<Vec<ApiPermission>>,
+
+    /// Restrict the key to specific actions on specific pipelines and
+    /// programs, identified by their ids.  When empty (the default), the key
+    /// is not restricted to specific resources.
+    #[serde(default)]
+    resource_scopes: Vec<ApiKeyScope>,
+
+    /// Time after which the key is no longer accepted.  The key never
+    /// expires if not specified.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+
+    /// Role of the key within the tenant, which limits the requests it can
+    /// issue the same way as for users.  Defaults to the role of users who
+    /// have not been assigned one (`--default-role`).
+    #[serde(default)]
+    role: Option<Role>,
 }
 
 /// Response to a successful API key creation.
@@ -162,6 +186,14 @@ pub(crate) async fn delete_api_key(
 #[utoipa::path(
     responses(
         (status = OK, description = "API key created successfully.", body = NewApiKeyResponse),
+        (status = BAD_REQUEST
+            , description = "The name is reserved for the API keys issued to pipelines."
+            , body = ErrorResponse
+            , example = json!(examples::reserved_api_key_name())),
+        (status = NOT_FOUND
+            , description = "A resource scope refers to a pipeline or program that does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline())),
         (status = CONFLICT
             , description = "An api key with this name already exists."
             , body = ErrorResponse
@@ -177,21 +209,43 @@ async fn create_api_key(
     tenant_id: ReqData<TenantId>,
     req: web::Json<NewApiKeyRequest>,
 ) -> Result<HttpResponse, ManagerError> {
+    // Keys issued to pipelines get privileges that users' keys don't, see
+    // `pipeline_graph`.
+    if req.name.starts_with(PIPELINE_INPUT_KEY_PREFIX) {
+        return Err(ManagerError::InvalidNameParam {
+            value: req.name.clone(),
+            error: format!("names starting with '{PIPELINE_INPUT_KEY_PREFIX}' are reserved"),
+        });
+    }
     let api_key = crate::auth::generate_api_key();
     let id = Uuid::now_v7();
-    let res = state
-        .db
-        .lock()
-        .await
+    let db = state.db.lock().await;
+
+    // Scopes can only refer to resources of the tenant.
+    for scope in &req.resource_scopes {
+        match scope.resource {
+            ApiResource::Pipeline(pipeline_id) => {
+                db.get_pipeline_descr_by_id(*tenant_id, pipeline_id, None)
+                    .await?;
+            }
+            ApiResource::Program(program_id) => {
+                db.get_program_by_id(*tenant_id, program_id, false).await?;
+            }
+        }
+    }
+
+    let res = db
         .store_api_key_hash(
             *tenant_id,
             id,
             &req.name,
             &api_key,
-            vec![
-                crate::db::ApiPermission::Read,
-                crate::db::ApiPermission::Write,
-            ],
+            req.scopes
+                .clone()
+                .unwrap_or_else(|| vec![ApiPermission::Read, ApiPermission::Write]),
+            req.resource_scopes.clone(),
+            req.expires_at,
+            req.role.unwrap_or(state._config.default_role),
         )
         .await
         .map(|_| {
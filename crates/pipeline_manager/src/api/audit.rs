@@ -0,0 +1,338 @@
+/// Audit log of mutating API requests.
+use super::{ManagerError, ServerState};
+use crate::{
+    auth::{Actor, TenantId},
+    db::{
+        audit::{AuditEvent, AuditEventFilter, AuditEventId, AuditOutcome},
+        storage::Storage,
+        ProjectDB,
+    },
+};
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse, Url},
+    get,
+    http::{
+        header::{CacheControl, CacheDirective},
+        Method, StatusCode,
+    },
+    web::{self, Data as WebData, ReqData},
+    Error, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Deserialize;
+use std::{future::Future, sync::Arc};
+use tokio::sync::{mpsc, Mutex};
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+/// Maximal number of audit events queued for [`write_events`] before requests
+/// wait for it to catch up.
+const AUDIT_QUEUE_SIZE: usize = 1024;
+
+/// Queues audit events to be written to the database by [`write_events`].
+pub(crate) type AuditEventSender = mpsc::Sender<(TenantId, AuditEvent)>;
+
+/// Creates the queue of audit events and spawns the task that writes them
+/// to the database.
+pub(crate) fn spawn_writer(db: Arc<Mutex<ProjectDB>>) -> AuditEventSender {
+    let (sender, receiver) = mpsc::channel(AUDIT_QUEUE_SIZE);
+    tokio::spawn(write_events(db, receiver));
+    sender
+}
+
+/// Writes queued audit events to the database.
+///
+/// Requests only queue their events, so that they don't contend for the
+/// database lock.  The writer takes the lock once for all events queued at
+/// the time.
+async fn write_events(
+    db: Arc<Mutex<ProjectDB>>,
+    mut receiver: mpsc::Receiver<(TenantId, AuditEvent)>,
+) {
+    while let Some(first) = receiver.recv().await {
+        let mut events = vec![first];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        let db = db.lock().await;
+        for (tenant_id, event) in events {
+            if let Err(e) = db.new_audit_event(tenant_id, &event).await {
+                error!("Could not record audit event {event:?} (tenant:{tenant_id}): {e}");
+            }
+        }
+    }
+}
+
+/// Filters applied when listing audit events.
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct AuditEventQuery {
+    /// Only events issued by this actor, e.g., `user:alice` or
+    /// `api_key:my-api-key`.
+    actor: Option<String>,
+    /// Only events with this action, e.g., `pipeline.start`.
+    action: Option<String>,
+    /// Only events targeting the resource with this name.
+    target: Option<String>,
+    /// Only events with this outcome.
+    outcome: Option<AuditOutcome>,
+    /// Only events that occurred at or after this time.
+    since: Option<DateTime<Utc>>,
+    /// Only events that occurred before this time.
+    until: Option<DateTime<Utc>>,
+    /// Maximum number of events to return.  The most recent events are
+    /// returned first.
+    limit: Option<u32>,
+}
+
+/// List audit events
+///
+/// Returns the mutating API requests issued by users and API keys of the
+/// tenant, most recent first.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Audit events retrieved successfully", body = [AuditEvent]),
+    ),
+    params(AuditEventQuery),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Audit"
+)]
+#[get("/audit")]
+pub(crate) async fn list_audit_events(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    query: web::Query<AuditEventQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let query = query.into_inner();
+    let filter = AuditEventFilter {
+        actor: query.actor,
+        action: query.action,
+        target: query.target,
+        outcome: query.outcome,
+        since: query.since,
+        until: query.until,
+        limit: query.limit,
+    };
+    let events = state
+        .db
+        .lock()
+        .await
+        .list_audit_events(*tenant_id, &filter)
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&events))
+}
+
+/// Middleware that records an audit event for every mutating request once
+/// the response is available.  Events are written to the database in the
+/// background, so they may appear in the audit log with a short delay.
+///
+/// Must be wrapped by the authentication middleware, which tags the request
+/// with the tenant and the actor.
+pub(crate) async fn record<B>(
+    response: impl Future<Output = Result<ServiceResponse<B>, Error>>,
+) -> Result<ServiceResponse<B>, Error> {
+    let response = response.await?;
+    let req = response.request();
+    let tenant_id = req.extensions().get::<TenantId>().copied();
+    let actor = req.extensions().get::<Actor>().cloned();
+    if let (Some(tenant_id), Some(actor), Some(state)) =
+        (tenant_id, actor, req.app_data::<WebData<ServerState>>())
+    {
+        let path = Url::new(req.uri().clone()).path().to_string();
+        record_event(
+            state,
+            tenant_id,
+            &actor,
+            req.method(),
+            &path,
+            response.status(),
+        )
+        .await;
+    }
+    Ok(response)
+}
+
+/// Records a request rejected by the authentication middleware because the
+/// actor lacks the required permissions.
+pub(crate) async fn record_denied(req: &ServiceRequest, tenant_id: TenantId, actor: &Actor) {
+    if let Some(state) = req.app_data::<WebData<ServerState>>() {
+        let path = Url::new(req.uri().clone()).path().to_string();
+        record_event(
+            state,
+            tenant_id,
+            actor,
+            req.method(),
+            &path,
+            StatusCode::FORBIDDEN,
+        )
+        .await;
+    }
+}
+
+async fn record_event(
+    state: &ServerState,
+    tenant_id: TenantId,
+    actor: &Actor,
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+) {
+    let Some((action, target)) = audited_action(method, path) else {
+        return;
+    };
+    let outcome = if status.is_success() || status.is_redirection() {
+        AuditOutcome::Success
+    } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        AuditOutcome::Denied
+    } else {
+        AuditOutcome::Failure
+    };
+    let event = AuditEvent {
+        audit_event_id: AuditEventId(Uuid::now_v7()),
+        timestamp: Utc::now(),
+        actor: actor.0.clone(),
+        action,
+        target,
+        outcome,
+        status_code: status.as_u16(),
+    };
+    // Failing to record the event does not fail the request, which has
+    // already been processed.
+    if let Err(e) = state.audit_events.send((tenant_id, event)).await {
+        let (tenant_id, event) = e.0;
+        error!(
+            "Could not record audit event {event:?} (tenant:{tenant_id}): writer has terminated"
+        );
+    }
+}
+
+/// Returns the action performed by a `method` request to `path` along with
+/// the name of the targeted resource, if any.
+///
+/// Returns `None` for requests that are not audited: reads, and data plane
+/// requests that push data to or read data from a running pipeline.
+fn audited_action(method: &Method, path: &str) -> Option<(String, Option<String>)> {
+    if *method == Method::GET || *method == Method::HEAD || *method == Method::OPTIONS {
+        return None;
+    }
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let (resource, rest) = match segments.as_slice() {
+        ["v0", "import"] => return Some(("bundle.import".to_string(), None)),
+        ["v0", collection, rest @ ..] => (
+            match *collection {
+                "pipelines" => "pipeline",
+                "programs" => "program",
+                "connectors" => "connector",
+                "services" => "service",
+                "api_keys" => "api_key",
+                "roles" => "role",
+                "secrets" => "secret",
+                "webhooks" => "webhook",
+                _ => return Some((format!("{method} {path}"), None)),
+            },
+            rest,
+        ),
+        _ => return Some((format!("{method} {path}"), None)),
+    };
+    let operation = match (method.as_str(), rest) {
+        (_, [_, "ingress" | "egress" | "lookup", ..]) => return None,
+        ("POST", []) => "create",
+        ("PUT", [_]) => "create_or_replace",
+        ("PATCH", [_]) => "update",
+        ("DELETE", [_]) => "delete",
+        ("POST", [_, "probes"]) => "probe",
+        ("PUT", [_, "schedules", _]) => "create_or_replace_schedule",
+        ("DELETE", [_, "schedules", _]) => "delete_schedule",
+        ("POST", [_, operation]) => operation,
+        _ => {
+            return Some((
+                format!("{method} {path}"),
+                rest.first().map(|s| s.to_string()),
+            ))
+        }
+    };
+    Some((
+        format!("{resource}.{operation}"),
+        rest.first().map(|s| s.to_string()),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::audited_action;
+    use actix_web::http::Method;
+
+    #[test]
+    fn audited_actions() {
+        let action = |method, path| audited_action(&method, path);
+        assert_eq!(action(Method::GET, "/v0/pipelines/p1"), None);
+        assert_eq!(action(Method::POST, "/v0/pipelines/p1/ingress/t1"), None);
+        assert_eq!(action(Method::POST, "/v0/pipelines/p1/egress/v1"), None);
+        assert_eq!(
+            action(Method::POST, "/v0/pipelines/p1/start"),
+            Some(("pipeline.start".to_string(), Some("p1".to_string())))
+        );
+        assert_eq!(
+            action(Method::PUT, "/v0/pipelines/p1/schedules/nightly"),
+            Some((
+                "pipeline.create_or_replace_schedule".to_string(),
+                Some("p1".to_string())
+            ))
+        );
+        assert_eq!(
+            action(Method::DELETE, "/v0/pipelines/p1/schedules/nightly"),
+            Some((
+                "pipeline.delete_schedule".to_string(),
+                Some("p1".to_string())
+            ))
+        );
+        assert_eq!(
+            action(Method::POST, "/v0/programs/prog/compile"),
+            Some(("program.compile".to_string(), Some("prog".to_string())))
+        );
+        assert_eq!(
+            action(Method::PATCH, "/v0/connectors/c1"),
+            Some(("connector.update".to_string(), Some("c1".to_string())))
+        );
+        assert_eq!(
+            action(Method::PUT, "/v0/roles/alice"),
+            Some((
+                "role.create_or_replace".to_string(),
+                Some("alice".to_string())
+            ))
+        );
+        assert_eq!(
+            action(Method::DELETE, "/v0/secrets/kafka-password"),
+            Some((
+                "secret.delete".to_string(),
+                Some("kafka-password".to_string())
+            ))
+        );
+        assert_eq!(
+            action(Method::POST, "/v0/webhooks"),
+            Some(("webhook.create".to_string(), None))
+        );
+        assert_eq!(
+            action(Method::DELETE, "/v0/webhooks/slack-alerts"),
+            Some((
+                "webhook.delete".to_string(),
+                Some("slack-alerts".to_string())
+            ))
+        );
+        assert_eq!(
+            action(Method::POST, "/v0/services/s1/probes"),
+            Some(("service.probe".to_string(), Some("s1".to_string())))
+        );
+        assert_eq!(
+            action(Method::POST, "/v0/api_keys"),
+            Some(("api_key.create".to_string(), None))
+        );
+        assert_eq!(
+            action(Method::POST, "/v0/import"),
+            Some(("bundle.import".to_string(), None))
+        );
+    }
+}
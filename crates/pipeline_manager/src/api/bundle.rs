@@ -0,0 +1,233 @@
+/// API to export and import the programs, connectors, services and pipelines
+/// of a tenant
+use super::{ManagerError, ServerState};
+use crate::{
+    auth::{SecretsAccess, TenantId},
+    db::{
+        bundle::{
+            self, Bundle, BundleAction, BundleChange, BundleResource, ImportOptions,
+            BUNDLE_FORMAT_VERSION,
+        },
+        DBError,
+    },
+    quotas::{check_compilation_quotas, tenant_quotas},
+    secrets::check_database_secret_access,
+};
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective, CONTENT_TYPE},
+    post,
+    web::{self, Data as WebData, ReqData},
+    HttpRequest, HttpResponse,
+};
+use futures_util::StreamExt;
+use log::info;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Maximum size of an imported bundle.
+const MAX_BUNDLE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Serialization format of a bundle.
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BundleFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct ExportQuery {
+    /// Format of the exported bundle: `json` (default) or `yaml`.
+    format: Option<BundleFormat>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct ImportQuery {
+    /// Report the changes the import would make without applying them.
+    dry_run: Option<bool>,
+    /// Delete the programs, connectors, services and pipelines that are not
+    /// part of the bundle.
+    prune: Option<bool>,
+}
+
+/// Response to an import request.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ImportResponse {
+    /// True if the changes were only computed and not applied.
+    dry_run: bool,
+    /// Resources created, updated or deleted by the import, in the order in
+    /// which they are applied.
+    changes: Vec<BundleChange>,
+}
+
+/// Export the programs, connectors, services and pipelines of the tenant.
+///
+/// The bundle can be imported into another pipeline manager instance via the
+/// `/import` endpoint. Secret references in connector and service
+/// configurations are exported as is, without being resolved. Credentials
+/// stored inline in connector and service configurations are replaced by
+/// `<redacted>`.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Bundle exported successfully", body = Bundle),
+    ),
+    params(ExportQuery),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Manager"
+)]
+#[get("/export")]
+pub(crate) async fn export_bundle(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let db = state.db.lock().await;
+    let bundle = bundle::export_bundle(&*db, *tenant_id).await?;
+    drop(db);
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header(CacheControl(vec![CacheDirective::NoCache]));
+    match query.format.unwrap_or_default() {
+        BundleFormat::Json => Ok(response.json(&bundle)),
+        BundleFormat::Yaml => {
+            let yaml = serde_yaml::to_string(&bundle).map_err(|e| {
+                DBError::invalid_data(format!("Error serializing bundle to YAML: {e}"))
+            })?;
+            Ok(response.content_type("application/yaml").body(yaml))
+        }
+    }
+}
+
+/// Import a bundle of programs, connectors, services and pipelines.
+///
+/// Creates the resources of the bundle that do not exist and updates those
+/// that differ from the bundle, identifying resources by name. Importing the
+/// same bundle again makes no changes. With `prune`, the resources that are
+/// not part of the bundle are deleted; pipelines must be shut down to be
+/// deleted. The changes are applied atomically.
+///
+/// Credentials that are `<redacted>` keep the value of the existing
+/// connector or service of the same name. Programs that are created or
+/// updated are compiled, which counts toward the compilation quota of the
+/// tenant.
+///
+/// The bundle is parsed as YAML if the request's content type is
+/// `application/yaml`, and as JSON otherwise.
+#[utoipa::path(
+    request_body = Bundle,
+    responses(
+        (status = OK, description = "Bundle imported successfully, or the changes it would make if `dry_run` is set", body = ImportResponse),
+        (status = BAD_REQUEST
+            , description = "The bundle is invalid, a credential is redacted with no existing value to take it from, or a pipeline to delete is not shut down"
+            , body = ErrorResponse),
+        (status = NOT_FOUND
+            , description = "A pipeline refers to a program or connector, or a connector to a service, that does not exist"
+            , body = ErrorResponse),
+        (status = CONFLICT
+            , description = "The bundle contains two resources of the same kind with the same name"
+            , body = ErrorResponse
+            , example = json!(super::examples::duplicate_bundle_name())),
+        (status = FORBIDDEN
+            , description = "The bundle references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(super::examples::database_secret_not_authorized())),
+        (status = TOO_MANY_REQUESTS
+            , description = "Compiling the programs of the bundle would exceed the compilation quota of the tenant"
+            , body = ErrorResponse
+            , example = json!(super::examples::quota_exceeded())),
+    ),
+    params(ImportQuery),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Manager"
+)]
+#[post("/import")]
+pub(crate) async fn import_bundle(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
+    req: HttpRequest,
+    query: web::Query<ImportQuery>,
+    mut body: web::Payload,
+) -> Result<HttpResponse, ManagerError> {
+    let mut bytes = web::BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| ManagerError::InvalidBundle {
+            error: e.to_string(),
+        })?;
+        if bytes.len() + chunk.len() > MAX_BUNDLE_SIZE {
+            return Err(ManagerError::InvalidBundle {
+                error: format!("bundle exceeds the maximum size of {MAX_BUNDLE_SIZE} bytes"),
+            });
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let is_yaml = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .is_some_and(|ct| ct.contains("yaml"));
+    let bundle: Bundle = if is_yaml {
+        serde_yaml::from_slice(&bytes).map_err(|e| ManagerError::InvalidBundle {
+            error: e.to_string(),
+        })?
+    } else {
+        serde_json::from_slice(&bytes).map_err(|e| ManagerError::InvalidBundle {
+            error: e.to_string(),
+        })?
+    };
+    if bundle.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(ManagerError::InvalidBundle {
+            error: format!(
+                "unsupported format version {}; the supported version is {BUNDLE_FORMAT_VERSION}",
+                bundle.format_version
+            ),
+        });
+    }
+    check_database_secret_access(*secrets_access, &bundle)?;
+
+    let options = ImportOptions {
+        dry_run: query.dry_run.unwrap_or(false),
+        prune: query.prune.unwrap_or(false),
+    };
+    let db = state.db.lock().await;
+    // Check the quotas against the planned changes before applying them.
+    let mut changes = bundle::import_bundle(
+        &db,
+        *tenant_id,
+        &bundle,
+        ImportOptions {
+            dry_run: true,
+            ..options
+        },
+    )
+    .await?;
+    let compiled: Vec<&str> = changes
+        .iter()
+        .filter(|c| c.resource == BundleResource::Program && c.action != BundleAction::Delete)
+        .map(|c| c.name.as_str())
+        .collect();
+    let quotas = tenant_quotas(&db, &state._config, *tenant_id).await?;
+    check_compilation_quotas(&db, &quotas, *tenant_id, &compiled).await?;
+    if !options.dry_run {
+        changes = bundle::import_bundle(&db, *tenant_id, &bundle, options).await?;
+    }
+    drop(db);
+    if !options.dry_run {
+        for change in &changes {
+            info!(
+                "Import: {:?} {} {} (tenant: {})",
+                change.action, change.resource, change.name, *tenant_id
+            );
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&ImportResponse {
+            dry_run: options.dry_run,
+            changes,
+        }))
+}
@@ -14,8 +14,9 @@ use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::{examples, parse_string_param},
-    auth::TenantId,
+    auth::{SecretsAccess, TenantId},
     db::{storage::Storage, ConnectorId, DBError},
+    secrets::check_database_secret_access,
 };
 
 use super::{ManagerError, ServerState};
@@ -140,6 +141,10 @@ async fn list_connectors(
         , description = "A connector with this name already exists in the database"
         , body = ErrorResponse
         , example = json!(examples::duplicate_name())),
+        (status = FORBIDDEN
+            , description = "The configuration references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(examples::database_secret_not_authorized())),
     ),
     context_path = "/v0",
     security(("JSON web token (JWT) or API key" = [])),
@@ -149,8 +154,10 @@ async fn list_connectors(
 async fn new_connector(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
     request: web::Json<NewConnectorRequest>,
-) -> Result<HttpResponse, DBError> {
+) -> Result<HttpResponse, ManagerError> {
+    check_database_secret_access(*secrets_access, &request.config)?;
     let connector_id = state
         .db
         .lock()
@@ -183,6 +190,10 @@ async fn new_connector(
             , description = "Specified connector name does not exist"
             , body = ErrorResponse
             , example = json!(examples::unknown_name())),
+        (status = FORBIDDEN
+            , description = "The configuration references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(examples::database_secret_not_authorized())),
     ),
     params(
         ("connector_name" = String, Path, description = "Unique connector name")
@@ -195,9 +206,11 @@ async fn new_connector(
 async fn update_connector(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
     req: HttpRequest,
     body: web::Json<UpdateConnectorRequest>,
 ) -> Result<HttpResponse, ManagerError> {
+    check_database_secret_access(*secrets_access, &body.config)?;
     let connector_name = parse_string_param(&req, "connector_name")?;
     let db = state.db.lock().await;
     db.update_connector_by_name(
@@ -228,6 +241,10 @@ async fn update_connector(
             , description = "A connector with this name already exists in the database"
             , body = ErrorResponse
             , example = json!(examples::duplicate_name())),
+        (status = FORBIDDEN
+            , description = "The configuration references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(examples::database_secret_not_authorized())),
     ),
     params(
         ("connector_name" = String, Path, description = "Unique connector name")
@@ -240,9 +257,11 @@ async fn update_connector(
 async fn create_or_replace_connector(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
     request: HttpRequest,
     body: web::Json<CreateOrReplaceConnectorRequest>,
 ) -> Result<HttpResponse, ManagerError> {
+    check_database_secret_access(*secrets_access, &body.config)?;
     let connector_name = parse_string_param(&request, "connector_name")?;
     let (created, connector_id) = state
         .db
@@ -296,7 +315,7 @@ async fn delete_connector(
         .db
         .lock()
         .await
-        .delete_connector(*tenant_id, &connector_name)
+        .delete_connector(*tenant_id, &connector_name, None)
         .await?;
 
     info!(
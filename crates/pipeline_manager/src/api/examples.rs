@@ -29,10 +29,73 @@ pub(crate) fn unknown_name() -> ErrorResponse {
     })
 }
 
+pub(crate) fn unknown_user_role() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::UnknownUserRole {
+        user_name: "alice".to_string(),
+    })
+}
+
+pub(crate) fn unknown_secret() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::UnknownSecret {
+        secret_name: "kafka-password".to_string(),
+    })
+}
+
+pub(crate) fn invalid_secret_name() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::InvalidSecretName {
+        secret_name: "Kafka_Password".to_string(),
+    })
+}
+
+pub(crate) fn unknown_webhook() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::UnknownWebhook {
+        webhook_name: "slack-alerts".to_string(),
+    })
+}
+
+pub(crate) fn invalid_webhook() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::InvalidWebhook {
+        reason: "the webhook must subscribe to at least one event type".to_string(),
+    })
+}
+
+pub(crate) fn unknown_pipeline_schedule() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::UnknownPipelineSchedule {
+        schedule_name: "business-hours".to_string(),
+    })
+}
+
+pub(crate) fn invalid_pipeline_schedule() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::InvalidPipelineSchedule {
+        reason: "unknown time zone 'Europe/Atlantis'".to_string(),
+    })
+}
+
+pub(crate) fn unknown_program_version() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::UnknownProgramVersion {
+        program_name: "example-program".to_string(),
+        version: Version(7),
+    })
+}
+
+pub(crate) fn invalid_version_param() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&ManagerError::InvalidVersionParam {
+        value: "latest".to_string(),
+        error: "invalid digit found in string".to_string(),
+    })
+}
+
 pub(crate) fn duplicate_name() -> ErrorResponse {
     ErrorResponse::from_error_nolog(&DBError::DuplicateName)
 }
 
+pub(crate) fn duplicate_bundle_name() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::DuplicateBundleName {
+        resource: "connector".to_string(),
+        name: "orders-kafka".to_string(),
+    })
+}
+
 pub(crate) fn program_in_use_by_pipeline() -> ErrorResponse {
     ErrorResponse::from_error_nolog(&DBError::ProgramInUseByPipeline {
         program_name: "unknown_name".to_string(),
@@ -49,6 +112,12 @@ pub(crate) fn outdated_program_version() -> ErrorResponse {
     })
 }
 
+pub(crate) fn program_not_compiling() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&DBError::ProgramNotCompiling {
+        program_name: "example".to_string(),
+    })
+}
+
 pub(crate) fn unknown_pipeline() -> ErrorResponse {
     ErrorResponse::from_error_nolog(&DBError::UnknownPipeline {
         pipeline_id: PipelineId(uuid!("2e79afe1-ff4d-44d3-af5f-9397de7746c0")),
@@ -120,6 +189,47 @@ pub(crate) fn illegal_pipeline_action() -> ErrorResponse {
     })
 }
 
+pub(crate) fn pipeline_upgrade_rejected() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&RunnerError::PipelineUpgradeRejected {
+        pipeline_id: PipelineId(uuid!("2e79afe1-ff4d-44d3-af5f-9397de7746c0")),
+        error: "the tables of the pipeline must not change, but table 'orders' is changed"
+            .to_string(),
+    })
+}
+
+pub(crate) fn quota_exceeded() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&ManagerError::QuotaExceeded {
+        quota: "workers".to_string(),
+        limit: 16,
+        usage: 12,
+        requested: 8,
+    })
+}
+
+pub(crate) fn quota_violation() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&ManagerError::QuotaViolation {
+        quota: "memory_mb".to_string(),
+        reason: "the pipeline must set 'resources.memory_mb_max'".to_string(),
+    })
+}
+
+pub(crate) fn reserved_api_key_name() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&ManagerError::InvalidNameParam {
+        value: "pipeline-input-my-key".to_string(),
+        error: "names starting with 'pipeline-input-' are reserved".to_string(),
+    })
+}
+
+pub(crate) fn egress_backpressure_not_allowed() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&ManagerError::EgressBackpressureNotAllowed)
+}
+
+pub(crate) fn database_secret_not_authorized() -> ErrorResponse {
+    ErrorResponse::from_error_nolog(&ManagerError::DatabaseSecretNotAuthorized {
+        path: "transport.config.kafka_options.sasl.password".to_string(),
+    })
+}
+
 pub(crate) fn cannot_delete_when_running() -> ErrorResponse {
     ErrorResponse::from_error_nolog(&RunnerError::IllegalPipelineStateTransition {
             pipeline_id: PipelineId(uuid!("2e79afe1-ff4d-44d3-af5f-9397de7746c0")),
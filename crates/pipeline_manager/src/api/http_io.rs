@@ -5,10 +5,12 @@ use actix_web::{
     HttpRequest, HttpResponse,
 };
 use log::debug;
+use serde::Deserialize;
 
 use crate::{
     api::{examples, parse_string_param},
-    auth::TenantId,
+    auth::{Actor, TenantId},
+    pipeline_graph::is_pipeline_input_key,
 };
 
 use super::{ManagerError, ServerState};
@@ -22,6 +24,12 @@ use super::{ManagerError, ServerState};
 /// The pipeline ingests data as it arrives without waiting for the end of
 /// the request.  Successful HTTP response indicates that all data has been
 /// ingested successfully.
+///
+/// With `?wait=processed`, the response is delayed until the pipeline has
+/// finished processing all data in the request and pushed the resulting
+/// outputs to all output connectors, and the response body
+/// contains the step number at which the data was processed, e.g.,
+/// `{"step": 42}`.
 // TODO: implement chunked and batch modes.
 #[utoipa::path(
     responses(
@@ -67,6 +75,7 @@ use super::{ManagerError, ServerState};
         ("format" = String, Query, description = "Input data format, e.g., 'csv' or 'json'."),
         ("array" = Option<bool>, Query, description = "Set to `true` if updates in this stream are packaged into JSON arrays (used in conjunction with `format=json`). The default values is `false`."),
         ("update_format" = Option<JsonUpdateFormat>, Query, description = "JSON data change event format (used in conjunction with `format=json`).  The default value is 'insert_delete'."),
+        ("wait" = Option<IngressWait>, Query, description = "When to respond to the request. Must be one of 'parsed' or 'processed'. The default value is 'parsed'."),
     ),
     context_path = "/v0",
     security(("JSON web token (JWT) or API key" = [])),
@@ -111,6 +120,14 @@ async fn http_input(
         .await
 }
 
+/// Query parameters of [`http_output`] that the pipeline manager checks
+/// before forwarding the request.
+#[derive(Deserialize)]
+struct EgressParams {
+    #[serde(default)]
+    backpressure: bool,
+}
+
 /// Subscribe to a stream of updates from a SQL view or table.
 ///
 /// The pipeline responds with a continuous stream of changes to the specified
@@ -120,6 +137,17 @@ async fn http_input(
 /// The pipeline continues sending updates until the client closes the
 /// connection or the pipeline is shut down.
 ///
+/// With `?mode=snapshot&query=table`, the pipeline responds with the current
+/// contents of the table or view and closes the connection.  The request body
+/// may contain a `TableSnapshotQuery` to restrict the output to a key range
+/// and to read the contents page by page.  With `?mode=snapshot_and_watch`,
+/// the pipeline responds with the current contents followed by the stream of
+/// changes.  Table snapshots are only available when the program is
+/// configured with `materialized: true`.
+///
+/// `?backpressure=true` is reserved for pipelines that read from other
+/// pipelines, since a client that reads slowly stalls the pipeline.
+///
 /// This API is a POST instead of a GET, because when performing neighborhood
 /// queries (query='neighborhood'), the call expects a request body which
 /// contains, among other things, a full row to execute a neighborhood search
@@ -153,6 +181,10 @@ async fn http_input(
             , body = ErrorResponse
             // , example = json!(examples::unknown_output_format())
             ),
+        (status = FORBIDDEN
+            , description = "The request sets `backpressure=true`, but it is not issued by a pipeline."
+            , body = ErrorResponse
+            , example = json!(examples::egress_backpressure_not_allowed())),
         (status = INTERNAL_SERVER_ERROR
             , description = "Request failed."
             , body = ErrorResponse),
@@ -163,13 +195,14 @@ async fn http_input(
             description = "SQL table name. Unquoted SQL names have to be capitalized. Quoted SQL names have to exactly match the case from the SQL program."),
         ("format" = String, Query, description = "Output data format, e.g., 'csv' or 'json'."),
         ("query" = Option<OutputQuery>, Query, description = "Query to execute on the table. Must be one of 'table', 'neighborhood', or 'quantiles'. The default value is 'table'"),
-        ("mode" = Option<EgressMode>, Query, description = "Output mode. Must be one of 'watch' or 'snapshot'. The default value is 'watch'"),
+        ("mode" = Option<EgressMode>, Query, description = "Output mode. Must be one of 'watch', 'snapshot', or 'snapshot_and_watch'. The default value is 'watch'"),
         ("quantiles" = Option<u32>, Query, description = "For 'quantiles' queries: the number of quantiles to output. The default value is 100."),
         ("array" = Option<bool>, Query, description = "Set to `true` to group updates in this stream into JSON arrays (used in conjunction with `format=json`). The default value is `false`"),
+        ("backpressure" = Option<bool>, Query, description = "Set to `true` to make the pipeline wait for this client to read its output instead of dropping output that the client falls behind on (used in conjunction with `mode=watch` or `mode=snapshot_and_watch`). Only allowed for the API keys that the pipeline manager issues to pipelines that read from other pipelines. The default value is `false`"),
     ),
     request_body(
         content = Option<NeighborhoodQuery>,
-        description = "When the `query` parameter is set to 'neighborhood', the body of the request must contain a neighborhood specification.",
+        description = "When the `query` parameter is set to 'neighborhood', the body of the request must contain a neighborhood specification. When `query` is 'table' and `mode` is 'snapshot', the body of the request may contain a `TableSnapshotQuery`.",
         content_type = "application/json",
     ),
     context_path = "/v0",
@@ -180,6 +213,7 @@ async fn http_input(
 async fn http_output(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    actor: ReqData<Actor>,
     client: WebData<awc::Client>,
     req: HttpRequest,
     body: web::Payload,
@@ -193,6 +227,13 @@ async fn http_output(
         }
         Some(table_name) => table_name,
     };
+    // Invalid parameters are rejected by the pipeline.
+    let backpressure = web::Query::<EgressParams>::from_query(req.query_string())
+        .map(|params| params.backpressure)
+        .unwrap_or_default();
+    if backpressure && !is_pipeline_input_key(&actor) {
+        return Err(ManagerError::EgressBackpressureNotAllowed);
+    }
     let endpoint = format!("egress/{table_name}");
     state
         .runner
@@ -206,3 +247,86 @@ async fn http_output(
         )
         .await
 }
+
+/// Look up records in a SQL view by key.
+///
+/// The body of the request contains a JSON array of keys.  The pipeline
+/// responds with a JSON array that contains one element per key: the record
+/// associated with the key or `null` if there is no such record.
+///
+/// Lookups are served from an index maintained by the pipeline without
+/// waiting for the pipeline to process new inputs.  Only views declared with
+/// a primary key (`CREATE VIEW v PRIMARY KEY (k) AS ...`) support this API.
+/// Keys are encoded as records that contain the key columns.
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Lookup completed successfully. The body of the response contains a JSON array of records."
+            , content_type = "application/json"),
+        (status = BAD_REQUEST
+            , description = "Specified pipeline id is not a valid uuid."
+            , body = ErrorResponse
+            , example = json!(examples::invalid_uuid_param())),
+        (status = NOT_FOUND
+            , description = "Specified pipeline id does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline())),
+        (status = NOT_FOUND
+            , description = "Specified table or view does not exist."
+            , body = ErrorResponse),
+        (status = GONE
+            , description = "Pipeline is not currently running because it has been shutdown or not yet started."
+            , body = ErrorResponse
+            , example = json!(examples::pipeline_shutdown())),
+        (status = METHOD_NOT_ALLOWED
+            , description = "The specified view is not declared with a primary key."
+            , body = ErrorResponse),
+        (status = BAD_REQUEST
+            , description = "Error parsing the keys in the body of the request."
+            , body = ErrorResponse),
+        (status = INTERNAL_SERVER_ERROR
+            , description = "Request failed."
+            , body = ErrorResponse),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        ("view_name" = String, Path,
+            description = "SQL view name. Unquoted SQL names have to be capitalized. Quoted SQL names have to exactly match the case from the SQL program."),
+    ),
+    request_body(
+        content = Vec<Object>,
+        description = "JSON array of keys to look up.",
+        content_type = "application/json",
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "HTTP input/output"
+)]
+#[post("/pipelines/{pipeline_name}/lookup/{view_name}")]
+async fn http_lookup(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    client: WebData<awc::Client>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let view_name = match req.match_info().get("view_name") {
+        None => {
+            return Err(ManagerError::MissingUrlEncodedParam { param: "view_name" });
+        }
+        Some(view_name) => view_name,
+    };
+    let endpoint = format!("lookup/{view_name}");
+    state
+        .runner
+        .forward_to_pipeline_as_stream(
+            *tenant_id,
+            &pipeline_name,
+            &endpoint,
+            req,
+            body,
+            client.as_ref(),
+        )
+        .await
+}
@@ -17,13 +17,21 @@
 //!   compiled pipelines and for interacting with them at runtime.
 
 mod api_key;
+pub(crate) mod audit;
+mod bundle;
 mod config_api;
 mod connector;
 mod examples;
 mod http_io;
 mod pipeline;
+mod pipeline_event;
+mod pipeline_schedule;
 mod program;
+mod quota;
+mod role;
+mod secret;
 mod service;
+mod webhook;
 
 use crate::prober::service::{
     ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse, ServiceProbeResult,
@@ -60,6 +68,7 @@ use crate::db::{
 };
 pub use crate::error::ManagerError;
 use crate::runner::RunnerApi;
+use pipeline_event::{forward_pipeline_events, pipeline_event_sender, PipelineEventSender};
 
 use crate::auth::TenantId;
 
@@ -122,12 +131,25 @@ request is rejected."
         program::update_program,
         program::create_or_replace_program,
         program::compile_program,
+        program::cancel_program_compilation,
+        program::list_program_versions,
+        program::get_program_version,
+        program::diff_program_versions,
+        program::rollback_program,
         program::delete_program,
         pipeline::new_pipeline,
         pipeline::update_pipeline,
         pipeline::create_or_replace_pipeline,
         pipeline::list_pipelines,
+        pipeline::get_pipeline_graph,
         pipeline::pipeline_stats,
+        pipeline::pipeline_logs,
+        pipeline_event::list_pipeline_events,
+        pipeline_event::stream_pipeline_events,
+        pipeline_schedule::list_pipeline_schedules,
+        pipeline_schedule::get_pipeline_schedule,
+        pipeline_schedule::create_or_replace_pipeline_schedule,
+        pipeline_schedule::delete_pipeline_schedule,
         pipeline::get_pipeline,
         pipeline::get_pipeline_config,
         pipeline::pipeline_validate,
@@ -149,10 +171,25 @@ request is rejected."
         service::list_service_probes,
         http_io::http_input,
         http_io::http_output,
+        http_io::http_lookup,
         api_key::create_api_key,
         api_key::list_api_keys,
         api_key::get_api_key,
         api_key::delete_api_key,
+        role::list_user_roles,
+        role::set_user_role,
+        role::delete_user_role,
+        secret::list_secrets,
+        secret::set_secret,
+        secret::delete_secret,
+        webhook::list_webhooks,
+        webhook::new_webhook,
+        webhook::delete_webhook,
+        webhook::list_webhook_deliveries,
+        quota::get_quotas,
+        audit::list_audit_events,
+        bundle::export_bundle,
+        bundle::import_bundle,
         config_api::get_authentication_config,
         config_api::get_demos,
     ),
@@ -160,9 +197,13 @@ request is rejected."
         crate::auth::AuthProvider,
         crate::auth::ProviderAwsCognito,
         crate::auth::ProviderGoogleIdentity,
-        crate::compiler::SqlCompilerMessage,
+        crate::auth::ProviderGenericOidc,
+        crate::compiler::CompilerDiagnostic,
+        crate::compiler::DiagnosticSeverity,
+        crate::compiler::SqlPosition,
         crate::db::AttachedConnector,
         crate::db::ProgramDescr,
+        crate::db::ProgramVersionDescr,
         crate::db::ConnectorDescr,
         crate::db::ServiceDescr,
         crate::db::ServiceProbeDescr,
@@ -175,12 +216,51 @@ request is rejected."
         crate::db::ApiKeyId,
         crate::db::ApiKeyDescr,
         crate::db::ApiPermission,
+        crate::db::ApiKeyScope,
+        crate::db::ApiResource,
+        crate::db::ApiAction,
+        crate::db::audit::Role,
+        crate::db::audit::UserRole,
+        crate::db::secret::SecretDescr,
+        crate::db::audit::AuditEvent,
+        crate::db::audit::AuditEventId,
+        crate::db::audit::AuditOutcome,
+        crate::db::pipeline_event::PipelineEvent,
+        crate::db::pipeline_schedule::PipelineScheduleId,
+        crate::db::pipeline_schedule::ScheduledAction,
+        crate::db::pipeline_schedule::PipelineScheduleConfig,
+        crate::db::pipeline_schedule::PipelineScheduleDescr,
+        crate::pipeline_graph::PipelineGraph,
+        crate::pipeline_graph::PipelineNode,
+        crate::pipeline_graph::PipelineDependency,
+        crate::db::PipelineEventId,
+        crate::db::webhook::WebhookId,
+        crate::db::webhook::WebhookDescr,
+        crate::db::webhook::WebhookEventType,
+        crate::db::webhook::WebhookDeliveryId,
+        crate::db::webhook::WebhookDeliveryStatus,
+        crate::db::webhook::WebhookDelivery,
+        crate::config::TenantQuotas,
+        crate::quotas::QuotaUsage,
+        crate::quotas::QuotasResponse,
+        crate::db::bundle::Bundle,
+        crate::db::bundle::BundledProgram,
+        crate::db::bundle::BundledConnector,
+        crate::db::bundle::BundledService,
+        crate::db::bundle::BundledPipeline,
+        crate::db::bundle::BundleResource,
+        crate::db::bundle::BundleAction,
+        crate::db::bundle::BundleChange,
+        crate::validation::PipelineValidation,
+        crate::validation::ConnectorValidation,
+        crate::validation::DryRunResult,
         pipeline_types::program_schema::ProgramSchema,
         pipeline_types::program_schema::Relation,
         pipeline_types::program_schema::SqlType,
         pipeline_types::program_schema::Field,
         pipeline_types::program_schema::ColumnType,
         pipeline_types::query::NeighborhoodQuery,
+        pipeline_types::query::TableSnapshotQuery,
         pipeline_types::query::OutputQuery,
         pipeline_types::config::PipelineConfig,
         pipeline_types::config::InputEndpointConfig,
@@ -192,6 +272,7 @@ request is rejected."
         pipeline_types::config::TransportConfig,
         pipeline_types::config::FormatConfig,
         pipeline_types::config::ResourceConfig,
+        pipeline_types::config::RestartPolicy,
         pipeline_types::transport::file::FileInputConfig,
         pipeline_types::transport::file::FileOutputConfig,
         pipeline_types::transport::url::UrlInputConfig,
@@ -202,10 +283,13 @@ request is rejected."
         pipeline_types::transport::kafka::KafkaLogLevel,
         pipeline_types::transport::http::Chunk,
         pipeline_types::transport::http::EgressMode,
+        pipeline_types::transport::http::IngressWait,
         pipeline_types::transport::s3::AwsCredentials,
         pipeline_types::transport::s3::ConsumeStrategy,
         pipeline_types::transport::s3::ReadStrategy,
         pipeline_types::transport::s3::S3InputConfig,
+        pipeline_types::transport::pipeline::PipelineInputConfig,
+        pipeline_types::format::arrow_ipc::ArrowIpcEncoderConfig,
         pipeline_types::format::csv::CsvEncoderConfig,
         pipeline_types::format::csv::CsvParserConfig,
         pipeline_types::format::json::JsonEncoderConfig,
@@ -217,6 +301,9 @@ request is rejected."
         pipeline_types::error::ErrorResponse,
         pipeline_types::service::ServiceConfig,
         pipeline_types::service::KafkaService,
+        pipeline_types::service::PostgresService,
+        pipeline_types::service::S3Service,
+        pipeline_types::service::HttpService,
         TenantId,
         ProgramId,
         PipelineId,
@@ -234,6 +321,9 @@ request is rejected."
         program::CreateOrReplaceProgramRequest,
         program::CreateOrReplaceProgramResponse,
         program::CompileProgramRequest,
+        program::RollbackProgramRequest,
+        program::ProgramVersionDiff,
+        program::ProgramSchemaDiff,
         pipeline::NewPipelineRequest,
         pipeline::NewPipelineResponse,
         pipeline::UpdatePipelineRequest,
@@ -255,6 +345,12 @@ request is rejected."
         service::CreateServiceProbeResponse,
         api_key::NewApiKeyRequest,
         api_key::NewApiKeyResponse,
+        role::SetUserRoleRequest,
+        secret::SetSecretRequest,
+        webhook::NewWebhookRequest,
+        webhook::NewWebhookResponse,
+        bundle::BundleFormat,
+        bundle::ImportResponse,
         ServiceProbeType,
         ServiceProbeRequest,
         ServiceProbeResponse,
@@ -268,6 +364,10 @@ request is rejected."
         (name = "Pipelines", description = "Manage pipelines"),
         (name = "Connectors", description = "Manage data connectors"),
         (name = "Services", description = "Manage services"),
+        (name = "Roles", description = "Manage the roles of users"),
+        (name = "Secrets", description = "Manage secrets stored in the database"),
+        (name = "Webhooks", description = "Manage webhooks notified of program and pipeline events"),
+        (name = "Audit", description = "Inspect the audit log"),
     ),
 )]
 pub struct ApiDoc;
@@ -301,12 +401,25 @@ fn api_scope() -> Scope {
         .service(program::update_program)
         .service(program::create_or_replace_program)
         .service(program::compile_program)
+        .service(program::cancel_program_compilation)
+        .service(program::list_program_versions)
+        .service(program::get_program_version)
+        .service(program::diff_program_versions)
+        .service(program::rollback_program)
         .service(program::delete_program)
         .service(pipeline::new_pipeline)
         .service(pipeline::update_pipeline)
         .service(pipeline::create_or_replace_pipeline)
         .service(pipeline::list_pipelines)
+        .service(pipeline::get_pipeline_graph)
         .service(pipeline::pipeline_stats)
+        .service(pipeline::pipeline_logs)
+        .service(pipeline_event::list_pipeline_events)
+        .service(pipeline_event::stream_pipeline_events)
+        .service(pipeline_schedule::list_pipeline_schedules)
+        .service(pipeline_schedule::get_pipeline_schedule)
+        .service(pipeline_schedule::create_or_replace_pipeline_schedule)
+        .service(pipeline_schedule::delete_pipeline_schedule)
         .service(pipeline::get_pipeline)
         .service(pipeline::get_pipeline_config)
         .service(pipeline::pipeline_action)
@@ -331,8 +444,23 @@ fn api_scope() -> Scope {
         .service(api_key::list_api_keys)
         .service(api_key::get_api_key)
         .service(api_key::delete_api_key)
+        .service(role::list_user_roles)
+        .service(role::set_user_role)
+        .service(role::delete_user_role)
+        .service(secret::list_secrets)
+        .service(secret::set_secret)
+        .service(secret::delete_secret)
+        .service(webhook::list_webhooks)
+        .service(webhook::new_webhook)
+        .service(webhook::delete_webhook)
+        .service(webhook::list_webhook_deliveries)
+        .service(quota::get_quotas)
+        .service(audit::list_audit_events)
+        .service(bundle::export_bundle)
+        .service(bundle::import_bundle)
         .service(http_io::http_input)
         .service(http_io::http_output)
+        .service(http_io::http_lookup)
         .service(config_api::get_demos)
 }
 
@@ -382,21 +510,28 @@ pub(crate) struct ServerState {
     // requests.
     pub db: Arc<Mutex<ProjectDB>>,
     runner: RunnerApi,
-    _config: ApiServerConfig,
+    pub(crate) _config: ApiServerConfig,
     pub jwk_cache: Arc<Mutex<JwkCache>>,
     probe: Arc<Mutex<Probe>>,
+    /// Forwards pipeline status transitions to the clients following them.
+    pipeline_events: PipelineEventSender,
+    /// Queues audit events to be written to the database.
+    audit_events: audit::AuditEventSender,
 }
 
 impl ServerState {
     pub async fn new(config: ApiServerConfig, db: Arc<Mutex<ProjectDB>>) -> AnyResult<Self> {
         let runner = RunnerApi::new(db.clone());
         let db_copy = db.clone();
+        let audit_events = audit::spawn_writer(db.clone());
         Ok(Self {
             db,
             runner,
             _config: config,
             jwk_cache: Arc::new(Mutex::new(JwkCache::new())),
             probe: Probe::new(db_copy).await,
+            pipeline_events: pipeline_event_sender(),
+            audit_events,
         })
     }
 }
@@ -416,13 +551,25 @@ fn create_listener(api_config: &ApiServerConfig) -> AnyResult<TcpListener> {
 
 pub async fn run(db: Arc<Mutex<ProjectDB>>, api_config: ApiServerConfig) -> AnyResult<()> {
     let listener = create_listener(&api_config)?;
-    let state = WebData::new(ServerState::new(api_config.clone(), db).await?);
+    let state = WebData::new(ServerState::new(api_config.clone(), db.clone()).await?);
+    tokio::spawn(forward_pipeline_events(
+        db.clone(),
+        state.pipeline_events.clone(),
+    ));
+    tokio::spawn(crate::webhooks::dispatch(
+        db.clone(),
+        api_config.webhook_allowed_hosts.clone(),
+    ));
+    tokio::spawn(crate::schedules::run(db, api_config.clone()));
     let bind_address = api_config.bind_address.clone();
     let port = api_config.port;
     let auth_configuration = match api_config.auth_provider {
         crate::config::AuthProviderType::None => None,
         crate::config::AuthProviderType::AwsCognito => Some(crate::auth::aws_auth_config()),
         crate::config::AuthProviderType::GoogleIdentity => Some(crate::auth::google_auth_config()),
+        crate::config::AuthProviderType::GenericOidc => {
+            Some(crate::auth::generic_oidc_auth_config())
+        }
     };
     let server = match auth_configuration {
         // We instantiate an awc::Client that can be used if the api-server needs to
@@ -438,7 +585,11 @@ pub async fn run(db: Arc<Mutex<ProjectDB>>, api_config: ApiServerConfig) -> AnyR
                     .app_data(client)
                     .wrap(Logger::default().exclude("/healthz"))
                     .wrap(api_config.cors())
-                    .service(api_scope().wrap(auth_middleware))
+                    .service(
+                        api_scope()
+                            .wrap_fn(|req, srv| audit::record(srv.call(req)))
+                            .wrap(auth_middleware),
+                    )
                     .service(public_scope())
             });
             server.listen(listener)?.run()
@@ -451,10 +602,14 @@ pub async fn run(db: Arc<Mutex<ProjectDB>>, api_config: ApiServerConfig) -> AnyR
                     .app_data(client)
                     .wrap(Logger::default().exclude("/healthz"))
                     .wrap(api_config.cors())
-                    .service(api_scope().wrap_fn(|req, srv| {
-                        let req = crate::auth::tag_with_default_tenant_id(req);
-                        srv.call(req)
-                    }))
+                    .service(
+                        api_scope()
+                            .wrap_fn(|req, srv| audit::record(srv.call(req)))
+                            .wrap_fn(|req, srv| {
+                                let req = crate::auth::tag_with_default_tenant_id(req);
+                                srv.call(req)
+                            }),
+                    )
                     .service(public_scope())
             });
             server.listen(listener)?.run()
@@ -8,19 +8,36 @@ use actix_web::{
     HttpRequest, HttpResponse,
 };
 use log::{debug, info};
-use pipeline_types::config::RuntimeConfig;
+use pipeline_types::config::{ConnectorConfig, RuntimeConfig};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::{examples, parse_string_param},
-    auth::TenantId,
+    auth::{Actor, TenantId},
     db::{storage::Storage, AttachedConnector, DBError, PipelineId, Version},
+    pipeline_graph::pipeline_graph,
+    quotas::tenant_quotas,
+    secrets::fetch_database_secrets,
+    validation::{validate_pipeline, ValidationOptions},
 };
 
 use super::{ManagerError, ServerState};
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Number of records fetched from each input connector during a dry run,
+/// unless specified otherwise.
+const DEFAULT_SAMPLE_SIZE: usize = 10;
+
+/// Upper bound on the number of records fetched from each input connector
+/// during a dry run.
+const MAX_SAMPLE_SIZE: usize = 100;
+
+/// Maximum time spent fetching records from an input connector during a dry
+/// run.
+const DRY_RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Request to create a new pipeline.
 #[derive(Debug, Deserialize, ToSchema)]
 #[serde(deny_unknown_fields)]
@@ -360,6 +377,30 @@ pub(crate) async fn pipeline_deployed(
         .json(&descr))
 }
 
+/// Retrieve the dependency graph of pipelines.
+///
+/// The graph has an edge for each input connector of a pipeline that reads
+/// a view of another pipeline using the `pipeline_input` transport.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Pipeline dependency graph retrieved successfully.", body = PipelineGraph)
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[get("/pipeline_graph")]
+pub(crate) async fn get_pipeline_graph(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+) -> Result<HttpResponse, DBError> {
+    let graph = pipeline_graph(&*state.db.lock().await, *tenant_id).await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(graph))
+}
+
 /// Retrieve pipeline metrics and performance counters.
 #[utoipa::path(
     responses(
@@ -395,6 +436,67 @@ pub(crate) async fn pipeline_stats(
         .await
 }
 
+/// Query parameters to retrieve pipeline logs.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PipelineLogsQuery {
+    /// Only return the last `tail` lines of the log.
+    tail: Option<usize>,
+    /// Keep the connection open and stream new log lines as the pipeline
+    /// produces them, until the pipeline process terminates.  The default is
+    /// `false`.
+    follow: Option<bool>,
+}
+
+/// Retrieve the output of the pipeline process.
+///
+/// Returns stdout and stderr of the pipeline as plain text, including output
+/// from previous runs of the pipeline up to a size limit configured in the
+/// local runner.  With `?follow=true`, the response is a stream that
+/// continues with new output until the pipeline process terminates or the
+/// client disconnects.
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Pipeline log retrieved successfully."
+            , content_type = "text/plain"
+            , body = String),
+        (status = NOT_FOUND
+            , description = "Specified pipeline name does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline())),
+        (status = INTERNAL_SERVER_ERROR
+            , description = "Failed to retrieve the log from the local runner."
+            , body = ErrorResponse),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        PipelineLogsQuery,
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[get("/pipelines/{pipeline_name}/logs")]
+pub(crate) async fn pipeline_logs(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    client: WebData<awc::Client>,
+    req: HttpRequest,
+    _query: web::Query<PipelineLogsQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    state
+        .runner
+        .pipeline_logs(
+            *tenant_id,
+            &pipeline_name,
+            &state._config.runner_hostname_port,
+            &req,
+            client.as_ref(),
+        )
+        .await
+}
+
 /// Fetch a pipeline by ID.
 #[utoipa::path(
     responses(
@@ -474,18 +576,37 @@ pub(crate) async fn get_pipeline_config(
         .json(&expanded_config))
 }
 
+/// Query parameters to validate a pipeline.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ValidatePipelineQuery {
+    /// Fetch and parse a sample of records from every input connector.
+    /// The default is `false`.
+    dry_run: Option<bool>,
+    /// Maximum number of records fetched from each input connector during
+    /// a dry run.  The default is 10, the maximum is 100.
+    sample_size: Option<u32>,
+}
+
 /// Validate a pipeline.
 ///
 /// Checks whether a pipeline is configured correctly. This includes
 /// checking whether the pipeline references a valid compiled program,
 /// whether the connectors reference valid tables/views in the program,
 /// and more.
+///
+/// In addition, the configuration of each attached connector is checked:
+/// references to database secrets must resolve and other secret references
+/// must be well-formed, the transport configuration must be valid for the
+/// direction of the connector, and the format must be able to represent the
+/// schema of the table or view.  With `?dry_run=true`, a sample of records
+/// is fetched from every URL and Kafka input connector and parsed against
+/// the schema of its table.  Problems with individual connectors are
+/// reported in the response body rather than as an error.
 #[utoipa::path(
     responses(
         (status = OK
-            , description = "Validate a Pipeline config."
-            , content_type = "application/json"
-            , body = String),
+            , description = "Validation results of the pipeline connectors."
+            , body = PipelineValidation),
         (status = BAD_REQUEST
             , description = "Invalid pipeline."
             , body = ErrorResponse
@@ -505,6 +626,7 @@ pub(crate) async fn get_pipeline_config(
     ),
     params(
         ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        ValidatePipelineQuery,
     ),
     context_path = "/v0",
     security(("JSON web token (JWT) or API key" = [])),
@@ -515,14 +637,37 @@ pub(crate) async fn pipeline_validate(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
     req: HttpRequest,
+    query: web::Query<ValidatePipelineQuery>,
 ) -> Result<HttpResponse, ManagerError> {
     let pipeline_name = parse_string_param(&req, "pipeline_name")?;
-    let db = state.db.lock().await;
-    let pipeline = db.get_pipeline_by_name(*tenant_id, &pipeline_name).await?;
-    Ok(db
-        .is_pipeline_deployable(*tenant_id, pipeline.descriptor.pipeline_id)
-        .await
-        .map(|_| HttpResponse::Ok().json("Pipeline successfully validated."))?)
+    let (pipeline, program, connectors, database_secrets) = {
+        let db = state.db.lock().await;
+        let pipeline = db.get_pipeline_by_name(*tenant_id, &pipeline_name).await?;
+        let (pipeline, program, connectors) = db
+            .is_pipeline_deployable(*tenant_id, pipeline.descriptor.pipeline_id)
+            .await?;
+        let configs: Vec<&ConnectorConfig> = connectors.iter().map(|c| &c.config).collect();
+        let database_secrets = fetch_database_secrets(&db, *tenant_id, &configs).await;
+        (pipeline, program, connectors, database_secrets)
+    };
+
+    // Do not hold the database lock while connecting to data sources.
+    let options = ValidationOptions {
+        database_secrets,
+        dry_run: query.dry_run.unwrap_or(false),
+        sample_size: query
+            .sample_size
+            .map_or(DEFAULT_SAMPLE_SIZE, |n| n as usize)
+            .min(MAX_SAMPLE_SIZE),
+        timeout: DRY_RUN_TIMEOUT,
+        allowed_hosts: state._config.dry_run_allowed_hosts.clone(),
+    };
+    let validation = validate_pipeline(&pipeline, &program, &connectors, &options).await;
+    debug!(
+        "Validated pipeline {pipeline_name} (valid: {}) (tenant:{})",
+        validation.valid, *tenant_id
+    );
+    Ok(HttpResponse::Ok().json(&validation))
 }
 
 /// Change the desired state of the pipeline.
@@ -543,6 +688,24 @@ pub(crate) async fn pipeline_validate(
 /// - 'start': Start processing data.
 /// - 'pause': Pause the pipeline.
 /// - 'shutdown': Terminate the execution of the pipeline.
+/// - 'upgrade': Upgrade a running or paused pipeline to a new revision, built
+///   from the current version of its program and its current configuration,
+///   without shutting the pipeline down.
+///
+/// An upgrade is only possible if the new program declares the same tables
+/// as the running one, the input connectors of the pipeline are unchanged,
+/// and all connectors of the pipeline are fault tolerant.  The new revision
+/// starts in a standby instance next to the running one, which rebuilds the
+/// state of the pipeline by replaying the input that the running instance
+/// recorded in its input connectors, without producing output.  Once it has
+/// caught up, the running instance is stopped and the standby takes over in
+/// the status the pipeline had before the upgrade: it writes the output that
+/// the old instance did not write, and continues to read the input where the
+/// old instance stopped.  Since both instances process the same input steps,
+/// views whose definitions are unchanged produce the same output, so output
+/// connectors see no spurious changes.  An output connector of a view whose
+/// definition changed must be connected to a new topic, or the upgrade fails.
+/// If the upgrade fails, the running instance keeps running the old revision.
 #[utoipa::path(
     responses(
         (status = ACCEPTED
@@ -560,8 +723,17 @@ pub(crate) async fn pipeline_validate(
                 ("Invalidtable or view reference" = (description = "Connectors reference a view that doesn't exist.", value = json!(examples::pipeline_invalid_output_ac()))),
                 ("Invalid action" = (description = "Invalid action specified", value = json!(examples::invalid_pipeline_action()))),
                 ("Action cannot be applied" = (description = "Action is not applicable in the current state of the pipeline.", value = json!(examples::illegal_pipeline_action()))),
+                ("Upgrade rejected" = (description = "The pipeline cannot be upgraded in place to a new revision.", value = json!(examples::pipeline_upgrade_rejected()))),
             )
         ),
+        (status = FORBIDDEN
+            , description = "The pipeline exceeds a quota of the tenant on its own."
+            , body = ErrorResponse
+            , example = json!(examples::quota_violation())),
+        (status = TOO_MANY_REQUESTS
+            , description = "Running the pipeline together with the other running or paused pipelines would exceed a quota of the tenant."
+            , body = ErrorResponse
+            , example = json!(examples::quota_exceeded())),
         (status = NOT_FOUND
             , description = "Specified pipeline id does not exist."
             , body = ErrorResponse
@@ -573,7 +745,7 @@ pub(crate) async fn pipeline_validate(
     ),
     params(
         ("pipeline_name" = String, Path, description = "Unique pipeline name"),
-        ("action" = String, Path, description = "Pipeline action [start, pause, shutdown]")
+        ("action" = String, Path, description = "Pipeline action [start, pause, shutdown, upgrade]")
     ),
     context_path = "/v0",
     security(("JSON web token (JWT) or API key" = [])),
@@ -583,28 +755,41 @@ pub(crate) async fn pipeline_validate(
 pub(crate) async fn pipeline_action(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    actor: ReqData<Actor>,
     req: HttpRequest,
 ) -> Result<HttpResponse, ManagerError> {
     let pipeline_name = parse_string_param(&req, "pipeline_name")?;
     let action = parse_pipeline_action(&req)?;
 
+    let quotas = if matches!(action, "start" | "pause" | "upgrade") {
+        tenant_quotas(&*state.db.lock().await, &state._config, *tenant_id).await?
+    } else {
+        Default::default()
+    };
+
     match action {
         "start" => {
             state
                 .runner
-                .start_pipeline(*tenant_id, &pipeline_name)
+                .start_pipeline(*tenant_id, &pipeline_name, &actor.0, &quotas)
                 .await?
         }
         "pause" => {
             state
                 .runner
-                .pause_pipeline(*tenant_id, &pipeline_name)
+                .pause_pipeline(*tenant_id, &pipeline_name, &actor.0, &quotas)
                 .await?
         }
         "shutdown" => {
             state
                 .runner
-                .shutdown_pipeline(*tenant_id, &pipeline_name)
+                .shutdown_pipeline(*tenant_id, &pipeline_name, &actor.0)
+                .await?
+        }
+        "upgrade" => {
+            state
+                .runner
+                .upgrade_pipeline(*tenant_id, &pipeline_name, &actor.0, &quotas)
                 .await?
         }
         _ => Err(ManagerError::InvalidPipelineAction {
@@ -656,9 +841,108 @@ pub(crate) async fn pipeline_delete(
 
     state
         .runner
-        .delete_pipeline(*tenant_id, &pipeline_name)
+        .delete_pipeline(*tenant_id, &pipeline_name, None)
         .await?;
 
     info!("Deleted pipeline {pipeline_name} (tenant:{})", *tenant_id);
     Ok(HttpResponse::Ok().finish())
 }
+
+#[cfg(test)]
+mod test {
+    use super::pipeline_action;
+    use crate::{
+        api::ServerState,
+        auth::{tag_with_default_tenant_id, TenantRecord},
+        config::{ApiServerConfig, AuthProviderType, TenantQuotas},
+        db::{audit::Role, storage::Storage, PipelineStatus},
+    };
+    use actix_web::{dev::Service, http::StatusCode, test, web::Data as WebData, App};
+    use pipeline_types::config::RuntimeConfig;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use uuid::Uuid;
+
+    /// Starting a pipeline that can never fit in a quota is rejected with
+    /// status 403, and starting one that doesn't fit next to the running
+    /// pipelines is rejected with status 429.
+    #[actix_web::test]
+    async fn pipeline_quotas() {
+        let (db, _temp) = crate::db::test::setup_pg().await;
+        let tenant_id = TenantRecord::default().id;
+        for (name, workers) in [("running", 1), ("large", 4), ("small", 1)] {
+            db.new_pipeline(
+                tenant_id,
+                Uuid::now_v7(),
+                &None,
+                name,
+                "",
+                &RuntimeConfig::from_yaml(&format!("workers: {workers}")),
+                &Some(vec![]),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        let config = ApiServerConfig {
+            port: 0,
+            bind_address: "0.0.0.0".to_owned(),
+            api_server_working_directory: "".to_owned(),
+            auth_provider: AuthProviderType::None,
+            dev_mode: false,
+            dump_openapi: false,
+            config_file: None,
+            allowed_origins: None,
+            demos: vec![],
+            runner_hostname_port: "127.0.0.1:8089".to_owned(),
+            dry_run_allowed_hosts: vec![],
+            webhook_allowed_hosts: vec![],
+            default_role: Role::Viewer,
+            admin_users: vec![],
+            quotas: TenantQuotas {
+                running_pipelines: Some(1),
+                workers: Some(2),
+                ..Default::default()
+            },
+            tenant_quotas: Default::default(),
+        };
+        let db = Arc::new(Mutex::new(db));
+        let state = ServerState::new(config, db.clone()).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(WebData::new(state))
+                .wrap_fn(|req, srv| srv.call(tag_with_default_tenant_id(req)))
+                .service(pipeline_action),
+        )
+        .await;
+        let start = |name: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/pipelines/{name}/start"))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, start("large")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], "QuotaViolation");
+
+        {
+            let db = db.lock().await;
+            let running = db.get_pipeline_by_name(tenant_id, "running").await.unwrap();
+            db.set_pipeline_desired_status(
+                tenant_id,
+                running.descriptor.pipeline_id,
+                PipelineStatus::Running,
+                "user:default",
+            )
+            .await
+            .unwrap();
+        }
+
+        let resp = test::call_service(&app, start("small")).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], "QuotaExceeded");
+        assert_eq!(body["details"]["quota"], "running_pipelines");
+    }
+}
@@ -0,0 +1,196 @@
+/// History and live stream of pipeline status transitions.
+use super::{examples, parse_string_param, ManagerError, ServerState};
+use crate::{
+    auth::TenantId,
+    db::{pipeline_event::PipelineEvent, storage::Storage, ProjectDB},
+    db_notifier::{self, DbNotification, Operation},
+};
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective},
+    web::{self, Bytes, Data as WebData, ReqData},
+    Error as ActixError, HttpRequest, HttpResponse,
+};
+use futures_util::stream;
+use log::warn;
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Mutex,
+};
+use utoipa::IntoParams;
+
+/// Number of pipeline events buffered for each client following the events
+/// before the client starts missing events.
+const FOLLOW_BUFFER_EVENTS: usize = 1024;
+
+/// How often to send a comment to clients following the events, so that
+/// idle connections are not closed by proxies.
+const KEEP_ALIVE_PERIOD: Duration = Duration::from_secs(15);
+
+/// Forwards the pipeline events of all tenants to the clients following
+/// them.
+pub(crate) type PipelineEventSender = broadcast::Sender<(TenantId, PipelineEvent)>;
+
+pub(crate) fn pipeline_event_sender() -> PipelineEventSender {
+    broadcast::channel(FOLLOW_BUFFER_EVENTS).0
+}
+
+/// Forwards the pipeline events recorded in the database to `sender` as the
+/// database notifies them.
+pub(crate) async fn forward_pipeline_events(
+    db: Arc<Mutex<ProjectDB>>,
+    sender: PipelineEventSender,
+) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(db_notifier::listen(db.clone(), tx));
+    while let Some(notification) = rx.recv().await {
+        let DbNotification::PipelineEvent(Operation::Add, tenant_id, pipeline_event_id) =
+            notification
+        else {
+            continue;
+        };
+        // Nobody is following the events.
+        if sender.receiver_count() == 0 {
+            continue;
+        }
+        match db
+            .lock()
+            .await
+            .get_pipeline_event(tenant_id, pipeline_event_id)
+            .await
+        {
+            Ok(event) => {
+                let _ = sender.send((tenant_id, event));
+            }
+            // The pipeline may have been deleted in the meantime.
+            Err(e) => warn!("Could not retrieve pipeline event {pipeline_event_id}: {e}"),
+        }
+    }
+}
+
+/// Query parameters to list pipeline events.
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct PipelineEventsQuery {
+    /// Maximum number of events to return.  The most recent events are
+    /// returned first.
+    limit: Option<u32>,
+}
+
+/// Retrieve the status transitions of a pipeline, most recent first.
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Pipeline events retrieved successfully."
+            , body = [PipelineEvent]),
+        (status = NOT_FOUND
+            , description = "Specified pipeline name does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline())),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        PipelineEventsQuery,
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[get("/pipelines/{pipeline_name}/events")]
+pub(crate) async fn list_pipeline_events(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+    query: web::Query<PipelineEventsQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let db = state.db.lock().await;
+    let pipeline_id = db
+        .get_pipeline_runtime_state_by_name(*tenant_id, &pipeline_name)
+        .await?
+        .pipeline_id;
+    let events = db
+        .list_pipeline_events(*tenant_id, pipeline_id, query.limit)
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&events))
+}
+
+/// Follow the status transitions of a pipeline.
+///
+/// Returns a stream of Server-Sent Events that continues until the client
+/// disconnects.  Every status transition of the pipeline is sent as a
+/// `status` event whose data is the JSON-encoded `PipelineEvent`.  A
+/// `lagged` event, whose data is the number of skipped transitions, is sent
+/// if the client does not keep up.
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Stream of pipeline events."
+            , content_type = "text/event-stream"
+            , body = String),
+        (status = NOT_FOUND
+            , description = "Specified pipeline name does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline())),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[get("/pipelines/{pipeline_name}/events/stream")]
+pub(crate) async fn stream_pipeline_events(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let pipeline_id = state
+        .db
+        .lock()
+        .await
+        .get_pipeline_runtime_state_by_name(*tenant_id, &pipeline_name)
+        .await?
+        .pipeline_id;
+    let tenant_id = *tenant_id;
+    let receiver = state.pipeline_events.subscribe();
+    let keep_alive = tokio::time::interval(KEEP_ALIVE_PERIOD);
+
+    let events = stream::unfold(
+        (receiver, keep_alive),
+        move |(mut receiver, mut keep_alive)| async move {
+            let message = loop {
+                tokio::select! {
+                    event = receiver.recv() => match event {
+                        Ok((tid, event)) if tid == tenant_id && event.pipeline_id == pipeline_id => {
+                            break format!(
+                                "id: {}\nevent: status\ndata: {}\n\n",
+                                event.pipeline_event_id,
+                                serde_json::to_string(&event).unwrap()
+                            );
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(skipped)) => {
+                            break format!("event: lagged\ndata: {skipped}\n\n");
+                        }
+                        Err(RecvError::Closed) => return None,
+                    },
+                    _ = keep_alive.tick() => break ": keep-alive\n\n".to_string(),
+                }
+            };
+            Some((
+                Ok::<_, ActixError>(Bytes::from(message)),
+                (receiver, keep_alive),
+            ))
+        },
+    );
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events))
+}
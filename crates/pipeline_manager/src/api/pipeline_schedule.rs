@@ -0,0 +1,208 @@
+/// API to schedule the actions of pipelines
+use super::{examples, parse_string_param, ManagerError, ServerState};
+use crate::{
+    auth::TenantId,
+    db::{pipeline_schedule::PipelineScheduleConfig, storage::Storage},
+    schedules::set_next_runs,
+};
+use actix_web::{
+    delete, get,
+    http::header::{CacheControl, CacheDirective},
+    put,
+    web::{self, Data as WebData, ReqData},
+    HttpRequest, HttpResponse,
+};
+use log::info;
+use uuid::Uuid;
+
+/// List the schedules of a pipeline
+///
+/// Every schedule reports its next runs in its time zone, along with the
+/// time and error of its last run.
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Pipeline schedules retrieved successfully."
+            , body = [PipelineScheduleDescr]),
+        (status = NOT_FOUND
+            , description = "Specified pipeline name does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_name())),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[get("/pipelines/{pipeline_name}/schedules")]
+pub(crate) async fn list_pipeline_schedules(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let mut schedules = state
+        .db
+        .lock()
+        .await
+        .list_pipeline_schedules(*tenant_id, &pipeline_name)
+        .await?;
+    schedules.iter_mut().for_each(set_next_runs);
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&schedules))
+}
+
+/// Retrieve a schedule of a pipeline
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Pipeline schedule retrieved successfully."
+            , body = PipelineScheduleDescr),
+        (status = NOT_FOUND
+            , description = "Specified pipeline or schedule does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline_schedule())),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        ("schedule_name" = String, Path, description = "Name of the schedule, unique per pipeline"),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[get("/pipelines/{pipeline_name}/schedules/{schedule_name}")]
+pub(crate) async fn get_pipeline_schedule(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let schedule_name = parse_string_param(&req, "schedule_name")?;
+    let mut schedule = state
+        .db
+        .lock()
+        .await
+        .get_pipeline_schedule(*tenant_id, &pipeline_name, &schedule_name)
+        .await?;
+    set_next_runs(&mut schedule);
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&schedule))
+}
+
+/// Create or replace a schedule of a pipeline
+///
+/// The schedule starts, pauses or shuts down the pipeline at the times of a
+/// crontab(5) expression, evaluated in the time zone of the schedule.  The
+/// actions are applied by the manager just like the corresponding pipeline
+/// actions, including quota checks, and recorded in the audit log as
+/// performed by `schedule:<schedule_name>`.  Replacing a schedule recomputes
+/// its next run.
+#[utoipa::path(
+    request_body = PipelineScheduleConfig,
+    responses(
+        (status = CREATED
+            , description = "Pipeline schedule created successfully."
+            , body = PipelineScheduleDescr),
+        (status = OK
+            , description = "Pipeline schedule replaced successfully."
+            , body = PipelineScheduleDescr),
+        (status = BAD_REQUEST
+            , description = "Invalid cron expression or time zone."
+            , body = ErrorResponse
+            , example = json!(examples::invalid_pipeline_schedule())),
+        (status = NOT_FOUND
+            , description = "Specified pipeline name does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_name())),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        ("schedule_name" = String, Path, description = "Name of the schedule, unique per pipeline"),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[put("/pipelines/{pipeline_name}/schedules/{schedule_name}")]
+pub(crate) async fn create_or_replace_pipeline_schedule(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+    body: web::Json<PipelineScheduleConfig>,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let schedule_name = parse_string_param(&req, "schedule_name")?;
+    let db = state.db.lock().await;
+    let (created, _) = db
+        .create_or_replace_pipeline_schedule(
+            *tenant_id,
+            Uuid::now_v7(),
+            &pipeline_name,
+            &schedule_name,
+            &body,
+        )
+        .await?;
+    let mut schedule = db
+        .get_pipeline_schedule(*tenant_id, &pipeline_name, &schedule_name)
+        .await?;
+    set_next_runs(&mut schedule);
+    let mut response = if created {
+        info!(
+            "Created schedule {schedule_name} of pipeline {pipeline_name} (tenant:{})",
+            *tenant_id
+        );
+        HttpResponse::Created()
+    } else {
+        info!(
+            "Replaced schedule {schedule_name} of pipeline {pipeline_name} (tenant:{})",
+            *tenant_id
+        );
+        HttpResponse::Ok()
+    };
+    Ok(response
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&schedule))
+}
+
+/// Delete a schedule of a pipeline
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Pipeline schedule deleted successfully"),
+        (status = NOT_FOUND
+            , description = "Specified pipeline or schedule does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_pipeline_schedule())),
+    ),
+    params(
+        ("pipeline_name" = String, Path, description = "Unique pipeline name"),
+        ("schedule_name" = String, Path, description = "Name of the schedule, unique per pipeline"),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Pipelines"
+)]
+#[delete("/pipelines/{pipeline_name}/schedules/{schedule_name}")]
+pub(crate) async fn delete_pipeline_schedule(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let pipeline_name = parse_string_param(&req, "pipeline_name")?;
+    let schedule_name = parse_string_param(&req, "schedule_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .delete_pipeline_schedule(*tenant_id, &pipeline_name, &schedule_name)
+        .await?;
+    info!(
+        "Deleted schedule {schedule_name} of pipeline {pipeline_name} (tenant:{})",
+        *tenant_id
+    );
+    Ok(HttpResponse::Ok().finish())
+}
@@ -7,14 +7,18 @@ use actix_web::{
     HttpRequest, HttpResponse,
 };
 use log::info;
+use pipeline_types::program_schema::{ProgramSchema, Relation};
 use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::{collections::BTreeMap, time::Duration};
 use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::{examples, parse_string_param},
     auth::TenantId,
-    compiler::ProgramConfig,
-    db::{storage::Storage, DBError, ProgramId, Version},
+    compiler::{ProgramConfig, ProgramStatus},
+    db::{storage::Storage, DBError, ProgramDescr, ProgramId, ProjectDB, Version},
+    quotas::{check_compilation_quotas, tenant_quotas},
 };
 
 use super::{ManagerError, ServerState};
@@ -37,6 +41,14 @@ pub struct WithCodeQuery {
     with_code: Option<bool>,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ProgramVersionDiffQuery {
+    /// Version to compare from.
+    from: Version,
+    /// Version to compare to.
+    to: Version,
+}
+
 /// Request to queue a program for compilation.
 #[derive(Deserialize, ToSchema)]
 #[allow(dead_code)] // Reason: this request type is deprecated
@@ -100,6 +112,45 @@ pub(crate) struct UpdateProgramResponse {
     version: Version,
 }
 
+/// Request to roll a program back to an earlier version.
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct RollbackProgramRequest {
+    /// Version whose code and configuration are restored.
+    version: Version,
+    /// A version guard: roll back the program only if the current program
+    /// version matches the supplied value.
+    guard: Option<Version>,
+}
+
+/// Differences between two versions of a program.
+#[derive(Debug, Serialize, ToSchema, PartialEq, Eq)]
+pub(crate) struct ProgramVersionDiff {
+    /// Version compared from.
+    from: Version,
+    /// Version compared to.
+    to: Version,
+    /// Line-by-line diff of the SQL code. Every line is prefixed with `-`
+    /// if it was removed, `+` if it was added, or a space if it is
+    /// unchanged.
+    code: String,
+    /// Whether the program configuration changed.
+    config_changed: bool,
+    /// Differences between the schemas of the two versions. Absent unless
+    /// both versions compiled successfully.
+    schema: Option<ProgramSchemaDiff>,
+}
+
+/// Tables and views that differ between two versions of a program.
+#[derive(Debug, Serialize, ToSchema, PartialEq, Eq)]
+pub(crate) struct ProgramSchemaDiff {
+    /// Relations that only exist in the newer version.
+    added: Vec<String>,
+    /// Relations that only exist in the older version.
+    removed: Vec<String>,
+    /// Relations that exist in both versions but whose definitions differ.
+    changed: Vec<String>,
+}
+
 /// Request to create or replace a program.
 #[derive(Debug, Deserialize, ToSchema)]
 pub(crate) struct CreateOrReplaceProgramRequest {
@@ -153,32 +204,21 @@ pub(crate) async fn get_programs(
     with_code: web::Query<WithCodeQuery>,
 ) -> Result<HttpResponse, ManagerError> {
     let with_code = with_code.with_code.unwrap_or(false);
-    let programs = if let Some(id) = req.id {
+    let db = state.db.lock().await;
+    let mut programs = if let Some(id) = req.id {
         vec![
-            state
-                .db
-                .lock()
-                .await
-                .get_program_by_id(*tenant_id, ProgramId(id), with_code)
+            db.get_program_by_id(*tenant_id, ProgramId(id), with_code)
                 .await?,
         ]
     } else if let Some(name) = req.name.clone() {
         vec![
-            state
-                .db
-                .lock()
-                .await
-                .get_program_by_name(*tenant_id, &name, with_code, None)
+            db.get_program_by_name(*tenant_id, &name, with_code, None)
                 .await?,
         ]
     } else {
-        state
-            .db
-            .lock()
-            .await
-            .list_programs(*tenant_id, with_code)
-            .await?
+        db.list_programs(*tenant_id, with_code).await?
     };
+    set_queue_positions(&db, &mut programs).await?;
     Ok(HttpResponse::Ok()
         .insert_header(CacheControl(vec![CacheDirective::NoCache]))
         .json(&programs))
@@ -210,18 +250,41 @@ async fn get_program(
 ) -> Result<HttpResponse, ManagerError> {
     let program_name = parse_string_param(&req, "program_name")?;
     let with_code = query.with_code.unwrap_or(false);
-    let program = state
-        .db
-        .lock()
-        .await
+    let db = state.db.lock().await;
+    let mut program = db
         .get_program_by_name(*tenant_id, &program_name, with_code, None)
         .await?;
+    set_queue_positions(&db, std::slice::from_mut(&mut program)).await?;
 
     Ok(HttpResponse::Ok()
         .insert_header(CacheControl(vec![CacheDirective::NoCache]))
         .json(&program))
 }
 
+/// Report the pending programs in `programs` as `Queued` at their position
+/// in the compilation queue.
+async fn set_queue_positions(db: &ProjectDB, programs: &mut [ProgramDescr]) -> Result<(), DBError> {
+    if !programs
+        .iter()
+        .any(|program| program.status == ProgramStatus::Pending)
+    {
+        return Ok(());
+    }
+    let queue = db.compilation_queue().await?;
+    for program in programs
+        .iter_mut()
+        .filter(|program| program.status == ProgramStatus::Pending)
+    {
+        if let Some(position) = queue
+            .iter()
+            .position(|(_, program_id, _)| *program_id == program.program_id)
+        {
+            program.status = ProgramStatus::Queued(position as u64 + 1);
+        }
+    }
+    Ok(())
+}
+
 /// Create a new program.
 #[utoipa::path(
     request_body = NewProgramRequest,
@@ -231,6 +294,9 @@ async fn get_program(
             , description = "A program with this name already exists in the database"
             , body = ErrorResponse
             , example = json!(examples::duplicate_name())),
+        (status = TOO_MANY_REQUESTS
+            , description = "Compiling the program would exceed the compilation quota of the tenant"
+            , body = ErrorResponse),
     ),
     context_path = "/v0",
     security(("JSON web token (JWT) or API key" = [])),
@@ -241,11 +307,11 @@ async fn new_program(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
     request: web::Json<NewProgramRequest>,
-) -> Result<HttpResponse, DBError> {
-    let (program_id, version) = state
-        .db
-        .lock()
-        .await
+) -> Result<HttpResponse, ManagerError> {
+    let db = state.db.lock().await;
+    let quotas = tenant_quotas(&db, &state._config, *tenant_id).await?;
+    check_compilation_quotas(&db, &quotas, *tenant_id, &[request.name.as_str()]).await?;
+    let (program_id, version) = db
         .new_program(
             *tenant_id,
             Uuid::now_v7(),
@@ -289,6 +355,9 @@ async fn new_program(
             , description = "A program with this name already exists in the database"
             , body = ErrorResponse
             , example = json!(examples::duplicate_name())),
+        (status = TOO_MANY_REQUESTS
+            , description = "Compiling the program would exceed the compilation quota of the tenant"
+            , body = ErrorResponse),
     ),
     params(
         ("program_name" = String, Path, description = "Unique program name")
@@ -306,6 +375,10 @@ async fn update_program(
 ) -> Result<HttpResponse, ManagerError> {
     let program_name = parse_string_param(&request, "program_name")?;
     let db = state.db.lock().await;
+    if body.code.is_some() || body.config.is_some() {
+        let quotas = tenant_quotas(&db, &state._config, *tenant_id).await?;
+        check_compilation_quotas(&db, &quotas, *tenant_id, &[program_name.as_str()]).await?;
+    }
     let version = db
         .update_program_by_name(
             *tenant_id,
@@ -337,6 +410,9 @@ async fn update_program(
             , description = "A program with this name already exists in the database"
             , body = ErrorResponse
             , example = json!(examples::duplicate_name())),
+        (status = TOO_MANY_REQUESTS
+            , description = "Compiling the program would exceed the compilation quota of the tenant"
+            , body = ErrorResponse),
     ),
     params(
         ("program_name" = String, Path, description = "Unique program name")
@@ -353,10 +429,10 @@ async fn create_or_replace_program(
     body: web::Json<CreateOrReplaceProgramRequest>,
 ) -> Result<HttpResponse, ManagerError> {
     let program_name = parse_string_param(&request, "program_name")?;
-    let (created, program_id, version) = state
-        .db
-        .lock()
-        .await
+    let db = state.db.lock().await;
+    let quotas = tenant_quotas(&db, &state._config, *tenant_id).await?;
+    check_compilation_quotas(&db, &quotas, *tenant_id, &[program_name.as_str()]).await?;
+    let (created, program_id, version) = db
         .create_or_replace_program(
             *tenant_id,
             &program_name,
@@ -425,6 +501,251 @@ async fn compile_program(
     Ok(HttpResponse::Accepted().finish())
 }
 
+/// Cancel the compilation of a program.
+///
+/// Stops the compiler if it is compiling the program, or removes the program
+/// from the compilation queue if it is waiting to be compiled, and sets the
+/// status of the program to `Cancelled`.  The program is compiled again when
+/// its code or configuration changes.
+#[utoipa::path(
+    responses(
+        (status = ACCEPTED, description = "Compilation cancelled"),
+        (status = NOT_FOUND
+            , description = "Specified program name does not exist"
+            , body = ErrorResponse
+            , example = json!(examples::unknown_name())),
+        (status = BAD_REQUEST
+            , description = "The program is not pending or compiling"
+            , body = ErrorResponse
+            , example = json!(examples::program_not_compiling())),
+    ),
+    params(
+        ("program_name" = String, Path, description = "Unique program name")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Programs"
+)]
+#[post("/programs/{program_name}/compile/cancel")]
+async fn cancel_program_compilation(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let program_name = parse_string_param(&req, "program_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .cancel_program_compilation(*tenant_id, &program_name)
+        .await?;
+    info!(
+        "Cancelled the compilation of program {program_name} (tenant: {})",
+        *tenant_id
+    );
+    Ok(HttpResponse::Accepted().finish())
+}
+
+/// List the versions of a program, most recent first.
+///
+/// A new version is recorded every time the program's code or configuration
+/// changes. The schema of a version is available once it compiled
+/// successfully.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Program versions retrieved successfully", body = [ProgramVersionDescr]),
+        (status = NOT_FOUND
+            , description = "Specified program name does not exist"
+            , body = ErrorResponse
+            , example = json!(examples::unknown_name())),
+    ),
+    params(
+        ("program_name" = String, Path, description = "Unique program name"),
+        WithCodeQuery
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Programs"
+)]
+#[get("/programs/{program_name}/versions")]
+async fn list_program_versions(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+    query: web::Query<WithCodeQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let program_name = parse_string_param(&req, "program_name")?;
+    let with_code = query.with_code.unwrap_or(false);
+    let versions = state
+        .db
+        .lock()
+        .await
+        .list_program_versions(*tenant_id, &program_name, with_code)
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&versions))
+}
+
+/// Fetch a version of a program.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Program version retrieved successfully", body = ProgramVersionDescr),
+        (status = BAD_REQUEST
+            , description = "Specified version is not a valid version number"
+            , body = ErrorResponse
+            , example = json!(examples::invalid_version_param())),
+        (status = NOT_FOUND
+            , description = "Specified program name or version does not exist"
+            , body = ErrorResponse
+            , examples(
+                ("Unknown program name" = (value = json!(examples::unknown_name()))),
+                ("Unknown program version" = (value = json!(examples::unknown_program_version())))
+            ),
+        ),
+    ),
+    params(
+        ("program_name" = String, Path, description = "Unique program name"),
+        ("version" = i64, Path, description = "Program version")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Programs"
+)]
+#[get("/programs/{program_name}/versions/{version}")]
+async fn get_program_version(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let program_name = parse_string_param(&req, "program_name")?;
+    let version = parse_version_param(&req, "version")?;
+    let version = state
+        .db
+        .lock()
+        .await
+        .get_program_version(*tenant_id, &program_name, version, None)
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&version))
+}
+
+/// Compare two versions of a program.
+///
+/// Reports the changes to the SQL code, whether the configuration changed,
+/// and the tables and views that were added, removed or changed.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Program versions compared successfully", body = ProgramVersionDiff),
+        (status = NOT_FOUND
+            , description = "Specified program name or version does not exist"
+            , body = ErrorResponse
+            , examples(
+                ("Unknown program name" = (value = json!(examples::unknown_name()))),
+                ("Unknown program version" = (value = json!(examples::unknown_program_version())))
+            ),
+        ),
+    ),
+    params(
+        ("program_name" = String, Path, description = "Unique program name"),
+        ProgramVersionDiffQuery
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Programs"
+)]
+#[get("/programs/{program_name}/diff")]
+async fn diff_program_versions(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+    query: web::Query<ProgramVersionDiffQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let program_name = parse_string_param(&req, "program_name")?;
+    let db = state.db.lock().await;
+    let from = db
+        .get_program_version(*tenant_id, &program_name, query.from, None)
+        .await?;
+    let to = db
+        .get_program_version(*tenant_id, &program_name, query.to, None)
+        .await?;
+    drop(db);
+    let diff = ProgramVersionDiff {
+        from: from.version,
+        to: to.version,
+        code: diff_lines(
+            from.code.as_deref().unwrap_or_default(),
+            to.code.as_deref().unwrap_or_default(),
+        ),
+        config_changed: from.config != to.config,
+        schema: match (&from.schema, &to.schema) {
+            (Some(from), Some(to)) => Some(diff_schemas(from, to)),
+            _ => None,
+        },
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&diff))
+}
+
+/// Roll a program back to an earlier version.
+///
+/// Restores the code and configuration of the specified version as a new
+/// version of the program, which is then recompiled. Rolling back to a
+/// version whose code and configuration equal the current ones leaves the
+/// program unchanged.
+#[utoipa::path(
+    request_body = RollbackProgramRequest,
+    responses(
+        (status = OK, description = "Program rolled back successfully", body = UpdateProgramResponse),
+        (status = NOT_FOUND
+            , description = "Specified program name or version does not exist"
+            , body = ErrorResponse
+            , examples(
+                ("Unknown program name" = (value = json!(examples::unknown_name()))),
+                ("Unknown program version" = (value = json!(examples::unknown_program_version())))
+            ),
+        ),
+        (status = CONFLICT
+            , description = "Program version specified in the guard doesn't match the latest program version in the database"
+            , body = ErrorResponse
+            , example = json!(examples::outdated_program_version())),
+        (status = TOO_MANY_REQUESTS
+            , description = "Compiling the program would exceed the compilation quota of the tenant"
+            , body = ErrorResponse),
+    ),
+    params(
+        ("program_name" = String, Path, description = "Unique program name")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Programs"
+)]
+#[post("/programs/{program_name}/rollback")]
+async fn rollback_program(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    request: HttpRequest,
+    body: web::Json<RollbackProgramRequest>,
+) -> Result<HttpResponse, ManagerError> {
+    let program_name = parse_string_param(&request, "program_name")?;
+    let db = state.db.lock().await;
+    let quotas = tenant_quotas(&db, &state._config, *tenant_id).await?;
+    check_compilation_quotas(&db, &quotas, *tenant_id, &[program_name.as_str()]).await?;
+    let version = db
+        .rollback_program(*tenant_id, &program_name, body.version, body.guard)
+        .await?;
+
+    info!(
+        "Rolled back program {program_name} to version {} as version {version} (tenant: {})",
+        body.version, *tenant_id
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&UpdateProgramResponse { version }))
+}
+
 /// Delete a program.
 ///
 /// Deletion fails if there is at least one pipeline associated with the
@@ -458,10 +779,226 @@ async fn delete_program(
     let program_name = parse_string_param(&req, "program_name")?;
     let db = state.db.lock().await;
     let resp = db
-        .delete_program(*tenant_id, &program_name)
+        .delete_program(*tenant_id, &program_name, None)
         .await
         .map(|_| HttpResponse::Ok().finish())?;
 
     info!("Deleted program {program_name} (tenant: {})", *tenant_id);
     Ok(resp)
 }
+
+fn parse_version_param(
+    req: &HttpRequest,
+    param_name: &'static str,
+) -> Result<Version, ManagerError> {
+    let value = parse_string_param(req, param_name)?;
+    value
+        .parse::<i64>()
+        .map(Version)
+        .map_err(|e| ManagerError::InvalidVersionParam {
+            value,
+            error: e.to_string(),
+        })
+}
+
+/// Maximal time spent looking for a minimal diff of two program versions,
+/// after which a larger diff is returned.
+const DIFF_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Line-by-line diff of `from` and `to`.
+fn diff_lines(from: &str, to: &str) -> String {
+    let mut diff = String::new();
+    for change in TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(from, to)
+        .iter_all_changes()
+    {
+        diff.push(match change.tag() {
+            ChangeTag::Equal => ' ',
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+        });
+        diff.push_str(change.value().trim_end_matches(&['\r', '\n'][..]));
+        diff.push('\n');
+    }
+    diff
+}
+
+fn diff_schemas(from: &ProgramSchema, to: &ProgramSchema) -> ProgramSchemaDiff {
+    let relations = |schema: &ProgramSchema| -> BTreeMap<String, Relation> {
+        schema
+            .inputs
+            .iter()
+            .chain(schema.outputs.iter())
+            .map(|relation| (relation.name(), relation.clone()))
+            .collect()
+    };
+    let from = relations(from);
+    let to = relations(to);
+    ProgramSchemaDiff {
+        added: to
+            .keys()
+            .filter(|name| !from.contains_key(*name))
+            .cloned()
+            .collect(),
+        removed: from
+            .keys()
+            .filter(|name| !to.contains_key(*name))
+            .cloned()
+            .collect(),
+        changed: from
+            .iter()
+            .filter(|(name, relation)| to.get(*name).is_some_and(|r| r != *relation))
+            .map(|(name, _)| name.clone())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cancel_program_compilation, diff_lines, diff_schemas, get_program, ProgramSchemaDiff,
+    };
+    use crate::{
+        api::ServerState,
+        auth::{tag_with_default_tenant_id, TenantRecord},
+        compiler::{ProgramConfig, ProgramStatus},
+        config::{ApiServerConfig, AuthProviderType},
+        db::{audit::Role, storage::Storage},
+    };
+    use actix_web::{dev::Service, http::StatusCode, test, web::Data as WebData, App};
+    use pipeline_types::program_schema::{ColumnType, Field, ProgramSchema, Relation, SqlType};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use uuid::Uuid;
+
+    #[test]
+    fn program_version_diff() {
+        assert_eq!(diff_lines("", ""), "");
+        assert_eq!(
+            diff_lines(
+                "CREATE TABLE t1(c1 INT);\nCREATE VIEW v1 AS SELECT * FROM t1;",
+                "CREATE TABLE t1(c1 INT);\nCREATE TABLE t2(c2 INT);\nCREATE VIEW v2 AS SELECT * FROM t2;"
+            ),
+            " CREATE TABLE t1(c1 INT);\n-CREATE VIEW v1 AS SELECT * FROM t1;\n+CREATE TABLE t2(c2 INT);\n+CREATE VIEW v2 AS SELECT * FROM t2;\n"
+        );
+        assert_eq!(diff_lines("a\nb\nc", "a\nc"), " a\n-b\n c\n");
+
+        let int_field = |nullable| Field {
+            name: "c1".to_string(),
+            case_sensitive: false,
+            columntype: ColumnType {
+                typ: SqlType::Int,
+                nullable,
+                precision: None,
+                scale: None,
+                component: None,
+            },
+        };
+        let from = ProgramSchema {
+            inputs: vec![
+                Relation::new("t1", false, vec![int_field(false)]),
+                Relation::new("t2", false, vec![]),
+            ],
+            outputs: vec![Relation::new("v1", false, vec![])],
+        };
+        let to = ProgramSchema {
+            inputs: vec![
+                Relation::new("T1", false, vec![int_field(true)]),
+                Relation::new("t3", false, vec![]),
+            ],
+            outputs: vec![Relation::new("v1", false, vec![])],
+        };
+        assert_eq!(
+            diff_schemas(&from, &to),
+            ProgramSchemaDiff {
+                added: vec!["t3".to_string()],
+                removed: vec!["t2".to_string()],
+                changed: vec!["t1".to_string()],
+            }
+        );
+    }
+
+    /// A pending program is reported as `Queued` at its position in the
+    /// queue.  Cancelling its compilation sets the program status to
+    /// `Cancelled`; cancelling it again or cancelling an unknown program fails.
+    #[actix_web::test]
+    async fn cancel_compilation() {
+        let (db, _temp) = crate::db::test::setup_pg().await;
+        let tenant_id = TenantRecord::default().id;
+        db.new_program(
+            tenant_id,
+            Uuid::now_v7(),
+            "p",
+            "",
+            "",
+            &ProgramConfig::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        let config = ApiServerConfig {
+            port: 0,
+            bind_address: "0.0.0.0".to_owned(),
+            api_server_working_directory: "".to_owned(),
+            auth_provider: AuthProviderType::None,
+            dev_mode: false,
+            dump_openapi: false,
+            config_file: None,
+            allowed_origins: None,
+            demos: vec![],
+            runner_hostname_port: "127.0.0.1:8089".to_owned(),
+            dry_run_allowed_hosts: vec![],
+            webhook_allowed_hosts: vec![],
+            default_role: Role::Viewer,
+            admin_users: vec![],
+            quotas: Default::default(),
+            tenant_quotas: Default::default(),
+        };
+        let db = Arc::new(Mutex::new(db));
+        let state = ServerState::new(config, db.clone()).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(WebData::new(state))
+                .wrap_fn(|req, srv| srv.call(tag_with_default_tenant_id(req)))
+                .service(get_program)
+                .service(cancel_program_compilation),
+        )
+        .await;
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/programs/p").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], serde_json::json!({ "Queued": 1 }));
+
+        let cancel = |name: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/programs/{name}/compile/cancel"))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, cancel("p")).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let program = db
+            .lock()
+            .await
+            .get_program_by_name(tenant_id, "p", false, None)
+            .await
+            .unwrap();
+        assert_eq!(program.status, ProgramStatus::Cancelled);
+
+        let resp = test::call_service(&app, cancel("p")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], "ProgramNotCompiling");
+
+        let resp = test::call_service(&app, cancel("unknown")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error_code"], "UnknownProgramName");
+    }
+}
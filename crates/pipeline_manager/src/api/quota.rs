@@ -0,0 +1,39 @@
+/// API to inspect the quotas of a tenant
+use super::{ManagerError, ServerState};
+use crate::{
+    auth::TenantId,
+    quotas::{quota_usage, tenant_quotas, QuotasResponse},
+};
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective},
+    web::{Data as WebData, ReqData},
+    HttpResponse,
+};
+
+/// Retrieve the quotas of the tenant and their current usage.
+///
+/// Quotas that are not set are unlimited.  Requests to start a pipeline or
+/// to compile a program that exceed a quota are rejected with status 429 if
+/// they can be admitted once other pipelines shut down or compilations
+/// finish, and with status 403 otherwise.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Quotas retrieved successfully", body = QuotasResponse),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Manager"
+)]
+#[get("/quotas")]
+pub(crate) async fn get_quotas(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+) -> Result<HttpResponse, ManagerError> {
+    let db = state.db.lock().await;
+    let quotas = tenant_quotas(&db, &state._config, *tenant_id).await?;
+    let usage = quota_usage(&db, *tenant_id).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&QuotasResponse { quotas, usage }))
+}
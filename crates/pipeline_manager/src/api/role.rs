@@ -0,0 +1,117 @@
+/// API to assign roles to the users of a tenant
+use super::{ManagerError, ServerState};
+use crate::{
+    api::{examples, parse_string_param},
+    auth::TenantId,
+    db::{audit::Role, storage::Storage},
+};
+use actix_web::{
+    delete, get,
+    http::header::{CacheControl, CacheDirective},
+    put,
+    web::{self, Data as WebData, ReqData},
+    HttpRequest, HttpResponse,
+};
+use log::info;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// Request to assign a role to a user.
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct SetUserRoleRequest {
+    /// Role assigned to the user.
+    role: Role,
+}
+
+/// List the roles assigned to users
+///
+/// Users without an assigned role have the `admin` role.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "User roles retrieved successfully", body = [UserRole]),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Roles"
+)]
+#[get("/roles")]
+pub(crate) async fn list_user_roles(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+) -> Result<HttpResponse, ManagerError> {
+    let roles = state.db.lock().await.list_user_roles(*tenant_id).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&roles))
+}
+
+/// Assign a role to a user
+///
+/// Replaces the role previously assigned to the user, if any.
+#[utoipa::path(
+    request_body = SetUserRoleRequest,
+    responses(
+        (status = OK, description = "Role assigned successfully"),
+    ),
+    params(
+        ("user_name" = String, Path, description = "Name of the user, i.e., the subject of the user's token")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Roles"
+)]
+#[put("/roles/{user_name}")]
+pub(crate) async fn set_user_role(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    request: HttpRequest,
+    body: web::Json<SetUserRoleRequest>,
+) -> Result<HttpResponse, ManagerError> {
+    let user_name = parse_string_param(&request, "user_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .set_user_role(*tenant_id, &user_name, body.role)
+        .await?;
+    info!(
+        "Assigned role '{}' to user {user_name} (tenant:{})",
+        body.role, *tenant_id
+    );
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Remove the role assigned to a user
+///
+/// The user reverts to the default `admin` role.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Role removed successfully"),
+        (status = NOT_FOUND
+            , description = "The user has not been assigned a role."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_user_role())),
+    ),
+    params(
+        ("user_name" = String, Path, description = "Name of the user, i.e., the subject of the user's token")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Roles"
+)]
+#[delete("/roles/{user_name}")]
+pub(crate) async fn delete_user_role(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let user_name = parse_string_param(&request, "user_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .delete_user_role(*tenant_id, &user_name)
+        .await?;
+    info!("Removed role of user {user_name} (tenant:{})", *tenant_id);
+    Ok(HttpResponse::Ok().finish())
+}
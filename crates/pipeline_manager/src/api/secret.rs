@@ -0,0 +1,122 @@
+/// API to manage the secrets stored in the database
+use super::{ManagerError, ServerState};
+use crate::{
+    api::{examples, parse_string_param},
+    auth::TenantId,
+    db::storage::Storage,
+};
+use actix_web::{
+    delete, get,
+    http::header::{CacheControl, CacheDirective},
+    put,
+    web::{self, Data as WebData, ReqData},
+    HttpRequest, HttpResponse,
+};
+use log::info;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// Request to set the value of a secret.
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SetSecretRequest {
+    /// Value of the secret.
+    value: String,
+}
+
+/// List the secrets stored in the database
+///
+/// Only the names of the secrets are returned, never their values.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Secrets retrieved successfully", body = [SecretDescr]),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Secrets"
+)]
+#[get("/secrets")]
+pub(crate) async fn list_secrets(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+) -> Result<HttpResponse, ManagerError> {
+    let secrets = state.db.lock().await.list_secrets(*tenant_id).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&secrets))
+}
+
+/// Set the value of a secret
+///
+/// The value is encrypted with the master key of the manager before it is
+/// stored.  Connector configurations refer to the secret as
+/// `${secret:db:<secret_name>}`.  Replaces the previous value of the secret,
+/// if any.
+#[utoipa::path(
+    request_body = SetSecretRequest,
+    responses(
+        (status = OK, description = "Secret stored successfully"),
+        (status = BAD_REQUEST
+            , description = "Invalid secret name."
+            , body = ErrorResponse
+            , example = json!(examples::invalid_secret_name())),
+        (status = SERVICE_UNAVAILABLE
+            , description = "No master key is configured."
+            , body = ErrorResponse),
+    ),
+    params(
+        ("secret_name" = String, Path, description = "Name of the secret")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Secrets"
+)]
+#[put("/secrets/{secret_name}")]
+pub(crate) async fn set_secret(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    request: HttpRequest,
+    body: web::Json<SetSecretRequest>,
+) -> Result<HttpResponse, ManagerError> {
+    let secret_name = parse_string_param(&request, "secret_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .set_secret(*tenant_id, &secret_name, &body.value)
+        .await?;
+    info!("Stored secret {secret_name} (tenant:{})", *tenant_id);
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Delete a secret
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Secret deleted successfully"),
+        (status = NOT_FOUND
+            , description = "Specified secret does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_secret())),
+    ),
+    params(
+        ("secret_name" = String, Path, description = "Name of the secret")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Secrets"
+)]
+#[delete("/secrets/{secret_name}")]
+pub(crate) async fn delete_secret(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let secret_name = parse_string_param(&request, "secret_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .delete_secret(*tenant_id, &secret_name)
+        .await?;
+    info!("Deleted secret {secret_name} (tenant:{})", *tenant_id);
+    Ok(HttpResponse::Ok().finish())
+}
@@ -6,8 +6,9 @@ use crate::db::ServiceProbeId;
 use crate::prober::service::{ServiceProbeRequest, ServiceProbeType};
 use crate::{
     api::examples,
-    auth::TenantId,
+    auth::{SecretsAccess, TenantId},
     db::{storage::Storage, DBError, ServiceId},
+    secrets::check_database_secret_access,
 };
 use actix_web::web::Query;
 use actix_web::{
@@ -175,6 +176,10 @@ async fn list_services(
         , description = "A service with this name already exists in the database"
         , body = ErrorResponse
         , example = json!(examples::duplicate_name())),
+        (status = FORBIDDEN
+            , description = "The configuration references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(examples::database_secret_not_authorized())),
     ),
     context_path = "/v0",
     security(("JSON web token (JWT) or API key" = [])),
@@ -184,8 +189,10 @@ async fn list_services(
 async fn new_service(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
     request: web::Json<NewServiceRequest>,
-) -> Result<HttpResponse, DBError> {
+) -> Result<HttpResponse, ManagerError> {
+    check_database_secret_access(*secrets_access, &request.config)?;
     let service_id = state
         .db
         .lock()
@@ -219,6 +226,10 @@ async fn new_service(
             , body = ErrorResponse
             , example = json!(examples::unknown_name())
         ),
+        (status = FORBIDDEN
+            , description = "The configuration references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(examples::database_secret_not_authorized())),
     ),
     params(
         ("service_name" = String, Path, description = "Unique service name")
@@ -231,9 +242,11 @@ async fn new_service(
 async fn update_service(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
     req: HttpRequest,
     body: web::Json<UpdateServiceRequest>,
 ) -> Result<HttpResponse, ManagerError> {
+    check_database_secret_access(*secrets_access, &body.config)?;
     let service_name = parse_string_param(&req, "service_name")?;
     let db = state.db.lock().await;
     db.update_service_by_name(
@@ -261,6 +274,10 @@ async fn update_service(
             , description = "A service with this name already exists in the database"
             , body = ErrorResponse
             , example = json!(examples::duplicate_name())),
+        (status = FORBIDDEN
+            , description = "The configuration references a database secret, but the caller is not allowed to access the secrets of the tenant"
+            , body = ErrorResponse
+            , example = json!(examples::database_secret_not_authorized())),
     ),
     params(
         ("service_name" = String, Path, description = "Unique service name")
@@ -273,9 +290,11 @@ async fn update_service(
 async fn create_or_replace_service(
     state: WebData<ServerState>,
     tenant_id: ReqData<TenantId>,
+    secrets_access: ReqData<SecretsAccess>,
     request: HttpRequest,
     body: web::Json<CreateOrReplaceServiceRequest>,
 ) -> Result<HttpResponse, ManagerError> {
+    check_database_secret_access(*secrets_access, &body.config)?;
     let service_name = parse_string_param(&request, "service_name")?;
     let (created, service_id) = state
         .db
@@ -326,7 +345,7 @@ async fn delete_service(
         .db
         .lock()
         .await
-        .delete_service(*tenant_id, &service_name)
+        .delete_service(*tenant_id, &service_name, None)
         .await?;
 
     info!("Deleted service {service_name} (tenant: {})", *tenant_id);
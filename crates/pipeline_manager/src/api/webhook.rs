@@ -0,0 +1,240 @@
+/// API to register webhooks and inspect their deliveries
+use super::{ManagerError, ServerState};
+use crate::{
+    api::{examples, parse_string_param},
+    auth::TenantId,
+    db::{
+        storage::Storage,
+        webhook::{WebhookEventType, WebhookId},
+        DBError,
+    },
+    outbound, webhooks,
+};
+use actix_web::{
+    delete, get,
+    http::header::{CacheControl, CacheDirective},
+    post,
+    web::{self, Data as WebData, ReqData},
+    HttpRequest, HttpResponse,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Request to register a webhook.
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct NewWebhookRequest {
+    /// Webhook name, unique per tenant.
+    #[schema(example = "slack-alerts")]
+    name: String,
+
+    /// HTTP(S) endpoint the events are posted to.
+    #[schema(example = "https://hooks.example.com/feldera")]
+    url: String,
+
+    /// Events delivered to the webhook.  Defaults to all event types.
+    #[serde(default)]
+    event_types: Option<Vec<WebhookEventType>>,
+
+    /// Secret that signs the deliveries.  A random secret is generated if
+    /// not specified.
+    #[serde(default)]
+    secret: Option<String>,
+}
+
+/// Response to a successful webhook registration.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct NewWebhookResponse {
+    /// Id of the newly registered webhook.
+    webhook_id: WebhookId,
+
+    /// Webhook name.
+    #[schema(example = "slack-alerts")]
+    name: String,
+
+    /// Secret that signs the deliveries.  There is no way to retrieve the
+    /// secret again from the pipeline-manager, so store it securely.
+    secret: String,
+}
+
+/// Query parameters to list webhook deliveries.
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct WebhookDeliveriesQuery {
+    /// Maximum number of deliveries to return.  The most recent deliveries
+    /// are returned first.
+    limit: Option<u32>,
+}
+
+/// List the webhooks of the tenant
+///
+/// Secrets are never returned.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Webhooks retrieved successfully", body = [WebhookDescr]),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Webhooks"
+)]
+#[get("/webhooks")]
+pub(crate) async fn list_webhooks(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+) -> Result<HttpResponse, ManagerError> {
+    let webhooks = state.db.lock().await.list_webhooks(*tenant_id).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&webhooks))
+}
+
+/// Register a webhook
+///
+/// The webhook is notified of program compilations that succeed or fail and
+/// of pipelines that transition to `Running`, `Failed` or `Shutdown`.  Every
+/// event is posted as JSON with an `X-Feldera-Signature` header of the form
+/// `t=<timestamp>,v1=<signature>`, where `<signature>` is the hexadecimal
+/// HMAC-SHA256 of `<timestamp>.<body>` keyed with the secret of the webhook.
+/// Failed deliveries are retried with exponential backoff.
+///
+/// Endpoints that resolve to loopback, private or link-local addresses are
+/// refused unless an administrator allows them.
+#[utoipa::path(
+    request_body = NewWebhookRequest,
+    responses(
+        (status = CREATED, description = "Webhook registered successfully.", body = NewWebhookResponse),
+        (status = BAD_REQUEST
+            , description = "Invalid or disallowed URL, or invalid event types."
+            , body = ErrorResponse
+            , example = json!(examples::invalid_webhook())),
+        (status = CONFLICT
+            , description = "A webhook with this name already exists."
+            , body = ErrorResponse
+            , example = json!(examples::duplicate_name())),
+        (status = SERVICE_UNAVAILABLE
+            , description = "No secrets master key is configured to encrypt the webhook secret."
+            , body = ErrorResponse),
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Webhooks"
+)]
+#[post("/webhooks")]
+pub(crate) async fn new_webhook(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    body: web::Json<NewWebhookRequest>,
+) -> Result<HttpResponse, ManagerError> {
+    // Malformed URLs are reported by the database.
+    if let Ok(url) = Url::parse(&body.url) {
+        outbound::resolve_url(&url, &state._config.webhook_allowed_hosts)
+            .await
+            .map_err(|reason| DBError::InvalidWebhook { reason })?;
+    }
+    let secret = body
+        .secret
+        .clone()
+        .unwrap_or_else(webhooks::generate_secret);
+    let event_types = body
+        .event_types
+        .clone()
+        .unwrap_or_else(|| WebhookEventType::ALL.to_vec());
+    let webhook_id = state
+        .db
+        .lock()
+        .await
+        .new_webhook(
+            *tenant_id,
+            Uuid::now_v7(),
+            &body.name,
+            &body.url,
+            &secret,
+            &event_types,
+        )
+        .await?;
+    info!("Registered webhook {} (tenant:{})", body.name, *tenant_id);
+    Ok(HttpResponse::Created()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&NewWebhookResponse {
+            webhook_id,
+            name: body.name.clone(),
+            secret,
+        }))
+}
+
+/// Delete a webhook
+///
+/// Pending deliveries of the webhook are discarded.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Webhook deleted successfully"),
+        (status = NOT_FOUND
+            , description = "Specified webhook does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_webhook())),
+    ),
+    params(
+        ("webhook_name" = String, Path, description = "Unique webhook name")
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Webhooks"
+)]
+#[delete("/webhooks/{webhook_name}")]
+pub(crate) async fn delete_webhook(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ManagerError> {
+    let webhook_name = parse_string_param(&req, "webhook_name")?;
+    state
+        .db
+        .lock()
+        .await
+        .delete_webhook(*tenant_id, &webhook_name)
+        .await?;
+    info!("Deleted webhook {webhook_name} (tenant:{})", *tenant_id);
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Retrieve the deliveries of a webhook, most recent first
+///
+/// Every delivery records its status, the number of attempts so far and the
+/// outcome of the last attempt.
+#[utoipa::path(
+    responses(
+        (status = OK
+            , description = "Webhook deliveries retrieved successfully."
+            , body = [WebhookDelivery]),
+        (status = NOT_FOUND
+            , description = "Specified webhook does not exist."
+            , body = ErrorResponse
+            , example = json!(examples::unknown_webhook())),
+    ),
+    params(
+        ("webhook_name" = String, Path, description = "Unique webhook name"),
+        WebhookDeliveriesQuery,
+    ),
+    context_path = "/v0",
+    security(("JSON web token (JWT) or API key" = [])),
+    tag = "Webhooks"
+)]
+#[get("/webhooks/{webhook_name}/deliveries")]
+pub(crate) async fn list_webhook_deliveries(
+    state: WebData<ServerState>,
+    tenant_id: ReqData<TenantId>,
+    req: HttpRequest,
+    query: web::Query<WebhookDeliveriesQuery>,
+) -> Result<HttpResponse, ManagerError> {
+    let webhook_name = parse_string_param(&req, "webhook_name")?;
+    let deliveries = state
+        .db
+        .lock()
+        .await
+        .list_webhook_deliveries(*tenant_id, &webhook_name, query.limit)
+        .await?;
+    Ok(HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(&deliveries))
+}
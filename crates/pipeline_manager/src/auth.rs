@@ -34,9 +34,27 @@
 //! OpenAPI spec (or look at the endpoints in `api/api_keys`).
 //! These API keys can then be used in the REST API similar to how JWT tokens
 //! are used above, but with the bearer token being "apikey:1234..." to
-//! authorize access. For now, we simply have two permission types: Read and
-//! Write. Later, we will expand to have fine-grained access to specific API
-//! resources.
+//! authorize access. Every key has tenant-wide permissions (Read and/or
+//! Write): `GET` requests require the Read permission and all other requests
+//! require the Write permission. A key can further be restricted to a set of
+//! actions (e.g., read, deploy, ingress) on specific pipelines and programs
+//! using resource scopes, in which case all requests that do not target one of
+//! these resources with one of the allowed actions are rejected. Keys can also
+//! carry an expiration time, after which they are no longer accepted.
+//!
+//! 3) Roles:
+//!
+//! Users authenticated via a bearer token can be assigned a role within their
+//! tenant (see the `Roles` endpoints): viewers can only issue read requests,
+//! operators can additionally start, pause and shut down pipelines and push
+//! data to them, and admins can issue all requests. Users without an assigned
+//! role get the role configured with `--default-role` (admin by default, so
+//! that existing deployments keep working), except for the users listed in
+//! `--admin-users`, who are always admins. API keys are assigned a role when
+//! they are created, which applies in addition to their permissions and
+//! resource scopes. Mutating requests, including the ones rejected because of
+//! a missing role or permission, are recorded in the audit log along with the
+//! user or API key that issued them (see `api/audit`).
 //!
 //! API keys are randomly generated 128 character sequences that are never
 //! stored in the pipeline manager or in the database. It is the responsibility
@@ -48,8 +66,12 @@
 use std::fmt::{self, Display};
 use std::{collections::HashMap, env};
 
+use actix_web::http::Method;
 use actix_web::HttpMessage;
-use actix_web::{dev::ServiceRequest, web::Data};
+use actix_web::{
+    dev::{ServiceRequest, Url},
+    web::Data,
+};
 use actix_web_httpauth::extractors::{
     bearer::{BearerAuth, Config},
     AuthenticationError,
@@ -66,8 +88,29 @@ use static_assertions::assert_impl_any;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::api::ServerState;
-use crate::db::{storage::Storage, ApiPermission, DBError, ProjectDB};
+use crate::api::{audit, ManagerError, ServerState};
+use crate::config::ApiServerConfig;
+use crate::db::{
+    audit::Role, storage::Storage, ApiAction, ApiKeyDescr, ApiKeyScope, ApiPermission, ApiResource,
+    DBError, ProjectDB,
+};
+
+/// The user or API key that issued a request, as recorded in the audit log:
+/// `user:<name>` for users and `api_key:<name>` for API keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Actor(pub String);
+
+/// Whether the issuer of a request may use the secrets stored in the
+/// database of its tenant, i.e., may read them through `/v0/secrets`.
+///
+/// Referencing a database secret in a connector or service configuration
+/// lets the configuration's users send the secret anywhere, so it requires
+/// the same access as reading the secret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SecretsAccess(pub bool);
+
+/// Path whose access rules apply to referencing database secrets.
+const SECRETS_PATH: &str = "/v0/secrets";
 
 // Used when no auth is configured, so we tag the request with the default user
 // and passthrough
@@ -75,6 +118,9 @@ pub(crate) fn tag_with_default_tenant_id(req: ServiceRequest) -> ServiceRequest
     req.extensions_mut().insert(DEFAULT_TENANT_ID);
     req.extensions_mut()
         .insert(vec![ApiPermission::Read, ApiPermission::Write]);
+    req.extensions_mut()
+        .insert(Actor("user:default".to_string()));
+    req.extensions_mut().insert(SecretsAccess(true));
     req
 }
 
@@ -104,22 +150,36 @@ async fn bearer_auth(
         AuthProvider::GoogleIdentity(_) => {
             decode_google_identity_token(token, &req, configuration).await
         }
+        AuthProvider::GenericOidc(_) => decode_generic_oidc_token(token, &req, configuration).await,
     };
     match token {
         Ok(claim) => {
             // TODO: Handle tenant deletions at some point
             let tenant = {
-                let ad = req.app_data::<Data<ServerState>>();
-                let db = &ad.unwrap().db.lock().await;
-                db.get_or_create_tenant_id(claim.tenant_name(), claim.provider())
-                    .await
+                let state = req.app_data::<Data<ServerState>>().unwrap();
+                let db = &state.db.lock().await;
+                resolve_tenant_and_role(db, &claim, &state._config).await
             };
 
             match tenant {
-                Ok(tenant_id) => {
+                Ok((tenant_id, role)) => {
+                    let actor = Actor(format!("user:{}", claim.subject()));
+                    let path = Url::new(req.uri().clone()).path().to_string();
+                    let required = required_role(req.method(), &path);
+                    if role < required {
+                        audit::record_denied(&req, tenant_id, &actor).await;
+                        return Err((
+                            ManagerError::InsufficientRole { role, required }.into(),
+                            req,
+                        ));
+                    }
                     req.extensions_mut().insert(tenant_id);
                     req.extensions_mut()
                         .insert(vec![ApiPermission::Read, ApiPermission::Write]);
+                    req.extensions_mut().insert(actor);
+                    req.extensions_mut().insert(SecretsAccess(
+                        role >= required_role(&Method::GET, SECRETS_PATH),
+                    ));
                     Ok(req)
                 }
                 Err(e) => {
@@ -168,14 +228,60 @@ async fn api_key_auth(
 ) -> Result<ServiceRequest, (actix_web::error::Error, ServiceRequest)> {
     // Check for an API-key
     let ad = req.app_data::<Data<ServerState>>();
+    let path = Url::new(req.uri().clone()).path().to_string();
     let validate = {
         let db = &ad.unwrap().db.lock().await;
-        validate_api_keys(db, api_key_str).await
+        match validate_api_keys(db, api_key_str).await {
+            Ok((tenant_id, descr)) => {
+                // Resource scopes refer to pipelines and programs by id.
+                let scope = if descr.resource_scopes.is_empty() {
+                    None
+                } else {
+                    resolve_request_scope(db, tenant_id, req.method(), &path).await
+                };
+                Ok((tenant_id, descr, scope))
+            }
+            Err(e) => Err(e),
+        }
     };
     match validate {
-        Ok((tenant_id, permissions)) => {
+        Ok((tenant_id, descr, scope)) => {
+            let actor = Actor(format!("api_key:{}", descr.name));
+            let required = required_role(req.method(), &path);
+            if descr.role < required {
+                audit::record_denied(&req, tenant_id, &actor).await;
+                return Err((
+                    ManagerError::InsufficientRole {
+                        role: descr.role,
+                        required,
+                    }
+                    .into(),
+                    req,
+                ));
+            }
+            if let Err(reason) = authorize_api_key(
+                req.method(),
+                &path,
+                &descr.scopes,
+                &descr.resource_scopes,
+                scope,
+            ) {
+                audit::record_denied(&req, tenant_id, &actor).await;
+                return Err((ManagerError::ApiKeyNotAuthorized { reason }.into(), req));
+            }
+            let secrets_access = descr.role >= required_role(&Method::GET, SECRETS_PATH)
+                && authorize_api_key(
+                    &Method::GET,
+                    SECRETS_PATH,
+                    &descr.scopes,
+                    &descr.resource_scopes,
+                    None,
+                )
+                .is_ok();
             req.extensions_mut().insert(tenant_id);
-            req.extensions_mut().insert(permissions);
+            req.extensions_mut().insert(descr.scopes);
+            req.extensions_mut().insert(actor);
+            req.extensions_mut().insert(SecretsAccess(secrets_access));
             Ok(req)
         }
         Err(_) => {
@@ -190,6 +296,141 @@ async fn api_key_auth(
     }
 }
 
+/// Checks that an API key with `permissions` and `resource_scopes` is allowed
+/// to issue a `method` request to `path`, where `scope` is the resource and
+/// action targeted by the request as returned by [`resolve_request_scope`].
+///
+/// Returns a description of the missing permission otherwise.
+fn authorize_api_key(
+    method: &Method,
+    path: &str,
+    permissions: &[ApiPermission],
+    resource_scopes: &[ApiKeyScope],
+    scope: Option<(ApiResource, ApiAction)>,
+) -> Result<(), String> {
+    let permission = if *method == Method::GET || *method == Method::HEAD {
+        ApiPermission::Read
+    } else {
+        ApiPermission::Write
+    };
+    if !permissions.contains(&permission) {
+        return Err(format!(
+            "API key does not have the {permission:?} permission required for {method} requests"
+        ));
+    }
+
+    if resource_scopes.is_empty() {
+        return Ok(());
+    }
+    match scope {
+        Some((resource, action)) if resource_scopes.iter().any(|s| s.allows(&resource, action)) => {
+            Ok(())
+        }
+        Some((resource, action)) => Err(format!(
+            "API key is not allowed to perform action '{action:?}' on {resource:?}"
+        )),
+        None => Err(format!(
+            "API key is restricted to specific pipelines and programs and cannot be used for '{method} {path}'"
+        )),
+    }
+}
+
+/// Returns the least privileged role allowed to issue a `method` request to
+/// `path`.
+fn required_role(method: &Method, path: &str) -> Role {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    if let ["v0", "api_keys" | "roles" | "secrets" | "webhooks" | "audit", ..] = segments.as_slice()
+    {
+        return Role::Admin;
+    }
+    if *method == Method::GET || *method == Method::HEAD {
+        return Role::Viewer;
+    }
+    match request_scope(method, path) {
+        Some((RequestTarget::Pipeline(_), ApiAction::Egress)) => Role::Viewer,
+        Some((RequestTarget::Pipeline(_), ApiAction::Deploy | ApiAction::Ingress)) => {
+            Role::Operator
+        }
+        _ => Role::Admin,
+    }
+}
+
+/// Pipeline or program targeted by a request, identified by the name in the
+/// request path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequestTarget<'a> {
+    Pipeline(&'a str),
+    Program(&'a str),
+}
+
+/// Determines the pipeline or program targeted by a `method` request to
+/// `path` and the action it performs.
+///
+/// Returns `None` for requests that do not target a single pipeline or
+/// program, e.g., listing all pipelines or managing API keys, and for
+/// requests that don't correspond to a known action.
+fn request_scope(method: &Method, path: &str) -> Option<(RequestTarget<'_>, ApiAction)> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let ["v0", collection, name, rest @ ..] = segments.as_slice() else {
+        return None;
+    };
+    match *collection {
+        "pipelines" => {
+            let action = match (method.as_str(), rest) {
+                ("GET", _) => ApiAction::Read,
+                ("PATCH" | "PUT", []) => ApiAction::Update,
+                ("DELETE", []) => ApiAction::Delete,
+                ("PUT" | "DELETE", ["schedules", _]) => ApiAction::Update,
+                ("POST", ["ingress", _]) => ApiAction::Ingress,
+                ("POST", ["egress" | "lookup", _]) => ApiAction::Egress,
+                ("POST", ["start" | "pause" | "shutdown" | "upgrade"]) => ApiAction::Deploy,
+                _ => return None,
+            };
+            Some((RequestTarget::Pipeline(name), action))
+        }
+        "programs" => {
+            let action = match (method.as_str(), rest) {
+                ("GET", _) => ApiAction::Read,
+                ("PATCH" | "PUT", [])
+                | ("POST", ["compile" | "rollback"] | ["compile", "cancel"]) => ApiAction::Update,
+                ("DELETE", []) => ApiAction::Delete,
+                _ => return None,
+            };
+            Some((RequestTarget::Program(name), action))
+        }
+        _ => None,
+    }
+}
+
+/// Determines the pipeline or program targeted by a `method` request to
+/// `path` within `tenant_id` and the action it performs.
+///
+/// Returns `None` if the request does not target a single pipeline or
+/// program or if the target does not exist.
+async fn resolve_request_scope(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    method: &Method,
+    path: &str,
+) -> Option<(ApiResource, ApiAction)> {
+    let (target, action) = request_scope(method, path)?;
+    let resource = match target {
+        RequestTarget::Pipeline(name) => ApiResource::Pipeline(
+            db.get_pipeline_descr_by_name(tenant_id, name, None)
+                .await
+                .ok()?
+                .pipeline_id,
+        ),
+        RequestTarget::Program(name) => ApiResource::Program(
+            db.get_program_by_name(tenant_id, name, false, None)
+                .await
+                .ok()?
+                .program_id,
+        ),
+    };
+    Some((resource, action))
+}
+
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 /// Represents information about the tenant, extracted
@@ -233,18 +474,35 @@ impl TenantRecord {
 #[derive(Debug)]
 enum Claim {
     AwsCognito(TokenData<AwsCognitoClaim>),
+    GenericOidc {
+        /// Value of the tenant claim configured for the provider.
+        tenant: String,
+        /// The authenticated user (`sub` claim).
+        subject: String,
+        /// The identity provider that issued the token.
+        issuer: String,
+    },
 }
 
 impl Claim {
     fn tenant_name(&self) -> String {
         match self {
             Claim::AwsCognito(t) => t.claims.sub.clone(),
+            Claim::GenericOidc { tenant, .. } => tenant.clone(),
+        }
+    }
+
+    fn subject(&self) -> String {
+        match self {
+            Claim::AwsCognito(t) => t.claims.sub.clone(),
+            Claim::GenericOidc { subject, .. } => subject.clone(),
         }
     }
 
     fn provider(&self) -> String {
         match self {
             Claim::AwsCognito(t) => t.claims.iss.clone(),
+            Claim::GenericOidc { issuer, .. } => issuer.clone(),
         }
     }
 }
@@ -262,10 +520,25 @@ pub(crate) struct ProviderGoogleIdentity {
     pub client_id: String,
 }
 
+/// Any OpenID Connect provider (e.g., Keycloak, Okta, Dex).
+///
+/// The provider's signing keys are located through OpenID Connect discovery
+/// at `<issuer>/.well-known/openid-configuration`, so clients only need the
+/// issuer URL and the client ID to log in.
+#[derive(Clone, Serialize, ToSchema)]
+pub(crate) struct ProviderGenericOidc {
+    pub issuer: String,
+    pub client_id: String,
+    /// Claim that determines the tenant of an authenticated user.
+    #[serde(skip)]
+    pub tenant_claim: String,
+}
+
 #[derive(Clone, Serialize, ToSchema)]
 pub(crate) enum AuthProvider {
     AwsCognito(ProviderAwsCognito), // The argument is the URL to use for fetching JWKs
     GoogleIdentity(ProviderGoogleIdentity),
+    GenericOidc(ProviderGenericOidc),
 }
 
 pub(crate) fn aws_auth_config() -> AuthConfiguration {
@@ -309,6 +582,42 @@ pub(crate) fn google_auth_config() -> AuthConfiguration {
     }
 }
 
+/// Claim used to assign tenants by the generic OIDC provider when
+/// `AUTH_TENANT_CLAIM` is not set.
+const DEFAULT_OIDC_TENANT_CLAIM: &str = "sub";
+
+pub(crate) fn generic_oidc_auth_config() -> AuthConfiguration {
+    let client_id =
+        env::var("AUTH_CLIENT_ID").expect("Missing environment variable AUTH_CLIENT_ID");
+    let issuer = env::var("AUTH_ISSUER").expect("Missing environment variable AUTH_ISSUER");
+    let audience = env::var("AUTH_AUDIENCE").unwrap_or_else(|_| client_id.clone());
+    let tenant_claim =
+        env::var("AUTH_TENANT_CLAIM").unwrap_or_else(|_| DEFAULT_OIDC_TENANT_CLAIM.to_string());
+    generic_oidc_config(issuer, client_id, audience, tenant_claim)
+}
+
+/// Configuration of a generic OIDC provider that accepts tokens issued by
+/// `issuer` for `audience`, and assigns tenants based on `tenant_claim`.
+fn generic_oidc_config(
+    issuer: String,
+    client_id: String,
+    audience: String,
+    tenant_claim: String,
+) -> AuthConfiguration {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_issuer(&[&issuer]);
+    validation.set_audience(&[audience]);
+    AuthConfiguration {
+        provider: AuthProvider::GenericOidc(ProviderGenericOidc {
+            issuer,
+            client_id: client_id.clone(),
+            tenant_claim,
+        }),
+        validation,
+        client_id,
+    }
+}
+
 #[derive(Clone)]
 // Expected issuer and client_id for each authentication request
 pub(crate) struct AuthConfiguration {
@@ -378,6 +687,16 @@ impl std::fmt::Display for AuthError {
     }
 }
 
+impl From<JsonPayloadError> for AuthError {
+    fn from(value: JsonPayloadError) -> Self {
+        match value {
+            JsonPayloadError::Deserialize(json_error) => Self::JwkShape(json_error.to_string()),
+            JsonPayloadError::Payload(payload) => Self::JwkPayload(payload),
+            JsonPayloadError::ContentType => Self::JwkContentType,
+        }
+    }
+}
+
 impl From<awc::error::SendRequestError> for AuthError {
     fn from(value: awc::error::SendRequestError) -> Self {
         Self::JwkFetch(value)
@@ -444,6 +763,60 @@ async fn decode_aws_cognito_token(
     }
 }
 
+/// Validates a token issued by a generic OIDC provider and extracts the
+/// tenant from the configured tenant claim.
+async fn decode_generic_oidc_token(
+    token: &str,
+    req: &ServiceRequest,
+    configuration: &AuthConfiguration,
+) -> Result<Claim, AuthError> {
+    let AuthProvider::GenericOidc(provider) = &configuration.provider else {
+        unreachable!("generic OIDC token decoding requires a generic OIDC provider")
+    };
+    let header = decode_header(token)?;
+    if header.alg != Algorithm::RS256 {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidAlgorithm.into());
+    }
+    let Some(kid) = header.kid else {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    };
+    let jwk = {
+        let state = req.app_data::<Data<ServerState>>().unwrap();
+        let cache = &mut state.jwk_cache.lock().await;
+        cache.get(&kid, &configuration.provider).await?
+    };
+
+    let token_data = decode::<Value>(token, &jwk, &configuration.validation)?;
+    let tenant = claim_as_string(&token_data.claims, &provider.tenant_claim)
+        .filter(|tenant| !tenant.is_empty())
+        .ok_or_else(|| {
+            jsonwebtoken::errors::ErrorKind::MissingRequiredClaim(provider.tenant_claim.clone())
+        })?;
+    let subject = claim_as_string(&token_data.claims, "sub").unwrap_or_else(|| tenant.clone());
+    Ok(Claim::GenericOidc {
+        tenant,
+        subject,
+        issuer: provider.issuer.clone(),
+    })
+}
+
+/// Returns the value of claim `name` as a string.
+///
+/// Nested claims can be addressed using a dot-separated path, e.g.,
+/// `organization.id`.  A top-level claim whose name contains dots takes
+/// precedence over a nested claim with the same path.
+fn claim_as_string(claims: &Value, name: &str) -> Option<String> {
+    let value = claims.get(name).or_else(|| {
+        name.split('.')
+            .try_fold(claims, |value, key| value.get(key))
+    })?;
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
 async fn decode_google_identity_token(
     _token: &str,
     _req: &ServiceRequest,
@@ -503,18 +876,45 @@ async fn fetch_jwk_keys(
     provider: &AuthProvider,
 ) -> Result<HashMap<String, DecodingKey>, AuthError> {
     match &provider {
-        AuthProvider::AwsCognito(provider) => fetch_jwk_aws_cognito_keys(&provider.jwk_uri).await,
+        AuthProvider::AwsCognito(provider) => fetch_jwk_set(&provider.jwk_uri).await,
         AuthProvider::GoogleIdentity(provider) => {
             fetch_jwk_google_identity_keys(&provider.jwk_uri).await
         }
+        AuthProvider::GenericOidc(provider) => fetch_jwk_oidc_keys(&provider.issuer).await,
     }
 }
 
+/// Path of the OpenID Connect discovery document relative to the issuer.
+const OIDC_DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+
+/// Fetches the signing keys of a generic OIDC provider, using OpenID Connect
+/// discovery to locate its JWK set.
+///
+/// See <https://openid.net/specs/openid-connect-discovery-1_0.html>.
+async fn fetch_jwk_oidc_keys(issuer: &str) -> Result<HashMap<String, DecodingKey>, AuthError> {
+    let client = awc::Client::new();
+    let url = format!("{}{OIDC_DISCOVERY_PATH}", issuer.trim_end_matches('/'));
+    let discovery = client.get(&url).send().await?.json::<Value>().await?;
+
+    // The issuer in the discovery document must match the configured one.
+    let discovered_issuer = validate_field_is_str("issuer", &discovery).ok_or(
+        AuthError::JwkShape("Could not extract 'issuer' field from discovery document".to_owned()),
+    )?;
+    if discovered_issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(AuthError::JwkShape(format!(
+            "Discovery document issuer '{discovered_issuer}' does not match '{issuer}'"
+        )));
+    }
+    let jwks_uri = validate_field_is_str("jwks_uri", &discovery).ok_or(AuthError::JwkShape(
+        "Could not extract 'jwks_uri' field from discovery document".to_owned(),
+    ))?;
+    fetch_jwk_set(jwks_uri).await
+}
+
+// Fetches RS256 signing keys from the JWK set at `url`.
 // We don't want to fetch keys on every authentication attempt, so cache the
 // results. TODO: implement periodic refresh
-async fn fetch_jwk_aws_cognito_keys(
-    url: &String,
-) -> Result<HashMap<String, DecodingKey>, AuthError> {
+async fn fetch_jwk_set(url: &str) -> Result<HashMap<String, DecodingKey>, AuthError> {
     let client = awc::Client::new();
 
     let res = client.get(url).send().await;
@@ -532,7 +932,7 @@ async fn fetch_jwk_aws_cognito_keys(
                 .iter()
                 // While the AWS Cognito JWK endpoint shouldn't return keys
                 // that aren't based on RS256 or meant for verifying signatures,
-                // this guard should warn us when used with other auth providers later
+                // other providers (e.g., Keycloak) also publish encryption keys
                 .filter_map(|val| check_key_as_str("alg", "RS256", val))
                 .filter_map(|val| check_key_as_str("use", "sig", val));
 
@@ -597,10 +997,29 @@ fn validate_field_is_str<'a>(key: &str, json: &'a Value) -> Option<&'a str> {
 async fn validate_api_keys(
     db: &ProjectDB,
     api_key: &str,
-) -> Result<(TenantId, Vec<ApiPermission>), DBError> {
+) -> Result<(TenantId, ApiKeyDescr), DBError> {
     db.validate_api_key(api_key).await
 }
 
+/// Resolves the tenant of a claim and the role of the user within the tenant.
+/// Users without an assigned role get `config.default_role`, except for the
+/// users listed in `config.admin_users`, who are always admins.
+async fn resolve_tenant_and_role(
+    db: &ProjectDB,
+    claim: &Claim,
+    config: &ApiServerConfig,
+) -> Result<(TenantId, Role), DBError> {
+    let tenant_id = db
+        .get_or_create_tenant_id(claim.tenant_name(), claim.provider())
+        .await?;
+    let subject = claim.subject();
+    if config.admin_users.contains(&subject) {
+        return Ok((tenant_id, Role::Admin));
+    }
+    let role = db.get_user_role(tenant_id, &subject).await?;
+    Ok((tenant_id, role.unwrap_or(config.default_role)))
+}
+
 const API_KEY_LENGTH: usize = 128;
 pub const API_KEY_PREFIX: &str = "apikey:";
 
@@ -623,27 +1042,34 @@ mod test {
     use actix_web::{
         body::{BoxBody, EitherBody},
         dev::ServiceResponse,
-        http::{self},
-        test, web, App, HttpRequest, HttpResponse,
+        http::{self, Method},
+        test,
+        web::{self, ReqData},
+        App, HttpRequest, HttpResponse,
     };
     use actix_web_httpauth::middleware::HttpAuthentication;
     use base64::Engine;
     use cached::Cached;
     use chrono::Utc;
     use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+    use serde_json::json;
     use tokio::sync::Mutex;
     use uuid::Uuid;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     use crate::{
         api::ServerState,
-        auth::{
-            self, fetch_jwk_aws_cognito_keys, AuthConfiguration, AuthProvider, AwsCognitoClaim,
+        auth::{self, fetch_jwk_set, AuthConfiguration, AuthProvider, AwsCognitoClaim, TenantId},
+        config::{ApiServerConfig, AuthProviderType},
+        db::{
+            storage::Storage, ApiAction, ApiKeyScope, ApiPermission, ApiResource, PipelineId,
+            ProgramId,
         },
-        config::ApiServerConfig,
-        db::{storage::Storage, ApiPermission},
     };
 
-    use super::AuthError;
+    use super::{authorize_api_key, request_scope, required_role, AuthError, RequestTarget};
+    use crate::db::audit::Role;
 
     async fn setup(claim: AwsCognitoClaim) -> (String, DecodingKey) {
         let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
@@ -693,6 +1119,27 @@ mod test {
         }
     }
 
+    fn api_server_config(auth_provider: AuthProviderType) -> ApiServerConfig {
+        ApiServerConfig {
+            port: 0,
+            bind_address: "0.0.0.0".to_owned(),
+            api_server_working_directory: "".to_owned(),
+            auth_provider,
+            dev_mode: false,
+            dump_openapi: false,
+            config_file: None,
+            allowed_origins: None,
+            demos: vec![],
+            runner_hostname_port: "127.0.0.1:8089".to_owned(),
+            dry_run_allowed_hosts: vec![],
+            webhook_allowed_hosts: vec![],
+            default_role: Role::Viewer,
+            admin_users: vec![],
+            quotas: Default::default(),
+            tenant_quotas: Default::default(),
+        }
+    }
+
     async fn run_test(
         req: actix_http::Request,
         decoding_key: Option<DecodingKey>,
@@ -719,17 +1166,7 @@ mod test {
         let closure = auth::auth_validator;
         let auth_middleware = HttpAuthentication::with_fn(closure);
 
-        let manager_config = ApiServerConfig {
-            port: 0,
-            bind_address: "0.0.0.0".to_owned(),
-            api_server_working_directory: "".to_owned(),
-            auth_provider: crate::config::AuthProviderType::AwsCognito,
-            dev_mode: false,
-            dump_openapi: false,
-            config_file: None,
-            allowed_origins: None,
-            demos: vec![],
-        };
+        let manager_config = api_server_config(AuthProviderType::AwsCognito);
 
         let (conn, _temp) = crate::db::test::setup_pg().await;
         if api_key.is_some() {
@@ -743,6 +1180,9 @@ mod test {
                 "foo",
                 &api_key.unwrap(),
                 vec![ApiPermission::Read, ApiPermission::Write],
+                vec![],
+                None,
+                Role::Admin,
             )
             .await
             .unwrap();
@@ -771,6 +1211,7 @@ mod test {
                             *permissions,
                             vec![ApiPermission::Read, ApiPermission::Write]
                         );
+                        assert!(ext.get::<auth::SecretsAccess>().is_some());
                     }
                     HttpResponse::build(StatusCode::OK).await
                 }),
@@ -783,7 +1224,7 @@ mod test {
     #[actix_web::test]
     async fn invalid_url() {
         let url = "http://localhost/doesnotexist".to_owned();
-        let res = fetch_jwk_aws_cognito_keys(&url).await;
+        let res = fetch_jwk_set(&url).await;
         assert!(matches!(res.err().unwrap(), AuthError::JwkFetch(_)));
     }
 
@@ -930,4 +1371,209 @@ mod test {
         let res = run_test(req, None, Some(api_key), validation).await;
         assert_eq!(200, res.status());
     }
+
+    #[actix_web::test]
+    async fn generic_oidc_mock_issuer() {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let base64url = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+        // Mock issuer serving the discovery document and the JWK set.
+        let issuer = MockServer::start().await;
+        let issuer_url = issuer.uri();
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": issuer_url,
+                "jwks_uri": format!("{issuer_url}/keys"),
+            })))
+            .mount(&issuer)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/keys"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "keys": [{
+                    "kty": "RSA",
+                    "kid": "oidc01",
+                    "alg": "RS256",
+                    "use": "sig",
+                    "n": base64url.encode(rsa.n().to_vec()),
+                    "e": base64url.encode(rsa.e().to_vec()),
+                }]
+            })))
+            .mount(&issuer)
+            .await;
+
+        let config = auth::generic_oidc_config(
+            issuer_url.clone(),
+            "feldera-ui".to_string(),
+            "feldera".to_string(),
+            "org.id".to_string(),
+        );
+        let (conn, _temp) = crate::db::test::setup_pg().await;
+        let db = Arc::new(Mutex::new(conn));
+        let state = actix_web::web::Data::new(
+            ServerState::new(api_server_config(AuthProviderType::GenericOidc), db.clone())
+                .await
+                .unwrap(),
+        );
+        let app = App::new()
+            .app_data(state)
+            .app_data(config)
+            .wrap(HttpAuthentication::with_fn(auth::auth_validator))
+            .route(
+                "/",
+                web::get().to(|tenant_id: ReqData<TenantId>| async move {
+                    HttpResponse::Ok().body(tenant_id.0.to_string())
+                }),
+            );
+        let app = test::init_service(app).await;
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("oidc01".to_owned());
+        let encoding_key = EncodingKey::from_rsa_pem(&rsa.private_key_to_pem().unwrap()).unwrap();
+        let request = |claims: serde_json::Value| {
+            let token = encode(&header, &claims, &encoding_key).unwrap();
+            test::TestRequest::get()
+                .uri("/")
+                .insert_header((http::header::AUTHORIZATION, format!("Bearer {token}")))
+                .to_request()
+        };
+        let exp = Utc::now().timestamp() + 1000;
+
+        // The tenant is determined by the configured claim.
+        let res = test::call_service(
+            &app,
+            request(json!({
+                "iss": issuer_url, "aud": "feldera", "exp": exp,
+                "sub": "user1", "org": { "id": "acme" },
+            })),
+        )
+        .await;
+        assert_eq!(200, res.status());
+        let tenant_id = db
+            .lock()
+            .await
+            .get_or_create_tenant_id("acme".to_string(), issuer_url.clone())
+            .await
+            .unwrap();
+        assert_eq!(test::read_body(res).await, tenant_id.0.to_string());
+
+        // Tokens for a different audience are rejected.
+        let res = test::call_service(
+            &app,
+            request(json!({
+                "iss": issuer_url, "aud": "other", "exp": exp,
+                "sub": "user1", "org": { "id": "acme" },
+            })),
+        )
+        .await;
+        assert_eq!(401, res.status());
+
+        // Tokens without the tenant claim are rejected.
+        let res = test::call_service(
+            &app,
+            request(json!({ "iss": issuer_url, "aud": "feldera", "exp": exp, "sub": "user1" })),
+        )
+        .await;
+        assert_eq!(401, res.status());
+    }
+
+    #[test]
+    fn api_key_scopes() {
+        let all = [ApiPermission::Read, ApiPermission::Write];
+        let p1 = PipelineId(Uuid::now_v7());
+        let p2 = PipelineId(Uuid::now_v7());
+        let prog = ProgramId(Uuid::now_v7());
+        let scopes = [
+            ApiKeyScope {
+                resource: ApiResource::Pipeline(p1),
+                actions: vec![ApiAction::Read, ApiAction::Ingress, ApiAction::Deploy],
+            },
+            ApiKeyScope {
+                resource: ApiResource::Program(prog),
+                actions: vec![ApiAction::Update],
+            },
+        ];
+
+        // Resolves names the way `resolve_request_scope` does.
+        let resolve = |method: &Method, path: &str| {
+            request_scope(method, path).and_then(|(target, action)| {
+                let resource = match target {
+                    RequestTarget::Pipeline("p1") => ApiResource::Pipeline(p1),
+                    RequestTarget::Pipeline("p2") => ApiResource::Pipeline(p2),
+                    RequestTarget::Program("prog") => ApiResource::Program(prog),
+                    _ => return None,
+                };
+                Some((resource, action))
+            })
+        };
+
+        // Tenant-wide permissions.
+        assert!(authorize_api_key(&Method::GET, "/v0/pipelines", &all, &[], None).is_ok());
+        assert!(authorize_api_key(&Method::POST, "/v0/pipelines", &all, &[], None).is_ok());
+        let read = [ApiPermission::Read];
+        assert!(authorize_api_key(&Method::GET, "/v0/pipelines", &read, &[], None).is_ok());
+        assert!(authorize_api_key(&Method::POST, "/v0/pipelines", &read, &[], None).is_err());
+
+        // Resource scopes.
+        for (method, path, allowed) in [
+            (Method::GET, "/v0/pipelines/p1", true),
+            (Method::GET, "/v0/pipelines/p1/stats", true),
+            (Method::POST, "/v0/pipelines/p1/ingress/t1", true),
+            (Method::POST, "/v0/pipelines/p1/egress/v1", false),
+            (Method::POST, "/v0/pipelines/p1/start", true),
+            (Method::POST, "/v0/pipelines/p1/upgrade", true),
+            // Unknown actions are not treated as deployment actions.
+            (Method::POST, "/v0/pipelines/p1/ingress", false),
+            (Method::POST, "/v0/pipelines/p1/frobnicate", false),
+            (Method::PATCH, "/v0/pipelines/p1", false),
+            (Method::DELETE, "/v0/pipelines/p1", false),
+            (Method::GET, "/v0/pipelines/p2", false),
+            (Method::POST, "/v0/pipelines/p2/ingress/t1", false),
+            // Pipelines that don't exist cannot be in scope.
+            (Method::GET, "/v0/pipelines/p3", false),
+            (Method::POST, "/v0/programs/prog/compile", true),
+            (Method::PUT, "/v0/programs/prog", true),
+            (Method::POST, "/v0/programs/prog/rollback", true),
+            (Method::GET, "/v0/programs/prog/versions", false),
+            (Method::GET, "/v0/programs/prog", false),
+            (Method::GET, "/v0/pipelines", false),
+            (Method::POST, "/v0/api_keys", false),
+        ] {
+            assert_eq!(
+                authorize_api_key(&method, path, &all, &scopes, resolve(&method, path)).is_ok(),
+                allowed,
+                "{method} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn user_roles() {
+        for (method, path, role) in [
+            (Method::GET, "/v0/pipelines", Role::Viewer),
+            (Method::GET, "/v0/programs/prog", Role::Viewer),
+            (Method::POST, "/v0/pipelines/p1/egress/v1", Role::Viewer),
+            (Method::POST, "/v0/pipelines/p1/start", Role::Operator),
+            (Method::POST, "/v0/pipelines/p1/shutdown", Role::Operator),
+            (Method::POST, "/v0/pipelines/p1/ingress/t1", Role::Operator),
+            (Method::PATCH, "/v0/pipelines/p1", Role::Admin),
+            (Method::GET, "/v0/pipelines/p1/schedules", Role::Viewer),
+            (
+                Method::PUT,
+                "/v0/pipelines/p1/schedules/nightly",
+                Role::Admin,
+            ),
+            (Method::POST, "/v0/pipelines", Role::Admin),
+            (Method::POST, "/v0/programs/prog/compile", Role::Admin),
+            (Method::DELETE, "/v0/connectors/c1", Role::Admin),
+            (Method::GET, "/v0/api_keys", Role::Admin),
+            (Method::GET, "/v0/audit", Role::Admin),
+            (Method::PUT, "/v0/roles/alice", Role::Admin),
+            (Method::GET, "/v0/secrets", Role::Admin),
+            (Method::GET, "/v0/webhooks/w1/deliveries", Role::Admin),
+        ] {
+            assert_eq!(required_role(&method, path), role, "{method} {path}");
+        }
+    }
 }
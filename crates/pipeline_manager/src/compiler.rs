@@ -4,9 +4,10 @@ use crate::db::storage::Storage;
 use crate::db::{DBError, ProgramDescr, ProgramId, ProjectDB, Version};
 use crate::error::ManagerError;
 use crate::probe::Probe;
+use crate::webhooks;
 use actix_files::NamedFile;
 use actix_web::{get, web, HttpRequest, HttpServer, Responder};
-use futures_util::join;
+use futures_util::{future::try_join_all, join};
 use log::warn;
 use log::{debug, error, info, trace};
 use once_cell::sync::Lazy;
@@ -35,6 +36,13 @@ use tokio::{
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+mod cache;
+mod diagnostics;
+
+pub(crate) use diagnostics::{
+    CompilerDiagnostic, DiagnosticSeverity, SqlCompilerMessage, SqlPosition,
+};
+
 /// The frequency with which the compiler polls the project database
 /// for new compilation requests.
 const COMPILER_POLL_INTERVAL: Duration = Duration::from_millis(1000);
@@ -47,35 +55,6 @@ const COMPILER_POLL_INTERVAL: Duration = Duration::from_millis(1000);
 /// future.
 const GC_POLL_INTERVAL: Duration = Duration::from_secs(3);
 
-/// A SQL compiler error.
-///
-/// The SQL compiler returns a list of errors in the following JSON format if
-/// it's invoked with the `-je` option.
-///
-/// ```ignore
-///  [ {
-/// "startLineNumber" : 14,
-/// "startColumn" : 13,
-/// "endLineNumber" : 14,
-/// "endColumn" : 13,
-/// "warning" : false,
-/// "errorType" : "Error parsing SQL",
-/// "message" : "Encountered \"<EOF>\" at line 14, column 13."
-/// } ]
-/// ```
-#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, ToSchema, Clone)]
-#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct SqlCompilerMessage {
-    start_line_number: usize,
-    start_column: usize,
-    end_line_number: usize,
-    end_column: usize,
-    warning: bool,
-    error_type: String,
-    message: String,
-}
-
 /// Program compilation status.
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, ToSchema, Clone)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
@@ -83,6 +62,10 @@ pub(crate) enum ProgramStatus {
     /// Compilation request received from the user; program has been placed
     /// in the queue.
     Pending,
+    /// The program is waiting in the compilation queue at the given position,
+    /// starting at 1.  Reported by the API instead of `Pending`.
+    #[cfg_attr(test, proptest(skip))]
+    Queued(u64),
     /// Compilation of SQL -> Rust in progress.
     CompilingSql,
     /// Compiling Rust -> executable in progress.
@@ -91,11 +74,14 @@ pub(crate) enum ProgramStatus {
     #[cfg_attr(test, proptest(weight = 2))]
     Success,
     /// SQL compiler returned an error.
-    SqlError(Vec<SqlCompilerMessage>),
+    SqlError(Vec<CompilerDiagnostic>),
     /// Rust compiler returned an error.
-    RustError(String),
+    RustError(Vec<CompilerDiagnostic>),
     /// System/OS returned an error when trying to invoke commands.
     SystemError(String),
+    /// The user cancelled the compilation.  The program is compiled again
+    /// when its code or configuration changes.
+    Cancelled,
 }
 
 impl ProgramStatus {
@@ -125,6 +111,12 @@ impl ProgramStatus {
 pub struct ProgramConfig {
     /// Request a compilation profile.
     pub profile: CompilationProfile,
+    /// Maintain the contents of all tables and views of the program, so that
+    /// clients can query snapshots of them through the egress endpoint
+    /// (`mode=snapshot&query=table`).  Requires memory proportional to the
+    /// size of all tables and views.
+    #[serde(default)]
+    pub materialized: bool,
 }
 
 static METRICS: Lazy<CompilerMetrics> = Lazy::new(init_metrics);
@@ -135,6 +127,7 @@ static METRICS: Lazy<CompilerMetrics> = Lazy::new(init_metrics);
 pub struct CompilerMetrics {
     invocations: Family<MetricLabel, Counter>,
     latency: Family<MetricLabel, Histogram>,
+    cache_lookups: Family<CacheLabel, Counter>,
 }
 
 /// We break down metrics in this file by the compiler phase and exit status
@@ -158,6 +151,19 @@ enum Status {
     Error,
 }
 
+/// Binary cache lookups are broken down by whether the lookup found a cached
+/// binary or the program had to be compiled.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CacheLabel {
+    status: CacheStatus,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+enum CacheStatus {
+    Hit,
+    Miss,
+}
+
 fn init_metrics() -> CompilerMetrics {
     CompilerMetrics {
         invocations: Family::<MetricLabel, Counter>::default(),
@@ -166,6 +172,7 @@ fn init_metrics() -> CompilerMetrics {
             let buckets = [1.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 400.0];
             Histogram::new(buckets.into_iter())
         }),
+        cache_lookups: Family::<CacheLabel, Counter>::default(),
     }
 }
 
@@ -181,6 +188,11 @@ pub fn register_metrics(registry: &mut Registry) {
         Unit::Seconds,
         METRICS.latency.clone(),
     );
+    registry.register(
+        "binary_cache_lookups",
+        "Number of compiled-binary cache lookups by status (hit vs miss)",
+        METRICS.cache_lookups.clone(),
+    );
 }
 
 fn record(stage: StageType, status: Status, elapsed: f64) {
@@ -189,6 +201,13 @@ fn record(stage: StageType, status: Status, elapsed: f64) {
     METRICS.latency.get_or_create(&label).observe(elapsed);
 }
 
+fn record_cache(status: CacheStatus) {
+    METRICS
+        .cache_lookups
+        .get_or_create(&CacheLabel { status })
+        .inc();
+}
+
 pub struct Compiler {}
 
 /// The `main` function injected in each generated pipeline
@@ -299,6 +318,15 @@ impl Compiler {
     /// file, making sure that subsequent `cargo` runs do not access the
     /// network.
     pub async fn precompile_dependencies(config: &CompilerConfig) -> Result<(), ManagerError> {
+        // Each compilation worker builds in its own workspace.
+        for worker in 0..config.compilation_workers.max(1) {
+            Self::precompile_workspace(&config.for_worker(worker)).await?;
+        }
+        Ok(())
+    }
+
+    /// Precompile Rust dependencies in the workspace of `config`.
+    async fn precompile_workspace(config: &CompilerConfig) -> Result<(), ManagerError> {
         let program_id = ProgramId(Uuid::nil());
 
         Self::create_working_directory(config).await?;
@@ -358,9 +386,14 @@ impl Compiler {
                         e,
                     )
                 })?;
+            let errors: Vec<String> = diagnostics::rust_errors(&stdout, &stderr, exit_status, "")
+                .iter()
+                .map(ToString::to_string)
+                .collect();
             return Err(ManagerError::RustCompilerError {
                 error: format!(
-                    "Failed to precompile Rust dependencies\nstdout:\n{stdout}\nstderr:\n{stderr}"
+                    "Failed to precompile Rust dependencies\n{}",
+                    errors.join("\n")
                 ),
             });
         }
@@ -408,11 +441,15 @@ impl Compiler {
             .current_dir(&config.workspace_dir())
             .arg("build")
             .arg("--workspace")
+            .arg("--message-format=json")
             .arg("--profile")
             .arg(profile.to_string())
             .stdin(Stdio::null())
             .stderr(Stdio::from(err_file.into_std().await))
-            .stdout(Stdio::from(out_file.into_std().await));
+            .stdout(Stdio::from(out_file.into_std().await))
+            // Run `cargo` and the `rustc` processes it spawns in their own
+            // process group, so that cancelling the job kills all of them.
+            .process_group(0);
 
         command
             .spawn()
@@ -456,15 +493,189 @@ impl Compiler {
         db.create_compiled_binary_ref(
             program_id,
             version,
-            format!(
-                "http://{}:{}/binary/{program_id}/{version}",
-                config.binary_ref_host, config.binary_ref_port
-            ),
+            Self::binary_ref(config, program_id, version),
         )
         .await?;
         Ok(())
     }
 
+    /// URL at which the compiler serves the binary of a program version.
+    fn binary_ref(config: &CompilerConfig, program_id: ProgramId, version: Version) -> String {
+        format!(
+            "http://{}:{}/binary/{program_id}/{version}",
+            config.binary_ref_host, config.binary_ref_port
+        )
+    }
+
+    /// Sets the status of a program whose compilation succeeded or failed and
+    /// notifies the webhooks subscribed to the outcome.
+    async fn set_final_status(
+        db: &ProjectDB,
+        tenant_id: TenantId,
+        program_id: ProgramId,
+        program_name: &str,
+        version: Version,
+        status: ProgramStatus,
+    ) -> Result<(), ManagerError> {
+        db.set_program_status_guarded(tenant_id, program_id, version, status.clone())
+            .await?;
+        // The status is set even if the webhooks cannot be notified.
+        if let Err(e) = webhooks::queue_program_status(
+            db,
+            tenant_id,
+            program_id,
+            program_name,
+            version,
+            &status,
+        )
+        .await
+        {
+            error!("Unable to queue webhook deliveries of program {program_id}: {e}");
+        }
+        Ok(())
+    }
+
+    /// Key of the program in the binary cache, or `None` if the cache is
+    /// disabled.
+    fn cache_key(config: &CompilerConfig, program: &ProgramDescr) -> Option<String> {
+        if config.binary_cache_max_entries == 0 {
+            return None;
+        }
+        // Always pick the compiler server's profile if it is configured, instead of
+        // the one the program self-specifies.
+        let profile = config
+            .compilation_profile
+            .as_ref()
+            .unwrap_or(&program.config.profile);
+        let code = program.code.as_ref()?;
+        Some(cache::content_hash(
+            config,
+            code,
+            profile,
+            program.config.materialized,
+        ))
+    }
+
+    /// Reuse a cached binary compiled from the same SQL code with the same
+    /// profile and compiler instead of compiling the program.
+    ///
+    /// Moves the program to the `CompilingRust` state, which claims it like a
+    /// regular compilation, and returns the key of the cached binary, which
+    /// [`Self::finish_from_cache`] copies once the database is unlocked.
+    /// Returns `None` if no such binary is cached.
+    async fn claim_from_cache(
+        config: &CompilerConfig,
+        db: &ProjectDB,
+        tenant_id: TenantId,
+        program: &ProgramDescr,
+    ) -> Result<Option<String>, ManagerError> {
+        let Some(hash) = Self::cache_key(config, program) else {
+            return Ok(None);
+        };
+        let Some((schema, warnings)) = cache::lookup(config, &hash).await else {
+            record_cache(CacheStatus::Miss);
+            return Ok(None);
+        };
+        let program_id = program.program_id;
+        let version = program.version;
+        info!("Reusing cached binary {hash} for program {program_id} version {version} (tenant {tenant_id}).");
+
+        // Go through the same states as a regular compilation, so that the
+        // local state reconciler handles a failure half-way through.
+        db.update_program(
+            tenant_id,
+            program_id,
+            &None,
+            &None,
+            &None,
+            &Some(ProgramStatus::CompilingRust),
+            &Some(schema),
+            &None,
+            Some(version),
+            None,
+        )
+        .await?;
+        db.set_program_warnings(tenant_id, program_id, version, &warnings)
+            .await?;
+        Ok(Some(hash))
+    }
+
+    /// Copy the cached binary `hash` claimed by [`Self::claim_from_cache`]
+    /// into the binaries directory and complete the compilation of the
+    /// program.
+    ///
+    /// The database is only locked once the binary is copied.
+    async fn finish_from_cache(
+        config: &CompilerConfig,
+        db: &Mutex<ProjectDB>,
+        tenant_id: TenantId,
+        program: &ProgramDescr,
+        hash: &str,
+    ) -> Result<(), ManagerError> {
+        let program_id = program.program_id;
+        let version = program.version;
+        let source = config.cached_executable(hash);
+        let destination = config.versioned_executable(program_id, version);
+        fs::copy(&source, &destination).await.map_err(|e| {
+            ManagerError::io_error(
+                format!(
+                    "copying '{}' to '{}'",
+                    source.display(),
+                    destination.display()
+                ),
+                e,
+            )
+        })?;
+
+        let db = db.lock().await;
+        // The program may have been updated or the compilation cancelled
+        // during the copy, in which case the binary is discarded.
+        match db.get_program_by_id(tenant_id, program_id, false).await {
+            Ok(descr) if descr.version == version && descr.status.is_compiling() => {}
+            Ok(_) | Err(DBError::UnknownProgram { .. }) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        db.create_compiled_binary_ref(
+            program_id,
+            version,
+            Self::binary_ref(config, program_id, version),
+        )
+        .await?;
+        Self::set_final_status(
+            &db,
+            tenant_id,
+            program_id,
+            &program.name,
+            version,
+            ProgramStatus::Success,
+        )
+        .await?;
+        record_cache(CacheStatus::Hit);
+        Ok(())
+    }
+
+    /// Add the binary of a successfully compiled program, along with the
+    /// warnings reported while compiling it, to the binary cache.
+    ///
+    /// Failing to cache the binary does not fail the compilation.
+    async fn cache_binary(
+        config: &CompilerConfig,
+        program: &ProgramDescr,
+        warnings: &[CompilerDiagnostic],
+    ) {
+        let Some(hash) = Self::cache_key(config, program) else {
+            return;
+        };
+        let binary = config.versioned_executable(program.program_id, program.version);
+        let schema_path = config.schema_path(program.program_id);
+        if let Err(e) = cache::insert(config, &hash, &binary, &schema_path, warnings).await {
+            warn!(
+                "Unable to cache the binary of program {} version {}: {e}",
+                program.program_id, program.version
+            );
+        }
+    }
+
     /// Generate workspace-level `Cargo.toml`.
     async fn write_workspace_toml(
         config: &CompilerConfig,
@@ -756,6 +967,19 @@ inherits = "release"
         db: Arc<Mutex<ProjectDB>>,
     ) -> Result<(), ManagerError> {
         Self::reconcile_local_state(&config, &db).await?;
+        let workers = (0..config.compilation_workers.max(1))
+            .map(|worker| Self::compiler_worker(config.for_worker(worker), db.clone()));
+        try_join_all(workers).await?;
+        Ok(())
+    }
+
+    /// Compiles programs from the queue, one at a time, in the workspace of
+    /// `config`.
+    async fn compiler_worker(
+        config: CompilerConfig,
+        db: Arc<Mutex<ProjectDB>>,
+    ) -> Result<(), ManagerError> {
+        Self::create_working_directory(&config).await?;
         loop {
             let res = Self::compiler_task_inner(&config, &db).await;
             // Look for benign errors that the compiler can run into, in which case,
@@ -816,9 +1040,22 @@ inherits = "release"
                     let tenant_id = job.as_ref().unwrap().tenant_id;
                     let program_id = job.as_ref().unwrap().program.program_id;
                     let version = job.as_ref().unwrap().program.version;
+                    let program_name = job.as_ref().unwrap().program.name.clone();
                     let elapsed = job.as_ref().unwrap().stage_start_time.elapsed().as_secs_f64();
                     let db = db.lock().await;
 
+                    // The program may have been updated or the compilation
+                    // cancelled since the last poll, in which case the
+                    // outcome of the job is discarded.
+                    match db.get_program_by_id(tenant_id, program_id, false).await {
+                        Ok(descr) if descr.version == version && descr.status.is_compiling() => {}
+                        Ok(_) | Err(DBError::UnknownProgram { .. }) => {
+                            job = None;
+                            continue;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+
                     match exit_status {
                         Ok(status) if status.success() && job.as_ref().unwrap().is_sql() => {
                             record(StageType::Sql, Status::Success, elapsed);
@@ -847,15 +1084,26 @@ inherits = "release"
                                 Some(version),
                                 None
                             ).await?;
+                            // The SQL compiler also reports warnings when it succeeds.
+                            let warnings = job.as_ref().unwrap().sql_warnings(config).await;
+                            db.set_program_warnings(tenant_id, program_id, version, &warnings).await?;
+                            job.as_mut().unwrap().warnings = warnings;
 
                             info!("Invoking rust compiler for program {program_id} version {version} (tenant {tenant_id}). This will take a while.");
                             debug!("Set ProgramStatus::CompilingRust '{program_id}', version '{version}'");
                             job = Some(CompilationJob::rust(config, job.as_ref().unwrap()).await?);
                         }
                         Ok(status) if status.success() && job.as_ref().unwrap().is_rust() => {
+                            // Add the warnings of the Rust compiler to those of the SQL compiler.
+                            let rust_warnings = job.as_ref().unwrap().rust_warnings(config).await;
+                            if !rust_warnings.is_empty() {
+                                job.as_mut().unwrap().warnings.extend(rust_warnings);
+                                db.set_program_warnings(tenant_id, program_id, version, &job.as_ref().unwrap().warnings).await?;
+                            }
                             Self::version_binary(config, &db, &job.as_ref().unwrap().program).await?;
+                            Self::cache_binary(config, &job.as_ref().unwrap().program, &job.as_ref().unwrap().warnings).await;
                             // Rust compiler succeeded -- declare victory.
-                            db.set_program_status_guarded(tenant_id, program_id, version, ProgramStatus::Success).await?;
+                            Self::set_final_status(&db, tenant_id, program_id, &program_name, version, ProgramStatus::Success).await?;
                             info!("Successfully invoked rust compiler for program {program_id} version {version} (tenant {tenant_id}).");
                             debug!("Set ProgramStatus::Success '{program_id}', version '{version}'");
                             record(StageType::Rust, Status::Success, elapsed);
@@ -864,21 +1112,25 @@ inherits = "release"
                         Ok(status) => {
                             // Compilation failed - update program status with the compiler
                             // error message.
-                            let output = job.as_ref().unwrap().error_output(config).await?;
                             let status = if job.as_ref().unwrap().is_rust() {
-                                ProgramStatus::RustError(format!("{output}\nexit code: {status}"))
-                            } else if let Ok(messages) = serde_json::from_str(&output) {
-                                // If we can parse the SqlCompilerMessages
-                                // as JSON, we assume the compiler worked:
-                                record(StageType::Sql, Status::Error, elapsed);
-                                ProgramStatus::SqlError(messages)
+                                let (stdout, stderr) = job.as_ref().unwrap().rust_output(config).await?;
+                                let rust_code = job.as_ref().unwrap().rust_code(config).await;
+                                ProgramStatus::RustError(diagnostics::rust_errors(&stdout, &stderr, status, &rust_code))
                             } else {
-                                // Otherwise something unexpected happened
-                                // and we return a system error:
+                                let output = job.as_ref().unwrap().error_output(config).await?;
                                 record(StageType::Sql, Status::Error, elapsed);
-                                ProgramStatus::SystemError(format!("{output}\nexit code: {status}"))
+                                if let Ok(messages) = serde_json::from_str(&output) {
+                                    // If we can parse the SqlCompilerMessages
+                                    // as JSON, we assume the compiler worked:
+                                    let code = job.as_ref().unwrap().program.code.as_deref().unwrap_or_default();
+                                    ProgramStatus::SqlError(diagnostics::sql_diagnostics(messages, code))
+                                } else {
+                                    // Otherwise something unexpected happened
+                                    // and we return a system error:
+                                    ProgramStatus::SystemError(format!("{output}\nexit code: {status}"))
+                                }
                             };
-                            db.set_program_status_guarded(tenant_id, program_id, version, status).await?;
+                            Self::set_final_status(&db, tenant_id, program_id, &program_name, version, status).await?;
                             job = None;
                         }
                         Err(e) => {
@@ -889,29 +1141,32 @@ inherits = "release"
                                 record(StageType::Sql, Status::Error, elapsed);
                                 ProgramStatus::SystemError(format!("I/O error with sql-to-dbsp: {e}"))
                             };
-                            db.set_program_status_guarded(tenant_id, program_id, version, status).await?;
+                            Self::set_final_status(&db, tenant_id, program_id, &program_name, version, status).await?;
                             job = None;
                         }
                     }
                 }
             }
-            // Pick the next program from the queue.
+            // Pick the next program from the queue.  The database stays
+            // locked until the program leaves the `Pending` state, so that
+            // other workers do not pick the same program, but not while the
+            // compiler starts: if the program changes in the meantime, the
+            // poll above cancels the job.
             if job.is_none() {
-                let program = {
-                    let db = db.lock().await;
-                    if let Some((tenant_id, program_id, version)) = db.next_job().await? {
-                        trace!("Next program in the queue: '{program_id}', version '{version}'");
-                        let program = db.get_program_by_id(tenant_id, program_id, true).await?;
-                        Some((tenant_id, program))
-                    } else {
-                        None
+                let locked = db.lock().await;
+                if let Some((tenant_id, program_id, version)) = locked.next_job().await? {
+                    trace!("Next program in the queue: '{program_id}', version '{version}'");
+                    let program = locked
+                        .get_program_by_id(tenant_id, program_id, true)
+                        .await?;
+                    if let Some(hash) =
+                        Self::claim_from_cache(config, &locked, tenant_id, &program).await?
+                    {
+                        drop(locked);
+                        Self::finish_from_cache(config, db, tenant_id, &program, &hash).await?;
+                        continue;
                     }
-                };
-
-                if let Some((tenant_id, program)) = program {
-                    job = Some(CompilationJob::sql(tenant_id, config, &program).await?);
-                    db.lock()
-                        .await
+                    locked
                         .set_program_status_guarded(
                             tenant_id,
                             program.program_id,
@@ -919,6 +1174,8 @@ inherits = "release"
                             ProgramStatus::CompilingSql,
                         )
                         .await?;
+                    drop(locked);
+                    job = Some(CompilationJob::sql(tenant_id, config, &program).await?);
                 }
             }
         }
@@ -937,6 +1194,8 @@ struct CompilationJob {
     compiler_process: Child,
     stage_start_time: Instant,
     program: ProgramDescr,
+    /// Warnings reported by the SQL compiler.
+    warnings: Vec<CompilerDiagnostic>,
 }
 
 impl CompilationJob {
@@ -1001,7 +1260,8 @@ impl CompilationJob {
 
         // Run compiler, direct output to `main.rs`.
         let schema_path = config.schema_path(program_id);
-        let compiler_process = Command::new(config.sql_compiler_path())
+        let mut command = Command::new(config.sql_compiler_path());
+        command
             .arg("-js")
             .arg(schema_path)
             .arg(sql_file_path.as_os_str())
@@ -1011,10 +1271,15 @@ impl CompilationJob {
             .arg("--outputsAreSets")
             .arg("--ignoreOrder")
             .arg("--unquotedCasing")
-            .arg("lower")
+            .arg("lower");
+        if program.config.materialized {
+            command.arg("--materialized");
+        }
+        let compiler_process = command
             .stdin(Stdio::null())
             .stderr(Stdio::from(err_file.into_std().await))
             .stdout(Stdio::from(rust_file.into_std().await))
+            .process_group(0)
             .spawn()
             .map_err(|e| {
                 ManagerError::io_error(
@@ -1029,6 +1294,7 @@ impl CompilationJob {
             program: program.clone(),
             compiler_process,
             stage_start_time: Instant::now(),
+            warnings: Vec::new(),
         })
     }
 
@@ -1069,6 +1335,7 @@ impl CompilationJob {
             program: job.program.clone(),
             compiler_process,
             stage_start_time: Instant::now(),
+            warnings: job.warnings.clone(),
         })
     }
 
@@ -1091,14 +1358,7 @@ impl CompilationJob {
                 })?
             }
             Stage::Rust => {
-                let stdout_path = config.compiler_stdout_path(self.program.program_id);
-                let stdout = fs::read_to_string(&stdout_path).await.map_err(|e| {
-                    ManagerError::io_error(format!("reading '{}'", stdout_path.display()), e)
-                })?;
-                let stderr_path = config.compiler_stderr_path(self.program.program_id);
-                let stderr = fs::read_to_string(&stderr_path).await.map_err(|e| {
-                    ManagerError::io_error(format!("reading '{}'", stderr_path.display()), e)
-                })?;
+                let (stdout, stderr) = self.rust_output(config).await?;
                 format!("stdout:\n{stdout}\nstderr:\n{stderr}")
             }
         };
@@ -1106,18 +1366,92 @@ impl CompilationJob {
         Ok(output)
     }
 
+    /// Read the stdout and stderr of the Rust compiler.
+    async fn rust_output(&self, config: &CompilerConfig) -> Result<(String, String), ManagerError> {
+        let stdout_path = config.compiler_stdout_path(self.program.program_id);
+        let stdout = fs::read_to_string(&stdout_path).await.map_err(|e| {
+            ManagerError::io_error(format!("reading '{}'", stdout_path.display()), e)
+        })?;
+        let stderr_path = config.compiler_stderr_path(self.program.program_id);
+        let stderr = fs::read_to_string(&stderr_path).await.map_err(|e| {
+            ManagerError::io_error(format!("reading '{}'", stderr_path.display()), e)
+        })?;
+        Ok((stdout, stderr))
+    }
+
+    /// Read the generated Rust code of the program.
+    ///
+    /// Returns an empty string if it cannot be read.
+    async fn rust_code(&self, config: &CompilerConfig) -> String {
+        fs::read_to_string(config.rust_program_path(self.program.program_id))
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Read the warnings reported by a successful run of the Rust compiler
+    /// for the program.
+    ///
+    /// Returns no warnings if they cannot be read.
+    async fn rust_warnings(&self, config: &CompilerConfig) -> Vec<CompilerDiagnostic> {
+        let program_id = self.program.program_id;
+        let Ok(stdout) = fs::read_to_string(config.compiler_stdout_path(program_id)).await else {
+            return Vec::new();
+        };
+        let rust_code = self.rust_code(config).await;
+        diagnostics::rust_warnings(&stdout, &rust_code, &CompilerConfig::crate_name(program_id))
+    }
+
+    /// Read the warnings reported by a successful run of the SQL compiler.
+    ///
+    /// Returns no warnings if they cannot be read.
+    async fn sql_warnings(&self, config: &CompilerConfig) -> Vec<CompilerDiagnostic> {
+        let stderr_path = config.compiler_stderr_path(self.program.program_id);
+        let Ok(output) = fs::read_to_string(&stderr_path).await else {
+            return Vec::new();
+        };
+        if output.trim().is_empty() {
+            return Vec::new();
+        }
+        match serde_json::from_str(&output) {
+            Ok(messages) => {
+                let code = self.program.code.as_deref().unwrap_or_default();
+                diagnostics::sql_diagnostics(messages, code)
+            }
+            Err(e) => {
+                warn!(
+                    "Unable to parse the warnings of the SQL compiler for program {}: {e}",
+                    self.program.program_id
+                );
+                Vec::new()
+            }
+        }
+    }
+
     /// Kill (Rust or SQL) compiler process.
     async fn cancel(&mut self) {
-        let _ = self.compiler_process.kill().await;
+        kill_process_group(&mut self.compiler_process).await;
     }
 }
 
+/// Kills `child` along with the processes in its process group, e.g., the
+/// `rustc` processes spawned by `cargo`, and waits for `child` to exit.
+async fn kill_process_group(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        // SAFETY: `killpg` has no memory safety requirements.  `child` has not
+        // been reaped, so its process group still exists.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    let _ = child.kill().await;
+}
+
 #[cfg(test)]
 mod test {
     use std::{fs::File, sync::Arc};
 
     use tempfile::TempDir;
-    use tokio::{fs, sync::Mutex};
+    use tokio::{fs, io::AsyncBufReadExt, sync::Mutex};
     use uuid::Uuid;
 
     use crate::{
@@ -1139,6 +1473,7 @@ mod test {
                 "ignored",
                 &super::ProgramConfig {
                     profile: CompilationProfile::Unoptimized,
+                    materialized: false,
                 },
                 None,
             )
@@ -1170,6 +1505,9 @@ mod test {
             compiler_working_directory: workdir.to_owned(),
             binary_ref_host: "127.0.0.1".to_string(),
             binary_ref_port: 9090,
+            binary_cache_max_entries: 64,
+            compilation_workers: 1,
+            worker: 0,
         };
 
         let (db, _temp) = crate::db::test::setup_pg().await;
@@ -1235,6 +1573,9 @@ mod test {
             compiler_working_directory: workdir.to_owned(),
             binary_ref_host: "127.0.0.1".to_string(),
             binary_ref_port: 9090,
+            binary_cache_max_entries: 64,
+            compilation_workers: 1,
+            worker: 0,
         };
 
         let (db, _temp) = crate::db::test::setup_pg().await;
@@ -1281,6 +1622,9 @@ mod test {
             compiler_working_directory: workdir.to_owned(),
             binary_ref_host: "127.0.0.1".to_string(),
             binary_ref_port: 9090,
+            binary_cache_max_entries: 64,
+            compilation_workers: 1,
+            worker: 0,
         };
 
         let (db, _temp) = crate::db::test::setup_pg().await;
@@ -1334,4 +1678,126 @@ mod test {
         assert!(!path1.exists());
         assert!(!path2.exists());
     }
+
+    #[tokio::test]
+    async fn test_compiler_binary_cache_hit() {
+        let tid = TenantRecord::default().id;
+        let tmp_dir = TempDir::new().unwrap();
+        let workdir = tmp_dir.path().to_str().unwrap();
+        let conf = CompilerConfig {
+            sql_compiler_home: "".to_owned(),
+            dbsp_override_path: "../../".to_owned(),
+            compilation_profile: Some(crate::config::CompilationProfile::Unoptimized),
+            precompile: false,
+            compiler_working_directory: workdir.to_owned(),
+            binary_ref_host: "127.0.0.1".to_string(),
+            binary_ref_port: 9090,
+            binary_cache_max_entries: 64,
+            compilation_workers: 1,
+            worker: 0,
+        };
+        fs::create_dir(conf.binaries_dir()).await.unwrap();
+
+        let (db, _temp) = crate::db::test::setup_pg().await;
+        let db = Arc::new(Mutex::new(db));
+        let (pid, vid) = create_program(&db, "p1").await;
+        db.lock()
+            .await
+            .set_program_status_guarded(tid, pid, vid, ProgramStatus::Pending)
+            .await
+            .unwrap();
+        let program = db
+            .lock()
+            .await
+            .get_program_by_id(tid, pid, true)
+            .await
+            .unwrap();
+
+        // Nothing is cached yet.
+        assert!(
+            super::Compiler::claim_from_cache(&conf, &*db.lock().await, tid, &program)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Simulate an earlier compilation of the same code.
+        let hash = super::Compiler::cache_key(&conf, &program).unwrap();
+        fs::create_dir(conf.binary_cache_dir()).await.unwrap();
+        fs::write(conf.cached_executable(&hash), "binary")
+            .await
+            .unwrap();
+        fs::write(
+            conf.cached_schema_path(&hash),
+            r#"{"inputs":[],"outputs":[]}"#,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            Some(hash.clone()),
+            super::Compiler::claim_from_cache(&conf, &*db.lock().await, tid, &program)
+                .await
+                .unwrap()
+        );
+        super::Compiler::finish_from_cache(&conf, &db, tid, &program, &hash)
+            .await
+            .unwrap();
+        let program = db
+            .lock()
+            .await
+            .get_program_by_id(tid, pid, false)
+            .await
+            .unwrap();
+        assert_eq!(program.status, ProgramStatus::Success);
+        assert!(program.schema.is_some());
+        assert_eq!(
+            fs::read_to_string(conf.versioned_executable(pid, vid))
+                .await
+                .unwrap(),
+            "binary"
+        );
+        assert_eq!(
+            db.lock()
+                .await
+                .get_compiled_binary_ref(pid, vid)
+                .await
+                .unwrap(),
+            Some(format!("http://127.0.0.1:9090/binary/{pid}/{vid}"))
+        );
+    }
+
+    /// Cancelling a job kills the processes spawned by the compiler, e.g., the
+    /// `rustc` processes spawned by `cargo`, not just the compiler itself.
+    #[tokio::test]
+    async fn cancel_kills_process_group() {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg("sleep 60 & echo $!; wait")
+            .stdout(std::process::Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        tokio::io::BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .await
+            .unwrap();
+        let grandchild: u32 = line.trim().parse().unwrap();
+
+        super::kill_process_group(&mut child).await;
+
+        // The grandchild is reaped by init and may remain a zombie until then.
+        let stat = format!("/proc/{grandchild}/stat");
+        let killed = || {
+            std::fs::read_to_string(&stat)
+                .map(|stat| stat.contains(") Z "))
+                .unwrap_or(true)
+        };
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !killed() {
+            assert!(std::time::Instant::now() < deadline);
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    }
 }
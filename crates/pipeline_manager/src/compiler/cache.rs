@@ -0,0 +1,462 @@
+//! Cache of compiled binaries keyed by program content.
+//!
+//! A program's binary only depends on its SQL code, the compilation profile,
+//! whether its tables and views are materialized and the version of the
+//! compiler toolchain, so two programs (or two versions of the same program)
+//! that agree on all of them can share a binary.  The cache
+//! stores each binary under a hash of these inputs, next to the schema and
+//! the warnings produced by the SQL compiler, in
+//! `<working-directory>/binary_cache`.
+//!
+//! Cache entries are touched on every hit, and the least recently used entries
+//! are evicted once the cache holds more than
+//! [`CompilerConfig::binary_cache_max_entries`] binaries.
+use super::CompilerDiagnostic;
+use crate::config::{CompilationProfile, CompilerConfig};
+use crate::error::ManagerError;
+use log::{debug, warn};
+use openssl::sha::Sha256;
+use pipeline_types::program_schema::ProgramSchema;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tempfile::NamedTempFile;
+use tokio::fs;
+
+/// Extension of the file storing the schema of a cached binary.
+const SCHEMA_SUFFIX: &str = ".schema.json";
+
+/// Extension of the file storing the compiler warnings of a cached binary.
+const WARNINGS_SUFFIX: &str = ".warnings.json";
+
+/// Extension of the temporary files that cache entries are written to before
+/// they are renamed into place.
+const TMP_SUFFIX: &str = ".tmp";
+
+/// Age after which `evict` considers temporary files and files without a
+/// binary to be left behind by an interrupted insert, rather than to belong to
+/// an insert in progress in another compiler worker.
+const STALE_AGE: Duration = Duration::from_secs(3600);
+
+/// Computes the cache key of a program.
+///
+/// The compiler version is identified by the version of the pipeline manager
+/// together with the location of the SQL compiler and of the DBSP crates the
+/// generated code depends on.  Upgrading either in place requires clearing the
+/// cache directory.
+pub(crate) fn content_hash(
+    config: &CompilerConfig,
+    code: &str,
+    profile: &CompilationProfile,
+    materialized: bool,
+) -> String {
+    let mut hasher = Sha256::new();
+    // Length-prefix each input, so that different inputs cannot produce the
+    // same byte sequence.
+    for input in [
+        env!("CARGO_PKG_VERSION"),
+        &config.sql_compiler_home,
+        &config.dbsp_override_path,
+        &profile.to_string(),
+        if materialized { "materialized" } else { "" },
+        code,
+    ] {
+        hasher.update(&(input.len() as u64).to_le_bytes());
+        hasher.update(input.as_bytes());
+    }
+    hasher.finish().iter().fold(String::new(), |mut hash, b| {
+        let _ = write!(hash, "{b:02x}");
+        hash
+    })
+}
+
+/// Looks up a binary in the cache, returning the schema of the program and
+/// the warnings reported while compiling it if the binary is available at
+/// `config.cached_executable(hash)`.
+///
+/// Marks the entry as recently used.
+pub(crate) async fn lookup(
+    config: &CompilerConfig,
+    hash: &str,
+) -> Option<(ProgramSchema, Vec<CompilerDiagnostic>)> {
+    let binary = config.cached_executable(hash);
+    let schema_path = config.cached_schema_path(hash);
+    if !binary.is_file() {
+        return None;
+    }
+    let schema = match fs::read_to_string(&schema_path).await {
+        Ok(schema) => schema,
+        Err(e) => {
+            warn!("Ignoring cached binary {hash} with unreadable schema: {e}");
+            return None;
+        }
+    };
+    let schema = match serde_json::from_str(&schema) {
+        Ok(schema) => schema,
+        Err(e) => {
+            warn!("Ignoring cached binary {hash} with invalid schema: {e}");
+            return None;
+        }
+    };
+    // Entries cached without warnings have none.
+    let warnings = match fs::read_to_string(config.cached_warnings_path(hash)).await {
+        Ok(warnings) => serde_json::from_str(&warnings).unwrap_or_else(|e| {
+            warn!("Ignoring invalid warnings of cached binary {hash}: {e}");
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    if let Err(e) = touch(&binary) {
+        warn!("Unable to update the access time of cached binary {hash}: {e}");
+    }
+    Some((schema, warnings))
+}
+
+/// Adds the binary at `binary` with the schema at `schema_path` and
+/// `warnings` to the cache, then evicts the least recently used entries if
+/// the cache is full.
+pub(crate) async fn insert(
+    config: &CompilerConfig,
+    hash: &str,
+    binary: &Path,
+    schema_path: &Path,
+    warnings: &[CompilerDiagnostic],
+) -> Result<(), ManagerError> {
+    let cache_dir = config.binary_cache_dir();
+    fs::create_dir_all(&cache_dir)
+        .await
+        .map_err(|e| ManagerError::io_error(format!("creating '{}'", cache_dir.display()), e))?;
+
+    // Write the schema first: `lookup` ignores binaries without a schema,
+    // while a schema without a binary is removed by `evict`.  Each file is
+    // written to a uniquely named temporary file and renamed into place, so
+    // that concurrent lookups and inserts of the same entry never observe a
+    // partially written file.
+    let schema = fs::read(schema_path)
+        .await
+        .map_err(|e| ManagerError::io_error(format!("reading '{}'", schema_path.display()), e))?;
+    write_entry_file(&cache_dir, &config.cached_schema_path(hash), &schema).await?;
+    let warnings = serde_json::to_string(warnings).unwrap_or_else(|_| "[]".to_string());
+    write_entry_file(
+        &cache_dir,
+        &config.cached_warnings_path(hash),
+        warnings.as_bytes(),
+    )
+    .await?;
+
+    let cached_binary = config.cached_executable(hash);
+    let tmp = temp_file(&cache_dir)?;
+    // `fs::copy` preserves the permissions of the binary.
+    fs::copy(binary, tmp.path()).await.map_err(|e| {
+        ManagerError::io_error(
+            format!(
+                "copying '{}' to '{}'",
+                binary.display(),
+                tmp.path().display()
+            ),
+            e,
+        )
+    })?;
+    persist(tmp, &cached_binary)?;
+    debug!("Cached binary {hash}");
+
+    evict(config, config.binary_cache_max_entries).await
+}
+
+/// Removes the least recently used binaries until at most `max_entries`
+/// remain in the cache, along with stale schemas and temporary files that do
+/// not belong to a cached binary.
+pub(crate) async fn evict(config: &CompilerConfig, max_entries: usize) -> Result<(), ManagerError> {
+    let cache_dir = config.binary_cache_dir();
+    let mut entries = match fs::read_dir(&cache_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(ManagerError::io_error(
+                format!("reading '{}'", cache_dir.display()),
+                e,
+            ))
+        }
+    };
+
+    let mut binaries: Vec<(SystemTime, String)> = Vec::new();
+    let mut others: Vec<(SystemTime, String)> = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| ManagerError::io_error(format!("reading '{}'", cache_dir.display()), e))?
+    {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let modified = entry
+            .metadata()
+            .await
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        if name.ends_with(SCHEMA_SUFFIX)
+            || name.ends_with(WARNINGS_SUFFIX)
+            || name.ends_with(TMP_SUFFIX)
+        {
+            others.push((modified, name));
+        } else {
+            binaries.push((modified, name));
+        }
+    }
+
+    // Most recently used first.
+    binaries.sort_by(|a, b| b.cmp(a));
+    let evicted = binaries.split_off(binaries.len().min(max_entries));
+    for (_, hash) in &evicted {
+        debug!("Evicting cached binary {hash}");
+        remove(&config.cached_executable(hash)).await;
+        remove(&config.cached_schema_path(hash)).await;
+        remove(&config.cached_warnings_path(hash)).await;
+    }
+
+    // Schemas and warnings whose binary was never written, and temporary
+    // files, once they are old enough to have been left behind by an
+    // interrupted insert rather than to belong to an insert in progress.
+    let now = SystemTime::now();
+    for (modified, name) in others {
+        let stale = now
+            .duration_since(modified)
+            .is_ok_and(|age| age >= STALE_AGE);
+        let orphaned = match name
+            .strip_suffix(SCHEMA_SUFFIX)
+            .or_else(|| name.strip_suffix(WARNINGS_SUFFIX))
+        {
+            Some(hash) => !binaries.iter().any(|(_, h)| h == hash),
+            None => true,
+        };
+        if orphaned && stale {
+            remove(&cache_dir.join(name)).await;
+        }
+    }
+    Ok(())
+}
+
+/// Creates a uniquely named temporary file in `cache_dir`, which is removed
+/// if it is dropped before being persisted.
+fn temp_file(cache_dir: &Path) -> Result<NamedTempFile, ManagerError> {
+    tempfile::Builder::new()
+        .suffix(TMP_SUFFIX)
+        .tempfile_in(cache_dir)
+        .map_err(|e| {
+            ManagerError::io_error(
+                format!("creating a temporary file in '{}'", cache_dir.display()),
+                e,
+            )
+        })
+}
+
+/// Atomically replaces `path` with `tmp`.
+fn persist(tmp: NamedTempFile, path: &Path) -> Result<(), ManagerError> {
+    tmp.persist(path).map_err(|e| {
+        ManagerError::io_error(
+            format!(
+                "renaming '{}' to '{}'",
+                e.file.path().display(),
+                path.display()
+            ),
+            e.error,
+        )
+    })?;
+    Ok(())
+}
+
+/// Atomically replaces `path` with a file containing `contents`.
+async fn write_entry_file(
+    cache_dir: &Path,
+    path: &Path,
+    contents: &[u8],
+) -> Result<(), ManagerError> {
+    let tmp = temp_file(cache_dir)?;
+    fs::write(tmp.path(), contents)
+        .await
+        .map_err(|e| ManagerError::io_error(format!("writing '{}'", tmp.path().display()), e))?;
+    persist(tmp, path)
+}
+
+async fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!(
+                "Unable to remove '{}' from the binary cache: {e}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Sets the modification time of a cached binary to now.
+fn touch(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{content_hash, evict, insert, lookup, STALE_AGE};
+    use crate::compiler::{CompilerDiagnostic, DiagnosticSeverity};
+    use crate::config::{CompilationProfile, CompilerConfig};
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    fn config(workdir: &TempDir) -> CompilerConfig {
+        CompilerConfig {
+            sql_compiler_home: "".to_owned(),
+            dbsp_override_path: "../../".to_owned(),
+            compilation_profile: None,
+            precompile: false,
+            compiler_working_directory: workdir.path().to_str().unwrap().to_owned(),
+            binary_ref_host: "127.0.0.1".to_string(),
+            binary_ref_port: 9090,
+            binary_cache_max_entries: 2,
+            compilation_workers: 1,
+            worker: 0,
+        }
+    }
+
+    #[test]
+    fn hash_depends_on_all_inputs() {
+        let workdir = TempDir::new().unwrap();
+        let conf = config(&workdir);
+        let hash = content_hash(
+            &conf,
+            "create table t(a int);",
+            &CompilationProfile::Optimized,
+            false,
+        );
+        assert_eq!(hash.len(), 64);
+        assert_eq!(
+            hash,
+            content_hash(
+                &conf,
+                "create table t(a int);",
+                &CompilationProfile::Optimized,
+                false,
+            )
+        );
+        assert_ne!(
+            hash,
+            content_hash(
+                &conf,
+                "create table t(b int);",
+                &CompilationProfile::Optimized,
+                false,
+            )
+        );
+        assert_ne!(
+            hash,
+            content_hash(
+                &conf,
+                "create table t(a int);",
+                &CompilationProfile::Unoptimized,
+                false,
+            )
+        );
+        assert_ne!(
+            hash,
+            content_hash(
+                &conf,
+                "create table t(a int);",
+                &CompilationProfile::Optimized,
+                true,
+            )
+        );
+        let other = CompilerConfig {
+            sql_compiler_home: "/opt/sql-to-dbsp".to_owned(),
+            ..conf.clone()
+        };
+        assert_ne!(
+            hash,
+            content_hash(
+                &other,
+                "create table t(a int);",
+                &CompilationProfile::Optimized,
+                false,
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn lru_eviction() {
+        let workdir = TempDir::new().unwrap();
+        let conf = config(&workdir);
+        let binary = workdir.path().join("binary");
+        let schema = workdir.path().join("schema.json");
+        std::fs::write(&binary, "binary").unwrap();
+        std::fs::write(&schema, r#"{"inputs":[],"outputs":[]}"#).unwrap();
+
+        assert!(lookup(&conf, "h1").await.is_none());
+        let start = SystemTime::now() - Duration::from_secs(3600);
+        for (i, hash) in ["h1", "h2"].iter().enumerate() {
+            insert(&conf, hash, &binary, &schema, &[]).await.unwrap();
+            // Make access times deterministic.
+            std::fs::File::options()
+                .write(true)
+                .open(conf.cached_executable(hash))
+                .unwrap()
+                .set_modified(start + Duration::from_secs(i as u64))
+                .unwrap();
+        }
+        assert!(lookup(&conf, "h1").await.is_some());
+        assert!(lookup(&conf, "h2").await.is_some());
+        assert_eq!(
+            std::fs::read_to_string(conf.cached_executable("h1")).unwrap(),
+            "binary"
+        );
+
+        // Make `h2` the least recently used entry.
+        std::fs::File::options()
+            .write(true)
+            .open(conf.cached_executable("h2"))
+            .unwrap()
+            .set_modified(start)
+            .unwrap();
+        let warnings = vec![CompilerDiagnostic {
+            severity: DiagnosticSeverity::Warning,
+            error_type: "Unused".to_string(),
+            message: "Table is not used".to_string(),
+            start: None,
+            end: None,
+            snippet: None,
+        }];
+        insert(&conf, "h3", &binary, &schema, &warnings)
+            .await
+            .unwrap();
+        assert!(lookup(&conf, "h1").await.is_some());
+        assert!(lookup(&conf, "h2").await.is_none());
+        assert_eq!(lookup(&conf, "h3").await.unwrap().1, warnings);
+        assert!(!conf.cached_schema_path("h2").exists());
+        assert!(!conf.cached_warnings_path("h2").exists());
+
+        // A binary without a schema is not a hit.
+        std::fs::remove_file(conf.cached_schema_path("h3")).unwrap();
+        assert!(lookup(&conf, "h3").await.is_none());
+
+        evict(&conf, 0).await.unwrap();
+        assert_eq!(
+            std::fs::read_dir(conf.binary_cache_dir()).unwrap().count(),
+            0
+        );
+
+        // Temporary files and schemas without a binary may belong to an
+        // insert in progress, and are only removed once they are stale.
+        let fresh = conf.binary_cache_dir().join("fresh.tmp");
+        let stale = conf.binary_cache_dir().join("stale.tmp");
+        std::fs::write(&fresh, "binary").unwrap();
+        std::fs::write(conf.cached_schema_path("h4"), "{}").unwrap();
+        std::fs::File::create(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - STALE_AGE)
+            .unwrap();
+        evict(&conf, 0).await.unwrap();
+        assert!(fresh.exists());
+        assert!(conf.cached_schema_path("h4").exists());
+        assert!(!stale.exists());
+    }
+}
@@ -0,0 +1,411 @@
+//! Structured diagnostics reported by the SQL and Rust compilers.
+//!
+//! The SQL compiler reports errors and warnings as JSON (see
+//! [`SqlCompilerMessage`]), with positions in the SQL code of the program.
+//! `cargo` reports the diagnostics of `rustc` as JSON lines when invoked with
+//! `--message-format=json`.  Both are converted to [`CompilerDiagnostic`]s,
+//! which is what the API returns.
+//!
+//! The SQL compiler precedes the code of each operator in the generated Rust
+//! program with a comment such as `// DBSPMapOperator 75 at 3:1--5:10`, which
+//! holds the position of the operator in the SQL code, if known.  Diagnostics
+//! of `rustc` in the code of an operator get that position.
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::process::ExitStatus;
+use utoipa::ToSchema;
+
+/// A SQL compiler error.
+///
+/// The SQL compiler returns a list of errors in the following JSON format if
+/// it's invoked with the `-je` option.
+///
+/// ```ignore
+///  [ {
+/// "startLineNumber" : 14,
+/// "startColumn" : 13,
+/// "endLineNumber" : 14,
+/// "endColumn" : 13,
+/// "warning" : false,
+/// "errorType" : "Error parsing SQL",
+/// "message" : "Encountered \"<EOF>\" at line 14, column 13."
+/// } ]
+/// ```
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SqlCompilerMessage {
+    start_line_number: usize,
+    start_column: usize,
+    end_line_number: usize,
+    end_column: usize,
+    warning: bool,
+    error_type: String,
+    message: String,
+}
+
+/// Severity of a compiler diagnostic.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, ToSchema, Clone, Copy)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DiagnosticSeverity {
+    /// The program cannot be compiled.
+    Error,
+    /// The program compiles, but may not behave as intended.
+    Warning,
+}
+
+impl Display for DiagnosticSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticSeverity::Error => write!(f, "error"),
+            DiagnosticSeverity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A position in the SQL code of a program.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, ToSchema, Clone, Copy)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub(crate) struct SqlPosition {
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column number, starting at 1.
+    pub column: usize,
+}
+
+/// An error or warning reported while compiling a program.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, ToSchema, Clone)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub(crate) struct CompilerDiagnostic {
+    pub severity: DiagnosticSeverity,
+    /// Category of the problem, e.g., `Error parsing SQL` for the SQL
+    /// compiler or the error code for the Rust compiler.
+    pub error_type: String,
+    /// Description of the problem.
+    pub message: String,
+    /// Start of the offending SQL code, if the problem can be traced back to
+    /// the SQL code of the program.
+    pub start: Option<SqlPosition>,
+    /// End of the offending SQL code, inclusive.
+    pub end: Option<SqlPosition>,
+    /// The offending lines of SQL code or, for the Rust compiler, the
+    /// diagnostic as rendered by the compiler.
+    pub snippet: Option<String>,
+}
+
+impl Display for CompilerDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(start) = &self.start {
+            write!(f, " at {}:{}", start.line, start.column)?;
+        }
+        write!(f, ": {}: {}", self.error_type, self.message)?;
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{snippet}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<SqlCompilerMessage> for CompilerDiagnostic {
+    fn from(message: SqlCompilerMessage) -> Self {
+        // The SQL compiler reports line 0 for problems without a position.
+        let position = |line, column| (line > 0).then_some(SqlPosition { line, column });
+        let start = position(message.start_line_number, message.start_column);
+        let end = start.and(position(message.end_line_number, message.end_column));
+        Self {
+            severity: if message.warning {
+                DiagnosticSeverity::Warning
+            } else {
+                DiagnosticSeverity::Error
+            },
+            error_type: message.error_type,
+            message: message.message,
+            start,
+            end,
+            snippet: None,
+        }
+    }
+}
+
+/// Converts the messages of the SQL compiler into diagnostics, with snippets
+/// of `code`.
+pub(crate) fn sql_diagnostics(
+    messages: Vec<SqlCompilerMessage>,
+    code: &str,
+) -> Vec<CompilerDiagnostic> {
+    messages
+        .into_iter()
+        .map(|message| {
+            let mut diagnostic = CompilerDiagnostic::from(message);
+            diagnostic.snippet = snippet(code, diagnostic.start, diagnostic.end);
+            diagnostic
+        })
+        .collect()
+}
+
+/// Returns the lines of `code` from `start` to `end`.
+fn snippet(code: &str, start: Option<SqlPosition>, end: Option<SqlPosition>) -> Option<String> {
+    let start = start?.line;
+    let end = end.map_or(start, |end| end.line.max(start));
+    let lines: Vec<&str> = code.lines().skip(start - 1).take(end - start + 1).collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// A message printed by `cargo build --message-format=json`.
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    target: Option<CargoTarget>,
+    message: Option<RustcDiagnostic>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RustcDiagnostic {
+    message: String,
+    code: Option<RustcDiagnosticCode>,
+    level: String,
+    #[serde(default)]
+    spans: Vec<RustcSpan>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcDiagnosticCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: usize,
+    is_primary: bool,
+}
+
+/// Converts the messages of `cargo` in `stdout` into diagnostics, along with
+/// the name of the target they were reported for.  `rust_code` is the
+/// generated `main.rs` of the program.
+fn rust_diagnostics<'a>(
+    stdout: &'a str,
+    rust_code: &'a str,
+) -> impl Iterator<Item = (Option<String>, CompilerDiagnostic)> + 'a {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CargoMessage>(line).ok())
+        .filter(|message| message.reason == "compiler-message")
+        .filter_map(|message| Some((message.target, message.message?)))
+        .filter_map(move |(target, diagnostic)| {
+            let (severity, default_type) = if diagnostic.level.starts_with("error") {
+                (DiagnosticSeverity::Error, "Rust compiler error")
+            } else if diagnostic.level == "warning" {
+                (DiagnosticSeverity::Warning, "Rust compiler warning")
+            } else {
+                return None;
+            };
+            let (start, end) = diagnostic
+                .spans
+                .iter()
+                .find(|span| span.is_primary && span.file_name.ends_with("src/main.rs"))
+                .and_then(|span| operator_position(rust_code, span.line_start))
+                .unzip();
+            Some((
+                target.map(|target| target.name),
+                CompilerDiagnostic {
+                    severity,
+                    error_type: diagnostic
+                        .code
+                        .map_or_else(|| default_type.to_string(), |code| code.code),
+                    message: diagnostic.message,
+                    start,
+                    end,
+                    snippet: diagnostic.rendered,
+                },
+            ))
+        })
+}
+
+/// Returns the position in the SQL code of the operator whose Rust code
+/// contains `line` (starting at 1) of `rust_code`.
+fn operator_position(rust_code: &str, line: usize) -> Option<(SqlPosition, SqlPosition)> {
+    let comment = rust_code
+        .lines()
+        .take(line)
+        .filter_map(|line| line.trim_start().strip_prefix("// DBSP"))
+        .filter(|comment| {
+            comment
+                .split(' ')
+                .next()
+                .is_some_and(|operator| operator.ends_with("Operator"))
+        })
+        .last()?;
+    let (_, range) = comment.split_once(" at ")?;
+    let (start, end) = range.trim().split_once("--")?;
+    let position = |position: &str| {
+        let (line, column) = position.split_once(':')?;
+        Some(SqlPosition {
+            line: line.parse().ok()?,
+            column: column.parse().ok()?,
+        })
+    };
+    Some((position(start)?, position(end)?))
+}
+
+/// Extracts the warnings reported by `cargo build` for the program, i.e., for
+/// the target named `crate_name`, rather than for the SQL libraries.
+pub(crate) fn rust_warnings(
+    stdout: &str,
+    rust_code: &str,
+    crate_name: &str,
+) -> Vec<CompilerDiagnostic> {
+    rust_diagnostics(stdout, rust_code)
+        .filter(|(target, diagnostic)| {
+            diagnostic.severity == DiagnosticSeverity::Warning
+                && target.as_deref() == Some(crate_name)
+        })
+        .map(|(_, diagnostic)| diagnostic)
+        .collect()
+}
+
+/// Extracts the errors reported by a failed `cargo build`.
+///
+/// `stdout` holds the JSON messages of `cargo`.  Failures that are not
+/// reported by `rustc`, e.g., a failure to resolve dependencies or to link,
+/// are described in `stderr`, which is then returned as a single error.
+pub(crate) fn rust_errors(
+    stdout: &str,
+    stderr: &str,
+    status: ExitStatus,
+    rust_code: &str,
+) -> Vec<CompilerDiagnostic> {
+    let errors: Vec<CompilerDiagnostic> = rust_diagnostics(stdout, rust_code)
+        .map(|(_, diagnostic)| diagnostic)
+        .filter(|diagnostic| diagnostic.severity == DiagnosticSeverity::Error)
+        .collect();
+    if !errors.is_empty() {
+        return errors;
+    }
+    vec![CompilerDiagnostic {
+        severity: DiagnosticSeverity::Error,
+        error_type: "Rust compiler error".to_string(),
+        message: format!("{stderr}\nexit code: {status}"),
+        start: None,
+        end: None,
+        snippet: None,
+    }]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        rust_errors, rust_warnings, sql_diagnostics, CompilerDiagnostic, DiagnosticSeverity,
+        SqlCompilerMessage, SqlPosition,
+    };
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    #[test]
+    fn sql_messages() {
+        let messages: Vec<SqlCompilerMessage> = serde_json::from_str(
+            r#"[ {
+                "startLineNumber" : 2, "startColumn" : 8, "endLineNumber" : 3, "endColumn" : 4,
+                "warning" : false, "errorType" : "Error parsing SQL", "message" : "Encountered \"x\""
+            }, {
+                "startLineNumber" : 0, "startColumn" : 0, "endLineNumber" : 0, "endColumn" : 0,
+                "warning" : true, "errorType" : "Unused", "message" : "Table is not used"
+            } ]"#,
+        )
+        .unwrap();
+        let code = "CREATE TABLE t(a INT);\nCREATE VIEW v AS\nSELECT x FROM t;\n-- end";
+        assert_eq!(
+            sql_diagnostics(messages, code),
+            vec![
+                CompilerDiagnostic {
+                    severity: DiagnosticSeverity::Error,
+                    error_type: "Error parsing SQL".to_string(),
+                    message: "Encountered \"x\"".to_string(),
+                    start: Some(SqlPosition { line: 2, column: 8 }),
+                    end: Some(SqlPosition { line: 3, column: 4 }),
+                    snippet: Some("CREATE VIEW v AS\nSELECT x FROM t;".to_string()),
+                },
+                CompilerDiagnostic {
+                    severity: DiagnosticSeverity::Warning,
+                    error_type: "Unused".to_string(),
+                    message: "Table is not used".to_string(),
+                    start: None,
+                    end: None,
+                    snippet: None,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn cargo_messages() {
+        let stdout = r#"{"reason":"compiler-artifact","package_id":"dbsp"}
+{"reason":"compiler-message","message":{"message":"unused variable","code":null,"level":"warning","rendered":"warning: unused variable"}}
+{"reason":"compiler-message","message":{"message":"mismatched types","code":{"code":"E0308","explanation":null},"level":"error","rendered":"error[E0308]: mismatched types"}}
+{"reason":"build-finished","success":false}"#;
+        let errors = rust_errors(
+            stdout,
+            "error: could not compile",
+            ExitStatus::from_raw(256),
+            "",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error_type, "E0308");
+        assert_eq!(errors[0].message, "mismatched types");
+        assert_eq!(
+            errors[0].snippet.as_deref(),
+            Some("error[E0308]: mismatched types")
+        );
+
+        let errors = rust_errors("", "error: linking failed", ExitStatus::from_raw(256), "");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.starts_with("error: linking failed"));
+    }
+
+    #[test]
+    fn cargo_positions() {
+        let rust_code = "use dbsp::*;
+fn circuit() {
+    // DBSPSourceMultisetOperator 53 at 1:1--1:22
+    // CREATE TABLE t(a INT)
+    let stream53 = T();
+    // DBSPMapOperator 75
+    let stream75 = stream53.map(|t| t.0);
+    // DBSPSinkOperator 82 at 2:1--3:16
+    // CREATE VIEW v AS
+    // SELECT a FROM t
+    let stream82 = stream75.map(|t| t.1);
+}";
+        let stdout = r#"{"reason":"compiler-message","target":{"name":"project1"},"message":{"message":"no field `1`","code":{"code":"E0609"},"level":"error","spans":[{"file_name":"project1/src/main.rs","line_start":11,"is_primary":true}],"rendered":"error[E0609]"}}
+{"reason":"compiler-message","target":{"name":"project1"},"message":{"message":"mismatched types","code":null,"level":"error","spans":[{"file_name":"project1/src/main.rs","line_start":7,"is_primary":true}],"rendered":"error"}}
+{"reason":"compiler-message","target":{"name":"project1"},"message":{"message":"unused import","code":null,"level":"warning","spans":[{"file_name":"project1/src/main.rs","line_start":1,"is_primary":true}],"rendered":"warning"}}
+{"reason":"compiler-message","target":{"name":"sqllib"},"message":{"message":"unused variable","code":null,"level":"warning","spans":[],"rendered":"warning"}}"#;
+
+        let errors = rust_errors(stdout, "", ExitStatus::from_raw(256), rust_code);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].error_type, "E0609");
+        assert_eq!(errors[0].start, Some(SqlPosition { line: 2, column: 1 }));
+        assert_eq!(
+            errors[0].end,
+            Some(SqlPosition {
+                line: 3,
+                column: 16
+            })
+        );
+        // The map operator has no position in the SQL code.
+        assert_eq!(errors[1].start, None);
+
+        let warnings = rust_warnings(stdout, rust_code, "project1");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, DiagnosticSeverity::Warning);
+        assert_eq!(warnings[0].message, "unused import");
+        assert_eq!(warnings[0].start, None);
+    }
+}
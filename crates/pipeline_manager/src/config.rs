@@ -1,9 +1,13 @@
+use crate::auth::TenantId;
+use crate::db::audit::Role;
+use crate::db::secret::SecretsKey;
 use crate::db::{PipelineId, ProgramId, Version};
 use actix_web::http::header;
 use anyhow::{Error as AnyError, Result as AnyResult};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fmt::Display,
     fs::{canonicalize, create_dir_all},
     path::{Path, PathBuf},
@@ -48,6 +52,52 @@ fn default_binary_ref_port() -> u16 {
     9090
 }
 
+fn default_runner_port() -> u16 {
+    8089
+}
+
+fn default_runner_hostname_port() -> String {
+    "127.0.0.1:8089".to_string()
+}
+
+fn default_api_server_url() -> String {
+    "http://127.0.0.1:8080".to_string()
+}
+
+fn default_role() -> Role {
+    Role::Admin
+}
+
+fn default_pipeline_inherited_env() -> Vec<String> {
+    [
+        "PATH",
+        "HOME",
+        "TMPDIR",
+        "TZ",
+        "LANG",
+        "LC_ALL",
+        "RUST_LOG",
+        "RUST_BACKTRACE",
+        "VAULT_ADDR",
+        "VAULT_NAMESPACE",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+const fn default_binary_cache_max_entries() -> usize {
+    64
+}
+
+const fn default_compilation_workers() -> usize {
+    1
+}
+
+const fn default_pipeline_log_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
 /// Pipeline manager configuration read from a YAML config file or from command
 /// line arguments.
 #[derive(Parser, Deserialize, Debug, Clone)]
@@ -60,6 +110,13 @@ pub struct DatabaseConfig {
     #[serde(default = "default_db_connection_string")]
     #[arg(short, long, default_value_t = default_db_connection_string())]
     pub db_connection_string: String,
+
+    /// File containing the master key that encrypts the secrets stored in
+    /// the database, as 64 hexadecimal digits. Database secrets are
+    /// unavailable when no key is configured.
+    #[serde(default)]
+    #[arg(long)]
+    pub secrets_master_key_file: Option<String>,
 }
 
 impl DatabaseConfig {
@@ -72,6 +129,16 @@ impl DatabaseConfig {
             panic!("Invalid connection string {}", self.db_connection_string)
         }
     }
+
+    /// Master key for the secrets stored in the database, if configured.
+    pub(crate) fn secrets_master_key(&self) -> Option<SecretsKey> {
+        self.secrets_master_key_file.as_ref().map(|path| {
+            let hex = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("Unable to read secrets master key file '{path}': {e}"));
+            SecretsKey::from_hex(&hex)
+                .unwrap_or_else(|e| panic!("Invalid secrets master key in '{path}': {e}"))
+        })
+    }
 }
 
 #[derive(Parser, Deserialize, Debug, Clone, Default, PartialEq, Eq, clap::ValueEnum)]
@@ -80,6 +147,7 @@ pub enum AuthProviderType {
     None,
     AwsCognito,
     GoogleIdentity,
+    GenericOidc,
 }
 
 impl std::fmt::Display for AuthProviderType {
@@ -88,6 +156,7 @@ impl std::fmt::Display for AuthProviderType {
             AuthProviderType::None => write!(f, "none"),
             AuthProviderType::AwsCognito => write!(f, "aws-cognito"),
             AuthProviderType::GoogleIdentity => write!(f, "google-identity"),
+            AuthProviderType::GenericOidc => write!(f, "generic-oidc"),
         }
     }
 }
@@ -137,6 +206,16 @@ pub struct ApiServerConfig {
     ///
     /// We also only support implicit grants for now. We expect to
     /// support PKCE soon.
+    ///
+    /// ** Generic OIDC provider **
+    /// If the auth_provider is generic-oidc, AUTH_ISSUER is the issuer URL
+    /// of any OpenID Connect provider (e.g., Keycloak, Okta, Dex). Signing
+    /// keys are located via `<AUTH_ISSUER>/.well-known/openid-configuration`.
+    /// Two optional environment variables can be set:
+    ///
+    /// AUTH_AUDIENCE, the expected `aud` claim (defaults to AUTH_CLIENT_ID)
+    /// AUTH_TENANT_CLAIM, the claim that determines the tenant of a user
+    /// (defaults to `sub`; nested claims can be addressed as `org.id`)
     #[serde(default)]
     #[arg(long, action = clap::ArgAction::Set, default_value_t=AuthProviderType::None)]
     pub auth_provider: AuthProviderType,
@@ -174,6 +253,55 @@ pub struct ApiServerConfig {
     #[serde(default)]
     #[arg(long)]
     pub demos: Vec<String>,
+
+    /// Hostname and port of the local runner's HTTP service, which the API
+    /// server contacts to retrieve pipeline logs.
+    #[serde(default = "default_runner_hostname_port")]
+    #[arg(long, default_value_t = default_runner_hostname_port())]
+    pub runner_hostname_port: String,
+
+    /// Hosts that dry runs of URL input connectors may fetch data from even
+    /// though they resolve to loopback, private or link-local addresses.
+    /// Other such hosts are refused, so that dry runs cannot be used to probe
+    /// the network of the API server.
+    #[serde(default)]
+    #[arg(long, value_delimiter = ',')]
+    pub dry_run_allowed_hosts: Vec<String>,
+
+    /// Hosts that webhooks may post events to even though they resolve to
+    /// loopback, private or link-local addresses.  Other such hosts are
+    /// refused, so that webhooks cannot be used to reach services in the
+    /// network of the API server.
+    #[serde(default)]
+    #[arg(long, value_delimiter = ',')]
+    pub webhook_allowed_hosts: Vec<String>,
+
+    /// Role of authenticated users who have not been assigned a role within
+    /// their tenant, and of API keys created without a role.
+    #[serde(default = "default_role")]
+    #[arg(long, value_enum, default_value_t = default_role())]
+    pub default_role: Role,
+
+    /// Users who are admins of their tenant regardless of the role assigned
+    /// to them, identified by the subject (`sub`) claim of their token.  At
+    /// least one admin is needed to assign roles to other users.
+    #[serde(default)]
+    #[arg(long, value_delimiter = ',')]
+    pub admin_users: Vec<String>,
+
+    /// Quotas that apply to every tenant, unless overridden in
+    /// `tenant_quotas`.
+    #[serde(default)]
+    #[command(flatten)]
+    pub quotas: TenantQuotas,
+
+    /// Quotas of individual tenants, indexed by tenant name (the claim that
+    /// identifies the tenant).  A quota that is not set for a tenant falls
+    /// back to the corresponding quota in `quotas`.  Can only be set in the
+    /// config file.
+    #[serde(default)]
+    #[arg(skip)]
+    pub tenant_quotas: BTreeMap<String, TenantQuotas>,
 }
 
 impl ApiServerConfig {
@@ -234,6 +362,57 @@ impl ApiServerConfig {
     }
 }
 
+/// Limits on the pipelines and compilations of a tenant.
+///
+/// Quotas that are not set are unlimited.  Pipelines count toward the quotas
+/// from the moment they are started or paused until they are shut down.
+#[derive(Parser, Default, Eq, PartialEq, Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TenantQuotas {
+    /// Maximum number of running or paused pipelines.
+    #[serde(default)]
+    #[arg(long = "quota-running-pipelines")]
+    pub running_pipelines: Option<u64>,
+
+    /// Maximum total number of workers of the running or paused pipelines.
+    #[serde(default)]
+    #[arg(long = "quota-workers")]
+    pub workers: Option<u64>,
+
+    /// Maximum total `memory_mb_max` of the running or paused pipelines.
+    /// When set, pipelines must set `memory_mb_max` to be started.
+    #[serde(default)]
+    #[arg(long = "quota-memory-mb")]
+    pub memory_mb: Option<u64>,
+
+    /// Maximum total `storage_mb_max` of the running or paused pipelines.
+    /// When set, pipelines must set `storage_mb_max` to be started.
+    #[serde(default)]
+    #[arg(long = "quota-storage-mb")]
+    pub storage_mb: Option<u64>,
+
+    /// Maximum number of programs that are compiling or waiting to be
+    /// compiled.
+    #[serde(default)]
+    #[arg(long = "quota-concurrent-compilations")]
+    pub concurrent_compilations: Option<u64>,
+}
+
+impl TenantQuotas {
+    /// Returns the quotas in `self`, with the ones that are not set taken
+    /// from `defaults`.
+    pub(crate) fn or(&self, defaults: &TenantQuotas) -> TenantQuotas {
+        TenantQuotas {
+            running_pipelines: self.running_pipelines.or(defaults.running_pipelines),
+            workers: self.workers.or(defaults.workers),
+            memory_mb: self.memory_mb.or(defaults.memory_mb),
+            storage_mb: self.storage_mb.or(defaults.storage_mb),
+            concurrent_compilations: self
+                .concurrent_compilations
+                .or(defaults.concurrent_compilations),
+        }
+    }
+}
+
 /// Argument to `cargo build --profile <>` passed to the rust compiler
 ///
 /// Note that this is a hint to the backend, and can be overriden by
@@ -330,6 +509,35 @@ pub struct CompilerConfig {
     /// for runners.
     #[arg(long, default_value_t = default_binary_ref_port())]
     pub binary_ref_port: u16,
+
+    /// Maximum number of compiled binaries kept in the binary cache.
+    ///
+    /// Binaries are cached by a hash of the SQL code, the compiler version
+    /// and the compilation profile, so that compiling a program identical to
+    /// a previously compiled one reuses its binary.  The least recently used
+    /// binaries are evicted when the cache is full.  Set to 0 to disable the
+    /// cache.
+    #[serde(default = "default_binary_cache_max_entries")]
+    #[arg(long, default_value_t = default_binary_cache_max_entries())]
+    pub binary_cache_max_entries: usize,
+
+    /// Number of programs compiled concurrently.
+    ///
+    /// Each compilation worker builds programs in its own cargo workspace,
+    /// so workers do not wait for each other's build locks.  Pending programs
+    /// are assigned to workers fairly across tenants: a program of a tenant
+    /// with fewer compilations in progress is compiled first.
+    #[serde(default = "default_compilation_workers")]
+    #[arg(long, default_value_t = default_compilation_workers())]
+    pub compilation_workers: usize,
+
+    /// Index of the compilation worker that uses this configuration.
+    ///
+    /// Set by the compiler for each of its workers; selects the cargo
+    /// workspace of the worker.
+    #[serde(skip)]
+    #[arg(skip)]
+    pub(crate) worker: usize,
 }
 
 impl CompilerConfig {
@@ -343,9 +551,23 @@ impl CompilerConfig {
 
     /// Directory where the manager maintains the generated cargo workspace.
     ///
-    /// e.g., `<working-directory>/cargo_workspace`
+    /// e.g., `<working-directory>/cargo_workspace` for the first compilation
+    /// worker and `<working-directory>/cargo_workspace<n>` for worker `n`.
     pub(crate) fn workspace_dir(&self) -> PathBuf {
-        Path::new(&self.compiler_working_directory).join("cargo_workspace")
+        let dir = if self.worker == 0 {
+            "cargo_workspace".to_string()
+        } else {
+            format!("cargo_workspace{}", self.worker)
+        };
+        Path::new(&self.compiler_working_directory).join(dir)
+    }
+
+    /// Configuration of compilation worker `worker`.
+    pub(crate) fn for_worker(&self, worker: usize) -> Self {
+        Self {
+            worker,
+            ..self.clone()
+        }
     }
 
     /// Directory where the manager stores binary artefacts needed to
@@ -356,6 +578,29 @@ impl CompilerConfig {
         Path::new(&self.compiler_working_directory).join("binaries")
     }
 
+    /// Directory where the manager caches compiled binaries by content hash.
+    ///
+    /// e.g., `<working-directory>/binary_cache`
+    pub(crate) fn binary_cache_dir(&self) -> PathBuf {
+        Path::new(&self.compiler_working_directory).join("binary_cache")
+    }
+
+    /// Location of a cached executable.
+    pub(crate) fn cached_executable(&self, hash: &str) -> PathBuf {
+        self.binary_cache_dir().join(hash)
+    }
+
+    /// Location of the schema of a cached executable.
+    pub(crate) fn cached_schema_path(&self, hash: &str) -> PathBuf {
+        self.binary_cache_dir().join(format!("{hash}.schema.json"))
+    }
+
+    /// Location of the compiler warnings of a cached executable.
+    pub(crate) fn cached_warnings_path(&self, hash: &str) -> PathBuf {
+        self.binary_cache_dir()
+            .join(format!("{hash}.warnings.json"))
+    }
+
     /// Location of the versioned executable.
     /// e.g., `<working-directory>/binaries/
     /// project0188e0cd-d8b0-71d5-bb5a-2f66c7b07dfb-v11`
@@ -510,6 +755,73 @@ pub struct LocalRunnerConfig {
     #[serde(default = "default_server_address")]
     #[arg(long, default_value_t = default_server_address())]
     pub pipeline_host: String,
+
+    /// Address the local runner's HTTP service, which serves pipeline logs
+    /// to the API server, binds to.
+    ///
+    /// The service does not authenticate its clients, which must go through
+    /// the API server instead, so it should not be exposed beyond the
+    /// network shared with the API server.
+    #[serde(default = "default_server_address")]
+    #[arg(long, default_value_t = default_server_address())]
+    pub runner_bind_address: String,
+
+    /// Port of the local runner's HTTP service, which serves pipeline logs.
+    #[serde(default = "default_runner_port")]
+    #[arg(long, default_value_t = default_runner_port())]
+    pub runner_port: u16,
+
+    /// Maximal size of a pipeline log file in bytes.
+    ///
+    /// The runner retains the output of each pipeline, including previous
+    /// runs, in a log file.  When the file exceeds this size, it is rotated,
+    /// keeping at most one rotated file, so each pipeline occupies at most
+    /// twice this amount of disk space.
+    #[serde(default = "default_pipeline_log_max_bytes")]
+    #[arg(long, default_value_t = default_pipeline_log_max_bytes())]
+    pub pipeline_log_max_bytes: u64,
+
+    /// Delegated cgroup v2 directory under which the runner creates a cgroup
+    /// for each pipeline, e.g., `/sys/fs/cgroup/feldera.slice/pipelines`.
+    ///
+    /// The directory must be writable by the runner and must not contain any
+    /// processes.  The `cpu`, `cpuset` and `memory` controllers are used to
+    /// enforce the pipeline's resource configuration.  When not specified or
+    /// not usable, memory limits are enforced with rlimits instead.  Pipeline
+    /// CPU affinity is restricted in both cases.
+    #[serde(default)]
+    #[arg(long)]
+    pub pipeline_cgroup: Option<String>,
+
+    /// URL at which pipelines reach the API server.
+    ///
+    /// Connectors that read from other pipelines connect to those pipelines
+    /// through the API server.
+    #[serde(default = "default_api_server_url")]
+    #[arg(long, default_value_t = default_api_server_url())]
+    pub api_server_url: String,
+
+    /// Directory holding a HashiCorp Vault token for each tenant, in a file
+    /// named after the tenant id.
+    ///
+    /// Pipelines run with the token of their tenant, in `VAULT_TOKEN`, and
+    /// can only read the secrets under path `<tenant id>` of each secrets
+    /// engine.  The Vault server is configured through the `VAULT_ADDR` and
+    /// `VAULT_NAMESPACE` variables of the runner.  When not specified,
+    /// pipelines cannot read secrets from Vault.
+    #[serde(default)]
+    #[arg(long)]
+    pub vault_tokens_directory: Option<String>,
+
+    /// Variables of the runner's environment that pipelines inherit.
+    ///
+    /// Pipelines run with a clean environment that only contains these
+    /// variables, the secrets they reference and their Vault token.  Add the
+    /// variables used by connectors, e.g., `AWS_REGION`, `SSL_CERT_FILE` or
+    /// `HTTPS_PROXY`, to make them available to pipelines.
+    #[serde(default = "default_pipeline_inherited_env")]
+    #[arg(long, value_delimiter = ',', default_values_t = default_pipeline_inherited_env())]
+    pub pipeline_inherited_env: Vec<String>,
 }
 
 impl LocalRunnerConfig {
@@ -544,27 +856,62 @@ impl LocalRunnerConfig {
             .join(format!("pipeline{pipeline_id}"))
     }
 
-    /// Location to write the fetched pipeline binary to.
+    /// Location to store the files of instance `instance` of the pipeline.
+    ///
+    /// A pipeline that is being upgraded runs two instances side by side,
+    /// each with its own files.
+    pub(crate) fn pipeline_instance_dir(&self, pipeline_id: PipelineId, instance: u64) -> PathBuf {
+        self.pipeline_dir(pipeline_id)
+            .join(format!("instance{instance}"))
+    }
+
+    /// Storage location of an instance of the pipeline, which is kept when
+    /// the pipeline is restarted after a failure.
+    pub(crate) fn pipeline_storage_dir(&self, pipeline_id: PipelineId, instance: u64) -> PathBuf {
+        self.pipeline_instance_dir(pipeline_id, instance)
+            .join("storage")
+    }
+
+    /// Location to write the fetched pipeline binary of `instance` to.
     pub(crate) fn binary_file_path(
         &self,
         pipeline_id: PipelineId,
+        instance: u64,
         program: ProgramId,
         version: Version,
     ) -> PathBuf {
-        self.pipeline_dir(pipeline_id)
+        self.pipeline_instance_dir(pipeline_id, instance)
             .join(format!("program_{program}_v{version}"))
     }
 
+    /// Location of the Vault token of tenant `tenant_id`.
+    pub(crate) fn vault_token_path(&self, tenant_id: TenantId) -> Option<PathBuf> {
+        self.vault_tokens_directory
+            .as_ref()
+            .map(|dir| Path::new(dir).join(tenant_id.0.to_string()))
+    }
+
     /// Location to write the pipeline config file.
-    pub(crate) fn config_file_path(&self, pipeline_id: PipelineId) -> PathBuf {
-        self.pipeline_dir(pipeline_id).join("config.yaml")
+    pub(crate) fn config_file_path(&self, pipeline_id: PipelineId, instance: u64) -> PathBuf {
+        self.pipeline_instance_dir(pipeline_id, instance)
+            .join("config.yaml")
     }
 
     /// Location for pipeline port file
-    pub(crate) fn port_file_path(&self, pipeline_id: PipelineId) -> PathBuf {
-        self.pipeline_dir(pipeline_id)
+    pub(crate) fn port_file_path(&self, pipeline_id: PipelineId, instance: u64) -> PathBuf {
+        self.pipeline_instance_dir(pipeline_id, instance)
             .join(pipeline_types::transport::http::SERVER_PORT_FILE)
     }
+
+    /// Location of pipeline log files.
+    ///
+    /// Unlike [`Self::pipeline_dir`], this directory is preserved when
+    /// the pipeline is shut down.
+    pub(crate) fn pipeline_log_dir(&self, pipeline_id: PipelineId) -> PathBuf {
+        Path::new(&self.runner_working_directory)
+            .join("logs")
+            .join(format!("pipeline{pipeline_id}"))
+    }
 }
 
 fn default_prober_web_server_port() -> u16 {
@@ -601,6 +948,14 @@ pub struct ProberConfig {
     /// Probe timeout (ms).
     #[arg(long, default_value_t = default_probe_timeout_ms())]
     pub probe_timeout_ms: u64,
+
+    /// Hosts that HTTP, PostgreSQL and S3 probes may connect to even though
+    /// they resolve to loopback, private or link-local addresses.  Other
+    /// such hosts are refused, so that probes cannot be used to reach
+    /// services in the network of the prober.
+    #[serde(default)]
+    #[arg(long, value_delimiter = ',')]
+    pub probe_allowed_hosts: Vec<String>,
 }
 
 impl ProberConfig {
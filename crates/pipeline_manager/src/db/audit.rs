@@ -0,0 +1,318 @@
+use crate::auth::TenantId;
+use crate::db::pipeline::convert_bigint_to_time;
+use crate::db::{DBError, ProjectDB};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Role of a user within a tenant.
+///
+/// Roles are ordered by the set of operations they allow: every role
+/// allows all operations allowed by the roles before it.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    ToSchema,
+    clap::ValueEnum,
+)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Read-only access to all resources of the tenant.
+    Viewer,
+    /// Start, pause and shut down pipelines and push data to them.
+    Operator,
+    /// Create, modify and delete all resources, manage API keys and
+    /// assign roles.
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Operator => write!(f, "operator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = DBError;
+
+    fn from_str(input: &str) -> Result<Role, Self::Err> {
+        match input {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(DBError::invalid_data(format!(
+                "Unexpected role in the database: '{input}'"
+            ))),
+        }
+    }
+}
+
+/// Role assigned to a user.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct UserRole {
+    /// Name of the user, as identified by the authentication provider
+    /// (the `sub` claim of the user's token).
+    pub user_name: String,
+    pub role: Role,
+}
+
+/// Unique audit event id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[repr(transparent)]
+#[serde(transparent)]
+pub struct AuditEventId(pub Uuid);
+impl Display for AuditEventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Outcome of an audited request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AuditOutcome {
+    /// The request succeeded.
+    Success,
+    /// The request was authorized but failed.
+    Failure,
+    /// The request was rejected because the actor lacks the required
+    /// permissions.
+    Denied,
+}
+
+impl Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOutcome::Success => write!(f, "success"),
+            AuditOutcome::Failure => write!(f, "failure"),
+            AuditOutcome::Denied => write!(f, "denied"),
+        }
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = DBError;
+
+    fn from_str(input: &str) -> Result<AuditOutcome, Self::Err> {
+        match input {
+            "success" => Ok(AuditOutcome::Success),
+            "failure" => Ok(AuditOutcome::Failure),
+            "denied" => Ok(AuditOutcome::Denied),
+            _ => Err(DBError::invalid_data(format!(
+                "Unexpected audit outcome in the database: '{input}'"
+            ))),
+        }
+    }
+}
+
+/// Audit log entry recording a mutating API request.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct AuditEvent {
+    pub audit_event_id: AuditEventId,
+    /// Time when the request was completed.
+    pub timestamp: DateTime<Utc>,
+    /// User (`user:<name>`) or API key (`api_key:<name>`) that issued the
+    /// request, or pipeline schedule (`schedule:<name>`) that applied a
+    /// scheduled action.
+    pub actor: String,
+    /// Action performed by the request, e.g., `pipeline.start` or
+    /// `program.update`.
+    pub action: String,
+    /// Name of the pipeline, program, connector, service, API key or user
+    /// targeted by the request, if any.
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    /// HTTP status code of the response.
+    pub status_code: u16,
+}
+
+/// Conditions that audit events must satisfy to be listed.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct AuditEventFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    /// Only events at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only events before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Maximum number of events to return (the most recent ones).
+    pub limit: Option<u32>,
+}
+
+pub(crate) async fn set_user_role(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    user_name: &str,
+    role: Role,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "INSERT INTO user_role (tenant_id, user_name, role) VALUES ($1, $2, $3)
+             ON CONFLICT (tenant_id, user_name) DO UPDATE SET role = excluded.role",
+        )
+        .await?;
+    manager
+        .execute(&stmt, &[&tenant_id.0, &user_name, &role.to_string()])
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)
+        .map_err(|e| ProjectDB::maybe_tenant_id_foreign_key_constraint_err(e, tenant_id, None))?;
+    Ok(())
+}
+
+pub(crate) async fn get_user_role(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    user_name: &str,
+) -> Result<Option<Role>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("SELECT role FROM user_role WHERE tenant_id = $1 AND user_name = $2")
+        .await?;
+    let row = manager
+        .query_opt(&stmt, &[&tenant_id.0, &user_name])
+        .await?;
+    row.map(|row| Role::from_str(row.get(0))).transpose()
+}
+
+pub(crate) async fn list_user_roles(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+) -> Result<Vec<UserRole>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT user_name, role FROM user_role WHERE tenant_id = $1 ORDER BY user_name",
+        )
+        .await?;
+    let rows = manager.query(&stmt, &[&tenant_id.0]).await?;
+    rows.iter()
+        .map(|row| {
+            Ok(UserRole {
+                user_name: row.get(0),
+                role: Role::from_str(row.get(1))?,
+            })
+        })
+        .collect()
+}
+
+pub(crate) async fn delete_user_role(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    user_name: &str,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("DELETE FROM user_role WHERE tenant_id = $1 AND user_name = $2")
+        .await?;
+    let res = manager.execute(&stmt, &[&tenant_id.0, &user_name]).await?;
+    if res > 0 {
+        Ok(())
+    } else {
+        Err(DBError::UnknownUserRole {
+            user_name: user_name.to_string(),
+        })
+    }
+}
+
+pub(crate) async fn new_audit_event(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    event: &AuditEvent,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "INSERT INTO audit_log (id, tenant_id, timestamp, actor, action, target, outcome, status_code)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .await?;
+    manager
+        .execute(
+            &stmt,
+            &[
+                &event.audit_event_id.0,
+                &tenant_id.0,
+                &event.timestamp.timestamp(),
+                &event.actor,
+                &event.action,
+                &event.target,
+                &event.outcome.to_string(),
+                &(event.status_code as i32),
+            ],
+        )
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)
+        .map_err(|e| ProjectDB::maybe_tenant_id_foreign_key_constraint_err(e, tenant_id, None))?;
+    Ok(())
+}
+
+pub(crate) async fn list_audit_events(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    filter: &AuditEventFilter,
+) -> Result<Vec<AuditEvent>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, timestamp, actor, action, target, outcome, status_code
+             FROM audit_log
+             WHERE tenant_id = $1
+                   AND ($2::varchar IS NULL OR actor = $2)
+                   AND ($3::varchar IS NULL OR action = $3)
+                   AND ($4::varchar IS NULL OR target = $4)
+                   AND ($5::varchar IS NULL OR outcome = $5)
+                   AND ($6::bigint IS NULL OR timestamp >= $6)
+                   AND ($7::bigint IS NULL OR timestamp < $7)
+             ORDER BY timestamp DESC, id DESC
+             LIMIT $8",
+        )
+        .await?;
+    let rows = manager
+        .query(
+            &stmt,
+            &[
+                &tenant_id.0,
+                &filter.actor,
+                &filter.action,
+                &filter.target,
+                &filter.outcome.map(|outcome| outcome.to_string()),
+                &filter.since.map(|t| t.timestamp()),
+                &filter.until.map(|t| t.timestamp()),
+                &filter.limit.map(i64::from),
+            ],
+        )
+        .await?;
+    rows.iter().map(row_to_audit_event).collect()
+}
+
+fn row_to_audit_event(row: &Row) -> Result<AuditEvent, DBError> {
+    Ok(AuditEvent {
+        audit_event_id: AuditEventId(row.get(0)),
+        timestamp: convert_bigint_to_time("audit_log.timestamp", row.get(1))?,
+        actor: row.get(2),
+        action: row.get(3),
+        target: row.get(4),
+        outcome: AuditOutcome::from_str(row.get(5))?,
+        status_code: row.get::<_, i32>(6) as u16,
+    })
+}
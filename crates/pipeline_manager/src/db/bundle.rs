@@ -0,0 +1,720 @@
+//! Export and import of the programs, connectors, services and pipelines of a
+//! tenant as a single bundle.
+//!
+//! Resources are identified by name in a bundle, so that a bundle exported
+//! from one pipeline manager instance can be imported into another.
+//! Secret references such as `${secret:kafka-password}` are exported
+//! unresolved, while credentials stored inline in connector and service
+//! configurations are replaced by [`REDACTED`].
+use crate::auth::TenantId;
+use crate::compiler::ProgramConfig;
+use crate::db::storage::Storage;
+use crate::db::{AttachedConnector, DBError, PipelineStatus, ProjectDB};
+use deadpool_postgres::Transaction;
+use pipeline_types::config::{ConnectorConfig, RuntimeConfig};
+use pipeline_types::service::ServiceConfig;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Display;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Version of the bundle format produced and accepted by this pipeline
+/// manager.
+pub(crate) const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Value of the credentials of connector and service configurations in an
+/// exported bundle.
+///
+/// When importing a bundle, redacted credentials keep the value of the
+/// existing connector or service with the same name.
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// Substrings of the (lowercase) configuration keys whose values are
+/// credentials.
+const CREDENTIAL_KEYS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "credential",
+    "private_key",
+    "key.pem",
+    "sasl.jaas.config",
+    "authorization",
+];
+
+/// Programs, connectors, services and pipelines of a tenant.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Bundle {
+    /// Version of the bundle format.
+    pub format_version: u32,
+    #[serde(default)]
+    pub programs: Vec<BundledProgram>,
+    #[serde(default)]
+    pub connectors: Vec<BundledConnector>,
+    #[serde(default)]
+    pub services: Vec<BundledService>,
+    #[serde(default)]
+    pub pipelines: Vec<BundledPipeline>,
+}
+
+/// Program in a bundle.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BundledProgram {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// SQL code of the program.
+    pub code: String,
+    #[serde(default)]
+    pub config: ProgramConfig,
+}
+
+/// Connector in a bundle.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BundledConnector {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub config: ConnectorConfig,
+}
+
+/// Service in a bundle.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BundledService {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub config: ServiceConfig,
+}
+
+/// Pipeline in a bundle.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BundledPipeline {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Name of the program the pipeline runs, which must be part of the
+    /// bundle or already exist.
+    pub program_name: Option<String>,
+    pub config: RuntimeConfig,
+    /// Connectors attached to the pipeline, which must be part of the
+    /// bundle or already exist.
+    #[serde(default)]
+    pub attached_connectors: Vec<AttachedConnector>,
+}
+
+/// Kind of resource in a bundle.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BundleResource {
+    Program,
+    Connector,
+    Service,
+    Pipeline,
+}
+
+impl Display for BundleResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleResource::Program => write!(f, "program"),
+            BundleResource::Connector => write!(f, "connector"),
+            BundleResource::Service => write!(f, "service"),
+            BundleResource::Pipeline => write!(f, "pipeline"),
+        }
+    }
+}
+
+/// Change applied to a resource by an import.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BundleAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A resource created, updated or deleted by an import.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct BundleChange {
+    pub resource: BundleResource,
+    pub name: String,
+    pub action: BundleAction,
+}
+
+/// Options of an import.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ImportOptions {
+    /// Only compute the changes, without applying them.
+    pub dry_run: bool,
+    /// Delete the resources of the tenant that are not part of the bundle.
+    pub prune: bool,
+}
+
+/// Exports the programs, connectors, services and pipelines of a tenant,
+/// sorted by name, with their credentials redacted.
+pub(crate) async fn export_bundle<S: Storage + ?Sized>(
+    db: &S,
+    tenant_id: TenantId,
+) -> Result<Bundle, DBError> {
+    let mut bundle = current_bundle(db, tenant_id).await?;
+    for connector in bundle.connectors.iter_mut() {
+        connector.config = redact_credentials(&connector.config)?;
+    }
+    for service in bundle.services.iter_mut() {
+        service.config = redact_credentials(&service.config)?;
+    }
+    Ok(bundle)
+}
+
+/// Returns the programs, connectors, services and pipelines of a tenant,
+/// sorted by name.
+async fn current_bundle<S: Storage + ?Sized>(
+    db: &S,
+    tenant_id: TenantId,
+) -> Result<Bundle, DBError> {
+    let mut programs: Vec<BundledProgram> = db
+        .list_programs(tenant_id, true)
+        .await?
+        .into_iter()
+        .map(|p| BundledProgram {
+            name: p.name,
+            description: p.description,
+            code: p.code.unwrap_or_default(),
+            config: p.config,
+        })
+        .collect();
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut connectors: Vec<BundledConnector> = db
+        .list_connectors(tenant_id)
+        .await?
+        .into_iter()
+        .map(|c| BundledConnector {
+            name: c.name,
+            description: c.description,
+            config: c.config,
+        })
+        .collect();
+    connectors.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut services: Vec<BundledService> = db
+        .list_services(tenant_id, &None)
+        .await?
+        .into_iter()
+        .map(|s| BundledService {
+            name: s.name,
+            description: s.description,
+            config: s.config,
+        })
+        .collect();
+    services.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut pipelines: Vec<BundledPipeline> = db
+        .list_pipelines(tenant_id)
+        .await?
+        .into_iter()
+        .map(|p| BundledPipeline {
+            name: p.descriptor.name,
+            description: p.descriptor.description,
+            program_name: p.descriptor.program_name,
+            config: p.descriptor.config,
+            attached_connectors: sorted_attachments(p.descriptor.attached_connectors),
+        })
+        .collect();
+    pipelines.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Bundle {
+        format_version: BUNDLE_FORMAT_VERSION,
+        programs,
+        connectors,
+        services,
+        pipelines,
+    })
+}
+
+/// Makes the resources of a tenant match `bundle`: creates the resources
+/// missing from the tenant and updates those that differ from the bundle.
+/// With `options.prune`, also deletes the resources that are not part of the
+/// bundle.
+///
+/// Returns the changes, which are only computed and not applied with
+/// `options.dry_run`. Importing the same bundle twice makes no changes the
+/// second time.
+///
+/// The changes are applied in a single transaction: if applying a change
+/// fails, none of them are.
+pub(crate) async fn import_bundle(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    bundle: &Bundle,
+    options: ImportOptions,
+) -> Result<Vec<BundleChange>, DBError> {
+    let current = current_bundle(db, tenant_id).await?;
+    let bundle = restore_credentials(bundle, &current)?;
+    let changes = plan_import(&current, &bundle, options.prune)?;
+
+    // Pipelines can only be deleted once they are shut down.
+    for change in &changes {
+        if change.resource == BundleResource::Pipeline && change.action == BundleAction::Delete {
+            let pipeline = db.get_pipeline_by_name(tenant_id, &change.name).await?;
+            if pipeline.state.current_status != PipelineStatus::Shutdown
+                || pipeline.state.desired_status != PipelineStatus::Shutdown
+            {
+                return Err(DBError::PipelineNotShutdown {
+                    pipeline_name: change.name.clone(),
+                });
+            }
+        }
+    }
+    if options.dry_run {
+        return Ok(changes);
+    }
+
+    let mut client = db.pool.get().await?;
+    let txn = client.transaction().await?;
+    for change in &changes {
+        apply_change(db, tenant_id, &bundle, change, &txn).await?;
+    }
+    txn.commit().await?;
+    Ok(changes)
+}
+
+/// Computes the changes that make `current` match `bundle`, in the order in
+/// which they must be applied: services, connectors and programs are created
+/// and updated before the pipelines that use them, and deleted after them.
+fn plan_import(
+    current: &Bundle,
+    bundle: &Bundle,
+    prune: bool,
+) -> Result<Vec<BundleChange>, DBError> {
+    let programs = by_name(BundleResource::Program, &bundle.programs, |p| &p.name)?;
+    let connectors = by_name(BundleResource::Connector, &bundle.connectors, |c| &c.name)?;
+    let services = by_name(BundleResource::Service, &bundle.services, |s| &s.name)?;
+    let pipelines = by_name(BundleResource::Pipeline, &bundle.pipelines, |p| &p.name)?;
+    let current_programs = by_name(BundleResource::Program, &current.programs, |p| &p.name)?;
+    let current_connectors = by_name(BundleResource::Connector, &current.connectors, |c| &c.name)?;
+    let current_services = by_name(BundleResource::Service, &current.services, |s| &s.name)?;
+    let current_pipelines = by_name(BundleResource::Pipeline, &current.pipelines, |p| &p.name)?;
+
+    // Connectors may only refer to services, and pipelines to programs and
+    // connectors, that exist once the bundle is imported.
+    for connector in connectors.values() {
+        if let Some(service_name) = connector.config.transport.service_name() {
+            if !services.contains_key(service_name)
+                && (prune || !current_services.contains_key(service_name))
+            {
+                return Err(DBError::UnknownServiceName {
+                    service_name: service_name.to_string(),
+                });
+            }
+        }
+    }
+    for pipeline in pipelines.values() {
+        if let Some(program_name) = &pipeline.program_name {
+            if !programs.contains_key(program_name.as_str())
+                && (prune || !current_programs.contains_key(program_name.as_str()))
+            {
+                return Err(DBError::UnknownProgramName {
+                    program_name: program_name.clone(),
+                });
+            }
+        }
+        for ac in &pipeline.attached_connectors {
+            if !connectors.contains_key(ac.connector_name.as_str())
+                && (prune || !current_connectors.contains_key(ac.connector_name.as_str()))
+            {
+                return Err(DBError::UnknownConnectorName {
+                    connector_name: ac.connector_name.clone(),
+                });
+            }
+        }
+    }
+
+    let mut changes = Vec::new();
+    upserts(
+        &mut changes,
+        BundleResource::Service,
+        &services,
+        &current_services,
+    );
+    upserts(
+        &mut changes,
+        BundleResource::Connector,
+        &connectors,
+        &current_connectors,
+    );
+    upserts(
+        &mut changes,
+        BundleResource::Program,
+        &programs,
+        &current_programs,
+    );
+    // Attached connectors are compared regardless of their order.
+    let sorted_pipelines: BTreeMap<&str, BundledPipeline> = pipelines
+        .iter()
+        .map(|(name, p)| {
+            let mut p = (*p).clone();
+            p.attached_connectors = sorted_attachments(p.attached_connectors);
+            (*name, p)
+        })
+        .collect();
+    let sorted_pipelines: BTreeMap<&str, &BundledPipeline> = sorted_pipelines
+        .iter()
+        .map(|(name, p)| (*name, p))
+        .collect();
+    upserts(
+        &mut changes,
+        BundleResource::Pipeline,
+        &sorted_pipelines,
+        &current_pipelines,
+    );
+    if prune {
+        deletes(
+            &mut changes,
+            BundleResource::Pipeline,
+            &pipelines.keys().copied().collect(),
+            &current_pipelines,
+        );
+        deletes(
+            &mut changes,
+            BundleResource::Program,
+            &programs.keys().copied().collect(),
+            &current_programs,
+        );
+        deletes(
+            &mut changes,
+            BundleResource::Connector,
+            &connectors.keys().copied().collect(),
+            &current_connectors,
+        );
+        deletes(
+            &mut changes,
+            BundleResource::Service,
+            &services.keys().copied().collect(),
+            &current_services,
+        );
+    }
+    Ok(changes)
+}
+
+/// Indexes `resources` of kind `kind` by name, rejecting duplicate names.
+fn by_name<'a, T>(
+    kind: BundleResource,
+    resources: &'a [T],
+    name: impl Fn(&'a T) -> &'a String,
+) -> Result<BTreeMap<&'a str, &'a T>, DBError> {
+    let mut map = BTreeMap::new();
+    for resource in resources {
+        let name = name(resource);
+        if map.insert(name.as_str(), resource).is_some() {
+            return Err(DBError::DuplicateBundleName {
+                resource: kind.to_string(),
+                name: name.clone(),
+            });
+        }
+    }
+    Ok(map)
+}
+
+fn upserts<T: PartialEq>(
+    changes: &mut Vec<BundleChange>,
+    resource: BundleResource,
+    bundled: &BTreeMap<&str, &T>,
+    current: &BTreeMap<&str, &T>,
+) {
+    for (name, r) in bundled {
+        let action = match current.get(name) {
+            None => BundleAction::Create,
+            Some(c) if c != r => BundleAction::Update,
+            Some(_) => continue,
+        };
+        changes.push(BundleChange {
+            resource,
+            name: name.to_string(),
+            action,
+        });
+    }
+}
+
+fn deletes<T>(
+    changes: &mut Vec<BundleChange>,
+    resource: BundleResource,
+    bundled: &BTreeSet<&str>,
+    current: &BTreeMap<&str, &T>,
+) {
+    for name in current.keys().filter(|name| !bundled.contains(*name)) {
+        changes.push(BundleChange {
+            resource,
+            name: name.to_string(),
+            action: BundleAction::Delete,
+        });
+    }
+}
+
+/// Returns `config` with the values of its credential keys replaced by
+/// [`REDACTED`].  Secret references are kept, since they do not reveal the
+/// secret.
+fn redact_credentials<T: Serialize + DeserializeOwned>(config: &T) -> Result<T, DBError> {
+    fn redact(value: &mut JsonValue) {
+        match value {
+            JsonValue::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    match value {
+                        JsonValue::String(s) if is_credential_key(key) && !is_secret_ref(s) => {
+                            *s = REDACTED.to_string();
+                        }
+                        value => redact(value),
+                    }
+                }
+            }
+            JsonValue::Array(values) => values.iter_mut().for_each(redact),
+            _ => {}
+        }
+    }
+    let mut json = serde_json::to_value(config)
+        .map_err(|e| DBError::invalid_data(format!("Error serializing configuration: {e}")))?;
+    redact(&mut json);
+    serde_json::from_value(json)
+        .map_err(|e| DBError::invalid_data(format!("Error redacting configuration: {e}")))
+}
+
+fn is_credential_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    CREDENTIAL_KEYS.iter().any(|k| key.contains(k))
+}
+
+fn is_secret_ref(value: &str) -> bool {
+    value.starts_with("${secret:") && value.ends_with('}')
+}
+
+/// Returns `bundle` with the [`REDACTED`] credentials of its connectors and
+/// services replaced by those of the connectors and services of the same name
+/// in `current`.
+///
+/// Fails if a redacted credential has no current value to restore.
+fn restore_credentials(bundle: &Bundle, current: &Bundle) -> Result<Bundle, DBError> {
+    let mut bundle = bundle.clone();
+    for connector in bundle.connectors.iter_mut() {
+        let existing = current.connectors.iter().find(|c| c.name == connector.name);
+        connector.config = restore_config_credentials(
+            BundleResource::Connector,
+            &connector.name,
+            &connector.config,
+            existing.map(|c| &c.config),
+        )?;
+    }
+    for service in bundle.services.iter_mut() {
+        let existing = current.services.iter().find(|s| s.name == service.name);
+        service.config = restore_config_credentials(
+            BundleResource::Service,
+            &service.name,
+            &service.config,
+            existing.map(|s| &s.config),
+        )?;
+    }
+    Ok(bundle)
+}
+
+fn restore_config_credentials<T: Serialize + DeserializeOwned>(
+    resource: BundleResource,
+    name: &str,
+    config: &T,
+    current: Option<&T>,
+) -> Result<T, DBError> {
+    fn restore(value: &mut JsonValue, current: &JsonValue) {
+        match (value, current) {
+            (JsonValue::Object(map), JsonValue::Object(current)) => {
+                for (key, value) in map.iter_mut() {
+                    if let Some(current) = current.get(key) {
+                        if value.as_str() == Some(REDACTED) {
+                            *value = current.clone();
+                        } else {
+                            restore(value, current);
+                        }
+                    }
+                }
+            }
+            (JsonValue::Array(values), JsonValue::Array(current)) => {
+                for (value, current) in values.iter_mut().zip(current) {
+                    restore(value, current);
+                }
+            }
+            _ => {}
+        }
+    }
+    fn find_redacted(value: &JsonValue, path: &str) -> Option<String> {
+        match value {
+            JsonValue::String(s) if s == REDACTED => Some(path.to_string()),
+            JsonValue::Object(map) => map
+                .iter()
+                .find_map(|(key, value)| find_redacted(value, &format!("{path}.{key}"))),
+            JsonValue::Array(values) => values
+                .iter()
+                .enumerate()
+                .find_map(|(i, value)| find_redacted(value, &format!("{path}[{i}]"))),
+            _ => None,
+        }
+    }
+
+    let to_json = |config: &T| {
+        serde_json::to_value(config)
+            .map_err(|e| DBError::invalid_data(format!("Error serializing configuration: {e}")))
+    };
+    let mut json = to_json(config)?;
+    if let Some(current) = current {
+        restore(&mut json, &to_json(current)?);
+    }
+    if let Some(path) = find_redacted(&json, "config") {
+        return Err(DBError::RedactedBundleCredential {
+            resource: resource.to_string(),
+            name: name.to_string(),
+            path,
+        });
+    }
+    serde_json::from_value(json).map_err(|e| {
+        DBError::invalid_data(format!("Error restoring configuration credentials: {e}"))
+    })
+}
+
+fn sorted_attachments(mut attached_connectors: Vec<AttachedConnector>) -> Vec<AttachedConnector> {
+    attached_connectors.sort_by(|a, b| a.name.cmp(&b.name));
+    attached_connectors
+}
+
+async fn apply_change(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    bundle: &Bundle,
+    change: &BundleChange,
+    txn: &Transaction<'_>,
+) -> Result<(), DBError> {
+    let txn = Some(txn);
+    let name = change.name.as_str();
+    match (change.resource, change.action) {
+        (BundleResource::Service, BundleAction::Delete) => {
+            db.delete_service(tenant_id, name, txn).await
+        }
+        (BundleResource::Service, action) => {
+            let s = bundle.services.iter().find(|s| s.name == name).unwrap();
+            if action == BundleAction::Create {
+                db.new_service(
+                    tenant_id,
+                    Uuid::now_v7(),
+                    name,
+                    &s.description,
+                    &s.config,
+                    txn,
+                )
+                .await?;
+            } else {
+                let current = db.get_service_by_name(tenant_id, name, txn).await?;
+                db.update_service(
+                    tenant_id,
+                    current.service_id,
+                    &Some(name),
+                    &Some(&s.description),
+                    &Some(s.config.clone()),
+                    txn,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        (BundleResource::Connector, BundleAction::Delete) => {
+            db.delete_connector(tenant_id, name, txn).await
+        }
+        (BundleResource::Connector, action) => {
+            let c = bundle.connectors.iter().find(|c| c.name == name).unwrap();
+            if action == BundleAction::Create {
+                db.new_connector(
+                    tenant_id,
+                    Uuid::now_v7(),
+                    name,
+                    &c.description,
+                    &c.config,
+                    txn,
+                )
+                .await?;
+            } else {
+                let current = db.get_connector_by_name(tenant_id, name, txn).await?;
+                db.update_connector(
+                    tenant_id,
+                    current.connector_id,
+                    &Some(name),
+                    &Some(&c.description),
+                    &Some(c.config.clone()),
+                    txn,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        (BundleResource::Program, BundleAction::Delete) => {
+            db.delete_program(tenant_id, name, txn).await
+        }
+        (BundleResource::Program, action) => {
+            let p = bundle.programs.iter().find(|p| p.name == name).unwrap();
+            if action == BundleAction::Create {
+                db.new_program(
+                    tenant_id,
+                    Uuid::now_v7(),
+                    name,
+                    &p.description,
+                    &p.code,
+                    &p.config,
+                    txn,
+                )
+                .await?;
+            } else {
+                let current = db.get_program_by_name(tenant_id, name, false, txn).await?;
+                db.update_program(
+                    tenant_id,
+                    current.program_id,
+                    &None,
+                    &Some(p.description.clone()),
+                    &Some(p.code.clone()),
+                    &None,
+                    &None,
+                    &Some(p.config.clone()),
+                    None,
+                    txn,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        (BundleResource::Pipeline, BundleAction::Delete) => {
+            db.delete_pipeline(tenant_id, name, txn).await
+        }
+        (BundleResource::Pipeline, action) => {
+            let p = bundle.pipelines.iter().find(|p| p.name == name).unwrap();
+            let connectors = Some(p.attached_connectors.clone());
+            if action == BundleAction::Create {
+                db.new_pipeline(
+                    tenant_id,
+                    Uuid::now_v7(),
+                    &p.program_name,
+                    name,
+                    &p.description,
+                    &p.config,
+                    &connectors,
+                    txn,
+                )
+                .await?;
+            } else {
+                let current = db.get_pipeline_descr_by_name(tenant_id, name, txn).await?;
+                db.update_pipeline(
+                    tenant_id,
+                    current.pipeline_id,
+                    &p.program_name,
+                    name,
+                    &p.description,
+                    &Some(p.config.clone()),
+                    &connectors,
+                    txn,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -223,14 +223,19 @@ pub(crate) async fn delete_connector(
     db: &ProjectDB,
     tenant_id: TenantId,
     connector_name: &str,
+    txn: Option<&Transaction<'_>>,
 ) -> Result<(), DBError> {
-    let manager = db.pool.get().await?;
-    let stmt = manager
-        .prepare_cached("DELETE FROM connector WHERE name = $1 AND tenant_id = $2")
-        .await?;
-    let res = manager
-        .execute(&stmt, &[&connector_name, &tenant_id.0])
-        .await?;
+    let query = "DELETE FROM connector WHERE name = $1 AND tenant_id = $2";
+    let res = if let Some(txn) = txn {
+        let stmt = txn.prepare_cached(query).await?;
+        txn.execute(&stmt, &[&connector_name, &tenant_id.0]).await
+    } else {
+        let manager = db.pool.get().await?;
+        let stmt = manager.prepare_cached(query).await?;
+        manager
+            .execute(&stmt, &[&connector_name, &tenant_id.0])
+            .await
+    }?;
 
     if res > 0 {
         Ok(())
@@ -1,6 +1,6 @@
 use super::{ConnectorId, PipelineId, ProgramId, Version};
 use crate::auth::TenantId;
-use crate::db::{ServiceId, ServiceProbeId};
+use crate::db::{PipelineEventId, ServiceId, ServiceProbeId};
 use actix_web::{
     body::BoxBody, http::StatusCode, HttpResponse, HttpResponseBuilder, ResponseError,
 };
@@ -55,6 +55,10 @@ pub enum DBError {
     UnknownProgramName {
         program_name: String,
     },
+    UnknownProgramVersion {
+        program_name: String,
+        version: Version,
+    },
     ProgramInUseByPipeline {
         program_name: String,
     },
@@ -64,6 +68,18 @@ pub enum DBError {
     UnknownPipeline {
         pipeline_id: PipelineId,
     },
+    PipelineNotShutdown {
+        pipeline_name: String,
+    },
+    DuplicateBundleName {
+        resource: String,
+        name: String,
+    },
+    RedactedBundleCredential {
+        resource: String,
+        name: String,
+        path: String,
+    },
     UnknownPipelineName {
         pipeline_name: String,
     },
@@ -88,6 +104,31 @@ pub enum DBError {
     UnknownApiKey {
         name: String,
     },
+    UnknownUserRole {
+        user_name: String,
+    },
+    UnknownSecret {
+        secret_name: String,
+    },
+    UnknownPipelineEvent {
+        pipeline_event_id: PipelineEventId,
+    },
+    UnknownWebhook {
+        webhook_name: String,
+    },
+    InvalidWebhook {
+        reason: String,
+    },
+    UnknownPipelineSchedule {
+        schedule_name: String,
+    },
+    InvalidPipelineSchedule {
+        reason: String,
+    },
+    InvalidSecretName {
+        secret_name: String,
+    },
+    SecretsNotConfigured,
     UnknownTenant {
         tenant_id: TenantId,
     },
@@ -127,6 +168,9 @@ pub enum DBError {
     ProgramNotSet,
     ProgramNotCompiled,
     ProgramFailedToCompile,
+    ProgramNotCompiling {
+        program_name: String,
+    },
     NoRevisionAvailable {
         pipeline_id: PipelineId,
     },
@@ -402,6 +446,34 @@ impl Display for DBError {
             DBError::UnknownProgramName { program_name } => {
                 write!(f, "Unknown program name '{program_name}'")
             }
+            DBError::UnknownProgramVersion {
+                program_name,
+                version,
+            } => {
+                write!(f, "Program '{program_name}' has no version {version}")
+            }
+            DBError::PipelineNotShutdown { pipeline_name } => {
+                write!(
+                    f,
+                    "Pipeline '{pipeline_name}' must be shut down before it can be deleted"
+                )
+            }
+            DBError::DuplicateBundleName { resource, name } => {
+                write!(
+                    f,
+                    "The bundle contains more than one {resource} named '{name}'"
+                )
+            }
+            DBError::RedactedBundleCredential {
+                resource,
+                name,
+                path,
+            } => {
+                write!(
+                    f,
+                    "The credential at '{path}' of {resource} '{name}' is redacted and there is no existing {resource} to take it from; replace it with the credential or a secret reference"
+                )
+            }
             DBError::ProgramInUseByPipeline { program_name } => {
                 write!(f, "Program named '{program_name}' is in use by a pipeline")
             }
@@ -444,6 +516,39 @@ impl Display for DBError {
             DBError::UnknownApiKey { name } => {
                 write!(f, "Unknown API key '{name}'")
             }
+            DBError::UnknownUserRole { user_name } => {
+                write!(f, "No role assigned to user '{user_name}'")
+            }
+            DBError::UnknownSecret { secret_name } => {
+                write!(f, "Unknown secret '{secret_name}'")
+            }
+            DBError::UnknownPipelineEvent { pipeline_event_id } => {
+                write!(f, "Unknown pipeline event id '{pipeline_event_id}'")
+            }
+            DBError::UnknownWebhook { webhook_name } => {
+                write!(f, "Unknown webhook '{webhook_name}'")
+            }
+            DBError::InvalidWebhook { reason } => {
+                write!(f, "Invalid webhook: {reason}")
+            }
+            DBError::UnknownPipelineSchedule { schedule_name } => {
+                write!(f, "Unknown pipeline schedule '{schedule_name}'")
+            }
+            DBError::InvalidPipelineSchedule { reason } => {
+                write!(f, "Invalid pipeline schedule: {reason}")
+            }
+            DBError::InvalidSecretName { secret_name } => {
+                write!(
+                    f,
+                    "Invalid secret name '{secret_name}': names must consist of lowercase letters and digits, optionally separated by hyphens"
+                )
+            }
+            DBError::SecretsNotConfigured => {
+                write!(
+                    f,
+                    "Secrets cannot be stored in the database: no master key is configured (--secrets-master-key-file)"
+                )
+            }
             DBError::UnknownTenant { tenant_id } => {
                 write!(f, "Unknown tenant id '{tenant_id}'")
             }
@@ -484,6 +589,9 @@ impl Display for DBError {
                     "The program attached to the pipeline did not compile successfully"
                 )
             }
+            DBError::ProgramNotCompiling { program_name } => {
+                write!(f, "Program '{program_name}' is not pending or compiling")
+            }
             DBError::NoRevisionAvailable { pipeline_id } => {
                 write!(
                     f,
@@ -529,9 +637,13 @@ impl DetailedError for DBError {
             Self::InvalidStatus { .. } => Cow::from("InvalidStatus"),
             Self::UnknownProgram { .. } => Cow::from("UnknownProgram"),
             Self::UnknownProgramName { .. } => Cow::from("UnknownProgramName"),
+            Self::UnknownProgramVersion { .. } => Cow::from("UnknownProgramVersion"),
             Self::ProgramInUseByPipeline { .. } => Cow::from("ProgramInUseByPipeline"),
             Self::OutdatedProgramVersion { .. } => Cow::from("OutdatedProgramVersion"),
             Self::UnknownPipeline { .. } => Cow::from("UnknownPipeline"),
+            Self::PipelineNotShutdown { .. } => Cow::from("PipelineNotShutdown"),
+            Self::DuplicateBundleName { .. } => Cow::from("DuplicateBundleName"),
+            Self::RedactedBundleCredential { .. } => Cow::from("RedactedBundleCredential"),
             Self::UnknownPipelineName { .. } => Cow::from("UnknownPipelineName"),
             Self::UnknownConnector { .. } => Cow::from("UnknownConnector"),
             Self::UnknownConnectorName { .. } => Cow::from("UnknownConnectorName"),
@@ -540,6 +652,15 @@ impl DetailedError for DBError {
             Self::UnknownServiceName { .. } => Cow::from("UnknownServiceName"),
             Self::UnknownServiceProbe { .. } => Cow::from("UnknownServiceProbe"),
             Self::UnknownApiKey { .. } => Cow::from("UnknownApiKey"),
+            Self::UnknownUserRole { .. } => Cow::from("UnknownUserRole"),
+            Self::UnknownSecret { .. } => Cow::from("UnknownSecret"),
+            Self::UnknownPipelineEvent { .. } => Cow::from("UnknownPipelineEvent"),
+            Self::UnknownWebhook { .. } => Cow::from("UnknownWebhook"),
+            Self::InvalidWebhook { .. } => Cow::from("InvalidWebhook"),
+            Self::UnknownPipelineSchedule { .. } => Cow::from("UnknownPipelineSchedule"),
+            Self::InvalidPipelineSchedule { .. } => Cow::from("InvalidPipelineSchedule"),
+            Self::InvalidSecretName { .. } => Cow::from("InvalidSecretName"),
+            Self::SecretsNotConfigured => Cow::from("SecretsNotConfigured"),
             Self::UnknownTenant { .. } => Cow::from("UnknownTenant"),
             Self::UnknownAttachedConnector { .. } => Cow::from("UnknownAttachedConnector"),
             Self::UnknownName { .. } => Cow::from("UnknownName"),
@@ -553,6 +674,7 @@ impl DetailedError for DBError {
             Self::ProgramNotSet => Cow::from("ProgramNotSet"),
             Self::ProgramNotCompiled => Cow::from("ProgramNotCompiled"),
             Self::ProgramFailedToCompile => Cow::from("ProgramFailedToCompile"),
+            Self::ProgramNotCompiling { .. } => Cow::from("ProgramNotCompiling"),
             Self::NoRevisionAvailable { .. } => Cow::from("NoRevisionAvailable"),
             Self::RevisionNotChanged => Cow::from("RevisionNotChanged"),
             Self::TablesNotInSchema { .. } => Cow::from("TablesNotInSchema"),
@@ -565,10 +687,18 @@ impl DetailedError for DBError {
         match self {
             Self::UnknownProgram { .. } => Level::Info,
             Self::UnknownProgramName { .. } => Level::Info,
+            Self::UnknownProgramVersion { .. } => Level::Info,
             Self::UnknownPipeline { .. } => Level::Info,
             Self::UnknownConnector { .. } => Level::Info,
             Self::UnknownConnectorName { .. } => Level::Info,
             Self::UnknownName { .. } => Level::Info,
+            Self::UnknownUserRole { .. } => Level::Info,
+            Self::UnknownSecret { .. } => Level::Info,
+            Self::UnknownWebhook { .. } => Level::Info,
+            Self::UnknownPipelineSchedule { .. } => Level::Info,
+            Self::ProgramNotCompiling { .. } => Level::Info,
+            Self::DuplicateBundleName { .. } => Level::Info,
+            Self::RedactedBundleCredential { .. } => Level::Info,
             _ => Level::Error,
         }
     }
@@ -588,10 +718,14 @@ impl ResponseError for DBError {
             Self::InvalidStatus { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Self::UnknownProgram { .. } => StatusCode::NOT_FOUND,
             Self::UnknownProgramName { .. } => StatusCode::NOT_FOUND,
+            Self::UnknownProgramVersion { .. } => StatusCode::NOT_FOUND,
             Self::ProgramInUseByPipeline { .. } => StatusCode::BAD_REQUEST,
             Self::DuplicateName => StatusCode::CONFLICT,
             Self::OutdatedProgramVersion { .. } => StatusCode::CONFLICT,
             Self::UnknownPipeline { .. } => StatusCode::NOT_FOUND,
+            Self::PipelineNotShutdown { .. } => StatusCode::BAD_REQUEST,
+            Self::DuplicateBundleName { .. } => StatusCode::CONFLICT,
+            Self::RedactedBundleCredential { .. } => StatusCode::BAD_REQUEST,
             Self::UnknownPipelineName { .. } => StatusCode::NOT_FOUND,
             Self::UnknownConnector { .. } => StatusCode::NOT_FOUND,
             Self::UnknownConnectorName { .. } => StatusCode::NOT_FOUND,
@@ -600,6 +734,15 @@ impl ResponseError for DBError {
             Self::UnknownServiceName { .. } => StatusCode::NOT_FOUND,
             Self::UnknownServiceProbe { .. } => StatusCode::NOT_FOUND,
             Self::UnknownApiKey { .. } => StatusCode::NOT_FOUND,
+            Self::UnknownUserRole { .. } => StatusCode::NOT_FOUND,
+            Self::UnknownSecret { .. } => StatusCode::NOT_FOUND,
+            Self::UnknownPipelineEvent { .. } => StatusCode::NOT_FOUND,
+            Self::UnknownWebhook { .. } => StatusCode::NOT_FOUND,
+            Self::InvalidWebhook { .. } => StatusCode::BAD_REQUEST,
+            Self::UnknownPipelineSchedule { .. } => StatusCode::NOT_FOUND,
+            Self::InvalidPipelineSchedule { .. } => StatusCode::BAD_REQUEST,
+            Self::InvalidSecretName { .. } => StatusCode::BAD_REQUEST,
+            Self::SecretsNotConfigured => StatusCode::SERVICE_UNAVAILABLE,
             // TODO: should we report not found instead?
             Self::UnknownTenant { .. } => StatusCode::UNAUTHORIZED,
             Self::UnknownAttachedConnector { .. } => StatusCode::NOT_FOUND,
@@ -609,6 +752,7 @@ impl ResponseError for DBError {
             Self::UnknownName { .. } => StatusCode::NOT_FOUND,
             Self::ProgramNotCompiled => StatusCode::SERVICE_UNAVAILABLE,
             Self::ProgramFailedToCompile => StatusCode::BAD_REQUEST,
+            Self::ProgramNotCompiling { .. } => StatusCode::BAD_REQUEST,
             Self::ProgramNotSet => StatusCode::BAD_REQUEST,
             // should in practice not happen, e.g., would mean a Uuid conflict:
             Self::UniqueKeyViolation { .. } => StatusCode::INTERNAL_SERVER_ERROR,
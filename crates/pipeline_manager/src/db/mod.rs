@@ -2,7 +2,7 @@
 use crate::config::ApiServerConfig;
 use crate::{
     auth::{TenantId, TenantRecord},
-    compiler::{ProgramConfig, ProgramStatus},
+    compiler::{CompilerDiagnostic, ProgramConfig, ProgramStatus},
     config::DatabaseConfig,
 };
 use async_trait::async_trait;
@@ -49,10 +49,15 @@ mod embedded {
 /// We use the `status` and `status_since` columns to maintain the compilation
 /// queue.  A program is enqueued for compilation by setting its status to
 /// `ProgramStatus::Pending`.  The `status_since` column is set to the current
-/// time, which determines the position of the program in the queue.
+/// time, which determines the position of the program in the queue among
+/// the programs of tenants with as many compilations in progress.  Programs
+/// of tenants with fewer compilations in progress are compiled first, so
+/// that tenants share the compiler workers fairly.
 pub struct ProjectDB {
     pub config: tokio_postgres::Config,
     pool: Pool,
+    /// Master key of the secrets stored in the database.
+    secrets_key: Option<SecretsKey>,
     // Used in dev mode for having an embedded Postgres DB live through the
     // lifetime of the program.
     #[cfg(feature = "pg-embed")]
@@ -61,7 +66,7 @@ pub struct ProjectDB {
 }
 
 /// Version number.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 #[repr(transparent)]
 #[serde(transparent)]
@@ -90,6 +95,14 @@ pub(crate) struct ApiKeyDescr {
     pub id: ApiKeyId,
     pub name: String,
     pub scopes: Vec<ApiPermission>,
+    /// Pipelines and programs the key is restricted to.  An empty list
+    /// means that the key is not restricted to specific resources.
+    pub resource_scopes: Vec<ApiKeyScope>,
+    /// Time after which the key is no longer accepted.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Role of the key within the tenant, which limits the requests it can
+    /// issue the same way as for users.
+    pub role: Role,
 }
 
 /// Permission types for invoking pipeline manager APIs
@@ -115,11 +128,60 @@ impl FromStr for ApiPermission {
     }
 }
 
+/// Grants an API key permission to perform a set of actions on a single
+/// pipeline or program.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub(crate) struct ApiKeyScope {
+    /// Pipeline or program the scope applies to.
+    pub resource: ApiResource,
+    /// Actions the key may perform on the resource.
+    pub actions: Vec<ApiAction>,
+}
+
+impl ApiKeyScope {
+    /// Returns true if the scope allows `action` on `resource`.
+    pub(crate) fn allows(&self, resource: &ApiResource, action: ApiAction) -> bool {
+        &self.resource == resource && self.actions.contains(&action)
+    }
+}
+
+/// Pipeline manager resource that an API key can be scoped to, identified by
+/// its id, so that the scope is not affected by renaming the resource or
+/// reusing its name.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ApiResource {
+    Pipeline(PipelineId),
+    Program(ProgramId),
+}
+
+/// Actions that an API key scope can grant on a resource.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ApiAction {
+    /// Retrieve the resource, its configuration, status, statistics and logs.
+    Read,
+    /// Modify the resource, or compile a program.
+    Update,
+    /// Delete the resource.
+    Delete,
+    /// Start, pause, or shut down a pipeline.
+    Deploy,
+    /// Push data to the tables of a pipeline.
+    Ingress,
+    /// Read data from the tables and views of a pipeline.
+    Egress,
+}
+
 // Re-exports
 // Program
 mod program;
 pub(crate) use self::program::ProgramDescr;
 pub use self::program::ProgramId;
+pub(crate) use self::program::ProgramVersionDescr;
 
 // Connectors
 mod connector;
@@ -138,11 +200,41 @@ pub(crate) use self::pipeline::PipelineRuntimeState;
 pub use self::pipeline::PipelineStatus;
 pub(crate) use self::pipeline::Revision;
 
+// Pipeline status transitions
+pub(crate) mod pipeline_event;
+use self::pipeline_event::PipelineEvent;
+pub use self::pipeline_event::PipelineEventId;
+
 // Services
 mod service;
 pub(crate) use self::service::ServiceDescr;
 pub use self::service::ServiceId;
 
+// Roles and audit log
+pub(crate) mod audit;
+use self::audit::{AuditEvent, AuditEventFilter, Role, UserRole};
+
+// Export and import of tenant resources
+pub(crate) mod bundle;
+
+// Encrypted secrets
+pub(crate) mod secret;
+use self::secret::{SecretDescr, SecretsKey};
+
+// Webhook notifications
+pub(crate) mod webhook;
+use self::webhook::{
+    PendingWebhookDelivery, WebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryId,
+    WebhookDescr, WebhookEventType, WebhookId,
+};
+
+// Scheduled pipeline actions
+pub(crate) mod pipeline_schedule;
+use self::pipeline_schedule::{
+    DuePipelineSchedule, PipelineScheduleConfig, PipelineScheduleDescr, PipelineScheduleId,
+    PipelineScheduleRun,
+};
+
 // The goal for these methods is to avoid multiple DB interactions as much as
 // possible and if not, use transactions
 #[async_trait]
@@ -251,8 +343,40 @@ impl Storage for ProjectDB {
         Ok(program::get_program_by_name(self, tenant_id, program_name, with_code, txn).await?)
     }
 
-    async fn delete_program(&self, tenant_id: TenantId, program_name: &str) -> Result<(), DBError> {
-        Ok(program::delete_program(self, tenant_id, program_name).await?)
+    async fn list_program_versions(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        with_code: bool,
+    ) -> Result<Vec<ProgramVersionDescr>, DBError> {
+        program::list_program_versions(self, tenant_id, program_name, with_code).await
+    }
+
+    async fn get_program_version(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        version: Version,
+        txn: Option<&Transaction<'_>>,
+    ) -> Result<ProgramVersionDescr, DBError> {
+        program::get_program_version(self, tenant_id, program_name, version, txn).await
+    }
+
+    async fn delete_program(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        txn: Option<&Transaction<'_>>,
+    ) -> Result<(), DBError> {
+        Ok(program::delete_program(self, tenant_id, program_name, txn).await?)
+    }
+
+    async fn cancel_program_compilation(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+    ) -> Result<(), DBError> {
+        Ok(program::cancel_program_compilation(self, tenant_id, program_name).await?)
     }
 
     async fn all_programs(&self) -> Result<Vec<(TenantId, ProgramDescr)>, DBError> {
@@ -264,23 +388,7 @@ impl Storage for ProjectDB {
     }
 
     async fn next_job(&self) -> Result<Option<(TenantId, ProgramId, Version)>, DBError> {
-        let manager = self.pool.get().await?;
-        // Find the oldest pending project.
-        let stmt = manager
-            .prepare_cached(
-                "SELECT id, version, tenant_id FROM program WHERE status = 'pending' AND status_since = (SELECT min(status_since) FROM program WHERE status = 'pending')"
-            )
-            .await?;
-        let res = manager.query(&stmt, &[]).await?;
-
-        if let Some(row) = res.first() {
-            let program_id: ProgramId = ProgramId(row.get(0));
-            let version: Version = Version(row.get(1));
-            let tenant_id: TenantId = TenantId(row.get(2));
-            Ok(Some((tenant_id, program_id, version)))
-        } else {
-            Ok(None)
-        }
+        program::next_job(self).await
     }
 
     async fn create_pipeline_deployment(
@@ -455,13 +563,48 @@ impl Storage for ProjectDB {
         tenant_id: TenantId,
         pipeline_id: PipelineId,
         desired_status: PipelineStatus,
+        actor: &str,
+    ) -> Result<(), DBError> {
+        Ok(pipeline::set_pipeline_desired_status(
+            self,
+            tenant_id,
+            pipeline_id,
+            desired_status,
+            actor,
+        )
+        .await?)
+    }
+
+    async fn request_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        revision: &PipelineRevision,
+        actor: &str,
     ) -> Result<(), DBError> {
         Ok(
-            pipeline::set_pipeline_desired_status(self, tenant_id, pipeline_id, desired_status)
+            pipeline::request_pipeline_upgrade(self, tenant_id, pipeline_id, revision, actor)
                 .await?,
         )
     }
 
+    async fn get_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+    ) -> Result<Option<PipelineRevision>, DBError> {
+        Ok(pipeline::get_pipeline_upgrade(self, tenant_id, pipeline_id).await?)
+    }
+
+    async fn finish_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        deploy: bool,
+    ) -> Result<(), DBError> {
+        Ok(pipeline::finish_pipeline_upgrade(self, tenant_id, pipeline_id, deploy).await?)
+    }
+
     /// Returns true if the connector of a given name is an input connector.
     async fn attached_connector_is_input(
         &self,
@@ -476,8 +619,9 @@ impl Storage for ProjectDB {
         &self,
         tenant_id: TenantId,
         pipeline_name: &str,
+        txn: Option<&Transaction<'_>>,
     ) -> Result<(), DBError> {
-        Ok(pipeline::delete_pipeline(self, tenant_id, pipeline_name).await?)
+        Ok(pipeline::delete_pipeline(self, tenant_id, pipeline_name, txn).await?)
     }
 
     async fn new_connector(
@@ -538,28 +682,22 @@ impl Storage for ProjectDB {
         &self,
         tenant_id: TenantId,
         connector_name: &str,
+        txn: Option<&Transaction<'_>>,
     ) -> Result<(), DBError> {
-        Ok(connector::delete_connector(self, tenant_id, connector_name).await?)
+        Ok(connector::delete_connector(self, tenant_id, connector_name, txn).await?)
     }
 
     async fn list_api_keys(&self, tenant_id: TenantId) -> Result<Vec<ApiKeyDescr>, DBError> {
         let manager = self.pool.get().await?;
         let stmt = manager
-            .prepare_cached("SELECT id, name, scopes FROM api_key WHERE tenant_id = $1")
+            .prepare_cached(
+                "SELECT id, name, scopes, resource_scopes, expires_at, role FROM api_key WHERE tenant_id = $1",
+            )
             .await?;
         let rows = manager.query(&stmt, &[&tenant_id.0]).await?;
         let mut result = Vec::with_capacity(rows.len());
         for row in rows {
-            let id: ApiKeyId = ApiKeyId(row.get(0));
-            let name: String = row.get(1);
-            let vec: Vec<String> = row.get(2);
-            let scopes = vec
-                .iter()
-                .map(|s| {
-                    ApiPermission::from_str(s).expect("Unexpected ApiPermission string in the DB")
-                })
-                .collect();
-            result.push(ApiKeyDescr { id, name, scopes });
+            result.push(Self::row_to_api_key_descr(&row)?);
         }
         Ok(result)
     }
@@ -568,22 +706,12 @@ impl Storage for ProjectDB {
         let manager = self.pool.get().await?;
         let stmt = manager
             .prepare_cached(
-                "SELECT id, name, scopes FROM api_key WHERE tenant_id = $1 and name = $2",
+                "SELECT id, name, scopes, resource_scopes, expires_at, role FROM api_key WHERE tenant_id = $1 and name = $2",
             )
             .await?;
         let maybe_row = manager.query_opt(&stmt, &[&tenant_id.0, &name]).await?;
         if let Some(row) = maybe_row {
-            let id: ApiKeyId = ApiKeyId(row.get(0));
-            let name: String = row.get(1);
-            let vec: Vec<String> = row.get(2);
-            let scopes = vec
-                .iter()
-                .map(|s| {
-                    ApiPermission::from_str(s).expect("Unexpected ApiPermission string in the DB")
-                })
-                .collect();
-
-            Ok(ApiKeyDescr { id, name, scopes })
+            Self::row_to_api_key_descr(&row)
         } else {
             Err(DBError::UnknownApiKey {
                 name: name.to_string(),
@@ -606,6 +734,7 @@ impl Storage for ProjectDB {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn store_api_key_hash(
         &self,
         tenant_id: TenantId,
@@ -613,14 +742,18 @@ impl Storage for ProjectDB {
         name: &str,
         key: &str,
         scopes: Vec<ApiPermission>,
+        resource_scopes: Vec<ApiKeyScope>,
+        expires_at: Option<DateTime<Utc>>,
+        role: Role,
     ) -> Result<(), DBError> {
         let mut hasher = sha::Sha256::new();
         hasher.update(key.as_bytes());
         let hash = openssl::base64::encode_block(&hasher.finish());
+        let resource_scopes = serde_json::to_string(&resource_scopes).unwrap();
         let manager = self.pool.get().await?;
         let stmt = manager
             .prepare_cached(
-                "INSERT INTO api_key (id, tenant_id, name, hash, scopes) VALUES ($1, $2, $3, $4, $5)",
+                "INSERT INTO api_key (id, tenant_id, name, hash, scopes, resource_scopes, expires_at, role) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
             )
             .await?;
         let res = manager
@@ -638,6 +771,9 @@ impl Storage for ProjectDB {
                             ApiPermission::Write => API_PERMISSION_WRITE,
                         })
                         .collect::<Vec<&str>>(),
+                    &resource_scopes,
+                    &expires_at.map(|t| t.timestamp()),
+                    &role.to_string(),
                 ],
             )
             .await
@@ -652,28 +788,21 @@ impl Storage for ProjectDB {
         }
     }
 
-    async fn validate_api_key(
-        &self,
-        api_key: &str,
-    ) -> Result<(TenantId, Vec<ApiPermission>), DBError> {
+    async fn validate_api_key(&self, api_key: &str) -> Result<(TenantId, ApiKeyDescr), DBError> {
         let mut hasher = sha::Sha256::new();
         hasher.update(api_key.as_bytes());
         let hash = openssl::base64::encode_block(&hasher.finish());
         let manager = self.pool.get().await?;
         let stmt = manager
-            .prepare_cached("SELECT tenant_id, scopes FROM api_key WHERE hash = $1")
+            .prepare_cached(
+                "SELECT id, name, scopes, resource_scopes, expires_at, role, tenant_id FROM api_key WHERE hash = $1 AND (expires_at IS NULL OR expires_at > $2)",
+            )
             .await?;
         let res = manager
-            .query_one(&stmt, &[&hash])
+            .query_one(&stmt, &[&hash, &Utc::now().timestamp()])
             .await
             .map_err(|_| DBError::InvalidKey)?;
-        let tenant_id = TenantId(res.get(0));
-        let vec: Vec<String> = res.get(1);
-        let vec = vec
-            .iter()
-            .map(|s| ApiPermission::from_str(s).expect("Unexpected ApiPermission string in the DB"))
-            .collect();
-        Ok((tenant_id, vec))
+        Ok((TenantId(res.get(6)), Self::row_to_api_key_descr(&res)?))
     }
 
     async fn get_or_create_tenant_id(
@@ -822,8 +951,13 @@ impl Storage for ProjectDB {
         )
     }
 
-    async fn delete_service(&self, tenant_id: TenantId, service_name: &str) -> Result<(), DBError> {
-        Ok(service::delete_service(self, tenant_id, service_name).await?)
+    async fn delete_service(
+        &self,
+        tenant_id: TenantId,
+        service_name: &str,
+        txn: Option<&Transaction<'_>>,
+    ) -> Result<(), DBError> {
+        Ok(service::delete_service(self, tenant_id, service_name, txn).await?)
     }
 
     async fn new_service_probe(
@@ -905,6 +1039,210 @@ impl Storage for ProjectDB {
                 .await?,
         )
     }
+
+    async fn set_user_role(
+        &self,
+        tenant_id: TenantId,
+        user_name: &str,
+        role: Role,
+    ) -> Result<(), DBError> {
+        audit::set_user_role(self, tenant_id, user_name, role).await
+    }
+
+    async fn get_user_role(
+        &self,
+        tenant_id: TenantId,
+        user_name: &str,
+    ) -> Result<Option<Role>, DBError> {
+        audit::get_user_role(self, tenant_id, user_name).await
+    }
+
+    async fn list_user_roles(&self, tenant_id: TenantId) -> Result<Vec<UserRole>, DBError> {
+        audit::list_user_roles(self, tenant_id).await
+    }
+
+    async fn delete_user_role(&self, tenant_id: TenantId, user_name: &str) -> Result<(), DBError> {
+        audit::delete_user_role(self, tenant_id, user_name).await
+    }
+
+    async fn new_audit_event(
+        &self,
+        tenant_id: TenantId,
+        event: &AuditEvent,
+    ) -> Result<(), DBError> {
+        audit::new_audit_event(self, tenant_id, event).await
+    }
+
+    async fn list_audit_events(
+        &self,
+        tenant_id: TenantId,
+        filter: &AuditEventFilter,
+    ) -> Result<Vec<AuditEvent>, DBError> {
+        audit::list_audit_events(self, tenant_id, filter).await
+    }
+
+    async fn set_secret(
+        &self,
+        tenant_id: TenantId,
+        name: &str,
+        value: &str,
+    ) -> Result<(), DBError> {
+        secret::set_secret(self, tenant_id, name, value).await
+    }
+
+    async fn list_secrets(&self, tenant_id: TenantId) -> Result<Vec<SecretDescr>, DBError> {
+        secret::list_secrets(self, tenant_id).await
+    }
+
+    async fn get_secret_value(&self, tenant_id: TenantId, name: &str) -> Result<String, DBError> {
+        secret::get_secret_value(self, tenant_id, name).await
+    }
+
+    async fn delete_secret(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError> {
+        secret::delete_secret(self, tenant_id, name).await
+    }
+
+    async fn new_pipeline_event(
+        &self,
+        tenant_id: TenantId,
+        event: &PipelineEvent,
+    ) -> Result<(), DBError> {
+        pipeline_event::new_pipeline_event(self, tenant_id, event).await
+    }
+
+    async fn list_pipeline_events(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        limit: Option<u32>,
+    ) -> Result<Vec<PipelineEvent>, DBError> {
+        pipeline_event::list_pipeline_events(self, tenant_id, pipeline_id, limit).await
+    }
+
+    async fn get_pipeline_event(
+        &self,
+        tenant_id: TenantId,
+        pipeline_event_id: PipelineEventId,
+    ) -> Result<PipelineEvent, DBError> {
+        pipeline_event::get_pipeline_event(self, tenant_id, pipeline_event_id).await
+    }
+
+    async fn new_webhook(
+        &self,
+        tenant_id: TenantId,
+        id: Uuid,
+        name: &str,
+        url: &str,
+        secret: &str,
+        event_types: &[WebhookEventType],
+    ) -> Result<WebhookId, DBError> {
+        webhook::new_webhook(self, tenant_id, id, name, url, secret, event_types).await
+    }
+
+    async fn list_webhooks(&self, tenant_id: TenantId) -> Result<Vec<WebhookDescr>, DBError> {
+        webhook::list_webhooks(self, tenant_id).await
+    }
+
+    async fn delete_webhook(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError> {
+        webhook::delete_webhook(self, tenant_id, name).await
+    }
+
+    async fn new_webhook_deliveries(
+        &self,
+        tenant_id: TenantId,
+        event_type: WebhookEventType,
+        payload: &serde_json::Value,
+    ) -> Result<usize, DBError> {
+        webhook::new_webhook_deliveries(self, tenant_id, event_type, payload).await
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        tenant_id: TenantId,
+        webhook_name: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<WebhookDelivery>, DBError> {
+        webhook::list_webhook_deliveries(self, tenant_id, webhook_name, limit).await
+    }
+
+    async fn next_webhook_deliveries(
+        &self,
+        limit: u32,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<PendingWebhookDelivery>, DBError> {
+        webhook::next_webhook_deliveries(self, limit, lease_until).await
+    }
+
+    async fn record_webhook_delivery_attempt(
+        &self,
+        webhook_delivery_id: WebhookDeliveryId,
+        attempt: &WebhookDeliveryAttempt,
+    ) -> Result<(), DBError> {
+        webhook::record_webhook_delivery_attempt(self, webhook_delivery_id, attempt).await
+    }
+
+    async fn create_or_replace_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        id: Uuid,
+        pipeline_name: &str,
+        schedule_name: &str,
+        config: &PipelineScheduleConfig,
+    ) -> Result<(bool, PipelineScheduleId), DBError> {
+        pipeline_schedule::create_or_replace_pipeline_schedule(
+            self,
+            tenant_id,
+            id,
+            pipeline_name,
+            schedule_name,
+            config,
+        )
+        .await
+    }
+
+    async fn list_pipeline_schedules(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+    ) -> Result<Vec<PipelineScheduleDescr>, DBError> {
+        pipeline_schedule::list_pipeline_schedules(self, tenant_id, pipeline_name).await
+    }
+
+    async fn get_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        schedule_name: &str,
+    ) -> Result<PipelineScheduleDescr, DBError> {
+        pipeline_schedule::get_pipeline_schedule(self, tenant_id, pipeline_name, schedule_name)
+            .await
+    }
+
+    async fn delete_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        schedule_name: &str,
+    ) -> Result<(), DBError> {
+        pipeline_schedule::delete_pipeline_schedule(self, tenant_id, pipeline_name, schedule_name)
+            .await
+    }
+
+    async fn next_pipeline_schedule_runs(
+        &self,
+        limit: u32,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<DuePipelineSchedule>, DBError> {
+        pipeline_schedule::next_pipeline_schedule_runs(self, limit, lease_until).await
+    }
+
+    async fn record_pipeline_schedule_run(
+        &self,
+        pipeline_schedule_id: PipelineScheduleId,
+        run: &PipelineScheduleRun,
+    ) -> Result<(), DBError> {
+        pipeline_schedule::record_pipeline_schedule_run(self, pipeline_schedule_id, run).await
+    }
 }
 
 impl ProjectDB {
@@ -921,15 +1259,19 @@ impl ProjectDB {
                 .postgres_embed_data_dir();
             let pg_inst = pg_setup::install(database_dir, true, Some(8082)).await?;
             let connection_string = pg_inst.db_uri.to_string();
-            return Self::connect_inner(connection_string.as_str(), Some(pg_inst)).await;
+            let mut db = Self::connect_inner(connection_string.as_str(), Some(pg_inst)).await?;
+            db.secrets_key = db_config.secrets_master_key();
+            return Ok(db);
         };
 
-        Self::connect_inner(
+        let mut db = Self::connect_inner(
             connection_str.as_str(),
             #[cfg(feature = "pg-embed")]
             None,
         )
-        .await
+        .await?;
+        db.secrets_key = db_config.secrets_master_key();
+        Ok(db)
     }
 
     /// Connect to the project database.
@@ -987,10 +1329,15 @@ impl ProjectDB {
         return Ok(Self {
             config,
             pool,
+            secrets_key: None,
             pg_inst,
         });
         #[cfg(not(feature = "pg-embed"))]
-        return Ok(Self { config, pool });
+        return Ok(Self {
+            config,
+            pool,
+            secrets_key: None,
+        });
     }
 
     /// We check if a program is 'in use' by checking if it is referenced by a
@@ -1014,6 +1361,42 @@ impl ProjectDB {
         Ok(row.get(0))
     }
 
+    /// Helper to convert a row of the `api_key` table into an `ApiKeyDescr`.
+    fn row_to_api_key_descr(row: &tokio_postgres::Row) -> Result<ApiKeyDescr, DBError> {
+        let id: ApiKeyId = ApiKeyId(row.get(0));
+        let name: String = row.get(1);
+        let vec: Vec<String> = row.get(2);
+        let scopes = vec
+            .iter()
+            .map(|s| ApiPermission::from_str(s).expect("Unexpected ApiPermission string in the DB"))
+            .collect();
+        let resource_scopes = Self::parse_resource_scopes(row.get(3))?;
+        let expires_at = row
+            .get::<_, Option<i64>>(4)
+            .map(|t| pipeline::convert_bigint_to_time("api_key.expires_at", t))
+            .transpose()?;
+        let role = Role::from_str(row.get(5))?;
+
+        Ok(ApiKeyDescr {
+            id,
+            name,
+            scopes,
+            resource_scopes,
+            expires_at,
+            role,
+        })
+    }
+
+    /// Helper to parse the JSON-encoded `resource_scopes` column of the
+    /// `api_key` table.
+    fn parse_resource_scopes(resource_scopes: &str) -> Result<Vec<ApiKeyScope>, DBError> {
+        serde_json::from_str(resource_scopes).map_err(|e| {
+            DBError::invalid_data(format!(
+                "Invalid API key scopes in the database: '{resource_scopes}' ({e})"
+            ))
+        })
+    }
+
     /// Helper to convert postgres error into a `DBError` if the underlying
     /// low-level error thrown by the database matches.
     fn maybe_unique_violation(err: PgError) -> DBError {
@@ -1028,6 +1411,7 @@ impl ProjectDB {
                     }
                     Some("pipeline_pkey") => DBError::unique_key_violation("pipeline_pkey"),
                     Some("api_key_pkey") => DBError::unique_key_violation("api_key_pkey"),
+                    Some("audit_log_pkey") => DBError::unique_key_violation("audit_log_pkey"),
                     Some("unique_hash") => DBError::duplicate_key(),
                     Some(_constraint) => DBError::DuplicateName,
                     None => DBError::DuplicateName,
@@ -1101,7 +1485,8 @@ impl ProjectDB {
             if let Some(db_err) = db_err {
                 if db_err.code() == &tokio_postgres::error::SqlState::FOREIGN_KEY_VIOLATION
                     && (db_err.constraint() == Some("pipeline_pipeline_id_fkey")
-                        || db_err.constraint() == Some("attached_connector_pipeline_id_fkey"))
+                        || db_err.constraint() == Some("attached_connector_pipeline_id_fkey")
+                        || db_err.constraint() == Some("pipeline_event_pipeline_id_fkey"))
                 {
                     return DBError::UnknownPipeline { pipeline_id };
                 }
@@ -1179,6 +1564,38 @@ impl ProjectDB {
         err
     }
 
+    /// Retrieve the name of a tenant, i.e., the claim that identifies it.
+    pub(crate) async fn get_tenant_name(&self, tenant_id: TenantId) -> Result<String, DBError> {
+        let manager = self.pool.get().await?;
+        let stmt = manager
+            .prepare_cached("SELECT tenant FROM tenant WHERE id = $1")
+            .await?;
+        let row = manager.query_opt(&stmt, &[&tenant_id.0]).await?;
+        row.map(|row| row.get(0))
+            .ok_or(DBError::UnknownTenant { tenant_id })
+    }
+
+    /// Record the warnings reported while compiling version `version` of a
+    /// program.  Does nothing if the program has moved on to another version.
+    pub(crate) async fn set_program_warnings(
+        &self,
+        tenant_id: TenantId,
+        program_id: ProgramId,
+        version: Version,
+        warnings: &[CompilerDiagnostic],
+    ) -> Result<(), DBError> {
+        program::set_program_warnings(self, tenant_id, program_id, version, warnings).await
+    }
+
+    /// Retrieve the pending programs of all tenants in the order in which
+    /// the compiler picks them, to report the position of programs in the
+    /// queue.  The compiler itself uses [`Storage::next_job`].
+    pub(crate) async fn compilation_queue(
+        &self,
+    ) -> Result<Vec<(TenantId, ProgramId, Version)>, DBError> {
+        program::compilation_queue(self).await
+    }
+
     // TODO: Should be part of the Storage trait
     pub(crate) async fn pipeline_config(
         &self,
@@ -1281,6 +1698,44 @@ impl ProjectDB {
         Ok(version)
     }
 
+    /// Rolls a program back to an earlier version by, within a transaction,
+    /// creating a new version with the code and configuration of `version`.
+    ///
+    /// Returns the new version, which is queued for compilation. The version
+    /// is unchanged if the program already matches `version`.
+    pub(crate) async fn rollback_program(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        version: Version,
+        guard: Option<Version>,
+    ) -> Result<Version, DBError> {
+        let mut manager = self.pool.get().await?;
+        let txn = manager.transaction().await?;
+        let program = self
+            .get_program_by_name(tenant_id, program_name, false, Some(&txn))
+            .await?;
+        let target = self
+            .get_program_version(tenant_id, program_name, version, Some(&txn))
+            .await?;
+        let new_version = self
+            .update_program(
+                tenant_id,
+                program.program_id,
+                &None,
+                &None,
+                &target.code,
+                &None,
+                &None,
+                &Some(target.config),
+                guard,
+                Some(&txn),
+            )
+            .await?;
+        txn.commit().await?;
+        Ok(new_version)
+    }
+
     pub(crate) async fn create_or_replace_pipeline(
         &self,
         tenant_id: TenantId,
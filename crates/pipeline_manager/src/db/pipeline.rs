@@ -10,6 +10,7 @@ use futures_util::TryFutureExt;
 use pipeline_types::{
     config::{
         ConnectorConfig, InputEndpointConfig, OutputEndpointConfig, PipelineConfig, RuntimeConfig,
+        TransportConfig,
     },
     error::ErrorResponse,
 };
@@ -445,9 +446,10 @@ impl PipelineRevision {
             }
             let output_endpoint_config = OutputEndpointConfig {
                 stream: Cow::from(ac.relation_name.clone()),
-                // This field gets skipped during serialization/deserialization,
-                // so it doesn't matter what value we use here
+                // These fields get skipped during serialization/deserialization,
+                // so it doesn't matter what values we use here
                 query: Default::default(),
+                snapshot: false,
                 connector_config: connector.unwrap().config.clone(),
                 output_buffer_config: Default::default(),
             };
@@ -464,6 +466,93 @@ impl PipelineRevision {
 
         Ok(pc)
     }
+
+    /// Checks whether a pipeline running this revision can be upgraded in
+    /// place to revision `to`.
+    ///
+    /// The new revision runs in a standby instance of the pipeline, which
+    /// rebuilds the state of the running instance by replaying the input
+    /// steps that the running instance recorded in its fault-tolerant input
+    /// connectors, and then takes over its fault-tolerant output connectors.
+    /// This requires the new program to declare the same tables as the
+    /// running one, the input connectors to be unchanged, and all connectors
+    /// to be fault tolerant.  Returns the reason why the upgrade is not
+    /// possible otherwise.
+    pub(crate) fn check_upgrade(&self, to: &PipelineRevision) -> Result<(), String> {
+        if to.program.program_id == self.program.program_id
+            && to.program.version == self.program.version
+        {
+            return Err(format!(
+                "the pipeline already runs version {} of program '{}'",
+                to.program.version, to.program.name
+            ));
+        }
+
+        let tables = |program: &ProgramDescr| -> BTreeMap<String, Relation> {
+            program
+                .schema
+                .iter()
+                .flat_map(|schema| schema.inputs.iter())
+                .map(|relation| (relation.name(), relation.clone()))
+                .collect()
+        };
+        let from = tables(&self.program);
+        let new = tables(&to.program);
+        let mut changes = Vec::new();
+        for (name, relation) in &new {
+            match from.get(name) {
+                None => changes.push(format!("table '{name}' is added")),
+                Some(old) if old.fields != relation.fields => {
+                    changes.push(format!("table '{name}' is changed"))
+                }
+                Some(_) => (),
+            }
+        }
+        for name in from.keys().filter(|name| !new.contains_key(*name)) {
+            changes.push(format!("table '{name}' is removed"));
+        }
+        if !changes.is_empty() {
+            return Err(format!(
+                "the tables of the pipeline must not change, but {}",
+                changes.join(", ")
+            ));
+        }
+
+        if to.config.inputs != self.config.inputs {
+            return Err("the input connectors of the pipeline must not change".to_string());
+        }
+        let not_fault_tolerant: Vec<&str> = to
+            .config
+            .inputs
+            .iter()
+            .filter(|(_, input)| {
+                !matches!(
+                    &input.connector_config.transport,
+                    TransportConfig::KafkaInput(kafka) if kafka.fault_tolerance.is_some()
+                )
+            })
+            .map(|(name, _)| name.as_ref())
+            .chain(
+                to.config
+                    .outputs
+                    .iter()
+                    .filter(|(_, output)| {
+                        !matches!(
+                            &output.connector_config.transport,
+                            TransportConfig::KafkaOutput(kafka) if kafka.fault_tolerance.is_some()
+                        )
+                    })
+                    .map(|(name, _)| name.as_ref()),
+            )
+            .collect();
+        if !not_fault_tolerant.is_empty() {
+            return Err(format!(
+                "the connectors of the pipeline must be fault tolerant, but these are not: {}",
+                not_fault_tolerant.join(", ")
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// Pipeline descriptor.
@@ -518,6 +607,23 @@ pub(crate) struct PipelineRuntimeState {
     /// Time when the pipeline started executing.
     #[cfg_attr(test, proptest(value = "Utc::now()"))]
     pub created: DateTime<Utc>,
+
+    /// Number of consecutive times the pipeline was restarted after failing,
+    /// according to its restart policy.
+    ///
+    /// Reset when the user shuts the pipeline down, and once the restarted
+    /// pipeline has been running without failing for a while.
+    #[cfg_attr(test, proptest(strategy = "0..10u32"))]
+    pub restart_count: u32,
+
+    /// Error that caused the most recent automatic restart of the pipeline.
+    #[cfg_attr(test, proptest(value = "None"))]
+    pub last_failure: Option<ErrorResponse>,
+
+    /// User (`user:<name>`), API key (`api_key:<name>`) or pipeline schedule
+    /// (`schedule:<name>`) that requested the desired status of the
+    /// pipeline.
+    pub desired_status_actor: Option<String>,
 }
 
 impl PipelineRuntimeState {
@@ -676,7 +782,8 @@ pub(crate) async fn list_pipelines(
                                                 'is_input', is_input))
                             FILTER (WHERE ac.name IS NOT NULL),
                     '[]'),
-            rt.location, rt.desired_status, rt.current_status, rt.status_since, rt.error, rt.created
+            rt.location, rt.desired_status, rt.current_status, rt.status_since, rt.error, rt.created,
+            rt.restart_count, rt.last_failure, rt.desired_status_actor
             FROM pipeline p
             INNER JOIN pipeline_runtime_state rt on p.id = rt.id
             LEFT JOIN program on p.program_id = program.id
@@ -714,7 +821,8 @@ pub(crate) async fn get_pipeline_by_id(
                                                     'is_input', is_input))
                                 FILTER (WHERE ac.name IS NOT NULL),
                         '[]'),
-                rt.location, rt.desired_status, rt.current_status, rt.status_since, rt.error, rt.created
+                rt.location, rt.desired_status, rt.current_status, rt.status_since, rt.error, rt.created,
+            rt.restart_count, rt.last_failure, rt.desired_status_actor
                 FROM pipeline p
                 INNER JOIN pipeline_runtime_state rt on p.id = rt.id
                 LEFT JOIN program on p.program_id = program.id
@@ -780,7 +888,8 @@ pub(crate) async fn get_pipeline_runtime_state_by_id(
     let manager = db.pool.get().await?;
     let stmt = manager
         .prepare_cached(
-            "SELECT location, desired_status, current_status, status_since, error, created
+            "SELECT location, desired_status, current_status, status_since, error, created,
+                    restart_count, last_failure, desired_status_actor
                 FROM pipeline_runtime_state
                 WHERE id = $1 AND tenant_id = $2",
         )
@@ -805,6 +914,12 @@ pub(crate) async fn get_pipeline_runtime_state_by_id(
                 .map(|s| deserialize_error_response(pipeline_id, &s))
                 .transpose()?,
             created: convert_bigint_to_time("pipeline_runtime_state.created", row.get(5))?,
+            restart_count: row.get::<_, i64>(6) as u32,
+            last_failure: row
+                .get::<_, Option<String>>(7)
+                .map(|s| deserialize_error_response(pipeline_id, &s))
+                .transpose()?,
+            desired_status_actor: row.get(8),
         })
     } else {
         Err(DBError::UnknownPipeline { pipeline_id })
@@ -819,7 +934,8 @@ pub(crate) async fn get_pipeline_runtime_state_by_name(
     let manager = db.pool.get().await?;
     let stmt = manager
         .prepare_cached(
-            "SELECT pr.location, pr.desired_status, pr.current_status, pr.status_since, pr.error, pr.created, pr.id
+            "SELECT pr.location, pr.desired_status, pr.current_status, pr.status_since, pr.error, pr.created, pr.id,
+                    pr.restart_count, pr.last_failure, pr.desired_status_actor
                 FROM pipeline_runtime_state pr
                 JOIN pipeline p
                     ON p.id = pr.id AND p.tenant_id = pr.tenant_id
@@ -847,6 +963,12 @@ pub(crate) async fn get_pipeline_runtime_state_by_name(
                 .map(|s| deserialize_error_response(pipeline_id, &s))
                 .transpose()?,
             created: convert_bigint_to_time("pipeline_runtime_state.created", row.get(5))?,
+            restart_count: row.get::<_, i64>(7) as u32,
+            last_failure: row
+                .get::<_, Option<String>>(8)
+                .map(|s| deserialize_error_response(pipeline_id, &s))
+                .transpose()?,
+            desired_status_actor: row.get(9),
         })
     } else {
         Err(DBError::UnknownPipelineName {
@@ -906,7 +1028,8 @@ pub(crate) async fn get_pipeline_by_name(
                                                     'is_input', is_input))
                                 FILTER (WHERE ac.name IS NOT NULL),
                         '[]'),
-                rt.location, rt.desired_status, rt.current_status, rt.status_since, rt.error, rt.created
+                rt.location, rt.desired_status, rt.current_status, rt.status_since, rt.error, rt.created,
+            rt.restart_count, rt.last_failure, rt.desired_status_actor
                 FROM pipeline p
                 INNER JOIN pipeline_runtime_state rt on p.id = rt.id
                 LEFT JOIN program on p.program_id = program.id
@@ -1104,7 +1227,9 @@ pub(crate) async fn update_pipeline_runtime_state(
                     current_status = $4,
                     status_since = $5,
                     created = $6,
-                    error = $7
+                    error = $7,
+                    restart_count = $8,
+                    last_failure = $9
                 WHERE id = $1 AND tenant_id = $2
                 ",
         )
@@ -1124,6 +1249,11 @@ pub(crate) async fn update_pipeline_runtime_state(
                     .error
                     .as_ref()
                     .map(|e| serde_json::to_string(&e).unwrap()),
+                &(state.restart_count as i64),
+                &state
+                    .last_failure
+                    .as_ref()
+                    .map(|e| serde_json::to_string(&e).unwrap()),
             ],
         )
         .await?;
@@ -1140,20 +1270,25 @@ pub(crate) async fn set_pipeline_desired_status(
     tenant_id: TenantId,
     pipeline_id: PipelineId,
     desired_status: PipelineStatus,
+    actor: &str,
 ) -> Result<(), DBError> {
     let desired_status: &'static str = desired_status.into();
     let manager = db.pool.get().await?;
     let stmt = manager
         .prepare_cached(
             "UPDATE pipeline_runtime_state
-                SET desired_status = $3
+                SET desired_status = $3,
+                    desired_status_actor = $4
                 WHERE tenant_id = $1 AND id = $2
                 ",
         )
         .await?;
 
     let modified_rows = manager
-        .execute(&stmt, &[&tenant_id.0, &pipeline_id.0, &desired_status])
+        .execute(
+            &stmt,
+            &[&tenant_id.0, &pipeline_id.0, &desired_status, &actor],
+        )
         .await?;
 
     if modified_rows == 0 {
@@ -1162,6 +1297,108 @@ pub(crate) async fn set_pipeline_desired_status(
     Ok(())
 }
 
+/// Records a request to upgrade the running instance of a pipeline to
+/// `revision`, on behalf of `actor`.
+pub(crate) async fn request_pipeline_upgrade(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_id: PipelineId,
+    revision: &PipelineRevision,
+    actor: &str,
+) -> Result<(), DBError> {
+    let revision = serde_json::to_string(revision).unwrap();
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "UPDATE pipeline_runtime_state
+                SET upgrade_revision = $3,
+                    desired_status_actor = $4
+                WHERE tenant_id = $1 AND id = $2
+                ",
+        )
+        .await?;
+
+    let modified_rows = manager
+        .execute(&stmt, &[&tenant_id.0, &pipeline_id.0, &revision, &actor])
+        .await?;
+
+    if modified_rows == 0 {
+        return Err(DBError::UnknownPipeline { pipeline_id });
+    }
+    Ok(())
+}
+
+/// Returns the revision that the running instance of a pipeline must be
+/// upgraded to, if an upgrade is pending.
+pub(crate) async fn get_pipeline_upgrade(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_id: PipelineId,
+) -> Result<Option<PipelineRevision>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT upgrade_revision FROM pipeline_runtime_state WHERE tenant_id = $1 AND id = $2",
+        )
+        .await?;
+
+    let row = manager
+        .query_opt(&stmt, &[&tenant_id.0, &pipeline_id.0])
+        .await?
+        .ok_or(DBError::UnknownPipeline { pipeline_id })?;
+    let revision: Option<String> = row.get(0);
+    Ok(revision.map(|revision| serde_json::from_str(&revision).unwrap()))
+}
+
+/// Ends the pending upgrade of a pipeline, if any.  If `deploy` is true, the
+/// revision that the pipeline was upgraded to becomes its deployed revision.
+pub(crate) async fn finish_pipeline_upgrade(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_id: PipelineId,
+    deploy: bool,
+) -> Result<(), DBError> {
+    let mut client = db.pool.get().await?;
+    let txn = client.transaction().await?;
+    let stmt = txn
+        .prepare_cached(
+            "SELECT upgrade_revision FROM pipeline_runtime_state
+                WHERE tenant_id = $1 AND id = $2
+                FOR UPDATE
+                ",
+        )
+        .await?;
+    let revision: Option<String> = txn
+        .query_opt(&stmt, &[&tenant_id.0, &pipeline_id.0])
+        .await?
+        .and_then(|row| row.get(0));
+    let stmt = txn
+        .prepare_cached(
+            "UPDATE pipeline_runtime_state
+                SET upgrade_revision = NULL
+                WHERE tenant_id = $1 AND id = $2
+                ",
+        )
+        .await?;
+    txn.execute(&stmt, &[&tenant_id.0, &pipeline_id.0]).await?;
+
+    if let (Some(config), true) = (revision, deploy) {
+        let revision: PipelineRevision = serde_json::from_str(&config).unwrap();
+        let stmt = txn
+            .prepare_cached("INSERT INTO pipeline_deployment (id, pipeline_id, tenant_id, config) VALUES ($1, $2, $3, $4)
+                             ON CONFLICT ON CONSTRAINT unique_pipeline_id
+                             DO UPDATE SET id = EXCLUDED.id, config = EXCLUDED.config")
+            .await?;
+        txn.execute(
+            &stmt,
+            &[&revision.revision.0, &pipeline_id.0, &tenant_id.0, &config],
+        )
+        .await?;
+    }
+    txn.commit().await?;
+    Ok(())
+}
+
 /// Returns true if the connector of a given name is an input connector.
 pub(crate) async fn attached_connector_is_input(
     db: &ProjectDB,
@@ -1190,14 +1427,19 @@ pub(crate) async fn delete_pipeline(
     db: &ProjectDB,
     tenant_id: TenantId,
     pipeline_name: &str,
+    txn: Option<&Transaction<'_>>,
 ) -> Result<(), DBError> {
-    let manager = db.pool.get().await?;
-    let stmt = manager
-        .prepare_cached("DELETE FROM pipeline WHERE name = $1 AND tenant_id = $2")
-        .await?;
-    let res = manager
-        .execute(&stmt, &[&pipeline_name, &tenant_id.0])
-        .await?;
+    let query = "DELETE FROM pipeline WHERE name = $1 AND tenant_id = $2";
+    let res = if let Some(txn) = txn {
+        let stmt = txn.prepare_cached(query).await?;
+        txn.execute(&stmt, &[&pipeline_name, &tenant_id.0]).await
+    } else {
+        let manager = db.pool.get().await?;
+        let stmt = manager.prepare_cached(query).await?;
+        manager
+            .execute(&stmt, &[&pipeline_name, &tenant_id.0])
+            .await
+    }?;
     if res > 0 {
         Ok(())
     } else {
@@ -1224,7 +1466,10 @@ pub(crate) async fn is_pipeline_deployable(
     let program = db
         .get_program_by_name(tenant_id, program_name, true, Some(&txn))
         .await?;
-    let connectors = get_connectors_for_pipeline_id(db, tenant_id, pipeline_id, Some(&txn)).await?;
+    let mut connectors =
+        get_connectors_for_pipeline_id(db, tenant_id, pipeline_id, Some(&txn)).await?;
+    resolve_service_references(db, tenant_id, &mut connectors, Some(&txn)).await?;
+    check_pipeline_references(db, tenant_id, &pipeline, &connectors, Some(&txn)).await?;
     txn.commit().await?;
     // Check that this configuration forms a valid snapshot
     PipelineRevision::validate(&pipeline, &connectors, &program)?;
@@ -1241,11 +1486,129 @@ pub(crate) async fn pipeline_config(
     let pipeline = db
         .get_pipeline_descr_by_name(tenant_id, pipeline_name, Some(&txn))
         .await?;
-    let connectors: Vec<ConnectorDescr> =
+    let mut connectors: Vec<ConnectorDescr> =
         get_connectors_for_pipeline_id(db, tenant_id, pipeline.pipeline_id, Some(&txn)).await?;
+    resolve_service_references(db, tenant_id, &mut connectors, Some(&txn)).await?;
     PipelineRevision::generate_pipeline_config(&pipeline, &connectors)
 }
 
+/// Completes the transport configuration of connectors that reference a
+/// service with the settings of the service.
+///
+/// Pipelines are deployed with the resolved configuration, so a change to a
+/// service takes effect the next time a pipeline that uses it is deployed.
+async fn resolve_service_references(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    connectors: &mut [ConnectorDescr],
+    txn: Option<&Transaction<'_>>,
+) -> Result<(), DBError> {
+    for connector in connectors.iter_mut() {
+        let Some(service_name) = connector.config.transport.service_name() else {
+            continue;
+        };
+        let service_name = service_name.to_string();
+        let service = match db.get_service_by_name(tenant_id, &service_name, txn).await {
+            Ok(service) => service,
+            Err(DBError::UnknownServiceName { .. }) => {
+                return Err(DBError::InvalidConnectorTransport {
+                    reason: format!(
+                        "connector '{}' references unknown service '{service_name}'",
+                        connector.name
+                    ),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        connector
+            .config
+            .transport
+            .resolve_service(&service.config)
+            .map_err(|reason| DBError::InvalidConnectorTransport {
+                reason: format!("connector '{}': {reason}", connector.name),
+            })?;
+    }
+    Ok(())
+}
+
+/// Checks that the connectors that read from other pipelines reference
+/// views that exist in the current programs of those pipelines, and that
+/// those programs are materialized: the connectors read a snapshot of the
+/// view whenever they connect.
+///
+/// The other pipelines don't need to be running: the connectors wait for
+/// them to start.
+async fn check_pipeline_references(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline: &PipelineDescr,
+    connectors: &[ConnectorDescr],
+    txn: Option<&Transaction<'_>>,
+) -> Result<(), DBError> {
+    for connector in connectors {
+        let TransportConfig::PipelineInput(config) = &connector.config.transport else {
+            continue;
+        };
+        let invalid = |reason: String| DBError::InvalidConnectorTransport {
+            reason: format!("connector '{}': {reason}", connector.name),
+        };
+        if connector.config.format.name != "json" {
+            return Err(invalid(format!(
+                "connectors that read from a pipeline must use the 'json' format, not '{}'",
+                connector.config.format.name
+            )));
+        }
+        if config.pipeline == pipeline.name {
+            return Err(invalid(
+                "a pipeline cannot read from its own views".to_string(),
+            ));
+        }
+        let upstream = match db
+            .get_pipeline_descr_by_name(tenant_id, &config.pipeline, txn)
+            .await
+        {
+            Ok(upstream) => upstream,
+            Err(DBError::UnknownPipelineName { .. }) => {
+                return Err(invalid(format!(
+                    "references unknown pipeline '{}'",
+                    config.pipeline
+                )))
+            }
+            Err(e) => return Err(e),
+        };
+        let Some(program_name) = &upstream.program_name else {
+            return Err(invalid(format!(
+                "pipeline '{}' does not have a program",
+                config.pipeline
+            )));
+        };
+        let program = db
+            .get_program_by_name(tenant_id, program_name, false, txn)
+            .await?;
+        let view = canonical_identifier(&config.view);
+        let exists = program.schema.iter().any(|schema| {
+            schema
+                .outputs
+                .iter()
+                .chain(schema.inputs.iter())
+                .any(|relation| relation.name() == view)
+        });
+        if !exists {
+            return Err(invalid(format!(
+                "pipeline '{}' does not have a view or table named '{}'",
+                config.pipeline, config.view
+            )));
+        }
+        if !program.config.materialized {
+            return Err(invalid(format!(
+                "the views of pipeline '{}' are not materialized; enable 'materialized' in the configuration of program '{program_name}'",
+                config.pipeline
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// Retrieve all connectors referenced by a pipeline.
 async fn get_connectors_for_pipeline_id(
     db: &ProjectDB,
@@ -1364,6 +1727,12 @@ fn row_to_pipeline(row: &Row) -> Result<Pipeline, DBError> {
             .map(|s| deserialize_error_response(pipeline_id, &s))
             .transpose()?,
         created: convert_bigint_to_time("pipeline_runtime_state.created", row.get(12))?,
+        restart_count: row.get::<_, i64>(13) as u32,
+        last_failure: row
+            .get::<_, Option<String>>(14)
+            .map(|s| deserialize_error_response(pipeline_id, &s))
+            .transpose()?,
+        desired_status_actor: row.get(15),
     };
 
     Ok(Pipeline { descriptor, state })
@@ -1403,7 +1772,7 @@ fn json_to_attached_connectors(connectors_json: Value) -> Vec<AttachedConnector>
     attached_connectors
 }
 
-fn deserialize_error_response(
+pub(crate) fn deserialize_error_response(
     pipeline_id: PipelineId,
     error_str: &str,
 ) -> Result<ErrorResponse, DBError> {
@@ -0,0 +1,146 @@
+use crate::auth::TenantId;
+use crate::db::pipeline::{convert_bigint_to_time, deserialize_error_response};
+use crate::db::{DBError, PipelineId, PipelineStatus, ProjectDB};
+use chrono::{DateTime, Utc};
+use pipeline_types::error::ErrorResponse;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Display;
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Actor of the status transitions initiated by the pipeline manager itself,
+/// e.g., failures and automatic restarts.
+pub(crate) const SYSTEM_ACTOR: &str = "system";
+
+/// Unique pipeline event id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[repr(transparent)]
+#[serde(transparent)]
+pub struct PipelineEventId(pub Uuid);
+impl Display for PipelineEventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Transition of a pipeline from one status to another, or upgrade of a
+/// running pipeline to a new revision, which keeps the status of the pipeline.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PipelineEvent {
+    pub pipeline_event_id: PipelineEventId,
+    pub pipeline_id: PipelineId,
+    /// Time of the transition.
+    pub timestamp: DateTime<Utc>,
+    /// Status of the pipeline before the transition.
+    pub from_status: PipelineStatus,
+    /// Status of the pipeline after the transition.
+    pub to_status: PipelineStatus,
+    /// Error that caused the transition, or the upgrade to fail, if any.
+    pub error: Option<ErrorResponse>,
+    /// User (`user:<name>`) or API key (`api_key:<name>`) whose request
+    /// caused the transition, pipeline schedule (`schedule:<name>`) whose
+    /// action caused it, or `system` for transitions initiated by the
+    /// pipeline manager, such as failures and automatic restarts.
+    pub actor: String,
+}
+
+pub(crate) async fn new_pipeline_event(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    event: &PipelineEvent,
+) -> Result<(), DBError> {
+    let from_status: &'static str = event.from_status.into();
+    let to_status: &'static str = event.to_status.into();
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "INSERT INTO pipeline_event (id, tenant_id, pipeline_id, timestamp, from_status, to_status, error, actor)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .await?;
+    manager
+        .execute(
+            &stmt,
+            &[
+                &event.pipeline_event_id.0,
+                &tenant_id.0,
+                &event.pipeline_id.0,
+                &event.timestamp.timestamp(),
+                &from_status,
+                &to_status,
+                &event
+                    .error
+                    .as_ref()
+                    .map(|e| serde_json::to_string(&e).unwrap()),
+                &event.actor,
+            ],
+        )
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)
+        .map_err(|e| ProjectDB::maybe_pipeline_id_foreign_key_constraint_err(e, event.pipeline_id))
+        .map_err(|e| ProjectDB::maybe_tenant_id_foreign_key_constraint_err(e, tenant_id, None))?;
+    Ok(())
+}
+
+pub(crate) async fn list_pipeline_events(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_id: PipelineId,
+    limit: Option<u32>,
+) -> Result<Vec<PipelineEvent>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, pipeline_id, timestamp, from_status, to_status, error, actor
+             FROM pipeline_event
+             WHERE tenant_id = $1 AND pipeline_id = $2
+             ORDER BY timestamp DESC, id DESC
+             LIMIT $3",
+        )
+        .await?;
+    let rows = manager
+        .query(
+            &stmt,
+            &[&tenant_id.0, &pipeline_id.0, &limit.map(i64::from)],
+        )
+        .await?;
+    rows.iter().map(row_to_pipeline_event).collect()
+}
+
+pub(crate) async fn get_pipeline_event(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_event_id: PipelineEventId,
+) -> Result<PipelineEvent, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, pipeline_id, timestamp, from_status, to_status, error, actor
+             FROM pipeline_event
+             WHERE tenant_id = $1 AND id = $2",
+        )
+        .await?;
+    let row = manager
+        .query_opt(&stmt, &[&tenant_id.0, &pipeline_event_id.0])
+        .await?
+        .ok_or(DBError::UnknownPipelineEvent { pipeline_event_id })?;
+    row_to_pipeline_event(&row)
+}
+
+fn row_to_pipeline_event(row: &Row) -> Result<PipelineEvent, DBError> {
+    let pipeline_id = PipelineId(row.get(1));
+    Ok(PipelineEvent {
+        pipeline_event_id: PipelineEventId(row.get(0)),
+        pipeline_id,
+        timestamp: convert_bigint_to_time("pipeline_event.timestamp", row.get(2))?,
+        from_status: row.get::<_, String>(3).try_into()?,
+        to_status: row.get::<_, String>(4).try_into()?,
+        error: row
+            .get::<_, Option<String>>(5)
+            .map(|s| deserialize_error_response(pipeline_id, &s))
+            .transpose()?,
+        actor: row.get(6),
+    })
+}
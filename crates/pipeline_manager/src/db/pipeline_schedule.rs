@@ -0,0 +1,418 @@
+use crate::auth::TenantId;
+use crate::db::pipeline::convert_bigint_to_time;
+use crate::db::{DBError, PipelineId, ProjectDB};
+use crate::schedules::CronSchedule;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Display;
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Unique pipeline schedule id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[repr(transparent)]
+#[serde(transparent)]
+pub struct PipelineScheduleId(pub Uuid);
+impl Display for PipelineScheduleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Action that a schedule applies to its pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScheduledAction {
+    /// Start the pipeline, like the `start` pipeline action.
+    Start,
+    /// Pause the pipeline, like the `pause` pipeline action.
+    Pause,
+    /// Shut the pipeline down, like the `shutdown` pipeline action.
+    Shutdown,
+}
+
+impl TryFrom<String> for ScheduledAction {
+    type Error = DBError;
+    fn try_from(value: String) -> Result<Self, DBError> {
+        match value.as_str() {
+            "start" => Ok(Self::Start),
+            "pause" => Ok(Self::Pause),
+            "shutdown" => Ok(Self::Shutdown),
+            _ => Err(DBError::invalid_data(format!(
+                "unknown scheduled action '{value}'"
+            ))),
+        }
+    }
+}
+
+impl From<ScheduledAction> for &'static str {
+    fn from(val: ScheduledAction) -> Self {
+        match val {
+            ScheduledAction::Start => "start",
+            ScheduledAction::Pause => "pause",
+            ScheduledAction::Shutdown => "shutdown",
+        }
+    }
+}
+
+fn default_time_zone() -> String {
+    "UTC".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Action applied to a pipeline at the times of a cron-style schedule.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PipelineScheduleConfig {
+    pub action: ScheduledAction,
+    /// Times at which the action is applied, as a crontab(5) expression
+    /// with five fields (minute, hour, day of month, month and day of week),
+    /// or one of `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`.
+    #[schema(example = "0 8 * * MON-FRI")]
+    pub cron: String,
+    /// IANA time zone in which the cron expression is evaluated.  Defaults
+    /// to `UTC`.
+    #[serde(default = "default_time_zone")]
+    #[schema(example = "Europe/Berlin")]
+    pub time_zone: String,
+    /// Whether the action is applied.  Defaults to `true`.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Schedule of an action applied to a pipeline.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PipelineScheduleDescr {
+    pub pipeline_schedule_id: PipelineScheduleId,
+    pub pipeline_id: PipelineId,
+    /// Name of the schedule, unique per pipeline.
+    pub name: String,
+    pub action: ScheduledAction,
+    /// Cron expression of the times of the schedule.
+    pub cron: String,
+    /// IANA time zone in which the cron expression is evaluated.
+    pub time_zone: String,
+    /// Whether the action is applied.
+    pub enabled: bool,
+    /// Time when the schedule was created.
+    pub created_at: DateTime<Utc>,
+    /// Time when the action is applied next, if the schedule is enabled.
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// Time when the action was last applied.
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Error of the last run of the action, if it failed.
+    pub last_error: Option<String>,
+    /// Upcoming times of the schedule in its time zone, starting at
+    /// `next_run_at`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub next_runs: Vec<DateTime<FixedOffset>>,
+}
+
+/// Enabled schedule whose next run is due, along with the pipeline to apply
+/// its action to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DuePipelineSchedule {
+    pub pipeline_schedule_id: PipelineScheduleId,
+    pub tenant_id: TenantId,
+    pub pipeline_name: String,
+    pub name: String,
+    pub action: ScheduledAction,
+    pub cron: String,
+    pub time_zone: String,
+    /// Time of the run that is due.
+    pub next_run_at: DateTime<Utc>,
+}
+
+/// Outcome of a run of a schedule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PipelineScheduleRun {
+    /// Time of the run that was due.  The next run of the schedule is only
+    /// updated if it was not changed in the meantime, e.g., by replacing
+    /// the schedule.
+    pub scheduled_at: DateTime<Utc>,
+    /// Time when the action was applied.
+    pub timestamp: DateTime<Utc>,
+    /// Time of the next run, if any.
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// Error returned by the action, if it failed.
+    pub error: Option<String>,
+}
+
+/// Checks the cron expression and time zone of a schedule and returns the
+/// time of its first run after `now`, or `None` if the schedule is
+/// disabled.
+pub(crate) fn first_pipeline_schedule_run(
+    config: &PipelineScheduleConfig,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, DBError> {
+    let schedule = CronSchedule::parse(&config.cron, &config.time_zone)
+        .map_err(|reason| DBError::InvalidPipelineSchedule { reason })?;
+    Ok(if config.enabled {
+        schedule.next_run(now)
+    } else {
+        None
+    })
+}
+
+async fn get_pipeline_id(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_name: &str,
+) -> Result<PipelineId, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("SELECT id FROM pipeline WHERE tenant_id = $1 AND name = $2")
+        .await?;
+    let row = manager
+        .query_opt(&stmt, &[&tenant_id.0, &pipeline_name])
+        .await?
+        .ok_or_else(|| DBError::UnknownPipelineName {
+            pipeline_name: pipeline_name.to_string(),
+        })?;
+    Ok(PipelineId(row.get(0)))
+}
+
+pub(crate) async fn create_or_replace_pipeline_schedule(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    id: Uuid,
+    pipeline_name: &str,
+    schedule_name: &str,
+    config: &PipelineScheduleConfig,
+) -> Result<(bool, PipelineScheduleId), DBError> {
+    let now = Utc::now();
+    let next_run_at = first_pipeline_schedule_run(config, now)?.map(|t| t.timestamp());
+    let pipeline_id = get_pipeline_id(db, tenant_id, pipeline_name).await?;
+    let action: &'static str = config.action.into();
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "UPDATE pipeline_schedule
+             SET action = $3, cron = $4, time_zone = $5, enabled = $6, next_run_at = $7
+             WHERE pipeline_id = $1 AND name = $2
+             RETURNING id",
+        )
+        .await?;
+    let row = manager
+        .query_opt(
+            &stmt,
+            &[
+                &pipeline_id.0,
+                &schedule_name,
+                &action,
+                &config.cron,
+                &config.time_zone,
+                &config.enabled,
+                &next_run_at,
+            ],
+        )
+        .await?;
+    if let Some(row) = row {
+        return Ok((false, PipelineScheduleId(row.get(0))));
+    }
+    let stmt = manager
+        .prepare_cached(
+            "INSERT INTO pipeline_schedule (id, tenant_id, pipeline_id, name, action, cron, time_zone, enabled, created_at, next_run_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .await?;
+    manager
+        .execute(
+            &stmt,
+            &[
+                &id,
+                &tenant_id.0,
+                &pipeline_id.0,
+                &schedule_name,
+                &action,
+                &config.cron,
+                &config.time_zone,
+                &config.enabled,
+                &now.timestamp(),
+                &next_run_at,
+            ],
+        )
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)
+        .map_err(|e| ProjectDB::maybe_tenant_id_foreign_key_constraint_err(e, tenant_id, None))?;
+    Ok((true, PipelineScheduleId(id)))
+}
+
+pub(crate) async fn list_pipeline_schedules(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_name: &str,
+) -> Result<Vec<PipelineScheduleDescr>, DBError> {
+    let pipeline_id = get_pipeline_id(db, tenant_id, pipeline_name).await?;
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, pipeline_id, name, action, cron, time_zone, enabled, created_at,
+                    next_run_at, last_run_at, last_error
+             FROM pipeline_schedule
+             WHERE pipeline_id = $1 ORDER BY name",
+        )
+        .await?;
+    let rows = manager.query(&stmt, &[&pipeline_id.0]).await?;
+    rows.iter().map(row_to_pipeline_schedule_descr).collect()
+}
+
+pub(crate) async fn get_pipeline_schedule(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_name: &str,
+    schedule_name: &str,
+) -> Result<PipelineScheduleDescr, DBError> {
+    let pipeline_id = get_pipeline_id(db, tenant_id, pipeline_name).await?;
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, pipeline_id, name, action, cron, time_zone, enabled, created_at,
+                    next_run_at, last_run_at, last_error
+             FROM pipeline_schedule
+             WHERE pipeline_id = $1 AND name = $2",
+        )
+        .await?;
+    let row = manager
+        .query_opt(&stmt, &[&pipeline_id.0, &schedule_name])
+        .await?
+        .ok_or_else(|| DBError::UnknownPipelineSchedule {
+            schedule_name: schedule_name.to_string(),
+        })?;
+    row_to_pipeline_schedule_descr(&row)
+}
+
+pub(crate) async fn delete_pipeline_schedule(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_name: &str,
+    schedule_name: &str,
+) -> Result<(), DBError> {
+    let pipeline_id = get_pipeline_id(db, tenant_id, pipeline_name).await?;
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("DELETE FROM pipeline_schedule WHERE pipeline_id = $1 AND name = $2")
+        .await?;
+    let res = manager
+        .execute(&stmt, &[&pipeline_id.0, &schedule_name])
+        .await?;
+    if res > 0 {
+        Ok(())
+    } else {
+        Err(DBError::UnknownPipelineSchedule {
+            schedule_name: schedule_name.to_string(),
+        })
+    }
+}
+
+/// Claims up to `limit` enabled schedules whose next run is due until
+/// `lease_until`, so that concurrent schedulers do not apply them as well.
+pub(crate) async fn next_pipeline_schedule_runs(
+    db: &ProjectDB,
+    limit: u32,
+    lease_until: DateTime<Utc>,
+) -> Result<Vec<DuePipelineSchedule>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "UPDATE pipeline_schedule s SET leased_until = $3
+             FROM pipeline p
+             WHERE s.pipeline_id = p.id AND s.id IN (
+                 SELECT id FROM pipeline_schedule
+                 WHERE enabled AND next_run_at <= $1
+                       AND (leased_until IS NULL OR leased_until <= $1)
+                 ORDER BY next_run_at, id
+                 LIMIT $2
+                 FOR UPDATE SKIP LOCKED)
+             RETURNING s.id, s.tenant_id, p.name, s.name, s.action, s.cron, s.time_zone,
+                       s.next_run_at",
+        )
+        .await?;
+    let rows = manager
+        .query(
+            &stmt,
+            &[
+                &Utc::now().timestamp(),
+                &i64::from(limit),
+                &lease_until.timestamp(),
+            ],
+        )
+        .await?;
+    let mut due = rows
+        .iter()
+        .map(|row| {
+            Ok(DuePipelineSchedule {
+                pipeline_schedule_id: PipelineScheduleId(row.get(0)),
+                tenant_id: TenantId(row.get(1)),
+                pipeline_name: row.get(2),
+                name: row.get(3),
+                action: row.get::<_, String>(4).try_into()?,
+                cron: row.get(5),
+                time_zone: row.get(6),
+                next_run_at: convert_bigint_to_time("pipeline_schedule.next_run_at", row.get(7))?,
+            })
+        })
+        .collect::<Result<Vec<_>, DBError>>()?;
+    // `RETURNING` does not preserve the order of the subquery.
+    due.sort_by_key(|schedule| (schedule.next_run_at, schedule.pipeline_schedule_id));
+    Ok(due)
+}
+
+pub(crate) async fn record_pipeline_schedule_run(
+    db: &ProjectDB,
+    pipeline_schedule_id: PipelineScheduleId,
+    run: &PipelineScheduleRun,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "UPDATE pipeline_schedule
+             SET last_run_at = $2, last_error = $3, leased_until = NULL,
+                 next_run_at = (CASE WHEN next_run_at = $4 THEN $5 ELSE next_run_at END)
+             WHERE id = $1",
+        )
+        .await?;
+    // The schedule no longer exists if it was deleted in the meantime, in
+    // which case there is nothing to record.
+    manager
+        .execute(
+            &stmt,
+            &[
+                &pipeline_schedule_id.0,
+                &run.timestamp.timestamp(),
+                &run.error,
+                &run.scheduled_at.timestamp(),
+                &run.next_run_at.map(|t| t.timestamp()),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+fn row_to_pipeline_schedule_descr(row: &Row) -> Result<PipelineScheduleDescr, DBError> {
+    Ok(PipelineScheduleDescr {
+        pipeline_schedule_id: PipelineScheduleId(row.get(0)),
+        pipeline_id: PipelineId(row.get(1)),
+        name: row.get(2),
+        action: row.get::<_, String>(3).try_into()?,
+        cron: row.get(4),
+        time_zone: row.get(5),
+        enabled: row.get(6),
+        created_at: convert_bigint_to_time("pipeline_schedule.created_at", row.get(7))?,
+        next_run_at: row
+            .get::<_, Option<i64>>(8)
+            .map(|t| convert_bigint_to_time("pipeline_schedule.next_run_at", t))
+            .transpose()?,
+        last_run_at: row
+            .get::<_, Option<i64>>(9)
+            .map(|t| convert_bigint_to_time("pipeline_schedule.last_run_at", t))
+            .transpose()?,
+        last_error: row.get(10),
+        next_runs: Vec::new(),
+    })
+}
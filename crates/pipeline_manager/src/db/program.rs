@@ -3,16 +3,21 @@ use std::{
     str::FromStr,
 };
 
+use chrono::{DateTime, Utc};
 use deadpool_postgres::Transaction;
 use log::{debug, error};
+use tokio_postgres::Row;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
-    api::ProgramStatus, auth::TenantId, compiler::ProgramConfig, config::CompilationProfile,
+    api::ProgramStatus,
+    auth::TenantId,
+    compiler::{CompilerDiagnostic, DiagnosticSeverity, ProgramConfig, SqlCompilerMessage},
+    config::CompilationProfile,
 };
 
-use super::{DBError, ProjectDB, Version};
+use super::{pipeline::convert_bigint_to_time, DBError, ProjectDB, Version};
 use serde::{Deserialize, Serialize};
 
 #[cfg(test)]
@@ -45,22 +50,46 @@ impl ProgramStatus {
             "compiling_rust" => Ok(Self::CompilingRust),
             "sql_error" => {
                 let error = error_string.unwrap_or_default();
-                if let Ok(messages) = serde_json::from_str(&error) {
-                    Ok(Self::SqlError(messages))
+                if let Ok(diagnostics) = serde_json::from_str(&error) {
+                    Ok(Self::SqlError(diagnostics))
+                } else if let Ok(messages) = serde_json::from_str::<Vec<SqlCompilerMessage>>(&error)
+                {
+                    // Stored before errors were recorded as diagnostics.
+                    Ok(Self::SqlError(
+                        messages.into_iter().map(CompilerDiagnostic::from).collect(),
+                    ))
                 } else {
-                    error!("Expected valid json for SqlCompilerMessage but got {:?}, did you update the struct without adjusting the database?", error);
+                    error!("Expected valid json for CompilerDiagnostic but got {:?}, did you update the struct without adjusting the database?", error);
                     Ok(Self::SystemError(error))
                 }
             }
-            "rust_error" => Ok(Self::RustError(error_string.unwrap_or_default())),
+            "rust_error" => {
+                let error = error_string.unwrap_or_default();
+                if let Ok(diagnostics) = serde_json::from_str(&error) {
+                    Ok(Self::RustError(diagnostics))
+                } else {
+                    // Stored before errors were recorded as diagnostics.
+                    Ok(Self::RustError(vec![CompilerDiagnostic {
+                        severity: DiagnosticSeverity::Error,
+                        error_type: "Rust compiler error".to_string(),
+                        message: error,
+                        start: None,
+                        end: None,
+                        snippet: None,
+                    }]))
+                }
+            }
             "system_error" => Ok(Self::SystemError(error_string.unwrap_or_default())),
+            "cancelled" => Ok(Self::Cancelled),
             status => Err(DBError::invalid_status(status.to_string())),
         }
     }
     fn to_columns(&self) -> (Option<String>, Option<String>) {
         match self {
             ProgramStatus::Success => (Some("success".to_string()), None),
-            ProgramStatus::Pending => (Some("pending".to_string()), None),
+            ProgramStatus::Pending | ProgramStatus::Queued(_) => {
+                (Some("pending".to_string()), None)
+            }
             ProgramStatus::CompilingSql => (Some("compiling_sql".to_string()), None),
             ProgramStatus::CompilingRust => (Some("compiling_rust".to_string()), None),
             ProgramStatus::SqlError(error) => {
@@ -72,11 +101,17 @@ impl ProgramStatus {
                 }
             }
             ProgramStatus::RustError(error) => {
-                (Some("rust_error".to_string()), Some(error.clone()))
+                if let Ok(error_string) = serde_json::to_string(&error) {
+                    (Some("rust_error".to_string()), Some(error_string))
+                } else {
+                    error!("Expected valid json for RustError, but got {:?}", error);
+                    (Some("rust_error".to_string()), None)
+                }
             }
             ProgramStatus::SystemError(error) => {
                 (Some("system_error".to_string()), Some(error.clone()))
             }
+            ProgramStatus::Cancelled => (Some("cancelled".to_string()), None),
         }
     }
 }
@@ -130,6 +165,27 @@ pub(crate) struct ProgramDescr {
 
     /// Program configuration
     pub config: ProgramConfig,
+
+    /// Warnings reported by the SQL and Rust compilers while compiling the
+    /// current version of the program.  Errors are reported in `status`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<CompilerDiagnostic>,
+}
+
+/// A version of a program, retained after the program is updated.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Eq, PartialEq, Clone)]
+pub(crate) struct ProgramVersionDescr {
+    /// Program version.
+    pub version: Version,
+    /// SQL code of this version.
+    pub code: Option<String>,
+    /// Program configuration of this version.
+    pub config: ProgramConfig,
+    /// Schema of this version, available once its SQL code compiled
+    /// successfully.
+    pub schema: Option<ProgramSchema>,
+    /// Time when this version was created.
+    pub created_at: DateTime<Utc>,
 }
 
 pub(crate) async fn list_programs(
@@ -142,7 +198,7 @@ pub(crate) async fn list_programs(
         .prepare_cached(
             r#"SELECT id, name, description, version, status, error, schema,
                 CASE WHEN $2 IS TRUE THEN code ELSE null END,
-                compilation_profile
+                compilation_profile, warnings, materialized
                 FROM program WHERE tenant_id = $1"#,
         )
         .await?;
@@ -169,7 +225,11 @@ pub(crate) async fn list_programs(
             schema,
             status,
             code: row.get(7),
-            config: ProgramConfig { profile },
+            config: ProgramConfig {
+                profile,
+                materialized: row.get(10),
+            },
+            warnings: decode_warnings(row.get(9))?,
         });
     }
 
@@ -188,10 +248,19 @@ pub(crate) async fn new_program(
     txn: Option<&Transaction<'_>>,
 ) -> Result<(ProgramId, Version), DBError> {
     debug!("new_program {program_name} {program_description} {program_code}");
-    let query = "INSERT INTO program (id, version, tenant_id, name, description, 
-                                      code, schema, status, error, status_since, compilation_profile)
-                 VALUES($1, 1, $2, $3, $4, $5, NULL, $6, $7, now(), $8);";
+    // The first version is recorded in the program history in the same
+    // statement.
+    let query = "WITH p AS (
+                    INSERT INTO program (id, version, tenant_id, name, description,
+                                         code, schema, status, error, status_since, compilation_profile,
+                                         materialized)
+                    VALUES($1, 1, $2, $3, $4, $5, NULL, $6, $7, now(), $8, $10)
+                    RETURNING id, tenant_id, version, code, compilation_profile, materialized
+                 )
+                 INSERT INTO program_version (program_id, tenant_id, version, code, compilation_profile, materialized, schema, created_at)
+                 SELECT id, tenant_id, version, code, compilation_profile, materialized, NULL, $9 FROM p;";
     let (status, error) = ProgramStatus::Pending.to_columns();
+    let created_at = Utc::now().timestamp();
     let row = if let Some(txn) = txn {
         let stmt = txn.prepare_cached(query).await?;
         txn.execute(
@@ -205,6 +274,8 @@ pub(crate) async fn new_program(
                 &status,
                 &error,
                 &config.profile.to_string(),
+                &created_at,
+                &config.materialized,
             ],
         )
         .await
@@ -223,6 +294,8 @@ pub(crate) async fn new_program(
                     &status,
                     &error,
                     &config.profile.to_string(),
+                    &created_at,
+                    &config.materialized,
                 ],
             )
             .await
@@ -251,7 +324,7 @@ pub(crate) async fn update_program(
     // current version. Only apply a change if the version matched the
     // guard.
     let get_version = txn
-        .prepare_cached("SELECT version, code, compilation_profile FROM program WHERE tenant_id = $1 AND id = $2 ")
+        .prepare_cached("SELECT version, code, compilation_profile, materialized FROM program WHERE tenant_id = $1 AND id = $2 ")
         .await?;
     let row = txn
         .query_opt(&get_version, &[&tenant_id.0, &program_id.0])
@@ -262,6 +335,7 @@ pub(crate) async fn update_program(
     let code: String = row.get(1);
     let profile =
         CompilationProfile::from_str(row.get(2)).expect("Expected a valid compilation profile");
+    let materialized: bool = row.get(3);
 
     if let Some(guard) = guard {
         if guard.0 != latest_version.0 {
@@ -280,23 +354,27 @@ pub(crate) async fn update_program(
                             status = (CASE WHEN version = $6 THEN COALESCE($7, status) ELSE 'pending' END),
                             error = (CASE WHEN version = $6 THEN COALESCE($8, error) ELSE NULL END),
                             status_since = (CASE WHEN $10 THEN now() ELSE status_since END),
+                            warnings = (CASE WHEN version = $6 THEN warnings ELSE NULL END),
                             schema = (CASE WHEN $11 THEN NULL
                                            WHEN version = $6 THEN COALESCE($9, schema)
                                            ELSE NULL END),
-                            compilation_profile = $12
+                            compilation_profile = $12,
+                            materialized = $13
                     WHERE tenant_id = $1 AND id = $2
                     RETURNING version
                 "#,
             )
             .await?;
     let has_code_changed = program_code.as_ref().is_some_and(|c| *c != code);
-    let has_profile_changed = config.as_ref().is_some_and(|c| c.profile != profile);
+    let has_config_changed = config
+        .as_ref()
+        .is_some_and(|c| c.profile != profile || c.materialized != materialized);
 
-    // Changing the program compilation profile currently counts as a new version, which in turn
+    // Changing the program configuration currently counts as a new version, which in turn
     // triggers recompilation from scratch (including the SQL compilation and resetting the
     // schema). This will likely cause redundant work, but is simpler and is not expected
     // to be the common mode of operation.
-    let new_version = if has_code_changed || has_profile_changed {
+    let new_version = if has_code_changed || has_config_changed {
         latest_version.0 + 1
     } else {
         latest_version.0
@@ -305,6 +383,7 @@ pub(crate) async fn update_program(
         .as_ref()
         .map(|c| c.profile.clone())
         .unwrap_or(profile);
+    let new_materialized = config.as_ref().map_or(materialized, |c| c.materialized);
     let status_change = !has_code_changed && (status.is_some() || schema.is_some());
     let reset_schema = status
         .as_ref()
@@ -337,17 +416,130 @@ pub(crate) async fn update_program(
                 &status_change,
                 &reset_schema,
                 &new_profile.to_string(),
+                &new_materialized,
             ],
         )
         .await
         .map_err(ProjectDB::maybe_unique_violation)?;
-    if let Some(row) = row {
-        Ok(Version(row.get(0)))
+    let Some(row) = row else {
+        return Err(DBError::UnknownProgram { program_id });
+    };
+    let version = Version(row.get(0));
+
+    // Record the new version in the program history, or the schema of the
+    // current version once it is known.
+    if version != latest_version {
+        let stmt = txn
+            .prepare_cached(
+                "INSERT INTO program_version (program_id, tenant_id, version, code, compilation_profile, materialized, schema, created_at)
+                 SELECT id, tenant_id, version, code, compilation_profile, materialized, NULL, $3
+                 FROM program WHERE tenant_id = $1 AND id = $2",
+            )
+            .await?;
+        txn.execute(
+            &stmt,
+            &[&tenant_id.0, &program_id.0, &Utc::now().timestamp()],
+        )
+        .await?;
+    } else if schema.is_some() || reset_schema {
+        let stmt = txn
+            .prepare_cached(
+                "UPDATE program_version SET schema = $4
+                 WHERE tenant_id = $1 AND program_id = $2 AND version = $3",
+            )
+            .await?;
+        txn.execute(&stmt, &[&tenant_id.0, &program_id.0, &version.0, &schema])
+            .await?;
+    }
+    Ok(version)
+}
+
+/// Lists the versions of a program, most recent first.
+pub(crate) async fn list_program_versions(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    program_name: &str,
+    with_code: bool,
+) -> Result<Vec<ProgramVersionDescr>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT v.version, CASE WHEN $3 IS TRUE THEN v.code ELSE null END,
+                    v.compilation_profile, v.schema, v.created_at, v.materialized
+             FROM program p JOIN program_version v ON v.program_id = p.id
+             WHERE p.tenant_id = $1 AND p.name = $2
+             ORDER BY v.version DESC",
+        )
+        .await?;
+    let rows = manager
+        .query(&stmt, &[&tenant_id.0, &program_name, &with_code])
+        .await?;
+    // Every program has at least one version.
+    if rows.is_empty() {
+        return Err(DBError::UnknownProgramName {
+            program_name: program_name.to_string(),
+        });
+    }
+    rows.iter().map(row_to_program_version_descr).collect()
+}
+
+/// Retrieves a version of a program.
+pub(crate) async fn get_program_version(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    program_name: &str,
+    version: Version,
+    txn: Option<&Transaction<'_>>,
+) -> Result<ProgramVersionDescr, DBError> {
+    let query = "SELECT v.version, v.code, v.compilation_profile, v.schema, v.created_at,
+                        v.materialized
+                 FROM program p LEFT JOIN program_version v ON v.program_id = p.id AND v.version = $3
+                 WHERE p.tenant_id = $1 AND p.name = $2";
+    let row = if let Some(txn) = txn {
+        let stmt = txn.prepare_cached(query).await?;
+        txn.query_opt(&stmt, &[&tenant_id.0, &program_name, &version.0])
+            .await?
     } else {
-        Err(DBError::UnknownProgram { program_id })
+        let manager = db.pool.get().await?;
+        let stmt = manager.prepare_cached(query).await?;
+        manager
+            .query_opt(&stmt, &[&tenant_id.0, &program_name, &version.0])
+            .await?
+    };
+    match row {
+        None => Err(DBError::UnknownProgramName {
+            program_name: program_name.to_string(),
+        }),
+        Some(row) if row.get::<_, Option<i64>>(0).is_none() => {
+            Err(DBError::UnknownProgramVersion {
+                program_name: program_name.to_string(),
+                version,
+            })
+        }
+        Some(row) => row_to_program_version_descr(&row),
     }
 }
 
+fn row_to_program_version_descr(row: &Row) -> Result<ProgramVersionDescr, DBError> {
+    let profile =
+        CompilationProfile::from_str(row.get(2)).expect("Expected valid compilation profile");
+    let schema: Option<ProgramSchema> = row
+        .get::<_, Option<String>>(3)
+        .map(|s| serde_json::from_str(&s))
+        .transpose()
+        .map_err(|e| DBError::invalid_data(format!("Error parsing program schema: {e}")))?;
+    Ok(ProgramVersionDescr {
+        version: Version(row.get(0)),
+        code: row.get(1),
+        config: ProgramConfig {
+            profile,
+            materialized: row.get(5),
+        },
+        schema,
+        created_at: convert_bigint_to_time("program_version.created_at", row.get(4))?,
+    })
+}
+
 /// Retrieve program descriptor.
 pub(crate) async fn get_program_by_id(
     db: &ProjectDB,
@@ -360,7 +552,7 @@ pub(crate) async fn get_program_by_id(
         .prepare_cached(
             "SELECT name, description, version, status, error, schema,
                 CASE WHEN $3 IS TRUE THEN code ELSE null END,
-                compilation_profile
+                compilation_profile, warnings, materialized
                 FROM program WHERE id = $1 AND tenant_id = $2",
         )
         .await?;
@@ -382,6 +574,8 @@ pub(crate) async fn get_program_by_id(
         let code: Option<String> = row.get(6);
         let profile =
             CompilationProfile::from_str(row.get(7)).expect("Expected valid compilation profile");
+        let warnings = decode_warnings(row.get(8))?;
+        let materialized: bool = row.get(9);
 
         let status = ProgramStatus::from_columns(&status, error)?;
         Ok(ProgramDescr {
@@ -392,7 +586,11 @@ pub(crate) async fn get_program_by_id(
             status,
             schema,
             code,
-            config: ProgramConfig { profile },
+            config: ProgramConfig {
+                profile,
+                materialized,
+            },
+            warnings,
         })
     } else {
         Err(DBError::UnknownProgram { program_id })
@@ -409,7 +607,7 @@ pub(crate) async fn get_program_by_name(
 ) -> Result<ProgramDescr, DBError> {
     let query = "SELECT id, description, version, status, error, schema, tenant_id,
                  CASE WHEN $3 IS TRUE THEN code ELSE null END,
-                 compilation_profile
+                 compilation_profile, warnings, materialized
                  FROM program WHERE name = $1 AND tenant_id = $2";
     let row = if let Some(txn) = txn {
         let stmt = txn.prepare_cached(query).await?;
@@ -437,6 +635,8 @@ pub(crate) async fn get_program_by_name(
         let code: Option<String> = row.get(7);
         let profile =
             CompilationProfile::from_str(row.get(8)).expect("Expected valid compilation profile");
+        let warnings = decode_warnings(row.get(9))?;
+        let materialized: bool = row.get(10);
 
         let status = ProgramStatus::from_columns(&status, error)?;
         Ok(ProgramDescr {
@@ -447,7 +647,11 @@ pub(crate) async fn get_program_by_name(
             status,
             schema,
             code,
-            config: ProgramConfig { profile },
+            config: ProgramConfig {
+                profile,
+                materialized,
+            },
+            warnings,
         })
     } else {
         Err(DBError::UnknownProgramName {
@@ -460,22 +664,97 @@ pub(crate) async fn delete_program(
     db: &ProjectDB,
     tenant_id: TenantId,
     program_name: &str,
+    txn: Option<&Transaction<'_>>,
+) -> Result<(), DBError> {
+    let query = "DELETE FROM program WHERE name = $1 AND tenant_id = $2";
+    let res = if let Some(txn) = txn {
+        let stmt = txn.prepare_cached(query).await?;
+        txn.execute(&stmt, &[&program_name, &tenant_id.0]).await
+    } else {
+        let manager = db.pool.get().await?;
+        let stmt = manager.prepare_cached(query).await?;
+        manager.execute(&stmt, &[&program_name, &tenant_id.0]).await
+    };
+    let res = res.map_err(|e| {
+        ProjectDB::maybe_program_id_in_use_foreign_key_constraint_err(e.into(), Some(program_name))
+    })?;
+    if res > 0 {
+        Ok(())
+    } else {
+        Err(DBError::UnknownProgramName {
+            program_name: program_name.to_string(),
+        })
+    }
+}
+
+/// Decode the `warnings` column.
+fn decode_warnings(warnings: Option<String>) -> Result<Vec<CompilerDiagnostic>, DBError> {
+    warnings
+        .map(|warnings| serde_json::from_str(&warnings))
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .map_err(|e| DBError::invalid_data(format!("Error parsing program warnings: {e}")))
+}
+
+pub(crate) async fn set_program_warnings(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    program_id: ProgramId,
+    version: Version,
+    warnings: &[CompilerDiagnostic],
 ) -> Result<(), DBError> {
+    let warnings = if warnings.is_empty() {
+        None
+    } else {
+        Some(
+            serde_json::to_string(warnings)
+                .map_err(|e| DBError::invalid_data(format!("Error encoding warnings: {e}")))?,
+        )
+    };
     let manager = db.pool.get().await?;
     let stmt = manager
-        .prepare_cached("DELETE FROM program WHERE name = $1 AND tenant_id = $2")
+        .prepare_cached(
+            "UPDATE program SET warnings = $4 WHERE tenant_id = $1 AND id = $2 AND version = $3",
+        )
+        .await?;
+    manager
+        .execute(&stmt, &[&tenant_id.0, &program_id.0, &version.0, &warnings])
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn cancel_program_compilation(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    program_name: &str,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            r#"UPDATE program
+                   SET status = 'cancelled', error = NULL, status_since = now()
+                   WHERE tenant_id = $1 AND name = $2
+                     AND status IN ('pending', 'compiling_sql', 'compiling_rust')"#,
+        )
         .await?;
     let res = manager
-        .execute(&stmt, &[&program_name, &tenant_id.0])
-        .await
-        .map_err(|e| {
-            ProjectDB::maybe_program_id_in_use_foreign_key_constraint_err(
-                e.into(),
-                Some(program_name),
-            )
-        })?;
+        .execute(&stmt, &[&tenant_id.0, &program_name])
+        .await?;
     if res > 0 {
-        Ok(())
+        return Ok(());
+    }
+
+    let stmt = manager
+        .prepare_cached("SELECT id FROM program WHERE tenant_id = $1 AND name = $2")
+        .await?;
+    if manager
+        .query_opt(&stmt, &[&tenant_id.0, &program_name])
+        .await?
+        .is_some()
+    {
+        Err(DBError::ProgramNotCompiling {
+            program_name: program_name.to_string(),
+        })
     } else {
         Err(DBError::UnknownProgramName {
             program_name: program_name.to_string(),
@@ -483,11 +762,54 @@ pub(crate) async fn delete_program(
     }
 }
 
+/// Pending programs of all tenants in the order in which the compiler picks
+/// them: programs of tenants with fewer programs being compiled go first.
+const COMPILATION_QUEUE_QUERY: &str = r#"SELECT p.id, p.version, p.tenant_id
+                   FROM program p
+                   WHERE p.status = 'pending'
+                   ORDER BY
+                       (SELECT count(*) FROM program c
+                            WHERE c.tenant_id = p.tenant_id
+                              AND c.status IN ('compiling_sql', 'compiling_rust')),
+                       p.status_since,
+                       p.id"#;
+
+fn queued_program(row: &Row) -> (TenantId, ProgramId, Version) {
+    (
+        TenantId(row.get(2)),
+        ProgramId(row.get(0)),
+        Version(row.get(1)),
+    )
+}
+
+pub(crate) async fn compilation_queue(
+    db: &ProjectDB,
+) -> Result<Vec<(TenantId, ProgramId, Version)>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager.prepare_cached(COMPILATION_QUEUE_QUERY).await?;
+    let rows = manager.query(&stmt, &[]).await?;
+
+    Ok(rows.iter().map(queued_program).collect())
+}
+
+pub(crate) async fn next_job(
+    db: &ProjectDB,
+) -> Result<Option<(TenantId, ProgramId, Version)>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(&format!("{COMPILATION_QUEUE_QUERY} LIMIT 1"))
+        .await?;
+    let row = manager.query_opt(&stmt, &[]).await?;
+
+    Ok(row.as_ref().map(queued_program))
+}
+
 pub(crate) async fn all_programs(db: &ProjectDB) -> Result<Vec<(TenantId, ProgramDescr)>, DBError> {
     let manager = db.pool.get().await?;
     let stmt = manager
         .prepare_cached(
-            r#"SELECT id, name, description, version, status, error, schema, tenant_id, compilation_profile
+            r#"SELECT id, name, description, version, status, error, schema, tenant_id, compilation_profile,
+                          warnings, materialized
                    FROM program"#,
         )
         .await?;
@@ -516,7 +838,11 @@ pub(crate) async fn all_programs(db: &ProjectDB) -> Result<Vec<(TenantId, Progra
                 schema,
                 status,
                 code: None,
-                config: ProgramConfig { profile },
+                config: ProgramConfig {
+                    profile,
+                    materialized: row.get(10),
+                },
+                warnings: decode_warnings(row.get(9))?,
             },
         ));
     }
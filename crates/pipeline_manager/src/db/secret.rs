@@ -0,0 +1,230 @@
+use crate::auth::TenantId;
+use crate::db::pipeline::convert_bigint_to_time;
+use crate::db::{DBError, ProjectDB};
+use chrono::{DateTime, Utc};
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+
+/// Length of the nonce of AES-256-GCM.
+const NONCE_LEN: usize = 12;
+
+/// Length of the authentication tag of AES-256-GCM.
+const TAG_LEN: usize = 16;
+
+/// Master key that encrypts the secrets stored in the database.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct SecretsKey([u8; 32]);
+
+impl fmt::Debug for SecretsKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretsKey(***)")
+    }
+}
+
+impl SecretsKey {
+    /// Parses a key given as 64 hexadecimal digits.
+    pub(crate) fn from_hex(hex: &str) -> Result<Self, String> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err("the key must consist of 64 hexadecimal digits (32 bytes)".to_string());
+        }
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        Ok(Self(key))
+    }
+
+    /// Encrypts `value`, returning the nonce and the ciphertext followed by
+    /// the authentication tag.
+    ///
+    /// The tenant and the name of the secret are authenticated along with
+    /// the value, so that an encrypted value cannot be moved to another
+    /// secret.
+    pub(crate) fn encrypt(
+        &self,
+        tenant_id: TenantId,
+        name: &str,
+        value: &str,
+    ) -> Result<(Vec<u8>, Vec<u8>), DBError> {
+        let mut nonce = vec![0u8; NONCE_LEN];
+        let mut tag = [0u8; TAG_LEN];
+        rand_bytes(&mut nonce)
+            .and_then(|()| {
+                encrypt_aead(
+                    Cipher::aes_256_gcm(),
+                    &self.0,
+                    Some(&nonce),
+                    &associated_data(tenant_id, name),
+                    value.as_bytes(),
+                    &mut tag,
+                )
+            })
+            .map(|mut ciphertext| {
+                ciphertext.extend_from_slice(&tag);
+                (nonce, ciphertext)
+            })
+            .map_err(|e| DBError::invalid_data(format!("unable to encrypt secret '{name}': {e}")))
+    }
+
+    pub(crate) fn decrypt(
+        &self,
+        tenant_id: TenantId,
+        name: &str,
+        nonce: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<String, DBError> {
+        let undecryptable = |reason: String| {
+            DBError::invalid_data(format!("unable to decrypt secret '{name}': {reason}"))
+        };
+        if ciphertext.len() < TAG_LEN {
+            return Err(undecryptable("ciphertext is truncated".to_string()));
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LEN);
+        let value = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(nonce),
+            &associated_data(tenant_id, name),
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| {
+            undecryptable("it was encrypted with a different master key or altered".to_string())
+        })?;
+        String::from_utf8(value).map_err(|e| undecryptable(e.to_string()))
+    }
+}
+
+fn associated_data(tenant_id: TenantId, name: &str) -> Vec<u8> {
+    let mut data = tenant_id.0.as_bytes().to_vec();
+    data.extend_from_slice(name.as_bytes());
+    data
+}
+
+/// Secret stored in the database.  Its value is never returned by the API.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SecretDescr {
+    /// Name of the secret, referenced as `${secret:db:<name>}`.
+    pub name: String,
+    /// Time when the secret was created.
+    pub created_at: DateTime<Utc>,
+    /// Time when the value of the secret was last replaced.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Checks that a secret name consists of lowercase letters and digits,
+/// optionally separated by single hyphens, like the names of secrets in
+/// the secrets directory.
+pub(crate) fn validate_secret_name(name: &str) -> Result<(), DBError> {
+    let valid = !name.is_empty()
+        && name.split('-').all(|w| {
+            !w.is_empty()
+                && w.bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(DBError::InvalidSecretName {
+            secret_name: name.to_string(),
+        })
+    }
+}
+
+pub(crate) async fn set_secret(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    name: &str,
+    value: &str,
+) -> Result<(), DBError> {
+    validate_secret_name(name)?;
+    let key = db
+        .secrets_key
+        .as_ref()
+        .ok_or(DBError::SecretsNotConfigured)?;
+    let (nonce, ciphertext) = key.encrypt(tenant_id, name, value)?;
+    let now = Utc::now().timestamp();
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "INSERT INTO secret (tenant_id, name, nonce, ciphertext, created_at, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $5)
+             ON CONFLICT (tenant_id, name) DO UPDATE
+             SET nonce = excluded.nonce, ciphertext = excluded.ciphertext, updated_at = excluded.updated_at",
+        )
+        .await?;
+    manager
+        .execute(&stmt, &[&tenant_id.0, &name, &nonce, &ciphertext, &now])
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)
+        .map_err(|e| ProjectDB::maybe_tenant_id_foreign_key_constraint_err(e, tenant_id, None))?;
+    Ok(())
+}
+
+pub(crate) async fn list_secrets(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+) -> Result<Vec<SecretDescr>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT name, created_at, updated_at FROM secret WHERE tenant_id = $1 ORDER BY name",
+        )
+        .await?;
+    let rows = manager.query(&stmt, &[&tenant_id.0]).await?;
+    rows.iter().map(row_to_secret_descr).collect()
+}
+
+pub(crate) async fn get_secret_value(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    name: &str,
+) -> Result<String, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("SELECT nonce, ciphertext FROM secret WHERE tenant_id = $1 AND name = $2")
+        .await?;
+    let row = manager
+        .query_opt(&stmt, &[&tenant_id.0, &name])
+        .await?
+        .ok_or_else(|| DBError::UnknownSecret {
+            secret_name: name.to_string(),
+        })?;
+    let key = db
+        .secrets_key
+        .as_ref()
+        .ok_or(DBError::SecretsNotConfigured)?;
+    key.decrypt(tenant_id, name, row.get(0), row.get(1))
+}
+
+pub(crate) async fn delete_secret(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    name: &str,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("DELETE FROM secret WHERE tenant_id = $1 AND name = $2")
+        .await?;
+    let res = manager.execute(&stmt, &[&tenant_id.0, &name]).await?;
+    if res > 0 {
+        Ok(())
+    } else {
+        Err(DBError::UnknownSecret {
+            secret_name: name.to_string(),
+        })
+    }
+}
+
+fn row_to_secret_descr(row: &Row) -> Result<SecretDescr, DBError> {
+    Ok(SecretDescr {
+        name: row.get(0),
+        created_at: convert_bigint_to_time("secret.created_at", row.get(1))?,
+        updated_at: convert_bigint_to_time("secret.updated_at", row.get(2))?,
+    })
+}
@@ -1,12 +1,24 @@
 use super::{
-    ApiKeyDescr, ApiPermission, AttachedConnector, ConnectorDescr, ConnectorId, DBError, Pipeline,
-    PipelineDescr, PipelineId, PipelineRevision, PipelineRuntimeState, PipelineStatus,
-    ProgramDescr, ProgramId, Revision, Version,
+    ApiKeyDescr, ApiKeyScope, ApiPermission, AttachedConnector, ConnectorDescr, ConnectorId,
+    DBError, Pipeline, PipelineDescr, PipelineId, PipelineRevision, PipelineRuntimeState,
+    PipelineStatus, ProgramDescr, ProgramId, Revision, Version,
 };
 use crate::api::ProgramStatus;
 use crate::auth::TenantId;
 use crate::compiler::ProgramConfig;
+use crate::db::audit::{AuditEvent, AuditEventFilter, Role, UserRole};
+use crate::db::pipeline_event::{PipelineEvent, PipelineEventId};
+use crate::db::pipeline_schedule::{
+    DuePipelineSchedule, PipelineScheduleConfig, PipelineScheduleDescr, PipelineScheduleId,
+    PipelineScheduleRun,
+};
+use crate::db::secret::SecretDescr;
 use crate::db::service::{ServiceProbeDescr, ServiceProbeId};
+use crate::db::webhook::{
+    PendingWebhookDelivery, WebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryId,
+    WebhookDescr, WebhookEventType, WebhookId,
+};
+use crate::db::ProgramVersionDescr;
 use crate::db::{ServiceDescr, ServiceId};
 use crate::prober::service::{ServiceProbeRequest, ServiceProbeResponse, ServiceProbeType};
 use async_trait::async_trait;
@@ -135,8 +147,41 @@ pub(crate) trait Storage {
         txn: Option<&Transaction<'_>>,
     ) -> Result<ProgramDescr, DBError>;
 
+    /// Lists the versions of a program, most recent first.
+    async fn list_program_versions(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        with_code: bool,
+    ) -> Result<Vec<ProgramVersionDescr>, DBError>;
+
+    /// Retrieves a version of a program, including its code.
+    async fn get_program_version(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        version: Version,
+        txn: Option<&Transaction<'_>>,
+    ) -> Result<ProgramVersionDescr, DBError>;
+
     /// Delete program from the database.
-    async fn delete_program(&self, tenant_id: TenantId, program_name: &str) -> Result<(), DBError>;
+    async fn delete_program(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        txn: Option<&Transaction<'_>>,
+    ) -> Result<(), DBError>;
+
+    /// Cancel the compilation of a program.
+    ///
+    /// Sets the status of the program to [`ProgramStatus::Cancelled`] if it
+    /// is pending or compiling.  Fails with [`DBError::ProgramNotCompiling`]
+    /// otherwise.
+    async fn cancel_program_compilation(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+    ) -> Result<(), DBError>;
 
     /// Retrieves all programs in the DB. Intended to be used by
     /// reconciliation loops.
@@ -148,8 +193,9 @@ pub(crate) trait Storage {
 
     /// Retrieves the first pending program from the queue.
     ///
-    /// Returns a pending program with the most recent `status_since` or `None`
-    /// if there are no pending programs in the DB.
+    /// Programs of tenants with the fewest compilations in progress come
+    /// first, followed by the oldest `status_since`.  Returns `None` if there
+    /// are no pending programs in the DB.
     async fn next_job(&self) -> Result<Option<(TenantId, ProgramId, Version)>, DBError>;
 
     /// Create a pipeline deployment, which is an immutable and complete
@@ -213,6 +259,7 @@ pub(crate) trait Storage {
         &self,
         tenant_id: TenantId,
         pipeline_name: &str,
+        txn: Option<&Transaction<'_>>,
     ) -> Result<(), DBError>;
 
     /// Retrieve pipeline for a given id.
@@ -262,11 +309,41 @@ pub(crate) trait Storage {
         state: &PipelineRuntimeState,
     ) -> Result<(), DBError>;
 
+    /// Sets the desired status of a pipeline, as requested by `actor`.
     async fn set_pipeline_desired_status(
         &self,
         tenant_id: TenantId,
         pipeline_id: PipelineId,
         desired_status: PipelineStatus,
+        actor: &str,
+    ) -> Result<(), DBError>;
+
+    /// Requests that the running instance of a pipeline is upgraded to
+    /// `revision`, on behalf of `actor`.
+    async fn request_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        revision: &PipelineRevision,
+        actor: &str,
+    ) -> Result<(), DBError>;
+
+    /// Returns the revision that the running instance of a pipeline must be
+    /// upgraded to, if an upgrade is pending.
+    async fn get_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+    ) -> Result<Option<PipelineRevision>, DBError>;
+
+    /// Ends the pending upgrade of a pipeline, if any.  If `deploy` is true,
+    /// the revision that the pipeline was upgraded to becomes its deployed
+    /// revision.
+    async fn finish_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        deploy: bool,
     ) -> Result<(), DBError>;
 
     async fn list_pipelines(&self, tenant_id: TenantId) -> Result<Vec<Pipeline>, DBError>;
@@ -323,6 +400,7 @@ pub(crate) trait Storage {
         &self,
         tenant_id: TenantId,
         connector_name: &str,
+        txn: Option<&Transaction<'_>>,
     ) -> Result<(), DBError>;
 
     /// Get a list of API key names
@@ -335,6 +413,7 @@ pub(crate) trait Storage {
     async fn delete_api_key(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError>;
 
     /// Persist an SHA-256 hash of an API key in the database
+    #[allow(clippy::too_many_arguments)]
     async fn store_api_key_hash(
         &self,
         tenant_id: TenantId,
@@ -342,11 +421,17 @@ pub(crate) trait Storage {
         name: &str,
         key: &str,
         permissions: Vec<ApiPermission>,
+        resource_scopes: Vec<ApiKeyScope>,
+        expires_at: Option<DateTime<Utc>>,
+        role: Role,
     ) -> Result<(), DBError>;
 
     /// Validate an API key against the database by comparing its SHA-256 hash
     /// against the stored value.
-    async fn validate_api_key(&self, key: &str) -> Result<(TenantId, Vec<ApiPermission>), DBError>;
+    ///
+    /// Returns the tenant the key belongs to along with the descriptor of the
+    /// key.  Expired keys are rejected.
+    async fn validate_api_key(&self, key: &str) -> Result<(TenantId, ApiKeyDescr), DBError>;
 
     /// Get the tenant ID from the database for a given tenant name and
     /// provider, else create a new tenant ID
@@ -449,7 +534,12 @@ pub(crate) trait Storage {
     /// Deletes the service by its provided name.
     ///
     /// Returns error if there does not exist a service with the provided name.
-    async fn delete_service(&self, tenant_id: TenantId, service_name: &str) -> Result<(), DBError>;
+    async fn delete_service(
+        &self,
+        tenant_id: TenantId,
+        service_name: &str,
+        txn: Option<&Transaction<'_>>,
+    ) -> Result<(), DBError>;
 
     /// Creates a new service probe.
     ///
@@ -515,6 +605,201 @@ pub(crate) trait Storage {
         txn: Option<&Transaction<'_>>,
     ) -> Result<Vec<ServiceProbeDescr>, DBError>;
 
+    /// Assigns a role to a user, replacing the role previously assigned to
+    /// the user, if any.
+    async fn set_user_role(
+        &self,
+        tenant_id: TenantId,
+        user_name: &str,
+        role: Role,
+    ) -> Result<(), DBError>;
+
+    /// Retrieves the role assigned to a user, or `None` if the user has not
+    /// been assigned a role.
+    async fn get_user_role(
+        &self,
+        tenant_id: TenantId,
+        user_name: &str,
+    ) -> Result<Option<Role>, DBError>;
+
+    /// Lists the roles assigned to users of the tenant, ordered by user name.
+    async fn list_user_roles(&self, tenant_id: TenantId) -> Result<Vec<UserRole>, DBError>;
+
+    /// Removes the role assigned to a user.
+    ///
+    /// Returns error if the user has not been assigned a role.
+    async fn delete_user_role(&self, tenant_id: TenantId, user_name: &str) -> Result<(), DBError>;
+
+    /// Records an audit event.
+    async fn new_audit_event(&self, tenant_id: TenantId, event: &AuditEvent)
+        -> Result<(), DBError>;
+
+    /// Lists the audit events of the tenant matching the filter, most recent
+    /// first.
+    async fn list_audit_events(
+        &self,
+        tenant_id: TenantId,
+        filter: &AuditEventFilter,
+    ) -> Result<Vec<AuditEvent>, DBError>;
+
+    /// Encrypts and stores the value of a secret, replacing the previous
+    /// value of the secret, if any.
+    ///
+    /// Returns error if no master key is configured.
+    async fn set_secret(&self, tenant_id: TenantId, name: &str, value: &str)
+        -> Result<(), DBError>;
+
+    /// Lists the secrets of the tenant, ordered by name.  Values are not
+    /// included.
+    async fn list_secrets(&self, tenant_id: TenantId) -> Result<Vec<SecretDescr>, DBError>;
+
+    /// Retrieves the decrypted value of a secret.
+    async fn get_secret_value(&self, tenant_id: TenantId, name: &str) -> Result<String, DBError>;
+
+    /// Deletes a secret.
+    ///
+    /// Returns error if the secret does not exist.
+    async fn delete_secret(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError>;
+
+    /// Records a status transition of a pipeline.
+    async fn new_pipeline_event(
+        &self,
+        tenant_id: TenantId,
+        event: &PipelineEvent,
+    ) -> Result<(), DBError>;
+
+    /// Lists the status transitions of a pipeline, most recent first.
+    async fn list_pipeline_events(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        limit: Option<u32>,
+    ) -> Result<Vec<PipelineEvent>, DBError>;
+
+    /// Retrieves a status transition of a pipeline.
+    async fn get_pipeline_event(
+        &self,
+        tenant_id: TenantId,
+        pipeline_event_id: PipelineEventId,
+    ) -> Result<PipelineEvent, DBError>;
+
+    /// Registers a webhook that is notified of the events of type
+    /// `event_types`.  Deliveries are signed with `secret`, which is stored
+    /// encrypted with the secrets master key.
+    ///
+    /// Returns error if the URL is not an HTTP(S) URL, no event type is
+    /// given or no secrets master key is configured.
+    async fn new_webhook(
+        &self,
+        tenant_id: TenantId,
+        id: Uuid,
+        name: &str,
+        url: &str,
+        secret: &str,
+        event_types: &[WebhookEventType],
+    ) -> Result<WebhookId, DBError>;
+
+    /// Lists the webhooks of the tenant, ordered by name.  Secrets are not
+    /// included.
+    async fn list_webhooks(&self, tenant_id: TenantId) -> Result<Vec<WebhookDescr>, DBError>;
+
+    /// Deletes a webhook along with its deliveries.
+    ///
+    /// Returns error if the webhook does not exist.
+    async fn delete_webhook(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError>;
+
+    /// Queues the delivery of an event to every webhook of the tenant that
+    /// subscribes to `event_type`.  Returns the number of queued
+    /// deliveries.
+    async fn new_webhook_deliveries(
+        &self,
+        tenant_id: TenantId,
+        event_type: WebhookEventType,
+        payload: &serde_json::Value,
+    ) -> Result<usize, DBError>;
+
+    /// Lists the deliveries of a webhook, most recent first.
+    async fn list_webhook_deliveries(
+        &self,
+        tenant_id: TenantId,
+        webhook_name: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<WebhookDelivery>, DBError>;
+
+    /// Claims up to `limit` pending deliveries of all tenants whose next
+    /// attempt is due, oldest first.  The claimed deliveries are not due again
+    /// before `lease_until`, unless their attempt is recorded earlier, so
+    /// concurrent callers never claim the same delivery.
+    async fn next_webhook_deliveries(
+        &self,
+        limit: u32,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<PendingWebhookDelivery>, DBError>;
+
+    /// Records the outcome of an attempt to deliver an event.
+    async fn record_webhook_delivery_attempt(
+        &self,
+        webhook_delivery_id: WebhookDeliveryId,
+        attempt: &WebhookDeliveryAttempt,
+    ) -> Result<(), DBError>;
+
+    /// Creates the schedule `schedule_name` of a pipeline, or replaces it
+    /// if it exists.  The next run is computed from the cron expression of
+    /// the schedule, while the time of the last run is kept on replace.
+    ///
+    /// Returns a flag that is `true` if the schedule was created along with
+    /// its id, or error if the cron expression or time zone is invalid.
+    async fn create_or_replace_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        id: Uuid,
+        pipeline_name: &str,
+        schedule_name: &str,
+        config: &PipelineScheduleConfig,
+    ) -> Result<(bool, PipelineScheduleId), DBError>;
+
+    /// Lists the schedules of a pipeline, ordered by name.
+    async fn list_pipeline_schedules(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+    ) -> Result<Vec<PipelineScheduleDescr>, DBError>;
+
+    /// Retrieves a schedule of a pipeline.
+    async fn get_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        schedule_name: &str,
+    ) -> Result<PipelineScheduleDescr, DBError>;
+
+    /// Deletes a schedule of a pipeline.
+    ///
+    /// Returns error if the schedule does not exist.
+    async fn delete_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        schedule_name: &str,
+    ) -> Result<(), DBError>;
+
+    /// Claims up to `limit` enabled schedules of all tenants whose next run
+    /// is due, earliest first.  The claimed schedules are not due again before
+    /// `lease_until`, unless their run is recorded earlier, so concurrent
+    /// callers never claim the same run.
+    async fn next_pipeline_schedule_runs(
+        &self,
+        limit: u32,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<DuePipelineSchedule>, DBError>;
+
+    /// Records the outcome of a run of a schedule and releases its lease.
+    async fn record_pipeline_schedule_run(
+        &self,
+        pipeline_schedule_id: PipelineScheduleId,
+        run: &PipelineScheduleRun,
+    ) -> Result<(), DBError>;
+
     /// Check connectivity to the DB
     async fn check_connection(&self) -> Result<(), DBError>;
 }
@@ -4,25 +4,45 @@ use super::{
     Version,
 };
 use super::{
-    ApiKeyDescr, ApiKeyId, ApiPermission, Pipeline, PipelineDescr, PipelineRuntimeState,
-    ProgramSchema,
+    ApiAction, ApiKeyDescr, ApiKeyId, ApiKeyScope, ApiPermission, ApiResource, Pipeline,
+    PipelineDescr, PipelineRuntimeState, ProgramSchema, ProgramVersionDescr,
 };
-use crate::auth::{self, TenantId, TenantRecord};
+use crate::auth::{self, Actor, TenantId, TenantRecord};
 use crate::compiler::ProgramConfig;
 use crate::config::CompilationProfile;
+use crate::db::audit::{AuditEvent, AuditEventFilter, AuditEventId, AuditOutcome, Role, UserRole};
+use crate::db::bundle::{self, BundleAction, BundleChange, BundleResource, ImportOptions};
 use crate::db::pipeline::convert_bigint_to_time;
+use crate::db::pipeline_event::{PipelineEvent, PipelineEventId};
+use crate::db::pipeline_schedule::{
+    first_pipeline_schedule_run, DuePipelineSchedule, PipelineScheduleConfig,
+    PipelineScheduleDescr, PipelineScheduleId, PipelineScheduleRun, ScheduledAction,
+};
+use crate::db::secret::{validate_secret_name, SecretDescr, SecretsKey};
 use crate::db::service::{ServiceProbeDescr, ServiceProbeId};
+use crate::db::webhook::{
+    validate_webhook, PendingWebhookDelivery, WebhookDelivery, WebhookDeliveryAttempt,
+    WebhookDeliveryId, WebhookDeliveryStatus, WebhookDescr, WebhookEventType, WebhookId,
+};
 use crate::db::{ServiceDescr, ServiceId};
+use crate::pipeline_graph::{
+    is_pipeline_input_key, issue_pipeline_input_key, pipeline_graph, revoke_pipeline_input_key,
+    PipelineDependency,
+};
 use crate::prober::service::{
     ServiceProbeRequest, ServiceProbeResponse, ServiceProbeStatus, ServiceProbeType,
 };
 use async_trait::async_trait;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use deadpool_postgres::Transaction;
 use openssl::sha::{self};
+use pipeline_types::error::ErrorResponse;
 use pipeline_types::service::{KafkaService, ServiceConfig};
 use pipeline_types::{
-    config::{ConnectorConfig, ResourceConfig, RuntimeConfig},
+    config::{
+        ConnectorConfig, OutputEndpointConfig, ResourceConfig, RestartPolicy, RuntimeConfig,
+        TransportConfig,
+    },
     program_schema::Relation,
 };
 use pretty_assertions::assert_eq;
@@ -201,6 +221,7 @@ async fn program_creation() {
             "ignored",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -217,7 +238,9 @@ async fn program_creation() {
         code: None,
         config: ProgramConfig {
             profile: CompilationProfile::Unoptimized,
+            materialized: false,
         },
+        warnings: vec![],
     };
     let actual = rows.get(0).unwrap();
     assert_eq!(1, rows.len());
@@ -234,7 +257,9 @@ async fn program_creation() {
         code: Some("ignored".to_string()),
         config: ProgramConfig {
             profile: CompilationProfile::Unoptimized,
+            materialized: false,
         },
+        warnings: vec![],
     };
     let actual = rows.get(0).unwrap();
     assert_eq!(1, rows.len());
@@ -251,7 +276,9 @@ async fn program_creation() {
         code: None,
         config: ProgramConfig {
             profile: CompilationProfile::Unoptimized,
+            materialized: false,
         },
+        warnings: vec![],
     };
     let (_, actual) = rows.get(0).unwrap();
     assert_eq!(1, rows.len());
@@ -272,6 +299,7 @@ async fn duplicate_program() {
             "ignored",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -286,6 +314,7 @@ async fn duplicate_program() {
             "ignored",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -309,6 +338,7 @@ async fn program_code() {
             "create table t1(c1 integer);",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -341,6 +371,7 @@ async fn update_program() {
             "create table t1(c1 integer);",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -392,6 +423,128 @@ async fn update_program() {
     assert_eq!("some new description", row.description);
 }
 
+#[tokio::test]
+async fn program_versions() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+    let (program_id, _) = handle
+        .db
+        .new_program(
+            tenant_id,
+            Uuid::now_v7(),
+            "test1",
+            "program desc",
+            "create table t1(c1 integer);",
+            &ProgramConfig {
+                profile: CompilationProfile::Unoptimized,
+                materialized: false,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let schema = ProgramSchema {
+        inputs: vec![Relation::new("t1", false, vec![])],
+        outputs: vec![],
+    };
+    handle
+        .db
+        .set_program_schema(tenant_id, program_id, schema.clone())
+        .await
+        .unwrap();
+    handle
+        .db
+        .update_program(
+            tenant_id,
+            program_id,
+            &None,
+            &None,
+            &Some("create table t2(c2 integer);".to_string()),
+            &None,
+            &None,
+            &None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let versions = handle
+        .db
+        .list_program_versions(tenant_id, "test1", true)
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![Version(2), Version(1)],
+        versions.iter().map(|v| v.version).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some("create table t2(c2 integer);".to_string()),
+        versions[0].code
+    );
+    assert_eq!(None, versions[0].schema);
+    assert_eq!(Some(schema), versions[1].schema);
+    let versions = handle
+        .db
+        .list_program_versions(tenant_id, "test1", false)
+        .await
+        .unwrap();
+    assert!(versions.iter().all(|v| v.code.is_none()));
+
+    let v1 = handle
+        .db
+        .get_program_version(tenant_id, "test1", Version(1), None)
+        .await
+        .unwrap();
+    assert_eq!(Some("create table t1(c1 integer);".to_string()), v1.code);
+    assert!(matches!(
+        handle
+            .db
+            .get_program_version(tenant_id, "test1", Version(3), None)
+            .await,
+        Err(DBError::UnknownProgramVersion { .. })
+    ));
+    assert!(matches!(
+        handle
+            .db
+            .list_program_versions(tenant_id, "test2", false)
+            .await,
+        Err(DBError::UnknownProgramName { .. })
+    ));
+
+    // Rolling back creates a new version with the code of the old one.
+    assert!(matches!(
+        handle
+            .db
+            .rollback_program(tenant_id, "test1", Version(1), Some(Version(1)))
+            .await,
+        Err(DBError::OutdatedProgramVersion { .. })
+    ));
+    let version = handle
+        .db
+        .rollback_program(tenant_id, "test1", Version(1), None)
+        .await
+        .unwrap();
+    assert_eq!(Version(3), version);
+    let descr = handle
+        .db
+        .get_program_by_id(tenant_id, program_id, true)
+        .await
+        .unwrap();
+    assert_eq!(Version(3), descr.version);
+    assert_eq!(ProgramStatus::Pending, descr.status);
+    assert_eq!("create table t1(c1 integer);", descr.code.unwrap());
+    assert_eq!(
+        3,
+        handle
+            .db
+            .list_program_versions(tenant_id, "test1", false)
+            .await
+            .unwrap()
+            .len()
+    );
+}
+
 #[tokio::test]
 async fn program_queries() {
     let handle = test_setup().await;
@@ -406,6 +559,7 @@ async fn program_queries() {
             "create table t1(c1 integer);",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -494,6 +648,7 @@ async fn project_pending() {
             "ignored",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -509,6 +664,7 @@ async fn project_pending() {
             "ignored",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -533,6 +689,77 @@ async fn project_pending() {
     // so it won't get picked up twice?
 }
 
+/// Pending programs of tenants with fewer compilations in progress are
+/// compiled first, and cancelled programs leave the queue.
+#[tokio::test]
+async fn compilation_queue() {
+    let handle = test_setup().await;
+    let tenant_a = TenantRecord::default().id;
+    let tenant_b = handle
+        .db
+        .get_or_create_tenant_id("b".to_string(), "b".to_string())
+        .await
+        .unwrap();
+    let mut programs = Vec::new();
+    for (tenant_id, name) in [(tenant_a, "a1"), (tenant_a, "a2"), (tenant_b, "b1")] {
+        let (program_id, version) = handle
+            .db
+            .new_program(
+                tenant_id,
+                Uuid::now_v7(),
+                name,
+                "",
+                "ignored",
+                &ProgramConfig::default(),
+                None,
+            )
+            .await
+            .unwrap();
+        programs.push((tenant_id, program_id, version));
+    }
+    let [a1, a2, b1] = programs[..] else {
+        unreachable!()
+    };
+    assert_eq!(handle.db.next_job().await.unwrap(), Some(a1));
+
+    // Tenant `a` is compiling a program, so the program of tenant `b` goes
+    // first.
+    handle
+        .db
+        .set_program_status_guarded(tenant_a, a1.1, a1.2, ProgramStatus::CompilingSql)
+        .await
+        .unwrap();
+    assert_eq!(handle.db.compilation_queue().await.unwrap(), vec![b1, a2]);
+    assert_eq!(handle.db.next_job().await.unwrap(), Some(b1));
+
+    handle
+        .db
+        .cancel_program_compilation(tenant_b, "b1")
+        .await
+        .unwrap();
+    handle
+        .db
+        .cancel_program_compilation(tenant_a, "a1")
+        .await
+        .unwrap();
+    assert_eq!(handle.db.compilation_queue().await.unwrap(), vec![a2]);
+    let b1 = handle
+        .db
+        .get_program_by_name(tenant_b, "b1", false, None)
+        .await
+        .unwrap();
+    assert_eq!(b1.status, ProgramStatus::Cancelled);
+
+    assert!(matches!(
+        handle.db.cancel_program_compilation(tenant_b, "b1").await,
+        Err(DBError::ProgramNotCompiling { .. })
+    ));
+    assert!(matches!(
+        handle.db.cancel_program_compilation(tenant_b, "a2").await,
+        Err(DBError::UnknownProgramName { .. })
+    ));
+}
+
 #[tokio::test]
 async fn update_status() {
     let handle = test_setup().await;
@@ -547,6 +774,7 @@ async fn update_status() {
             "create table t1(c1 integer);",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -701,13 +929,18 @@ async fn save_api_key() {
                 &format!("foo-{}", i),
                 &api_key,
                 vec![ApiPermission::Read, ApiPermission::Write],
+                vec![],
+                None,
+                Role::Admin,
             )
             .await
             .unwrap();
-        let scopes = handle.db.validate_api_key(&api_key).await.unwrap();
-        assert_eq!(tenant_id, scopes.0);
-        assert_eq!(&ApiPermission::Read, scopes.1.get(0).unwrap());
-        assert_eq!(&ApiPermission::Write, scopes.1.get(1).unwrap());
+        let (key_tenant_id, descr) = handle.db.validate_api_key(&api_key).await.unwrap();
+        assert_eq!(tenant_id, key_tenant_id);
+        assert_eq!(descr.name, format!("foo-{}", i));
+        assert_eq!(&ApiPermission::Read, descr.scopes.get(0).unwrap());
+        assert_eq!(&ApiPermission::Write, descr.scopes.get(1).unwrap());
+        assert!(descr.resource_scopes.is_empty());
 
         let api_key_2 = auth::generate_api_key();
         let err = handle.db.validate_api_key(&api_key_2).await.unwrap_err();
@@ -715,136 +948,1291 @@ async fn save_api_key() {
     }
 }
 
-/// A Function that commits twice and checks the second time errors, returns
-/// revision of first commit.
-async fn commit_check(handle: &DbHandle, tenant_id: TenantId, pipeline_id: PipelineId) -> Revision {
-    let new_revision_id = Uuid::now_v7();
-    let r = handle
+#[tokio::test]
+async fn api_key_scopes_and_expiration() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+    let resource_scopes = vec![ApiKeyScope {
+        resource: ApiResource::Pipeline(PipelineId(Uuid::now_v7())),
+        actions: vec![ApiAction::Read, ApiAction::Ingress],
+    }];
+    let expires_at = convert_bigint_to_time("", Utc::now().timestamp() + 3600).unwrap();
+
+    let api_key = auth::generate_api_key();
+    handle
         .db
-        .create_pipeline_deployment(new_revision_id, tenant_id, pipeline_id)
+        .store_api_key_hash(
+            tenant_id,
+            Uuid::now_v7(),
+            "scoped",
+            &api_key,
+            vec![ApiPermission::Read, ApiPermission::Write],
+            resource_scopes.clone(),
+            Some(expires_at),
+            Role::Operator,
+        )
         .await
         .unwrap();
-    assert_eq!(r.0, new_revision_id);
+    let (_, descr) = handle.db.validate_api_key(&api_key).await.unwrap();
+    assert_eq!(descr.resource_scopes, resource_scopes);
+    assert_eq!(descr.expires_at, Some(expires_at));
+    assert_eq!(descr.role, Role::Operator);
+    assert_eq!(
+        descr,
+        handle.db.get_api_key(tenant_id, "scoped").await.unwrap()
+    );
 
-    // We get an error the 2nd time since nothing changed
-    let e = handle
+    // Expired keys are rejected.
+    let api_key = auth::generate_api_key();
+    handle
         .db
-        .create_pipeline_deployment(new_revision_id, tenant_id, pipeline_id)
+        .store_api_key_hash(
+            tenant_id,
+            Uuid::now_v7(),
+            "expired",
+            &api_key,
+            vec![ApiPermission::Read, ApiPermission::Write],
+            vec![],
+            Some(Utc::now() - Duration::seconds(1)),
+            Role::Viewer,
+        )
         .await
-        .unwrap_err();
-    match e {
-        DBError::RevisionNotChanged => r,
-        e => {
-            // We should get a RevisionNotChanged error here since we
-            // didn't change anything inbetween
-            panic!("unexpected error trying to create revision 2nd time: {}", e)
-        }
-    }
+        .unwrap();
+    let err = handle.db.validate_api_key(&api_key).await.unwrap_err();
+    assert!(matches!(err, DBError::InvalidKey));
 }
 
 #[tokio::test]
-async fn create_tenant() {
+async fn user_roles() {
     let handle = test_setup().await;
-    let tenant_id_1 = handle
+    let tenant_id = TenantRecord::default().id;
+    assert_eq!(
+        handle.db.get_user_role(tenant_id, "alice").await.unwrap(),
+        None
+    );
+
+    handle
         .db
-        .get_or_create_tenant_id("x".to_string(), "y".to_string())
+        .set_user_role(tenant_id, "alice", Role::Viewer)
         .await
         .unwrap();
-    let tenant_id_2 = handle
+    handle
         .db
-        .get_or_create_tenant_id("x".to_string(), "y".to_string())
+        .set_user_role(tenant_id, "bob", Role::Admin)
         .await
         .unwrap();
-    // Concurrent attempts to create should also return the same tenant_id
-    let tenant_id_3 = handle
+    // Assigning a role again replaces the previous one.
+    handle
         .db
-        .create_tenant_if_not_exists(Uuid::now_v7(), "x".to_string(), "y".to_string())
+        .set_user_role(tenant_id, "alice", Role::Operator)
         .await
         .unwrap();
-    let tenant_id_4 = handle
+    assert_eq!(
+        handle.db.get_user_role(tenant_id, "alice").await.unwrap(),
+        Some(Role::Operator)
+    );
+    assert_eq!(
+        handle.db.list_user_roles(tenant_id).await.unwrap(),
+        vec![
+            UserRole {
+                user_name: "alice".to_string(),
+                role: Role::Operator
+            },
+            UserRole {
+                user_name: "bob".to_string(),
+                role: Role::Admin
+            },
+        ]
+    );
+
+    handle
         .db
-        .get_or_create_tenant_id("x".to_string(), "y".to_string())
+        .delete_user_role(tenant_id, "alice")
         .await
         .unwrap();
-    assert_eq!(tenant_id_1, tenant_id_2);
-    assert_eq!(tenant_id_2, tenant_id_3);
-    assert_eq!(tenant_id_3, tenant_id_4);
+    assert_eq!(
+        handle.db.get_user_role(tenant_id, "alice").await.unwrap(),
+        None
+    );
+    let err = handle
+        .db
+        .delete_user_role(tenant_id, "alice")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownUserRole { .. }));
 }
 
 #[tokio::test]
-async fn versioning_no_change_no_connectors() {
-    let _r = env_logger::try_init();
-
-    let handle = test_setup().await;
+async fn secrets() {
+    let mut handle = test_setup().await;
     let tenant_id = TenantRecord::default().id;
+    let err = handle
+        .db
+        .set_secret(tenant_id, "kafka-password", "hunter2")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::SecretsNotConfigured));
 
-    let (program_id, _) = handle
+    handle.db.secrets_key = Some(SecretsKey::from_hex(&"ab".repeat(32)).unwrap());
+    let err = handle
         .db
-        .new_program(
-            tenant_id,
-            Uuid::now_v7(),
-            "",
-            "",
-            "",
-            &ProgramConfig {
-                profile: CompilationProfile::Unoptimized,
-            },
-            None,
-        )
+        .set_secret(tenant_id, "Kafka_Password", "hunter2")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::InvalidSecretName { .. }));
+    handle
+        .db
+        .set_secret(tenant_id, "kafka-password", "hunter2")
         .await
         .unwrap();
     handle
         .db
-        .set_program_status_guarded(tenant_id, program_id, Version(1), ProgramStatus::Success)
+        .set_secret(tenant_id, "s3-key", "AKIA")
         .await
         .unwrap();
-    let rc = RuntimeConfig::from_yaml("");
-    let (pipeline_id, _version) = handle
+    // Setting a secret again replaces its value.
+    handle
         .db
-        .new_pipeline(
-            tenant_id,
-            Uuid::now_v7(),
-            &Some("".to_string()),
-            "",
-            "",
-            &rc,
-            &None,
-            None,
-        )
+        .set_secret(tenant_id, "kafka-password", "correct horse")
         .await
         .unwrap();
-    let _r = handle
+    assert_eq!(
+        handle
+            .db
+            .get_secret_value(tenant_id, "kafka-password")
+            .await
+            .unwrap(),
+        "correct horse"
+    );
+    let names: Vec<String> = handle
         .db
-        .set_program_schema(
-            tenant_id,
-            program_id,
-            ProgramSchema {
-                inputs: vec![],
-                outputs: vec![],
-            },
-        )
+        .list_secrets(tenant_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert_eq!(names, vec!["kafka-password", "s3-key"]);
+
+    // Values are not stored in plain text.
+    let manager = handle.db.pool.get().await.unwrap();
+    let row = manager
+        .query_one("SELECT ciphertext FROM secret WHERE name = 's3-key'", &[])
         .await
         .unwrap();
-    let _r1: Revision = commit_check(&handle, tenant_id, pipeline_id).await;
+    let ciphertext: Vec<u8> = row.get(0);
+    assert!(!ciphertext.windows(4).any(|w| w == b"AKIA"));
+
+    // Values cannot be decrypted with another master key.
+    handle.db.secrets_key = Some(SecretsKey::from_hex(&"cd".repeat(32)).unwrap());
+    let err = handle
+        .db
+        .get_secret_value(tenant_id, "s3-key")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::InvalidData { .. }));
+
+    handle.db.delete_secret(tenant_id, "s3-key").await.unwrap();
+    let err = handle
+        .db
+        .get_secret_value(tenant_id, "s3-key")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownSecret { .. }));
+    let err = handle
+        .db
+        .delete_secret(tenant_id, "s3-key")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownSecret { .. }));
 }
 
 #[tokio::test]
-async fn versioning() {
-    let _r = env_logger::try_init();
-
+async fn audit_log() {
     let handle = test_setup().await;
     let tenant_id = TenantRecord::default().id;
+    let now = Utc::now().timestamp();
+    let event =
+        |secs: i64, actor: &str, action: &str, target: &str, outcome: AuditOutcome| AuditEvent {
+            audit_event_id: AuditEventId(Uuid::now_v7()),
+            timestamp: convert_bigint_to_time("", now + secs).unwrap(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: Some(target.to_string()),
+            outcome,
+            status_code: match outcome {
+                AuditOutcome::Success => 202,
+                AuditOutcome::Failure => 400,
+                AuditOutcome::Denied => 403,
+            },
+        };
+    let events = vec![
+        event(
+            0,
+            "user:alice",
+            "pipeline.start",
+            "p1",
+            AuditOutcome::Success,
+        ),
+        event(1, "user:bob", "pipeline.start", "p1", AuditOutcome::Denied),
+        event(
+            2,
+            "api_key:ci",
+            "program.update",
+            "prog",
+            AuditOutcome::Success,
+        ),
+        event(
+            3,
+            "user:alice",
+            "pipeline.shutdown",
+            "p1",
+            AuditOutcome::Failure,
+        ),
+    ];
+    for e in &events {
+        handle.db.new_audit_event(tenant_id, e).await.unwrap();
+    }
 
-    let (program_id, _) = handle
+    // Most recent first.
+    let all = handle
         .db
-        .new_program(
-            tenant_id,
-            Uuid::now_v7(),
+        .list_audit_events(tenant_id, &AuditEventFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(all, events.iter().rev().cloned().collect::<Vec<_>>());
+
+    let by_actor = handle
+        .db
+        .list_audit_events(
+            tenant_id,
+            &AuditEventFilter {
+                actor: Some("user:alice".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(by_actor, vec![events[3].clone(), events[0].clone()]);
+
+    let by_action_and_outcome = handle
+        .db
+        .list_audit_events(
+            tenant_id,
+            &AuditEventFilter {
+                action: Some("pipeline.start".to_string()),
+                outcome: Some(AuditOutcome::Denied),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(by_action_and_outcome, vec![events[1].clone()]);
+
+    let by_time = handle
+        .db
+        .list_audit_events(
+            tenant_id,
+            &AuditEventFilter {
+                target: Some("p1".to_string()),
+                since: Some(events[1].timestamp),
+                until: Some(events[3].timestamp),
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(by_time, vec![events[1].clone()]);
+
+    // Events are scoped to the tenant.
+    let other_tenant = handle
+        .db
+        .get_or_create_tenant_id("other".to_string(), "provider".to_string())
+        .await
+        .unwrap();
+    assert!(handle
+        .db
+        .list_audit_events(other_tenant, &AuditEventFilter::default())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn pipeline_events() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+    let (pipeline_id, _) = handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &None,
+            "p1",
+            "",
+            &RuntimeConfig::from_yaml(""),
+            &None,
+            None,
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .set_pipeline_desired_status(
+            tenant_id,
+            pipeline_id,
+            PipelineStatus::Running,
+            "user:alice",
+        )
+        .await
+        .unwrap();
+    let state = handle
+        .db
+        .get_pipeline_runtime_state_by_id(tenant_id, pipeline_id)
+        .await
+        .unwrap();
+    assert_eq!(state.desired_status_actor.as_deref(), Some("user:alice"));
+
+    let now = Utc::now().timestamp();
+    let event = |secs: i64, from_status, to_status, actor: &str| PipelineEvent {
+        pipeline_event_id: PipelineEventId(Uuid::now_v7()),
+        pipeline_id,
+        timestamp: convert_bigint_to_time("", now + secs).unwrap(),
+        from_status,
+        to_status,
+        error: None,
+        actor: actor.to_string(),
+    };
+    let events = vec![
+        event(
+            0,
+            PipelineStatus::Shutdown,
+            PipelineStatus::Provisioning,
+            "user:alice",
+        ),
+        event(
+            1,
+            PipelineStatus::Provisioning,
+            PipelineStatus::Initializing,
+            "user:alice",
+        ),
+        PipelineEvent {
+            error: Some(ErrorResponse::from(&DBError::UnknownPipeline {
+                pipeline_id,
+            })),
+            ..event(
+                2,
+                PipelineStatus::Initializing,
+                PipelineStatus::Failed,
+                "system",
+            )
+        },
+    ];
+    for e in &events {
+        handle.db.new_pipeline_event(tenant_id, e).await.unwrap();
+    }
+
+    // Most recent first.
+    let all = handle
+        .db
+        .list_pipeline_events(tenant_id, pipeline_id, None)
+        .await
+        .unwrap();
+    assert_eq!(all, events.iter().rev().cloned().collect::<Vec<_>>());
+    let last = handle
+        .db
+        .list_pipeline_events(tenant_id, pipeline_id, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(last, vec![events[2].clone()]);
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_event(tenant_id, events[1].pipeline_event_id)
+            .await
+            .unwrap(),
+        events[1]
+    );
+
+    // Events of unknown pipelines are rejected.
+    let unknown = PipelineId(Uuid::now_v7());
+    assert!(matches!(
+        handle
+            .db
+            .new_pipeline_event(
+                tenant_id,
+                &PipelineEvent {
+                    pipeline_id: unknown,
+                    ..event(3, PipelineStatus::Shutdown, PipelineStatus::Shutdown, "system")
+                }
+            )
+            .await,
+        Err(DBError::UnknownPipeline { pipeline_id }) if pipeline_id == unknown
+    ));
+
+    // Events are deleted along with the pipeline.
+    handle
+        .db
+        .delete_pipeline(tenant_id, "p1", None)
+        .await
+        .unwrap();
+    assert!(matches!(
+        handle
+            .db
+            .get_pipeline_event(tenant_id, events[0].pipeline_event_id)
+            .await,
+        Err(DBError::UnknownPipelineEvent { .. })
+    ));
+}
+
+#[tokio::test]
+async fn webhooks() {
+    let mut handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+    // Secrets are stored encrypted.
+    let err = handle
+        .db
+        .new_webhook(
+            tenant_id,
+            Uuid::now_v7(),
+            "slack",
+            "https://hooks.example.com/slack",
+            "s3cr3t",
+            &[WebhookEventType::PipelineFailed],
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::SecretsNotConfigured));
+    handle.db.secrets_key = Some(SecretsKey::from_hex(&"ab".repeat(32)).unwrap());
+
+    let err = handle
+        .db
+        .new_webhook(
+            tenant_id,
+            Uuid::now_v7(),
+            "slack",
+            "ftp://example.com",
+            "s3cr3t",
+            &[WebhookEventType::PipelineFailed],
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::InvalidWebhook { .. }));
+    let err = handle
+        .db
+        .new_webhook(
+            tenant_id,
+            Uuid::now_v7(),
+            "slack",
+            "https://hooks.example.com/slack",
+            "s3cr3t",
+            &[],
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::InvalidWebhook { .. }));
+
+    let slack = handle
+        .db
+        .new_webhook(
+            tenant_id,
+            Uuid::now_v7(),
+            "slack",
+            "https://hooks.example.com/slack",
+            "s3cr3t",
+            &[
+                WebhookEventType::PipelineFailed,
+                WebhookEventType::ProgramCompilationFailed,
+            ],
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .new_webhook(
+            tenant_id,
+            Uuid::now_v7(),
+            "pagerduty",
+            "https://events.example.com/pagerduty",
+            "t0ps3cr3t",
+            &[WebhookEventType::PipelineFailed],
+        )
+        .await
+        .unwrap();
+    let err = handle
+        .db
+        .new_webhook(
+            tenant_id,
+            Uuid::now_v7(),
+            "slack",
+            "https://hooks.example.com/other",
+            "s3cr3t",
+            &WebhookEventType::ALL,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::DuplicateName));
+    let webhooks = handle.db.list_webhooks(tenant_id).await.unwrap();
+    assert_eq!(
+        webhooks.iter().map(|w| w.name.as_str()).collect::<Vec<_>>(),
+        vec!["pagerduty", "slack"]
+    );
+    assert_eq!(webhooks[1].webhook_id, slack);
+
+    // Events are only delivered to the webhooks that subscribe to them.
+    let payload = serde_json::json!({"pipeline_name": "p1"});
+    assert_eq!(
+        handle
+            .db
+            .new_webhook_deliveries(tenant_id, WebhookEventType::PipelineFailed, &payload)
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(
+        handle
+            .db
+            .new_webhook_deliveries(tenant_id, WebhookEventType::PipelineRunning, &payload)
+            .await
+            .unwrap(),
+        0
+    );
+    let lease_until = Utc::now() + Duration::seconds(60);
+    let mut due = handle
+        .db
+        .next_webhook_deliveries(10, lease_until)
+        .await
+        .unwrap();
+    assert_eq!(due.len(), 2);
+    // Claimed deliveries are not due again until their lease expires.
+    assert!(handle
+        .db
+        .next_webhook_deliveries(10, lease_until)
+        .await
+        .unwrap()
+        .is_empty());
+    due.sort_by(|d1, d2| d1.url.cmp(&d2.url));
+    assert_eq!(due[1].url, "https://hooks.example.com/slack");
+    assert_eq!(due[1].secret, "s3cr3t");
+    assert_eq!(due[1].attempts, 0);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&due[1].payload).unwrap(),
+        payload
+    );
+
+    // A failed attempt that is retried later is no longer due.
+    let now = Utc::now();
+    handle
+        .db
+        .record_webhook_delivery_attempt(
+            due[1].webhook_delivery_id,
+            &WebhookDeliveryAttempt {
+                timestamp: now,
+                status: WebhookDeliveryStatus::Pending,
+                next_attempt_at: Some(now + Duration::seconds(3600)),
+                response_status: Some(503),
+                error: Some("service unavailable".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .record_webhook_delivery_attempt(
+            due[0].webhook_delivery_id,
+            &WebhookDeliveryAttempt {
+                timestamp: now,
+                status: WebhookDeliveryStatus::Delivered,
+                next_attempt_at: None,
+                response_status: Some(200),
+                error: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(handle
+        .db
+        .next_webhook_deliveries(10, Utc::now())
+        .await
+        .unwrap()
+        .is_empty());
+
+    let deliveries = handle
+        .db
+        .list_webhook_deliveries(tenant_id, "slack", None)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, WebhookDeliveryStatus::Pending);
+    assert_eq!(deliveries[0].attempts, 1);
+    assert_eq!(deliveries[0].response_status, Some(503));
+    assert_eq!(deliveries[0].payload, payload);
+    let deliveries = handle
+        .db
+        .list_webhook_deliveries(tenant_id, "pagerduty", None)
+        .await
+        .unwrap();
+    assert_eq!(deliveries[0].status, WebhookDeliveryStatus::Delivered);
+
+    // Deliveries are deleted along with the webhook.
+    handle.db.delete_webhook(tenant_id, "slack").await.unwrap();
+    let err = handle
+        .db
+        .list_webhook_deliveries(tenant_id, "slack", None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownWebhook { .. }));
+    let err = handle
+        .db
+        .delete_webhook(tenant_id, "slack")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownWebhook { .. }));
+    assert_eq!(
+        handle
+            .db
+            .new_webhook_deliveries(
+                tenant_id,
+                WebhookEventType::ProgramCompilationFailed,
+                &payload
+            )
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn pipeline_schedules() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+    let nightly = PipelineScheduleConfig {
+        action: ScheduledAction::Start,
+        cron: "0 2 * * *".to_string(),
+        time_zone: "Europe/Berlin".to_string(),
+        enabled: true,
+    };
+    let err = handle
+        .db
+        .create_or_replace_pipeline_schedule(tenant_id, Uuid::now_v7(), "p1", "nightly", &nightly)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownPipelineName { .. }));
+
+    let (pipeline_id, _) = handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &None,
+            "p1",
+            "",
+            &RuntimeConfig::from_yaml(""),
+            &None,
+            None,
+        )
+        .await
+        .unwrap();
+    for (cron, time_zone) in [("0 2 * *", "UTC"), ("0 2 * * *", "Mars/Olympus_Mons")] {
+        let err = handle
+            .db
+            .create_or_replace_pipeline_schedule(
+                tenant_id,
+                Uuid::now_v7(),
+                "p1",
+                "nightly",
+                &PipelineScheduleConfig {
+                    cron: cron.to_string(),
+                    time_zone: time_zone.to_string(),
+                    ..nightly.clone()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DBError::InvalidPipelineSchedule { .. }));
+    }
+
+    let (created, nightly_id) = handle
+        .db
+        .create_or_replace_pipeline_schedule(tenant_id, Uuid::now_v7(), "p1", "nightly", &nightly)
+        .await
+        .unwrap();
+    assert!(created);
+    let (created, _) = handle
+        .db
+        .create_or_replace_pipeline_schedule(
+            tenant_id,
+            Uuid::now_v7(),
+            "p1",
+            "evening",
+            &PipelineScheduleConfig {
+                action: ScheduledAction::Shutdown,
+                cron: "0 18 * * MON-FRI".to_string(),
+                time_zone: "UTC".to_string(),
+                enabled: false,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(created);
+    let schedules = handle
+        .db
+        .list_pipeline_schedules(tenant_id, "p1")
+        .await
+        .unwrap();
+    assert_eq!(
+        schedules
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["evening", "nightly"]
+    );
+    // Disabled schedules have no next run.
+    assert_eq!(schedules[0].next_run_at, None);
+    let schedule = &schedules[1];
+    assert_eq!(schedule.pipeline_schedule_id, nightly_id);
+    assert_eq!(schedule.pipeline_id, pipeline_id);
+    assert!(schedule.next_run_at.unwrap() > Utc::now());
+    assert_eq!(schedule.last_run_at, None);
+
+    // Replacing a schedule keeps its id.
+    let (created, id) = handle
+        .db
+        .create_or_replace_pipeline_schedule(
+            tenant_id,
+            Uuid::now_v7(),
+            "p1",
+            "nightly",
+            &PipelineScheduleConfig {
+                action: ScheduledAction::Pause,
+                ..nightly.clone()
+            },
+        )
+        .await
+        .unwrap();
+    assert!(!created);
+    assert_eq!(id, nightly_id);
+    let schedule = handle
+        .db
+        .get_pipeline_schedule(tenant_id, "p1", "nightly")
+        .await
+        .unwrap();
+    assert_eq!(schedule.action, ScheduledAction::Pause);
+
+    // Nothing is due until the next run is in the past.
+    let lease_until = Utc::now() + Duration::seconds(60);
+    assert!(handle
+        .db
+        .next_pipeline_schedule_runs(10, lease_until)
+        .await
+        .unwrap()
+        .is_empty());
+    let past = convert_bigint_to_time("", Utc::now().timestamp() - 60).unwrap();
+    handle
+        .db
+        .record_pipeline_schedule_run(
+            nightly_id,
+            &PipelineScheduleRun {
+                scheduled_at: schedule.next_run_at.unwrap(),
+                timestamp: past,
+                next_run_at: Some(past),
+                error: None,
+            },
+        )
+        .await
+        .unwrap();
+    let due = handle
+        .db
+        .next_pipeline_schedule_runs(10, lease_until)
+        .await
+        .unwrap();
+    assert_eq!(
+        due,
+        vec![DuePipelineSchedule {
+            pipeline_schedule_id: nightly_id,
+            tenant_id,
+            pipeline_name: "p1".to_string(),
+            name: "nightly".to_string(),
+            action: ScheduledAction::Pause,
+            cron: nightly.cron.clone(),
+            time_zone: nightly.time_zone.clone(),
+            next_run_at: past,
+        }]
+    );
+    // Claimed runs are not due again until their lease expires.
+    assert!(handle
+        .db
+        .next_pipeline_schedule_runs(10, lease_until)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // A run only moves the next run forward if it was the one that was due.
+    let later = convert_bigint_to_time("", Utc::now().timestamp() + 3600).unwrap();
+    for scheduled_at in [later, past] {
+        handle
+            .db
+            .record_pipeline_schedule_run(
+                nightly_id,
+                &PipelineScheduleRun {
+                    scheduled_at,
+                    timestamp: past,
+                    next_run_at: Some(later),
+                    error: Some("quota exceeded".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        let schedule = handle
+            .db
+            .get_pipeline_schedule(tenant_id, "p1", "nightly")
+            .await
+            .unwrap();
+        let expected = if scheduled_at == past { later } else { past };
+        assert_eq!(schedule.next_run_at, Some(expected));
+        assert_eq!(schedule.last_run_at, Some(past));
+        assert_eq!(schedule.last_error.as_deref(), Some("quota exceeded"));
+    }
+    assert!(handle
+        .db
+        .next_pipeline_schedule_runs(10, lease_until)
+        .await
+        .unwrap()
+        .is_empty());
+
+    handle
+        .db
+        .delete_pipeline_schedule(tenant_id, "p1", "evening")
+        .await
+        .unwrap();
+    let err = handle
+        .db
+        .delete_pipeline_schedule(tenant_id, "p1", "evening")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownPipelineSchedule { .. }));
+    let err = handle
+        .db
+        .get_pipeline_schedule(tenant_id, "p1", "evening")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DBError::UnknownPipelineSchedule { .. }));
+
+    // Schedules are deleted along with the pipeline.
+    handle
+        .db
+        .delete_pipeline(tenant_id, "p1", None)
+        .await
+        .unwrap();
+    handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &None,
+            "p1",
+            "",
+            &RuntimeConfig::from_yaml(""),
+            &None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(handle
+        .db
+        .list_pipeline_schedules(tenant_id, "p1")
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// A Function that commits twice and checks the second time errors, returns
+/// revision of first commit.
+async fn commit_check(handle: &DbHandle, tenant_id: TenantId, pipeline_id: PipelineId) -> Revision {
+    let new_revision_id = Uuid::now_v7();
+    let r = handle
+        .db
+        .create_pipeline_deployment(new_revision_id, tenant_id, pipeline_id)
+        .await
+        .unwrap();
+    assert_eq!(r.0, new_revision_id);
+
+    // We get an error the 2nd time since nothing changed
+    let e = handle
+        .db
+        .create_pipeline_deployment(new_revision_id, tenant_id, pipeline_id)
+        .await
+        .unwrap_err();
+    match e {
+        DBError::RevisionNotChanged => r,
+        e => {
+            // We should get a RevisionNotChanged error here since we
+            // didn't change anything inbetween
+            panic!("unexpected error trying to create revision 2nd time: {}", e)
+        }
+    }
+}
+
+#[tokio::test]
+async fn export_import_bundle() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+    handle
+        .db
+        .new_program(
+            tenant_id,
+            Uuid::now_v7(),
+            "p1",
+            "program desc",
+            "create table t1(c1 integer);",
+            &ProgramConfig::default(),
+            None,
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .new_connector(
+            tenant_id,
+            Uuid::now_v7(),
+            "c1",
+            "connector desc",
+            &test_connector_config(),
+            None,
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .new_service(
+            tenant_id,
+            Uuid::now_v7(),
+            "s1",
+            "service desc",
+            &ServiceConfig::Kafka(KafkaService {
+                bootstrap_servers: vec!["example.com:9092".to_string()],
+                options: Default::default(),
+            }),
+            None,
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &Some("p1".to_string()),
+            "pl1",
+            "pipeline desc",
+            &RuntimeConfig::from_yaml(""),
+            &Some(vec![AttachedConnector {
+                name: "ac1".to_string(),
+                is_input: true,
+                connector_name: "c1".to_string(),
+                relation_name: "t1".to_string(),
+            }]),
+            None,
+        )
+        .await
+        .unwrap();
+    let exported = bundle::export_bundle(&handle.db, tenant_id).await.unwrap();
+    assert_eq!(1, exported.programs.len());
+    assert_eq!(1, exported.connectors.len());
+    assert_eq!(1, exported.services.len());
+    assert_eq!(1, exported.pipelines.len());
+
+    // Import into another tenant: a dry run makes no changes.
+    let other = handle
+        .db
+        .get_or_create_tenant_id("other".to_string(), "provider".to_string())
+        .await
+        .unwrap();
+    let created = |resource, name: &str| BundleChange {
+        resource,
+        name: name.to_string(),
+        action: BundleAction::Create,
+    };
+    let expected = vec![
+        created(BundleResource::Service, "s1"),
+        created(BundleResource::Connector, "c1"),
+        created(BundleResource::Program, "p1"),
+        created(BundleResource::Pipeline, "pl1"),
+    ];
+    let dry_run = ImportOptions {
+        dry_run: true,
+        prune: false,
+    };
+    let changes = bundle::import_bundle(&handle.db, other, &exported, dry_run)
+        .await
+        .unwrap();
+    assert_eq!(expected, changes);
+    assert!(handle
+        .db
+        .list_programs(other, false)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Importing is idempotent.
+    let changes = bundle::import_bundle(&handle.db, other, &exported, ImportOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(expected, changes);
+    let changes = bundle::import_bundle(&handle.db, other, &exported, ImportOptions::default())
+        .await
+        .unwrap();
+    assert!(changes.is_empty());
+    let mut reexported = bundle::export_bundle(&handle.db, other).await.unwrap();
+    assert_eq!(exported, reexported);
+
+    // Updates and deletions.
+    reexported.programs[0].code = "create table t1(c1 bigint);".to_string();
+    reexported.services.clear();
+    let changes = bundle::import_bundle(
+        &handle.db,
+        other,
+        &reexported,
+        ImportOptions {
+            dry_run: false,
+            prune: true,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        vec![
+            BundleChange {
+                resource: BundleResource::Program,
+                name: "p1".to_string(),
+                action: BundleAction::Update,
+            },
+            BundleChange {
+                resource: BundleResource::Service,
+                name: "s1".to_string(),
+                action: BundleAction::Delete,
+            },
+        ],
+        changes
+    );
+    let program = handle
+        .db
+        .get_program_by_name(other, "p1", true, None)
+        .await
+        .unwrap();
+    assert_eq!(Version(2), program.version);
+    assert!(handle
+        .db
+        .list_services(other, &None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Pipelines may only refer to programs part of the bundle when pruning.
+    reexported.programs.clear();
+    assert!(matches!(
+        bundle::import_bundle(
+            &handle.db,
+            other,
+            &reexported,
+            ImportOptions {
+                dry_run: true,
+                prune: true,
+            },
+        )
+        .await,
+        Err(DBError::UnknownProgramName { .. })
+    ));
+    reexported.connectors.push(reexported.connectors[0].clone());
+    assert!(matches!(
+        bundle::import_bundle(&handle.db, other, &reexported, dry_run).await,
+        Err(DBError::DuplicateBundleName { resource, name })
+            if resource == "connector" && name == "c1"
+    ));
+
+    // Inline credentials are redacted on export and kept on import.
+    let kafka_service = |password: &str| {
+        ServiceConfig::Kafka(KafkaService {
+            bootstrap_servers: vec!["example.com:9092".to_string()],
+            options: BTreeMap::from([
+                ("sasl.password".to_string(), password.to_string()),
+                ("sasl.username".to_string(), "user".to_string()),
+            ]),
+        })
+    };
+    handle
+        .db
+        .new_service(
+            tenant_id,
+            Uuid::now_v7(),
+            "s2",
+            "service desc",
+            &kafka_service("s3cr3t"),
+            None,
+        )
+        .await
+        .unwrap();
+    let mut exported = bundle::export_bundle(&handle.db, tenant_id).await.unwrap();
+    let s2 = exported
+        .services
+        .iter_mut()
+        .find(|s| s.name == "s2")
+        .unwrap();
+    assert_eq!(kafka_service(bundle::REDACTED), s2.config);
+    s2.description = "new desc".to_string();
+    let changes = bundle::import_bundle(&handle.db, tenant_id, &exported, ImportOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![BundleChange {
+            resource: BundleResource::Service,
+            name: "s2".to_string(),
+            action: BundleAction::Update,
+        }],
+        changes
+    );
+    let s2 = handle
+        .db
+        .get_service_by_name(tenant_id, "s2", None)
+        .await
+        .unwrap();
+    assert_eq!("new desc", s2.description);
+    assert_eq!(kafka_service("s3cr3t"), s2.config);
+
+    // A redacted credential cannot be imported without an existing value, and
+    // a failed import makes no changes.
+    let fresh = handle
+        .db
+        .get_or_create_tenant_id("fresh".to_string(), "provider".to_string())
+        .await
+        .unwrap();
+    assert!(matches!(
+        bundle::import_bundle(&handle.db, fresh, &exported, ImportOptions::default()).await,
+        Err(DBError::RedactedBundleCredential { resource, name, path })
+            if resource == "service" && name == "s2" && path == "config.kafka.options.sasl.password"
+    ));
+    exported.services.retain(|s| s.name != "s2");
+    let attached = exported.pipelines[0].attached_connectors[0].clone();
+    exported.pipelines[0].attached_connectors.push(attached);
+    assert!(
+        bundle::import_bundle(&handle.db, fresh, &exported, ImportOptions::default())
+            .await
+            .is_err()
+    );
+    assert!(handle
+        .db
+        .list_programs(fresh, false)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn create_tenant() {
+    let handle = test_setup().await;
+    let tenant_id_1 = handle
+        .db
+        .get_or_create_tenant_id("x".to_string(), "y".to_string())
+        .await
+        .unwrap();
+    let tenant_id_2 = handle
+        .db
+        .get_or_create_tenant_id("x".to_string(), "y".to_string())
+        .await
+        .unwrap();
+    // Concurrent attempts to create should also return the same tenant_id
+    let tenant_id_3 = handle
+        .db
+        .create_tenant_if_not_exists(Uuid::now_v7(), "x".to_string(), "y".to_string())
+        .await
+        .unwrap();
+    let tenant_id_4 = handle
+        .db
+        .get_or_create_tenant_id("x".to_string(), "y".to_string())
+        .await
+        .unwrap();
+    assert_eq!(tenant_id_1, tenant_id_2);
+    assert_eq!(tenant_id_2, tenant_id_3);
+    assert_eq!(tenant_id_3, tenant_id_4);
+}
+
+#[tokio::test]
+async fn versioning_no_change_no_connectors() {
+    let _r = env_logger::try_init();
+
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+
+    let (program_id, _) = handle
+        .db
+        .new_program(
+            tenant_id,
+            Uuid::now_v7(),
+            "",
+            "",
+            "",
+            &ProgramConfig {
+                profile: CompilationProfile::Unoptimized,
+                materialized: false,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .set_program_status_guarded(tenant_id, program_id, Version(1), ProgramStatus::Success)
+        .await
+        .unwrap();
+    let rc = RuntimeConfig::from_yaml("");
+    let (pipeline_id, _version) = handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &Some("".to_string()),
+            "",
+            "",
+            &rc,
+            &None,
+            None,
+        )
+        .await
+        .unwrap();
+    let _r = handle
+        .db
+        .set_program_schema(
+            tenant_id,
+            program_id,
+            ProgramSchema {
+                inputs: vec![],
+                outputs: vec![],
+            },
+        )
+        .await
+        .unwrap();
+    let _r1: Revision = commit_check(&handle, tenant_id, pipeline_id).await;
+}
+
+#[tokio::test]
+async fn versioning() {
+    let _r = env_logger::try_init();
+
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+
+    let (program_id, _) = handle
+        .db
+        .new_program(
+            tenant_id,
+            Uuid::now_v7(),
             "test1",
             "program desc",
             "only schema matters--this isn't compiled",
             &ProgramConfig {
                 profile: CompilationProfile::Unoptimized,
+                materialized: false,
             },
             None,
         )
@@ -983,6 +2371,8 @@ async fn versioning() {
         min_batch_size_records: 0,
         max_buffering_delay_usecs: 0,
         resources: ResourceConfig::default(),
+        restart_policy: RestartPolicy::default(),
+        flight_port: None,
     };
     handle
         .db
@@ -1101,8 +2491,232 @@ async fn versioning() {
         )
         .await
         .unwrap();
-    let r6 = commit_check(&handle, tenant_id, pipeline_id).await;
-    assert_ne!(r5, r6, "we got a new revision");
+    let r6 = commit_check(&handle, tenant_id, pipeline_id).await;
+    assert_ne!(r5, r6, "we got a new revision");
+}
+
+#[tokio::test]
+async fn pipeline_upgrade() {
+    let _r = env_logger::try_init();
+
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+
+    let (program_id, _) = handle
+        .db
+        .new_program(
+            tenant_id,
+            Uuid::now_v7(),
+            "test1",
+            "program desc",
+            "only schema matters--this isn't compiled",
+            &ProgramConfig {
+                profile: CompilationProfile::Unoptimized,
+                materialized: false,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    handle
+        .db
+        .set_program_status_guarded(tenant_id, program_id, Version(1), ProgramStatus::Success)
+        .await
+        .unwrap();
+    handle
+        .db
+        .set_program_schema(
+            tenant_id,
+            program_id,
+            ProgramSchema {
+                inputs: vec![Relation::new("t1", false, vec![])],
+                outputs: vec![Relation::new("v1", false, vec![])],
+            },
+        )
+        .await
+        .unwrap();
+    let config = ConnectorConfig::from_yaml_str(
+        r#"
+transport:
+    name: kafka_input
+    config:
+        topics: [test_input1]
+        fault_tolerance: {}
+format:
+    name: csv"#,
+    );
+    handle
+        .db
+        .new_connector(tenant_id, Uuid::now_v7(), "a", "b", &config, None)
+        .await
+        .unwrap();
+    let ac = AttachedConnector {
+        name: "ac1".to_string(),
+        is_input: true,
+        connector_name: "a".to_string(),
+        relation_name: "t1".to_string(),
+    };
+    let (pipeline_id, _version) = handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &Some("test1".to_string()),
+            "1",
+            "2",
+            &RuntimeConfig::from_yaml(""),
+            &Some(vec![ac]),
+            None,
+        )
+        .await
+        .unwrap();
+    commit_check(&handle, tenant_id, pipeline_id).await;
+
+    let running = handle
+        .db
+        .get_pipeline_deployment(tenant_id, pipeline_id)
+        .await
+        .unwrap();
+
+    // The same version of the program cannot replace itself.
+    assert!(running.check_upgrade(&running).is_err());
+
+    // A new version with the same tables can.
+    let mut upgrade = running.clone();
+    upgrade.revision = Revision(Uuid::now_v7());
+    upgrade.program.version = Version(upgrade.program.version.0 + 1);
+    running.check_upgrade(&upgrade).unwrap();
+
+    // Adding a table is rejected.
+    let mut added = upgrade.clone();
+    added
+        .program
+        .schema
+        .as_mut()
+        .unwrap()
+        .inputs
+        .push(Relation::new("t2", false, vec![]));
+    let error = running.check_upgrade(&added).unwrap_err();
+    assert!(error.contains("table 't2' is added"), "{error}");
+
+    // Changing the input connectors is rejected.
+    let mut changed = upgrade.clone();
+    changed.config.inputs.clear();
+    assert!(running.check_upgrade(&changed).is_err());
+
+    // Input connectors must be fault tolerant.
+    let mut not_ft = running.clone();
+    for input in not_ft.config.inputs.values_mut() {
+        if let TransportConfig::KafkaInput(kafka) = &mut input.connector_config.transport {
+            kafka.fault_tolerance = None;
+        }
+    }
+    let mut not_ft_upgrade = not_ft.clone();
+    not_ft_upgrade.program = upgrade.program.clone();
+    let error = not_ft.check_upgrade(&not_ft_upgrade).unwrap_err();
+    assert!(error.contains("ac1"), "{error}");
+
+    // So must output connectors.
+    let mut output = upgrade.clone();
+    output.config.outputs.insert(
+        "ac2".into(),
+        OutputEndpointConfig {
+            stream: "v1".into(),
+            query: Default::default(),
+            snapshot: false,
+            connector_config: ConnectorConfig::from_yaml_str(
+                r#"
+transport:
+    name: kafka_output
+    config:
+        topic: test_output1
+format:
+    name: csv"#,
+            ),
+            output_buffer_config: Default::default(),
+        },
+    );
+    let error = running.check_upgrade(&output).unwrap_err();
+    assert!(error.contains("ac2"), "{error}");
+
+    // A pending upgrade only becomes the deployed revision once it succeeds.
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_upgrade(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        None
+    );
+    handle
+        .db
+        .request_pipeline_upgrade(tenant_id, pipeline_id, &upgrade, "admin")
+        .await
+        .unwrap();
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_upgrade(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        Some(upgrade.clone())
+    );
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_deployment(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        running
+    );
+    handle
+        .db
+        .finish_pipeline_upgrade(tenant_id, pipeline_id, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_upgrade(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_deployment(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        running
+    );
+
+    handle
+        .db
+        .request_pipeline_upgrade(tenant_id, pipeline_id, &upgrade, "admin")
+        .await
+        .unwrap();
+    handle
+        .db
+        .finish_pipeline_upgrade(tenant_id, pipeline_id, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_upgrade(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        handle
+            .db
+            .get_pipeline_deployment(tenant_id, pipeline_id)
+            .await
+            .unwrap(),
+        upgrade
+    );
 }
 
 #[tokio::test]
@@ -1161,6 +2775,243 @@ async fn service_name_change() {
     }
 }
 
+/// Connectors that reference a service are deployed with the settings of
+/// the service.
+#[tokio::test]
+async fn connector_service_reference() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+
+    let connector = |service: &str| {
+        ConnectorConfig::from_yaml_str(&format!(
+            r#"
+transport:
+    name: kafka_input
+    config:
+        topics: [t]
+        group.id: "group0"
+        kafka_service: {service}
+format:
+    name: csv"#
+        ))
+    };
+    handle
+        .db
+        .new_connector(
+            tenant_id,
+            Uuid::now_v7(),
+            "c",
+            "",
+            &connector("kafka1"),
+            None,
+        )
+        .await
+        .unwrap();
+    let ac = AttachedConnector {
+        name: "input".to_string(),
+        is_input: true,
+        connector_name: "c".to_string(),
+        relation_name: "t".to_string(),
+    };
+    handle
+        .db
+        .new_pipeline(
+            tenant_id,
+            Uuid::now_v7(),
+            &None,
+            "p",
+            "",
+            &RuntimeConfig::from_yaml(""),
+            &Some(vec![ac]),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // The service does not exist.
+    let err = handle.db.pipeline_config(tenant_id, "p").await.unwrap_err();
+    assert!(
+        matches!(&err, DBError::InvalidConnectorTransport { reason } if reason == "connector 'c' references unknown service 'kafka1'"),
+        "{err:?}"
+    );
+
+    handle
+        .db
+        .new_service(
+            tenant_id,
+            Uuid::now_v7(),
+            "kafka1",
+            "",
+            &ServiceConfig::Kafka(KafkaService {
+                bootstrap_servers: vec!["kafka:9092".to_string()],
+                options: BTreeMap::from([("group.id".to_string(), "ignored".to_string())]),
+            }),
+            None,
+        )
+        .await
+        .unwrap();
+    let config = handle.db.pipeline_config(tenant_id, "p").await.unwrap();
+    let TransportConfig::KafkaInput(kafka) = &config.inputs["input"].connector_config.transport
+    else {
+        panic!("unexpected transport")
+    };
+    assert_eq!(kafka.kafka_service, None);
+    assert_eq!(kafka.kafka_options["bootstrap.servers"], "kafka:9092");
+    assert_eq!(kafka.kafka_options["group.id"], "group0");
+
+    // The service is not of the type the transport expects.
+    handle
+        .db
+        .update_connector_by_name(tenant_id, "c", &None, &None, &Some(connector("http1")))
+        .await
+        .unwrap();
+    handle
+        .db
+        .new_service(
+            tenant_id,
+            Uuid::now_v7(),
+            "http1",
+            "",
+            &ServiceConfig::from_yaml_str("!http\nurl: http://localhost\n"),
+            None,
+        )
+        .await
+        .unwrap();
+    let err = handle.db.pipeline_config(tenant_id, "p").await.unwrap_err();
+    assert!(
+        matches!(&err, DBError::InvalidConnectorTransport { reason } if reason == "connector 'c': transport 'kafka_input' cannot use a service of type 'http'"),
+        "{err:?}"
+    );
+}
+
+/// Pipelines that read from other pipelines appear in the dependency graph,
+/// and get an API key that is only valid for the pipelines they read from.
+#[tokio::test]
+async fn pipeline_input() {
+    let handle = test_setup().await;
+    let tenant_id = TenantRecord::default().id;
+
+    let connector = ConnectorConfig::from_yaml_str(
+        r#"
+transport:
+    name: pipeline_input
+    config:
+        pipeline: upstream
+        view: v
+format:
+    name: json
+    config:
+        update_format: insert_delete
+        array: true"#,
+    );
+    handle
+        .db
+        .new_connector(tenant_id, Uuid::now_v7(), "c", "", &connector, None)
+        .await
+        .unwrap();
+    for (name, connectors) in [
+        ("upstream", vec![]),
+        (
+            "p",
+            vec![AttachedConnector {
+                name: "input".to_string(),
+                is_input: true,
+                connector_name: "c".to_string(),
+                relation_name: "t".to_string(),
+            }],
+        ),
+    ] {
+        handle
+            .db
+            .new_pipeline(
+                tenant_id,
+                Uuid::now_v7(),
+                &None,
+                name,
+                "",
+                &RuntimeConfig::from_yaml(""),
+                &Some(connectors),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    let graph = pipeline_graph(&handle.db, tenant_id).await.unwrap();
+    assert_eq!(graph.pipelines.len(), 2);
+    assert!(graph
+        .pipelines
+        .iter()
+        .all(|node| node.current_status == PipelineStatus::Shutdown));
+    assert_eq!(
+        graph.dependencies,
+        vec![PipelineDependency {
+            pipeline: "p".to_string(),
+            connector: "input".to_string(),
+            table: "t".to_string(),
+            upstream: "upstream".to_string(),
+            view: "v".to_string(),
+        }]
+    );
+
+    let pipeline_id = handle
+        .db
+        .get_pipeline_by_name(tenant_id, "p")
+        .await
+        .unwrap()
+        .descriptor
+        .pipeline_id;
+    let mut config = handle.db.pipeline_config(tenant_id, "p").await.unwrap();
+    issue_pipeline_input_key(&handle.db, tenant_id, pipeline_id, &mut config)
+        .await
+        .unwrap();
+    let TransportConfig::PipelineInput(input) = &config.inputs["input"].connector_config.transport
+    else {
+        panic!("unexpected transport")
+    };
+    let api_key = input.api_key.clone().unwrap();
+    let (_, descr) = handle.db.validate_api_key(&api_key).await.unwrap();
+    let upstream_id = handle
+        .db
+        .get_pipeline_by_name(tenant_id, "upstream")
+        .await
+        .unwrap()
+        .descriptor
+        .pipeline_id;
+    assert_eq!(
+        descr.resource_scopes,
+        vec![ApiKeyScope {
+            resource: ApiResource::Pipeline(upstream_id),
+            actions: vec![ApiAction::Egress],
+        }]
+    );
+    // Only the key may read from the other pipeline with backpressure.
+    assert!(is_pipeline_input_key(&Actor(format!(
+        "api_key:{}",
+        descr.name
+    ))));
+    assert!(!is_pipeline_input_key(&Actor(format!(
+        "user:{}",
+        descr.name
+    ))));
+
+    // Issuing a new key revokes the previous one.
+    issue_pipeline_input_key(&handle.db, tenant_id, pipeline_id, &mut config)
+        .await
+        .unwrap();
+    let err = handle.db.validate_api_key(&api_key).await.unwrap_err();
+    assert!(matches!(err, DBError::InvalidKey));
+
+    revoke_pipeline_input_key(&handle.db, tenant_id, pipeline_id)
+        .await
+        .unwrap();
+    assert!(handle.db.list_api_keys(tenant_id).await.unwrap().is_empty());
+    // Revoking is idempotent.
+    revoke_pipeline_input_key(&handle.db, tenant_id, pipeline_id)
+        .await
+        .unwrap();
+}
+
 /// Generate uuids but limits the the randomess to the first bits.
 ///
 /// This ensures that we have a good chance of generating a uuid that is already
@@ -1253,6 +3104,21 @@ pub(crate) fn limited_option_service_config() -> impl Strategy<Value = Option<Se
     })
 }
 
+/// Generate an optional API key expiration time, either in the past or in
+/// the future, with a precision of seconds.
+pub(crate) fn limited_option_expiration() -> impl Strategy<Value = Option<DateTime<Utc>>> {
+    any::<Option<bool>>().prop_map(|expired| {
+        expired.map(|expired| {
+            let now = convert_bigint_to_time("", Utc::now().timestamp()).unwrap();
+            if expired {
+                now - Duration::hours(1)
+            } else {
+                now + Duration::hours(1)
+            }
+        })
+    })
+}
+
 /// Generate an optional list limit
 pub(crate) fn limited_option_list_limit() -> impl Strategy<Value = Option<u32>> {
     any::<Option<u8>>().prop_map(|byte| byte.map(|b| (b % 5) as u32))
@@ -1297,6 +3163,8 @@ pub(crate) fn runtime_config() -> impl Strategy<Value = RuntimeConfig> {
             memory_mb_max: config.7,
             storage_mb_max: config.8,
         },
+        restart_policy: RestartPolicy::default(),
+        flight_port: None,
     })
 }
 
@@ -1328,30 +3196,34 @@ pub(crate) fn option_runtime_config() -> impl Strategy<Value = Option<RuntimeCon
                 memory_mb_max: config.7,
                 storage_mb_max: config.8,
             },
+            restart_policy: RestartPolicy::default(),
+            flight_port: None,
         })
     })
 }
 
 /// Generate different program configurations
 pub(crate) fn program_config() -> impl Strategy<Value = ProgramConfig> {
-    any::<(bool,)>().prop_map(|config| ProgramConfig {
+    any::<(bool, bool)>().prop_map(|config| ProgramConfig {
         profile: if config.0 {
             CompilationProfile::Unoptimized
         } else {
             CompilationProfile::Optimized
         },
+        materialized: config.1,
     })
 }
 
 /// Generate different program configurations
 pub(crate) fn option_program_config() -> impl Strategy<Value = Option<ProgramConfig>> {
-    any::<Option<(bool,)>>().prop_map(|c| {
+    any::<Option<(bool, bool)>>().prop_map(|c| {
         c.map(|config| ProgramConfig {
             profile: if config.0 {
                 CompilationProfile::Unoptimized
             } else {
                 CompilationProfile::Optimized
             },
+            materialized: config.1,
         })
     })
 }
@@ -1382,6 +3254,7 @@ enum StorageAction {
     SetProgramStatusGuarded(TenantId, ProgramId, Version, ProgramStatus),
     SetProgramSchema(TenantId, ProgramId, ProgramSchema),
     DeleteProgram(TenantId, String),
+    CancelProgramCompilation(TenantId, String),
     AllPrograms,
     NextJob,
     NewPipeline(
@@ -1406,7 +3279,7 @@ enum StorageAction {
         Option<Vec<AttachedConnector>>,
     ),
     UpdatePipelineRuntimeState(TenantId, PipelineId, PipelineRuntimeState),
-    SetPipelineDesiredStatus(TenantId, PipelineId, PipelineStatus),
+    SetPipelineDesiredStatus(TenantId, PipelineId, PipelineStatus, String),
     DeletePipeline(TenantId, String),
     GetPipelineById(TenantId, PipelineId),
     GetPipelineByName(TenantId, String),
@@ -1487,6 +3360,9 @@ enum StorageAction {
         String,
         String,
         Vec<ApiPermission>,
+        Vec<ApiKeyScope>,
+        #[proptest(strategy = "limited_option_expiration()")] Option<DateTime<Utc>>,
+        Role,
     ),
     ValidateApiKey(TenantId, String),
     CreatePipelineRevision(
@@ -1495,6 +3371,10 @@ enum StorageAction {
         PipelineId,
     ),
     GetCommittedPipeline(TenantId, PipelineId),
+    SetUserRole(TenantId, String, Role),
+    GetUserRole(TenantId, String),
+    ListUserRoles(TenantId),
+    DeleteUserRole(TenantId, String),
 }
 
 fn check_responses<T: Debug + PartialEq>(step: usize, model: DBResult<T>, impl_: DBResult<T>) {
@@ -1737,8 +3617,14 @@ fn db_impl_behaves_like_model() {
                             }
                             StorageAction::DeleteProgram(tenant_id, program_name) => {
                                 create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
-                                let model_response = model.delete_program(tenant_id, &program_name).await;
-                                let impl_response = handle.db.delete_program(tenant_id, &program_name).await;
+                                let model_response = model.delete_program(tenant_id, &program_name, None).await;
+                                let impl_response = handle.db.delete_program(tenant_id, &program_name, None).await;
+                                check_responses(i, model_response, impl_response);
+                            }
+                            StorageAction::CancelProgramCompilation(tenant_id, program_name) => {
+                                create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
+                                let model_response = model.cancel_program_compilation(tenant_id, &program_name).await;
+                                let impl_response = handle.db.cancel_program_compilation(tenant_id, &program_name).await;
                                 check_responses(i, model_response, impl_response);
                             }
                             StorageAction::AllPrograms => {
@@ -1822,16 +3708,16 @@ fn db_impl_behaves_like_model() {
                                 let impl_response = handle.db.update_pipeline_runtime_state(tenant_id, pipeline_id, &state).await;
                                 check_responses(i, model_response, impl_response);
                             }
-                            StorageAction::SetPipelineDesiredStatus(tenant_id, pipeline_id, status) => {
+                            StorageAction::SetPipelineDesiredStatus(tenant_id, pipeline_id, status, actor) => {
                                 create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
-                                let model_response = model.set_pipeline_desired_status(tenant_id, pipeline_id, status).await;
-                                let impl_response = handle.db.set_pipeline_desired_status(tenant_id, pipeline_id, status).await;
+                                let model_response = model.set_pipeline_desired_status(tenant_id, pipeline_id, status, &actor).await;
+                                let impl_response = handle.db.set_pipeline_desired_status(tenant_id, pipeline_id, status, &actor).await;
                                 check_responses(i, model_response, impl_response);
                             }
                             StorageAction::DeletePipeline(tenant_id, pipeline_name) => {
                                 create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
-                                let model_response = model.delete_pipeline(tenant_id, &pipeline_name).await;
-                                let impl_response = handle.db.delete_pipeline(tenant_id, &pipeline_name).await;
+                                let model_response = model.delete_pipeline(tenant_id, &pipeline_name, None).await;
+                                let impl_response = handle.db.delete_pipeline(tenant_id, &pipeline_name, None).await;
                                 check_responses(i, model_response, impl_response);
                             }
                             StorageAction::ListConnectors(tenant_id,) => {
@@ -1872,8 +3758,8 @@ fn db_impl_behaves_like_model() {
                             }
                             StorageAction::DeleteConnector(tenant_id, connector_name) => {
                                 create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
-                                let model_response = model.delete_connector(tenant_id, &connector_name).await;
-                                let impl_response = handle.db.delete_connector(tenant_id, &connector_name).await;
+                                let model_response = model.delete_connector(tenant_id, &connector_name, None).await;
+                                let impl_response = handle.db.delete_connector(tenant_id, &connector_name, None).await;
                                 check_responses(i, model_response, impl_response);
                             }
                             StorageAction::ListApiKeys(tenant_id) => {
@@ -1895,10 +3781,10 @@ fn db_impl_behaves_like_model() {
                                 let impl_response = handle.db.delete_api_key(tenant_id, &name).await;
                                 check_responses(i, model_response, impl_response);
                             },
-                            StorageAction::StoreApiKeyHash(tenant_id, id, name, key, permissions) => {
+                            StorageAction::StoreApiKeyHash(tenant_id, id, name, key, permissions, resource_scopes, expires_at, role) => {
                                 create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
-                                let model_response = model.store_api_key_hash(tenant_id, id, &name, &key, permissions.clone()).await;
-                                let impl_response = handle.db.store_api_key_hash(tenant_id, id, &name, &key, permissions.clone()).await;
+                                let model_response = model.store_api_key_hash(tenant_id, id, &name, &key, permissions.clone(), resource_scopes.clone(), expires_at, role).await;
+                                let impl_response = handle.db.store_api_key_hash(tenant_id, id, &name, &key, permissions.clone(), resource_scopes.clone(), expires_at, role).await;
                                 check_responses(i, model_response, impl_response);
                             },
                             StorageAction::ValidateApiKey(tenant_id,key) => {
@@ -1957,8 +3843,8 @@ fn db_impl_behaves_like_model() {
                             }
                             StorageAction::DeleteService(tenant_id, service_name) => {
                                 create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
-                                let model_response = model.delete_service(tenant_id, &service_name).await;
-                                let impl_response = handle.db.delete_service(tenant_id, &service_name).await;
+                                let model_response = model.delete_service(tenant_id, &service_name, None).await;
+                                let impl_response = handle.db.delete_service(tenant_id, &service_name, None).await;
                                 check_responses(i, model_response, impl_response);
                             }
                             StorageAction::NewServiceProbe(tenant_id, service_id, id, request, created_at) => {
@@ -1996,6 +3882,32 @@ fn db_impl_behaves_like_model() {
                                 let impl_response = handle.db.list_service_probes(tenant_id, service_id, limit, probe_type.clone(), None).await;
                                 check_responses(i, model_response, impl_response);
                             }
+                            StorageAction::SetUserRole(tenant_id, user_name, role) => {
+                                create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
+                                let model_response = model.set_user_role(tenant_id, &user_name, role).await;
+                                let impl_response = handle.db.set_user_role(tenant_id, &user_name, role).await;
+                                check_responses(i, model_response, impl_response);
+                            }
+                            StorageAction::GetUserRole(tenant_id, user_name) => {
+                                create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
+                                let model_response = model.get_user_role(tenant_id, &user_name).await;
+                                let impl_response = handle.db.get_user_role(tenant_id, &user_name).await;
+                                check_responses(i, model_response, impl_response);
+                            }
+                            StorageAction::ListUserRoles(tenant_id) => {
+                                create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
+                                let model_response = model.list_user_roles(tenant_id).await.unwrap();
+                                let mut impl_response = handle.db.list_user_roles(tenant_id).await.unwrap();
+                                // The collation of the database may order names differently
+                                impl_response.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+                                assert_eq!(model_response, impl_response);
+                            }
+                            StorageAction::DeleteUserRole(tenant_id, user_name) => {
+                                create_tenants_if_not_exists(&model, &handle, tenant_id).await.unwrap();
+                                let model_response = model.delete_user_role(tenant_id, &user_name).await;
+                                let impl_response = handle.db.delete_user_role(tenant_id, &user_name).await;
+                                check_responses(i, model_response, impl_response);
+                            }
                         }
                     }
                 });
@@ -2016,13 +3928,34 @@ type ProgramData = (ProgramDescr, SystemTime);
 struct DbModel {
     // `programs` Format is: (program, code, created)
     pub programs: BTreeMap<(TenantId, ProgramId), ProgramData>,
+    pub program_versions: BTreeMap<(TenantId, ProgramId, Version), ProgramVersionDescr>,
     pub pipelines: BTreeMap<(TenantId, PipelineId), Pipeline>,
     pub history: BTreeMap<(TenantId, PipelineId), PipelineRevision>,
-    pub api_keys: BTreeMap<(TenantId, String), (ApiKeyId, String, Vec<ApiPermission>)>,
+    #[allow(clippy::type_complexity)]
+    pub api_keys: BTreeMap<
+        (TenantId, String),
+        (
+            ApiKeyId,
+            String,
+            Vec<ApiPermission>,
+            Vec<ApiKeyScope>,
+            Option<DateTime<Utc>>,
+            Role,
+        ),
+    >,
     pub connectors: BTreeMap<(TenantId, ConnectorId), ConnectorDescr>,
     pub services: BTreeMap<(TenantId, ServiceId), ServiceDescr>,
     pub service_probes: BTreeMap<(TenantId, ServiceProbeId), (ServiceProbeDescr, ServiceId)>,
     pub tenants: BTreeMap<TenantId, TenantRecord>,
+    pub user_roles: BTreeMap<(TenantId, String), Role>,
+    pub audit_events: BTreeMap<(TenantId, AuditEventId), AuditEvent>,
+    pub secrets: BTreeMap<(TenantId, String), (SecretDescr, String)>,
+    pub pipeline_events: BTreeMap<(TenantId, PipelineEventId), PipelineEvent>,
+    pub webhooks: BTreeMap<(TenantId, String), (WebhookDescr, String)>,
+    pub webhook_deliveries: BTreeMap<WebhookDeliveryId, (TenantId, WebhookDelivery)>,
+    pub upgrade_requests: BTreeMap<(TenantId, PipelineId), PipelineRevision>,
+    pub pipeline_schedules: BTreeMap<(PipelineId, String), (TenantId, PipelineScheduleDescr)>,
+    pub pipeline_schedule_leases: BTreeMap<PipelineScheduleId, DateTime<Utc>>,
 }
 
 #[async_trait]
@@ -2070,6 +4003,16 @@ impl Storage for Mutex<DbModel> {
         let program_id = ProgramId(id);
         let version = Version(1);
 
+        s.program_versions.insert(
+            (tenant_id, program_id, version),
+            ProgramVersionDescr {
+                version,
+                code: Some(program_code.to_owned()),
+                config: config.clone(),
+                schema: None,
+                created_at: Utc::now(),
+            },
+        );
         s.programs.insert(
             (tenant_id, program_id),
             (
@@ -2082,6 +4025,7 @@ impl Storage for Mutex<DbModel> {
                     version,
                     code: Some(program_code.to_owned()),
                     config: config.clone(),
+                    warnings: vec![],
                 },
                 SystemTime::now(),
             ),
@@ -2146,7 +4090,8 @@ impl Storage for Mutex<DbModel> {
             });
         }
 
-        s.programs
+        let version = s
+            .programs
             .get_mut(&(tenant_id, program_id))
             .map(|(p, e)| {
                 let cur_code = p.code.clone().unwrap();
@@ -2195,7 +4140,69 @@ impl Storage for Mutex<DbModel> {
             })
             // This cannot fail because we already
             // checked whether the program exists
-            .ok_or(DBError::UnknownProgram { program_id })
+            .ok_or(DBError::UnknownProgram { program_id })?;
+
+        // Record the new version, or the schema of the current version.
+        let (p, _) = s.programs.get(&(tenant_id, program_id)).unwrap().clone();
+        if version != program_descr.version {
+            s.program_versions.insert(
+                (tenant_id, program_id, version),
+                ProgramVersionDescr {
+                    version,
+                    code: p.code,
+                    config: p.config,
+                    schema: None,
+                    created_at: Utc::now(),
+                },
+            );
+        } else if let Some(v) = s
+            .program_versions
+            .get_mut(&(tenant_id, program_id, version))
+        {
+            v.schema = p.schema;
+        }
+        Ok(version)
+    }
+
+    async fn list_program_versions(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        with_code: bool,
+    ) -> DBResult<Vec<ProgramVersionDescr>> {
+        let program = self
+            .get_program_by_name(tenant_id, program_name, false, None)
+            .await?;
+        let s = self.lock().await;
+        Ok(s.program_versions
+            .iter()
+            .filter(|((tid, pid, _), _)| *tid == tenant_id && *pid == program.program_id)
+            .rev()
+            .map(|(_, v)| ProgramVersionDescr {
+                code: if with_code { v.code.clone() } else { None },
+                ..v.clone()
+            })
+            .collect())
+    }
+
+    async fn get_program_version(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        version: Version,
+        _txn: Option<&Transaction<'_>>,
+    ) -> DBResult<ProgramVersionDescr> {
+        let program = self
+            .get_program_by_name(tenant_id, program_name, false, None)
+            .await?;
+        let s = self.lock().await;
+        s.program_versions
+            .get(&(tenant_id, program.program_id, version))
+            .cloned()
+            .ok_or(DBError::UnknownProgramVersion {
+                program_name: program_name.to_string(),
+                version,
+            })
     }
 
     async fn get_program_by_id(
@@ -2237,7 +4244,12 @@ impl Storage for Mutex<DbModel> {
             })?)
     }
 
-    async fn delete_program(&self, tenant_id: TenantId, program_name: &str) -> DBResult<()> {
+    async fn delete_program(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+        _txn: Option<&Transaction<'_>>,
+    ) -> DBResult<()> {
         let mut s = self.lock().await;
         // Foreign key delete:
         let program = &s
@@ -2268,11 +4280,37 @@ impl Storage for Mutex<DbModel> {
                 .ok_or(DBError::UnknownProgramName {
                     program_name: program_name.to_string(),
                 })?;
+            s.program_versions
+                .retain(|(tid, pid, _), _| !(*tid == tenant_id && *pid == program_id));
 
             Ok(())
         }
     }
 
+    async fn cancel_program_compilation(
+        &self,
+        tenant_id: TenantId,
+        program_name: &str,
+    ) -> DBResult<()> {
+        let mut s = self.lock().await;
+        let (program, status_since) = s
+            .programs
+            .iter_mut()
+            .find(|c| c.0 .0 == tenant_id && c.1 .0.name == program_name)
+            .ok_or(DBError::UnknownProgramName {
+                program_name: program_name.to_string(),
+            })?
+            .1;
+        if program.status != ProgramStatus::Pending && !program.status.is_compiling() {
+            return Err(DBError::ProgramNotCompiling {
+                program_name: program_name.to_string(),
+            });
+        }
+        program.status = ProgramStatus::Cancelled;
+        *status_since = SystemTime::now();
+        Ok(())
+    }
+
     async fn all_programs(&self) -> DBResult<Vec<(TenantId, ProgramDescr)>> {
         let s = self.lock().await;
         Ok(s.programs
@@ -2298,15 +4336,22 @@ impl Storage for Mutex<DbModel> {
         &self,
     ) -> DBResult<Option<(super::TenantId, super::ProgramId, super::Version)>> {
         let s = self.lock().await;
-        let mut values: Vec<(&(TenantId, ProgramId), &ProgramData)> =
-            Vec::from_iter(s.programs.iter());
-        values.sort_by(|(_, t1), (_, t2)| t1.1.cmp(&t2.1));
-
-        values
+        let in_flight = |tenant_id: TenantId| {
+            s.programs
+                .iter()
+                .filter(|(k, v)| k.0 == tenant_id && v.0.status.is_compiling())
+                .count()
+        };
+        let mut values: Vec<(&(TenantId, ProgramId), &ProgramData)> = s
+            .programs
             .iter()
-            .find(|(_, v)| v.0.status == ProgramStatus::Pending)
-            .map(|(k, v)| Ok(Some((k.0, v.0.program_id, v.0.version))))
-            .unwrap_or(Ok(None))
+            .filter(|(_, v)| v.0.status == ProgramStatus::Pending)
+            .collect();
+        values.sort_by_key(|(k, v)| (in_flight(k.0), v.1, v.0.program_id));
+
+        Ok(values
+            .first()
+            .map(|(k, v)| (k.0, v.0.program_id, v.0.version)))
     }
 
     async fn create_pipeline_deployment(
@@ -2507,6 +4552,9 @@ impl Storage for Mutex<DbModel> {
                     status_since: Utc::now(),
                     error: None,
                     created: Utc::now(),
+                    restart_count: 0,
+                    last_failure: None,
+                    desired_status_actor: None,
                 },
             },
         );
@@ -2607,7 +4655,12 @@ impl Storage for Mutex<DbModel> {
         todo!()
     }
 
-    async fn delete_pipeline(&self, tenant_id: TenantId, pipeline_name: &str) -> DBResult<()> {
+    async fn delete_pipeline(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        _txn: Option<&Transaction<'_>>,
+    ) -> DBResult<()> {
         let pipeline = self.get_pipeline_by_name(tenant_id, pipeline_name).await?;
         let mut s = self.lock().await;
         let _r = s
@@ -2615,6 +4668,12 @@ impl Storage for Mutex<DbModel> {
             .remove(&(tenant_id, pipeline.descriptor.pipeline_id));
         s.pipelines
             .remove(&(tenant_id, pipeline.descriptor.pipeline_id));
+        s.pipeline_events
+            .retain(|_, e| e.pipeline_id != pipeline.descriptor.pipeline_id);
+        s.upgrade_requests
+            .remove(&(tenant_id, pipeline.descriptor.pipeline_id));
+        s.pipeline_schedules
+            .retain(|(pipeline_id, _), _| *pipeline_id != pipeline.descriptor.pipeline_id);
         Ok(())
     }
 
@@ -2679,6 +4738,8 @@ impl Storage for Mutex<DbModel> {
         pipeline.state.status_since = state.status_since;
         pipeline.state.error = state.error.clone();
         pipeline.state.created = state.created;
+        pipeline.state.restart_count = state.restart_count;
+        pipeline.state.last_failure = state.last_failure.clone();
 
         Ok(())
     }
@@ -2688,6 +4749,27 @@ impl Storage for Mutex<DbModel> {
         tenant_id: TenantId,
         pipeline_id: PipelineId,
         desired_status: PipelineStatus,
+        actor: &str,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+
+        let state = &mut s
+            .pipelines
+            .get_mut(&(tenant_id, pipeline_id))
+            .ok_or(DBError::UnknownPipeline { pipeline_id })?
+            .state;
+        state.desired_status = desired_status;
+        state.desired_status_actor = Some(actor.to_string());
+
+        Ok(())
+    }
+
+    async fn request_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        revision: &PipelineRevision,
+        actor: &str,
     ) -> Result<(), DBError> {
         let mut s = self.lock().await;
 
@@ -2695,8 +4777,37 @@ impl Storage for Mutex<DbModel> {
             .get_mut(&(tenant_id, pipeline_id))
             .ok_or(DBError::UnknownPipeline { pipeline_id })?
             .state
-            .desired_status = desired_status;
+            .desired_status_actor = Some(actor.to_string());
+        s.upgrade_requests
+            .insert((tenant_id, pipeline_id), revision.clone());
+
+        Ok(())
+    }
 
+    async fn get_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+    ) -> Result<Option<PipelineRevision>, DBError> {
+        let s = self.lock().await;
+        s.pipelines
+            .get(&(tenant_id, pipeline_id))
+            .ok_or(DBError::UnknownPipeline { pipeline_id })?;
+        Ok(s.upgrade_requests.get(&(tenant_id, pipeline_id)).cloned())
+    }
+
+    async fn finish_pipeline_upgrade(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        deploy: bool,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        if let Some(revision) = s.upgrade_requests.remove(&(tenant_id, pipeline_id)) {
+            if deploy {
+                s.history.insert((tenant_id, pipeline_id), revision);
+            }
+        }
         Ok(())
     }
 
@@ -2857,7 +4968,12 @@ impl Storage for Mutex<DbModel> {
         Ok(())
     }
 
-    async fn delete_connector(&self, tenant_id: TenantId, connector_name: &str) -> DBResult<()> {
+    async fn delete_connector(
+        &self,
+        tenant_id: TenantId,
+        connector_name: &str,
+        _txn: Option<&Transaction<'_>>,
+    ) -> DBResult<()> {
         let mut s = self.lock().await;
         let connector_id = s
             .connectors
@@ -2887,6 +5003,9 @@ impl Storage for Mutex<DbModel> {
                 id: k.1 .0,
                 name: k.0 .1.clone(),
                 scopes: k.1 .2.clone(),
+                resource_scopes: k.1 .3.clone(),
+                expires_at: k.1 .4,
+                role: k.1 .5,
             })
             .collect())
     }
@@ -2902,6 +5021,9 @@ impl Storage for Mutex<DbModel> {
                     id: k.0,
                     name: name.to_string(),
                     scopes: k.2.clone(),
+                    resource_scopes: k.3.clone(),
+                    expires_at: k.4,
+                    role: k.5,
                 })
             },
         )
@@ -2924,6 +5046,9 @@ impl Storage for Mutex<DbModel> {
         name: &str,
         key: &str,
         permissions: Vec<ApiPermission>,
+        resource_scopes: Vec<ApiKeyScope>,
+        expires_at: Option<DateTime<Utc>>,
+        role: Role,
     ) -> DBResult<()> {
         let mut s = self.lock().await;
         let mut hasher = sha::Sha256::new();
@@ -2940,26 +5065,46 @@ impl Storage for Mutex<DbModel> {
         }
         s.api_keys.insert(
             (tenant_id, name.to_string()),
-            (ApiKeyId(id), hash, permissions),
+            (
+                ApiKeyId(id),
+                hash,
+                permissions,
+                resource_scopes,
+                expires_at,
+                role,
+            ),
         );
         Ok(())
     }
 
-    async fn validate_api_key(&self, key: &str) -> DBResult<(TenantId, Vec<ApiPermission>)> {
+    async fn validate_api_key(&self, key: &str) -> DBResult<(TenantId, ApiKeyDescr)> {
         let s = self.lock().await;
         let mut hasher = sha::Sha256::new();
         hasher.update(key.as_bytes());
         let hash = openssl::base64::encode_block(&hasher.finish());
-        let record: Vec<(TenantId, Vec<ApiPermission>)> = s
+        let now = Utc::now();
+        let record: Vec<(TenantId, ApiKeyDescr)> = s
             .api_keys
             .iter()
-            .filter(|k| k.1 .1 == hash)
-            .map(|k| (k.0 .0, k.1 .2.clone()))
+            .filter(|k| k.1 .1 == hash && !k.1 .4.is_some_and(|expires_at| expires_at <= now))
+            .map(|k| {
+                (
+                    k.0 .0,
+                    ApiKeyDescr {
+                        id: k.1 .0,
+                        name: k.0 .1.clone(),
+                        scopes: k.1 .2.clone(),
+                        resource_scopes: k.1 .3.clone(),
+                        expires_at: k.1 .4,
+                        role: k.1 .5,
+                    },
+                )
+            })
             .collect();
         assert!(record.len() <= 1);
         let record = record.get(0);
         match record {
-            Some(record) => Ok((record.0, record.1.clone())),
+            Some(record) => Ok(record.clone()),
             None => Err(DBError::InvalidKey),
         }
     }
@@ -3149,7 +5294,12 @@ impl Storage for Mutex<DbModel> {
         Ok(())
     }
 
-    async fn delete_service(&self, tenant_id: TenantId, service_name: &str) -> Result<(), DBError> {
+    async fn delete_service(
+        &self,
+        tenant_id: TenantId,
+        service_name: &str,
+        _txn: Option<&Transaction<'_>>,
+    ) -> Result<(), DBError> {
         let mut s = self.lock().await;
         // Retrieve the service identifier and check the name exists
         let ((_, service_id), _) = s
@@ -3394,4 +5544,587 @@ impl Storage for Mutex<DbModel> {
 
         Ok(list)
     }
+
+    async fn set_user_role(
+        &self,
+        tenant_id: TenantId,
+        user_name: &str,
+        role: Role,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        s.user_roles
+            .insert((tenant_id, user_name.to_string()), role);
+        Ok(())
+    }
+
+    async fn get_user_role(
+        &self,
+        tenant_id: TenantId,
+        user_name: &str,
+    ) -> Result<Option<Role>, DBError> {
+        let s = self.lock().await;
+        Ok(s.user_roles
+            .get(&(tenant_id, user_name.to_string()))
+            .copied())
+    }
+
+    async fn list_user_roles(&self, tenant_id: TenantId) -> Result<Vec<UserRole>, DBError> {
+        let s = self.lock().await;
+        // BTreeMap iteration order matches `ORDER BY user_name`.
+        Ok(s.user_roles
+            .iter()
+            .filter(|((tid, _), _)| *tid == tenant_id)
+            .map(|((_, user_name), role)| UserRole {
+                user_name: user_name.clone(),
+                role: *role,
+            })
+            .collect())
+    }
+
+    async fn delete_user_role(&self, tenant_id: TenantId, user_name: &str) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        s.user_roles
+            .remove(&(tenant_id, user_name.to_string()))
+            .map(|_| ())
+            .ok_or(DBError::UnknownUserRole {
+                user_name: user_name.to_string(),
+            })
+    }
+
+    async fn new_audit_event(
+        &self,
+        tenant_id: TenantId,
+        event: &AuditEvent,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        if s.audit_events
+            .keys()
+            .any(|(_, id)| *id == event.audit_event_id)
+        {
+            return Err(DBError::unique_key_violation("audit_log_pkey"));
+        }
+        s.audit_events
+            .insert((tenant_id, event.audit_event_id), event.clone());
+        Ok(())
+    }
+
+    async fn list_audit_events(
+        &self,
+        tenant_id: TenantId,
+        filter: &AuditEventFilter,
+    ) -> Result<Vec<AuditEvent>, DBError> {
+        let s = self.lock().await;
+        let mut list: Vec<AuditEvent> = s
+            .audit_events
+            .iter()
+            .filter(|((tid, _), _)| *tid == tenant_id)
+            .map(|(_, event)| event.clone())
+            .filter(|e| filter.actor.is_none() || filter.actor.as_ref() == Some(&e.actor))
+            .filter(|e| filter.action.is_none() || filter.action.as_ref() == Some(&e.action))
+            .filter(|e| filter.target.is_none() || filter.target == e.target)
+            .filter(|e| filter.outcome.is_none() || filter.outcome == Some(e.outcome))
+            .filter(|e| !filter.since.is_some_and(|t| e.timestamp < t))
+            .filter(|e| !filter.until.is_some_and(|t| e.timestamp >= t))
+            .collect();
+        // Sort descending on (timestamp, id)
+        list.sort_by(|e1, e2| {
+            (e2.timestamp, e2.audit_event_id).cmp(&(e1.timestamp, e1.audit_event_id))
+        });
+        if let Some(limit) = filter.limit {
+            list.truncate(limit as usize);
+        }
+        Ok(list)
+    }
+
+    async fn set_secret(
+        &self,
+        tenant_id: TenantId,
+        name: &str,
+        value: &str,
+    ) -> Result<(), DBError> {
+        validate_secret_name(name)?;
+        let mut s = self.lock().await;
+        let now = convert_bigint_to_time("", Utc::now().timestamp())?;
+        let created_at = s
+            .secrets
+            .get(&(tenant_id, name.to_string()))
+            .map_or(now, |(descr, _)| descr.created_at);
+        s.secrets.insert(
+            (tenant_id, name.to_string()),
+            (
+                SecretDescr {
+                    name: name.to_string(),
+                    created_at,
+                    updated_at: now,
+                },
+                value.to_string(),
+            ),
+        );
+        Ok(())
+    }
+
+    async fn list_secrets(&self, tenant_id: TenantId) -> Result<Vec<SecretDescr>, DBError> {
+        let s = self.lock().await;
+        // BTreeMap iteration order matches `ORDER BY name`.
+        Ok(s.secrets
+            .iter()
+            .filter(|((tid, _), _)| *tid == tenant_id)
+            .map(|(_, (descr, _))| descr.clone())
+            .collect())
+    }
+
+    async fn get_secret_value(&self, tenant_id: TenantId, name: &str) -> Result<String, DBError> {
+        let s = self.lock().await;
+        s.secrets
+            .get(&(tenant_id, name.to_string()))
+            .map(|(_, value)| value.clone())
+            .ok_or(DBError::UnknownSecret {
+                secret_name: name.to_string(),
+            })
+    }
+
+    async fn delete_secret(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        s.secrets
+            .remove(&(tenant_id, name.to_string()))
+            .map(|_| ())
+            .ok_or(DBError::UnknownSecret {
+                secret_name: name.to_string(),
+            })
+    }
+
+    async fn new_pipeline_event(
+        &self,
+        tenant_id: TenantId,
+        event: &PipelineEvent,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        if !s.pipelines.contains_key(&(tenant_id, event.pipeline_id)) {
+            return Err(DBError::UnknownPipeline {
+                pipeline_id: event.pipeline_id,
+            });
+        }
+        if s.pipeline_events
+            .keys()
+            .any(|(_, id)| *id == event.pipeline_event_id)
+        {
+            return Err(DBError::unique_key_violation("pipeline_event_pkey"));
+        }
+        s.pipeline_events
+            .insert((tenant_id, event.pipeline_event_id), event.clone());
+        Ok(())
+    }
+
+    async fn list_pipeline_events(
+        &self,
+        tenant_id: TenantId,
+        pipeline_id: PipelineId,
+        limit: Option<u32>,
+    ) -> Result<Vec<PipelineEvent>, DBError> {
+        let s = self.lock().await;
+        let mut list: Vec<PipelineEvent> = s
+            .pipeline_events
+            .iter()
+            .filter(|((tid, _), e)| *tid == tenant_id && e.pipeline_id == pipeline_id)
+            .map(|(_, event)| event.clone())
+            .collect();
+        // Sort descending on (timestamp, id)
+        list.sort_by(|e1, e2| {
+            (e2.timestamp, e2.pipeline_event_id).cmp(&(e1.timestamp, e1.pipeline_event_id))
+        });
+        if let Some(limit) = limit {
+            list.truncate(limit as usize);
+        }
+        Ok(list)
+    }
+
+    async fn get_pipeline_event(
+        &self,
+        tenant_id: TenantId,
+        pipeline_event_id: PipelineEventId,
+    ) -> Result<PipelineEvent, DBError> {
+        let s = self.lock().await;
+        s.pipeline_events
+            .get(&(tenant_id, pipeline_event_id))
+            .cloned()
+            .ok_or(DBError::UnknownPipelineEvent { pipeline_event_id })
+    }
+
+    async fn new_webhook(
+        &self,
+        tenant_id: TenantId,
+        id: Uuid,
+        name: &str,
+        url: &str,
+        secret: &str,
+        event_types: &[WebhookEventType],
+    ) -> Result<WebhookId, DBError> {
+        validate_webhook(url, event_types)?;
+        let mut s = self.lock().await;
+        if s.webhooks.contains_key(&(tenant_id, name.to_string())) {
+            return Err(DBError::DuplicateName);
+        }
+        if s.webhooks.values().any(|(w, _)| w.webhook_id.0 == id) {
+            return Err(DBError::unique_key_violation("webhook_pkey"));
+        }
+        s.webhooks.insert(
+            (tenant_id, name.to_string()),
+            (
+                WebhookDescr {
+                    webhook_id: WebhookId(id),
+                    name: name.to_string(),
+                    url: url.to_string(),
+                    event_types: event_types.to_vec(),
+                    created_at: convert_bigint_to_time("", Utc::now().timestamp())?,
+                },
+                secret.to_string(),
+            ),
+        );
+        Ok(WebhookId(id))
+    }
+
+    async fn list_webhooks(&self, tenant_id: TenantId) -> Result<Vec<WebhookDescr>, DBError> {
+        let s = self.lock().await;
+        // BTreeMap iteration order matches `ORDER BY name`.
+        Ok(s.webhooks
+            .iter()
+            .filter(|((tid, _), _)| *tid == tenant_id)
+            .map(|(_, (descr, _))| descr.clone())
+            .collect())
+    }
+
+    async fn delete_webhook(&self, tenant_id: TenantId, name: &str) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        let (webhook, _) =
+            s.webhooks
+                .remove(&(tenant_id, name.to_string()))
+                .ok_or(DBError::UnknownWebhook {
+                    webhook_name: name.to_string(),
+                })?;
+        s.webhook_deliveries
+            .retain(|_, (_, d)| d.webhook_id != webhook.webhook_id);
+        Ok(())
+    }
+
+    async fn new_webhook_deliveries(
+        &self,
+        tenant_id: TenantId,
+        event_type: WebhookEventType,
+        payload: &serde_json::Value,
+    ) -> Result<usize, DBError> {
+        let mut s = self.lock().await;
+        let now = convert_bigint_to_time("", Utc::now().timestamp())?;
+        let webhook_ids: Vec<WebhookId> = s
+            .webhooks
+            .iter()
+            .filter(|((tid, _), (w, _))| *tid == tenant_id && w.event_types.contains(&event_type))
+            .map(|(_, (w, _))| w.webhook_id)
+            .collect();
+        for webhook_id in &webhook_ids {
+            let webhook_delivery_id = WebhookDeliveryId(Uuid::now_v7());
+            s.webhook_deliveries.insert(
+                webhook_delivery_id,
+                (
+                    tenant_id,
+                    WebhookDelivery {
+                        webhook_delivery_id,
+                        webhook_id: *webhook_id,
+                        event_type,
+                        payload: payload.clone(),
+                        status: WebhookDeliveryStatus::Pending,
+                        attempts: 0,
+                        created_at: now,
+                        last_attempt_at: None,
+                        next_attempt_at: Some(now),
+                        response_status: None,
+                        last_error: None,
+                    },
+                ),
+            );
+        }
+        Ok(webhook_ids.len())
+    }
+
+    async fn list_webhook_deliveries(
+        &self,
+        tenant_id: TenantId,
+        webhook_name: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<WebhookDelivery>, DBError> {
+        let s = self.lock().await;
+        let (webhook, _) = s
+            .webhooks
+            .get(&(tenant_id, webhook_name.to_string()))
+            .ok_or(DBError::UnknownWebhook {
+                webhook_name: webhook_name.to_string(),
+            })?;
+        let mut list: Vec<WebhookDelivery> = s
+            .webhook_deliveries
+            .values()
+            .filter(|(_, d)| d.webhook_id == webhook.webhook_id)
+            .map(|(_, d)| d.clone())
+            .collect();
+        // Sort descending on (created_at, id)
+        list.sort_by(|d1, d2| {
+            (d2.created_at, d2.webhook_delivery_id).cmp(&(d1.created_at, d1.webhook_delivery_id))
+        });
+        if let Some(limit) = limit {
+            list.truncate(limit as usize);
+        }
+        Ok(list)
+    }
+
+    async fn next_webhook_deliveries(
+        &self,
+        limit: u32,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<PendingWebhookDelivery>, DBError> {
+        let mut s = self.lock().await;
+        let now = Utc::now();
+        let lease_until = convert_bigint_to_time("", lease_until.timestamp())?;
+        let mut due: Vec<WebhookDelivery> = s
+            .webhook_deliveries
+            .values()
+            .map(|(_, d)| d)
+            .filter(|d| {
+                d.status == WebhookDeliveryStatus::Pending
+                    && matches!(d.next_attempt_at, Some(t) if t <= now)
+            })
+            .cloned()
+            .collect();
+        due.sort_by_key(|d| (d.next_attempt_at, d.webhook_delivery_id));
+        due.truncate(limit as usize);
+        for d in &due {
+            s.webhook_deliveries
+                .get_mut(&d.webhook_delivery_id)
+                .unwrap()
+                .1
+                .next_attempt_at = Some(lease_until);
+        }
+        Ok(due
+            .into_iter()
+            .map(|d| {
+                let (webhook, secret) = s
+                    .webhooks
+                    .values()
+                    .find(|(w, _)| w.webhook_id == d.webhook_id)
+                    .unwrap();
+                PendingWebhookDelivery {
+                    webhook_delivery_id: d.webhook_delivery_id,
+                    event_type: d.event_type,
+                    payload: d.payload.to_string(),
+                    attempts: d.attempts,
+                    url: webhook.url.clone(),
+                    secret: secret.clone(),
+                }
+            })
+            .collect())
+    }
+
+    async fn record_webhook_delivery_attempt(
+        &self,
+        webhook_delivery_id: WebhookDeliveryId,
+        attempt: &WebhookDeliveryAttempt,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        if let Some((_, d)) = s.webhook_deliveries.get_mut(&webhook_delivery_id) {
+            d.status = attempt.status;
+            d.attempts += 1;
+            d.last_attempt_at = Some(convert_bigint_to_time("", attempt.timestamp.timestamp())?);
+            d.next_attempt_at = attempt
+                .next_attempt_at
+                .map(|t| convert_bigint_to_time("", t.timestamp()))
+                .transpose()?;
+            d.response_status = attempt.response_status;
+            d.last_error = attempt.error.clone();
+        }
+        Ok(())
+    }
+
+    async fn create_or_replace_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        id: Uuid,
+        pipeline_name: &str,
+        schedule_name: &str,
+        config: &PipelineScheduleConfig,
+    ) -> Result<(bool, PipelineScheduleId), DBError> {
+        let now = convert_bigint_to_time("", Utc::now().timestamp())?;
+        let next_run_at = first_pipeline_schedule_run(config, now)?;
+        let pipeline_id = self
+            .get_pipeline_by_name(tenant_id, pipeline_name)
+            .await?
+            .descriptor
+            .pipeline_id;
+        let mut s = self.lock().await;
+        if let Some((_, schedule)) = s
+            .pipeline_schedules
+            .get_mut(&(pipeline_id, schedule_name.to_string()))
+        {
+            schedule.action = config.action;
+            schedule.cron = config.cron.clone();
+            schedule.time_zone = config.time_zone.clone();
+            schedule.enabled = config.enabled;
+            schedule.next_run_at = next_run_at;
+            return Ok((false, schedule.pipeline_schedule_id));
+        }
+        if s.pipeline_schedules
+            .values()
+            .any(|(_, d)| d.pipeline_schedule_id.0 == id)
+        {
+            return Err(DBError::unique_key_violation("pipeline_schedule_pkey"));
+        }
+        s.pipeline_schedules.insert(
+            (pipeline_id, schedule_name.to_string()),
+            (
+                tenant_id,
+                PipelineScheduleDescr {
+                    pipeline_schedule_id: PipelineScheduleId(id),
+                    pipeline_id,
+                    name: schedule_name.to_string(),
+                    action: config.action,
+                    cron: config.cron.clone(),
+                    time_zone: config.time_zone.clone(),
+                    enabled: config.enabled,
+                    created_at: now,
+                    next_run_at,
+                    last_run_at: None,
+                    last_error: None,
+                    next_runs: Vec::new(),
+                },
+            ),
+        );
+        Ok((true, PipelineScheduleId(id)))
+    }
+
+    async fn list_pipeline_schedules(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+    ) -> Result<Vec<PipelineScheduleDescr>, DBError> {
+        let pipeline_id = self
+            .get_pipeline_by_name(tenant_id, pipeline_name)
+            .await?
+            .descriptor
+            .pipeline_id;
+        let s = self.lock().await;
+        // BTreeMap iteration order matches `ORDER BY name`.
+        Ok(s.pipeline_schedules
+            .iter()
+            .filter(|((pid, _), _)| *pid == pipeline_id)
+            .map(|(_, (_, descr))| descr.clone())
+            .collect())
+    }
+
+    async fn get_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        schedule_name: &str,
+    ) -> Result<PipelineScheduleDescr, DBError> {
+        let pipeline_id = self
+            .get_pipeline_by_name(tenant_id, pipeline_name)
+            .await?
+            .descriptor
+            .pipeline_id;
+        let s = self.lock().await;
+        s.pipeline_schedules
+            .get(&(pipeline_id, schedule_name.to_string()))
+            .map(|(_, descr)| descr.clone())
+            .ok_or(DBError::UnknownPipelineSchedule {
+                schedule_name: schedule_name.to_string(),
+            })
+    }
+
+    async fn delete_pipeline_schedule(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        schedule_name: &str,
+    ) -> Result<(), DBError> {
+        let pipeline_id = self
+            .get_pipeline_by_name(tenant_id, pipeline_name)
+            .await?
+            .descriptor
+            .pipeline_id;
+        let mut s = self.lock().await;
+        s.pipeline_schedules
+            .remove(&(pipeline_id, schedule_name.to_string()))
+            .map(|_| ())
+            .ok_or(DBError::UnknownPipelineSchedule {
+                schedule_name: schedule_name.to_string(),
+            })
+    }
+
+    async fn next_pipeline_schedule_runs(
+        &self,
+        limit: u32,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Vec<DuePipelineSchedule>, DBError> {
+        let mut s = self.lock().await;
+        let now = Utc::now();
+        let lease_until = convert_bigint_to_time("", lease_until.timestamp())?;
+        let mut due: Vec<(TenantId, PipelineScheduleDescr, DateTime<Utc>)> = s
+            .pipeline_schedules
+            .values()
+            .filter(|(_, d)| {
+                s.pipeline_schedule_leases
+                    .get(&d.pipeline_schedule_id)
+                    .map_or(true, |leased_until| *leased_until <= now)
+            })
+            .filter_map(|(tenant_id, d)| match d.next_run_at {
+                Some(t) if d.enabled && t <= now => Some((*tenant_id, d.clone(), t)),
+                _ => None,
+            })
+            .collect();
+        due.sort_by_key(|(_, d, t)| (*t, d.pipeline_schedule_id));
+        due.truncate(limit as usize);
+        for (_, d, _) in &due {
+            s.pipeline_schedule_leases
+                .insert(d.pipeline_schedule_id, lease_until);
+        }
+        Ok(due
+            .into_iter()
+            .map(|(tenant_id, d, next_run_at)| DuePipelineSchedule {
+                pipeline_schedule_id: d.pipeline_schedule_id,
+                tenant_id,
+                pipeline_name: s
+                    .pipelines
+                    .get(&(tenant_id, d.pipeline_id))
+                    .unwrap()
+                    .descriptor
+                    .name
+                    .clone(),
+                name: d.name.clone(),
+                action: d.action,
+                cron: d.cron.clone(),
+                time_zone: d.time_zone.clone(),
+                next_run_at,
+            })
+            .collect())
+    }
+
+    async fn record_pipeline_schedule_run(
+        &self,
+        pipeline_schedule_id: PipelineScheduleId,
+        run: &PipelineScheduleRun,
+    ) -> Result<(), DBError> {
+        let mut s = self.lock().await;
+        s.pipeline_schedule_leases.remove(&pipeline_schedule_id);
+        if let Some((_, d)) = s
+            .pipeline_schedules
+            .values_mut()
+            .find(|(_, d)| d.pipeline_schedule_id == pipeline_schedule_id)
+        {
+            d.last_run_at = Some(convert_bigint_to_time("", run.timestamp.timestamp())?);
+            d.last_error = run.error.clone();
+            if d.next_run_at == Some(run.scheduled_at) {
+                d.next_run_at = run
+                    .next_run_at
+                    .map(|t| convert_bigint_to_time("", t.timestamp()))
+                    .transpose()?;
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,512 @@
+use crate::auth::TenantId;
+use crate::db::pipeline::convert_bigint_to_time;
+use crate::db::{DBError, PipelineStatus, ProjectDB};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::fmt::Display;
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Unique webhook id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[repr(transparent)]
+#[serde(transparent)]
+pub struct WebhookId(pub Uuid);
+impl Display for WebhookId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Unique webhook delivery id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[repr(transparent)]
+#[serde(transparent)]
+pub struct WebhookDeliveryId(pub Uuid);
+impl Display for WebhookDeliveryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Event that a webhook can subscribe to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookEventType {
+    /// A program version compiled successfully.
+    ProgramCompilationSucceeded,
+    /// A program version failed to compile.
+    ProgramCompilationFailed,
+    /// A pipeline transitioned to `Running`.
+    PipelineRunning,
+    /// A pipeline transitioned to `Failed`.
+    PipelineFailed,
+    /// A pipeline transitioned to `Shutdown`.
+    PipelineShutdown,
+}
+
+impl WebhookEventType {
+    pub(crate) const ALL: [WebhookEventType; 5] = [
+        WebhookEventType::ProgramCompilationSucceeded,
+        WebhookEventType::ProgramCompilationFailed,
+        WebhookEventType::PipelineRunning,
+        WebhookEventType::PipelineFailed,
+        WebhookEventType::PipelineShutdown,
+    ];
+
+    /// Returns the event that a transition of a pipeline to `status` raises,
+    /// if any.
+    pub(crate) fn for_pipeline_status(status: PipelineStatus) -> Option<Self> {
+        match status {
+            PipelineStatus::Running => Some(WebhookEventType::PipelineRunning),
+            PipelineStatus::Failed => Some(WebhookEventType::PipelineFailed),
+            PipelineStatus::Shutdown => Some(WebhookEventType::PipelineShutdown),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<String> for WebhookEventType {
+    type Error = DBError;
+    fn try_from(value: String) -> Result<Self, DBError> {
+        match value.as_str() {
+            "program_compilation_succeeded" => Ok(Self::ProgramCompilationSucceeded),
+            "program_compilation_failed" => Ok(Self::ProgramCompilationFailed),
+            "pipeline_running" => Ok(Self::PipelineRunning),
+            "pipeline_failed" => Ok(Self::PipelineFailed),
+            "pipeline_shutdown" => Ok(Self::PipelineShutdown),
+            _ => Err(DBError::invalid_data(format!(
+                "unknown webhook event type '{value}'"
+            ))),
+        }
+    }
+}
+
+impl From<WebhookEventType> for &'static str {
+    fn from(val: WebhookEventType) -> Self {
+        match val {
+            WebhookEventType::ProgramCompilationSucceeded => "program_compilation_succeeded",
+            WebhookEventType::ProgramCompilationFailed => "program_compilation_failed",
+            WebhookEventType::PipelineRunning => "pipeline_running",
+            WebhookEventType::PipelineFailed => "pipeline_failed",
+            WebhookEventType::PipelineShutdown => "pipeline_shutdown",
+        }
+    }
+}
+
+/// Webhook registered by a tenant.  The signing secret of the webhook is
+/// never returned by the API.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct WebhookDescr {
+    pub webhook_id: WebhookId,
+    /// Name of the webhook, unique per tenant.
+    pub name: String,
+    /// Endpoint the events are posted to.
+    pub url: String,
+    /// Events delivered to the webhook.
+    pub event_types: Vec<WebhookEventType>,
+    /// Time when the webhook was created.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Status of the delivery of an event to a webhook.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookDeliveryStatus {
+    /// The event has not been delivered yet; it is retried at
+    /// `next_attempt_at`.
+    Pending,
+    /// The endpoint acknowledged the event with a 2xx response.
+    Delivered,
+    /// All delivery attempts failed.
+    Failed,
+}
+
+impl TryFrom<String> for WebhookDeliveryStatus {
+    type Error = DBError;
+    fn try_from(value: String) -> Result<Self, DBError> {
+        match value.as_str() {
+            "pending" => Ok(Self::Pending),
+            "delivered" => Ok(Self::Delivered),
+            "failed" => Ok(Self::Failed),
+            _ => Err(DBError::invalid_data(format!(
+                "unknown webhook delivery status '{value}'"
+            ))),
+        }
+    }
+}
+
+impl From<WebhookDeliveryStatus> for &'static str {
+    fn from(val: WebhookDeliveryStatus) -> Self {
+        match val {
+            WebhookDeliveryStatus::Pending => "pending",
+            WebhookDeliveryStatus::Delivered => "delivered",
+            WebhookDeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Delivery of an event to a webhook.
+#[derive(Deserialize, Serialize, ToSchema, Debug, Clone, PartialEq)]
+pub(crate) struct WebhookDelivery {
+    pub webhook_delivery_id: WebhookDeliveryId,
+    pub webhook_id: WebhookId,
+    pub event_type: WebhookEventType,
+    /// Body posted to the endpoint.
+    #[schema(value_type = Object)]
+    pub payload: JsonValue,
+    pub status: WebhookDeliveryStatus,
+    /// Number of delivery attempts so far.
+    pub attempts: u32,
+    /// Time when the event occurred.
+    pub created_at: DateTime<Utc>,
+    /// Time of the last delivery attempt.
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// Time of the next delivery attempt of a pending delivery.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// HTTP status code returned by the endpoint on the last attempt.
+    pub response_status: Option<u16>,
+    /// Error of the last failed attempt.
+    pub last_error: Option<String>,
+}
+
+/// Delivery that is due, along with the endpoint to deliver it to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PendingWebhookDelivery {
+    pub webhook_delivery_id: WebhookDeliveryId,
+    pub event_type: WebhookEventType,
+    /// Body of the request, signed as is.
+    pub payload: String,
+    /// Number of delivery attempts so far.
+    pub attempts: u32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Outcome of an attempt to deliver an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WebhookDeliveryAttempt {
+    pub timestamp: DateTime<Utc>,
+    /// Status of the delivery after the attempt.
+    pub status: WebhookDeliveryStatus,
+    /// Time of the next attempt, if the delivery remains pending.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Checks that a webhook posts to an HTTP(S) URL and subscribes to at least
+/// one event.
+pub(crate) fn validate_webhook(url: &str, event_types: &[WebhookEventType]) -> Result<(), DBError> {
+    let invalid = |reason: String| DBError::InvalidWebhook { reason };
+    let parsed = url::Url::parse(url).map_err(|e| invalid(format!("invalid URL '{url}': {e}")))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(invalid(format!(
+            "URL '{url}' must use the 'http' or 'https' scheme"
+        )));
+    }
+    if event_types.is_empty() {
+        return Err(invalid(
+            "the webhook must subscribe to at least one event type".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Name under which the signing secret of a webhook is encrypted.
+fn secret_name(webhook_id: Uuid) -> String {
+    format!("webhook/{webhook_id}")
+}
+
+pub(crate) async fn new_webhook(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    id: Uuid,
+    name: &str,
+    url: &str,
+    secret: &str,
+    event_types: &[WebhookEventType],
+) -> Result<WebhookId, DBError> {
+    validate_webhook(url, event_types)?;
+    let key = db
+        .secrets_key
+        .as_ref()
+        .ok_or(DBError::SecretsNotConfigured)?;
+    let (nonce, ciphertext) = key.encrypt(tenant_id, &secret_name(id), secret)?;
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "INSERT INTO webhook (id, tenant_id, name, url, secret_nonce, secret_ciphertext, event_types, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .await?;
+    manager
+        .execute(
+            &stmt,
+            &[
+                &id,
+                &tenant_id.0,
+                &name,
+                &url,
+                &nonce,
+                &ciphertext,
+                &serde_json::to_string(event_types).unwrap(),
+                &Utc::now().timestamp(),
+            ],
+        )
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)
+        .map_err(|e| ProjectDB::maybe_tenant_id_foreign_key_constraint_err(e, tenant_id, None))?;
+    Ok(WebhookId(id))
+}
+
+pub(crate) async fn list_webhooks(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+) -> Result<Vec<WebhookDescr>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, name, url, event_types, created_at FROM webhook
+             WHERE tenant_id = $1 ORDER BY name",
+        )
+        .await?;
+    let rows = manager.query(&stmt, &[&tenant_id.0]).await?;
+    rows.iter().map(row_to_webhook_descr).collect()
+}
+
+pub(crate) async fn delete_webhook(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    name: &str,
+) -> Result<(), DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("DELETE FROM webhook WHERE tenant_id = $1 AND name = $2")
+        .await?;
+    let res = manager.execute(&stmt, &[&tenant_id.0, &name]).await?;
+    if res > 0 {
+        Ok(())
+    } else {
+        Err(DBError::UnknownWebhook {
+            webhook_name: name.to_string(),
+        })
+    }
+}
+
+pub(crate) async fn new_webhook_deliveries(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    event_type: WebhookEventType,
+    payload: &JsonValue,
+) -> Result<usize, DBError> {
+    let mut manager = db.pool.get().await?;
+    let txn = manager.transaction().await?;
+    let stmt = txn
+        .prepare_cached("SELECT id, event_types FROM webhook WHERE tenant_id = $1")
+        .await?;
+    let rows = txn.query(&stmt, &[&tenant_id.0]).await?;
+    let insert = txn
+        .prepare_cached(
+            "INSERT INTO webhook_delivery (id, tenant_id, webhook_id, event_type, payload, status, attempts, created_at, next_attempt_at)
+             VALUES ($1, $2, $3, $4, $5, 'pending', 0, $6, $6)",
+        )
+        .await?;
+    let event_type_str: &'static str = event_type.into();
+    let payload = payload.to_string();
+    let now = Utc::now().timestamp();
+    let mut deliveries = 0;
+    for row in rows {
+        if !deserialize_event_types(row.get(1))?.contains(&event_type) {
+            continue;
+        }
+        let webhook_id: Uuid = row.get(0);
+        txn.execute(
+            &insert,
+            &[
+                &Uuid::now_v7(),
+                &tenant_id.0,
+                &webhook_id,
+                &event_type_str,
+                &payload,
+                &now,
+            ],
+        )
+        .await
+        .map_err(ProjectDB::maybe_unique_violation)?;
+        deliveries += 1;
+    }
+    txn.commit().await?;
+    Ok(deliveries)
+}
+
+pub(crate) async fn list_webhook_deliveries(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    webhook_name: &str,
+    limit: Option<u32>,
+) -> Result<Vec<WebhookDelivery>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached("SELECT id FROM webhook WHERE tenant_id = $1 AND name = $2")
+        .await?;
+    let webhook_id: Uuid = manager
+        .query_opt(&stmt, &[&tenant_id.0, &webhook_name])
+        .await?
+        .ok_or_else(|| DBError::UnknownWebhook {
+            webhook_name: webhook_name.to_string(),
+        })?
+        .get(0);
+    let stmt = manager
+        .prepare_cached(
+            "SELECT id, webhook_id, event_type, payload, status, attempts, created_at,
+                    last_attempt_at, next_attempt_at, response_status, last_error
+             FROM webhook_delivery
+             WHERE webhook_id = $1
+             ORDER BY created_at DESC, id DESC
+             LIMIT $2",
+        )
+        .await?;
+    let rows = manager
+        .query(&stmt, &[&webhook_id, &limit.map(i64::from)])
+        .await?;
+    rows.iter().map(row_to_webhook_delivery).collect()
+}
+
+/// Claims up to `limit` pending deliveries that are due until `lease_until`,
+/// so that concurrent dispatchers do not attempt them as well.
+pub(crate) async fn next_webhook_deliveries(
+    db: &ProjectDB,
+    limit: u32,
+    lease_until: DateTime<Utc>,
+) -> Result<Vec<PendingWebhookDelivery>, DBError> {
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "UPDATE webhook_delivery d SET next_attempt_at = $3
+             FROM webhook w
+             WHERE d.webhook_id = w.id AND d.id IN (
+                 SELECT id FROM webhook_delivery
+                 WHERE status = 'pending' AND next_attempt_at <= $1
+                 ORDER BY next_attempt_at, id
+                 LIMIT $2
+                 FOR UPDATE SKIP LOCKED)
+             RETURNING d.id, d.event_type, d.payload, d.attempts, w.url, w.tenant_id, w.id,
+                       w.secret_nonce, w.secret_ciphertext",
+        )
+        .await?;
+    let rows = manager
+        .query(
+            &stmt,
+            &[
+                &Utc::now().timestamp(),
+                &i64::from(limit),
+                &lease_until.timestamp(),
+            ],
+        )
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(PendingWebhookDelivery {
+                webhook_delivery_id: WebhookDeliveryId(row.get(0)),
+                event_type: row.get::<_, String>(1).try_into()?,
+                payload: row.get(2),
+                attempts: row.get::<_, i64>(3) as u32,
+                url: row.get(4),
+                secret: decrypt_secret(db, row, 5)?,
+            })
+        })
+        .collect()
+}
+
+/// Decrypts the signing secret of the webhook in `row`, given by the tenant
+/// id, webhook id, nonce and ciphertext columns starting at `column`.
+fn decrypt_secret(db: &ProjectDB, row: &Row, column: usize) -> Result<String, DBError> {
+    let tenant_id = TenantId(row.get(column));
+    let webhook_id: Uuid = row.get(column + 1);
+    let nonce: Vec<u8> = row.get(column + 2);
+    let ciphertext: Vec<u8> = row.get(column + 3);
+    db.secrets_key
+        .as_ref()
+        .ok_or(DBError::SecretsNotConfigured)?
+        .decrypt(tenant_id, &secret_name(webhook_id), &nonce, &ciphertext)
+}
+
+pub(crate) async fn record_webhook_delivery_attempt(
+    db: &ProjectDB,
+    webhook_delivery_id: WebhookDeliveryId,
+    attempt: &WebhookDeliveryAttempt,
+) -> Result<(), DBError> {
+    let status: &'static str = attempt.status.into();
+    let manager = db.pool.get().await?;
+    let stmt = manager
+        .prepare_cached(
+            "UPDATE webhook_delivery
+             SET status = $2, attempts = attempts + 1, last_attempt_at = $3, next_attempt_at = $4,
+                 response_status = $5, last_error = $6
+             WHERE id = $1",
+        )
+        .await?;
+    // The delivery no longer exists if its webhook was deleted in the
+    // meantime, in which case there is nothing to record.
+    manager
+        .execute(
+            &stmt,
+            &[
+                &webhook_delivery_id.0,
+                &status,
+                &attempt.timestamp.timestamp(),
+                &attempt.next_attempt_at.map(|t| t.timestamp()),
+                &attempt.response_status.map(i32::from),
+                &attempt.error,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+fn deserialize_event_types(event_types: String) -> Result<Vec<WebhookEventType>, DBError> {
+    serde_json::from_str(&event_types).map_err(|e| {
+        DBError::invalid_data(format!(
+            "error deserializing webhook event types '{event_types}': {e}"
+        ))
+    })
+}
+
+fn row_to_webhook_descr(row: &Row) -> Result<WebhookDescr, DBError> {
+    Ok(WebhookDescr {
+        webhook_id: WebhookId(row.get(0)),
+        name: row.get(1),
+        url: row.get(2),
+        event_types: deserialize_event_types(row.get(3))?,
+        created_at: convert_bigint_to_time("webhook.created_at", row.get(4))?,
+    })
+}
+
+fn row_to_webhook_delivery(row: &Row) -> Result<WebhookDelivery, DBError> {
+    let payload: String = row.get(3);
+    Ok(WebhookDelivery {
+        webhook_delivery_id: WebhookDeliveryId(row.get(0)),
+        webhook_id: WebhookId(row.get(1)),
+        event_type: row.get::<_, String>(2).try_into()?,
+        payload: serde_json::from_str(&payload).map_err(|e| {
+            DBError::invalid_data(format!("error deserializing webhook payload: {e}"))
+        })?,
+        status: row.get::<_, String>(4).try_into()?,
+        attempts: row.get::<_, i64>(5) as u32,
+        created_at: convert_bigint_to_time("webhook_delivery.created_at", row.get(6))?,
+        last_attempt_at: row
+            .get::<_, Option<i64>>(7)
+            .map(|t| convert_bigint_to_time("webhook_delivery.last_attempt_at", t))
+            .transpose()?,
+        next_attempt_at: row
+            .get::<_, Option<i64>>(8)
+            .map(|t| convert_bigint_to_time("webhook_delivery.next_attempt_at", t))
+            .transpose()?,
+        response_status: row.get::<_, Option<i32>>(9).map(|s| s as u16),
+        last_error: row.get(10),
+    })
+}
@@ -12,13 +12,13 @@ use uuid::Uuid;
 
 use crate::{
     auth::TenantId,
-    db::{storage::Storage, DBError, PipelineId, ProgramId, ProjectDB},
+    db::{storage::Storage, DBError, PipelineEventId, PipelineId, ProgramId, ProjectDB},
 };
 
 const RETRY_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(2);
 
-/// Listen for changes to a table (currently set up for the program, pipeline
-/// and pipeline_runtime_state tables).
+/// Listen for changes to a table (currently set up for the program, pipeline,
+/// pipeline_runtime_state and pipeline_event tables).
 ///
 /// Partly inspired by the Kubernetes Informers machinery.
 ///
@@ -50,7 +50,9 @@ pub async fn listen(conn: Arc<tokio::sync::Mutex<ProjectDB>>, tx: UnboundedSende
         let client_copy = client.clone();
         tokio::spawn(async move {
             client_copy
-                .batch_execute("LISTEN program; LISTEN pipeline; LISTEN pipeline_runtime_state;")
+                .batch_execute(
+                    "LISTEN program; LISTEN pipeline; LISTEN pipeline_runtime_state; LISTEN pipeline_event;",
+                )
                 .await
                 .unwrap();
         });
@@ -155,9 +157,13 @@ async fn sync(
 /// Parse notifications generated by the database.
 /// The channel corresponds to the relation name.
 /// The payload is a String with the following shape: "Operation TenantId
-/// ProgramId"
+/// ObjectId"
 fn parse_notification(channel: &str, payload: &str) -> Result<DbNotification, NotificationError> {
-    if channel != "program" && channel != "pipeline" && channel != "pipeline_runtime_state" {
+    if channel != "program"
+        && channel != "pipeline"
+        && channel != "pipeline_runtime_state"
+        && channel != "pipeline_event"
+    {
         return Err(NotificationError::InvalidChannel(channel.to_string()));
     }
     let mut split = payload.split(' ');
@@ -172,12 +178,12 @@ fn parse_notification(channel: &str, payload: &str) -> Result<DbNotification, No
     }
     let operation = parse_operation(op_type.unwrap())?;
     let tenant_id = TenantId(Uuid::parse_str(tenant_id.unwrap())?);
-    let program_or_pipeline_id = Uuid::parse_str(program_id.unwrap())?;
+    let object_id = Uuid::parse_str(program_id.unwrap())?;
     match channel {
         "program" => Ok(DbNotification::Program(
             operation,
             tenant_id,
-            ProgramId(program_or_pipeline_id),
+            ProgramId(object_id),
         )),
         // TODO: it could be beneficial to differentiate between both channels.
         // Given that we do not return the full object, there is limited value in
@@ -185,7 +191,12 @@ fn parse_notification(channel: &str, payload: &str) -> Result<DbNotification, No
         "pipeline" | "pipeline_runtime_state" => Ok(DbNotification::Pipeline(
             operation,
             tenant_id,
-            PipelineId(program_or_pipeline_id),
+            PipelineId(object_id),
+        )),
+        "pipeline_event" => Ok(DbNotification::PipelineEvent(
+            operation,
+            tenant_id,
+            PipelineEventId(object_id),
         )),
         _ => unreachable!("Invalid channel"),
     }
@@ -195,6 +206,8 @@ fn parse_notification(channel: &str, payload: &str) -> Result<DbNotification, No
 pub enum DbNotification {
     Program(Operation, TenantId, ProgramId),
     Pipeline(Operation, TenantId, PipelineId),
+    /// A status transition of a pipeline was recorded.
+    PipelineEvent(Operation, TenantId, PipelineEventId),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -268,6 +281,7 @@ mod test {
                     "ignored",
                     &ProgramConfig {
                         profile: CompilationProfile::Unoptimized,
+                        materialized: false,
                     },
                     None,
                 )
@@ -351,12 +365,12 @@ mod test {
             // Deletes
             conn.lock()
                 .await
-                .delete_program(tenant_id, &updated_program_name)
+                .delete_program(tenant_id, &updated_program_name, None)
                 .await
                 .unwrap();
             conn.lock()
                 .await
-                .delete_pipeline(tenant_id, pipeline_name)
+                .delete_pipeline(tenant_id, pipeline_name, None)
                 .await
                 .unwrap();
 
@@ -393,6 +407,7 @@ mod test {
                 "ignored",
                 &ProgramConfig {
                     profile: CompilationProfile::Unoptimized,
+                    materialized: false,
                 },
                 None,
             )
@@ -17,6 +17,7 @@
 //! `dbsp_adapters` crate, i.e., errors returned by the pipeline manager and
 //! by individual pipelines have the same format.
 
+use crate::db::audit::Role;
 use crate::db::DBError;
 use crate::runner::RunnerError;
 use actix_web::{
@@ -52,9 +53,16 @@ pub enum ManagerError {
         value: String,
         error: String,
     },
+    InvalidVersionParam {
+        value: String,
+        error: String,
+    },
     InvalidPipelineAction {
         action: String,
     },
+    InvalidBundle {
+        error: String,
+    },
     DBError {
         #[serde(flatten)]
         db_error: DBError,
@@ -77,6 +85,34 @@ pub enum ManagerError {
     RustCompilerError {
         error: String,
     },
+    ApiKeyNotAuthorized {
+        reason: String,
+    },
+    InsufficientRole {
+        role: Role,
+        required: Role,
+    },
+    /// A configuration references a database secret, but the issuer of the
+    /// request is not allowed to access the secrets of the tenant.
+    DatabaseSecretNotAuthorized {
+        path: String,
+    },
+    /// Only the API keys issued to pipelines may read the output of a
+    /// pipeline with backpressure.
+    EgressBackpressureNotAllowed,
+    /// Admitting the request would exceed a quota of the tenant.  The request
+    /// can be retried once other pipelines shut down or compilations finish.
+    QuotaExceeded {
+        quota: String,
+        limit: u64,
+        usage: u64,
+        requested: u64,
+    },
+    /// The request can never be admitted under a quota of the tenant.
+    QuotaViolation {
+        quota: String,
+        reason: String,
+    },
 }
 
 impl ManagerError {
@@ -162,9 +198,15 @@ impl Display for ManagerError {
             Self::InvalidNameParam { value, error } => {
                 write!(f, "Invalid name string '{value}': '{error}'")
             }
+            Self::InvalidVersionParam { value, error } => {
+                write!(f, "Invalid version '{value}': '{error}'")
+            }
             Self::InvalidPipelineAction { action } => {
                 write!(f, "Invalid pipeline action '{action}'; valid actions are: 'deploy', 'start', 'pause', or 'shutdown'")
             }
+            Self::InvalidBundle { error } => {
+                write!(f, "Invalid bundle: {error}")
+            }
             Self::DBError { db_error } => db_error.fmt(f),
             Self::RunnerError { runner_error } => runner_error.fmt(f),
             Self::IoError {
@@ -178,6 +220,41 @@ impl Display for ManagerError {
             Self::RustCompilerError { error } => {
                 write!(f, "Error compiling generated Rust code: {error}")
             }
+            Self::ApiKeyNotAuthorized { reason } => {
+                write!(f, "Request not authorized: {reason}")
+            }
+            Self::InsufficientRole { role, required } => {
+                write!(
+                    f,
+                    "Request not authorized: the '{required}' role is required, but the user has the '{role}' role"
+                )
+            }
+            Self::DatabaseSecretNotAuthorized { path } => {
+                write!(
+                    f,
+                    "Request not authorized: '{path}' references a database secret, which requires access to the secrets of the tenant"
+                )
+            }
+            Self::EgressBackpressureNotAllowed => {
+                write!(
+                    f,
+                    "Request not authorized: only pipelines may read the output of another pipeline with backpressure"
+                )
+            }
+            Self::QuotaExceeded {
+                quota,
+                limit,
+                usage,
+                requested,
+            } => {
+                write!(
+                    f,
+                    "Quota '{quota}' exceeded: {requested} requested, but {usage} of {limit} are in use"
+                )
+            }
+            Self::QuotaViolation { quota, reason } => {
+                write!(f, "Request violates quota '{quota}': {reason}")
+            }
         }
     }
 }
@@ -191,12 +268,20 @@ impl ResponseError for ManagerError {
             Self::MissingUrlEncodedParam { .. } => StatusCode::BAD_REQUEST,
             Self::InvalidUuidParam { .. } => StatusCode::BAD_REQUEST,
             Self::InvalidNameParam { .. } => StatusCode::BAD_REQUEST,
+            Self::InvalidVersionParam { .. } => StatusCode::BAD_REQUEST,
             Self::InvalidPipelineAction { .. } => StatusCode::BAD_REQUEST,
+            Self::InvalidBundle { .. } => StatusCode::BAD_REQUEST,
             Self::DBError { db_error } => db_error.status_code(),
             Self::RunnerError { runner_error } => runner_error.status_code(),
             Self::IoError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Self::InvalidProgramSchema { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Self::RustCompilerError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ApiKeyNotAuthorized { .. } => StatusCode::FORBIDDEN,
+            Self::InsufficientRole { .. } => StatusCode::FORBIDDEN,
+            Self::DatabaseSecretNotAuthorized { .. } => StatusCode::FORBIDDEN,
+            Self::EgressBackpressureNotAllowed => StatusCode::FORBIDDEN,
+            Self::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::QuotaViolation { .. } => StatusCode::FORBIDDEN,
         }
     }
 
@@ -214,12 +299,20 @@ impl DetailedError for ManagerError {
             Self::MissingUrlEncodedParam { .. } => Cow::from("MissingUrlEncodedParam"),
             Self::InvalidUuidParam { .. } => Cow::from("InvalidUuidParam"),
             Self::InvalidNameParam { .. } => Cow::from("InvalidNameParam"),
+            Self::InvalidVersionParam { .. } => Cow::from("InvalidVersionParam"),
             Self::InvalidPipelineAction { .. } => Cow::from("InvalidPipelineAction"),
+            Self::InvalidBundle { .. } => Cow::from("InvalidBundle"),
             Self::DBError { db_error } => db_error.error_code(),
             Self::RunnerError { runner_error } => runner_error.error_code(),
             Self::IoError { .. } => Cow::from("ManagerIoError"),
             Self::InvalidProgramSchema { .. } => Cow::from("InvalidProgramSchema"),
             Self::RustCompilerError { .. } => Cow::from("RustCompilerError"),
+            Self::ApiKeyNotAuthorized { .. } => Cow::from("ApiKeyNotAuthorized"),
+            Self::InsufficientRole { .. } => Cow::from("InsufficientRole"),
+            Self::DatabaseSecretNotAuthorized { .. } => Cow::from("DatabaseSecretNotAuthorized"),
+            Self::EgressBackpressureNotAllowed => Cow::from("EgressBackpressureNotAllowed"),
+            Self::QuotaExceeded { .. } => Cow::from("QuotaExceeded"),
+            Self::QuotaViolation { .. } => Cow::from("QuotaViolation"),
         }
     }
 
@@ -227,6 +320,12 @@ impl DetailedError for ManagerError {
         match self {
             Self::DBError { db_error } => db_error.log_level(),
             Self::RunnerError { runner_error } => runner_error.log_level(),
+            Self::ApiKeyNotAuthorized { .. } => Level::Info,
+            Self::InsufficientRole { .. } => Level::Info,
+            Self::DatabaseSecretNotAuthorized { .. } => Level::Info,
+            Self::EgressBackpressureNotAllowed => Level::Info,
+            Self::QuotaExceeded { .. } => Level::Info,
+            Self::QuotaViolation { .. } => Level::Info,
             _ => Level::Error,
         }
     }
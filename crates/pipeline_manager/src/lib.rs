@@ -12,8 +12,16 @@ pub mod db_notifier;
 pub mod local_runner;
 pub mod logging;
 pub mod metrics;
+mod outbound;
 pub mod pipeline_automata;
+mod pipeline_graph;
+mod pipeline_logs;
 pub mod probe;
 pub mod prober;
+mod quotas;
 pub mod retries;
 pub mod runner;
+mod schedules;
+mod secrets;
+mod validation;
+mod webhooks;
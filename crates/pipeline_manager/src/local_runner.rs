@@ -3,15 +3,30 @@
 use crate::db_notifier::{DbNotification, Operation};
 use crate::pipeline_automata::{fetch_binary_ref, PipelineAutomaton};
 use crate::pipeline_automata::{PipelineExecutionDesc, PipelineExecutor};
+use crate::pipeline_logs::{pipeline_logs, PipelineLog, PipelineLogs};
 use crate::{
     api::ManagerError,
     config::LocalRunnerConfig,
     db::{PipelineId, ProjectDB},
     runner::RunnerError,
 };
+use actix_web::{web, App, HttpServer};
 use async_trait::async_trait;
-use log::trace;
-use std::{collections::BTreeMap, process::Stdio, sync::Arc};
+use chrono::Utc;
+use log::{error, trace, warn};
+use pipeline_types::config::TransportConfig;
+use pipeline_types::secret_ref::{
+    DATABASE_SECRET_ENV_PREFIX, ENV_SECRET_PREFIX, VAULT_PATH_PREFIX_ENV,
+};
+use resources::{is_allocation_failure, PipelineLimits, ResourceLimiter};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    process::Stdio,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
+};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::{Child, Command};
 use tokio::sync::Notify;
 use tokio::{
@@ -21,12 +36,31 @@ use tokio::{
     sync::Mutex,
 };
 
+mod resources;
+
+/// A pipeline process started by the runner.
+struct PipelineProcess {
+    process: Child,
+    /// Resource limits of the process.
+    limits: PipelineLimits,
+    /// Set when the process reports that it failed to allocate memory.
+    allocation_failed: Arc<AtomicBool>,
+}
+
 /// A handle to the pipeline process that kills the pipeline
 /// on `drop`.
 pub struct ProcessRunner {
     pipeline_id: PipelineId,
-    pipeline_process: Option<Child>,
+    pipeline_process: Option<PipelineProcess>,
+    /// The standby process of a pipeline that is being upgraded.
+    standby_process: Option<PipelineProcess>,
+    /// Instance of the pipeline that the pipeline process runs, which
+    /// determines where its files are stored.  The standby process runs
+    /// instance `instance + 1`.
+    instance: u64,
     config: Arc<LocalRunnerConfig>,
+    logs: Arc<PipelineLogs>,
+    limiter: Arc<ResourceLimiter>,
 }
 
 impl Drop for ProcessRunner {
@@ -35,40 +69,85 @@ impl Drop for ProcessRunner {
         // pipeline before destroying the automaton, but we make sure that the
         // pipeline process is still killed on error.  We use `start_kill`
         // to avoid blocking in `drop`.
-        self.pipeline_process.as_mut().map(|p| p.start_kill());
+        for p in [self.pipeline_process.take(), self.standby_process.take()]
+            .into_iter()
+            .flatten()
+        {
+            let mut process = p.process;
+            let _ = process.start_kill();
+            self.limiter.release(p.limits);
+        }
     }
 }
 
 impl ProcessRunner {
+    async fn kill_process(&self, process: Option<PipelineProcess>) {
+        if let Some(mut p) = process {
+            let _ = p.process.kill().await;
+            let _ = p.process.wait().await;
+            self.limiter.release(p.limits);
+        }
+    }
+
     async fn kill_pipeline(&mut self) {
-        if let Some(mut p) = self.pipeline_process.take() {
-            let _ = p.kill().await;
-            let _ = p.wait().await;
+        let process = self.pipeline_process.take();
+        self.kill_process(process).await;
+        self.logs.end_run(self.pipeline_id).await;
+    }
+
+    async fn remove_instance_dir(&self, instance: u64) {
+        let dir = self
+            .config
+            .pipeline_instance_dir(self.pipeline_id, instance);
+        match remove_dir_all(&dir).await {
+            Ok(_) => (),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => {
+                warn!(
+                    "Failed to delete directory '{}' of pipeline {}: {e}",
+                    dir.display(),
+                    self.pipeline_id
+                );
+            }
         }
     }
-}
 
-#[async_trait]
-impl PipelineExecutor for ProcessRunner {
-    async fn start(&mut self, ped: PipelineExecutionDesc) -> Result<(), ManagerError> {
+    /// Starts a process that runs `instance` of the pipeline described by
+    /// `ped`, in standby mode if `standby` is true.
+    async fn spawn_process(
+        &self,
+        mut ped: PipelineExecutionDesc,
+        instance: u64,
+        standby: bool,
+    ) -> Result<PipelineProcess, ManagerError> {
         let pipeline_id = ped.pipeline_id;
         let program_id = ped.program_id;
         let version = ped.version;
 
-        log::debug!("Pipeline config is '{:?}'", ped.config);
-
-        // Create pipeline directory (delete old directory if exists); write metadata
-        // and config files to it.
-        let pipeline_dir = self.config.pipeline_dir(pipeline_id);
-
-        let _ = remove_dir_all(&pipeline_dir).await;
-        create_dir_all(&pipeline_dir).await.map_err(|e| {
+        // Create the directory of the instance and its storage directory;
+        // write metadata and config files to it.  The directory only exists
+        // when the pipeline is restarted after a failure, in which case the
+        // storage of the failed run is kept.
+        let storage_dir = self.config.pipeline_storage_dir(pipeline_id, instance);
+        create_dir_all(&storage_dir).await.map_err(|e| {
             ManagerError::io_error(
-                format!("creating pipeline directory '{}'", pipeline_dir.display()),
+                format!("creating pipeline directory '{}'", storage_dir.display()),
                 e,
             )
         })?;
-        let config_file_path = self.config.config_file_path(pipeline_id);
+        // The port file of the previous run would make the pipeline appear
+        // to be up before it is.
+        let _ = fs::remove_file(self.config.port_file_path(pipeline_id, instance)).await;
+        if ped.config.storage_location.is_none() {
+            ped.config.storage_location = Some(storage_dir.to_string_lossy().into_owned());
+        }
+        // Connectors that read from other pipelines do so through the API.
+        for input in ped.config.inputs.values_mut() {
+            if let TransportConfig::PipelineInput(config) = &mut input.connector_config.transport {
+                config.api_url = Some(self.config.api_server_url.clone());
+            }
+        }
+        let config_file_path = self.config.config_file_path(pipeline_id, instance);
         let expanded_config = serde_yaml::to_string(&ped.config).unwrap();
         fs::write(&config_file_path, &expanded_config)
             .await
@@ -83,29 +162,102 @@ impl PipelineExecutor for ProcessRunner {
             &self.config,
             &ped.binary_ref,
             pipeline_id,
+            instance,
             program_id,
             version,
         )
         .await?;
 
-        // Run executable, set current directory to pipeline directory, pass metadata
-        // file and config as arguments.
-        let pipeline_process = Command::new(fetched_executable)
-            .current_dir(self.config.pipeline_dir(pipeline_id))
+        // Separate the output of this run from previous runs in the log.
+        let log = self.logs.get(pipeline_id).await?;
+        log.lock()
+            .await
+            .append(&format!(
+                "[runner] {}: starting {}pipeline (program version {version})",
+                Utc::now().to_rfc3339(),
+                if standby { "standby " } else { "" }
+            ))
+            .await;
+
+        // The pipeline runs user-supplied code: it only inherits the variables
+        // of the runner that it needs, and gets the database secrets and the
+        // Vault token of its tenant.
+        let mut env = pipeline_env(std::env::vars(), &self.config.pipeline_inherited_env);
+        env.append(&mut ped.secret_env);
+        if let Some(token_path) = self.config.vault_token_path(ped.tenant_id) {
+            match fs::read_to_string(&token_path).await {
+                Ok(token) => {
+                    env.insert("VAULT_TOKEN".to_string(), token.trim().to_string());
+                    env.insert(VAULT_PATH_PREFIX_ENV.to_string(), ped.tenant_id.to_string());
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(ManagerError::io_error(
+                        format!("reading Vault token '{}'", token_path.display()),
+                        e,
+                    ))
+                }
+            }
+        }
+
+        // Run executable, set current directory to the instance directory,
+        // pass metadata file and config as arguments.
+        let mut command = Command::new(fetched_executable);
+        command
+            .env_clear()
+            .envs(env)
+            .current_dir(self.config.pipeline_instance_dir(pipeline_id, instance))
             .arg("--config-file")
             .arg(&config_file_path)
             .stdin(Stdio::null())
-            .spawn()
-            .map_err(|e| RunnerError::PipelineStartupError {
-                pipeline_id,
-                error: e.to_string(),
-            })?;
-        self.pipeline_process = Some(pipeline_process);
-        Ok(())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(flight_port) = ped.config.global.flight_port {
+            command.arg("--flight-port").arg(flight_port.to_string());
+        }
+        if standby {
+            command.arg("--standby");
+        }
+        let limits = self.limiter.apply(
+            pipeline_id,
+            instance,
+            &ped.config.global.resources,
+            ped.config.global.workers,
+            &mut command,
+        )?;
+        let mut process = match command.spawn() {
+            Ok(process) => process,
+            Err(e) => {
+                self.limiter.release(limits);
+                return Err(RunnerError::PipelineStartupError {
+                    pipeline_id,
+                    error: e.to_string(),
+                }
+                .into());
+            }
+        };
+        let allocation_failed = Arc::new(AtomicBool::new(false));
+        if let Some(stdout) = process.stdout.take() {
+            spawn(capture_output(
+                stdout,
+                log.clone(),
+                allocation_failed.clone(),
+            ));
+        }
+        if let Some(stderr) = process.stderr.take() {
+            spawn(capture_output(stderr, log, allocation_failed.clone()));
+        }
+        Ok(PipelineProcess {
+            process,
+            limits,
+            allocation_failed,
+        })
     }
 
-    async fn get_location(&mut self) -> Result<Option<String>, ManagerError> {
-        let port_file_path = self.config.port_file_path(self.pipeline_id);
+    /// Returns the location of the HTTP server of `instance` of the pipeline,
+    /// once it has written its port file.
+    async fn instance_location(&self, instance: u64) -> Result<Option<String>, ManagerError> {
+        let port_file_path = self.config.port_file_path(self.pipeline_id, instance);
         let host = &self.config.pipeline_host;
 
         match fs::read_to_string(port_file_path).await {
@@ -122,16 +274,62 @@ impl PipelineExecutor for ProcessRunner {
             Err(_) => Ok(None),
         }
     }
+}
+
+/// Selects the variables of the runner's environment `vars` that pipelines
+/// inherit: the `inherited` variables and the secrets in variables starting
+/// with [`ENV_SECRET_PREFIX`], except those reserved for database secrets.
+fn pipeline_env(
+    vars: impl Iterator<Item = (String, String)>,
+    inherited: &[String],
+) -> BTreeMap<String, String> {
+    vars.filter(|(name, _)| {
+        inherited.contains(name)
+            || (name.starts_with(ENV_SECRET_PREFIX)
+                && !name.starts_with(DATABASE_SECRET_ENV_PREFIX))
+    })
+    .collect()
+}
+
+#[async_trait]
+impl PipelineExecutor for ProcessRunner {
+    async fn start(&mut self, ped: PipelineExecutionDesc) -> Result<(), ManagerError> {
+        self.pipeline_process = Some(self.spawn_process(ped, self.instance, false).await?);
+        Ok(())
+    }
+
+    async fn get_location(&mut self) -> Result<Option<String>, ManagerError> {
+        self.instance_location(self.instance).await
+    }
 
     async fn check_if_shutdown(&mut self) -> bool {
         self.pipeline_process
             .as_mut()
-            .map(|p| p.try_wait().is_ok())
+            .map(|p| p.process.try_wait().is_ok())
             .unwrap_or(true)
     }
 
+    async fn termination_reason(&mut self) -> Option<RunnerError> {
+        let process = self.pipeline_process.as_ref()?;
+        if process.limits.oom_killed() || process.allocation_failed.load(Ordering::Acquire) {
+            Some(RunnerError::PipelineOutOfMemory {
+                pipeline_id: self.pipeline_id,
+                memory_mb_max: process.limits.memory_mb_max(),
+            })
+        } else {
+            None
+        }
+    }
+
+    async fn kill(&mut self) -> Result<(), ManagerError> {
+        self.kill_pipeline().await;
+        Ok(())
+    }
+
     async fn shutdown(&mut self) -> Result<(), ManagerError> {
         self.kill_pipeline().await;
+        let standby = self.standby_process.take();
+        self.kill_process(standby).await;
         match remove_dir_all(self.config.pipeline_dir(self.pipeline_id)).await {
             Ok(_) => (),
             Err(e) => {
@@ -142,8 +340,74 @@ impl PipelineExecutor for ProcessRunner {
                 );
             }
         }
+        self.instance = 0;
+        Ok(())
+    }
+
+    async fn start_standby(&mut self, ped: PipelineExecutionDesc) -> Result<(), ManagerError> {
+        // The standby rebuilds the state of the pipeline from scratch.
+        self.remove_instance_dir(self.instance + 1).await;
+        self.standby_process = Some(self.spawn_process(ped, self.instance + 1, true).await?);
         Ok(())
     }
+
+    async fn get_standby_location(&mut self) -> Result<Option<String>, ManagerError> {
+        self.instance_location(self.instance + 1).await
+    }
+
+    async fn promote_standby(&mut self) -> Result<(), ManagerError> {
+        let standby = self.standby_process.take().ok_or_else(|| {
+            ManagerError::from(RunnerError::PipelineUpgradeError {
+                pipeline_id: self.pipeline_id,
+                error: "the standby process is not running".to_string(),
+            })
+        })?;
+        let process = self.pipeline_process.replace(standby);
+        self.kill_process(process).await;
+        self.remove_instance_dir(self.instance).await;
+        self.instance += 1;
+        Ok(())
+    }
+
+    async fn kill_standby(&mut self) -> Result<(), ManagerError> {
+        let standby = self.standby_process.take();
+        self.kill_process(standby).await;
+        self.remove_instance_dir(self.instance + 1).await;
+        Ok(())
+    }
+}
+
+/// Copy the output of a pipeline process to the pipeline log line by line.
+///
+/// `allocation_failed` is set if the pipeline reports a failure to allocate
+/// memory.
+async fn capture_output<R>(
+    output: R,
+    log: Arc<Mutex<PipelineLog>>,
+    allocation_failed: Arc<AtomicBool>,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut reader = BufReader::new(output);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buf);
+                let line = line.trim_end_matches(['\n', '\r']);
+                if is_allocation_failure(line) {
+                    allocation_failed.store(true, Ordering::Release);
+                }
+                log.lock().await.append(line).await;
+            }
+            Err(e) => {
+                warn!("Failed to read pipeline output: {e}");
+                break;
+            }
+        }
+    }
 }
 
 /// Starts a runner that executes pipelines locally
@@ -162,14 +426,58 @@ impl PipelineExecutor for ProcessRunner {
 /// To shutdown the pipeline, the runner sends a `/shutdown` HTTP request to the
 /// pipeline.  This request is asynchronous: the pipeline may continue running
 /// for a few seconds after the request succeeds.
+///
+/// # Upgrading a pipeline
+///
+/// To upgrade a running pipeline, the runner starts a standby process of the
+/// new revision next to the pipeline process, with `--standby` and its own
+/// directory.  Once the standby has caught up with the pipeline process, the
+/// runner kills the pipeline process and the standby becomes the pipeline
+/// process.
+///
+/// # Pipeline logs
+///
+/// The runner captures the output of pipeline processes and serves it to the
+/// API server over HTTP on `runner_bind_address:runner_port` (see
+/// [`crate::pipeline_logs`]).
+///
+/// # Resource limits
+///
+/// The runner enforces the resource configuration of pipelines using cgroups
+/// or rlimits.  See the `resources` module for details.  A pipeline that runs out of memory fails
+/// with a [`RunnerError::PipelineOutOfMemory`] error.
 pub async fn run(db: Arc<Mutex<ProjectDB>>, config: &LocalRunnerConfig) {
-    let runner_task = spawn(reconcile(db, Arc::new(config.clone())));
+    let config = Arc::new(config.clone());
+    let logs = Arc::new(PipelineLogs::new(config.clone()));
+    let logs_data = web::Data::from(logs.clone());
+    let db_data = web::Data::from(db.clone());
+    let address = (config.runner_bind_address.clone(), config.runner_port);
+    match HttpServer::new(move || {
+        App::new()
+            .app_data(logs_data.clone())
+            .app_data(db_data.clone())
+            .service(pipeline_logs)
+    })
+    .bind(&address)
+    {
+        Ok(server) => {
+            spawn(server.run());
+        }
+        Err(e) => error!(
+            "Failed to bind the runner HTTP service to {}:{}; pipeline logs will not be available: {e}",
+            address.0, address.1
+        ),
+    }
+    let limiter = Arc::new(ResourceLimiter::new(&config));
+    let runner_task = spawn(reconcile(db, config, logs, limiter));
     runner_task.await.unwrap().unwrap();
 }
 
 async fn reconcile(
     db: Arc<Mutex<ProjectDB>>,
     config: Arc<LocalRunnerConfig>,
+    logs: Arc<PipelineLogs>,
+    limiter: Arc<ResourceLimiter>,
 ) -> Result<(), ManagerError> {
     let pipelines: Mutex<BTreeMap<PipelineId, Arc<Notify>>> = Mutex::new(BTreeMap::new());
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
@@ -189,7 +497,11 @@ async fn reconcile(
                             let pipeline_handle = ProcessRunner {
                                 pipeline_id,
                                 pipeline_process: None,
+                                standby_process: None,
+                                instance: 0,
                                 config: config.clone(),
+                                logs: logs.clone(),
+                                limiter: limiter.clone(),
                             };
                             spawn(
                                 PipelineAutomaton::new(
@@ -210,6 +522,7 @@ async fn reconcile(
                         // Notify the automaton so it shuts down
                         n.notify_one();
                     }
+                    logs.remove(pipeline_id).await;
                 }
             };
         }
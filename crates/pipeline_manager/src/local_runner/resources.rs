@@ -0,0 +1,539 @@
+//! Enforcement of pipeline resource limits by the local runner.
+//!
+//! The runner applies the [`ResourceConfig`] of a pipeline to the pipeline
+//! process as follows:
+//!
+//! * When [`LocalRunnerConfig::pipeline_cgroup`] names a usable cgroup v2
+//!   directory, each pipeline runs in its own child cgroup, `pipeline<id>`.
+//!   `memory_mb_max` and `memory_mb_min` become the cgroup's `memory.max` and
+//!   `memory.low`, `cpu_cores_max` its `cpu.max`, and `cpu_cores_min`
+//!   determines its `cpu.weight`.  A pipeline that exceeds `memory_mb_max` is
+//!   terminated by the kernel OOM killer, which the runner detects through the
+//!   cgroup's `memory.events`.
+//!
+//! * Otherwise, `memory_mb_max` is enforced with `RLIMIT_DATA`: allocations
+//!   beyond the limit fail, which aborts the pipeline with a "memory
+//!   allocation of N bytes failed" message in its log.
+//!
+//! In both cases, the pipeline process, including all its worker threads, is
+//! pinned to `cpu_cores_max` CPUs.  The runner hands out the least used of the
+//! CPUs it may run on itself, so that pipelines are spread across the machine.
+//! `storage_mb_max` is not enforced by the local runner.
+//!
+//! Resource limits are only enforced on Linux.
+use crate::{config::LocalRunnerConfig, db::PipelineId, error::ManagerError};
+use log::{info, warn};
+use pipeline_types::config::ResourceConfig;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::process::Command;
+
+/// Bytes per megabyte, as the unit of memory in [`ResourceConfig`].
+const MIB: u64 = 1024 * 1024;
+
+/// Period of the cgroup CPU bandwidth controller, in microseconds.
+const CPU_PERIOD_USECS: u64 = 100_000;
+
+/// `cpu.weight` of a cgroup per reserved CPU core.  This is the default
+/// weight of a cgroup, so that a pipeline reserving one core competes for CPU
+/// time on equal terms with other processes.
+const CPU_WEIGHT_PER_CORE: u64 = 100;
+
+/// Allocates CPUs to pipelines, preferring the least used ones.
+#[derive(Debug)]
+struct CpuAllocator {
+    /// The CPUs available to pipelines and the number of pipelines pinned to
+    /// each of them.
+    usage: BTreeMap<usize, usize>,
+}
+
+impl CpuAllocator {
+    fn new(cpus: impl IntoIterator<Item = usize>) -> Self {
+        Self {
+            usage: cpus.into_iter().map(|cpu| (cpu, 0)).collect(),
+        }
+    }
+
+    /// Allocates the `n` least used CPUs, or all CPUs if there are fewer than
+    /// `n`, and returns them in ascending order.
+    fn allocate(&mut self, n: usize) -> Vec<usize> {
+        let mut candidates: Vec<(usize, usize)> = self
+            .usage
+            .iter()
+            .map(|(&cpu, &count)| (count, cpu))
+            .collect();
+        candidates.sort_unstable();
+        let mut cpus: Vec<usize> = candidates.into_iter().take(n).map(|(_, cpu)| cpu).collect();
+        cpus.sort_unstable();
+        for cpu in &cpus {
+            *self.usage.get_mut(cpu).unwrap() += 1;
+        }
+        cpus
+    }
+
+    /// Returns CPUs previously returned by [`Self::allocate`].
+    fn release(&mut self, cpus: &[usize]) {
+        for cpu in cpus {
+            if let Some(count) = self.usage.get_mut(cpu) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// Resource limits applied to a running pipeline.
+#[derive(Debug)]
+pub(crate) struct PipelineLimits {
+    /// The pipeline's cgroup, if it runs in one.
+    cgroup: Option<PathBuf>,
+    /// The CPUs the pipeline is pinned to; empty if it is not pinned.
+    cpus: Vec<usize>,
+    memory_mb_max: Option<u64>,
+    /// Number of OOM kills in the pipeline's cgroup when it was started.
+    oom_kills_at_start: u64,
+}
+
+impl PipelineLimits {
+    pub(crate) fn memory_mb_max(&self) -> Option<u64> {
+        self.memory_mb_max
+    }
+
+    /// Returns whether the kernel OOM killer terminated the pipeline for
+    /// exceeding the memory limit of its cgroup.
+    pub(crate) fn oom_killed(&self) -> bool {
+        self.cgroup
+            .as_deref()
+            .map(|cgroup| oom_kills(cgroup) > self.oom_kills_at_start)
+            .unwrap_or(false)
+    }
+}
+
+/// Applies pipeline resource configurations to pipeline processes.
+///
+/// A single limiter is shared by all pipelines of the runner, so that it can
+/// spread them across the available CPUs.
+pub(crate) struct ResourceLimiter {
+    /// Parent of the pipeline cgroups, if cgroups are used.
+    cgroup: Option<PathBuf>,
+    cpus: Mutex<CpuAllocator>,
+}
+
+impl ResourceLimiter {
+    pub(crate) fn new(config: &LocalRunnerConfig) -> Self {
+        let cgroup = config.pipeline_cgroup.as_ref().and_then(|dir| {
+            match enable_controllers(Path::new(dir)) {
+                Ok(()) => {
+                    info!("Enforcing pipeline resource limits with cgroup '{dir}'");
+                    Some(PathBuf::from(dir))
+                }
+                Err(e) => {
+                    warn!(
+                        "Unable to enforce pipeline resource limits with cgroup '{dir}', using rlimits instead: {e}"
+                    );
+                    None
+                }
+            }
+        });
+        Self {
+            cgroup,
+            cpus: Mutex::new(CpuAllocator::new(available_cpus())),
+        }
+    }
+
+    /// Sets up the limits in `resources` for instance `instance` of pipeline
+    /// `pipeline_id` and arranges for `command`, which runs the instance, to
+    /// be subject to them.
+    ///
+    /// The returned limits must be passed to [`Self::release`] once the
+    /// pipeline process has terminated.
+    pub(crate) fn apply(
+        &self,
+        pipeline_id: PipelineId,
+        instance: u64,
+        resources: &ResourceConfig,
+        workers: u16,
+        command: &mut Command,
+    ) -> Result<PipelineLimits, ManagerError> {
+        if !cfg!(target_os = "linux") {
+            if resources != &ResourceConfig::default() {
+                warn!(
+                    "Resource limits of pipeline {pipeline_id} are not enforced on this platform"
+                );
+            }
+            return Ok(PipelineLimits {
+                cgroup: None,
+                cpus: Vec::new(),
+                memory_mb_max: resources.memory_mb_max,
+                oom_kills_at_start: 0,
+            });
+        }
+
+        let cpus = match resources.cpu_cores_max {
+            Some(cores) => self.cpus.lock().unwrap().allocate(cores as usize),
+            None => Vec::new(),
+        };
+        if !cpus.is_empty() && cpus.len() < workers as usize {
+            warn!(
+                "Pipeline {pipeline_id} runs {workers} workers on {} CPUs",
+                cpus.len()
+            );
+        }
+        let mut limits = PipelineLimits {
+            cgroup: None,
+            cpus,
+            memory_mb_max: resources.memory_mb_max,
+            oom_kills_at_start: 0,
+        };
+
+        if let Some(root) = &self.cgroup {
+            let cgroup = root.join(format!("pipeline{pipeline_id}-{instance}"));
+            if let Err(e) = create_cgroup(&cgroup, resources, &limits.cpus) {
+                self.release(limits);
+                return Err(ManagerError::io_error(
+                    format!("configuring cgroup '{}'", cgroup.display()),
+                    e,
+                ));
+            }
+            limits.oom_kills_at_start = oom_kills(&cgroup);
+            limits.cgroup = Some(cgroup);
+        }
+
+        // The memory limit is enforced by the cgroup if there is one.
+        let rlimit_mb = if limits.cgroup.is_none() {
+            resources.memory_mb_max
+        } else {
+            None
+        };
+        if let Err(e) = restrict_process(command, limits.cgroup.as_deref(), &limits.cpus, rlimit_mb)
+        {
+            self.release(limits);
+            return Err(ManagerError::io_error(
+                format!("restricting resources of pipeline {pipeline_id}"),
+                e,
+            ));
+        }
+        Ok(limits)
+    }
+
+    /// Releases the CPUs allocated to a pipeline and removes its cgroup.
+    pub(crate) fn release(&self, limits: PipelineLimits) {
+        self.cpus.lock().unwrap().release(&limits.cpus);
+        if let Some(cgroup) = &limits.cgroup {
+            if let Err(e) = fs::remove_dir(cgroup) {
+                warn!("Failed to remove cgroup '{}': {e}", cgroup.display());
+            }
+        }
+    }
+}
+
+/// Enables the controllers used to limit pipelines in the children of the
+/// cgroup `dir`.
+#[cfg(target_os = "linux")]
+fn enable_controllers(dir: &Path) -> io::Result<()> {
+    if !dir.join("cgroup.controllers").exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "not a cgroup v2 directory",
+        ));
+    }
+    fs::write(dir.join("cgroup.subtree_control"), "+cpu +cpuset +memory")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_controllers(_dir: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cgroups are only supported on Linux",
+    ))
+}
+
+/// Creates the cgroup `dir` configured according to `resources`.
+fn create_cgroup(dir: &Path, resources: &ResourceConfig, cpus: &[usize]) -> io::Result<()> {
+    // Remove the cgroup of a previous run of the pipeline that the runner
+    // failed to clean up, so that none of its settings linger.  This fails if
+    // the cgroup still contains processes, in which case we reuse it.
+    let _ = fs::remove_dir(dir);
+    fs::create_dir_all(dir)?;
+    for (file, value) in cgroup_settings(resources, cpus) {
+        fs::write(dir.join(file), value)?;
+    }
+    Ok(())
+}
+
+/// Returns the cgroup interface files that implement `resources` along with
+/// the values to write to them.
+fn cgroup_settings(resources: &ResourceConfig, cpus: &[usize]) -> Vec<(&'static str, String)> {
+    let mut settings = vec![
+        (
+            "memory.max",
+            match resources.memory_mb_max {
+                Some(mb) => mb.saturating_mul(MIB).to_string(),
+                None => "max".to_string(),
+            },
+        ),
+        (
+            "memory.low",
+            resources
+                .memory_mb_min
+                .map(|mb| mb.saturating_mul(MIB))
+                .unwrap_or(0)
+                .to_string(),
+        ),
+        (
+            "cpu.max",
+            match resources.cpu_cores_max {
+                Some(cores) => format!(
+                    "{} {CPU_PERIOD_USECS}",
+                    cores.saturating_mul(CPU_PERIOD_USECS)
+                ),
+                None => "max".to_string(),
+            },
+        ),
+        (
+            "cpu.weight",
+            resources
+                .cpu_cores_min
+                .map(|cores| cores.saturating_mul(CPU_WEIGHT_PER_CORE).clamp(1, 10_000))
+                .unwrap_or(CPU_WEIGHT_PER_CORE)
+                .to_string(),
+        ),
+    ];
+    if !cpus.is_empty() {
+        settings.push(("cpuset.cpus", cpu_list(cpus)));
+    }
+    settings
+}
+
+/// Formats sorted CPU numbers in the list format of `cpuset.cpus`, e.g.,
+/// `0-3,6`.
+fn cpu_list(cpus: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{first}-{last}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns the number of processes in cgroup `dir` killed by the OOM killer,
+/// or 0 if unknown.
+fn oom_kills(dir: &Path) -> u64 {
+    fs::read_to_string(dir.join("memory.events"))
+        .map(|events| parse_oom_kills(&events))
+        .unwrap_or(0)
+}
+
+/// Extracts the `oom_kill` counter from the contents of `memory.events`.
+fn parse_oom_kills(events: &str) -> u64 {
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Returns whether `line` of pipeline output reports that the pipeline failed
+/// to allocate memory, after which it aborts.
+pub(crate) fn is_allocation_failure(line: &str) -> bool {
+    line.starts_with("memory allocation of ") && line.ends_with(" bytes failed")
+}
+
+/// Returns the CPUs the runner may run on, which are the CPUs available to
+/// pipelines.
+#[cfg(target_os = "linux")]
+fn available_cpus() -> Vec<usize> {
+    // SAFETY: `cpu_set_t` is a plain bit mask, which `sched_getaffinity`
+    // fills in.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            warn!(
+                "Unable to determine available CPUs, pipelines will not be pinned: {}",
+                io::Error::last_os_error()
+            );
+            return Vec::new();
+        }
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+            .collect()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn available_cpus() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Arranges for the process started by `command` to move into `cgroup`, to
+/// be pinned to `cpus` and to be limited to `memory_mb_max` of data.
+#[cfg(target_os = "linux")]
+fn restrict_process(
+    command: &mut Command,
+    cgroup: Option<&Path>,
+    cpus: &[usize],
+    memory_mb_max: Option<u64>,
+) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    // The restrictions are applied by the child process between `fork` and
+    // `exec`, where it must not allocate, so we prepare everything here.
+    let procs = cgroup
+        .map(|dir| CString::new(dir.join("cgroup.procs").as_os_str().as_bytes()))
+        .transpose()?;
+    let affinity = if cpus.is_empty() {
+        None
+    } else {
+        // SAFETY: `cpu_set_t` is a plain bit mask and all CPUs were returned
+        // by `sched_getaffinity`, so they are within its bounds.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &cpu in cpus {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            Some(set)
+        }
+    };
+    let rlimit = memory_mb_max.map(|mb| {
+        let bytes = mb.saturating_mul(MIB);
+        libc::rlimit {
+            rlim_cur: bytes,
+            rlim_max: bytes,
+        }
+    });
+
+    // SAFETY: the closure only makes async-signal-safe system calls.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(procs) = &procs {
+                // Writing 0 to `cgroup.procs` moves the writing process.
+                let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let result = if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                };
+                libc::close(fd);
+                result?;
+            }
+            if let Some(set) = &affinity {
+                if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            // Unlike `RLIMIT_AS`, `RLIMIT_DATA` does not count address space
+            // that is merely reserved, e.g., by the allocator.
+            if let Some(rlimit) = &rlimit {
+                if libc::setrlimit(libc::RLIMIT_DATA, rlimit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn restrict_process(
+    _command: &mut Command,
+    _cgroup: Option<&Path>,
+    _cpus: &[usize],
+    _memory_mb_max: Option<u64>,
+) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cgroup_settings, cpu_list, is_allocation_failure, parse_oom_kills, CpuAllocator};
+    use pipeline_types::config::ResourceConfig;
+
+    #[test]
+    fn cpu_allocation() {
+        let mut allocator = CpuAllocator::new([0, 1, 2, 3]);
+        assert_eq!(allocator.allocate(2), vec![0, 1]);
+        assert_eq!(allocator.allocate(1), vec![2]);
+        // Prefers the least used CPUs.
+        assert_eq!(allocator.allocate(2), vec![0, 3]);
+        // Requests for more CPUs than available get all of them.
+        assert_eq!(allocator.allocate(8), vec![0, 1, 2, 3]);
+
+        allocator.release(&[0, 1]);
+        allocator.release(&[0, 1, 2, 3]);
+        assert_eq!(allocator.allocate(2), vec![0, 1]);
+
+        let mut allocator = CpuAllocator::new([]);
+        assert!(allocator.allocate(2).is_empty());
+    }
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(cpu_list(&[]), "");
+        assert_eq!(cpu_list(&[3]), "3");
+        assert_eq!(cpu_list(&[0, 1, 2, 3, 6, 8, 9]), "0-3,6,8-9");
+    }
+
+    #[test]
+    fn cgroup_values() {
+        let settings = cgroup_settings(&ResourceConfig::default(), &[]);
+        assert_eq!(
+            settings,
+            vec![
+                ("memory.max", "max".to_string()),
+                ("memory.low", "0".to_string()),
+                ("cpu.max", "max".to_string()),
+                ("cpu.weight", "100".to_string()),
+            ]
+        );
+
+        let resources = ResourceConfig {
+            cpu_cores_min: Some(2),
+            cpu_cores_max: Some(4),
+            memory_mb_min: Some(512),
+            memory_mb_max: Some(2048),
+            storage_mb_max: None,
+        };
+        let settings = cgroup_settings(&resources, &[4, 5, 6, 7]);
+        assert_eq!(
+            settings,
+            vec![
+                ("memory.max", "2147483648".to_string()),
+                ("memory.low", "536870912".to_string()),
+                ("cpu.max", "400000 100000".to_string()),
+                ("cpu.weight", "200".to_string()),
+                ("cpuset.cpus", "4-7".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn oom_detection() {
+        let events = "low 0\nhigh 0\nmax 12\noom 3\noom_kill 2\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kills(events), 2);
+        assert_eq!(parse_oom_kills(""), 0);
+
+        assert!(is_allocation_failure(
+            "memory allocation of 1048576 bytes failed"
+        ));
+        assert!(!is_allocation_failure("allocated 1048576 bytes"));
+    }
+}
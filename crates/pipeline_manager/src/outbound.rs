@@ -0,0 +1,143 @@
+//! Checks on the connections that the pipeline manager opens to
+//! user-supplied hosts.
+//!
+//! Dry runs, service probes and webhooks let users make the pipeline manager
+//! connect to hosts of their choice.  Unless an administrator allows a host
+//! explicitly, hosts that resolve to loopback, private, link-local or
+//! otherwise internal addresses are refused, so that users cannot reach
+//! services in the network of the pipeline manager.  HTTP requests are
+//! pinned to the checked addresses and do not follow redirects, so the
+//! checked addresses are the ones that get contacted.
+use reqwest::redirect::Policy;
+use std::net::{IpAddr, SocketAddr};
+use url::Url;
+
+/// Returns true if `ip` is not a globally routable address.
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // Shared address space (100.64.0.0/10).
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local (fc00::/7) and link-local (fe80::/10).
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+/// Resolves `host` and checks that it may be contacted.
+///
+/// Returns the resolved addresses.  Hosts in `allowed_hosts` may resolve to
+/// any address.
+pub(crate) async fn resolve_host(
+    host: &str,
+    port: u16,
+    allowed_hosts: &[String],
+) -> Result<Vec<SocketAddr>, String> {
+    // IPv6 literals are bracketed in URLs but not in socket addresses.
+    let addrs: Vec<SocketAddr> =
+        tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+            .await
+            .map_err(|e| format!("unable to resolve '{host}': {e}"))?
+            .collect();
+    if addrs.is_empty() {
+        return Err(format!("unable to resolve '{host}'"));
+    }
+    if !allowed_hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+        && addrs.iter().any(|addr| is_internal(addr.ip()))
+    {
+        return Err(format!(
+            "host '{host}' resolves to an internal address; an administrator must allow it explicitly"
+        ));
+    }
+    Ok(addrs)
+}
+
+/// Resolves the host of `url` and checks that it may be contacted.
+pub(crate) async fn resolve_url(
+    url: &Url,
+    allowed_hosts: &[String],
+) -> Result<Vec<SocketAddr>, String> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!(
+            "unsupported URL scheme '{}'; expected 'http' or 'https'",
+            url.scheme()
+        ));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("URL '{url}' has no host"))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| format!("URL '{url}' has no port"))?;
+    resolve_host(host, port, allowed_hosts).await
+}
+
+/// Returns a client builder for requests to `url`, which does not follow
+/// redirects and only connects to the addresses checked by [`resolve_url`].
+pub(crate) async fn client_for(
+    url: &Url,
+    allowed_hosts: &[String],
+) -> Result<reqwest::ClientBuilder, String> {
+    let addrs = resolve_url(url, allowed_hosts).await?;
+    let mut builder = reqwest::Client::builder().redirect(Policy::none());
+    if let Some(domain) = url.domain() {
+        builder = builder.resolve_to_addrs(domain, &addrs);
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_internal, resolve_host, resolve_url};
+    use url::Url;
+
+    #[tokio::test]
+    async fn internal_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["8.8.8.8", "2001:4860:4860::8888"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{ip}");
+        }
+
+        let url = Url::parse("http://169.254.169.254/latest/meta-data").unwrap();
+        assert!(resolve_url(&url, &[]).await.is_err());
+        assert!(resolve_url(&url, &["169.254.169.254".to_string()])
+            .await
+            .is_ok());
+        let url = Url::parse("http://[::1]:8080/").unwrap();
+        assert!(resolve_url(&url, &[]).await.is_err());
+        let url = Url::parse("file:///etc/passwd").unwrap();
+        assert!(resolve_url(&url, &[]).await.is_err());
+        assert!(resolve_host("10.0.0.1", 5432, &[]).await.is_err());
+        assert!(resolve_host("8.8.8.8", 5432, &[]).await.is_ok());
+    }
+}
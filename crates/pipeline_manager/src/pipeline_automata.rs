@@ -6,10 +6,15 @@ use crate::{
     auth::TenantId,
     config::LocalRunnerConfig,
     db::{
-        storage::Storage, DBError, PipelineId, PipelineRevision, PipelineRuntimeState,
+        pipeline_event::{PipelineEvent, SYSTEM_ACTOR},
+        storage::Storage,
+        DBError, PipelineEventId, PipelineId, PipelineRevision, PipelineRuntimeState,
         PipelineStatus, ProjectDB,
     },
+    pipeline_graph::{issue_pipeline_input_key, revoke_pipeline_input_key},
     runner::RunnerError,
+    secrets::resolve_database_secrets,
+    webhooks,
 };
 use actix_web::http::{Method, StatusCode};
 use async_trait::async_trait;
@@ -19,10 +24,13 @@ use pipeline_types::config::PipelineConfig;
 use pipeline_types::error::ErrorResponse;
 use serde::Deserialize;
 use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
 use tokio::{fs, sync::Mutex, time::Duration};
 use tokio::{sync::Notify, time::timeout};
+use uuid::Uuid;
 
 /// Trait to be implemented by any pipeline runner. The PipelineAutomaton
 /// invokes these methods per pipeline.
@@ -39,9 +47,36 @@ pub trait PipelineExecutor {
     /// Returns whether the pipeline has been shutdown
     async fn check_if_shutdown(&mut self) -> bool;
 
+    /// Returns the reason why the pipeline was terminated by its execution
+    /// environment, e.g., for exceeding its memory limit, if known.  This
+    /// reason is reported instead of the symptom observed by the automaton
+    /// when the pipeline fails.
+    async fn termination_reason(&mut self) -> Option<RunnerError> {
+        None
+    }
+
     /// Initiates pipeline shutdown (e.g., send a SIGTERM successfully to the
-    /// process)
+    /// process) and deletes the storage of the pipeline.
     async fn shutdown(&mut self) -> Result<(), ManagerError>;
+
+    /// Terminates a failed pipeline that is about to be restarted, keeping
+    /// its storage so that the restarted pipeline can use it.
+    async fn kill(&mut self) -> Result<(), ManagerError>;
+
+    /// Starts a standby instance of the running pipeline that runs `ped`, to
+    /// which the pipeline is being upgraded (e.g., brings up a second process
+    /// that runs the pipeline binary in standby mode, with its own storage).
+    async fn start_standby(&mut self, ped: PipelineExecutionDesc) -> Result<(), ManagerError>;
+
+    /// Like [`Self::get_location`], for the standby instance.
+    async fn get_standby_location(&mut self) -> Result<Option<String>, ManagerError>;
+
+    /// Terminates the running instance of the pipeline and deletes its
+    /// storage, and makes the standby instance the running instance.
+    async fn promote_standby(&mut self) -> Result<(), ManagerError>;
+
+    /// Terminates the standby instance, if any, and deletes its storage.
+    async fn kill_standby(&mut self) -> Result<(), ManagerError>;
 }
 
 /// Pipeline automaton monitors the runtime state of a single pipeline
@@ -57,27 +92,74 @@ where
     pipeline_handle: T,
     db: Arc<Mutex<ProjectDB>>,
     notifier: Arc<Notify>,
+    /// The upgrade of the running pipeline in progress, if any.
+    upgrade: Option<Upgrade>,
+}
+
+/// An upgrade of a running pipeline to a new revision in progress.
+///
+/// The new revision runs in a standby instance of the pipeline, which
+/// replays the input steps recorded by the running instance until it takes
+/// over.
+struct Upgrade {
+    phase: UpgradePhase,
+    /// Time when the upgrade entered `phase`.
+    since: DateTime<Utc>,
+}
+
+enum UpgradePhase {
+    /// Waiting for the HTTP server of the standby to come online.
+    Provisioning,
+    /// Waiting for the standby at `location` to initialize its connectors.
+    Initializing { location: String },
+    /// The standby at `location` replays the input of the running instance.
+    Replaying { location: String },
 }
 
 /// A description of a pipeline to execute
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Clone)]
 pub struct PipelineExecutionDesc {
+    pub tenant_id: TenantId,
     pub pipeline_id: PipelineId,
     pub pipeline_name: String,
     pub program_id: ProgramId,
     pub version: Version,
     pub config: PipelineConfig,
     pub binary_ref: String,
+    /// Environment variables holding the database secrets referenced by
+    /// `config`, which must be passed to the pipeline process.
+    pub secret_env: BTreeMap<String, String>,
 }
 
-fn to_execution_desc(pr: PipelineRevision, binary_ref: String) -> PipelineExecutionDesc {
+impl fmt::Debug for PipelineExecutionDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PipelineExecutionDesc")
+            .field("tenant_id", &self.tenant_id)
+            .field("pipeline_id", &self.pipeline_id)
+            .field("pipeline_name", &self.pipeline_name)
+            .field("program_id", &self.program_id)
+            .field("version", &self.version)
+            .field("config", &self.config)
+            .field("binary_ref", &self.binary_ref)
+            .field("secret_env", &self.secret_env.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn to_execution_desc(
+    tenant_id: TenantId,
+    pr: PipelineRevision,
+    binary_ref: String,
+) -> PipelineExecutionDesc {
     PipelineExecutionDesc {
+        tenant_id,
         pipeline_id: pr.pipeline.pipeline_id,
         pipeline_name: pr.pipeline.name,
         program_id: pr.program.program_id,
         version: pr.program.version,
         config: pr.config,
         binary_ref,
+        secret_env: BTreeMap::new(),
     }
 }
 
@@ -108,6 +190,19 @@ impl<T: PipelineExecutor> PipelineAutomaton<T> {
     /// How often to poll for the pipeline process to exit.
     const SHUTDOWN_POLL_PERIOD: Duration = Duration::from_millis(300);
 
+    /// Time a restarted pipeline must run without failing before its
+    /// restart count is reset.
+    const RESTART_COUNT_RESET_PERIOD: Duration = Duration::from_secs(600);
+
+    /// How often to poll the standby instance of a pipeline that is being
+    /// upgraded.
+    const UPGRADE_POLL_PERIOD: Duration = Duration::from_millis(1_000);
+
+    /// Max number of steps that the standby instance of a pipeline may be
+    /// behind the running instance when it takes over.  The pipeline doesn't
+    /// process new input while the standby replays these steps.
+    const UPGRADE_MAX_LAG: u64 = 10;
+
     /// Create a new PipelineAutomaton for a given pipeline
     pub fn new(
         pipeline_id: PipelineId,
@@ -122,6 +217,7 @@ impl<T: PipelineExecutor> PipelineAutomaton<T> {
             pipeline_handle,
             db,
             notifier,
+            upgrade: None,
         }
     }
 
@@ -161,6 +257,21 @@ impl<T: PipelineExecutor> PipelineAutomaton<T> {
             .get_pipeline_runtime_state_by_id(self.tenant_id, self.pipeline_id)
             .await?;
         drop(db);
+        // The standby instance of a pipeline being upgraded follows the running
+        // instance, so the upgrade fails if the status of the pipeline changes.
+        if self.upgrade.is_some()
+            && !(matches!(
+                pipeline.current_status,
+                PipelineStatus::Running | PipelineStatus::Paused
+            ) && pipeline.desired_status == pipeline.current_status)
+        {
+            self.upgrade = None;
+            self.abort_upgrade(
+                &pipeline,
+                "the status of the pipeline changed before the upgrade completed".to_string(),
+            )
+            .await?;
+        }
         let transition: State = match (pipeline.current_status, pipeline.desired_status) {
             (PipelineStatus::Shutdown, PipelineStatus::Running)
             | (PipelineStatus::Shutdown, PipelineStatus::Paused) => {
@@ -183,14 +294,40 @@ impl<T: PipelineExecutor> PipelineAutomaton<T> {
                     }
                     .into());
                 }
+                let mut execution_desc =
+                    to_execution_desc(self.tenant_id, revision, executable_ref.unwrap());
+                debug!("Pipeline config is '{:?}'", execution_desc.config);
+                // Secrets stored in the database are only accessible to the manager:
+                // pass them to the pipeline in its environment.
+                let mut resolved =
+                    resolve_database_secrets(&db, self.tenant_id, &mut execution_desc.config)
+                        .await
+                        .map(|secret_env| execution_desc.secret_env = secret_env);
+                if resolved.is_ok() {
+                    // Connectors that read from other pipelines authenticate
+                    // with a key that is only valid while this pipeline runs.
+                    resolved = issue_pipeline_input_key(
+                        &db,
+                        self.tenant_id,
+                        pipeline_id,
+                        &mut execution_desc.config,
+                    )
+                    .await
+                    .map_err(|e| e.to_string());
+                }
                 drop(db);
-                let execution_desc = to_execution_desc(revision, executable_ref.unwrap());
 
                 poll_timeout = Self::PROVISIONING_POLL_PERIOD;
                 // This requires start() to be idempotent. If the process crashes after start
                 // is called but before the state machine is correctly updated in the DB, then
                 // on restart, start will be called again
-                match self.pipeline_handle.start(execution_desc).await {
+                let started = match resolved {
+                    Ok(()) => self.pipeline_handle.start(execution_desc).await,
+                    Err(error) => {
+                        Err(RunnerError::PipelineStartupError { pipeline_id, error }.into())
+                    }
+                };
+                match started {
                     Ok(_) => {
                         info!(
                             "Pipeline {} started (Tenant {})",
@@ -400,10 +537,30 @@ impl<T: PipelineExecutor> PipelineAutomaton<T> {
                 let _ = self.pipeline_handle.shutdown().await;
                 State::Transition(PipelineStatus::Shutdown, error)
             }
-            // Steady-state operation.  Periodically poll the pipeline.
-            (PipelineStatus::Running, _)
-            | (PipelineStatus::Paused, _)
-            | (PipelineStatus::Failed, _) => self.probe(&mut pipeline).await?,
+            // The pipeline failed while the user wants it to run.  Restart it
+            // if its restart policy allows it.
+            (PipelineStatus::Failed, PipelineStatus::Running)
+            | (PipelineStatus::Failed, PipelineStatus::Paused) => {
+                self.restart(&mut pipeline, &mut poll_timeout).await?
+            }
+            // Steady-state operation.  Periodically poll the pipeline, and
+            // upgrade it when requested.
+            (PipelineStatus::Running, _) | (PipelineStatus::Paused, _) => {
+                if pipeline.restart_count > 0
+                    && Self::timeout_expired(
+                        pipeline.status_since,
+                        Self::RESTART_COUNT_RESET_PERIOD,
+                    )
+                {
+                    pipeline.restart_count = 0;
+                    self.update_pipeline_runtime_state(&pipeline).await?;
+                }
+                match self.probe(&mut pipeline).await? {
+                    State::Unchanged => self.upgrade(&mut pipeline, &mut poll_timeout).await?,
+                    transition => transition,
+                }
+            }
+            (PipelineStatus::Failed, _) => self.probe(&mut pipeline).await?,
             (PipelineStatus::Shutdown, PipelineStatus::Shutdown) => State::Unchanged,
             _ => {
                 error!(
@@ -413,17 +570,438 @@ impl<T: PipelineExecutor> PipelineAutomaton<T> {
                 State::Unchanged
             }
         };
-        if let State::Transition(new_status, error) = transition {
+        if let State::Transition(new_status, mut error) = transition {
+            if new_status == PipelineStatus::Failed && error.is_some() {
+                if let Some(reason) = self.pipeline_handle.termination_reason().await {
+                    error = Some(reason.into());
+                }
+            }
             debug!(
                 "Pipeline {} current state is changing from {:?} to {:?} (desired: {:?})",
                 self.pipeline_id, pipeline.current_status, new_status, pipeline.desired_status
             );
+            if new_status == PipelineStatus::Shutdown
+                && pipeline.desired_status == PipelineStatus::Shutdown
+            {
+                pipeline.restart_count = 0;
+            }
+            let actor = Self::transition_actor(&pipeline, &error);
+            let from_status = pipeline.current_status;
             pipeline.set_current_status(new_status, error);
             self.update_pipeline_runtime_state(&pipeline).await?;
+            let event = PipelineEvent {
+                pipeline_event_id: PipelineEventId(Uuid::now_v7()),
+                pipeline_id: self.pipeline_id,
+                timestamp: pipeline.status_since,
+                from_status,
+                to_status: new_status,
+                error: pipeline.error.clone(),
+                actor,
+            };
+            let db = self.db.lock().await;
+            self.record_event(&db, &event).await?;
+            if new_status == PipelineStatus::Shutdown {
+                revoke_pipeline_input_key(&db, self.tenant_id, self.pipeline_id).await?;
+                // An upgrade requested before the pipeline stopped doesn't
+                // apply to the next run.
+                db.finish_pipeline_upgrade(self.tenant_id, self.pipeline_id, false)
+                    .await?;
+            }
         }
         Ok(poll_timeout)
     }
 
+    /// Records `event` and queues its delivery to the webhooks of the tenant.
+    async fn record_event(&self, db: &ProjectDB, event: &PipelineEvent) -> Result<(), DBError> {
+        db.new_pipeline_event(self.tenant_id, event).await?;
+        // Failing to notify webhooks must not hold up the pipeline.
+        if let Err(e) = webhooks::queue_pipeline_event(db, self.tenant_id, event).await {
+            error!(
+                "Unable to queue webhook deliveries of pipeline {} event: {e}",
+                self.pipeline_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Advances the upgrade of the running pipeline to the revision requested
+    /// with the `upgrade` action, if any.
+    ///
+    /// The new revision starts in a standby instance of the pipeline, which
+    /// replays the input steps recorded by the running instance without
+    /// producing output.  Once the standby is at most
+    /// [`Self::UPGRADE_MAX_LAG`] steps behind, the running instance is
+    /// terminated and the standby is activated: it replays the remaining
+    /// steps, writes the output that the running instance didn't write, and
+    /// becomes the running instance in the current status of the pipeline.
+    /// An upgrade that fails before the standby takes over leaves the running
+    /// instance alone.
+    async fn upgrade(
+        &mut self,
+        pipeline: &mut PipelineRuntimeState,
+        poll_timeout: &mut Duration,
+    ) -> Result<State, ManagerError> {
+        let Some(upgrade) = self.upgrade.take() else {
+            let revision = self
+                .db
+                .lock()
+                .await
+                .get_pipeline_upgrade(self.tenant_id, self.pipeline_id)
+                .await?;
+            if let Some(revision) = revision {
+                self.start_upgrade(pipeline, revision, poll_timeout).await?;
+            }
+            return Ok(State::Unchanged);
+        };
+
+        let next = match &upgrade.phase {
+            UpgradePhase::Provisioning => match self.pipeline_handle.get_standby_location().await {
+                Ok(Some(location)) => Ok(Some(UpgradePhase::Initializing { location })),
+                Ok(None) if Self::timeout_expired(upgrade.since, Self::PROVISIONING_TIMEOUT) => {
+                    Err(RunnerError::PipelineProvisioningTimeout {
+                        pipeline_id: self.pipeline_id,
+                        timeout: Self::PROVISIONING_TIMEOUT,
+                    }
+                    .to_string())
+                }
+                Ok(None) => Ok(None),
+                Err(e) => Err(e.to_string()),
+            },
+            UpgradePhase::Initializing { location } => {
+                self.initialize_standby(location, upgrade.since).await
+            }
+            UpgradePhase::Replaying { location } => {
+                match self.standby_lag(pipeline, location).await {
+                    Ok(lag) if lag <= Self::UPGRADE_MAX_LAG => {
+                        return self.promote_standby(pipeline, location).await;
+                    }
+                    Ok(_) => Ok(None),
+                    Err(error) => Err(error),
+                }
+            }
+        };
+        match next {
+            Ok(phase) => {
+                self.upgrade = Some(match phase {
+                    Some(phase) => Upgrade {
+                        phase,
+                        since: Utc::now(),
+                    },
+                    None => upgrade,
+                });
+                *poll_timeout = Self::UPGRADE_POLL_PERIOD;
+            }
+            Err(error) => self.abort_upgrade(pipeline, error).await?,
+        }
+        Ok(State::Unchanged)
+    }
+
+    /// Starts a standby instance of the pipeline that runs `revision`.
+    async fn start_upgrade(
+        &mut self,
+        pipeline: &PipelineRuntimeState,
+        revision: PipelineRevision,
+        poll_timeout: &mut Duration,
+    ) -> Result<(), ManagerError> {
+        info!(
+            "Upgrading pipeline {} to revision {} (Tenant {})",
+            self.pipeline_id, revision.revision, self.tenant_id
+        );
+        let db = self.db.lock().await;
+        let executable_ref = db
+            .get_compiled_binary_ref(revision.program.program_id, revision.program.version)
+            .await?;
+        let execution_desc = match executable_ref {
+            None => Err(format!(
+                "Did not receive a compiled binary URL for version {} of the program",
+                revision.program.version
+            )),
+            Some(executable_ref) => {
+                let mut execution_desc =
+                    to_execution_desc(self.tenant_id, revision, executable_ref);
+                // The inputs of a pipeline that can be upgraded are all Kafka
+                // connectors, so the standby doesn't need an input key.
+                resolve_database_secrets(&db, self.tenant_id, &mut execution_desc.config)
+                    .await
+                    .map(|secret_env| {
+                        execution_desc.secret_env = secret_env;
+                        execution_desc
+                    })
+            }
+        };
+        drop(db);
+
+        let started = match execution_desc {
+            Ok(execution_desc) => self
+                .pipeline_handle
+                .start_standby(execution_desc)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(error) => Err(error),
+        };
+        match started {
+            Ok(()) => {
+                self.upgrade = Some(Upgrade {
+                    phase: UpgradePhase::Provisioning,
+                    since: Utc::now(),
+                });
+                *poll_timeout = Self::PROVISIONING_POLL_PERIOD;
+                Ok(())
+            }
+            Err(error) => self.abort_upgrade(pipeline, error).await,
+        }
+    }
+
+    /// Waits for the standby at `location` to initialize, and then starts it
+    /// so that it follows the running instance.
+    async fn initialize_standby(
+        &self,
+        location: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<UpgradePhase>, String> {
+        match pipeline_http_request_json_response(self.pipeline_id, Method::GET, "stats", location)
+            .await
+        {
+            Ok((status, _)) if status.is_success() => {
+                self.pipeline_request(location, "start").await?;
+                Ok(Some(UpgradePhase::Replaying {
+                    location: location.to_string(),
+                }))
+            }
+            Ok((status, body)) if status != StatusCode::SERVICE_UNAVAILABLE => {
+                Err(Self::error_response_from_json(self.pipeline_id, status, &body).message)
+            }
+            _ => {
+                if Self::timeout_expired(since, Self::INITIALIZATION_TIMEOUT) {
+                    Err(RunnerError::PipelineInitializationTimeout {
+                        pipeline_id: self.pipeline_id,
+                        timeout: Self::INITIALIZATION_TIMEOUT,
+                    }
+                    .to_string())
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Returns the number of steps that the standby at `location` is behind
+    /// the running instance of the pipeline.
+    async fn standby_lag(
+        &self,
+        pipeline: &PipelineRuntimeState,
+        location: &str,
+    ) -> Result<u64, String> {
+        let running = self.pipeline_step(&pipeline.location).await?;
+        let standby = self.pipeline_step(location).await?;
+        Ok(running.saturating_sub(standby))
+    }
+
+    /// Returns the number of steps that the pipeline instance at `location`
+    /// has performed.  Fails if the instance or any of its endpoints failed.
+    async fn pipeline_step(&self, location: &str) -> Result<u64, String> {
+        let (status, body) =
+            pipeline_http_request_json_response(self.pipeline_id, Method::GET, "stats", location)
+                .await
+                .map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(Self::error_response_from_json(self.pipeline_id, status, &body).message);
+        }
+        let fatal_error = [&body["inputs"], &body["outputs"]]
+            .into_iter()
+            .filter_map(|endpoints| endpoints.as_array())
+            .flatten()
+            .find_map(|endpoint| {
+                Some(format!(
+                    "endpoint '{}' failed: {}",
+                    endpoint["endpoint_name"].as_str()?,
+                    endpoint["fatal_error"].as_str()?
+                ))
+            });
+        if let Some(error) = fatal_error {
+            return Err(error);
+        }
+        body["global_metrics"]["step"].as_u64().ok_or_else(|| {
+            format!("Pipeline status descriptor doesn't contain a valid 'global_metrics.step' field: '{body}'")
+        })
+    }
+
+    /// Sends a `GET` request to `endpoint` of the pipeline instance at
+    /// `location`.
+    async fn pipeline_request(&self, location: &str, endpoint: &str) -> Result<(), String> {
+        let (status, body) =
+            pipeline_http_request_json_response(self.pipeline_id, Method::GET, endpoint, location)
+                .await
+                .map_err(|e| e.to_string())?;
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(Self::error_response_from_json(self.pipeline_id, status, &body).message)
+        }
+    }
+
+    /// Terminates the running instance of the pipeline and activates the
+    /// standby at `location` in its place.
+    ///
+    /// The pipeline fails if the standby cannot be activated, since the
+    /// running instance is gone by then.
+    async fn promote_standby(
+        &mut self,
+        pipeline: &mut PipelineRuntimeState,
+        location: &str,
+    ) -> Result<State, ManagerError> {
+        info!(
+            "Standby of pipeline {} caught up, taking over (Tenant {})",
+            self.pipeline_id, self.tenant_id
+        );
+        let mut activated = self
+            .pipeline_handle
+            .promote_standby()
+            .await
+            .map_err(|e| e.to_string());
+        if activated.is_ok() {
+            pipeline.set_location(location.to_string());
+            if pipeline.current_status == PipelineStatus::Paused {
+                activated = self.pipeline_request(location, "pause").await;
+            }
+        }
+        if activated.is_ok() {
+            activated = self.pipeline_request(location, "activate").await;
+        }
+        if let Err(error) = activated {
+            // The pipeline restarts on the deployed revision, if at all.
+            self.db
+                .lock()
+                .await
+                .finish_pipeline_upgrade(self.tenant_id, self.pipeline_id, false)
+                .await?;
+            return Ok(State::Transition(
+                PipelineStatus::Failed,
+                Some(
+                    RunnerError::PipelineUpgradeError {
+                        pipeline_id: self.pipeline_id,
+                        error,
+                    }
+                    .into(),
+                ),
+            ));
+        }
+
+        self.update_pipeline_runtime_state(pipeline).await?;
+        let db = self.db.lock().await;
+        db.finish_pipeline_upgrade(self.tenant_id, self.pipeline_id, true)
+            .await?;
+        info!(
+            "Pipeline {} upgraded (Tenant {})",
+            self.pipeline_id, self.tenant_id
+        );
+        let event = PipelineEvent {
+            pipeline_event_id: PipelineEventId(Uuid::now_v7()),
+            pipeline_id: self.pipeline_id,
+            timestamp: Utc::now(),
+            from_status: pipeline.current_status,
+            to_status: pipeline.current_status,
+            error: None,
+            actor: Self::transition_actor(pipeline, &None),
+        };
+        self.record_event(&db, &event).await?;
+        Ok(State::Unchanged)
+    }
+
+    /// Ends a failed upgrade of the pipeline: terminates the standby instance
+    /// and records the failure as an event of the pipeline, which keeps its
+    /// status.
+    async fn abort_upgrade(
+        &mut self,
+        pipeline: &PipelineRuntimeState,
+        error: String,
+    ) -> Result<(), ManagerError> {
+        let error = RunnerError::PipelineUpgradeError {
+            pipeline_id: self.pipeline_id,
+            error,
+        };
+        error!("{error} (Tenant {})", self.tenant_id);
+        if let Err(e) = self.pipeline_handle.kill_standby().await {
+            error!(
+                "Unable to terminate the standby of pipeline {}: {e}",
+                self.pipeline_id
+            );
+        }
+        let db = self.db.lock().await;
+        db.finish_pipeline_upgrade(self.tenant_id, self.pipeline_id, false)
+            .await?;
+        let event = PipelineEvent {
+            pipeline_event_id: PipelineEventId(Uuid::now_v7()),
+            pipeline_id: self.pipeline_id,
+            timestamp: Utc::now(),
+            from_status: pipeline.current_status,
+            to_status: pipeline.current_status,
+            error: Some(ErrorResponse::from_error_nolog(&error)),
+            actor: SYSTEM_ACTOR.to_string(),
+        };
+        self.record_event(&db, &event).await?;
+        Ok(())
+    }
+
+    /// Returns the actor to which a transition of the pipeline is attributed.
+    ///
+    /// Failures and automatic restarts are initiated by the manager; all
+    /// other transitions are caused by the request that set the desired
+    /// status of the pipeline.
+    fn transition_actor(pipeline: &PipelineRuntimeState, error: &Option<ErrorResponse>) -> String {
+        let restart = pipeline.current_status == PipelineStatus::Failed
+            && pipeline.desired_status != PipelineStatus::Shutdown;
+        match &pipeline.desired_status_actor {
+            Some(actor) if error.is_none() && !restart => actor.clone(),
+            _ => SYSTEM_ACTOR.to_string(),
+        }
+    }
+
+    /// Restarts a failed pipeline once the delay required by its restart
+    /// policy has expired, by moving it to the `Shutdown` state, from which
+    /// it is started again with the storage of the failed run.  Pipelines
+    /// that must not be restarted remain `Failed`.
+    async fn restart(
+        &mut self,
+        pipeline: &mut PipelineRuntimeState,
+        poll_timeout: &mut Duration,
+    ) -> Result<State, ManagerError> {
+        let policy = self
+            .db
+            .lock()
+            .await
+            .get_pipeline_deployment(self.tenant_id, self.pipeline_id)
+            .await?
+            .config
+            .global
+            .restart_policy;
+        match policy.restart_delay(pipeline.restart_count) {
+            None => self.probe(pipeline).await,
+            Some(delay) if Self::timeout_expired(pipeline.status_since, delay) => {
+                info!(
+                    "Restarting failed pipeline {} (restart {}) (Tenant {})",
+                    self.pipeline_id,
+                    pipeline.restart_count + 1,
+                    self.tenant_id
+                );
+                // Make sure that the failed pipeline process is gone.
+                let _ = self.pipeline_handle.kill().await;
+                pipeline.restart_count += 1;
+                pipeline.last_failure = pipeline.error.clone();
+                Ok(State::Transition(PipelineStatus::Shutdown, None))
+            }
+            Some(delay) => {
+                let elapsed = (Utc::now() - pipeline.status_since)
+                    .to_std()
+                    .unwrap_or_default();
+                *poll_timeout = delay
+                    .saturating_sub(elapsed)
+                    .min(Self::DEFAULT_PIPELINE_POLL_PERIOD);
+                Ok(State::Unchanged)
+            }
+        }
+    }
+
     async fn probe(&mut self, pipeline: &mut PipelineRuntimeState) -> Result<State, ManagerError> {
         match pipeline_http_request_json_response(
             self.pipeline_id,
@@ -542,6 +1120,7 @@ pub async fn fetch_binary_ref(
     config: &LocalRunnerConfig,
     binary_ref: &str,
     pipeline_id: PipelineId,
+    instance: u64,
     program_id: ProgramId,
     version: Version,
 ) -> Result<String, ManagerError> {
@@ -579,7 +1158,7 @@ pub async fn fetch_binary_ref(
                 Ok(resp) => {
                     let resp = resp.bytes().await.expect("Binary reference should be accessible as bytes");
                     let resp_ref = resp.as_ref();
-                    let path = config.binary_file_path(pipeline_id, program_id, version);
+                    let path = config.binary_file_path(pipeline_id, instance, program_id, version);
                     let mut file = tokio::fs::File::options()
                         .create(true)
                         .truncate(true)
@@ -655,8 +1234,9 @@ mod test {
 
     use crate::compiler::ProgramConfig;
     use crate::config::CompilationProfile;
+    use crate::db::pipeline_event::PipelineEvent;
     use crate::db::storage::Storage;
-    use crate::db::{PipelineId, PipelineStatus, ProjectDB};
+    use crate::db::{PipelineId, PipelineRuntimeState, PipelineStatus, ProjectDB, Revision};
     use crate::pipeline_automata::PipelineAutomaton;
     use crate::{api::ManagerError, auth::TenantRecord};
 
@@ -667,11 +1247,19 @@ mod test {
 
     struct MockPipeline {
         uri: String,
+        /// Whether the pipeline has storage, which is created when it starts
+        /// and deleted when it shuts down.
+        storage: bool,
+        /// Location of the standby instance started by an upgrade.
+        standby_uri: String,
+        /// Whether a standby instance is running.
+        standby: bool,
     }
 
     #[async_trait]
     impl PipelineExecutor for MockPipeline {
         async fn start(&mut self, _ped: PipelineExecutionDesc) -> Result<(), ManagerError> {
+            self.storage = true;
             Ok(())
         }
 
@@ -684,6 +1272,31 @@ mod test {
         }
 
         async fn shutdown(&mut self) -> Result<(), ManagerError> {
+            self.storage = false;
+            Ok(())
+        }
+
+        async fn kill(&mut self) -> Result<(), ManagerError> {
+            Ok(())
+        }
+
+        async fn start_standby(&mut self, _ped: PipelineExecutionDesc) -> Result<(), ManagerError> {
+            self.standby = true;
+            Ok(())
+        }
+
+        async fn get_standby_location(&mut self) -> Result<Option<String>, ManagerError> {
+            Ok(Some(self.standby_uri.clone()))
+        }
+
+        async fn promote_standby(&mut self) -> Result<(), ManagerError> {
+            self.uri = self.standby_uri.clone();
+            self.standby = false;
+            Ok(())
+        }
+
+        async fn kill_standby(&mut self) -> Result<(), ManagerError> {
+            self.standby = false;
             Ok(())
         }
     }
@@ -699,7 +1312,12 @@ mod test {
             self.conn
                 .lock()
                 .await
-                .set_pipeline_desired_status(automaton.tenant_id, automaton.pipeline_id, status)
+                .set_pipeline_desired_status(
+                    automaton.tenant_id,
+                    automaton.pipeline_id,
+                    status,
+                    "user:default",
+                )
                 .await
                 .unwrap();
         }
@@ -716,12 +1334,36 @@ mod test {
             assert_eq!(status, pipeline.current_status);
         }
 
+        async fn runtime_state(&self) -> PipelineRuntimeState {
+            let automaton = &self.automaton;
+            self.conn
+                .lock()
+                .await
+                .get_pipeline_runtime_state_by_id(automaton.tenant_id, automaton.pipeline_id)
+                .await
+                .unwrap()
+        }
+
+        async fn events(&self) -> Vec<PipelineEvent> {
+            let automaton = &self.automaton;
+            self.conn
+                .lock()
+                .await
+                .list_pipeline_events(automaton.tenant_id, automaton.pipeline_id, None)
+                .await
+                .unwrap()
+        }
+
         async fn tick(&mut self) {
             self.automaton.do_run().await.unwrap();
         }
     }
 
-    async fn setup(conn: Arc<Mutex<ProjectDB>>, uri: String) -> AutomatonTest {
+    async fn setup(
+        conn: Arc<Mutex<ProjectDB>>,
+        uri: String,
+        runtime_config: &str,
+    ) -> AutomatonTest {
         // Create some programs and pipelines before listening for changes
         let tenant_id = TenantRecord::default().id;
         let program_id = Uuid::now_v7();
@@ -737,6 +1379,7 @@ mod test {
                 "ignored",
                 &ProgramConfig {
                     profile: CompilationProfile::Unoptimized,
+                    materialized: false,
                 },
                 None,
             )
@@ -766,7 +1409,7 @@ mod test {
             )
             .await
             .unwrap();
-        let rc = RuntimeConfig::from_yaml("");
+        let rc = RuntimeConfig::from_yaml(runtime_config);
         let pipeline_id = Uuid::now_v7();
         let _ = conn
             .lock()
@@ -802,7 +1445,12 @@ mod test {
             tenant_id,
             conn.clone(),
             notifier.clone(),
-            MockPipeline { uri },
+            MockPipeline {
+                uri,
+                storage: false,
+                standby_uri: String::new(),
+                standby: false,
+            },
         );
         AutomatonTest {
             conn: conn.clone(),
@@ -827,7 +1475,7 @@ mod test {
             .await;
 
         let addr = mock_server.address().to_string();
-        let mut test = setup(conn.clone(), addr).await;
+        let mut test = setup(conn.clone(), addr, "").await;
         test.set_desired_state(PipelineStatus::Paused).await;
         test.check_current_state(PipelineStatus::Shutdown).await;
         test.tick().await;
@@ -836,5 +1484,258 @@ mod test {
         test.check_current_state(PipelineStatus::Initializing).await;
         test.tick().await;
         test.check_current_state(PipelineStatus::Paused).await;
+
+        // Every transition is recorded, most recent first.
+        let events = test.events().await;
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.from_status, e.to_status, e.actor.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    PipelineStatus::Initializing,
+                    PipelineStatus::Paused,
+                    "user:default"
+                ),
+                (
+                    PipelineStatus::Provisioning,
+                    PipelineStatus::Initializing,
+                    "user:default"
+                ),
+                (
+                    PipelineStatus::Shutdown,
+                    PipelineStatus::Provisioning,
+                    "user:default"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pipeline_restart() {
+        logging::init_logging("foo".into());
+        let (conn, _temp) = crate::db::test::setup_pg().await;
+        let conn = Arc::new(tokio::sync::Mutex::new(conn));
+        // The pipeline fails to initialize.
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/stats"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(serde_json::json!({
+                "message": "pipeline crashed",
+                "error_code": "Crash",
+                "details": null
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let addr = mock_server.address().to_string();
+        let mut test = setup(
+            conn.clone(),
+            addr,
+            "restart_policy:\n  type: on_failure\n  max_retries: 1\n  delay_secs: 0",
+        )
+        .await;
+        test.set_desired_state(PipelineStatus::Paused).await;
+        for status in [
+            PipelineStatus::Provisioning,
+            PipelineStatus::Initializing,
+            PipelineStatus::Failed,
+        ] {
+            test.tick().await;
+            test.check_current_state(status).await;
+        }
+
+        // The first failure is followed by a restart.
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        test.tick().await;
+        let state = test.runtime_state().await;
+        assert_eq!(state.current_status, PipelineStatus::Shutdown);
+        assert_eq!(state.restart_count, 1);
+        assert_eq!(state.last_failure.unwrap().message, "pipeline crashed");
+        // The restarted pipeline keeps the storage of the failed run.
+        assert!(test.automaton.pipeline_handle.storage);
+        let events = test.events().await;
+        assert_eq!(events[0].to_status, PipelineStatus::Shutdown);
+        assert_eq!(events[0].actor, "system");
+        assert_eq!(events[1].to_status, PipelineStatus::Failed);
+        assert_eq!(
+            events[1].error.as_ref().unwrap().message,
+            "pipeline crashed"
+        );
+        assert_eq!(events[1].actor, "system");
+        for status in [
+            PipelineStatus::Provisioning,
+            PipelineStatus::Initializing,
+            PipelineStatus::Failed,
+        ] {
+            test.tick().await;
+            test.check_current_state(status).await;
+        }
+
+        // The pipeline ran out of retries.
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        test.tick().await;
+        test.check_current_state(PipelineStatus::Failed).await;
+        assert_eq!(test.runtime_state().await.restart_count, 1);
+
+        // Shutting the pipeline down resets the restart count.
+        test.set_desired_state(PipelineStatus::Shutdown).await;
+        test.tick().await;
+        let state = test.runtime_state().await;
+        assert_eq!(state.current_status, PipelineStatus::Shutdown);
+        assert_eq!(state.restart_count, 0);
+        assert!(state.last_failure.is_some());
+        assert!(!test.automaton.pipeline_handle.storage);
+    }
+
+    /// Mounts the endpoints of a pipeline instance that reports `state` and
+    /// `step` on `server`.
+    async fn mock_instance(server: &MockServer, state: &str, step: u64) {
+        Mock::given(method("GET"))
+            .and(path("/stats"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "global_metrics": { "state": state, "step": step },
+                "inputs": [],
+                "outputs": [],
+            })))
+            .mount(server)
+            .await;
+        for endpoint in ["/start", "/pause", "/activate"] {
+            Mock::given(method("GET"))
+                .and(path(endpoint))
+                .respond_with(ResponseTemplate::new(200).set_body_json("ok"))
+                .mount(server)
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_upgrade() {
+        logging::init_logging("foo".into());
+        let (conn, _temp) = crate::db::test::setup_pg().await;
+        let conn = Arc::new(tokio::sync::Mutex::new(conn));
+        let running = MockServer::start().await;
+        mock_instance(&running, "Paused", 5).await;
+        let standby = MockServer::start().await;
+        mock_instance(&standby, "Paused", 5).await;
+
+        let mut test = setup(conn.clone(), running.address().to_string(), "").await;
+        test.automaton.pipeline_handle.standby_uri = standby.address().to_string();
+        test.set_desired_state(PipelineStatus::Paused).await;
+        for _ in 0..3 {
+            test.tick().await;
+        }
+        test.check_current_state(PipelineStatus::Paused).await;
+
+        let (tenant_id, pipeline_id) = (test.automaton.tenant_id, test.automaton.pipeline_id);
+        let deployed = conn
+            .lock()
+            .await
+            .get_pipeline_deployment(tenant_id, pipeline_id)
+            .await
+            .unwrap();
+        let mut upgrade = deployed.clone();
+        upgrade.revision = Revision(Uuid::now_v7());
+        conn.lock()
+            .await
+            .request_pipeline_upgrade(tenant_id, pipeline_id, &upgrade, "user:admin")
+            .await
+            .unwrap();
+
+        // The standby starts, initializes and replays the input of the
+        // running instance.
+        test.tick().await;
+        assert!(test.automaton.pipeline_handle.standby);
+        test.tick().await;
+        test.tick().await;
+        assert_eq!(
+            test.runtime_state().await.location,
+            running.address().to_string()
+        );
+
+        // It caught up, so it takes over.
+        test.tick().await;
+        let state = test.runtime_state().await;
+        assert_eq!(state.current_status, PipelineStatus::Paused);
+        assert_eq!(state.location, standby.address().to_string());
+        assert!(!test.automaton.pipeline_handle.standby);
+        let requests = standby.received_requests().await.unwrap();
+        let paths: Vec<&str> = requests
+            .iter()
+            .map(|request| request.url.path())
+            .filter(|path| *path != "/stats")
+            .collect();
+        assert_eq!(paths, vec!["/start", "/pause", "/activate"]);
+        let db = conn.lock().await;
+        assert_eq!(
+            db.get_pipeline_deployment(tenant_id, pipeline_id)
+                .await
+                .unwrap(),
+            upgrade
+        );
+        assert_eq!(
+            db.get_pipeline_upgrade(tenant_id, pipeline_id)
+                .await
+                .unwrap(),
+            None
+        );
+        drop(db);
+        let events = test.events().await;
+        assert_eq!(events[0].from_status, PipelineStatus::Paused);
+        assert_eq!(events[0].to_status, PipelineStatus::Paused);
+        assert_eq!(events[0].error, None);
+        assert_eq!(events[0].actor, "user:admin");
+
+        // A standby that fails is terminated and leaves the running instance
+        // alone.
+        let failing = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/stats"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(serde_json::json!({
+                "message": "standby crashed",
+                "error_code": "Crash",
+                "details": null
+            })))
+            .mount(&failing)
+            .await;
+        test.automaton.pipeline_handle.standby_uri = failing.address().to_string();
+        let mut second = upgrade.clone();
+        second.revision = Revision(Uuid::now_v7());
+        conn.lock()
+            .await
+            .request_pipeline_upgrade(tenant_id, pipeline_id, &second, "user:admin")
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            test.tick().await;
+        }
+        assert!(!test.automaton.pipeline_handle.standby);
+        let state = test.runtime_state().await;
+        assert_eq!(state.current_status, PipelineStatus::Paused);
+        assert_eq!(state.location, standby.address().to_string());
+        let db = conn.lock().await;
+        assert_eq!(
+            db.get_pipeline_deployment(tenant_id, pipeline_id)
+                .await
+                .unwrap(),
+            upgrade
+        );
+        assert_eq!(
+            db.get_pipeline_upgrade(tenant_id, pipeline_id)
+                .await
+                .unwrap(),
+            None
+        );
+        drop(db);
+        let events = test.events().await;
+        assert_eq!(events[0].to_status, PipelineStatus::Paused);
+        assert!(events[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .message
+            .contains("standby crashed"));
+        assert_eq!(events[0].actor, "system");
     }
 }
@@ -0,0 +1,183 @@
+//! Pipelines that read from other pipelines.
+//!
+//! A `pipeline_input` connector reads the changes to a view of another
+//! pipeline of the same tenant through the `/egress` endpoint of the API.
+//! The manager issues each pipeline with such connectors an API key that
+//! only allows reading from the pipelines it depends on when it starts the
+//! pipeline, and revokes the key when the pipeline shuts down.  Only these
+//! keys may read from the `/egress` endpoint with backpressure, which lets
+//! the reader stall the pipeline it reads from.
+use crate::auth::{generate_api_key, Actor, TenantId};
+use crate::db::{
+    audit::Role, storage::Storage, ApiAction, ApiKeyScope, ApiPermission, ApiResource, DBError,
+    PipelineId, PipelineStatus, ProjectDB,
+};
+use pipeline_types::config::{PipelineConfig, TransportConfig};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A pipeline in the dependency graph.
+#[derive(Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PipelineNode {
+    /// Pipeline name.
+    pub name: String,
+    /// Current status of the pipeline.
+    pub current_status: PipelineStatus,
+}
+
+/// An input connector of a pipeline that reads a view of another pipeline.
+#[derive(Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PipelineDependency {
+    /// Pipeline that the connector is attached to.
+    pub pipeline: String,
+    /// Name of the attached connector.
+    pub connector: String,
+    /// Table of `pipeline` that the connector feeds.
+    pub table: String,
+    /// Pipeline that the connector reads from.  The pipeline may no longer
+    /// exist, in which case it is not in the list of nodes.
+    pub upstream: String,
+    /// View of `upstream` that the connector reads.
+    pub view: String,
+}
+
+/// Graph of the pipelines of a tenant, with an edge for each connector of a
+/// pipeline that reads from another pipeline.
+#[derive(Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PipelineGraph {
+    pub pipelines: Vec<PipelineNode>,
+    pub dependencies: Vec<PipelineDependency>,
+}
+
+/// Builds the dependency graph of the pipelines of a tenant.
+pub(crate) async fn pipeline_graph(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+) -> Result<PipelineGraph, DBError> {
+    let pipelines = db.list_pipelines(tenant_id).await?;
+    let connectors = db.list_connectors(tenant_id).await?;
+
+    let mut dependencies = Vec::new();
+    for pipeline in &pipelines {
+        for ac in pipeline.descriptor.attached_connectors.iter() {
+            if !ac.is_input {
+                continue;
+            }
+            let Some(connector) = connectors.iter().find(|c| c.name == ac.connector_name) else {
+                continue;
+            };
+            if let TransportConfig::PipelineInput(config) = &connector.config.transport {
+                dependencies.push(PipelineDependency {
+                    pipeline: pipeline.descriptor.name.clone(),
+                    connector: ac.name.clone(),
+                    table: ac.relation_name.clone(),
+                    upstream: config.pipeline.clone(),
+                    view: config.view.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(PipelineGraph {
+        pipelines: pipelines
+            .into_iter()
+            .map(|p| PipelineNode {
+                name: p.descriptor.name,
+                current_status: p.state.current_status,
+            })
+            .collect(),
+        dependencies,
+    })
+}
+
+/// Prefix of the names of the API keys issued to pipelines.  Users cannot
+/// create API keys with such names.
+pub(crate) const PIPELINE_INPUT_KEY_PREFIX: &str = "pipeline-input-";
+
+/// Name of the API key issued to pipeline `pipeline_id`.
+fn api_key_name(pipeline_id: PipelineId) -> String {
+    format!("{PIPELINE_INPUT_KEY_PREFIX}{pipeline_id}")
+}
+
+/// Whether `actor` authenticated with an API key issued to a pipeline.
+pub(crate) fn is_pipeline_input_key(actor: &Actor) -> bool {
+    actor
+        .0
+        .strip_prefix("api_key:")
+        .is_some_and(|name| name.starts_with(PIPELINE_INPUT_KEY_PREFIX))
+}
+
+/// Issues an API key that allows the `pipeline_input` connectors in `config`
+/// to read from the pipelines they depend on, and passes the key to the
+/// connectors.
+///
+/// The key replaces the one issued when the pipeline was last started, if
+/// any.  Does nothing if the pipeline has no `pipeline_input` connectors.
+pub(crate) async fn issue_pipeline_input_key(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_id: PipelineId,
+    config: &mut PipelineConfig,
+) -> Result<(), DBError> {
+    let upstream: BTreeSet<String> = config
+        .inputs
+        .values()
+        .filter_map(|input| match &input.connector_config.transport {
+            TransportConfig::PipelineInput(config) => Some(config.pipeline.clone()),
+            _ => None,
+        })
+        .collect();
+    if upstream.is_empty() {
+        return Ok(());
+    }
+
+    revoke_pipeline_input_key(db, tenant_id, pipeline_id).await?;
+    let api_key = generate_api_key();
+    let mut scopes = Vec::new();
+    for pipeline in upstream {
+        let upstream_id = db
+            .get_pipeline_descr_by_name(tenant_id, &pipeline, None)
+            .await?
+            .pipeline_id;
+        scopes.push(ApiKeyScope {
+            resource: ApiResource::Pipeline(upstream_id),
+            actions: vec![ApiAction::Egress],
+        });
+    }
+    db.store_api_key_hash(
+        tenant_id,
+        Uuid::now_v7(),
+        &api_key_name(pipeline_id),
+        &api_key,
+        vec![ApiPermission::Write],
+        scopes,
+        None,
+        Role::Viewer,
+    )
+    .await?;
+
+    for input in config.inputs.values_mut() {
+        if let TransportConfig::PipelineInput(config) = &mut input.connector_config.transport {
+            config.api_key = Some(api_key.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Revokes the API key issued to pipeline `pipeline_id` by
+/// [`issue_pipeline_input_key`], if any.
+pub(crate) async fn revoke_pipeline_input_key(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline_id: PipelineId,
+) -> Result<(), DBError> {
+    match db
+        .delete_api_key(tenant_id, &api_key_name(pipeline_id))
+        .await
+    {
+        Ok(()) | Err(DBError::UnknownApiKey { .. }) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,349 @@
+//! Capture and retention of pipeline process output.
+//!
+//! The local runner appends the stdout and stderr of each pipeline process
+//! to a per-pipeline log file, which is preserved across pipeline runs so that
+//! the output of a `Failed` pipeline can be inspected after the fact.  When
+//! the log file exceeds the configured size, it is rotated, keeping at most one
+//! rotated file.
+//!
+//! The runner serves the logs via `GET /logs/{tenant_id}/{pipeline_id}` to
+//! the API server, which authenticates the client and resolves the pipeline
+//! name within the client's tenant before forwarding the request as part of
+//! `GET /v0/pipelines/{pipeline_name}/logs`.  The runner's service is not
+//! authenticated and binds to `runner_bind_address`, which should not be
+//! reachable by anyone but the API server.
+
+use crate::{
+    api::ManagerError,
+    auth::TenantId,
+    config::LocalRunnerConfig,
+    db::{storage::Storage, PipelineId, ProjectDB},
+};
+use actix_web::{
+    get,
+    http::header::ContentType,
+    web::{self, Bytes},
+    Error as ActixError, HttpRequest, HttpResponse,
+};
+use futures_util::{stream, StreamExt};
+use log::warn;
+use serde::Deserialize;
+use std::{collections::BTreeMap, io, path::PathBuf, sync::Arc};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{
+        broadcast::{self, error::RecvError},
+        Mutex,
+    },
+};
+use uuid::Uuid;
+
+/// Name of the current log file in the pipeline log directory.
+const LOG_FILE: &str = "pipeline.log";
+
+/// Name of the rotated log file in the pipeline log directory.
+const ROTATED_LOG_FILE: &str = "pipeline.log.1";
+
+/// Number of log lines buffered for each client following the log before
+/// the client starts missing lines.
+const FOLLOW_BUFFER_LINES: usize = 4096;
+
+/// Log of a single pipeline.
+pub(crate) struct PipelineLog {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    /// Forwards new lines to clients following the log.  Replaced at the end
+    /// of each pipeline run, which ends the streams of these clients.
+    sender: broadcast::Sender<String>,
+}
+
+impl PipelineLog {
+    async fn open(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir).await?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE))
+            .await?;
+        let size = file.metadata().await?.len();
+        Ok(Self {
+            dir,
+            file,
+            size,
+            max_bytes,
+            sender: broadcast::channel(FOLLOW_BUFFER_LINES).0,
+        })
+    }
+
+    /// Append `line` to the log and forward it to clients following the log.
+    pub(crate) async fn append(&mut self, line: &str) {
+        let line = format!("{line}\n");
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            if let Err(e) = self.rotate().await {
+                warn!("Failed to rotate log in '{}': {e}", self.dir.display());
+            }
+        }
+        match self.file.write_all(line.as_bytes()).await {
+            Ok(()) => self.size += line.len() as u64,
+            Err(e) => warn!("Failed to write log in '{}': {e}", self.dir.display()),
+        }
+        // A failure simply means that nobody is following the log.
+        let _ = self.sender.send(line);
+    }
+
+    /// End the streams of clients following the log, once they have received
+    /// the lines logged so far.  Called when the pipeline process terminates.
+    pub(crate) fn end_run(&mut self) {
+        self.sender = broadcast::channel(FOLLOW_BUFFER_LINES).0;
+    }
+
+    /// Replace the rotated log file with the current one and start a new
+    /// current log file.
+    async fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(self.dir.join(LOG_FILE), self.dir.join(ROTATED_LOG_FILE)).await?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(self.dir.join(LOG_FILE))
+            .await?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Returns the retained contents of the log, limited to the last `tail`
+    /// lines if specified.
+    async fn read(&self, tail: Option<usize>) -> io::Result<String> {
+        let mut contents = Vec::new();
+        for name in [ROTATED_LOG_FILE, LOG_FILE] {
+            match fs::read(self.dir.join(name)).await {
+                Ok(bytes) => contents.extend_from_slice(&bytes),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let contents = String::from_utf8_lossy(&contents);
+        Ok(match tail {
+            Some(tail) => tail_lines(&contents, tail).to_string(),
+            None => contents.into_owned(),
+        })
+    }
+}
+
+/// Returns the suffix of `contents` consisting of the last `n` lines.
+fn tail_lines(contents: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    let body = contents.strip_suffix('\n').unwrap_or(contents);
+    match body.rmatch_indices('\n').nth(n - 1) {
+        Some((pos, _)) => &contents[pos + 1..],
+        None => contents,
+    }
+}
+
+/// Logs of all pipelines managed by the local runner.
+pub(crate) struct PipelineLogs {
+    config: Arc<LocalRunnerConfig>,
+    logs: Mutex<BTreeMap<PipelineId, Arc<Mutex<PipelineLog>>>>,
+}
+
+impl PipelineLogs {
+    pub(crate) fn new(config: Arc<LocalRunnerConfig>) -> Self {
+        Self {
+            config,
+            logs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the log of `pipeline_id`, opening or creating the log file
+    /// if necessary.
+    pub(crate) async fn get(
+        &self,
+        pipeline_id: PipelineId,
+    ) -> Result<Arc<Mutex<PipelineLog>>, ManagerError> {
+        let mut logs = self.logs.lock().await;
+        if let Some(log) = logs.get(&pipeline_id) {
+            return Ok(log.clone());
+        }
+
+        let dir = self.config.pipeline_log_dir(pipeline_id);
+        let log = PipelineLog::open(dir.clone(), self.config.pipeline_log_max_bytes)
+            .await
+            .map_err(|e| {
+                ManagerError::io_error(format!("opening pipeline log in '{}'", dir.display()), e)
+            })?;
+        let log = Arc::new(Mutex::new(log));
+        logs.insert(pipeline_id, log.clone());
+        Ok(log)
+    }
+
+    /// End the streams of clients following the log of `pipeline_id`.
+    pub(crate) async fn end_run(&self, pipeline_id: PipelineId) {
+        let log = self.logs.lock().await.get(&pipeline_id).cloned();
+        if let Some(log) = log {
+            log.lock().await.end_run();
+        }
+    }
+
+    /// Delete the logs of a pipeline that no longer exists.
+    pub(crate) async fn remove(&self, pipeline_id: PipelineId) {
+        // Dropping the log ends the streams of clients following it.
+        self.logs.lock().await.remove(&pipeline_id);
+        let dir = self.config.pipeline_log_dir(pipeline_id);
+        if let Err(e) = fs::remove_dir_all(&dir).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "Failed to delete pipeline log directory '{}': {e}",
+                    dir.display()
+                );
+            }
+        }
+    }
+}
+
+/// URL-encoded arguments to the `/logs/{tenant_id}/{pipeline_id}` endpoint.
+#[derive(Debug, Deserialize)]
+pub(crate) struct LogsArgs {
+    /// Only return the last `tail` lines of the log.
+    #[serde(default)]
+    pub tail: Option<usize>,
+
+    /// Keep the connection open and stream new log lines as they are
+    /// produced, until the pipeline process terminates.
+    #[serde(default)]
+    pub follow: bool,
+}
+
+/// Parse the UUID in URL-encoded parameter `param`.
+fn parse_uuid_param(req: &HttpRequest, param: &'static str) -> Result<Uuid, ManagerError> {
+    match req.match_info().get(param) {
+        None => Err(ManagerError::MissingUrlEncodedParam { param }),
+        Some(id) => id
+            .parse::<Uuid>()
+            .map_err(|e| ManagerError::InvalidUuidParam {
+                value: id.to_string(),
+                error: e.to_string(),
+            }),
+    }
+}
+
+/// Retrieve the log of a pipeline.
+#[get("/logs/{tenant_id}/{pipeline_id}")]
+pub(crate) async fn pipeline_logs(
+    logs: web::Data<PipelineLogs>,
+    db: web::Data<Mutex<ProjectDB>>,
+    req: HttpRequest,
+    args: web::Query<LogsArgs>,
+) -> Result<HttpResponse, ManagerError> {
+    let tenant_id = TenantId(parse_uuid_param(&req, "tenant_id")?);
+    let pipeline_id = PipelineId(parse_uuid_param(&req, "pipeline_id")?);
+
+    // Only open logs of existing pipelines of the tenant, so that requests
+    // for arbitrary ids don't create log directories.
+    db.lock()
+        .await
+        .get_pipeline_descr_by_id(tenant_id, pipeline_id, None)
+        .await?;
+    let log = logs.get(pipeline_id).await?;
+
+    // Read the log and subscribe to new lines atomically, so that followers
+    // neither miss nor duplicate lines.
+    let (contents, receiver) = {
+        let log = log.lock().await;
+        let contents = log.read(args.tail).await.map_err(|e| {
+            ManagerError::io_error(format!("reading log of pipeline {pipeline_id}"), e)
+        })?;
+        (contents, args.follow.then(|| log.sender.subscribe()))
+    };
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header(ContentType::plaintext());
+
+    let Some(receiver) = receiver else {
+        return Ok(response.body(contents));
+    };
+
+    let updates = stream::unfold(receiver, |mut receiver| async move {
+        let chunk = match receiver.recv().await {
+            Ok(line) => line,
+            Err(RecvError::Lagged(skipped)) => format!("[{skipped} log lines skipped]\n"),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok::<_, ActixError>(Bytes::from(chunk)), receiver))
+    });
+    Ok(response.streaming(
+        stream::once(async move { Ok::<_, ActixError>(Bytes::from(contents)) }).chain(updates),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tail_lines, PipelineLog, LOG_FILE, ROTATED_LOG_FILE};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_tail_lines() {
+        let contents = "a\nb\nc\n";
+        assert_eq!(tail_lines(contents, 0), "");
+        assert_eq!(tail_lines(contents, 1), "c\n");
+        assert_eq!(tail_lines(contents, 2), "b\nc\n");
+        assert_eq!(tail_lines(contents, 3), "a\nb\nc\n");
+        assert_eq!(tail_lines(contents, 10), "a\nb\nc\n");
+        assert_eq!(tail_lines("a\nb", 1), "b");
+    }
+
+    #[tokio::test]
+    async fn test_log_rotation() {
+        let dir = TempDir::new().unwrap();
+
+        // Each line takes 6 bytes including the newline.
+        let mut log = PipelineLog::open(dir.path().to_path_buf(), 12)
+            .await
+            .unwrap();
+        for line in ["run 1", "line1", "line2"] {
+            log.append(line).await;
+        }
+        assert_eq!(log.read(None).await.unwrap(), "run 1\nline1\nline2\n");
+
+        // Reopening the log preserves previous contents.
+        drop(log);
+        let mut log = PipelineLog::open(dir.path().to_path_buf(), 12)
+            .await
+            .unwrap();
+        let mut receiver = log.sender.subscribe();
+        log.append("run 2").await;
+        assert_eq!(receiver.try_recv().unwrap(), "run 2\n");
+        assert_eq!(
+            log.read(None).await.unwrap(),
+            "run 1\nline1\nline2\nrun 2\n"
+        );
+
+        // Only the current and one rotated file are retained.
+        log.append("line3").await;
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(ROTATED_LOG_FILE)).unwrap(),
+            "line2\nrun 2\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(LOG_FILE)).unwrap(),
+            "line3\n"
+        );
+        assert_eq!(log.read(None).await.unwrap(), "line2\nrun 2\nline3\n");
+        assert_eq!(log.read(Some(1)).await.unwrap(), "line3\n");
+
+        // Followers are disconnected at the end of the run, after receiving
+        // the remaining lines.
+        let mut receiver = log.sender.subscribe();
+        log.append("line4").await;
+        log.end_run();
+        assert_eq!(receiver.recv().await.unwrap(), "line4\n");
+        assert!(matches!(
+            receiver.recv().await,
+            Err(tokio::sync::broadcast::error::RecvError::Closed)
+        ));
+    }
+}
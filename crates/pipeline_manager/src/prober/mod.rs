@@ -5,7 +5,8 @@ use crate::api::ManagerError;
 use crate::config::ProberConfig;
 use crate::db::storage::Storage;
 use crate::db::{DBError, ProjectDB};
-use crate::prober::service::probe_service;
+use crate::prober::service::{probe_service, ServiceProbeError, ServiceProbeResponse};
+use crate::secrets::resolve_service_secrets;
 use actix_web::{get, web, HttpServer, Responder};
 use log::{debug, error, info};
 use std::sync::Arc;
@@ -40,7 +41,7 @@ async fn service_probing_task(
                 if probe.is_none() {
                     break;
                 }
-                let (service_probe_id, tenant_id, request, service_config) = probe.unwrap();
+                let (service_probe_id, tenant_id, request, mut service_config) = probe.unwrap();
                 info!("Probe: {}", service_probe_id);
 
                 // Store in database the probe is started
@@ -52,11 +53,21 @@ async fn service_probing_task(
 
                 // Perform the probe with timeout
                 debug!("Probe request: {:?}", &request);
-                let response = probe_service(
-                    &service_config,
-                    request,
-                    Duration::from_millis(config.probe_timeout_ms),
-                );
+                let resolved =
+                    resolve_service_secrets(&*db.lock().await, tenant_id, &mut service_config)
+                        .await;
+                let response = match resolved {
+                    Ok(()) => {
+                        probe_service(
+                            &service_config,
+                            request,
+                            Duration::from_millis(config.probe_timeout_ms),
+                            &config.probe_allowed_hosts,
+                        )
+                        .await
+                    }
+                    Err(e) => ServiceProbeResponse::Error(ServiceProbeError::Other(e)),
+                };
                 let finished_at = chrono::offset::Utc::now();
                 debug!("Probe response: {:?}", &response);
 
@@ -0,0 +1,149 @@
+use crate::outbound;
+use crate::prober::service::{
+    unsupported_request, ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse,
+    ServiceProbeResult,
+};
+use pipeline_types::service::HttpService;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::BTreeMap;
+use url::Url;
+
+/// Convert the headers of the service configuration into a header map.
+fn create_headers(http_service: &HttpService) -> Result<HeaderMap, ServiceProbeError> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &http_service.headers {
+        let name = HeaderName::try_from(name.as_str())
+            .map_err(|e| ServiceProbeError::Other(format!("invalid header name '{name}': {e}")))?;
+        let value = HeaderValue::try_from(value.as_str()).map_err(|e| {
+            ServiceProbeError::Other(format!("invalid value of header '{name}': {e}"))
+        })?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+/// Send a HEAD request to the URL of the service.
+async fn head(
+    http_service: &HttpService,
+    allowed_hosts: &[String],
+) -> Result<reqwest::Response, ServiceProbeError> {
+    let headers = create_headers(http_service)?;
+    let url = Url::parse(&http_service.url)
+        .map_err(|e| ServiceProbeError::Other(format!("invalid URL: {e}")))?;
+    outbound::client_for(&url, allowed_hosts)
+        .await
+        .map_err(ServiceProbeError::Other)?
+        .build()
+        .map_err(|e| ServiceProbeError::Other(e.to_string()))?
+        .head(url)
+        .headers(headers)
+        .send()
+        .await
+        .map_err(|e| ServiceProbeError::Other(e.to_string()))
+}
+
+/// Perform a probe for the HTTP service.
+///
+/// Any response to a HEAD request, regardless of its status code, counts as
+/// connectivity: the status code is reported by `HttpHead`.
+pub async fn probe_http_service(
+    http_service: &HttpService,
+    probe: ServiceProbeRequest,
+    allowed_hosts: &[String],
+) -> ServiceProbeResponse {
+    match probe {
+        ServiceProbeRequest::TestConnectivity => match head(http_service, allowed_hosts).await {
+            Ok(_response) => ServiceProbeResponse::Success(ServiceProbeResult::Connected),
+            Err(e) => ServiceProbeResponse::Error(e),
+        },
+        ServiceProbeRequest::HttpHead => match head(http_service, allowed_hosts).await {
+            Ok(response) => ServiceProbeResponse::Success(ServiceProbeResult::HttpHead {
+                status: response.status().as_u16(),
+                headers: response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            name.to_string(),
+                            String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                        )
+                    })
+                    .collect::<BTreeMap<String, String>>(),
+            }),
+            Err(e) => ServiceProbeResponse::Error(e),
+        },
+        probe => unsupported_request("http", probe),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::probe_http_service;
+    use crate::prober::service::{
+        ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse, ServiceProbeResult,
+    };
+    use pipeline_types::service::HttpService;
+    use std::collections::BTreeMap;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn http_head() {
+        let server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/data"))
+            .and(header("authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).insert_header("x-test", "value"))
+            .mount(&server)
+            .await;
+
+        // The mock server listens on the loopback interface.
+        let allowed_hosts = vec!["127.0.0.1".to_string()];
+        let service = HttpService {
+            url: format!("{}/data", server.uri()),
+            headers: BTreeMap::from([("Authorization".to_string(), "Bearer token".to_string())]),
+        };
+        assert_eq!(
+            probe_http_service(
+                &service,
+                ServiceProbeRequest::TestConnectivity,
+                &allowed_hosts
+            )
+            .await,
+            ServiceProbeResponse::Success(ServiceProbeResult::Connected)
+        );
+        match probe_http_service(&service, ServiceProbeRequest::HttpHead, &allowed_hosts).await {
+            ServiceProbeResponse::Success(ServiceProbeResult::HttpHead { status, headers }) => {
+                assert_eq!(status, 200);
+                assert_eq!(headers["x-test"], "value");
+            }
+            response => panic!("unexpected response: {response:?}"),
+        }
+
+        // Without the header, the mock server does not match the request.
+        let service = HttpService {
+            headers: BTreeMap::new(),
+            ..service
+        };
+        match probe_http_service(&service, ServiceProbeRequest::HttpHead, &allowed_hosts).await {
+            ServiceProbeResponse::Success(ServiceProbeResult::HttpHead { status, .. }) => {
+                assert_eq!(status, 404);
+            }
+            response => panic!("unexpected response: {response:?}"),
+        }
+
+        // Hosts that resolve to internal addresses must be allowed explicitly.
+        assert!(matches!(
+            probe_http_service(&service, ServiceProbeRequest::HttpHead, &[]).await,
+            ServiceProbeResponse::Error(ServiceProbeError::Other(_))
+        ));
+
+        assert_eq!(
+            probe_http_service(&service, ServiceProbeRequest::S3ListBuckets, &allowed_hosts).await,
+            ServiceProbeResponse::Error(ServiceProbeError::UnsupportedRequest {
+                service_type: "http".to_string(),
+                probe_type: "s3_list_buckets".to_string(),
+            })
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::prober::service::{
-    ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse, ServiceProbeResult,
+    unsupported_request, ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse,
+    ServiceProbeResult,
 };
 use pipeline_types::service::KafkaService;
 use rdkafka::consumer::{BaseConsumer, Consumer};
@@ -43,8 +44,6 @@ pub fn probe_kafka_service(
     timeout: Duration,
 ) -> ServiceProbeResponse {
     // Only general and Kafka probe requests are valid.
-    // When new services are added, a match will be done
-    // with UnsupportedRequest error being returned for others.
     match probe {
         ServiceProbeRequest::TestConnectivity => match fetch_metadata(kafka_service, timeout) {
             Ok(_metadata) => ServiceProbeResponse::Success(ServiceProbeResult::Connected),
@@ -60,6 +59,7 @@ pub fn probe_kafka_service(
             )),
             Err(e) => ServiceProbeResponse::Error(e),
         },
+        probe => unsupported_request("kafka", probe),
     }
 }
 
@@ -1,8 +1,15 @@
+mod http;
 mod kafka;
+mod postgres;
 mod probe;
+mod s3;
 
+use http::probe_http_service;
 use kafka::probe_kafka_service;
 use pipeline_types::service::ServiceConfig;
+use postgres::probe_postgres_service;
+use s3::probe_s3_service;
+use std::future::Future;
 use std::time::Duration;
 
 // Exported
@@ -13,12 +20,54 @@ pub use probe::{
 
 /// Perform the probe for the service.
 /// Returns with a failure if the timeout is exceeded.
-pub fn probe_service(
+///
+/// HTTP, PostgreSQL and S3 probes refuse to connect to hosts that resolve to
+/// internal addresses, unless they are in `allowed_hosts`.
+pub async fn probe_service(
     service_config: &ServiceConfig,
     probe: ServiceProbeRequest,
     timeout: Duration,
+    allowed_hosts: &[String],
 ) -> ServiceProbeResponse {
     match service_config {
         ServiceConfig::Kafka(kafka_service) => probe_kafka_service(kafka_service, probe, timeout),
+        ServiceConfig::Postgres(postgres_service) => {
+            with_timeout(
+                timeout,
+                probe_postgres_service(postgres_service, probe, allowed_hosts),
+            )
+            .await
+        }
+        ServiceConfig::S3(s3_service) => {
+            with_timeout(timeout, probe_s3_service(s3_service, probe, allowed_hosts)).await
+        }
+        ServiceConfig::Http(http_service) => {
+            with_timeout(
+                timeout,
+                probe_http_service(http_service, probe, allowed_hosts),
+            )
+            .await
+        }
     }
 }
+
+/// Runs an asynchronous probe, failing with
+/// [`ServiceProbeError::TimeoutExceeded`] if it does not complete in time.
+async fn with_timeout(
+    timeout: Duration,
+    probe: impl Future<Output = ServiceProbeResponse>,
+) -> ServiceProbeResponse {
+    tokio::time::timeout(timeout, probe)
+        .await
+        .unwrap_or(ServiceProbeResponse::Error(
+            ServiceProbeError::TimeoutExceeded,
+        ))
+}
+
+/// Response to a probe request the service type does not support.
+fn unsupported_request(service_type: &str, probe: ServiceProbeRequest) -> ServiceProbeResponse {
+    ServiceProbeResponse::Error(ServiceProbeError::UnsupportedRequest {
+        service_type: service_type.to_string(),
+        probe_type: <&'static str>::from(probe.probe_type()).to_string(),
+    })
+}
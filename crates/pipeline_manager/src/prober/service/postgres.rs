@@ -0,0 +1,191 @@
+use crate::outbound;
+use crate::prober::service::{
+    unsupported_request, ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse,
+    ServiceProbeResult,
+};
+use log::debug;
+use pipeline_types::service::PostgresService;
+use std::net::SocketAddr;
+use tokio_postgres::{Client, NoTls};
+
+/// Create the client configuration using the service configuration.
+///
+/// The client connects to `addr`, the address that the host of the service
+/// was checked to resolve to, rather than resolving the host again.
+fn create_client_config(
+    postgres_service: &PostgresService,
+    addr: SocketAddr,
+) -> tokio_postgres::Config {
+    let mut config = tokio_postgres::Config::new();
+    config
+        .host(&addr.ip().to_string())
+        .port(addr.port())
+        .user(&postgres_service.user)
+        .dbname(&postgres_service.dbname);
+    if let Some(password) = &postgres_service.password {
+        config.password(password);
+    }
+    config
+}
+
+/// Connect to the database, driving the connection in a separate task.
+async fn connect(
+    postgres_service: &PostgresService,
+    allowed_hosts: &[String],
+) -> Result<Client, ServiceProbeError> {
+    let addrs =
+        outbound::resolve_host(&postgres_service.host, postgres_service.port, allowed_hosts)
+            .await
+            .map_err(ServiceProbeError::Other)?;
+    let (client, connection) = create_client_config(postgres_service, addrs[0])
+        .connect(NoTls)
+        .await
+        .map_err(|e| ServiceProbeError::Other(e.to_string()))?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Postgres probe connection terminated: {e}");
+        }
+    });
+    Ok(client)
+}
+
+async fn list_tables(client: &Client) -> Result<Vec<String>, ServiceProbeError> {
+    let rows = client
+        .query(
+            "SELECT table_schema::text, table_name::text FROM information_schema.tables
+             WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+             ORDER BY table_schema, table_name",
+            &[],
+        )
+        .await
+        .map_err(|e| ServiceProbeError::Other(e.to_string()))?;
+    Ok(rows
+        .iter()
+        .map(|row| format!("{}.{}", row.get::<_, &str>(0), row.get::<_, &str>(1)))
+        .collect())
+}
+
+/// Perform a probe for the PostgreSQL service.
+pub async fn probe_postgres_service(
+    postgres_service: &PostgresService,
+    probe: ServiceProbeRequest,
+    allowed_hosts: &[String],
+) -> ServiceProbeResponse {
+    match probe {
+        ServiceProbeRequest::TestConnectivity => {
+            match connect(postgres_service, allowed_hosts).await {
+                Ok(client) => match client.simple_query("SELECT 1").await {
+                    Ok(_) => ServiceProbeResponse::Success(ServiceProbeResult::Connected),
+                    Err(e) => ServiceProbeResponse::Error(ServiceProbeError::Other(e.to_string())),
+                },
+                Err(e) => ServiceProbeResponse::Error(e),
+            }
+        }
+        ServiceProbeRequest::PostgresListTables => {
+            let result = match connect(postgres_service, allowed_hosts).await {
+                Ok(client) => list_tables(&client).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(tables) => {
+                    ServiceProbeResponse::Success(ServiceProbeResult::PostgresTables(tables))
+                }
+                Err(e) => ServiceProbeResponse::Error(e),
+            }
+        }
+        probe => unsupported_request("postgres", probe),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::probe_postgres_service;
+    use crate::prober::service::{
+        ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse, ServiceProbeResult,
+    };
+    use pipeline_types::service::PostgresService;
+
+    /// Probes the database the tests run against, which stands in for a
+    /// user database.
+    #[cfg(not(feature = "pg-embed"))]
+    #[tokio::test]
+    async fn probe_test_database() {
+        use tokio_postgres::config::Host;
+
+        let (_db, config) = crate::db::test::setup_pg().await;
+        let host = match &config.get_hosts()[0] {
+            Host::Tcp(host) => host.clone(),
+            Host::Unix(path) => path.to_string_lossy().into_owned(),
+        };
+        let service = PostgresService {
+            host,
+            port: config.get_ports().first().copied().unwrap_or(5432),
+            user: config.get_user().unwrap().to_string(),
+            password: config
+                .get_password()
+                .map(|p| String::from_utf8_lossy(p).into_owned()),
+            dbname: config.get_dbname().unwrap().to_string(),
+        };
+
+        let allowed_hosts = vec![service.host.clone()];
+        assert_eq!(
+            probe_postgres_service(
+                &service,
+                ServiceProbeRequest::TestConnectivity,
+                &allowed_hosts
+            )
+            .await,
+            ServiceProbeResponse::Success(ServiceProbeResult::Connected)
+        );
+        match probe_postgres_service(
+            &service,
+            ServiceProbeRequest::PostgresListTables,
+            &allowed_hosts,
+        )
+        .await
+        {
+            ServiceProbeResponse::Success(ServiceProbeResult::PostgresTables(tables)) => {
+                // Created by the pipeline manager migrations.
+                assert!(tables.contains(&"public.program".to_string()), "{tables:?}");
+                assert!(!tables.iter().any(|t| t.starts_with("pg_catalog.")));
+            }
+            response => panic!("unexpected response: {response:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn unsupported_and_unreachable() {
+        let service = PostgresService {
+            host: "127.0.0.1".to_string(),
+            // Nothing listens on port 1.
+            port: 1,
+            user: "postgres".to_string(),
+            password: None,
+            dbname: "postgres".to_string(),
+        };
+        let allowed_hosts = vec!["127.0.0.1".to_string()];
+        assert_eq!(
+            probe_postgres_service(&service, ServiceProbeRequest::KafkaGetTopics, &[]).await,
+            ServiceProbeResponse::Error(ServiceProbeError::UnsupportedRequest {
+                service_type: "postgres".to_string(),
+                probe_type: "kafka_get_topics".to_string(),
+            })
+        );
+        assert!(matches!(
+            probe_postgres_service(
+                &service,
+                ServiceProbeRequest::TestConnectivity,
+                &allowed_hosts
+            )
+            .await,
+            ServiceProbeResponse::Error(ServiceProbeError::Other(_))
+        ));
+        // Hosts that resolve to internal addresses must be allowed explicitly.
+        assert_eq!(
+            probe_postgres_service(&service, ServiceProbeRequest::TestConnectivity, &[]).await,
+            ServiceProbeResponse::Error(ServiceProbeError::Other(
+                "host '127.0.0.1' resolves to an internal address; an administrator must allow it explicitly".to_string()
+            ))
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::db::DBError;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use thiserror::Error as ThisError;
 use utoipa::ToSchema;
 
@@ -62,6 +63,9 @@ impl From<ServiceProbeStatus> for &'static str {
 pub enum ServiceProbeType {
     TestConnectivity,
     KafkaGetTopics,
+    S3ListBuckets,
+    PostgresListTables,
+    HttpHead,
 }
 
 impl TryFrom<String> for ServiceProbeType {
@@ -70,6 +74,9 @@ impl TryFrom<String> for ServiceProbeType {
         match value.as_str() {
             "test_connectivity" => Ok(Self::TestConnectivity),
             "kafka_get_topics" => Ok(Self::KafkaGetTopics),
+            "s3_list_buckets" => Ok(Self::S3ListBuckets),
+            "postgres_list_tables" => Ok(Self::PostgresListTables),
+            "http_head" => Ok(Self::HttpHead),
             _ => Err(DBError::unknown_service_probe_type(value)),
         }
     }
@@ -80,6 +87,9 @@ impl From<ServiceProbeType> for &'static str {
         match val {
             ServiceProbeType::TestConnectivity => "test_connectivity",
             ServiceProbeType::KafkaGetTopics => "kafka_get_topics",
+            ServiceProbeType::S3ListBuckets => "s3_list_buckets",
+            ServiceProbeType::PostgresListTables => "postgres_list_tables",
+            ServiceProbeType::HttpHead => "http_head",
         }
     }
 }
@@ -95,6 +105,13 @@ pub enum ServiceProbeRequest {
     TestConnectivity,
     /// Retrieves the names of all Kafka topics present.
     KafkaGetTopics,
+    /// Retrieves the names of all buckets of an S3 service.
+    S3ListBuckets,
+    /// Retrieves the names of all tables of a PostgreSQL database, except for
+    /// system tables.
+    PostgresListTables,
+    /// Sends a HEAD request to the URL of an HTTP service.
+    HttpHead,
 }
 
 impl ServiceProbeRequest {
@@ -103,6 +120,9 @@ impl ServiceProbeRequest {
         match self {
             ServiceProbeRequest::TestConnectivity => ServiceProbeType::TestConnectivity,
             ServiceProbeRequest::KafkaGetTopics => ServiceProbeType::KafkaGetTopics,
+            ServiceProbeRequest::S3ListBuckets => ServiceProbeType::S3ListBuckets,
+            ServiceProbeRequest::PostgresListTables => ServiceProbeType::PostgresListTables,
+            ServiceProbeRequest::HttpHead => ServiceProbeType::HttpHead,
         }
     }
 
@@ -126,6 +146,16 @@ pub enum ServiceProbeResult {
     Connected,
     /// The names of all Kafka topics of the service.
     KafkaTopics(Vec<String>),
+    /// The names of all buckets of the S3 service.
+    S3Buckets(Vec<String>),
+    /// The names of all tables of the PostgreSQL database, qualified by
+    /// their schema (e.g., "public.orders").
+    PostgresTables(Vec<String>),
+    /// Status code and headers of the response to a HEAD request.
+    HttpHead {
+        status: u16,
+        headers: BTreeMap<String, String>,
+    },
 }
 
 /// Range of possible errors that can occur during a service probe.
@@ -223,6 +253,9 @@ mod tests {
         for (string_repr, corresponding_type) in [
             ("test_connectivity", ServiceProbeType::TestConnectivity),
             ("kafka_get_topics", ServiceProbeType::KafkaGetTopics),
+            ("s3_list_buckets", ServiceProbeType::S3ListBuckets),
+            ("postgres_list_tables", ServiceProbeType::PostgresListTables),
+            ("http_head", ServiceProbeType::HttpHead),
         ] {
             // Converting type into string (e.g., for storing in database)
             assert_eq!(
@@ -287,5 +320,23 @@ mod tests {
             request,
             ServiceProbeRequest::from_yaml_str(&request.to_yaml())
         );
+
+        for (request, probe_type) in [
+            (
+                ServiceProbeRequest::S3ListBuckets,
+                ServiceProbeType::S3ListBuckets,
+            ),
+            (
+                ServiceProbeRequest::PostgresListTables,
+                ServiceProbeType::PostgresListTables,
+            ),
+            (ServiceProbeRequest::HttpHead, ServiceProbeType::HttpHead),
+        ] {
+            assert_eq!(request.probe_type(), probe_type);
+            assert_eq!(
+                request,
+                ServiceProbeRequest::from_yaml_str(&request.to_yaml())
+            );
+        }
     }
 }
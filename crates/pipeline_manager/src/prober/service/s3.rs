@@ -0,0 +1,184 @@
+use crate::outbound;
+use crate::prober::service::{
+    unsupported_request, ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse,
+    ServiceProbeResult,
+};
+use aws_sdk_s3::error::DisplayErrorContext;
+use pipeline_types::service::S3Service;
+use pipeline_types::transport::s3::AwsCredentials;
+use url::Url;
+
+/// Client configuration for the service, built the same way as by the S3
+/// input transport of the pipelines.
+fn to_s3_config(s3_service: &S3Service) -> aws_sdk_s3::Config {
+    let mut config_builder = aws_sdk_s3::Config::builder()
+        .region(aws_types::region::Region::new(s3_service.region.clone()));
+    if let Some(endpoint_url) = &s3_service.endpoint_url {
+        // S3-compatible object stores typically do not support
+        // virtual-hosted-style bucket addressing.
+        config_builder = config_builder
+            .endpoint_url(endpoint_url)
+            .force_path_style(true);
+    }
+    match &s3_service.credentials {
+        Some(AwsCredentials::AccessKey {
+            aws_access_key_id,
+            aws_secret_access_key,
+        }) => {
+            let credentials = aws_sdk_s3::config::Credentials::new(
+                aws_access_key_id.clone(),
+                aws_secret_access_key.clone(),
+                None,
+                None,
+                "credential-provider",
+            );
+            config_builder.credentials_provider(credentials).build()
+        }
+        Some(AwsCredentials::NoSignRequest) | None => config_builder.build(),
+    }
+}
+
+/// Lists the buckets of the service using the `ListBuckets` operation.
+///
+/// Custom endpoints must not resolve to internal addresses, unless they are
+/// in `allowed_hosts`.
+async fn list_buckets(
+    s3_service: &S3Service,
+    allowed_hosts: &[String],
+) -> Result<Vec<String>, ServiceProbeError> {
+    if let Some(endpoint_url) = &s3_service.endpoint_url {
+        let url = Url::parse(endpoint_url)
+            .map_err(|e| ServiceProbeError::Other(format!("invalid S3 endpoint: {e}")))?;
+        outbound::resolve_url(&url, allowed_hosts)
+            .await
+            .map_err(ServiceProbeError::Other)?;
+    }
+    let client = aws_sdk_s3::Client::from_conf(to_s3_config(s3_service));
+    let output = client.list_buckets().send().await.map_err(|e| {
+        ServiceProbeError::Other(format!(
+            "unable to list buckets: {}",
+            DisplayErrorContext(&e)
+        ))
+    })?;
+    Ok(output
+        .buckets()
+        .iter()
+        .filter_map(|bucket| bucket.name().map(str::to_string))
+        .collect())
+}
+
+/// Perform a probe for the S3 service.
+pub async fn probe_s3_service(
+    s3_service: &S3Service,
+    probe: ServiceProbeRequest,
+    allowed_hosts: &[String],
+) -> ServiceProbeResponse {
+    match probe {
+        ServiceProbeRequest::TestConnectivity => {
+            match list_buckets(s3_service, allowed_hosts).await {
+                Ok(_buckets) => ServiceProbeResponse::Success(ServiceProbeResult::Connected),
+                Err(e) => ServiceProbeResponse::Error(e),
+            }
+        }
+        ServiceProbeRequest::S3ListBuckets => match list_buckets(s3_service, allowed_hosts).await {
+            Ok(buckets) => ServiceProbeResponse::Success(ServiceProbeResult::S3Buckets(buckets)),
+            Err(e) => ServiceProbeResponse::Error(e),
+        },
+        probe => unsupported_request("s3", probe),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::probe_s3_service;
+    use crate::prober::service::{
+        ServiceProbeError, ServiceProbeRequest, ServiceProbeResponse, ServiceProbeResult,
+    };
+    use pipeline_types::service::S3Service;
+    use pipeline_types::transport::s3::AwsCredentials;
+    use wiremock::matchers::{header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn list_buckets() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .and(header_exists("authorization"))
+            .and(header_exists("x-amz-date"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+                <ListAllMyBucketsResult>
+                    <Owner><ID>1</ID><DisplayName>owner</DisplayName></Owner>
+                    <Buckets>
+                        <Bucket><Name>bucket1</Name><CreationDate>2024-01-01T00:00:00.000Z</CreationDate></Bucket>
+                        <Bucket><Name>bucket2</Name><CreationDate>2024-01-01T00:00:00.000Z</CreationDate></Bucket>
+                    </Buckets>
+                </ListAllMyBucketsResult>",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(403).set_body_string(
+                "<Error><Code>AccessDenied</Code><Message>Access Denied</Message></Error>",
+            ))
+            .mount(&server)
+            .await;
+
+        // The mock server listens on the loopback interface.
+        let allowed_hosts = vec!["127.0.0.1".to_string()];
+        let service = S3Service {
+            region: "us-east-1".to_string(),
+            endpoint_url: Some(server.uri()),
+            credentials: Some(AwsCredentials::AccessKey {
+                aws_access_key_id: "id".to_string(),
+                aws_secret_access_key: "secret".to_string(),
+            }),
+        };
+        assert_eq!(
+            probe_s3_service(&service, ServiceProbeRequest::S3ListBuckets, &allowed_hosts).await,
+            ServiceProbeResponse::Success(ServiceProbeResult::S3Buckets(vec![
+                "bucket1".to_string(),
+                "bucket2".to_string()
+            ]))
+        );
+        assert_eq!(
+            probe_s3_service(
+                &service,
+                ServiceProbeRequest::TestConnectivity,
+                &allowed_hosts
+            )
+            .await,
+            ServiceProbeResponse::Success(ServiceProbeResult::Connected)
+        );
+
+        // Hosts that resolve to internal addresses must be allowed explicitly.
+        assert!(matches!(
+            probe_s3_service(&service, ServiceProbeRequest::S3ListBuckets, &[]).await,
+            ServiceProbeResponse::Error(ServiceProbeError::Other(_))
+        ));
+
+        // Unsigned requests are rejected.
+        let service = S3Service {
+            credentials: None,
+            ..service
+        };
+        assert!(matches!(
+            probe_s3_service(&service, ServiceProbeRequest::S3ListBuckets, &allowed_hosts).await,
+            ServiceProbeResponse::Error(ServiceProbeError::Other(_))
+        ));
+        assert_eq!(
+            probe_s3_service(
+                &service,
+                ServiceProbeRequest::PostgresListTables,
+                &allowed_hosts
+            )
+            .await,
+            ServiceProbeResponse::Error(ServiceProbeError::UnsupportedRequest {
+                service_type: "s3".to_string(),
+                probe_type: "postgres_list_tables".to_string(),
+            })
+        );
+    }
+}
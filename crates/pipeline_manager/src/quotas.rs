@@ -0,0 +1,285 @@
+//! Per-tenant quotas.
+//!
+//! Quotas limit the pipelines that a tenant can run and the programs it can
+//! compile at the same time.  They are enforced when the API admits a request
+//! that starts a pipeline or a compilation:
+//!
+//! * If the request alone exceeds a quota, or does not declare the resources
+//!   that a quota limits, it can never be admitted and is rejected with
+//!   `403 Forbidden`.
+//! * If the request fits in the quota, but not together with the pipelines
+//!   and compilations of the tenant that are already admitted, it is rejected
+//!   with `429 Too Many Requests` and can be retried later.
+//!
+//! Pipelines that are already admitted count with the configuration they
+//! were deployed with, since edits to their configuration only take effect
+//! when they are restarted.  The checks run under the same lock of the
+//! database as the actions they admit, so that concurrent requests cannot
+//! both be admitted against the same usage.
+use crate::api::ManagerError;
+use crate::auth::TenantId;
+use crate::compiler::ProgramStatus;
+use crate::config::{ApiServerConfig, TenantQuotas};
+use crate::db::{storage::Storage, DBError, Pipeline, PipelineStatus, ProgramDescr, ProjectDB};
+use pipeline_types::config::RuntimeConfig;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Resources used by the pipelines and compilations of a tenant, in the
+/// units of the corresponding quotas.
+#[derive(Serialize, ToSchema, Debug, Default, Clone, Eq, PartialEq)]
+pub(crate) struct QuotaUsage {
+    /// Number of running or paused pipelines.
+    pub running_pipelines: u64,
+    /// Total number of workers of the running or paused pipelines.
+    pub workers: u64,
+    /// Total `memory_mb_max` of the running or paused pipelines.
+    pub memory_mb: u64,
+    /// Total `storage_mb_max` of the running or paused pipelines.
+    pub storage_mb: u64,
+    /// Number of programs that are compiling or waiting to be compiled.
+    pub concurrent_compilations: u64,
+}
+
+impl QuotaUsage {
+    fn add_pipeline(&mut self, config: &RuntimeConfig) {
+        self.running_pipelines += 1;
+        self.workers += config.workers as u64;
+        self.memory_mb += config.resources.memory_mb_max.unwrap_or(0);
+        self.storage_mb += config.resources.storage_mb_max.unwrap_or(0);
+    }
+}
+
+/// Quotas of a tenant and their current usage.
+#[derive(Serialize, ToSchema, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct QuotasResponse {
+    pub quotas: TenantQuotas,
+    pub usage: QuotaUsage,
+}
+
+/// Returns true if `pipeline` counts toward the quotas of its tenant.
+fn is_admitted(pipeline: &Pipeline) -> bool {
+    pipeline.state.desired_status != PipelineStatus::Shutdown
+}
+
+/// Returns the runtime configuration that admitted pipeline `pipeline` was
+/// deployed with.
+async fn deployed_config(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    pipeline: &Pipeline,
+) -> Result<RuntimeConfig, DBError> {
+    match db
+        .get_pipeline_deployment(tenant_id, pipeline.descriptor.pipeline_id)
+        .await
+    {
+        Ok(revision) => Ok(revision.config.global),
+        // Pipelines are deployed when they are started, from their current
+        // configuration.
+        Err(DBError::NoRevisionAvailable { .. }) => Ok(pipeline.descriptor.config.clone()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if `program` counts toward the compilation quota of its
+/// tenant.
+fn is_compiling(program: &ProgramDescr) -> bool {
+    program.status == ProgramStatus::Pending || program.status.is_compiling()
+}
+
+/// Returns the quotas of tenant `tenant_id` under `config`.
+pub(crate) async fn tenant_quotas(
+    db: &ProjectDB,
+    config: &ApiServerConfig,
+    tenant_id: TenantId,
+) -> Result<TenantQuotas, DBError> {
+    if config.tenant_quotas.is_empty() {
+        return Ok(config.quotas.clone());
+    }
+    let tenant_name = db.get_tenant_name(tenant_id).await?;
+    Ok(match config.tenant_quotas.get(&tenant_name) {
+        Some(quotas) => quotas.or(&config.quotas),
+        None => config.quotas.clone(),
+    })
+}
+
+/// Returns the resources currently used by tenant `tenant_id`.
+pub(crate) async fn quota_usage(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+) -> Result<QuotaUsage, DBError> {
+    let mut usage = QuotaUsage::default();
+    for pipeline in db.list_pipelines(tenant_id).await? {
+        if is_admitted(&pipeline) {
+            usage.add_pipeline(&deployed_config(db, tenant_id, &pipeline).await?);
+        }
+    }
+    usage.concurrent_compilations = db
+        .list_programs(tenant_id, false)
+        .await?
+        .iter()
+        .filter(|program| is_compiling(program))
+        .count() as u64;
+    Ok(usage)
+}
+
+/// Checks that starting or pausing pipeline `pipeline_name` of tenant
+/// `tenant_id` does not exceed `quotas`.
+///
+/// A pipeline that is already running or paused was admitted when it was
+/// started, so it is only checked again when it is upgraded to a new
+/// revision, which deploys its current configuration.
+///
+/// The caller must hold the lock of `db` until the pipeline is admitted, or
+/// the usage can change between the check and the action.
+pub(crate) async fn check_pipeline_quotas(
+    db: &ProjectDB,
+    quotas: &TenantQuotas,
+    tenant_id: TenantId,
+    pipeline_name: &str,
+    upgrade: bool,
+) -> Result<(), ManagerError> {
+    if quotas.running_pipelines.is_none()
+        && quotas.workers.is_none()
+        && quotas.memory_mb.is_none()
+        && quotas.storage_mb.is_none()
+    {
+        return Ok(());
+    }
+
+    let pipelines = db.list_pipelines(tenant_id).await?;
+    let Some(pipeline) = pipelines
+        .iter()
+        .find(|pipeline| pipeline.descriptor.name == pipeline_name)
+    else {
+        // The action reports the unknown pipeline.
+        return Ok(());
+    };
+    if is_admitted(pipeline) && !upgrade {
+        return Ok(());
+    }
+
+    let resources = &pipeline.descriptor.config.resources;
+    if quotas.memory_mb.is_some() && resources.memory_mb_max.is_none() {
+        return Err(ManagerError::QuotaViolation {
+            quota: "memory_mb".to_string(),
+            reason: "the pipeline must set 'resources.memory_mb_max'".to_string(),
+        });
+    }
+    if quotas.storage_mb.is_some() && resources.storage_mb_max.is_none() {
+        return Err(ManagerError::QuotaViolation {
+            quota: "storage_mb".to_string(),
+            reason: "the pipeline must set 'resources.storage_mb_max'".to_string(),
+        });
+    }
+
+    let mut usage = QuotaUsage::default();
+    for other in pipelines.iter() {
+        if other.descriptor.pipeline_id != pipeline.descriptor.pipeline_id && is_admitted(other) {
+            usage.add_pipeline(&deployed_config(db, tenant_id, other).await?);
+        }
+    }
+    let mut requested = QuotaUsage::default();
+    requested.add_pipeline(&pipeline.descriptor.config);
+
+    check_quota(
+        "running_pipelines",
+        quotas.running_pipelines,
+        usage.running_pipelines,
+        requested.running_pipelines,
+    )?;
+    check_quota("workers", quotas.workers, usage.workers, requested.workers)?;
+    check_quota(
+        "memory_mb",
+        quotas.memory_mb,
+        usage.memory_mb,
+        requested.memory_mb,
+    )?;
+    check_quota(
+        "storage_mb",
+        quotas.storage_mb,
+        usage.storage_mb,
+        requested.storage_mb,
+    )
+}
+
+/// Checks that compiling new versions of programs `program_names` of tenant
+/// `tenant_id` does not exceed `quotas`.
+///
+/// A new version replaces the pending compilation of the program, if any, so
+/// the programs themselves do not count toward the quota.
+pub(crate) async fn check_compilation_quotas(
+    db: &ProjectDB,
+    quotas: &TenantQuotas,
+    tenant_id: TenantId,
+    program_names: &[&str],
+) -> Result<(), ManagerError> {
+    if quotas.concurrent_compilations.is_none() || program_names.is_empty() {
+        return Ok(());
+    }
+    let usage = db
+        .list_programs(tenant_id, false)
+        .await?
+        .iter()
+        .filter(|program| !program_names.contains(&program.name.as_str()) && is_compiling(program))
+        .count() as u64;
+    check_quota(
+        "concurrent_compilations",
+        quotas.concurrent_compilations,
+        usage,
+        program_names.len() as u64,
+    )
+}
+
+/// Checks that using `requested` more units of quota `quota`, on top of
+/// `usage`, does not exceed `limit`.
+fn check_quota(
+    quota: &str,
+    limit: Option<u64>,
+    usage: u64,
+    requested: u64,
+) -> Result<(), ManagerError> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    if requested > limit {
+        Err(ManagerError::QuotaViolation {
+            quota: quota.to_string(),
+            reason: format!("{requested} requested, but the quota is {limit}"),
+        })
+    } else if usage + requested > limit {
+        Err(ManagerError::QuotaExceeded {
+            quota: quota.to_string(),
+            limit,
+            usage,
+            requested,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_quota;
+    use crate::api::ManagerError;
+
+    #[test]
+    fn quota_errors() {
+        assert!(check_quota("workers", None, 100, 100).is_ok());
+        assert!(check_quota("workers", Some(8), 4, 4).is_ok());
+        assert!(matches!(
+            check_quota("workers", Some(8), 6, 4),
+            Err(ManagerError::QuotaExceeded {
+                limit: 8,
+                usage: 6,
+                requested: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_quota("workers", Some(8), 0, 10),
+            Err(ManagerError::QuotaViolation { .. })
+        ));
+    }
+}
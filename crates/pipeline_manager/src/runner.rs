@@ -1,7 +1,12 @@
 use crate::{
     api::ManagerError,
     auth::TenantId,
-    db::{storage::Storage, DBError, PipelineId, PipelineRuntimeState, PipelineStatus, ProjectDB},
+    config::TenantQuotas,
+    db::{
+        storage::Storage, DBError, PipelineId, PipelineRevision, PipelineRuntimeState,
+        PipelineStatus, ProjectDB, Revision,
+    },
+    quotas::check_pipeline_quotas,
 };
 use actix_web::{
     body::BoxBody,
@@ -64,6 +69,18 @@ pub enum RunnerError {
         pipeline_id: PipelineId,
         error: String,
     },
+    PipelineOutOfMemory {
+        pipeline_id: PipelineId,
+        memory_mb_max: Option<u64>,
+    },
+    PipelineUpgradeRejected {
+        pipeline_id: PipelineId,
+        error: String,
+    },
+    PipelineUpgradeError {
+        pipeline_id: PipelineId,
+        error: String,
+    },
 }
 
 impl DetailedError for RunnerError {
@@ -83,6 +100,9 @@ impl DetailedError for RunnerError {
                 Cow::from("IllegalPipelineStateTransition")
             }
             Self::BinaryFetchError { .. } => Cow::from("BinaryFetchError"),
+            Self::PipelineOutOfMemory { .. } => Cow::from("PipelineOutOfMemory"),
+            Self::PipelineUpgradeRejected { .. } => Cow::from("PipelineUpgradeRejected"),
+            Self::PipelineUpgradeError { .. } => Cow::from("PipelineUpgradeError"),
         }
     }
 }
@@ -150,6 +170,33 @@ impl Display for RunnerError {
                     "Failed to fetch binary executable for running pipeline {pipeline_id}: {error}"
                 )
             }
+            Self::PipelineOutOfMemory {
+                pipeline_id,
+                memory_mb_max: Some(memory_mb_max),
+            } => {
+                write!(
+                    f,
+                    "Pipeline {pipeline_id} was terminated because it exceeded its memory limit of {memory_mb_max} MB (resources.memory_mb_max)"
+                )
+            }
+            Self::PipelineOutOfMemory {
+                pipeline_id,
+                memory_mb_max: None,
+            } => {
+                write!(
+                    f,
+                    "Pipeline {pipeline_id} was terminated because it ran out of memory"
+                )
+            }
+            Self::PipelineUpgradeRejected { pipeline_id, error } => {
+                write!(
+                    f,
+                    "Pipeline {pipeline_id} cannot be upgraded in place: {error}"
+                )
+            }
+            Self::PipelineUpgradeError { pipeline_id, error } => {
+                write!(f, "Upgrade of pipeline {pipeline_id} failed: {error}")
+            }
         }
     }
 }
@@ -175,6 +222,9 @@ impl ResponseError for RunnerError {
             Self::PipelineShutdownError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Self::IllegalPipelineStateTransition { .. } => StatusCode::BAD_REQUEST,
             Self::BinaryFetchError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::PipelineOutOfMemory { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::PipelineUpgradeRejected { .. } => StatusCode::BAD_REQUEST,
+            Self::PipelineUpgradeError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
@@ -208,9 +258,16 @@ impl RunnerApi {
         &self,
         tenant_id: TenantId,
         pipeline_name: &str,
+        actor: &str,
     ) -> Result<(), ManagerError> {
-        self.set_desired_status(tenant_id, pipeline_name, PipelineStatus::Shutdown)
-            .await?;
+        self.set_desired_status(
+            tenant_id,
+            pipeline_name,
+            PipelineStatus::Shutdown,
+            actor,
+            None,
+        )
+        .await?;
         Ok(())
     }
 
@@ -239,7 +296,7 @@ impl RunnerApi {
             None,
         )?;
 
-        db.delete_pipeline(tenant_id, pipeline_name).await?;
+        db.delete_pipeline(tenant_id, pipeline_name, None).await?;
 
         // No need to do anything else since the pipeline was in the `Shutdown` state.
         // The pipeline tokio task will self-destruct when it polls pipeline
@@ -250,28 +307,109 @@ impl RunnerApi {
 
     /// Set the desired state of the pipeline to [`PipelineStatus::Paused`].
     ///
-    /// If the pipeline is currently in the `Shutdown` state, will validate
-    /// and commit the pipeline before running it.
+    /// If the pipeline is currently in the `Shutdown` state, will check
+    /// `quotas`, and validate and commit the pipeline before running it.
     pub(crate) async fn pause_pipeline(
         &self,
         tenant_id: TenantId,
         pipeline_name: &str,
+        actor: &str,
+        quotas: &TenantQuotas,
     ) -> Result<(), ManagerError> {
-        self.set_desired_status(tenant_id, pipeline_name, PipelineStatus::Paused)
-            .await?;
+        self.set_desired_status(
+            tenant_id,
+            pipeline_name,
+            PipelineStatus::Paused,
+            actor,
+            Some(quotas),
+        )
+        .await?;
         Ok(())
     }
 
     /// Set the desired state of the pipeline to [`PipelineStatus::Running`].
     ///
-    /// If the pipeline is currently in the `Shutdown` state, will validate
-    /// and commit the pipeline before running it.
+    /// If the pipeline is currently in the `Shutdown` state, will check
+    /// `quotas`, and validate and commit the pipeline before running it.
     pub(crate) async fn start_pipeline(
         &self,
         tenant_id: TenantId,
         pipeline_name: &str,
+        actor: &str,
+        quotas: &TenantQuotas,
     ) -> Result<(), ManagerError> {
-        self.set_desired_status(tenant_id, pipeline_name, PipelineStatus::Running)
+        self.set_desired_status(
+            tenant_id,
+            pipeline_name,
+            PipelineStatus::Running,
+            actor,
+            Some(quotas),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Upgrade a running or paused pipeline to a new revision, built from the
+    /// current version of its program and its current configuration.
+    ///
+    /// Requests the pipeline automaton to start the new revision in a
+    /// standby instance of the pipeline, which rebuilds the state of the
+    /// running instance by replaying its recorded input and then takes over
+    /// its outputs.  The new revision only becomes the deployed revision of
+    /// the pipeline once the standby took over.  See
+    /// [`PipelineRevision::check_upgrade`] for the conditions under which an
+    /// upgrade is possible.  The new revision must fit in `quotas`.
+    pub(crate) async fn upgrade_pipeline(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        actor: &str,
+        quotas: &TenantQuotas,
+    ) -> Result<(), ManagerError> {
+        let db = self.db.lock().await;
+        let pipeline_state = db
+            .get_pipeline_runtime_state_by_name(tenant_id, pipeline_name)
+            .await?;
+        let pipeline_id = pipeline_state.pipeline_id;
+
+        if !matches!(
+            pipeline_state.current_status,
+            PipelineStatus::Running | PipelineStatus::Paused
+        ) || pipeline_state.desired_status != pipeline_state.current_status
+        {
+            Err(RunnerError::IllegalPipelineStateTransition {
+                pipeline_id,
+                error: "Only a running or paused pipeline can be upgraded. Wait for pending status changes of the pipeline to complete before upgrading it.".to_string(),
+                current_status: pipeline_state.current_status,
+                desired_status: pipeline_state.desired_status,
+                requested_status: None,
+            })?;
+        }
+        if db
+            .get_pipeline_upgrade(tenant_id, pipeline_id)
+            .await?
+            .is_some()
+        {
+            Err(RunnerError::IllegalPipelineStateTransition {
+                pipeline_id,
+                error: "The pipeline is already being upgraded. Wait for the upgrade to complete before upgrading it again.".to_string(),
+                current_status: pipeline_state.current_status,
+                desired_status: pipeline_state.desired_status,
+                requested_status: None,
+            })?;
+        }
+        check_pipeline_quotas(&db, quotas, tenant_id, pipeline_name, true).await?;
+
+        let deployed = db.get_pipeline_deployment(tenant_id, pipeline_id).await?;
+        let (pipeline, program, connectors) =
+            db.is_pipeline_deployable(tenant_id, pipeline_id).await?;
+        let revision =
+            PipelineRevision::new(Revision(Uuid::now_v7()), pipeline, connectors, program);
+        deployed
+            .check_upgrade(&revision)
+            .map_err(|error| RunnerError::PipelineUpgradeRejected { pipeline_id, error })?;
+
+        db.request_pipeline_upgrade(tenant_id, pipeline_id, &revision, actor)
             .await?;
         Ok(())
     }
@@ -340,6 +478,8 @@ impl RunnerApi {
         tenant_id: TenantId,
         pipeline_name: &str,
         new_desired_status: PipelineStatus,
+        actor: &str,
+        quotas: Option<&TenantQuotas>,
     ) -> Result<(), ManagerError> {
         // TODO: this function should run in a transaction to avoid conflicts with
         // another manager instance.
@@ -356,6 +496,12 @@ impl RunnerApi {
             Some(new_desired_status),
         )?;
 
+        // Check quotas under the same lock as the status change, so that
+        // concurrent requests can't exceed them.
+        if let Some(quotas) = quotas {
+            check_pipeline_quotas(&db, quotas, tenant_id, pipeline_name, false).await?;
+        }
+
         // When starting a previously shutdown pipeline, commit its config first.
         if pipeline_state.current_status == PipelineStatus::Shutdown
             && new_desired_status != PipelineStatus::Shutdown
@@ -363,7 +509,7 @@ impl RunnerApi {
             Self::commit_revision(&db, tenant_id, pipeline_id).await?;
         }
 
-        db.set_pipeline_desired_status(tenant_id, pipeline_id, new_desired_status)
+        db.set_pipeline_desired_status(tenant_id, pipeline_id, new_desired_status, actor)
             .await?;
         Ok(())
     }
@@ -521,4 +667,46 @@ impl RunnerApi {
         }
         Ok(builder.streaming(response))
     }
+
+    /// Retrieve the log of a pipeline from the local runner listening on
+    /// `runner_hostname_port`.
+    ///
+    /// The response from the runner is streamed back to the client, which
+    /// supports following the log.
+    pub(crate) async fn pipeline_logs(
+        &self,
+        tenant_id: TenantId,
+        pipeline_name: &str,
+        runner_hostname_port: &str,
+        req: &HttpRequest,
+        client: &awc::Client,
+    ) -> Result<HttpResponse, ManagerError> {
+        let pipeline_id = self
+            .db
+            .lock()
+            .await
+            .get_pipeline_by_name(tenant_id, pipeline_name)
+            .await?
+            .descriptor
+            .pipeline_id;
+
+        let url = format!(
+            "http://{runner_hostname_port}/logs/{tenant_id}/{pipeline_id}?{}",
+            req.query_string()
+        );
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| RunnerError::HttpForwardError {
+                pipeline_id,
+                error: e.to_string(),
+            })?;
+
+        let mut builder = HttpResponseBuilder::new(response.status());
+        for header in response.headers().into_iter() {
+            builder.append_header(header);
+        }
+        Ok(builder.streaming(response))
+    }
 }
@@ -0,0 +1,411 @@
+//! Pipeline actions applied on cron-style schedules.
+//!
+//! Tenants attach named schedules to their pipelines, each of which starts,
+//! pauses or shuts down the pipeline at the times of a crontab(5) expression
+//! evaluated in an IANA time zone.  The scheduler, which runs in the API
+//! server, applies the actions that are due through the [`RunnerApi`], just
+//! like the pipeline action endpoint, including its quota checks.  Every
+//! scheduled action is logged and recorded in the audit log of the tenant as
+//! performed by `schedule:<name>`.  Schedules are leased while their action
+//! is applied, so that several API servers can share a database.
+//!
+//! Runs that were missed, e.g., while the manager was down, are applied once
+//! as soon as the scheduler finds them; the next run is always computed from
+//! the time the action was applied.
+use crate::api::ManagerError;
+use crate::config::ApiServerConfig;
+use crate::db::audit::{AuditEvent, AuditEventId, AuditOutcome};
+use crate::db::pipeline_schedule::{
+    DuePipelineSchedule, PipelineScheduleDescr, PipelineScheduleRun, ScheduledAction,
+};
+use crate::db::{storage::Storage, ProjectDB};
+use crate::quotas::tenant_quotas;
+use crate::runner::RunnerApi;
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use chrono::{DateTime, FixedOffset, Offset, Utc};
+use chrono_tz::Tz;
+use log::{error, info, warn};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Number of upcoming runs of a schedule reported by the API.
+const NEXT_RUNS: usize = 5;
+
+/// How often the scheduler checks for schedules that are due.
+const SCHEDULER_POLL_PERIOD: Duration = Duration::from_secs(5);
+
+/// Maximum number of schedules applied per check.
+const SCHEDULER_BATCH_SIZE: u32 = 32;
+
+/// How long a scheduler owns the schedules it retrieves.  Runs whose outcome
+/// is not recorded by then, e.g., because the scheduler crashed, are applied
+/// by any scheduler.
+const SCHEDULE_LEASE: Duration = Duration::from_secs(300);
+
+/// Days of the week, in the order of their numbers in crontab(5).
+const DAYS_OF_WEEK: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Cron expression of a schedule, evaluated in the time zone of the
+/// schedule.
+#[derive(Debug, Clone)]
+pub(crate) struct CronSchedule {
+    /// The times of the schedule are the times of any of these.  `cron`
+    /// requires both the day of month and the day of week to match, so an
+    /// expression that restricts both is split in two, one per field.
+    schedules: Vec<cron::Schedule>,
+    time_zone: Tz,
+}
+
+impl CronSchedule {
+    /// Parses a cron expression and the name of the IANA time zone it is
+    /// evaluated in, e.g., `Europe/Berlin`.
+    ///
+    /// The expression consists of the five fields of crontab(5): minute,
+    /// hour, day of month, month and day of week, where days of the week are
+    /// numbered from 0 (Sunday) to 7 (Sunday again) or named (`MON`).
+    /// As in crontab(5), if both the day of month and the day of week are
+    /// restricted, i.e., do not start with `*`, the schedule runs on the days
+    /// that match either of them.  `@hourly`, `@daily`, `@weekly`,
+    /// `@monthly` and `@yearly` are accepted as well.
+    pub(crate) fn parse(cron: &str, time_zone: &str) -> Result<Self, String> {
+        let time_zone =
+            Tz::from_str(time_zone).map_err(|_| format!("unknown time zone '{time_zone}'"))?;
+        let expressions = match cron.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [keyword] if keyword.starts_with('@') => vec![keyword.to_string()],
+            [minute, hour, day_of_month, month, day_of_week] => {
+                let expression = |day_of_month: &str, day_of_week: &str| {
+                    format!(
+                        "0 {minute} {hour} {day_of_month} {month} {}",
+                        days_of_week(day_of_week)
+                    )
+                };
+                let restricted = |field: &str| !field.starts_with('*') && field != "?";
+                if restricted(day_of_month) && restricted(day_of_week) {
+                    vec![expression(day_of_month, "*"), expression("*", day_of_week)]
+                } else {
+                    vec![expression(day_of_month, day_of_week)]
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "invalid cron expression '{cron}': expected five fields (minute, hour, day of month, month and day of week) or one of @hourly, @daily, @weekly, @monthly and @yearly"
+                ))
+            }
+        };
+        let schedules = expressions
+            .iter()
+            .map(|expression| cron::Schedule::from_str(expression))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("invalid cron expression '{cron}': {e}"))?;
+        Ok(Self {
+            schedules,
+            time_zone,
+        })
+    }
+
+    /// Returns the first time of the schedule after `after`, if any.
+    pub(crate) fn next_run(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let after = after.with_timezone(&self.time_zone);
+        self.schedules
+            .iter()
+            .filter_map(|schedule| schedule.after(&after).next())
+            .min()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    /// Returns up to `n` times of the schedule, in its time zone, starting
+    /// with `first`, which must be a time of the schedule.
+    pub(crate) fn runs_from(&self, first: DateTime<Utc>, n: usize) -> Vec<DateTime<FixedOffset>> {
+        std::iter::successors(Some(first), |t| self.next_run(*t))
+            .take(n)
+            .map(|t| {
+                let t = t.with_timezone(&self.time_zone);
+                t.with_timezone(&t.offset().fix())
+            })
+            .collect()
+    }
+}
+
+/// Converts the day-of-week field of a crontab(5) expression to the list of
+/// the names of the days it selects.  `cron` numbers days from 1 (Sunday)
+/// to 7 (Saturday), so numbers cannot be passed to it as is.
+///
+/// Returns the field unchanged if it cannot be converted, so that `cron`
+/// reports the error.
+fn days_of_week(field: &str) -> String {
+    if field == "*" || field == "?" {
+        return field.to_string();
+    }
+    let mut days = [false; 7];
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return field.to_string(),
+            },
+            None => (item, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (0, 6),
+            Some((first, last)) => match (day_of_week(first), day_of_week(last)) {
+                (Some(first), Some(last)) if first <= last => (first, last),
+                _ => return field.to_string(),
+            },
+            // `<day>/<step>` selects every `<step>` days from `<day>` to the
+            // end of the week.
+            None => match day_of_week(range) {
+                Some(day) if step > 1 => (day, 6),
+                Some(day) => (day, day),
+                None => return field.to_string(),
+            },
+        };
+        for day in (first..=last).step_by(step) {
+            days[day % 7] = true;
+        }
+    }
+    DAYS_OF_WEEK
+        .iter()
+        .zip(days)
+        .filter_map(|(name, selected)| selected.then_some(*name))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses a day of the week given by its number (0 to 7) or name.
+fn day_of_week(day: &str) -> Option<usize> {
+    match day.parse::<usize>() {
+        Ok(day) => (day <= 7).then_some(day),
+        Err(_) => DAYS_OF_WEEK
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(day)),
+    }
+}
+
+/// Fills in the upcoming runs of a schedule, starting with its next run.
+pub(crate) fn set_next_runs(schedule: &mut PipelineScheduleDescr) {
+    let Some(next_run_at) = schedule.next_run_at else {
+        return;
+    };
+    if let Ok(cron) = CronSchedule::parse(&schedule.cron, &schedule.time_zone) {
+        schedule.next_runs = cron.runs_from(next_run_at, NEXT_RUNS);
+    }
+}
+
+/// Applies the actions of the schedules that are due until the process
+/// exits.
+pub(crate) async fn run(db: Arc<Mutex<ProjectDB>>, config: ApiServerConfig) {
+    let runner = RunnerApi::new(db.clone());
+    loop {
+        let lease_until = Utc::now() + chrono::Duration::from_std(SCHEDULE_LEASE).unwrap();
+        let schedules = db
+            .lock()
+            .await
+            .next_pipeline_schedule_runs(SCHEDULER_BATCH_SIZE, lease_until)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Unable to retrieve due pipeline schedules: {e}");
+                Vec::new()
+            });
+        for schedule in &schedules {
+            let result = apply(&db, &config, &runner, schedule).await;
+            record(&db, schedule, result).await;
+        }
+        // Keep going without waiting while there is a backlog.
+        if schedules.len() < SCHEDULER_BATCH_SIZE as usize {
+            tokio::time::sleep(SCHEDULER_POLL_PERIOD).await;
+        }
+    }
+}
+
+/// Applies the action of a schedule to its pipeline.
+async fn apply(
+    db: &Arc<Mutex<ProjectDB>>,
+    config: &ApiServerConfig,
+    runner: &RunnerApi,
+    schedule: &DuePipelineSchedule,
+) -> Result<(), ManagerError> {
+    let tenant_id = schedule.tenant_id;
+    let pipeline_name = &schedule.pipeline_name;
+    let actor = format!("schedule:{}", schedule.name);
+    match schedule.action {
+        ScheduledAction::Start => {
+            let quotas = tenant_quotas(&*db.lock().await, config, tenant_id).await?;
+            runner
+                .start_pipeline(tenant_id, pipeline_name, &actor, &quotas)
+                .await
+        }
+        ScheduledAction::Pause => {
+            let quotas = tenant_quotas(&*db.lock().await, config, tenant_id).await?;
+            runner
+                .pause_pipeline(tenant_id, pipeline_name, &actor, &quotas)
+                .await
+        }
+        ScheduledAction::Shutdown => {
+            runner
+                .shutdown_pipeline(tenant_id, pipeline_name, &actor)
+                .await
+        }
+    }
+}
+
+/// Logs the outcome of a scheduled action, records it in the audit log and
+/// moves the schedule to its next run.
+async fn record(
+    db: &Arc<Mutex<ProjectDB>>,
+    schedule: &DuePipelineSchedule,
+    result: Result<(), ManagerError>,
+) {
+    let timestamp = Utc::now();
+    let action: &'static str = schedule.action.into();
+    let (outcome, status_code, error) = match result {
+        Ok(()) => {
+            info!(
+                "Applied scheduled '{action}' action of schedule '{}' to pipeline {} (tenant:{})",
+                schedule.name, schedule.pipeline_name, schedule.tenant_id
+            );
+            (AuditOutcome::Success, StatusCode::ACCEPTED, None)
+        }
+        Err(e) => {
+            warn!(
+                "Scheduled '{action}' action of schedule '{}' failed for pipeline {} (tenant:{}): {e}",
+                schedule.name, schedule.pipeline_name, schedule.tenant_id
+            );
+            (AuditOutcome::Failure, e.status_code(), Some(e.to_string()))
+        }
+    };
+    let next_run_at = match CronSchedule::parse(&schedule.cron, &schedule.time_zone) {
+        Ok(cron) => cron.next_run(timestamp),
+        Err(e) => {
+            error!(
+                "Disabling pipeline schedule {}: {e}",
+                schedule.pipeline_schedule_id
+            );
+            None
+        }
+    };
+    let event = AuditEvent {
+        audit_event_id: AuditEventId(Uuid::now_v7()),
+        timestamp,
+        actor: format!("schedule:{}", schedule.name),
+        action: format!("pipeline.{action}"),
+        target: Some(schedule.pipeline_name.clone()),
+        outcome,
+        status_code: status_code.as_u16(),
+    };
+    let run = PipelineScheduleRun {
+        scheduled_at: schedule.next_run_at,
+        timestamp,
+        next_run_at,
+        error,
+    };
+    let db = db.lock().await;
+    if let Err(e) = db.new_audit_event(schedule.tenant_id, &event).await {
+        error!(
+            "Unable to record the audit event of pipeline schedule {}: {e}",
+            schedule.pipeline_schedule_id
+        );
+    }
+    if let Err(e) = db
+        .record_pipeline_schedule_run(schedule.pipeline_schedule_id, &run)
+        .await
+    {
+        error!(
+            "Unable to record the run of pipeline schedule {}: {e}",
+            schedule.pipeline_schedule_id
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{days_of_week, CronSchedule};
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn runs(cron: &str, time_zone: &str, after: DateTime<Utc>, n: usize) -> Vec<String> {
+        let schedule = CronSchedule::parse(cron, time_zone).unwrap();
+        schedule
+            .runs_from(schedule.next_run(after).unwrap(), n)
+            .iter()
+            .map(|t| t.to_rfc3339())
+            .collect()
+    }
+
+    #[test]
+    fn days_of_week_field() {
+        assert_eq!(days_of_week("*"), "*");
+        assert_eq!(days_of_week("1-5"), "MON,TUE,WED,THU,FRI");
+        assert_eq!(days_of_week("0,7"), "SUN");
+        assert_eq!(days_of_week("1-7"), "SUN,MON,TUE,WED,THU,FRI,SAT");
+        assert_eq!(days_of_week("mon,Wed,5"), "MON,WED,FRI");
+        assert_eq!(days_of_week("*/2"), "SUN,TUE,THU,SAT");
+        assert_eq!(days_of_week("1/3"), "MON,THU");
+        assert_eq!(days_of_week("5-1"), "5-1");
+        assert_eq!(days_of_week("8"), "8");
+    }
+
+    #[test]
+    fn schedules() {
+        // Friday, March 29, 2024; daylight saving time starts in Europe on
+        // Sunday, March 31.
+        let after = Utc.with_ymd_and_hms(2024, 3, 29, 12, 0, 0).unwrap();
+        assert_eq!(
+            runs("0 8 * * 1-5", "Europe/Berlin", after, 2),
+            vec!["2024-04-01T08:00:00+02:00", "2024-04-02T08:00:00+02:00"]
+        );
+        assert_eq!(
+            runs("0 18 * * FRI", "America/New_York", after, 2),
+            vec!["2024-03-29T18:00:00-04:00", "2024-04-05T18:00:00-04:00"]
+        );
+        // 02:30 does not exist in Berlin on March 31.
+        assert_eq!(
+            runs("30 2 * * *", "Europe/Berlin", after, 2),
+            vec!["2024-03-30T02:30:00+01:00", "2024-04-01T02:30:00+02:00"]
+        );
+        assert_eq!(
+            runs("@daily", "UTC", after, 1),
+            vec!["2024-03-30T00:00:00+00:00"]
+        );
+        assert_eq!(
+            CronSchedule::parse("*/15 * * * *", "UTC")
+                .unwrap()
+                .next_run(after),
+            Some(Utc.with_ymd_and_hms(2024, 3, 29, 12, 15, 0).unwrap())
+        );
+
+        // Restricting both the day of month and the day of week selects the
+        // days that match either: the 1st and 15th of the month and Mondays.
+        assert_eq!(
+            runs("0 8 1,15 * MON", "UTC", after, 4),
+            vec![
+                "2024-04-01T08:00:00+00:00",
+                "2024-04-08T08:00:00+00:00",
+                "2024-04-15T08:00:00+00:00",
+                "2024-04-22T08:00:00+00:00"
+            ]
+        );
+        assert_eq!(
+            runs("0 8 13 * FRI", "UTC", after, 3),
+            vec![
+                "2024-04-05T08:00:00+00:00",
+                "2024-04-12T08:00:00+00:00",
+                "2024-04-13T08:00:00+00:00"
+            ]
+        );
+        // A field that starts with `*` is not a restriction, so both fields
+        // must match.
+        assert_eq!(
+            runs("0 8 13 * */2", "UTC", after, 1),
+            vec!["2024-04-13T08:00:00+00:00"]
+        );
+
+        assert!(CronSchedule::parse("0 8 * *", "UTC").is_err());
+        assert!(CronSchedule::parse("0 0 8 * * MON", "UTC").is_err());
+        assert!(CronSchedule::parse("0 25 * * *", "UTC").is_err());
+        assert!(CronSchedule::parse("@reboot", "UTC").is_err());
+        assert!(CronSchedule::parse("0 8 * * *", "Mars/Olympus_Mons").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Resolution of secret references in connector configurations by the
+//! pipeline manager.
+//!
+//! Pipelines resolve secret references themselves, except for secrets stored
+//! in the database of the manager (`${secret:db:<name>}`), which only the
+//! manager can decrypt.  Right before starting a pipeline, the manager
+//! decrypts the database secrets that the pipeline references and passes
+//! them to the pipeline in environment variables, rewriting the references
+//! into references to those variables, so that the secrets are never written
+//! to the configuration file of the pipeline.
+//!
+//! When validating a pipeline, the manager resolves the references to
+//! database secrets and checks that the references to secrets of the other
+//! providers are well-formed.  Services can only be probed if they reference
+//! no secrets other than database secrets.
+use crate::api::ManagerError;
+use crate::auth::{SecretsAccess, TenantId};
+use crate::db::secret::validate_secret_name;
+use crate::db::{storage::Storage, ProjectDB};
+use pipeline_types::config::{ConnectorConfig, PipelineConfig};
+use pipeline_types::secret_ref::{
+    database_secret_env_var, replace_secret_refs_in_json, secret_refs_in_json, SecretProviderType,
+    VaultSecretRef, DATABASE_SECRET_ENV_PREFIX, ENV_SECRET_PREFIX,
+};
+use pipeline_types::service::ServiceConfig;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+
+/// Checks that the issuer of a request that stores `config` may reference
+/// the database secrets that `config` references.
+pub(crate) fn check_database_secret_access<T: Serialize>(
+    access: SecretsAccess,
+    config: &T,
+) -> Result<(), ManagerError> {
+    if access.0 {
+        return Ok(());
+    }
+    let json = serde_json::to_value(config).unwrap_or(JsonValue::Null);
+    match secret_refs_in_json(&json)
+        .into_iter()
+        .find(|(_, secret_ref)| {
+            matches!(
+                SecretProviderType::parse(secret_ref),
+                Ok((SecretProviderType::Database, _))
+            )
+        }) {
+        Some((path, _)) => Err(ManagerError::DatabaseSecretNotAuthorized { path }),
+        None => Ok(()),
+    }
+}
+
+/// Values of the database secrets referenced by a set of connector
+/// configurations, indexed by secret name.
+///
+/// The values are fetched upfront, so that the database lock does not need
+/// to be held while resolving the secrets of other providers.
+pub(crate) type DatabaseSecrets = BTreeMap<String, Result<String, String>>;
+
+/// Fetches the values of the database secrets referenced by `configs`.
+pub(crate) async fn fetch_database_secrets(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    configs: &[&ConnectorConfig],
+) -> DatabaseSecrets {
+    let mut secrets = DatabaseSecrets::new();
+    for config in configs {
+        let json = serde_json::to_value(config).unwrap_or(JsonValue::Null);
+        for (_, secret_ref) in secret_refs_in_json(&json) {
+            if let Ok((SecretProviderType::Database, name)) = SecretProviderType::parse(&secret_ref)
+            {
+                if !secrets.contains_key(name) {
+                    let value = db
+                        .get_secret_value(tenant_id, name)
+                        .await
+                        .map_err(|e| e.to_string());
+                    secrets.insert(name.to_string(), value);
+                }
+            }
+        }
+    }
+    secrets
+}
+
+/// Replaces the references to database secrets in the connector
+/// configurations of a pipeline with references to environment variables
+/// of the pipeline.
+///
+/// Returns the environment variables, which hold the values of the secrets.
+/// References to secrets of other providers are left for the pipeline to
+/// resolve.
+pub(crate) async fn resolve_database_secrets(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    config: &mut PipelineConfig,
+) -> Result<BTreeMap<String, String>, String> {
+    let mut vars = BTreeMap::new();
+    for (name, input) in config.inputs.iter_mut() {
+        database_secrets_to_env(db, tenant_id, &mut input.connector_config, &mut vars)
+            .await
+            .map_err(|e| format!("input connector '{name}': {e}"))?;
+    }
+    for (name, output) in config.outputs.iter_mut() {
+        database_secrets_to_env(db, tenant_id, &mut output.connector_config, &mut vars)
+            .await
+            .map_err(|e| format!("output connector '{name}': {e}"))?;
+    }
+    Ok(vars)
+}
+
+/// Replaces the references to database secrets in `config` with references
+/// to environment variables, adding the variables to `vars`.
+async fn database_secrets_to_env(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    config: &mut ConnectorConfig,
+    vars: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+    let mut json = serde_json::to_value(&*config).map_err(|e| e.to_string())?;
+    let mut refs = BTreeMap::new();
+    for (path, secret_ref) in secret_refs_in_json(&json) {
+        if let Ok((SecretProviderType::Database, name)) = SecretProviderType::parse(&secret_ref) {
+            let value = db
+                .get_secret_value(tenant_id, name)
+                .await
+                .map_err(|e| format!("{path}: {e}"))?;
+            let var = database_secret_env_var(name);
+            vars.insert(var.clone(), value);
+            refs.insert(secret_ref, format!("${{secret:env:{var}}}"));
+        }
+    }
+    if refs.is_empty() {
+        return Ok(());
+    }
+    replace_secret_refs_in_json(&mut json, &refs);
+    *config = serde_json::from_value(json)
+        .map_err(|e| format!("configuration is invalid after resolving secrets: {e}"))?;
+    Ok(())
+}
+
+/// Replaces the references to database secrets in a service configuration
+/// with the values of the secrets, so that the manager can probe the
+/// service.
+///
+/// Fails if the configuration references secrets of other providers, which
+/// only pipelines can resolve.
+pub(crate) async fn resolve_service_secrets(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    config: &mut ServiceConfig,
+) -> Result<(), String> {
+    if resolve_database_secrets_in(db, tenant_id, config).await? {
+        Err("the service references secrets other than database secrets, which only pipelines can resolve".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Replaces the references to database secrets in `config` with the values
+/// of the secrets.
+///
+/// Returns `true` if `config` references secrets of other providers.
+async fn resolve_database_secrets_in<T>(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    config: &mut T,
+) -> Result<bool, String>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let mut json = serde_json::to_value(&*config).map_err(|e| e.to_string())?;
+    let mut secrets = BTreeMap::new();
+    let mut other_providers = false;
+    for (path, secret_ref) in secret_refs_in_json(&json) {
+        match SecretProviderType::parse(&secret_ref) {
+            Ok((SecretProviderType::Database, name)) => {
+                let value = db
+                    .get_secret_value(tenant_id, name)
+                    .await
+                    .map_err(|e| format!("{path}: {e}"))?;
+                secrets.insert(secret_ref, value);
+            }
+            _ => other_providers = true,
+        }
+    }
+    if secrets.is_empty() {
+        return Ok(other_providers);
+    }
+    replace_secret_refs_in_json(&mut json, &secrets);
+    *config = serde_json::from_value(json)
+        .map_err(|e| format!("configuration is invalid after resolving secrets: {e}"))?;
+    Ok(other_providers)
+}
+
+/// Replaces the references to database secrets in `value` with the values of
+/// the secrets and checks that the references to secrets of other providers
+/// are well-formed, reporting the references that cannot be resolved.
+///
+/// Secrets of the other providers are only readable from the host and the
+/// environment of the pipeline, so the manager never reads them.  Returns
+/// `true` if `value` references such secrets.
+pub(crate) fn resolve_secrets(
+    value: &mut JsonValue,
+    database: &DatabaseSecrets,
+    errors: &mut Vec<String>,
+) -> bool {
+    let mut secrets = BTreeMap::new();
+    let mut resolved_by_pipeline = false;
+    for (path, secret_ref) in secret_refs_in_json(value) {
+        let res = match SecretProviderType::parse(&secret_ref) {
+            Ok((SecretProviderType::Database, name)) => match database.get(name) {
+                Some(Ok(secret)) => {
+                    secrets.insert(secret_ref.clone(), secret.clone());
+                    Ok(())
+                }
+                Some(Err(e)) => Err(e.clone()),
+                None => Err(format!("Unknown secret '{name}'")),
+            },
+            Ok((SecretProviderType::Directory, name)) => {
+                resolved_by_pipeline = true;
+                validate_secret_name(name).map_err(|e| e.to_string())
+            }
+            Ok((SecretProviderType::Env, variable)) => {
+                resolved_by_pipeline = true;
+                if !variable.starts_with(ENV_SECRET_PREFIX) || variable == ENV_SECRET_PREFIX {
+                    Err(format!(
+                        "secret reference '{secret_ref}' does not name an environment variable starting with {ENV_SECRET_PREFIX}"
+                    ))
+                } else if variable.starts_with(DATABASE_SECRET_ENV_PREFIX) {
+                    Err(format!(
+                        "environment variables starting with {DATABASE_SECRET_ENV_PREFIX} are reserved for database secrets, which are referenced as '${{secret:db:<name>}}'"
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            Ok((SecretProviderType::Vault, identifier)) => {
+                resolved_by_pipeline = true;
+                VaultSecretRef::parse(identifier).map(|_| ())
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            errors.push(format!("{path}: {e}"));
+        }
+    }
+    replace_secret_refs_in_json(value, &secrets);
+    resolved_by_pipeline
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_database_secret_access, resolve_secrets, DatabaseSecrets};
+    use crate::api::ManagerError;
+    use crate::auth::SecretsAccess;
+
+    #[test]
+    fn secrets() {
+        let database = DatabaseSecrets::from([
+            (
+                "kafka-password".to_string(),
+                Ok("correct horse".to_string()),
+            ),
+            (
+                "missing".to_string(),
+                Err("Unknown secret 'missing'".to_string()),
+            ),
+        ]);
+        let mut value = serde_json::json!({
+            "a": "${secret:db:kafka-password}",
+            "b": ["plain", "${secret:db:missing}"],
+            "c": "${secret:Invalid_Name}",
+            "d": "${secret:aws:key}",
+            "e": "${secret:vault:secret#password}",
+            "f": "${secret:env:HOME}",
+            "g": "${secret:env:FELDERA_SECRET_DB_KAFKA_PASSWORD}",
+        });
+        let mut errors = Vec::new();
+        assert!(resolve_secrets(&mut value, &database, &mut errors));
+        assert_eq!(value["a"], "correct horse");
+        assert_eq!(value["b"][0], "plain");
+        assert_eq!(errors.len(), 6);
+        assert_eq!(errors[0], "b[1]: Unknown secret 'missing'");
+        assert!(errors[1].starts_with("c: Invalid secret name 'Invalid_Name'"));
+        assert_eq!(
+            errors[2],
+            "d: unknown secret provider 'aws' in secret reference 'aws:key'"
+        );
+        assert!(errors[3].starts_with("e: Vault secret reference 'secret#password'"));
+        assert!(errors[4].starts_with("f: secret reference 'env:HOME' does not name"));
+        assert!(errors[5].starts_with("g: environment variables starting with"));
+
+        let mut value = serde_json::json!({"a": "${secret:db:kafka-password}"});
+        let mut errors = Vec::new();
+        assert!(!resolve_secrets(&mut value, &database, &mut errors));
+        assert!(errors.is_empty());
+
+        let mut value = serde_json::json!({"a": "${secret:kafka-password}", "b": "${secret:env:FELDERA_SECRET_TOKEN}"});
+        assert!(resolve_secrets(&mut value, &database, &mut errors));
+        assert!(errors.is_empty());
+        assert_eq!(value["a"], "${secret:kafka-password}");
+    }
+
+    #[test]
+    fn database_secret_access() {
+        let config = serde_json::json!({
+            "a": "${secret:kafka-password}",
+            "b": {"c": ["${secret:db:kafka-password}"]},
+        });
+        assert!(check_database_secret_access(SecretsAccess(true), &config).is_ok());
+        assert!(matches!(
+            check_database_secret_access(SecretsAccess(false), &config),
+            Err(ManagerError::DatabaseSecretNotAuthorized { path }) if path == "b.c[0]"
+        ));
+        let config = serde_json::json!({"a": "${secret:kafka-password}"});
+        assert!(check_database_secret_access(SecretsAccess(false), &config).is_ok());
+    }
+}
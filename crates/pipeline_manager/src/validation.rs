@@ -0,0 +1,942 @@
+//! Validation of the connectors attached to a pipeline before it is deployed.
+//!
+//! For every attached connector we check the secrets referenced by its
+//! configuration, check that its transport matches the direction of the
+//! attachment and that its format can represent the table or view it is
+//! attached to.  Input connectors can optionally be dry-run: we fetch a
+//! sample of records from the data source and parse them against the schema
+//! of the table, without deploying the pipeline.
+use crate::db::{AttachedConnector, ConnectorDescr, PipelineDescr, ProgramDescr};
+use crate::outbound;
+use crate::secrets::{resolve_secrets, DatabaseSecrets};
+use anyhow::{bail, Error as AnyError, Result as AnyResult};
+use dbsp_adapters::{
+    ControllerError, DeCollectionHandle, DeCollectionStream, InputCollectionHandle, InputFormat,
+    RecordFormat,
+};
+use log::debug;
+use pipeline_types::config::{ConnectorConfig, FormatConfig, TransportConfig};
+use pipeline_types::format::arrow_ipc::ArrowIpcEncoderConfig;
+use pipeline_types::format::csv::{CsvEncoderConfig, CsvParserConfig};
+use pipeline_types::format::json::{
+    JsonEncoderConfig, JsonFlavor, JsonParserConfig, JsonUpdateFormat,
+};
+use pipeline_types::format::parquet::{ParquetEncoderConfig, ParquetParserConfig};
+use pipeline_types::program_schema::{canonical_identifier, ColumnType, Relation, SqlType};
+use pipeline_types::transport::kafka::KafkaInputConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::{ClientConfig, Message};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Maximum number of bytes read from a URL during a dry run.
+const DRY_RUN_MAX_BYTES: usize = 1024 * 1024;
+
+/// Options that control the validation of a pipeline.
+#[derive(Debug, Clone)]
+pub(crate) struct ValidationOptions {
+    /// Values of the database secrets referenced by connector configurations.
+    pub database_secrets: DatabaseSecrets,
+    /// Fetch and parse a sample of records from every input connector.
+    pub dry_run: bool,
+    /// Maximum number of records fetched per input connector.
+    pub sample_size: usize,
+    /// Maximum time spent fetching records from a single input connector.
+    pub timeout: Duration,
+    /// Hosts that URL input connectors may fetch data from even though they
+    /// resolve to internal addresses.
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Result of validating the connectors of a pipeline.
+#[derive(Debug, Serialize, ToSchema, PartialEq)]
+pub(crate) struct PipelineValidation {
+    /// True if no problem was found with any of the connectors.
+    pub valid: bool,
+    /// Validation result of each attached connector.
+    pub connectors: Vec<ConnectorValidation>,
+}
+
+/// Result of validating a connector attached to a pipeline.
+#[derive(Debug, Serialize, ToSchema, PartialEq)]
+pub(crate) struct ConnectorValidation {
+    /// Name of the attachment.
+    pub name: String,
+    /// Name of the connector.
+    pub connector_name: String,
+    /// Table or view the connector is attached to.
+    pub relation_name: String,
+    /// True for input connectors, false for output connectors.
+    pub is_input: bool,
+    /// Problems found with the configuration of the connector.
+    pub errors: Vec<String>,
+    /// Outcome of the dry run.  Only set for input connectors when a dry run
+    /// was requested and the configuration of the connector is valid.
+    pub dry_run: Option<DryRunResult>,
+}
+
+/// Outcome of fetching and parsing a sample of records from an input
+/// connector.
+#[derive(Debug, Serialize, ToSchema, PartialEq)]
+pub(crate) struct DryRunResult {
+    /// Number of records that were fetched and parsed successfully.
+    pub records: usize,
+    /// The records that were parsed successfully, as JSON objects.
+    #[schema(value_type = Vec<Object>)]
+    pub sample: Vec<JsonValue>,
+    /// Error that interrupted the dry run, if any.
+    pub error: Option<String>,
+}
+
+/// Validates the connectors attached to a pipeline.
+///
+/// The pipeline must have passed `PipelineRevision::validate`, i.e., its
+/// program is compiled and all attached connectors refer to existing tables
+/// and views.
+pub(crate) async fn validate_pipeline(
+    pipeline: &PipelineDescr,
+    program: &ProgramDescr,
+    connectors: &[ConnectorDescr],
+    options: &ValidationOptions,
+) -> PipelineValidation {
+    let mut results = Vec::new();
+    for ac in &pipeline.attached_connectors {
+        let connector = connectors
+            .iter()
+            .find(|c| c.name == ac.connector_name)
+            .expect("pre-condition: supplied all connectors necessary");
+        let relation = program.schema.as_ref().and_then(|schema| {
+            let relations = if ac.is_input {
+                &schema.inputs
+            } else {
+                &schema.outputs
+            };
+            let name = canonical_identifier(&ac.relation_name);
+            relations.iter().find(|r| r.name() == name)
+        });
+        results.push(validate_connector(ac, &connector.config, relation, options).await);
+    }
+    PipelineValidation {
+        valid: results.iter().all(|r| {
+            r.errors.is_empty() && !matches!(&r.dry_run, Some(DryRunResult { error: Some(_), .. }))
+        }),
+        connectors: results,
+    }
+}
+
+async fn validate_connector(
+    ac: &AttachedConnector,
+    config: &ConnectorConfig,
+    relation: Option<&Relation>,
+    options: &ValidationOptions,
+) -> ConnectorValidation {
+    let mut errors = Vec::new();
+
+    // Resolve secrets on the JSON representation of the configuration, which
+    // lets us substitute them in any string field.
+    let mut json = serde_json::to_value(config).unwrap_or(JsonValue::Null);
+    let resolved_by_pipeline = resolve_secrets(&mut json, &options.database_secrets, &mut errors);
+    let resolved: Option<ConnectorConfig> = if errors.is_empty() {
+        match serde_json::from_value(json) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                errors.push(format!(
+                    "configuration is invalid after resolving secrets: {e}"
+                ));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    check_transport(&config.transport, ac.is_input, &mut errors);
+    match relation {
+        Some(relation) => check_format(&config.format, relation, ac.is_input, &mut errors),
+        None => errors.push(format!(
+            "{} '{}' does not exist in the program",
+            if ac.is_input { "table" } else { "view" },
+            ac.relation_name
+        )),
+    }
+
+    let dry_run = match (resolved, relation) {
+        (Some(resolved), Some(relation)) if options.dry_run && ac.is_input && errors.is_empty() => {
+            // Only the pipeline can resolve the secrets of these providers.
+            if resolved_by_pipeline {
+                Some(DryRunResult {
+                    records: 0,
+                    sample: Vec::new(),
+                    error: Some(
+                        "dry run is not supported for connectors that reference secrets other than database secrets"
+                            .to_string(),
+                    ),
+                })
+            } else {
+                Some(dry_run(&resolved, relation, options).await)
+            }
+        }
+        _ => None,
+    };
+
+    ConnectorValidation {
+        name: ac.name.clone(),
+        connector_name: ac.connector_name.clone(),
+        relation_name: ac.relation_name.clone(),
+        is_input: ac.is_input,
+        errors,
+        dry_run,
+    }
+}
+
+/// Checks the transport configuration, without connecting to the data source
+/// or destination.
+fn check_transport(transport: &TransportConfig, is_input: bool, errors: &mut Vec<String>) {
+    let name = transport.name();
+    if is_input != name.ends_with("_input") {
+        errors.push(format!(
+            "transport '{name}' cannot be used by an {} connector",
+            if is_input { "input" } else { "output" }
+        ));
+        return;
+    }
+    match transport {
+        TransportConfig::FileInput(config) if config.path.is_empty() => {
+            errors.push("transport: file path is empty".to_string())
+        }
+        TransportConfig::FileOutput(config) if config.path.is_empty() => {
+            errors.push("transport: file path is empty".to_string())
+        }
+        TransportConfig::KafkaInput(config) => {
+            if config.topics.is_empty() {
+                errors.push("transport: no Kafka topics to subscribe to".to_string());
+            }
+            if let Err(e) = config.clone().validate() {
+                errors.push(format!("transport: {e}"));
+            }
+        }
+        TransportConfig::KafkaOutput(config) => {
+            if config.topic.is_empty() {
+                errors.push("transport: Kafka topic is empty".to_string());
+            }
+            if let Err(e) = config.clone().validate() {
+                errors.push(format!("transport: {e}"));
+            }
+        }
+        TransportConfig::UrlInput(config) => match url::Url::parse(&config.path) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            Ok(url) => errors.push(format!(
+                "transport: unsupported URL scheme '{}'; expected 'http' or 'https'",
+                url.scheme()
+            )),
+            Err(e) => errors.push(format!("transport: invalid URL '{}': {e}", config.path)),
+        },
+        TransportConfig::S3Input(config) => {
+            if config.bucket_name.is_empty() {
+                errors.push("transport: S3 bucket name is empty".to_string());
+            }
+            if config.region.is_empty() {
+                errors.push("transport: AWS region is empty".to_string());
+            }
+        }
+        TransportConfig::PipelineInput(config) => {
+            if config.pipeline.is_empty() {
+                errors.push("transport: pipeline name is empty".to_string());
+            }
+            if config.view.is_empty() {
+                errors.push("transport: view name is empty".to_string());
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_format_config<T: DeserializeOwned>(
+    format: &FormatConfig,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    match T::deserialize(&format.config) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            errors.push(format!(
+                "format: invalid '{}' configuration: {e}",
+                format.name
+            ));
+            None
+        }
+    }
+}
+
+/// Checks that the format configuration parses and that the format can
+/// represent the records of `relation`.
+fn check_format(
+    format: &FormatConfig,
+    relation: &Relation,
+    is_input: bool,
+    errors: &mut Vec<String>,
+) {
+    match (format.name.as_ref(), is_input) {
+        ("csv", true) => {
+            parse_format_config::<CsvParserConfig>(format, errors);
+        }
+        ("csv", false) => {
+            parse_format_config::<CsvEncoderConfig>(format, errors);
+        }
+        ("json", true) => {
+            if let Some(config) = parse_format_config::<JsonParserConfig>(format, errors) {
+                if config.update_format == JsonUpdateFormat::Snowflake {
+                    errors.push(
+                        "format: the 'snowflake' update format is not supported for JSON input"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        ("json", false) => {
+            if let Some(config) = parse_format_config::<JsonEncoderConfig>(format, errors) {
+                if !matches!(
+                    config.update_format,
+                    JsonUpdateFormat::InsertDelete
+                        | JsonUpdateFormat::Snowflake
+                        | JsonUpdateFormat::Debezium
+                ) {
+                    errors.push(format!(
+                        "format: the {:?} update format is not supported for JSON output",
+                        config.update_format
+                    ));
+                }
+            }
+        }
+        ("parquet", true) => {
+            parse_format_config::<ParquetParserConfig>(format, errors);
+        }
+        ("parquet", false) | ("arrow_ipc", false) => {
+            if format.name == "parquet" {
+                parse_format_config::<ParquetEncoderConfig>(format, errors);
+            } else {
+                parse_format_config::<ArrowIpcEncoderConfig>(format, errors);
+            }
+            // The Arrow schema of the view is derived from its SQL schema,
+            // which is not possible for these types.
+            for field in &relation.fields {
+                if matches!(
+                    field.columntype.typ,
+                    SqlType::Binary | SqlType::Varbinary | SqlType::Interval | SqlType::Array
+                ) {
+                    errors.push(format!(
+                        "format: column '{}' of type {} cannot be encoded as {}",
+                        field.name,
+                        <&'static str>::from(field.columntype.typ.clone()),
+                        format.name
+                    ));
+                }
+            }
+        }
+        (name, _) => errors.push(format!(
+            "format: unknown {} format '{name}'",
+            if is_input { "input" } else { "output" }
+        )),
+    }
+}
+
+/// Fetches a sample of records from an input connector and parses them
+/// against the schema of its table.
+async fn dry_run(
+    config: &ConnectorConfig,
+    relation: &Relation,
+    options: &ValidationOptions,
+) -> DryRunResult {
+    match fetch_sample(&config.transport, options).await {
+        Ok(chunks) => {
+            debug!("Dry run fetched {} chunks", chunks.len());
+            parse_sample(&chunks, &config.format, relation, options.sample_size)
+        }
+        Err(error) => DryRunResult {
+            records: 0,
+            sample: Vec::new(),
+            error: Some(error),
+        },
+    }
+}
+
+/// Fetches raw data from the data source of an input connector.
+///
+/// Returns a list of chunks, each of which contains complete records: the
+/// content of a URL, truncated after the last complete line if it exceeds
+/// [`DRY_RUN_MAX_BYTES`], or the payloads of Kafka messages.
+///
+/// Transports that read files are refused: the files belong to the host of
+/// the pipeline, and reading them on the host of the API server would let
+/// users read its files.
+async fn fetch_sample(
+    transport: &TransportConfig,
+    options: &ValidationOptions,
+) -> Result<Vec<Vec<u8>>, String> {
+    match transport {
+        TransportConfig::UrlInput(config) => {
+            let url = url::Url::parse(&config.path)
+                .map_err(|e| format!("invalid URL '{}': {e}", config.path))?;
+            let client = outbound::client_for(&url, &options.allowed_hosts)
+                .await?
+                .timeout(options.timeout)
+                .build()
+                .map_err(|e| e.to_string())?;
+            let mut request = client.get(url);
+            for (name, value) in &config.headers {
+                request = request.header(name, value);
+            }
+            let mut response = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("unable to fetch '{}': {e}", config.path))?;
+            let mut data = Vec::new();
+            while data.len() <= DRY_RUN_MAX_BYTES {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => data.extend_from_slice(&chunk),
+                    Ok(None) => break,
+                    Err(e) => return Err(format!("unable to fetch '{}': {e}", config.path)),
+                }
+            }
+            Ok(vec![truncate_to_complete_lines(data)])
+        }
+        TransportConfig::KafkaInput(config) => {
+            let config = config.clone();
+            let sample_size = options.sample_size;
+            let timeout = options.timeout;
+            tokio::task::spawn_blocking(move || fetch_kafka_sample(config, sample_size, timeout))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        transport => Err(format!(
+            "dry run is not supported for the '{}' transport",
+            transport.name()
+        )),
+    }
+}
+
+/// Drops the data after the last newline if the data exceeds
+/// [`DRY_RUN_MAX_BYTES`], so that a truncated record is not reported as a
+/// parse error.
+fn truncate_to_complete_lines(mut data: Vec<u8>) -> Vec<u8> {
+    if data.len() > DRY_RUN_MAX_BYTES {
+        let end = data.iter().rposition(|&b| b == b'\n').unwrap_or(0);
+        data.truncate(end);
+    }
+    data
+}
+
+/// Reads up to `sample_size` messages from the topics of a Kafka input
+/// connector, starting from the earliest offset unless the connector
+/// specifies `auto.offset.reset`.  Offsets are never committed.
+fn fetch_kafka_sample(
+    mut config: KafkaInputConfig,
+    sample_size: usize,
+    timeout: Duration,
+) -> Result<Vec<Vec<u8>>, String> {
+    config.validate().map_err(|e| e.to_string())?;
+    let mut client_config = ClientConfig::new();
+    for (k, v) in &config.kafka_options {
+        client_config.set(k, v);
+    }
+    client_config.set("enable.auto.commit", "false");
+    if !config.kafka_options.contains_key("auto.offset.reset") {
+        client_config.set("auto.offset.reset", "earliest");
+    }
+    let consumer: BaseConsumer = client_config
+        .create()
+        .map_err(|e| format!("unable to create Kafka consumer: {e}"))?;
+    let topics: Vec<&str> = config.topics.iter().map(String::as_str).collect();
+    consumer
+        .subscribe(&topics)
+        .map_err(|e| format!("unable to subscribe to {topics:?}: {e}"))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut messages = Vec::new();
+    while messages.len() < sample_size {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match consumer.poll(remaining) {
+            Some(Ok(message)) => {
+                if let Some(payload) = message.payload() {
+                    messages.push(payload.to_vec());
+                }
+            }
+            Some(Err(e)) => return Err(format!("error reading from Kafka: {e}")),
+            None => break,
+        }
+    }
+    if messages.is_empty() {
+        return Err(format!(
+            "no messages received from {topics:?} within {} seconds",
+            timeout.as_secs()
+        ));
+    }
+    Ok(messages)
+}
+
+/// Parses the chunks fetched from an input connector with the parser that
+/// the pipeline would use for the format of the connector, collecting up to
+/// `sample_size` records.
+fn parse_sample(
+    chunks: &[Vec<u8>],
+    format: &FormatConfig,
+    relation: &Relation,
+    sample_size: usize,
+) -> DryRunResult {
+    let sample = Arc::new(Mutex::new(Vec::new()));
+    let result = |error: Option<String>| {
+        let sample = std::mem::take(&mut *sample.lock().unwrap());
+        DryRunResult {
+            records: sample.len(),
+            sample,
+            error,
+        }
+    };
+
+    // Parquet and Arrow IPC are only supported by the HTTP input transport.
+    if format.name != "json" && format.name != "csv" {
+        return result(Some(format!(
+            "dry run is not supported for the '{}' format",
+            format.name
+        )));
+    }
+    let Some(input_format) = <dyn InputFormat>::get_format(&format.name) else {
+        return result(Some(format!("unknown input format '{}'", format.name)));
+    };
+    let handle = InputCollectionHandle::new(
+        relation.clone(),
+        SampleHandle {
+            relation: relation.clone(),
+            sample: sample.clone(),
+            sample_size,
+        },
+    );
+    let mut parser = match input_format.new_parser(&relation.name(), &handle, &format.config) {
+        Ok(parser) => parser,
+        Err(e) => return result(Some(e.to_string())),
+    };
+
+    for chunk in chunks {
+        let (_, errors) = parser.input_chunk(chunk);
+        if let Some(error) = errors.into_iter().next() {
+            return result(Some(error.to_string()));
+        }
+        if sample.lock().unwrap().len() >= sample_size {
+            break;
+        }
+    }
+    let (_, errors) = parser.eoi();
+    result(errors.into_iter().next().map(|e| e.to_string()))
+}
+
+/// Records parsed from an input connector during a dry run, shared between
+/// the streams of a [`SampleHandle`].
+type Sample = Arc<Mutex<Vec<JsonValue>>>;
+
+/// Input collection handle that stands in for a table of the program during
+/// a dry run.
+///
+/// The parsers of the adapters split the input into records and unwrap the
+/// update format, then pass every record to a [`DeCollectionStream`], which
+/// in a pipeline deserializes it into the Rust type of the table.  Those
+/// types only exist in the compiled program, so [`SampleStream`] instead
+/// checks the record against the SQL schema of the table.
+struct SampleHandle {
+    relation: Relation,
+    sample: Sample,
+    sample_size: usize,
+}
+
+impl DeCollectionHandle for SampleHandle {
+    fn configure_deserializer(
+        &self,
+        record_format: RecordFormat,
+    ) -> Result<Box<dyn DeCollectionStream>, ControllerError> {
+        Ok(Box::new(SampleStream {
+            relation: self.relation.clone(),
+            record_format,
+            buffer: Vec::new(),
+            sample: self.sample.clone(),
+            sample_size: self.sample_size,
+        }))
+    }
+}
+
+struct SampleStream {
+    relation: Relation,
+    record_format: RecordFormat,
+    /// Records added since the last `flush`.
+    buffer: Vec<JsonValue>,
+    sample: Sample,
+    sample_size: usize,
+}
+
+impl SampleStream {
+    /// Checks a record and adds it to the buffer.  Records beyond the size
+    /// of the sample are accepted without being checked.
+    fn push(&mut self, data: &[u8]) -> AnyResult<()> {
+        if self.sample.lock().unwrap().len() + self.buffer.len() >= self.sample_size {
+            return Ok(());
+        }
+        let record = match &self.record_format {
+            RecordFormat::Json(flavor) => {
+                let record: JsonValue = serde_json::from_slice(data)?;
+                if *flavor == JsonFlavor::Default {
+                    check_json_record(&record, &self.relation).map_err(AnyError::msg)?;
+                }
+                record
+            }
+            RecordFormat::Csv => csv_record(data, &self.relation).map_err(AnyError::msg)?,
+            RecordFormat::Parquet(_) => bail!("dry run is not supported for the 'parquet' format"),
+        };
+        self.buffer.push(record);
+        Ok(())
+    }
+}
+
+impl DeCollectionStream for SampleStream {
+    fn insert(&mut self, data: &[u8]) -> AnyResult<()> {
+        self.push(data)
+    }
+
+    fn delete(&mut self, data: &[u8]) -> AnyResult<()> {
+        self.push(data)
+    }
+
+    fn update(&mut self, data: &[u8]) -> AnyResult<()> {
+        self.push(data)
+    }
+
+    fn reserve(&mut self, _reservation: usize) {}
+
+    fn flush(&mut self) {
+        self.sample.lock().unwrap().append(&mut self.buffer);
+    }
+
+    fn clear_buffer(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn fork(&self) -> Box<dyn DeCollectionStream> {
+        Box::new(SampleStream {
+            relation: self.relation.clone(),
+            record_format: self.record_format.clone(),
+            buffer: Vec::new(),
+            sample: self.sample.clone(),
+            sample_size: self.sample_size,
+        })
+    }
+}
+
+/// Checks a record in the default JSON encoding against the table schema.
+fn check_json_record(record: &JsonValue, relation: &Relation) -> Result<(), String> {
+    let JsonValue::Object(fields) = record else {
+        return Err(format!("expected a JSON object, found {record}"));
+    };
+    for field in &relation.fields {
+        let value = fields
+            .iter()
+            .find(|(k, _)| {
+                if field.case_sensitive {
+                    *k == &field.name
+                } else {
+                    k.eq_ignore_ascii_case(&field.name)
+                }
+            })
+            .map(|(_, v)| v);
+        match value {
+            None | Some(JsonValue::Null) => {
+                if !field.columntype.nullable {
+                    return Err(format!(
+                        "missing value for non-nullable column '{}'",
+                        field.name
+                    ));
+                }
+            }
+            Some(value) => check_json_value(value, &field.columntype)
+                .map_err(|e| format!("column '{}': {e}", field.name))?,
+        }
+    }
+    Ok(())
+}
+
+fn check_json_value(value: &JsonValue, columntype: &ColumnType) -> Result<(), String> {
+    let int_range = |min: i64, max: i64| -> Result<(), String> {
+        match value.as_i64() {
+            Some(v) if v >= min && v <= max => Ok(()),
+            _ => Err(format!(
+                "expected an integer between {min} and {max}, found {value}"
+            )),
+        }
+    };
+    let typ = &columntype.typ;
+    match typ {
+        SqlType::Boolean if !value.is_boolean() => {
+            Err(format!("expected a boolean, found {value}"))
+        }
+        SqlType::TinyInt => int_range(i8::MIN.into(), i8::MAX.into()),
+        SqlType::SmallInt => int_range(i16::MIN.into(), i16::MAX.into()),
+        SqlType::Int => int_range(i32::MIN.into(), i32::MAX.into()),
+        SqlType::BigInt => int_range(i64::MIN, i64::MAX),
+        SqlType::Real | SqlType::Double if !value.is_number() => {
+            Err(format!("expected a number, found {value}"))
+        }
+        SqlType::Decimal if !value.is_number() && !value.is_string() => {
+            Err(format!("expected a decimal number, found {value}"))
+        }
+        SqlType::Char | SqlType::Varchar | SqlType::Date | SqlType::Time | SqlType::Timestamp
+            if !value.is_string() =>
+        {
+            Err(format!("expected a string, found {value}"))
+        }
+        SqlType::Array => match (value, &columntype.component) {
+            (JsonValue::Array(values), Some(component)) => values.iter().try_for_each(|v| {
+                if v.is_null() {
+                    Ok(())
+                } else {
+                    check_json_value(v, component)
+                }
+            }),
+            (JsonValue::Array(_), None) => Ok(()),
+            _ => Err(format!("expected an array, found {value}")),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Checks a CSV record against the table schema and converts it to a JSON
+/// object.
+fn csv_record(data: &[u8], relation: &Relation) -> Result<JsonValue, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(data);
+    let mut values = csv::StringRecord::new();
+    match reader.read_record(&mut values) {
+        Ok(true) => {}
+        Ok(false) => return Err("empty CSV record".to_string()),
+        Err(e) => return Err(format!("invalid CSV record: {e}")),
+    }
+    if values.len() != relation.fields.len() {
+        return Err(format!(
+            "expected {} columns, found {}",
+            relation.fields.len(),
+            values.len()
+        ));
+    }
+    let mut record = serde_json::Map::new();
+    for (field, value) in relation.fields.iter().zip(values.iter()) {
+        check_csv_value(value, &field.columntype)
+            .map_err(|e| format!("column '{}': {e}", field.name))?;
+        record.insert(field.name.clone(), JsonValue::String(value.to_string()));
+    }
+    Ok(JsonValue::Object(record))
+}
+
+fn check_csv_value(value: &str, columntype: &ColumnType) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    let ok = match columntype.typ {
+        SqlType::Boolean => value == "true" || value == "false",
+        SqlType::TinyInt => value.parse::<i8>().is_ok(),
+        SqlType::SmallInt => value.parse::<i16>().is_ok(),
+        SqlType::Int => value.parse::<i32>().is_ok(),
+        SqlType::BigInt => value.parse::<i64>().is_ok(),
+        SqlType::Real | SqlType::Double | SqlType::Decimal => value.parse::<f64>().is_ok(),
+        _ => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{value}' is not a valid {} value",
+            <&'static str>::from(columntype.typ.clone())
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_format, check_transport, dry_run, parse_sample, DryRunResult, ValidationOptions,
+    };
+    use pipeline_types::config::{ConnectorConfig, FormatConfig, TransportConfig};
+    use pipeline_types::program_schema::{ColumnType, Field, Relation, SqlType};
+    use pipeline_types::transport::file::FileInputConfig;
+    use std::borrow::Cow;
+    use std::time::Duration;
+
+    fn column(name: &str, typ: SqlType, nullable: bool) -> Field {
+        Field {
+            name: name.to_string(),
+            case_sensitive: false,
+            columntype: ColumnType {
+                typ,
+                nullable,
+                precision: None,
+                scale: None,
+                component: None,
+            },
+        }
+    }
+
+    fn relation() -> Relation {
+        Relation::new(
+            "t",
+            false,
+            vec![
+                column("id", SqlType::Int, false),
+                column("name", SqlType::Varchar, true),
+                column("data", SqlType::Varbinary, true),
+            ],
+        )
+    }
+
+    fn format(name: &str, config: &str) -> FormatConfig {
+        FormatConfig {
+            name: Cow::from(name.to_string()),
+            config: serde_yaml::from_str(config).unwrap(),
+        }
+    }
+
+    fn parse(contents: &str, format: FormatConfig) -> DryRunResult {
+        parse_sample(&[contents.as_bytes().to_vec()], &format, &relation(), 2)
+    }
+
+    #[test]
+    fn transports_and_formats() {
+        let mut errors = Vec::new();
+        let file = TransportConfig::FileInput(FileInputConfig {
+            path: "/tmp/input.json".to_string(),
+            buffer_size_bytes: None,
+            follow: false,
+        });
+        check_transport(&file, true, &mut errors);
+        assert!(errors.is_empty());
+        check_transport(&file, false, &mut errors);
+        assert_eq!(
+            errors,
+            vec!["transport 'file_input' cannot be used by an output connector"]
+        );
+
+        let mut errors = Vec::new();
+        check_format(
+            &format("json", "update_format: raw"),
+            &relation(),
+            true,
+            &mut errors,
+        );
+        check_format(&format("csv", "{}"), &relation(), true, &mut errors);
+        assert!(errors.is_empty(), "{errors:?}");
+        check_format(
+            &format("json", "update_format: bogus"),
+            &relation(),
+            true,
+            &mut errors,
+        );
+        check_format(
+            &format("json", "update_format: raw"),
+            &relation(),
+            false,
+            &mut errors,
+        );
+        check_format(&format("parquet", "{}"), &relation(), false, &mut errors);
+        check_format(&format("xml", "{}"), &relation(), true, &mut errors);
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        assert!(errors[0].starts_with("format: invalid 'json' configuration"));
+        assert_eq!(
+            errors[1],
+            "format: the Raw update format is not supported for JSON output"
+        );
+        assert_eq!(
+            errors[2],
+            "format: column 'data' of type VARBINARY cannot be encoded as parquet"
+        );
+        assert_eq!(errors[3], "format: unknown input format 'xml'");
+    }
+
+    #[tokio::test]
+    async fn dry_runs() {
+        // Files are read by the pipeline, never by the API server.
+        let config = ConnectorConfig {
+            transport: TransportConfig::FileInput(FileInputConfig {
+                path: "/etc/passwd".to_string(),
+                buffer_size_bytes: None,
+                follow: false,
+            }),
+            format: format("csv", "{}"),
+            max_buffered_records: 1,
+        };
+        let options = ValidationOptions {
+            database_secrets: Default::default(),
+            dry_run: true,
+            sample_size: 2,
+            timeout: Duration::from_secs(1),
+            allowed_hosts: vec![],
+        };
+        let result = dry_run(&config, &relation(), &options).await;
+        assert_eq!(result.records, 0);
+        assert!(result.sample.is_empty());
+        assert_eq!(
+            result.error.unwrap(),
+            "dry run is not supported for the 'file_input' transport"
+        );
+
+        let result = parse(
+            "{\"insert\": {\"id\": 1, \"name\": \"a\"}}\n{\"delete\": {\"ID\": 2}}\n{\"insert\": {\"id\": 3}}\n",
+            format("json", "{}"),
+        );
+        assert_eq!(result.error, None);
+        assert_eq!(result.records, 2);
+        assert_eq!(result.sample[1], serde_json::json!({"ID": 2}));
+
+        // An invalid update discards the whole array.
+        let result = parse(
+            "[{\"id\": 1}, {\"id\": \"x\"}]",
+            format("json", "update_format: raw\narray: true"),
+        );
+        assert_eq!(result.records, 0);
+        let error = result.error.unwrap();
+        assert!(
+            error.contains(
+                "column 'id': expected an integer between -2147483648 and 2147483647, found \"x\""
+            ),
+            "{error}"
+        );
+
+        let result = parse("{\"name\": \"a\"}", format("json", "update_format: raw"));
+        let error = result.error.unwrap();
+        assert!(
+            error.contains("missing value for non-nullable column 'id'"),
+            "{error}"
+        );
+
+        let result = parse("1,\"a,b\",\n2,c,\n", format("csv", "{}"));
+        assert_eq!(result.error, None);
+        assert_eq!(result.records, 2);
+        assert_eq!(
+            result.sample[0],
+            serde_json::json!({"id": "1", "name": "a,b", "data": ""})
+        );
+
+        let result = parse("x,a,\n", format("csv", "{}"));
+        let error = result.error.unwrap();
+        assert!(
+            error.contains("column 'id': 'x' is not a valid INTEGER value"),
+            "{error}"
+        );
+
+        let result = parse("", format("parquet", "{}"));
+        assert_eq!(
+            result.error.unwrap(),
+            "dry run is not supported for the 'parquet' format"
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! Notification of program and pipeline events to the webhooks registered by
+//! tenants.
+//!
+//! The compiler and the pipeline automata queue a delivery in the database
+//! for every webhook subscribed to an event.  The dispatcher, which runs in
+//! the API server, posts the deliveries that are due to their endpoints,
+//! retries failed ones with exponential backoff, and records the outcome of
+//! every attempt.  Deliveries are leased while they are attempted, so that
+//! several dispatchers can run against a database without delivering an
+//! event twice.  Endpoints that resolve to internal addresses are refused
+//! unless an administrator allows them (see [`crate::outbound`]).
+//!
+//! Every delivery is a JSON `POST` request with the following headers:
+//!
+//! * `X-Feldera-Event`: type of the event, e.g., `pipeline_failed`.
+//! * `X-Feldera-Delivery`: id of the delivery, identical across retries.
+//! * `X-Feldera-Signature`: `t=<timestamp>,v1=<signature>`, where
+//!   `<signature>` is the hexadecimal HMAC-SHA256 of `<timestamp>.<body>`
+//!   keyed with the secret of the webhook.
+use crate::api::ProgramStatus;
+use crate::auth::TenantId;
+use crate::db::pipeline_event::PipelineEvent;
+use crate::db::webhook::{
+    PendingWebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryStatus, WebhookEventType,
+};
+use crate::db::{storage::Storage, DBError, ProgramId, ProjectDB, Version};
+use crate::outbound;
+use chrono::Utc;
+use futures_util::future::join_all;
+use log::{debug, error, warn};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use url::Url;
+
+/// Number of attempts after which a delivery is marked as failed.
+const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+
+/// Delay before the first retry of a delivery; doubled on every subsequent
+/// retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Upper bound on the delay between two attempts of a delivery.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Maximum time to wait for an endpoint to respond.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a dispatcher owns the deliveries it retrieves.  Deliveries whose
+/// outcome is not recorded by then, e.g., because the dispatcher crashed, are
+/// retried by any dispatcher.
+const DELIVERY_LEASE: Duration = Duration::from_secs(60);
+
+/// How often the dispatcher checks for deliveries that are due.
+const DISPATCH_POLL_PERIOD: Duration = Duration::from_secs(2);
+
+/// Maximum number of deliveries attempted concurrently.
+const DISPATCH_BATCH_SIZE: u32 = 32;
+
+/// Generates the secret that signs the deliveries of a webhook.
+pub(crate) fn generate_secret() -> String {
+    let mut secret = [0u8; 32];
+    rand_bytes(&mut secret).expect("unable to generate random bytes");
+    to_hex(&secret)
+}
+
+/// Computes the signature of a delivery sent at `timestamp` (in seconds
+/// since the epoch).
+pub(crate) fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    hmac_sha256_hex(secret.as_bytes(), format!("{timestamp}.{body}").as_bytes())
+}
+
+fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    let key = PKey::hmac(key).expect("unable to create HMAC key");
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("unable to create signer");
+    signer.update(data).expect("unable to compute HMAC");
+    to_hex(&signer.sign_to_vec().expect("unable to compute HMAC"))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+/// Returns the delay before the next attempt of a delivery that failed
+/// `attempts` times.
+fn retry_delay(attempts: u32) -> Duration {
+    INITIAL_RETRY_DELAY
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Queues the delivery of a pipeline status transition to the webhooks
+/// subscribed to it.  Transitions to statuses other than `Running`, `Failed`
+/// and `Shutdown` are not delivered.
+pub(crate) async fn queue_pipeline_event(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    event: &PipelineEvent,
+) -> Result<(), DBError> {
+    let Some(event_type) = WebhookEventType::for_pipeline_status(event.to_status) else {
+        return Ok(());
+    };
+    let pipeline = db.get_pipeline_by_id(tenant_id, event.pipeline_id).await?;
+    let payload = json!({
+        "event_type": event_type,
+        "timestamp": event.timestamp,
+        "pipeline_name": pipeline.descriptor.name,
+        "pipeline_event": event,
+    });
+    let deliveries = db
+        .new_webhook_deliveries(tenant_id, event_type, &payload)
+        .await?;
+    debug!("Queued {deliveries} webhook deliveries of pipeline event {event_type:?}");
+    Ok(())
+}
+
+/// Queues the delivery of the outcome of a compilation to the webhooks
+/// subscribed to it.  Statuses other than success and compilation errors
+/// are not delivered.
+pub(crate) async fn queue_program_status(
+    db: &ProjectDB,
+    tenant_id: TenantId,
+    program_id: ProgramId,
+    program_name: &str,
+    version: Version,
+    status: &ProgramStatus,
+) -> Result<(), DBError> {
+    let event_type = if status.is_compiled() {
+        WebhookEventType::ProgramCompilationSucceeded
+    } else if status.has_failed_to_compile() {
+        WebhookEventType::ProgramCompilationFailed
+    } else {
+        return Ok(());
+    };
+    let payload = json!({
+        "event_type": event_type,
+        "timestamp": Utc::now(),
+        "program_id": program_id,
+        "program_name": program_name,
+        "version": version,
+        "status": status,
+    });
+    let deliveries = db
+        .new_webhook_deliveries(tenant_id, event_type, &payload)
+        .await?;
+    debug!("Queued {deliveries} webhook deliveries of program event {event_type:?}");
+    Ok(())
+}
+
+/// Delivers the queued events to their webhooks until the process exits.
+///
+/// Endpoints in `allowed_hosts` may resolve to internal addresses.
+pub(crate) async fn dispatch(db: Arc<Mutex<ProjectDB>>, allowed_hosts: Vec<String>) {
+    loop {
+        let lease_until = Utc::now() + chrono::Duration::from_std(DELIVERY_LEASE).unwrap();
+        let deliveries = db
+            .lock()
+            .await
+            .next_webhook_deliveries(DISPATCH_BATCH_SIZE, lease_until)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Unable to retrieve pending webhook deliveries: {e}");
+                Vec::new()
+            });
+        let attempts = join_all(deliveries.iter().map(|d| deliver(d, &allowed_hosts))).await;
+        for (delivery, attempt) in deliveries.iter().zip(attempts) {
+            if let Err(e) = db
+                .lock()
+                .await
+                .record_webhook_delivery_attempt(delivery.webhook_delivery_id, &attempt)
+                .await
+            {
+                error!(
+                    "Unable to record the outcome of webhook delivery {}: {e}",
+                    delivery.webhook_delivery_id
+                );
+            }
+        }
+        // Keep going without waiting while there is a backlog.
+        if deliveries.len() < DISPATCH_BATCH_SIZE as usize {
+            tokio::time::sleep(DISPATCH_POLL_PERIOD).await;
+        }
+    }
+}
+
+/// Builds a client for the endpoint of `delivery`, which only connects to
+/// the addresses that were checked.
+async fn endpoint_client(
+    delivery: &PendingWebhookDelivery,
+    allowed_hosts: &[String],
+) -> Result<reqwest::Client, String> {
+    let url = Url::parse(&delivery.url).map_err(|e| format!("invalid URL: {e}"))?;
+    let builder = outbound::client_for(&url, allowed_hosts)
+        .await
+        .map_err(|e| {
+            debug!(
+                "Refusing webhook delivery {} to '{}': {e}",
+                delivery.webhook_delivery_id, delivery.url
+            );
+            "the endpoint cannot be resolved or is not allowed".to_string()
+        })?;
+    builder
+        .build()
+        .map_err(|e| format!("unable to create HTTP client: {e}"))
+}
+
+/// Describes a failed request without the details of the failure, which are
+/// only logged.  The error is visible to the tenant, who must not be able to
+/// use it to probe the network of the pipeline manager.
+fn request_error(delivery: &PendingWebhookDelivery, e: &reqwest::Error) -> String {
+    debug!(
+        "Webhook delivery {} to '{}' failed: {e}",
+        delivery.webhook_delivery_id, delivery.url
+    );
+    if e.is_timeout() {
+        "request timed out".to_string()
+    } else if e.is_connect() {
+        "unable to connect to the endpoint".to_string()
+    } else {
+        "request failed".to_string()
+    }
+}
+
+/// Makes one attempt to deliver an event.
+async fn deliver(
+    delivery: &PendingWebhookDelivery,
+    allowed_hosts: &[String],
+) -> WebhookDeliveryAttempt {
+    let timestamp = Utc::now();
+    let (response_status, error) = match endpoint_client(delivery, allowed_hosts).await {
+        Ok(client) => send(&client, delivery, timestamp.timestamp()).await,
+        Err(e) => (None, Some(e)),
+    };
+
+    let attempts = delivery.attempts + 1;
+    let (status, next_attempt_at) = match &error {
+        None => (WebhookDeliveryStatus::Delivered, None),
+        Some(e) if attempts >= MAX_DELIVERY_ATTEMPTS => {
+            warn!(
+                "Giving up on webhook delivery {} to '{}' after {attempts} attempts: {e}",
+                delivery.webhook_delivery_id, delivery.url
+            );
+            (WebhookDeliveryStatus::Failed, None)
+        }
+        Some(e) => {
+            let delay = retry_delay(attempts);
+            warn!(
+                "Webhook delivery {} to '{}' failed (attempt {attempts}), retrying in {}s: {e}",
+                delivery.webhook_delivery_id,
+                delivery.url,
+                delay.as_secs()
+            );
+            (
+                WebhookDeliveryStatus::Pending,
+                Some(timestamp + chrono::Duration::from_std(delay).unwrap()),
+            )
+        }
+    };
+    WebhookDeliveryAttempt {
+        timestamp,
+        status,
+        next_attempt_at,
+        response_status,
+        error,
+    }
+}
+
+/// Posts `delivery` to its endpoint.  Returns the status of the response and
+/// the error, if the delivery failed.
+async fn send(
+    client: &reqwest::Client,
+    delivery: &PendingWebhookDelivery,
+    timestamp: i64,
+) -> (Option<u16>, Option<String>) {
+    let event_type: &'static str = delivery.event_type.into();
+    let signature = sign(&delivery.secret, timestamp, &delivery.payload);
+    let result = client
+        .post(&delivery.url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(CONTENT_TYPE, "application/json")
+        .header("X-Feldera-Event", event_type)
+        .header(
+            "X-Feldera-Delivery",
+            delivery.webhook_delivery_id.to_string(),
+        )
+        .header(
+            "X-Feldera-Signature",
+            format!("t={timestamp},v1={signature}"),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+    match result {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+        Ok(response) => (
+            Some(response.status().as_u16()),
+            Some(format!("endpoint responded with {}", response.status())),
+        ),
+        Err(e) => (None, Some(request_error(delivery, &e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        deliver, hmac_sha256_hex, retry_delay, sign, MAX_DELIVERY_ATTEMPTS, MAX_RETRY_DELAY,
+    };
+    use crate::db::webhook::{
+        PendingWebhookDelivery, WebhookDeliveryId, WebhookDeliveryStatus, WebhookEventType,
+    };
+    use std::time::Duration;
+    use uuid::Uuid;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn hmac() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            sign("s3cr3t", 1700000000, "{}"),
+            hmac_sha256_hex(b"s3cr3t", b"1700000000.{}")
+        );
+    }
+
+    #[test]
+    fn backoff() {
+        assert_eq!(retry_delay(1), Duration::from_secs(10));
+        assert_eq!(retry_delay(2), Duration::from_secs(20));
+        assert_eq!(retry_delay(4), Duration::from_secs(80));
+        assert_eq!(retry_delay(20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn delivery() {
+        let webhook_delivery_id = WebhookDeliveryId(Uuid::now_v7());
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/ok"))
+            .and(header("content-type", "application/json"))
+            .and(header("x-feldera-event", "pipeline_failed"))
+            .and(header(
+                "x-feldera-delivery",
+                webhook_delivery_id.to_string().as_str(),
+            ))
+            .and(header_exists("x-feldera-signature"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/unavailable"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let allowed_hosts = vec!["127.0.0.1".to_string()];
+        let delivery = PendingWebhookDelivery {
+            webhook_delivery_id,
+            event_type: WebhookEventType::PipelineFailed,
+            payload: r#"{"pipeline_name":"p1"}"#.to_string(),
+            attempts: 0,
+            url: format!("{}/ok", server.uri()),
+            secret: "s3cr3t".to_string(),
+        };
+        let attempt = deliver(&delivery, &allowed_hosts).await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(attempt.response_status, Some(204));
+        assert_eq!(attempt.next_attempt_at, None);
+
+        // Failed deliveries are retried until they run out of attempts.
+        let delivery = PendingWebhookDelivery {
+            url: format!("{}/unavailable", server.uri()),
+            ..delivery
+        };
+        let attempt = deliver(&delivery, &allowed_hosts).await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(attempt.response_status, Some(503));
+        assert!(attempt.next_attempt_at.unwrap() > attempt.timestamp);
+        assert!(attempt.error.is_some());
+        let attempt = deliver(
+            &PendingWebhookDelivery {
+                attempts: MAX_DELIVERY_ATTEMPTS - 1,
+                ..delivery.clone()
+            },
+            &allowed_hosts,
+        )
+        .await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Failed);
+        assert_eq!(attempt.next_attempt_at, None);
+
+        // Internal endpoints are refused unless they are allowed.
+        let attempt = deliver(&delivery, &[]).await;
+        assert_eq!(attempt.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(attempt.response_status, None);
+        assert_eq!(
+            attempt.error.as_deref(),
+            Some("the endpoint cannot be resolved or is not allowed")
+        );
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+}
@@ -60,7 +60,8 @@ const sidebars = {
                   'connectors/sources/http',
                   'connectors/sources/http-get',
                   'connectors/sources/kafka',
-                  'connectors/sources/debezium-mysql'
+                  'connectors/sources/debezium-mysql',
+                  'connectors/sources/pipeline'
               ]
           },
           {
@@ -248,14 +248,16 @@ SqlCreate SqlCreateView(Span s, boolean replace) :
 {
     final SqlIdentifier id;
     SqlNodeList columnList = null;
+    SqlNodeList primaryKey = null;
     final SqlNode query;
 }
 {
     <VIEW> id = CompoundIdentifier()
     [ columnList = ParenthesizedSimpleIdentifierList() ]
+    [ <PRIMARY> <KEY> primaryKey = ParenthesizedSimpleIdentifierList() ]
     <AS> query = OrderedQueryOrExpr(ExprContext.ACCEPT_QUERY) {
-        return SqlDdlNodes.createView(s.end(this), replace, id, columnList,
-            query);
+        return new SqlCreateViewDeclaration(s.end(this), replace, id, columnList,
+            primaryKey, query);
     }
 }
 
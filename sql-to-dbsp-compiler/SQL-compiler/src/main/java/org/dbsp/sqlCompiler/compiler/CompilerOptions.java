@@ -128,6 +128,8 @@ public class CompilerOptions {
         public boolean emitHandles = false;
         @Parameter(names = "--jdbcSource", description = "Connection string to a database that contains table metadata")
         public String metadataSource = "";
+        @Parameter(names = "--materialized", description = "Maintain the contents of every table and view, so that they can be queried as snapshots")
+        public boolean materialized = false;
 
         /** Only compare fields that matter. */
         public boolean same(IO ignoredIo) {
@@ -146,6 +148,7 @@ public class CompilerOptions {
                     ", inputFile=" + Utilities.singleQuote(this.inputFile) +
                     ", functionName=" + Utilities.singleQuote(this.functionName) +
                     ", verbosity=" + this.verbosity +
+                    ", materialized=" + this.materialized +
                     '}';
         }
     }
@@ -23,6 +23,7 @@
 
 package org.dbsp.sqlCompiler.compiler.backend.rust;
 
+import com.fasterxml.jackson.databind.node.ObjectNode;
 import org.dbsp.sqlCompiler.circuit.DBSPCircuit;
 import org.dbsp.sqlCompiler.circuit.DBSPPartialCircuit;
 import org.dbsp.sqlCompiler.circuit.operator.DBSPAggregateOperator;
@@ -45,7 +46,9 @@ import org.dbsp.sqlCompiler.compiler.InputColumnMetadata;
 import org.dbsp.sqlCompiler.compiler.InputTableMetadata;
 import org.dbsp.sqlCompiler.compiler.ProgramMetadata;
 import org.dbsp.sqlCompiler.compiler.errors.InternalCompilerError;
+import org.dbsp.sqlCompiler.compiler.errors.SourcePositionRange;
 import org.dbsp.sqlCompiler.compiler.frontend.CalciteObject;
+import org.dbsp.sqlCompiler.compiler.frontend.calciteCompiler.RelColumnMetadata;
 import org.dbsp.sqlCompiler.compiler.frontend.statements.IHasSchema;
 import org.dbsp.sqlCompiler.compiler.visitors.VisitDecision;
 import org.dbsp.sqlCompiler.compiler.visitors.inner.InnerVisitor;
@@ -59,6 +62,7 @@ import org.dbsp.sqlCompiler.ir.expression.DBSPExpression;
 import org.dbsp.sqlCompiler.ir.expression.DBSPFieldComparatorExpression;
 import org.dbsp.sqlCompiler.ir.expression.DBSPNoComparatorExpression;
 import org.dbsp.sqlCompiler.ir.expression.DBSPOpcode;
+import org.dbsp.sqlCompiler.ir.expression.DBSPTupleExpression;
 import org.dbsp.sqlCompiler.ir.expression.DBSPVariablePath;
 import org.dbsp.sqlCompiler.ir.expression.literal.DBSPBoolLiteral;
 import org.dbsp.sqlCompiler.ir.expression.literal.DBSPStrLiteral;
@@ -81,7 +85,9 @@ import org.dbsp.util.NameGen;
 import org.dbsp.util.Utilities;
 
 import javax.annotation.Nullable;
+import java.util.ArrayList;
 import java.util.HashSet;
+import java.util.List;
 import java.util.Locale;
 import java.util.Set;
 
@@ -120,6 +126,14 @@ public class ToRustVisitor extends CircuitVisitor {
         this.metadata = metadata;
     }
 
+    /** The JSON schema of a table or view, as passed to the catalog. */
+    DBSPStrLiteral schemaLiteral(IHasSchema description) {
+        ObjectNode json = (ObjectNode) description.asJson();
+        if (this.options.ioOptions.materialized)
+            json.put("materialized", true);
+        return new DBSPStrLiteral(json.toString(), false, true);
+    }
+
     protected void generateFromTrait(DBSPTypeStruct type) {
         DBSPTypeTuple tuple = type.toTuple();
         this.builder.append("impl From<")
@@ -417,7 +431,7 @@ public class ToRustVisitor extends CircuitVisitor {
         if (!this.useHandles) {
             this.builder.append("catalog.register_input_zset::<_, ");
             IHasSchema tableDescription = this.metadata.getTableDescription(operator.tableName);
-            DBSPStrLiteral json = new DBSPStrLiteral(tableDescription.asJson().toString(), false, true);
+            DBSPStrLiteral json = this.schemaLiteral(tableDescription);
             operator.originalRowType.accept(this.innerVisitor);
             this.builder.append(">(")
                     .append(operator.getOutputName())
@@ -510,7 +524,7 @@ public class ToRustVisitor extends CircuitVisitor {
         this.builder.decrease().append(");").newline();
         if (!this.useHandles) {
             IHasSchema tableDescription = this.metadata.getTableDescription(operator.tableName);
-            DBSPStrLiteral json = new DBSPStrLiteral(tableDescription.asJson().toString(), false, true);
+            DBSPStrLiteral json = this.schemaLiteral(tableDescription);
             this.builder.append("catalog.register_input_map::<");
             keyStructType.toTuple().accept(this.innerVisitor);
             this.builder.append(", ");
@@ -639,13 +653,48 @@ public class ToRustVisitor extends CircuitVisitor {
         this.generateRenameMacro(operator.viewName, type, null);
         if (!this.useHandles) {
             IHasSchema description = this.metadata.getViewDescription(operator.viewName);
-            DBSPStrLiteral json = new DBSPStrLiteral(description.asJson().toString(), false, true);
-            this.builder.append("catalog.register_output_zset::<_, ");
-            operator.originalRowType.accept(this.innerVisitor);
-            this.builder.append(">(")
-                    .append(operator.input().getOutputName())
+            DBSPStrLiteral json = this.schemaLiteral(description);
+            List<Integer> keyFields = new ArrayList<>();
+            List<RelColumnMetadata> columns = description.getColumns();
+            for (int i = 0; i < columns.size(); i++) {
+                if (columns.get(i).isPrimaryKey)
+                    keyFields.add(i);
+            }
+            @Nullable DBSPExpression keyFunc = null;
+            if (keyFields.isEmpty()) {
+                this.builder.append("catalog.register_output_zset::<_, ");
+                operator.originalRowType.accept(this.innerVisitor);
+                this.builder.append(">(");
+            } else {
+                // Views with a primary key are indexed by key, to serve point lookups.
+                List<DBSPTypeStruct.Field> fields = new ArrayList<>(type.fields.values());
+                DBSPTypeStruct keyStructType = new DBSPTypeStruct(type.getNode(),
+                        type.sanitizedName + "_key", type.sanitizedName + "_key",
+                        Linq.map(keyFields, fields::get));
+                item = new DBSPStructItem(keyStructType);
+                item.accept(this.innerVisitor);
+                this.generateFromTrait(keyStructType);
+                this.generateRenameMacro(keyStructType.name, keyStructType, null);
+
+                DBSPVariablePath var = new DBSPVariablePath("t", operator.input().getOutputZSetElementType().ref());
+                DBSPExpression[] keys = Linq.map(keyFields, index -> var.deref().field(index).applyCloneIfNeeded())
+                        .toArray(new DBSPExpression[0]);
+                keyFunc = new DBSPTupleExpression(keys).closure(var.asParameter());
+                this.builder.append("catalog.register_output_zset_with_lookup::<_, ");
+                operator.originalRowType.accept(this.innerVisitor);
+                this.builder.append(", ");
+                keyStructType.toTuple().accept(this.innerVisitor);
+                this.builder.append(", ");
+                keyStructType.accept(this.innerVisitor);
+                this.builder.append(", _>(");
+            }
+            this.builder.append(operator.input().getOutputName())
                     .append(".clone()")
                     .append(", ");
+            if (keyFunc != null) {
+                keyFunc.accept(this.innerVisitor);
+                this.builder.append(", ");
+            }
             json.accept(this.innerVisitor);
             this.builder.append(");")
                     .newline();
@@ -931,8 +980,12 @@ public class ToRustVisitor extends CircuitVisitor {
     }
 
      IIndentStream writeComments(DBSPOperator operator) {
+        // The pipeline manager uses the position to trace Rust compiler
+        // errors back to the SQL code.
+        SourcePositionRange position = operator.getSourcePosition();
         return this.writeComments(operator.getClass().getSimpleName() +
                 " " + operator.getIdString() +
+                (position.isValid() ? " at " + position : "") +
                 (operator.comment != null ? "\n" + operator.comment : ""));
     }
 
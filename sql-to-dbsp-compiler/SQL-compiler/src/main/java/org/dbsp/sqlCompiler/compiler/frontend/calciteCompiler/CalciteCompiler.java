@@ -65,7 +65,6 @@ import org.apache.calcite.sql.SqlTypeNameSpec;
 import org.apache.calcite.sql.ddl.SqlAttributeDefinition;
 import org.apache.calcite.sql.ddl.SqlColumnDeclaration;
 import org.apache.calcite.sql.ddl.SqlCreateTable;
-import org.apache.calcite.sql.ddl.SqlCreateView;
 import org.apache.calcite.sql.ddl.SqlDropTable;
 import org.apache.calcite.sql.ddl.SqlKeyConstraint;
 import org.apache.calcite.sql.fun.SqlLibrary;
@@ -557,8 +556,24 @@ public class CalciteCompiler implements IWritesLogs {
     }
 
     public List<RelColumnMetadata> createColumnsMetadata(CalciteObject node,
-            SqlIdentifier objectName, boolean view, RelRoot relRoot, @Nullable SqlNodeList columnNames) {
+            SqlIdentifier objectName, boolean view, RelRoot relRoot, @Nullable SqlNodeList columnNames,
+            @Nullable SqlNodeList primaryKey) {
         List<RelColumnMetadata> columns = new ArrayList<>();
+        Map<String, SqlIdentifier> keyColumns = new HashMap<>();
+        if (primaryKey != null) {
+            for (SqlNode keyColumn : primaryKey) {
+                SqlIdentifier identifier = (SqlIdentifier) keyColumn;
+                String name = identifier.getSimple();
+                if (keyColumns.containsKey(name)) {
+                    this.errorReporter.reportError(new SourcePositionRange(identifier.getParserPosition()),
+                            "Duplicate key column", "Column " + Utilities.singleQuote(name) +
+                                    " already declared as key");
+                    this.errorReporter.reportError(new SourcePositionRange(keyColumns.get(name).getParserPosition()),
+                            "Duplicate key column", "Previous declaration");
+                }
+                keyColumns.put(name, identifier);
+            }
+        }
         RelDataType rowType = relRoot.rel.getRowType();
         if (columnNames != null && columnNames.size() != relRoot.fields.size()) {
             this.errorReporter.reportError(
@@ -604,11 +619,17 @@ public class CalciteCompiler implements IWritesLogs {
                 }
                 colByName.put(specifiedName, field);
             }
+            boolean isPrimaryKey = keyColumns.remove(field.getName()) != null;
             RelColumnMetadata meta = new RelColumnMetadata(node,
-                    field, false, nameIsQuoted, null, null);
+                    field, isPrimaryKey, nameIsQuoted, null, null);
             columns.add(meta);
             index++;
         }
+        for (SqlIdentifier s: keyColumns.values()) {
+            this.errorReporter.reportError(new SourcePositionRange(s.getParserPosition()),
+                    "No such column", "Key field " + Utilities.singleQuote(s.toString()) +
+                            " does not correspond to a column");
+        }
         return columns;
     }
 
@@ -682,7 +703,7 @@ public class CalciteCompiler implements IWritesLogs {
                 return table;
             } else if (node.getKind().equals(SqlKind.CREATE_VIEW)) {
                 SqlToRelConverter converter = this.getConverter();
-                SqlCreateView cv = (SqlCreateView) node;
+                SqlCreateViewDeclaration cv = (SqlCreateViewDeclaration) node;
                 SqlNode query = cv.query;
                 if (cv.getReplace())
                     throw new UnsupportedException("OR REPLACE not supported", object);
@@ -691,7 +712,7 @@ public class CalciteCompiler implements IWritesLogs {
                         .newline();
                 RelRoot relRoot = converter.convertQuery(query, true, true);
                 List<RelColumnMetadata> columns = this.createColumnsMetadata(CalciteObject.create(node),
-                        cv.name, true, relRoot, cv.columnList);
+                        cv.name, true, relRoot, cv.columnList, cv.primaryKey);
                 RelNode optimized = this.optimize(relRoot.rel);
                 relRoot = relRoot.withRel(optimized);
                 String viewName = Catalog.identifierToString(cv.name);
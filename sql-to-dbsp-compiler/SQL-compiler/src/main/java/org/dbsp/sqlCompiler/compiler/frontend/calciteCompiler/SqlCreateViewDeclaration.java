@@ -0,0 +1,76 @@
+package org.dbsp.sqlCompiler.compiler.frontend.calciteCompiler;
+
+import org.apache.calcite.sql.SqlCreate;
+import org.apache.calcite.sql.SqlIdentifier;
+import org.apache.calcite.sql.SqlKind;
+import org.apache.calcite.sql.SqlNode;
+import org.apache.calcite.sql.SqlNodeList;
+import org.apache.calcite.sql.SqlOperator;
+import org.apache.calcite.sql.SqlSpecialOperator;
+import org.apache.calcite.sql.SqlWriter;
+import org.apache.calcite.sql.parser.SqlParserPos;
+import org.apache.calcite.util.ImmutableNullableList;
+
+import javax.annotation.Nullable;
+import java.util.List;
+import java.util.Objects;
+
+/** Our own version of CREATE VIEW, different from Calcite.
+ * The view may declare a PRIMARY KEY, which is used to index
+ * the contents of the view for point lookups. */
+public class SqlCreateViewDeclaration extends SqlCreate {
+    public final SqlIdentifier name;
+    @Nullable
+    public final SqlNodeList columnList;
+    @Nullable
+    public final SqlNodeList primaryKey;
+    public final SqlNode query;
+
+    private static final SqlSpecialOperator OPERATOR =
+            new SqlSpecialOperator("CREATE VIEW", SqlKind.CREATE_VIEW);
+
+    public SqlCreateViewDeclaration(SqlParserPos pos, boolean replace, SqlIdentifier name,
+                                    @Nullable SqlNodeList columnList, @Nullable SqlNodeList primaryKey,
+                                    SqlNode query) {
+        super(OPERATOR, pos, replace, false);
+        this.name = Objects.requireNonNull(name, "name");
+        this.columnList = columnList;
+        this.primaryKey = primaryKey;
+        this.query = Objects.requireNonNull(query, "query");
+    }
+
+    @Override public void unparse(SqlWriter writer, int leftPrec,
+                                  int rightPrec) {
+        writer.keyword(getReplace() ? "CREATE OR REPLACE" : "CREATE");
+        writer.keyword("VIEW");
+        this.name.unparse(writer, leftPrec, rightPrec);
+        if (this.columnList != null) {
+            SqlWriter.Frame frame = writer.startList("(", ")");
+            for (SqlNode c : this.columnList) {
+                writer.sep(",");
+                c.unparse(writer, 0, 0);
+            }
+            writer.endList(frame);
+        }
+        if (this.primaryKey != null) {
+            writer.keyword("PRIMARY KEY");
+            SqlWriter.Frame frame = writer.startList("(", ")");
+            for (SqlNode c : this.primaryKey) {
+                writer.sep(",");
+                c.unparse(writer, 0, 0);
+            }
+            writer.endList(frame);
+        }
+        writer.keyword("AS");
+        writer.newlineAndIndent();
+        this.query.unparse(writer, 0, 0);
+    }
+
+    @Override public SqlOperator getOperator() {
+        return OPERATOR;
+    }
+
+    @Override public List<SqlNode> getOperandList() {
+        return ImmutableNullableList.of(this.name, this.columnList, this.primaryKey, this.query);
+    }
+}
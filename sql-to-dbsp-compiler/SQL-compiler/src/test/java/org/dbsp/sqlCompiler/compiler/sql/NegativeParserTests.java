@@ -35,6 +35,16 @@ public class NegativeParserTests extends BaseSQLTests {
         TestUtil.assertMessagesContain(compiler.messages, "does not correspond to a column");
     }
 
+    @Test
+    public void validateViewKey() {
+        String ddl = """
+                CREATE TABLE T(COL1 INT);
+                CREATE VIEW V PRIMARY KEY (unknown) AS SELECT COL1 FROM T;""";
+        DBSPCompiler compiler = this.testCompiler();
+        compiler.compileStatements(ddl);
+        TestUtil.assertMessagesContain(compiler.messages, "does not correspond to a column");
+    }
+
     @Test
     public void testDuplicateTable() {
         String ddl = """
@@ -85,6 +85,7 @@ import java.io.FileWriter;
 import java.io.IOException;
 import java.io.PrintStream;
 import java.nio.charset.StandardCharsets;
+import java.nio.file.Paths;
 import java.sql.Connection;
 import java.sql.DriverManager;
 import java.sql.PreparedStatement;
@@ -606,6 +607,26 @@ public class OtherTests extends BaseSQLTests implements IWritesLogs {
         Utilities.compileAndTestRust(BaseSQLTests.rustDirectory, false);
     }
     
+    @Test
+    public void testViewPrimaryKeyCompiler() throws IOException, InterruptedException, SQLException {
+        String[] statements = new String[]{
+                """
+                CREATE TABLE T (
+                COL1 INT NOT NULL
+                , COL2 VARCHAR
+                )""",
+                "CREATE VIEW V PRIMARY KEY (COL1) AS SELECT COL1, MAX(COL2) AS COL2 FROM T GROUP BY COL1",
+                "CREATE VIEW V1 (X, Y) PRIMARY KEY (Y, X) AS SELECT COL2, COL1 FROM T"
+        };
+        File file = createInputScript(statements);
+        CompilerMessages messages = CompilerMain.execute("-o", BaseSQLTests.testFilePath, file.getPath());
+        System.err.println(messages);
+        Assert.assertEquals(0, messages.errorCount());
+        String rust = Utilities.readFile(Paths.get(BaseSQLTests.testFilePath));
+        Assert.assertTrue(rust.contains("catalog.register_output_zset_with_lookup::<"));
+        Utilities.compileAndTestRust(BaseSQLTests.rustDirectory, false);
+    }
+
     @Test
     public void testSchema() throws IOException, SQLException {
         String[] statements = new String[]{
@@ -46,6 +46,14 @@ const getStatusChipProps = (status: ProgramStatus) =>
         tooltip: 'Waiting on another program to finish compilation'
       }
     })
+    .with({ Queued: P.select() }, position => {
+      return {
+        label: `Queued (${position})`,
+        color: 'primary' as const,
+        'data-testid': 'box-status-queued',
+        tooltip: `Position ${position} in the compilation queue`
+      }
+    })
     .with('CompilingSql', () => {
       return {
         label: 'Compiling sql',
@@ -65,6 +73,14 @@ const getStatusChipProps = (status: ProgramStatus) =>
     .with('Success', () => {
       return { label: 'Ready', color: 'success' as const, 'data-testid': 'box-status-ready', tooltip: undefined }
     })
+    .with('Cancelled', () => {
+      return {
+        label: 'Cancelled',
+        color: 'secondary' as const,
+        'data-testid': 'box-status-cancelled',
+        tooltip: 'Compilation was cancelled; it restarts when the program changes'
+      }
+    })
     .exhaustive()
 
 export const TableSqlPrograms = () => {
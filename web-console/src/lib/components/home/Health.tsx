@@ -65,28 +65,31 @@ const programErrors = (program: ProgramDescr) =>
           })
       )
     )
-    .with({ RustError: P.select() }, (e: string) => [
-      new Error(e, {
-        cause: {
-          source: (
-            <>
-              System Error
-              <br />
-              <ProgramLink program={program} />
-              <br />
-              {program.program_id}
-            </>
-          ),
-          report: {
-            Error: '```\n' + limitMessage(e, 1000, '\n...Beginning of the error...') + '\n```',
-            SQL: () =>
-              ProgramsService.getProgram(program.name, true).then(
-                p => '```\n' + limitMessage(p.code, 7000, '\n...Beginning of the code...') + '\n```'
-              )
+    .with({ RustError: P.select() }, es => {
+      const e = es.map(d => d.snippet || d.message).join('\n')
+      return [
+        new Error(e, {
+          cause: {
+            source: (
+              <>
+                System Error
+                <br />
+                <ProgramLink program={program} />
+                <br />
+                {program.program_id}
+              </>
+            ),
+            report: {
+              Error: '```\n' + limitMessage(e, 1000, '\n...Beginning of the error...') + '\n```',
+              SQL: () =>
+                ProgramsService.getProgram(program.name, true).then(
+                  p => '```\n' + limitMessage(p.code, 7000, '\n...Beginning of the code...') + '\n```'
+                )
+            }
           }
-        }
-      })
-    ])
+        })
+      ]
+    })
     .with({ SystemError: P.select() }, (e: string) => [
       new Error(e, {
         cause: {
@@ -63,13 +63,20 @@ export const CompileIndicator = (props: { program: ProgramDescr }) => {
     .with({ version: 0 }, () => ({
       visible: false as const
     }))
-    .with({ status: 'Pending' }, () => ({
+    .with({ status: 'Pending' }, { status: { Queued: P._ } }, () => ({
       visible: true,
       color: 'warning' as const,
       isCompiling: true,
       label: labelPending,
       status: 'queued'
     }))
+    .with({ status: 'Cancelled' }, () => ({
+      visible: true,
+      color: 'secondary' as const,
+      isCompiling: false,
+      label: 'Cancelled',
+      status: 'cancelled'
+    }))
     .with({ status: 'CompilingSql' }, () => ({
       visible: true,
       color: 'warning' as const,
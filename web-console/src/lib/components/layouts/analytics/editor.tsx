@@ -12,10 +12,11 @@ import { invalidateQuery } from '$lib/functions/common/tanstack'
 import { PLACEHOLDER_VALUES } from '$lib/functions/placeholders'
 import {
   ApiError,
+  CompilerDiagnostic,
+  DiagnosticSeverity,
   NewProgramRequest,
   NewProgramResponse,
   ProgramDescr,
-  SqlCompilerMessage,
   UpdateProgramRequest
 } from '$lib/services/manager'
 import { ProgramsService } from '$lib/services/manager/services/ProgramsService'
@@ -174,7 +175,11 @@ const usePollCompilationStatusEffect = (project: ProgramDescr) => {
   const compilationStatus = useQuery({
     ...pipelineManagerQuery.programStatus(project.name),
     refetchInterval: 1000,
-    enabled: project.program_id !== '' && (project.status === 'Pending' || project.status === 'CompilingSql')
+    enabled:
+      project.program_id !== '' &&
+      (project.status === 'Pending' ||
+        project.status === 'CompilingSql' ||
+        (typeof project.status === 'object' && 'Queued' in project.status))
   })
 
   useEffect(() => {
@@ -203,25 +208,32 @@ const useDisplayCompilerErrorsInEditor = (project: ProgramDescr, editorRef: Muta
   const monaco = useMonaco()
   useEffect(() => {
     if (monaco !== null && editorRef.current !== null) {
-      match(project.status)
-        .with({ SqlError: P.select() }, (err: SqlCompilerMessage[]) => {
-          const monaco_markers = err.map(item => {
-            return {
-              startLineNumber: item.startLineNumber,
-              endLineNumber: item.endLineNumber,
-              startColumn: item.startColumn,
-              endColumn: item.endColumn + 1,
-              message: item.message,
-              severity: item.warning ? monaco.MarkerSeverity.Warning : monaco.MarkerSeverity.Error
-            }
-          })
-          monaco.editor.setModelMarkers(editorRef.current.getModel(), 'sql-errors', monaco_markers)
-        })
-        .otherwise(() => {
-          monaco.editor.setModelMarkers(editorRef.current.getModel(), 'sql-errors', [])
-        })
+      const errors = match(project.status)
+        .returnType<CompilerDiagnostic[]>()
+        .with({ SqlError: P.select() }, errs => errs)
+        .with({ RustError: P.select() }, errs => errs)
+        .otherwise(() => [])
+      // Only diagnostics with a position in the SQL code can be highlighted.
+      const monaco_markers = [...errors, ...(project.warnings ?? [])].flatMap(item =>
+        item.start
+          ? [
+              {
+                startLineNumber: item.start.line,
+                endLineNumber: (item.end ?? item.start).line,
+                startColumn: item.start.column,
+                endColumn: (item.end ?? item.start).column + 1,
+                message: item.message,
+                severity:
+                  item.severity === DiagnosticSeverity.WARNING
+                    ? monaco.MarkerSeverity.Warning
+                    : monaco.MarkerSeverity.Error
+              }
+            ]
+          : []
+      )
+      monaco.editor.setModelMarkers(editorRef.current.getModel(), 'sql-errors', monaco_markers)
     }
-  }, [monaco, project.status, editorRef])
+  }, [monaco, project.status, project.warnings, editorRef])
 }
 
 export const ProgramEditorImpl = ({
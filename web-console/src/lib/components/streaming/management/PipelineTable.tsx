@@ -661,7 +661,7 @@ const usePipelineStatus = (params: { row: Pipeline }) => {
       .with('Success', () => 'Ready' as const)
       .with('CompilingRust', () => 'CompilingRust' as const)
       .with('CompilingSql', () => 'NotReady' as const)
-      .with('Pending', () => 'Pending' as const)
+      .with('Pending', { Queued: P._ }, () => 'Pending' as const)
       .otherwise(() => 'Error' as const)
   })()
 
@@ -17,6 +17,7 @@ export { AwsCredentials } from './models/AwsCredentials'
 export type { Chunk } from './models/Chunk'
 export type { ColumnType } from './models/ColumnType'
 export type { CompileProgramRequest } from './models/CompileProgramRequest'
+export type { CompilerDiagnostic } from './models/CompilerDiagnostic'
 export type { ConnectorConfig } from './models/ConnectorConfig'
 export type { ConnectorDescr } from './models/ConnectorDescr'
 export type { ConnectorId } from './models/ConnectorId'
@@ -31,6 +32,7 @@ export type { CreateOrReplaceServiceRequest } from './models/CreateOrReplaceServ
 export type { CreateOrReplaceServiceResponse } from './models/CreateOrReplaceServiceResponse'
 export type { CsvEncoderConfig } from './models/CsvEncoderConfig'
 export type { CsvParserConfig } from './models/CsvParserConfig'
+export { DiagnosticSeverity } from './models/DiagnosticSeverity'
 export { EgressMode } from './models/EgressMode'
 export type { ErrorResponse } from './models/ErrorResponse'
 export type { Field } from './models/Field'
@@ -85,7 +87,7 @@ export type { S3InputConfig } from './models/S3InputConfig'
 export type { ServiceConfig } from './models/ServiceConfig'
 export type { ServiceDescr } from './models/ServiceDescr'
 export type { ServiceId } from './models/ServiceId'
-export type { SqlCompilerMessage } from './models/SqlCompilerMessage'
+export type { SqlPosition } from './models/SqlPosition'
 export { SqlType } from './models/SqlType'
 export type { TenantId } from './models/TenantId'
 export type { TransportConfig } from './models/TransportConfig'
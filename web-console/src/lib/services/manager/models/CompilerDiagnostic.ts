@@ -0,0 +1,28 @@
+/* generated using openapi-typescript-codegen -- do no edit */
+/* istanbul ignore file */
+/* tslint:disable */
+/* eslint-disable */
+import type { DiagnosticSeverity } from './DiagnosticSeverity'
+import type { SqlPosition } from './SqlPosition'
+/**
+ * An error or warning reported while compiling a program.
+ */
+export type CompilerDiagnostic = {
+  end?: SqlPosition | null
+  /**
+   * Category of the problem, e.g., `Error parsing SQL` for the SQL
+   * compiler or the error code for the Rust compiler.
+   */
+  error_type: string
+  /**
+   * Description of the problem.
+   */
+  message: string
+  severity: DiagnosticSeverity
+  /**
+   * The offending lines of SQL code or, for the Rust compiler, the
+   * diagnostic as rendered by the compiler.
+   */
+  snippet?: string | null
+  start?: SqlPosition | null
+}
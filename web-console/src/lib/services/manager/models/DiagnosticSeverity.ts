@@ -0,0 +1,11 @@
+/* generated using openapi-typescript-codegen -- do no edit */
+/* istanbul ignore file */
+/* tslint:disable */
+/* eslint-disable */
+/**
+ * Severity of a compiler diagnostic.
+ */
+export enum DiagnosticSeverity {
+  ERROR = 'error',
+  WARNING = 'warning'
+}
@@ -4,5 +4,6 @@
 /* eslint-disable */
 export enum EgressMode {
   WATCH = 'watch',
-  SNAPSHOT = 'snapshot'
+  SNAPSHOT = 'snapshot',
+  SNAPSHOT_AND_WATCH = 'snapshot_and_watch'
 }
@@ -2,6 +2,7 @@
 /* istanbul ignore file */
 /* tslint:disable */
 /* eslint-disable */
+import type { CompilerDiagnostic } from './CompilerDiagnostic'
 import type { ProgramId } from './ProgramId'
 import type { ProgramSchema } from './ProgramSchema'
 import type { ProgramStatus } from './ProgramStatus'
@@ -26,4 +27,9 @@ export type ProgramDescr = {
   schema?: ProgramSchema | null
   status: ProgramStatus
   version: Version
+  /**
+   * Warnings reported by the SQL and Rust compilers while compiling the
+   * current version of the program.  Errors are reported in `status`.
+   */
+  warnings?: Array<CompilerDiagnostic>
 }
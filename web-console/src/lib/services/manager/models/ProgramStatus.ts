@@ -2,12 +2,19 @@
 /* istanbul ignore file */
 /* tslint:disable */
 /* eslint-disable */
-import type { SqlCompilerMessage } from './SqlCompilerMessage'
+import type { CompilerDiagnostic } from './CompilerDiagnostic'
 /**
  * Program compilation status.
  */
 export type ProgramStatus =
   | 'Pending'
+  | {
+      /**
+       * The program is waiting in the compilation queue at the given position,
+       * starting at 1.  Reported by the API instead of `Pending`.
+       */
+      Queued: number
+    }
   | 'CompilingSql'
   | 'CompilingRust'
   | 'Success'
@@ -15,13 +22,13 @@ export type ProgramStatus =
       /**
        * SQL compiler returned an error.
        */
-      SqlError: Array<SqlCompilerMessage>
+      SqlError: Array<CompilerDiagnostic>
     }
   | {
       /**
        * Rust compiler returned an error.
        */
-      RustError: string
+      RustError: Array<CompilerDiagnostic>
     }
   | {
       /**
@@ -29,3 +36,4 @@ export type ProgramStatus =
        */
       SystemError: string
     }
+  | 'Cancelled'
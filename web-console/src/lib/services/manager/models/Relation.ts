@@ -11,5 +11,10 @@ import type { Field } from './Field'
 export type Relation = {
   case_sensitive?: boolean
   fields: Array<Field>
+  /**
+   * True if the pipeline maintains the contents of the relation, so that
+   * clients can query snapshots of it.
+   */
+  materialized?: boolean
   name: string
 }
@@ -0,0 +1,17 @@
+/* generated using openapi-typescript-codegen -- do no edit */
+/* istanbul ignore file */
+/* tslint:disable */
+/* eslint-disable */
+/**
+ * A position in the SQL code of a program.
+ */
+export type SqlPosition = {
+  /**
+   * Column number, starting at 1.
+   */
+  column: number
+  /**
+   * Line number, starting at 1.
+   */
+  line: number
+}
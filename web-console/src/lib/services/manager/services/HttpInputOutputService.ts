@@ -31,7 +31,7 @@ export class HttpInputOutputService {
    * @param tableName SQL table name. Unquoted SQL names have to be capitalized. Quoted SQL names have to exactly match the case from the SQL program.
    * @param format Output data format, e.g., 'csv' or 'json'.
    * @param query Query to execute on the table. Must be one of 'table', 'neighborhood', or 'quantiles'. The default value is 'table'
-   * @param mode Output mode. Must be one of 'watch' or 'snapshot'. The default value is 'watch'
+   * @param mode Output mode. Must be one of 'watch', 'snapshot', or 'snapshot_and_watch'. The default value is 'watch'
    * @param quantiles For 'quantiles' queries: the number of quantiles to output. The default value is 100.
    * @param array Set to `true` to group updates in this stream into JSON arrays (used in conjunction with `format=json`). The default value is `false`
    * @param requestBody When the `query` parameter is set to 'neighborhood', the body of the request must contain a neighborhood specification.